@@ -1,6 +1,64 @@
 use crate::error::{CliError, Result};
 use crate::utils::{file_ops, process};
-use std::path::PathBuf;
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+
+/// Line and function coverage totals of a coverage profile
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct CoverageSummary {
+    pub lines_covered: u64,
+    pub lines_total: u64,
+    pub functions_covered: u64,
+    pub functions_total: u64,
+}
+
+impl CoverageSummary {
+    pub fn line_percent(&self) -> f64 {
+        percent(self.lines_covered, self.lines_total)
+    }
+
+    pub fn function_percent(&self) -> f64 {
+        percent(self.functions_covered, self.functions_total)
+    }
+}
+
+fn percent(covered: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        covered as f64 * 100.0 / total as f64
+    }
+}
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryFormat {
+    #[default]
+    Text,
+    Json, // For CI integration
+}
+
+/// Options for summarizing the coverage of a corpus instead of (or in addition to) generating
+/// the html report
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SummaryOptions {
+    /// Print the line coverage summary (skips the html report)
+    pub line_count: bool,
+    /// Print the function coverage summary (skips the html report)
+    pub function_count: bool,
+    pub format: SummaryFormat,
+    /// Fail if the line coverage percentage is below this threshold
+    pub fail_under: Option<f64>,
+}
+
+impl SummaryOptions {
+    fn skip_report(&self) -> bool {
+        self.line_count || self.function_count
+    }
+
+    fn enabled(&self) -> bool {
+        self.skip_report() || self.fail_under.is_some()
+    }
+}
 
 pub struct CoverageCommand;
 
@@ -12,6 +70,7 @@ impl CoverageCommand {
         scenario: PathBuf,
         profraws: Option<Vec<PathBuf>>,
         run_only: bool,
+        summary_options: SummaryOptions,
     ) -> Result<()> {
         file_ops::ensure_file_exists(&bitcoind)?;
         file_ops::ensure_file_exists(&scenario)?;
@@ -44,15 +103,74 @@ impl CoverageCommand {
             }
         };
 
-        Self::generate_report(&output, &bitcoind, &profdata)?;
+        if !summary_options.skip_report() {
+            Self::generate_report(&output, &bitcoind, &profdata)?;
+        }
+
+        if summary_options.enabled() {
+            let summary =
+                Self::quick_summary(&process::get_llvm_command("llvm-cov"), &bitcoind, &profdata)?;
+            Self::print_summary(&summary, &summary_options)?;
+
+            if let Some(threshold) = summary_options.fail_under {
+                check_threshold(&summary, threshold)?;
+            }
+        }
+
         Ok(())
     }
 
-    fn run_one_input(
-        output: &PathBuf,
-        input: &PathBuf,
-        bitcoind: &PathBuf,
-        scenario: &PathBuf,
+    /// Summarize the line and function coverage of `profdata` using `llvm-cov report`, which is
+    /// much faster than generating the full html report
+    pub fn quick_summary(
+        llvm_cov: &str,
+        bitcoind: &Path,
+        profdata: &Path,
+    ) -> Result<CoverageSummary> {
+        let instr_profile_arg = format!("-instr-profile={}", profdata.display());
+        let args = [
+            "report",
+            bitcoind.to_str().unwrap(),
+            &instr_profile_arg,
+            "--format=text",
+        ];
+
+        let output = process::run_command_with_output(llvm_cov, &args, None)?;
+        parse_report(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn print_summary(summary: &CoverageSummary, options: &SummaryOptions) -> Result<()> {
+        match options.format {
+            SummaryFormat::Json => println!("{}", serde_json::to_string(summary)?),
+            SummaryFormat::Text => {
+                // Without explicit selection (i.e. only `--fail-under`) both counts are printed
+                let all = !options.line_count && !options.function_count;
+                if options.line_count || all {
+                    println!(
+                        "Lines: {}/{} covered ({:.2}%)",
+                        summary.lines_covered,
+                        summary.lines_total,
+                        summary.line_percent()
+                    );
+                }
+                if options.function_count || all {
+                    println!(
+                        "Functions: {}/{} covered ({:.2}%)",
+                        summary.functions_covered,
+                        summary.functions_total,
+                        summary.function_percent()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn run_one_input(
+        output: &Path,
+        input: &Path,
+        bitcoind: &Path,
+        scenario: &Path,
     ) -> Result<()> {
         log::info!("Running scenario with input: {}", input.display());
 
@@ -105,7 +223,7 @@ impl CoverageCommand {
         Ok(())
     }
 
-    fn merge_profraws(output: &PathBuf, profraws: &Vec<PathBuf>) -> Result<PathBuf> {
+    pub(crate) fn merge_profraws(output: &Path, profraws: &[PathBuf]) -> Result<PathBuf> {
         if profraws.is_empty() {
             return Err(CliError::InvalidInput(
                 "No profraws directory provided".to_string(),
@@ -143,3 +261,129 @@ impl CoverageCommand {
         Ok(merged)
     }
 }
+
+/// Fail if the line coverage of `summary` is below `threshold` percent
+fn check_threshold(summary: &CoverageSummary, threshold: f64) -> Result<()> {
+    let coverage = summary.line_percent();
+    if coverage < threshold {
+        return Err(CliError::CoverageBelowThreshold {
+            coverage,
+            threshold,
+        });
+    }
+    Ok(())
+}
+
+/// Parse the `TOTAL` row of `llvm-cov report` text output.
+///
+/// Columns are looked up by their header names (separated by at least two spaces), as the set of
+/// columns differs between llvm versions (e.g. instantiation and branch coverage columns).
+fn parse_report(report: &str) -> Result<CoverageSummary> {
+    let missing =
+        |what: &str| CliError::ProcessError(format!("Missing {} in llvm-cov report", what));
+
+    let header: Vec<&str> = report
+        .lines()
+        .find(|line| line.starts_with("Filename"))
+        .ok_or_else(|| missing("header"))?
+        .split("  ")
+        .map(str::trim)
+        .filter(|column| !column.is_empty())
+        .collect();
+    let totals: Vec<&str> = report
+        .lines()
+        .find(|line| line.starts_with("TOTAL"))
+        .ok_or_else(|| missing("TOTAL row"))?
+        .split_whitespace()
+        .collect();
+
+    let column = |name: &str| -> Result<u64> {
+        header
+            .iter()
+            .position(|column| *column == name)
+            .and_then(|index| totals.get(index))
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| missing(&format!("\"{}\" column", name)))
+    };
+
+    let lines_total = column("Lines")?;
+    let functions_total = column("Functions")?;
+    Ok(CoverageSummary {
+        lines_covered: lines_total.saturating_sub(column("Missed Lines")?),
+        lines_total,
+        functions_covered: functions_total.saturating_sub(column("Missed Functions")?),
+        functions_total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    const REPORT: &str = "Filename                      Regions    Missed Regions     Cover   Functions  Missed Functions  Executed       Lines      Missed Lines     Cover    Branches   Missed Branches     Cover
+-----------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+src/net_processing.cpp            120                30    75.00%          20                 5    75.00%         400               100    75.00%          80                40    50.00%
+src/validation.cpp                 80                60    25.00%          10                 8    20.00%         200               150    25.00%          40                30    25.00%
+-----------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+TOTAL                             200                90    55.00%          30                13    56.67%         600               250    58.33%         120                70    41.67%
+";
+
+    const EXPECTED: CoverageSummary = CoverageSummary {
+        lines_covered: 350,
+        lines_total: 600,
+        functions_covered: 17,
+        functions_total: 30,
+    };
+
+    #[test]
+    fn parse_report_totals() {
+        assert_eq!(parse_report(REPORT).unwrap(), EXPECTED);
+        assert!(parse_report("").is_err());
+        assert!(parse_report(REPORT.lines().next().unwrap()).is_err());
+    }
+
+    #[test]
+    fn parse_report_with_instantiation_columns() {
+        let report = "Filename   Regions    Missed Regions     Cover   Functions  Missed Functions  Executed  Instantiations   Missed Insts  Executed       Lines      Missed Lines     Cover
+TOTAL          200                90    55.00%          30                13    56.67%              40             20    50.00%         600               250    58.33%
+";
+        assert_eq!(parse_report(report).unwrap(), EXPECTED);
+    }
+
+    #[test]
+    fn quick_summary_runs_llvm_cov_report() {
+        let dir = std::env::temp_dir().join(format!("fuzzamoto-llvm-cov-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("report.txt"), REPORT).unwrap();
+
+        // Mock llvm-cov, only answering `report` invocations
+        let llvm_cov = dir.join("llvm-cov");
+        std::fs::write(
+            &llvm_cov,
+            format!(
+                "#!/bin/sh\n[ \"$1\" = report ] || exit 1\ncat {}\n",
+                dir.join("report.txt").display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&llvm_cov, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let summary = CoverageCommand::quick_summary(
+            llvm_cov.to_str().unwrap(),
+            Path::new("bitcoind"),
+            Path::new("coverage.profdata"),
+        )
+        .unwrap();
+        assert_eq!(summary, EXPECTED);
+        assert!((summary.line_percent() - 58.33).abs() < 0.01);
+
+        assert!(check_threshold(&summary, 50.0).is_ok());
+        assert!(matches!(
+            check_threshold(&summary, 60.0),
+            Err(CliError::CoverageBelowThreshold { .. })
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
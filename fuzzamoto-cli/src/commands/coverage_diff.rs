@@ -0,0 +1,366 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    commands::CoverageCommand,
+    error::{CliError, Result},
+    utils::{file_ops, process},
+};
+
+/// Summary of the line coverage difference between two corpora
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CoverageDelta {
+    /// Lines only covered by the "after" corpus
+    pub gained: usize,
+    /// Lines only covered by the "before" corpus
+    pub lost: usize,
+    /// Lines covered by both corpora
+    pub unchanged: usize,
+}
+
+impl CoverageDelta {
+    /// Approximate a delta from the number of covered lines alone, assuming that no covered lines
+    /// were swapped for others
+    fn from_line_counts(before: usize, after: usize) -> Self {
+        CoverageDelta {
+            gained: after.saturating_sub(before),
+            lost: before.saturating_sub(after),
+            unchanged: before.min(after),
+        }
+    }
+}
+
+/// Covered lines per source file
+type CoveredLines = BTreeMap<String, BTreeSet<u32>>;
+
+/// Line-level coverage difference between two corpora
+#[derive(Debug, Default)]
+struct LineDiff {
+    gained: CoveredLines,
+    lost: CoveredLines,
+    unchanged: usize,
+}
+
+impl LineDiff {
+    fn new(before: &CoveredLines, after: &CoveredLines) -> Self {
+        let mut diff = LineDiff::default();
+        let files: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        let empty = BTreeSet::new();
+
+        for file in files {
+            let before_lines = before.get(file).unwrap_or(&empty);
+            let after_lines = after.get(file).unwrap_or(&empty);
+
+            let gained: BTreeSet<u32> = after_lines.difference(before_lines).copied().collect();
+            let lost: BTreeSet<u32> = before_lines.difference(after_lines).copied().collect();
+            diff.unchanged += before_lines.intersection(after_lines).count();
+
+            if !gained.is_empty() {
+                diff.gained.insert(file.clone(), gained);
+            }
+            if !lost.is_empty() {
+                diff.lost.insert(file.clone(), lost);
+            }
+        }
+
+        diff
+    }
+
+    fn delta(&self) -> CoverageDelta {
+        CoverageDelta {
+            gained: self.gained.values().map(BTreeSet::len).sum(),
+            lost: self.lost.values().map(BTreeSet::len).sum(),
+            unchanged: self.unchanged,
+        }
+    }
+}
+
+pub struct CoverageDiffCommand;
+
+impl CoverageDiffCommand {
+    pub fn execute(
+        before_corpus: &Path,
+        after_corpus: &Path,
+        bitcoind: &Path,
+        scenario: &Path,
+        output: &Path,
+    ) -> Result<()> {
+        file_ops::ensure_file_exists(bitcoind)?;
+        file_ops::ensure_file_exists(scenario)?;
+        file_ops::create_dir_all(output)?;
+
+        let before = Self::profile_corpus(output, "before", before_corpus, bitcoind, scenario)?;
+        let after = Self::profile_corpus(output, "after", after_corpus, bitcoind, scenario)?;
+
+        let report_path = output.join("coverage-diff.html");
+        let delta = match (
+            Self::export_lcov(bitcoind, &before),
+            Self::export_lcov(bitcoind, &after),
+        ) {
+            (Ok(before_lcov), Ok(after_lcov)) => {
+                let diff = LineDiff::new(&parse_lcov(&before_lcov), &parse_lcov(&after_lcov));
+                std::fs::write(&report_path, render_html(&diff))?;
+                diff.delta()
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                log::warn!(
+                    "Line-level coverage export failed ({}), falling back to comparing covered line counts",
+                    e
+                );
+                let delta = CoverageDelta::from_line_counts(
+                    Self::covered_line_count(bitcoind, &before)?,
+                    Self::covered_line_count(bitcoind, &after)?,
+                );
+                std::fs::write(&report_path, render_summary_html(&delta))?;
+                delta
+            }
+        };
+
+        log::info!(
+            "Coverage diff: {} lines gained, {} lines lost, {} lines unchanged",
+            delta.gained,
+            delta.lost,
+            delta.unchanged
+        );
+        log::info!(
+            "Coverage diff report generated in: {}",
+            report_path.display()
+        );
+
+        Ok(())
+    }
+
+    /// Run all inputs of `corpus` and merge the resulting profiles into `<output>/<name>.profdata`
+    fn profile_corpus(
+        output: &Path,
+        name: &str,
+        corpus: &Path,
+        bitcoind: &Path,
+        scenario: &Path,
+    ) -> Result<PathBuf> {
+        let profraw_dir = output.join(name);
+        file_ops::create_dir_all(&profraw_dir)?;
+
+        for corpus_file in file_ops::read_dir_files(corpus)? {
+            if let Err(e) =
+                CoverageCommand::run_one_input(&profraw_dir, &corpus_file, bitcoind, scenario)
+            {
+                log::error!("Failed to run input ({:?}): {}", corpus_file, e);
+            }
+        }
+
+        let merged = CoverageCommand::merge_profraws(&profraw_dir, &[profraw_dir.clone()])?;
+        let profdata = output.join(format!("{}.profdata", name));
+        std::fs::rename(merged, &profdata)?;
+        Ok(profdata)
+    }
+
+    fn export_lcov(bitcoind: &Path, profdata: &Path) -> Result<String> {
+        let instr_profile_arg = format!("-instr-profile={}", profdata.display());
+        let args = [
+            "export",
+            bitcoind.to_str().unwrap(),
+            &instr_profile_arg,
+            "-format=lcov",
+        ];
+
+        let output =
+            process::run_command_with_output(&process::get_llvm_command("llvm-cov"), &args, None)?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn covered_line_count(bitcoind: &Path, profdata: &Path) -> Result<usize> {
+        let instr_profile_arg = format!("-instr-profile={}", profdata.display());
+        let args = [
+            "export",
+            bitcoind.to_str().unwrap(),
+            &instr_profile_arg,
+            "-summary-only",
+        ];
+
+        let output =
+            process::run_command_with_output(&process::get_llvm_command("llvm-cov"), &args, None)?;
+        parse_summary_covered_lines(&output.stdout)
+    }
+}
+
+/// Parse the covered lines (`DA:<line>,<count>` records with a non-zero count) of every source
+/// file (`SF:<path>`) from `llvm-cov export -format=lcov` output
+fn parse_lcov(lcov: &str) -> CoveredLines {
+    let mut covered = CoveredLines::new();
+    let mut current_file = None;
+
+    for line in lcov.lines() {
+        if let Some(file) = line.strip_prefix("SF:") {
+            current_file = Some(file.to_string());
+        } else if line == "end_of_record" {
+            current_file = None;
+        } else if let (Some(file), Some(record)) = (&current_file, line.strip_prefix("DA:")) {
+            let mut fields = record.split(',');
+            let (Some(Ok(line_number)), Some(Ok(count))) = (
+                fields.next().map(str::parse::<u32>),
+                fields.next().map(str::parse::<u64>),
+            ) else {
+                continue;
+            };
+            if count > 0 {
+                covered.entry(file.clone()).or_default().insert(line_number);
+            }
+        }
+    }
+
+    covered
+}
+
+/// Parse the total number of covered lines from `llvm-cov export -summary-only` output
+fn parse_summary_covered_lines(summary: &[u8]) -> Result<usize> {
+    let summary: serde_json::Value = serde_json::from_slice(summary)?;
+    summary["data"][0]["totals"]["lines"]["covered"]
+        .as_u64()
+        .and_then(|covered| usize::try_from(covered).ok())
+        .ok_or_else(|| {
+            CliError::ProcessError("Missing covered line count in llvm-cov summary".to_string())
+        })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_HEADER: &str = "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+<title>Coverage diff</title>\n<style>\n\
+body { font-family: monospace; }\n\
+.gained { background-color: #c8f7c5; }\n\
+.lost { background-color: #f7c5c5; }\n\
+</style>\n</head>\n<body>\n";
+
+fn render_delta(html: &mut String, delta: &CoverageDelta) {
+    let _ = writeln!(
+        html,
+        "<h1>Coverage diff</h1>\n<p><span class=\"gained\">{} lines gained</span>, \
+<span class=\"lost\">{} lines lost</span>, {} lines unchanged</p>",
+        delta.gained, delta.lost, delta.unchanged
+    );
+}
+
+/// Render a html report listing the gained (green) and lost (red) lines of every source file
+fn render_html(diff: &LineDiff) -> String {
+    let mut html = HTML_HEADER.to_string();
+    render_delta(&mut html, &diff.delta());
+
+    let files: BTreeSet<&String> = diff.gained.keys().chain(diff.lost.keys()).collect();
+    for file in files {
+        let _ = writeln!(html, "<h2>{}</h2>\n<ul>", escape_html(file));
+        let gained = diff.gained.get(file).into_iter().flatten();
+        let lost = diff.lost.get(file).into_iter().flatten();
+        let mut lines: Vec<(u32, &str)> = gained
+            .map(|line| (*line, "gained"))
+            .chain(lost.map(|line| (*line, "lost")))
+            .collect();
+        lines.sort_unstable();
+
+        for (line, class) in lines {
+            let _ = writeln!(html, "<li class=\"{class}\">line {line} {class}</li>");
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Render a html report for a delta computed without line-level coverage data
+fn render_summary_html(delta: &CoverageDelta) -> String {
+    let mut html = HTML_HEADER.to_string();
+    render_delta(&mut html, delta);
+    html.push_str(
+        "<p>Line-level coverage was unavailable, the delta only compares covered line counts.</p>\n",
+    );
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEFORE_LCOV: &str = "SF:/src/net_processing.cpp
+DA:10,4
+DA:11,0
+DA:12,1
+end_of_record
+SF:/src/validation.cpp
+DA:5,2
+end_of_record
+";
+
+    const AFTER_LCOV: &str = "SF:/src/net_processing.cpp
+DA:10,7
+DA:11,3
+DA:12,0
+end_of_record
+SF:/src/validation.cpp
+DA:5,1
+DA:6,1
+end_of_record
+SF:/src/txmempool.cpp
+DA:1,0
+end_of_record
+";
+
+    #[test]
+    fn parse_lcov_ignores_uncovered_lines() {
+        let covered = parse_lcov(BEFORE_LCOV);
+        assert_eq!(covered.len(), 2);
+        assert_eq!(covered["/src/net_processing.cpp"], BTreeSet::from([10, 12]));
+        assert_eq!(covered["/src/validation.cpp"], BTreeSet::from([5]));
+    }
+
+    #[test]
+    fn line_diff_reports_gained_and_lost_lines() {
+        let diff = LineDiff::new(&parse_lcov(BEFORE_LCOV), &parse_lcov(AFTER_LCOV));
+        assert_eq!(
+            diff.delta(),
+            CoverageDelta {
+                gained: 2,
+                lost: 1,
+                unchanged: 2,
+            }
+        );
+        assert_eq!(diff.gained["/src/net_processing.cpp"], BTreeSet::from([11]));
+        assert_eq!(diff.gained["/src/validation.cpp"], BTreeSet::from([6]));
+        assert_eq!(diff.lost["/src/net_processing.cpp"], BTreeSet::from([12]));
+
+        let html = render_html(&diff);
+        assert!(html.contains("<li class=\"gained\">line 11 gained</li>"));
+        assert!(html.contains("<li class=\"lost\">line 12 lost</li>"));
+        assert!(!html.contains("txmempool.cpp"));
+    }
+
+    #[test]
+    fn summary_fallback_compares_line_counts() {
+        let summary = |covered: usize| {
+            format!(
+                r#"{{"data":[{{"totals":{{"lines":{{"count":100,"covered":{covered},"percent":0}}}}}}],"type":"llvm.coverage.json.export","version":"2.0.1"}}"#
+            )
+        };
+        let before = parse_summary_covered_lines(summary(40).as_bytes()).unwrap();
+        let after = parse_summary_covered_lines(summary(55).as_bytes()).unwrap();
+
+        assert_eq!(
+            CoverageDelta::from_line_counts(before, after),
+            CoverageDelta {
+                gained: 15,
+                lost: 0,
+                unchanged: 40,
+            }
+        );
+        assert!(parse_summary_covered_lines(b"{\"data\":[]}").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+//! Export IR programs as standalone C or Python programs that replay the program's p2p messages
+//! against a node over plain TCP (v1 transport, regtest magic).
+//!
+//! Only `SendRawMessage` actions are replayed, other actions (e.g. `SetTime`) have no p2p
+//! equivalent. Every connection used by the program is opened as a fresh socket, so programs
+//! relying on connections from the snapshot need to perform the version handshake themselves.
+
+use std::fmt::Write;
+
+use bitcoin::{
+    Network,
+    hashes::{Hash, sha256d},
+    hex::FromHex,
+};
+use fuzzamoto_ir::{Program, compiler::Compiler};
+
+use crate::error::{CliError, Result};
+
+/// Default p2p port of a regtest node
+const DEFAULT_PORT: u16 = 18444;
+
+/// A p2p message serialized with its v1 transport header
+struct WireMessage {
+    connection: usize,
+    command: String,
+    bytes: Vec<u8>,
+}
+
+fn frame_message(command: &str, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(24 + payload.len());
+    bytes.extend_from_slice(&Network::Regtest.magic().to_bytes());
+
+    let mut command_bytes = [0u8; 12];
+    let len = command.len().min(command_bytes.len());
+    command_bytes[..len].copy_from_slice(&command.as_bytes()[..len]);
+    bytes.extend_from_slice(&command_bytes);
+
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&sha256d::Hash::hash(payload).as_byte_array()[..4]);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+fn wire_messages(program: &Program) -> Result<Vec<WireMessage>> {
+    let compiled = Compiler::new()
+        .compile(program)
+        .map_err(|e| CliError::InvalidInput(format!("Failed to compile program: {e:?}")))?;
+
+    compiled
+        .to_bitcoin_messages()
+        .into_iter()
+        .map(|message| {
+            let command = message.command.trim_end_matches('\0').to_string();
+            let payload = Vec::<u8>::from_hex(&message.payload_hex)
+                .map_err(|e| CliError::InvalidInput(format!("Invalid payload: {e}")))?;
+            Ok(WireMessage {
+                connection: message.connection,
+                bytes: frame_message(&command, &payload),
+                command,
+            })
+        })
+        .collect()
+}
+
+/// Escape everything but ASCII alphanumerics in a (potentially fuzzed) command name, so that it can
+/// be embedded in C comments and Python string literals
+fn escape_command(command: &str) -> String {
+    command
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_string(),
+            c if (c as u32) < 0x100 => format!("\\x{:02x}", c as u32),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+fn num_connections(messages: &[WireMessage]) -> usize {
+    messages
+        .iter()
+        .map(|message| message.connection + 1)
+        .max()
+        .unwrap_or(1)
+}
+
+fn c_array(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        out.push_str("    ");
+        for byte in chunk {
+            let _ = write!(out, "0x{byte:02x}, ");
+        }
+    }
+    out
+}
+
+/// Render `program` as a C source file replaying its messages
+pub fn program_to_c(program: &Program) -> Result<String> {
+    let messages = wire_messages(program)?;
+    let encoded = program.to_bytes_versioned();
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "// Generated by fuzzamoto-cli. Replays the p2p messages of an IR program.\n\
+         // Usage: ./replay [host] [port]\n\
+         #include <arpa/inet.h>\n\
+         #include <netinet/in.h>\n\
+         #include <stddef.h>\n\
+         #include <stdio.h>\n\
+         #include <stdlib.h>\n\
+         #include <sys/socket.h>\n\
+         #include <unistd.h>\n\
+         \n\
+         // postcard-encoded IR program\n\
+         unsigned char buf[] = {{\n{}\n}};\n\
+         \n\
+         #define NUM_CONNECTIONS {}\n",
+        c_array(&encoded),
+        num_connections(&messages)
+    );
+
+    for (i, message) in messages.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "// \"{}\" on connection {}\nstatic const unsigned char msg_{i}[] = {{\n{}\n}};\n",
+            escape_command(&message.command),
+            message.connection,
+            c_array(&message.bytes)
+        );
+    }
+
+    out.push_str(
+        "static int connect_to(const char *host, int port) {
+    struct sockaddr_in addr = {0};
+    addr.sin_family = AF_INET;
+    addr.sin_port = htons((unsigned short)port);
+    if (inet_pton(AF_INET, host, &addr.sin_addr) != 1) {
+        fprintf(stderr, \"invalid host: %s\\n\", host);
+        return -1;
+    }
+
+    int fd = socket(AF_INET, SOCK_STREAM, 0);
+    if (fd < 0) {
+        perror(\"socket\");
+        return -1;
+    }
+    if (connect(fd, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        perror(\"connect\");
+        close(fd);
+        return -1;
+    }
+    return fd;
+}
+
+static int send_message(int fd, const unsigned char *data, size_t len) {
+    while (len > 0) {
+        ssize_t sent = send(fd, data, len, 0);
+        if (sent <= 0) {
+            perror(\"send\");
+            return -1;
+        }
+        data += sent;
+        len -= (size_t)sent;
+    }
+    return 0;
+}
+
+int main(int argc, char **argv) {
+    const char *host = argc > 1 ? argv[1] : \"127.0.0.1\";
+",
+    );
+    let _ = writeln!(
+        out,
+        "    int port = argc > 2 ? atoi(argv[2]) : {DEFAULT_PORT};
+    int fds[NUM_CONNECTIONS];
+    for (int i = 0; i < NUM_CONNECTIONS; i++) {{
+        fds[i] = connect_to(host, port);
+        if (fds[i] < 0) {{
+            return 1;
+        }}
+    }}
+"
+    );
+
+    for (i, message) in messages.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "    if (send_message(fds[{}], msg_{i}, sizeof(msg_{i})) < 0) {{\n        return 1;\n    }}",
+            message.connection
+        );
+    }
+
+    out.push_str(
+        "
+    for (int i = 0; i < NUM_CONNECTIONS; i++) {
+        close(fds[i]);
+    }
+    return 0;
+}
+",
+    );
+
+    Ok(out)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Render `program` as a Python script replaying its messages
+pub fn program_to_python(program: &Program) -> Result<String> {
+    let messages = wire_messages(program)?;
+    let encoded = program.to_bytes_versioned();
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "#!/usr/bin/env python3\n\
+         # Generated by fuzzamoto-cli. Replays the p2p messages of an IR program.\n\
+         # Usage: python3 replay.py [host] [port]\n\
+         import socket\n\
+         import sys\n\
+         \n\
+         # postcard-encoded IR program\n\
+         BUF = bytes.fromhex(\"{}\")\n\
+         \n\
+         NUM_CONNECTIONS = {}\n\
+         \n\
+         # (connection, command, serialized message)\n\
+         MESSAGES = [",
+        hex(&encoded),
+        num_connections(&messages)
+    );
+    for message in &messages {
+        let _ = writeln!(
+            out,
+            "    ({}, \"{}\", bytes.fromhex(\"{}\")),",
+            message.connection,
+            escape_command(&message.command),
+            hex(&message.bytes)
+        );
+    }
+    let _ = write!(
+        out,
+        "]
+
+
+def main():
+    host = sys.argv[1] if len(sys.argv) > 1 else \"127.0.0.1\"
+    port = int(sys.argv[2]) if len(sys.argv) > 2 else {DEFAULT_PORT}
+    sockets = [socket.create_connection((host, port)) for _ in range(NUM_CONNECTIONS)]
+    for connection, _command, message in MESSAGES:
+        sockets[connection].sendall(message)
+    for sock in sockets:
+        sock.close()
+
+
+if __name__ == \"__main__\":
+    main()
+"
+    );
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::{Operation, ProgramBuilder, ProgramContext};
+    use std::process::Command;
+
+    fn program() -> Program {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 2,
+            timestamp: 0,
+        });
+        let payload = builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![1, 2]));
+        let mut msg_type = ['\0'; 12];
+        for (i, c) in "ping".chars().enumerate() {
+            msg_type[i] = c;
+        }
+        let msg_type = builder.force_append_expect_output(vec![], Operation::LoadMsgType(msg_type));
+        for connection in 0..2 {
+            let conn =
+                builder.force_append_expect_output(vec![], Operation::LoadConnection(connection));
+            builder.force_append(
+                vec![conn.index, msg_type.index, payload.index],
+                Operation::SendRawMessage,
+            );
+        }
+        builder.finalize().unwrap()
+    }
+
+    fn check_syntax(extension: &str, source: &str, command: &str, args: &[&str]) {
+        let path = std::env::temp_dir().join(format!(
+            "fuzzamoto-export-{}.{extension}",
+            std::process::id()
+        ));
+        std::fs::write(&path, source).unwrap();
+        let status = Command::new(command).args(args).arg(&path).status();
+        std::fs::remove_file(&path).unwrap();
+
+        match status {
+            Ok(status) => assert!(status.success(), "{command} rejected the exported program"),
+            // Skip if the toolchain is not available
+            Err(e) => eprintln!("skipping syntax check, failed to run {command}: {e}"),
+        }
+    }
+
+    #[test]
+    fn frames_messages() {
+        let bytes = frame_message("ping", &[1, 2]);
+        assert_eq!(&bytes[..4], &[0xfa, 0xbf, 0xb5, 0xda]);
+        assert_eq!(&bytes[4..8], b"ping");
+        assert_eq!(&bytes[16..20], &2u32.to_le_bytes());
+        assert_eq!(&bytes[24..], &[1, 2]);
+    }
+
+    #[test]
+    fn c_export_compiles() {
+        let source = program_to_c(&program()).unwrap();
+        assert!(source.contains("#define NUM_CONNECTIONS 2"));
+        assert_eq!(source.matches("send_message(fds[").count(), 2);
+        check_syntax("c", &source, "gcc", &["-fsyntax-only", "-Wall", "-Werror"]);
+    }
+
+    #[test]
+    fn python_export_compiles() {
+        let source = program_to_python(&program()).unwrap();
+        assert!(source.contains("NUM_CONNECTIONS = 2"));
+        check_syntax("py", &source, "python3", &["-m", "py_compile"]);
+    }
+}
@@ -1,6 +1,14 @@
 use crate::error::{CliError, Result};
 use crate::utils::{file_ops, nyx, process};
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Time the scenario has to finish a run on an empty input during `init --verify`
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time the temporary bitcoind has to (not) crash on startup during `init --verify`
+const BITCOIND_STARTUP_TIME: Duration = Duration::from_millis(500);
 
 pub struct InitCommand;
 
@@ -13,6 +21,7 @@ impl InitCommand {
         scenario: PathBuf,
         nyx_dir: PathBuf,
         rpc_path: Option<PathBuf>,
+        verify: bool,
     ) -> Result<()> {
         file_ops::ensure_sharedir_not_exists(&sharedir)?;
         file_ops::create_dir_all(&sharedir)?;
@@ -99,6 +108,9 @@ impl InitCommand {
         nyx::compile_packer_binaries(&nyx_dir)?;
         nyx::copy_packer_binaries(&nyx_dir, &sharedir)?;
         nyx::generate_nyx_config(&nyx_dir, &sharedir)?;
+        if let Err(e) = nyx::write_build_config(&nyx_dir) {
+            log::warn!("Failed to record the nyx directory: {}", e);
+        }
 
         // Create fuzz_no_pt.sh script
         let scenario_name = scenario
@@ -127,6 +139,188 @@ impl InitCommand {
             rpc_name,
         )?;
 
+        if verify {
+            if let Err(e) = Self::verify(&sharedir, &scenario, &binaries[0]) {
+                log::error!("Verification of the share directory setup failed: {}", e);
+                std::fs::remove_dir_all(&sharedir)?;
+                return Err(e);
+            }
+            log::info!("Verified share directory setup");
+        }
+
         Ok(())
     }
+
+    /// Check that `bitcoind` starts and that `scenario` runs on an empty input against it.
+    ///
+    /// A temporary regtest bitcoind (with its data directory inside `sharedir`) is started first,
+    /// then the scenario is executed with `FUZZAMOTO_INPUT=/dev/null` and has to exit successfully
+    /// within `VERIFY_TIMEOUT`.
+    pub fn verify(sharedir: &Path, scenario: &Path, bitcoind: &Path) -> Result<()> {
+        Self::verify_with_timeout(sharedir, scenario, bitcoind, VERIFY_TIMEOUT)
+    }
+
+    fn verify_with_timeout(
+        sharedir: &Path,
+        scenario: &Path,
+        bitcoind: &Path,
+        timeout: Duration,
+    ) -> Result<()> {
+        let datadir = sharedir.join("verify-datadir");
+        file_ops::create_dir_all(&datadir)?;
+
+        let result = Self::run_verification(&datadir, scenario, bitcoind, timeout);
+        let _ = std::fs::remove_dir_all(&datadir);
+        result
+    }
+
+    fn run_verification(
+        datadir: &Path,
+        scenario: &Path,
+        bitcoind: &Path,
+        timeout: Duration,
+    ) -> Result<()> {
+        log::info!("Starting temporary bitcoind: {}", bitcoind.display());
+        let mut node = Command::new(bitcoind)
+            .arg("-regtest")
+            .arg("-connect=0")
+            .arg("-listen=0")
+            .arg("-server=0")
+            .arg(format!("-datadir={}", datadir.display()))
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                CliError::ProcessError(format!(
+                    "Failed to start bitcoind {}: {}",
+                    bitcoind.display(),
+                    e
+                ))
+            })?;
+
+        std::thread::sleep(BITCOIND_STARTUP_TIME);
+        if let Some(status) = node.try_wait()? {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = node.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr);
+            }
+            return Err(CliError::ProcessError(format!(
+                "bitcoind exited during startup ({}): {}",
+                status,
+                stderr.trim()
+            )));
+        }
+
+        let result = Self::run_scenario(scenario, bitcoind, timeout);
+        process::kill_child(&mut node);
+        result
+    }
+
+    fn run_scenario(scenario: &Path, bitcoind: &Path, timeout: Duration) -> Result<()> {
+        log::info!("Running scenario: {}", scenario.display());
+        let mut child = Command::new(scenario)
+            .arg(bitcoind)
+            .env("FUZZAMOTO_INPUT", "/dev/null")
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| {
+                CliError::ProcessError(format!(
+                    "Failed to start scenario {}: {}",
+                    scenario.display(),
+                    e
+                ))
+            })?;
+
+        match process::wait_with_timeout(&mut child, timeout)? {
+            Some(status) if status.success() => Ok(()),
+            Some(status) => Err(CliError::ProcessError(format!(
+                "Scenario {} failed on an empty input ({})",
+                scenario.display(),
+                status
+            ))),
+            None => Err(CliError::ProcessError(format!(
+                "Scenario {} did not finish within {:?}",
+                scenario.display(),
+                timeout
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Create a scratch directory containing executable shell scripts named after `scripts`
+    fn setup(name: &str, scripts: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "fuzzamoto-cli-verify-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (script, body) in scripts {
+            let path = dir.join(script);
+            std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        dir
+    }
+
+    fn verify(dir: &Path) -> Result<()> {
+        InitCommand::verify_with_timeout(
+            dir,
+            &dir.join("scenario"),
+            &dir.join("bitcoind"),
+            Duration::from_secs(2),
+        )
+    }
+
+    #[test]
+    fn verify_succeeds() {
+        let dir = setup(
+            "ok",
+            &[
+                ("bitcoind", "exec sleep 30"),
+                ("scenario", "test \"$FUZZAMOTO_INPUT\" = /dev/null"),
+            ],
+        );
+        assert!(verify(&dir).is_ok());
+        assert!(!dir.join("verify-datadir").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify_detects_bitcoind_crash() {
+        let dir = setup(
+            "bitcoind-crash",
+            &[("bitcoind", "exit 1"), ("scenario", "exit 0")],
+        );
+        assert!(matches!(verify(&dir), Err(CliError::ProcessError(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify_detects_scenario_failure() {
+        let dir = setup(
+            "scenario-fail",
+            &[("bitcoind", "exec sleep 30"), ("scenario", "exit 1")],
+        );
+        assert!(matches!(verify(&dir), Err(CliError::ProcessError(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify_detects_scenario_timeout() {
+        let dir = setup(
+            "scenario-timeout",
+            &[("bitcoind", "exec sleep 30"), ("scenario", "exec sleep 30")],
+        );
+        assert!(matches!(verify(&dir), Err(CliError::ProcessError(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,21 +1,27 @@
 use clap::{Subcommand, ValueEnum};
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
 
-use fuzzamoto_ir::compiler::Compiler;
+use fuzzamoto_ir::compiler::{CompiledProgram, Compiler};
 use fuzzamoto_ir::{
-    AddTxToBlockGenerator, AddrRelayGenerator, AddrRelayV2Generator, AdvanceTimeGenerator,
-    BlockGenerator, BloomFilterAddGenerator, BloomFilterClearGenerator, BloomFilterLoadGenerator,
-    CompactFilterQueryGenerator, FullProgramContext, Generator, GetAddrGenerator, GetDataGenerator,
-    HeaderGenerator, InstructionContext, InventoryGenerator, LargeTxGenerator, LongChainGenerator,
-    OneParentOneChildGenerator, Program, ProgramBuilder, SendBlockGenerator, SendMessageGenerator,
-    SingleTxGenerator, TxoGenerator, WitnessGenerator,
+    FullProgramContext, GenerationEvent, Generator, PerTestcaseMetadata, Program, ProgramBuilder,
+    ProgramContext, default_generators, generate_program, generate_weighted_program,
 };
 
-use rand::Rng;
-use rand::rngs::ThreadRng;
-use rand::seq::SliceRandom;
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 
-use crate::error::{CliError, Result};
+use crate::{
+    commands::{
+        coverage_diff::CoverageDiffCommand,
+        export::{program_to_c, program_to_python},
+    },
+    error::{CliError, Result},
+    utils::file_ops::write_atomic,
+};
 
 pub struct IrCommand;
 
@@ -28,16 +34,100 @@ impl IrCommand {
                 programs,
                 context,
                 generators,
-            } => generate_ir(output, *iterations, *programs, context, generators),
-            IRCommands::Compile { input, output } => compile_ir(input, output),
+                seed,
+                generator_log,
+                weighted,
+                weights,
+                auto_weight,
+                auto_weight_output,
+            } => {
+                let mut rng: Box<dyn RngCore> = match seed {
+                    Some(seed) => Box::new(StdRng::seed_from_u64(*seed)),
+                    None => Box::new(rand::thread_rng()),
+                };
+                let weights = if *weighted {
+                    Some(generator_weights(
+                        context,
+                        generators,
+                        weights,
+                        *auto_weight,
+                        auto_weight_output,
+                        &mut rng,
+                    )?)
+                } else {
+                    None
+                };
+                generate_ir(
+                    output,
+                    *iterations,
+                    *programs,
+                    context,
+                    generators,
+                    generator_log,
+                    weights,
+                    rng,
+                )
+            }
+            IRCommands::FuzzSeed {
+                output,
+                context,
+                count_per_generator,
+                min_instructions,
+                generators,
+            } => fuzz_seed(
+                output,
+                context,
+                *count_per_generator,
+                *min_instructions,
+                generators,
+                Box::new(rand::thread_rng()),
+            ),
+            IRCommands::Compile {
+                input,
+                output,
+                strict_context,
+            } => compile_ir(input, output, strict_context),
             IRCommands::Print { input, json } => print_ir(input, *json),
+            IRCommands::ParseFromText { input, output } => parse_ir_text(input, output),
             IRCommands::Convert {
                 from,
                 to,
                 input,
                 output,
-            } => convert_ir(from, to, input, output),
-            IRCommands::Analyze { input } => analyze_ir(input),
+                force_migrate,
+            } => convert_ir(from, to, input, output, *force_migrate),
+            IRCommands::Analyze {
+                input,
+                compare_formats,
+                export_csv,
+                export_json,
+                filter_invalid,
+            } => analyze_ir(
+                input,
+                *compare_formats,
+                export_csv,
+                export_json,
+                *filter_invalid,
+            ),
+            IRCommands::Inspect { input } => inspect_ir(input),
+            IRCommands::Roundtrip { input } => roundtrip_ir(input),
+            IRCommands::GenerationStack {
+                generator_log,
+                input,
+            } => print_generation_stack(generator_log, input),
+            IRCommands::CoverageDiff {
+                before_corpus,
+                after_corpus,
+                bitcoind,
+                scenario,
+                output,
+            } => CoverageDiffCommand::execute(
+                before_corpus,
+                after_corpus,
+                bitcoind,
+                scenario,
+                output,
+            ),
         }
     }
 }
@@ -64,6 +154,59 @@ pub enum IRCommands {
             help = "Optional comma-separated list of generator names (defaults to all)"
         )]
         generators: Option<Vec<String>>,
+        #[arg(long, help = "Optional seed for deterministic generation")]
+        seed: Option<u64>,
+        #[arg(
+            long,
+            help = "Optional path to a json file recording the generation stack of each program"
+        )]
+        generator_log: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Choose generators according to weights (from --weights or --auto-weight) instead of uniformly"
+        )]
+        weighted: bool,
+        #[arg(
+            long,
+            requires = "weighted",
+            conflicts_with = "auto_weight",
+            help = "Path to a json file mapping generator names to weights (unlisted generators default to 1.0)"
+        )]
+        weights: Option<PathBuf>,
+        #[arg(
+            long,
+            requires = "weighted",
+            help = "Weight generators inversely proportional to the number of instructions they produce on an empty program"
+        )]
+        auto_weight: bool,
+        #[arg(
+            long,
+            requires = "auto_weight",
+            help = "Optional path to write the auto-computed weights to (usable with --weights)"
+        )]
+        auto_weight_output: Option<PathBuf>,
+    },
+    /// Generate a seed corpus with the same number of programs for every generator
+    FuzzSeed {
+        #[arg(long, help = "Path to the output directory for the seed corpus")]
+        output: PathBuf,
+        #[arg(long, help = "Path to the program context file")]
+        context: PathBuf,
+        #[arg(long, help = "Number of IR programs to generate per generator")]
+        count_per_generator: usize,
+        #[arg(
+            long,
+            help = "Minimum number of instructions of a generated program",
+            default_value_t = 1
+        )]
+        min_instructions: usize,
+        #[arg(
+            long,
+            value_delimiter = ',',
+            num_args = 1..,
+            help = "Optional comma-separated list of generator names (defaults to all)"
+        )]
+        generators: Option<Vec<String>>,
     },
     /// Compile fuzzamoto IR
     Compile {
@@ -71,6 +214,11 @@ pub enum IRCommands {
         input: PathBuf,
         #[arg(long, help = "Path to the output file/directory for the compiled IR")]
         output: PathBuf,
+        #[arg(
+            long,
+            help = "Path to a program context file to validate the programs against (programs referencing txos, headers or connections missing from the context fail to compile)"
+        )]
+        strict_context: Option<PathBuf>,
     },
 
     /// Convert fuzzamoto corpora
@@ -83,6 +231,12 @@ pub enum IRCommands {
         input: PathBuf,
         #[arg(long, help = "Path to the output file/directory for the converted IR")]
         output: PathBuf,
+        #[arg(
+            long,
+            help = "Migrate postcard IR written with a different schema version to the current schema",
+            default_value_t = false
+        )]
+        force_migrate: bool,
     },
 
     /// Print human readable IR
@@ -94,31 +248,171 @@ pub enum IRCommands {
         input: PathBuf,
     },
 
+    /// Parse human readable IR (as printed by `ir print`) into a postcard encoded program
+    ParseFromText {
+        #[arg(long, help = "Path to the input file with the human readable IR")]
+        input: PathBuf,
+        #[arg(long, help = "Path to the output file for the postcard encoded IR")]
+        output: PathBuf,
+    },
+
     /// Analyze IR corpus statistics
     Analyze {
         #[arg(help = "Path to the input IR directory to analyze")]
         input: PathBuf,
+        #[arg(
+            long,
+            help = "Compare the serialized size of the corpus across all serialization formats",
+            default_value_t = false
+        )]
+        compare_formats: bool,
+        #[arg(
+            long,
+            help = "Optional path to a csv file with statistics for every program"
+        )]
+        export_csv: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Optional path to a json file with statistics (incl. operation frequencies) for every program"
+        )]
+        export_json: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Exclude programs that are not statically valid",
+            default_value_t = false
+        )]
+        filter_invalid: bool,
+    },
+
+    /// Print the bitcoin p2p messages sent by an IR program
+    Inspect {
+        #[arg(help = "Path to the input IR file to be inspected")]
+        input: PathBuf,
+    },
+
+    /// Check that IR programs survive a postcard -> json -> postcard round trip unchanged
+    Roundtrip {
+        #[arg(help = "Path to the input IR file/directory to be checked")]
+        input: PathBuf,
+    },
+
+    /// Print the generators that produced each instruction of a generated IR program
+    GenerationStack {
+        #[arg(long, help = "Path to the generator log written by `ir generate`")]
+        generator_log: PathBuf,
+        #[arg(help = "Path to the input IR file")]
+        input: PathBuf,
+    },
+
+    /// Create a html report of the coverage lines gained and lost between two corpus versions
+    CoverageDiff {
+        #[arg(long, help = "Path to the corpus directory before the change")]
+        before_corpus: PathBuf,
+        #[arg(long, help = "Path to the corpus directory after the change")]
+        after_corpus: PathBuf,
+        #[arg(long, help = "Path to the coverage instrumented bitcoind binary")]
+        bitcoind: PathBuf,
+        #[arg(
+            long,
+            help = "Path to the fuzzamoto scenario binary that should be run with coverage measurer"
+        )]
+        scenario: PathBuf,
+        #[arg(
+            long,
+            help = "Path to the output directory for the profiles and the diff report"
+        )]
+        output: PathBuf,
     },
 }
 
-#[derive(ValueEnum, Debug, Clone)]
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CorpusFormat {
     Json,
-    Postcard, // Default corpus format (https://github.com/jamesmunns/postcard)
+    Postcard,    // Default corpus format (https://github.com/jamesmunns/postcard)
+    MessagePack, // https://msgpack.org
+    Cbor,        // https://cbor.io
+    C,           // Output only, C program replaying the program's messages
+    Python,      // Output only, Python script replaying the program's messages
 }
 
-pub fn generate_ir(
-    output: &PathBuf,
-    iterations: usize,
-    programs: usize,
-    context: &PathBuf,
-    generator_names: &Option<Vec<String>>,
-) -> Result<()> {
-    let context = std::fs::read(context.clone())?;
-    let context: FullProgramContext = postcard::from_bytes(&context)?;
+impl CorpusFormat {
+    /// Formats that programs can be both encoded to and decoded from
+    const SERIALIZATION_FORMATS: [CorpusFormat; 4] = [
+        CorpusFormat::Postcard,
+        CorpusFormat::Json,
+        CorpusFormat::MessagePack,
+        CorpusFormat::Cbor,
+    ];
+
+    /// Identify the format of an encoded program from its leading bytes.
+    ///
+    /// Programs are encoded as a two field struct, which starts with `{` in json, a 2-element
+    /// array (`0x92`) in MessagePack and a 2-entry map (`0xa2`, optionally preceded by the
+    /// self-describe tag `0xd9d9f7`) in CBOR. Postcard has no prefix, so it is the fallback. As
+    /// these prefixes may also start a valid postcard encoding, a candidate is only returned if
+    /// `bytes` actually decode in that format.
+    pub fn detect(bytes: &[u8]) -> Option<CorpusFormat> {
+        let first = bytes.iter().find(|b| !b.is_ascii_whitespace());
+        let candidate = match first {
+            Some(b'{') => Some(CorpusFormat::Json),
+            Some(0x92) => Some(CorpusFormat::MessagePack),
+            Some(0xa2) | Some(0xd9) => Some(CorpusFormat::Cbor),
+            _ => None,
+        };
+
+        candidate
+            .into_iter()
+            .chain(std::iter::once(CorpusFormat::Postcard))
+            .find(|format| format.decode(bytes).is_ok())
+    }
 
-    let mut rng = rand::thread_rng();
-    let mut generators = all_generators(&context);
+    fn decode(&self, bytes: &[u8]) -> Result<Program> {
+        Ok(match self {
+            CorpusFormat::Postcard => Program::from_bytes_current(bytes)?,
+            CorpusFormat::Json => serde_json::from_slice(bytes)?,
+            CorpusFormat::MessagePack => rmp_serde::from_slice(bytes)?,
+            CorpusFormat::Cbor => ciborium::from_reader(bytes)?,
+            CorpusFormat::C | CorpusFormat::Python => {
+                return Err(CliError::InvalidInput(format!(
+                    "{self:?} is an output only format"
+                )));
+            }
+        })
+    }
+
+    fn encode(&self, program: &Program) -> Result<Vec<u8>> {
+        Ok(match self {
+            CorpusFormat::Postcard => program.to_bytes_versioned(),
+            CorpusFormat::Json => serde_json::to_vec(program)?,
+            CorpusFormat::MessagePack => rmp_serde::to_vec(program)?,
+            CorpusFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(program, &mut bytes)?;
+                bytes
+            }
+            CorpusFormat::C => program_to_c(program)?.into_bytes(),
+            CorpusFormat::Python => program_to_python(program)?.into_bytes(),
+        })
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            CorpusFormat::Postcard => "ir",
+            CorpusFormat::Json => "json",
+            CorpusFormat::MessagePack => "msgpack",
+            CorpusFormat::Cbor => "cbor",
+            CorpusFormat::C => "c",
+            CorpusFormat::Python => "py",
+        }
+    }
+}
+
+/// Default generators for `context`, restricted to `generator_names` (if any)
+fn select_generators<R: RngCore>(
+    context: &FullProgramContext,
+    generator_names: &Option<Vec<String>>,
+) -> Result<Vec<Box<dyn Generator<R>>>> {
+    let mut generators = default_generators(context);
     if let Some(names) = generator_names {
         let requested: Vec<_> = names.iter().map(|s| s.to_lowercase()).collect();
         generators.retain(|g| {
@@ -138,98 +432,295 @@ pub fn generate_ir(
         ));
     }
 
-    for _ in 0..programs {
-        let mut used_generators = Vec::new();
-        let mut program = Program::unchecked_new(context.context.clone(), vec![]);
-
-        let mut insertion_index = 0;
-        for _i in 0..rng.gen_range(1..iterations) {
-            let mut builder = ProgramBuilder::new(program.context.clone());
-            if !program.instructions.is_empty() {
-                let instrs = &program.instructions[..insertion_index];
-                builder.append_all(instrs.iter().cloned()).unwrap();
-            }
+    Ok(generators)
+}
 
-            let variable_threshold = builder.variable_count();
+/// Number of programs generated per generator to compute `--auto-weight` weights
+const AUTO_WEIGHT_SAMPLES: usize = 16;
 
-            let generator = generators.choose(&mut rng).unwrap();
-            if let Err(_) = generator.generate(&mut builder, &mut rng, None) {
-                continue;
-            }
+/// Resolve the weights of the selected generators (in selection order), either from a json file
+/// mapping generator names to weights or by running every generator on an empty program
+/// (`--auto-weight`).
+fn generator_weights<R: RngCore>(
+    context: &PathBuf,
+    generator_names: &Option<Vec<String>>,
+    weights_file: &Option<PathBuf>,
+    auto_weight: bool,
+    auto_weight_output: &Option<PathBuf>,
+    rng: &mut R,
+) -> Result<Vec<f64>> {
+    let context = std::fs::read(context.clone())?;
+    let context: FullProgramContext = postcard::from_bytes(&context)?;
+    let generators = select_generators::<R>(&context, generator_names)?;
+
+    if auto_weight {
+        let weights = auto_generator_weights(&context.context, &generators, rng);
+        for (generator, weight) in generators.iter().zip(&weights) {
+            log::info!("{}: {:.4}", generator.name(), weight);
+        }
+        if let Some(path) = auto_weight_output {
+            let named: BTreeMap<&str, f64> = generators
+                .iter()
+                .map(|generator| generator.name())
+                .zip(weights.iter().copied())
+                .collect();
+            write_atomic(path, serde_json::to_string_pretty(&named)?.as_bytes())?;
+        }
+        return Ok(weights);
+    }
 
-            used_generators.push(generator.name().to_string());
+    let Some(weights_file) = weights_file else {
+        return Err(CliError::InvalidInput(
+            "--weighted requires either --weights or --auto-weight".to_string(),
+        ));
+    };
+    let named: BTreeMap<String, f64> = serde_json::from_slice(&std::fs::read(weights_file)?)?;
+    weights_from_names(&generators, &named)
+}
 
-            let second_half = Program::unchecked_new(
-                builder.context().clone(),
-                program.instructions[insertion_index..]
-                    .iter()
-                    .cloned()
-                    .collect(),
-            );
+/// Map generator names (case-insensitive) to the weights of `generators`. Generators missing from
+/// `named` get a weight of 1.0.
+fn weights_from_names<R: RngCore>(
+    generators: &[Box<dyn Generator<R>>],
+    named: &BTreeMap<String, f64>,
+) -> Result<Vec<f64>> {
+    let named: BTreeMap<String, f64> = named
+        .iter()
+        .map(|(name, weight)| (name.to_lowercase(), *weight))
+        .collect();
+
+    if let Some(unknown) = named.keys().find(|name| {
+        !generators
+            .iter()
+            .any(|generator| generator.name().to_lowercase() == **name)
+    }) {
+        return Err(CliError::InvalidInput(format!(
+            "Weight given for unknown or unselected generator: {}",
+            unknown
+        )));
+    }
 
-            builder
-                .append_program(
-                    second_half,
-                    variable_threshold,
-                    builder.variable_count() - variable_threshold,
-                )
-                .unwrap();
+    Ok(generators
+        .iter()
+        .map(|generator| {
+            named
+                .get(&generator.name().to_lowercase())
+                .copied()
+                .unwrap_or(1.0)
+        })
+        .collect())
+}
+
+/// Run every generator `AUTO_WEIGHT_SAMPLES` times on an empty program and weight it inversely
+/// proportional to the mean number of instructions of the resulting (compilable) programs.
+fn auto_generator_weights<R: RngCore>(
+    context: &ProgramContext,
+    generators: &[Box<dyn Generator<R>>],
+    rng: &mut R,
+) -> Vec<f64> {
+    let mut compiler = Compiler::new();
+    let mean_sizes: Vec<Option<f64>> = generators
+        .iter()
+        .map(|generator| {
+            let sizes: Vec<usize> = (0..AUTO_WEIGHT_SAMPLES)
+                .filter_map(|_| {
+                    let mut builder = ProgramBuilder::new(context.clone());
+                    generator.generate(&mut builder, rng, None).ok()?;
+                    let program = builder.finalize().ok()?;
+                    compiler.reset();
+                    compiler.compile(&program).ok()?;
+                    Some(program.instructions.len())
+                })
+                .collect();
+
+            if sizes.is_empty() {
+                log::warn!(
+                    "{} failed to generate a program on its own, assigning it the lowest weight",
+                    generator.name()
+                );
+                return None;
+            }
+            Some(sizes.iter().sum::<usize>() as f64 / sizes.len() as f64)
+        })
+        .collect();
+
+    inverse_size_weights(&mean_sizes)
+}
+
+/// Compute weights inversely proportional to the given program sizes. Generators without a size
+/// (i.e. that failed to generate) get the lowest weight of all others, so that they remain
+/// selectable in programs providing the variables they need.
+fn inverse_size_weights(sizes: &[Option<f64>]) -> Vec<f64> {
+    let weights: Vec<Option<f64>> = sizes
+        .iter()
+        .map(|size| size.map(|size| 1.0 / size.max(1.0)))
+        .collect();
+    let lowest = weights
+        .iter()
+        .flatten()
+        .copied()
+        .reduce(f64::min)
+        .unwrap_or(1.0);
+
+    weights
+        .into_iter()
+        .map(|weight| weight.unwrap_or(lowest))
+        .collect()
+}
 
-            program = builder.finalize().unwrap();
-            insertion_index = program
-                .get_random_instruction_index(&mut rng, InstructionContext::Global)
-                .unwrap()
-                .max(1);
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all, fields(programs, iterations))]
+pub fn generate_ir(
+    output: &PathBuf,
+    iterations: usize,
+    programs: usize,
+    context: &PathBuf,
+    generator_names: &Option<Vec<String>>,
+    generator_log: &Option<PathBuf>,
+    weights: Option<Vec<f64>>,
+    mut rng: Box<dyn RngCore>,
+) -> Result<()> {
+    let context = std::fs::read(context.clone())?;
+    let context: FullProgramContext = postcard::from_bytes(&context)?;
+    let generators = select_generators(&context, generator_names)?;
+
+    let weights = match weights {
+        Some(weights) if weights.len() != generators.len() => {
+            return Err(CliError::InvalidInput(format!(
+                "Expected {} generator weights, got {}",
+                generators.len(),
+                weights.len()
+            )));
         }
+        Some(weights) => Some(
+            WeightedIndex::new(&weights)
+                .map_err(|e| CliError::InvalidInput(format!("Invalid generator weights: {}", e)))?,
+        ),
+        None => None,
+    };
+
+    let mut log = BTreeMap::new();
+    for _ in 0..programs {
+        let (program, meta) = generate_weighted_program(
+            &context.context,
+            &generators,
+            weights.as_ref(),
+            iterations,
+            &mut rng,
+        );
 
         let file_name = output.join(format!("{:8x}.ir", rng.r#gen::<u64>()));
-        let bytes = postcard::to_allocvec(&program)?;
+        let bytes = program.to_bytes_versioned();
         std::fs::write(&file_name, &bytes)?;
 
         log::info!(
             "Generated IR: {} ({:?})",
             file_name.display(),
-            used_generators.join("-")
+            meta.generation_stack
+                .iter()
+                .map(|event| event.generator_name.as_str())
+                .collect::<Vec<_>>()
+                .join("-")
+        );
+        log.insert(
+            file_name.file_name().unwrap().to_string_lossy().to_string(),
+            meta.generation_stack,
         );
     }
 
+    if let Some(generator_log) = generator_log {
+        std::fs::write(generator_log, serde_json::to_string_pretty(&log)?)?;
+    }
+
     Ok(())
 }
 
-fn all_generators(context: &FullProgramContext) -> Vec<Box<dyn Generator<ThreadRng>>> {
-    vec![
-        Box::new(AdvanceTimeGenerator::default()),
-        Box::new(HeaderGenerator::new(context.headers.clone())),
-        Box::new(BlockGenerator::default()),
-        Box::new(BloomFilterLoadGenerator::default()),
-        Box::new(BloomFilterAddGenerator::default()),
-        Box::new(BloomFilterClearGenerator::default()),
-        Box::new(CompactFilterQueryGenerator::default()),
-        Box::new(GetDataGenerator::default()),
-        Box::new(InventoryGenerator::default()),
-        Box::new(SendBlockGenerator::default()),
-        Box::new(AddTxToBlockGenerator::default()),
-        Box::new(SendMessageGenerator::default()),
-        Box::new(WitnessGenerator::new()),
-        Box::new(SingleTxGenerator::default()),
-        Box::new(OneParentOneChildGenerator::default()),
-        Box::new(LongChainGenerator::default()),
-        Box::new(LargeTxGenerator::default()),
-        Box::new(TxoGenerator::new(context.txos.clone())),
-        Box::new(AddrRelayGenerator::default()),
-        Box::new(AddrRelayV2Generator::default()),
-        Box::new(GetAddrGenerator::default()),
-    ]
-}
-
-fn compile_ir_file(input: &PathBuf, output: &PathBuf) -> Result<()> {
+/// Max number of generator invocations per seed program
+const FUZZ_SEED_ITERATIONS: usize = 10;
+/// Max number of attempts at generating a seed program of at least `--min-instructions`
+/// instructions
+const FUZZ_SEED_MAX_ATTEMPTS: usize = 100;
+
+/// Generate `count_per_generator` programs for every selected generator, using each generator
+/// exclusively. Every program `<index>-<hash>.ir` is accompanied by a `<index>-<hash>.gen` sidecar
+/// file holding the name of the generator that produced it. Programs are numbered by ascending
+/// size, so that simple seeds come first.
+///
+/// Generators that depend on variables produced by other generators (e.g. transaction
+/// generators needing txos) may fail to produce programs of `min_instructions`, in which case
+/// they are skipped with a warning.
+pub fn fuzz_seed(
+    output: &PathBuf,
+    context: &PathBuf,
+    count_per_generator: usize,
+    min_instructions: usize,
+    generator_names: &Option<Vec<String>>,
+    mut rng: Box<dyn RngCore>,
+) -> Result<()> {
+    let context = std::fs::read(context.clone())?;
+    let context: FullProgramContext = postcard::from_bytes(&context)?;
+    let generators = select_generators(&context, generator_names)?;
+
+    let mut seeds = Vec::new();
+    for generator in generators {
+        let name = generator.name();
+        let generators = [generator];
+        let mut programs = Vec::new();
+        for _ in 0..count_per_generator * FUZZ_SEED_MAX_ATTEMPTS {
+            if programs.len() == count_per_generator {
+                break;
+            }
+            let (program, _) = generate_program(
+                &context.context,
+                &generators,
+                FUZZ_SEED_ITERATIONS,
+                &mut rng,
+            );
+            if program.instructions.len() >= min_instructions.max(1) {
+                programs.push(program.to_bytes_versioned());
+            }
+        }
+
+        if programs.len() < count_per_generator {
+            log::warn!(
+                "{name} only produced {} of {count_per_generator} programs with at least {min_instructions} instructions, skipping",
+                programs.len()
+            );
+            continue;
+        }
+        seeds.extend(programs.into_iter().map(|bytes| (name, bytes)));
+    }
+
+    seeds.sort_by_key(|(_, bytes)| bytes.len());
+    for (index, (name, bytes)) in seeds.iter().enumerate() {
+        let file_name = output.join(format!("{index:06}-{:016x}.ir", rng.r#gen::<u64>()));
+        std::fs::write(&file_name, bytes)?;
+        std::fs::write(file_name.with_extension("gen"), format!("{name}\n"))?;
+        log::info!("Generated seed: {} ({name})", file_name.display());
+    }
+
+    Ok(())
+}
+
+fn compile_ir_file(
+    input: &PathBuf,
+    output: &PathBuf,
+    context: Option<&FullProgramContext>,
+    cache: &mut HashMap<[u8; 32], CompiledProgram>,
+) -> Result<()> {
     assert!(input.is_file());
 
     let bytes = std::fs::read(input)?;
-    let program: Program = postcard::from_bytes(&bytes)?;
-
-    let mut compiler = Compiler::new();
-    let compiled = compiler.compile(&program).unwrap();
+    let program = Program::from_bytes_versioned(&bytes)?;
+
+    let compiled = match context {
+        Some(context) => Compiler::with_seed(program.hash_stable())
+            .compile_with_context(&program, context)
+            .map_err(|e| {
+                CliError::InvalidInput(format!("Failed to compile {}: {}", input.display(), e))
+            })?,
+        None => Compiler::compile_cached(&program, cache).unwrap(),
+    };
 
     let bytes = postcard::to_allocvec(&compiled)?;
     std::fs::write(output, &bytes)?;
@@ -237,28 +728,59 @@ fn compile_ir_file(input: &PathBuf, output: &PathBuf) -> Result<()> {
     Ok(())
 }
 
-fn compile_ir_dir(input: &PathBuf, output: &PathBuf) -> Result<()> {
+fn compile_ir_dir(
+    input: &PathBuf,
+    output: &PathBuf,
+    context: Option<&FullProgramContext>,
+) -> Result<()> {
+    // Identical programs in the corpus are only compiled once
+    let mut cache = HashMap::new();
+    let mut files = 0;
     for entry in input.read_dir()? {
         let path = entry?.path();
         if path.is_file() && !path.file_name().unwrap().to_str().unwrap().starts_with(".") {
             log::trace!("Compiling {:?}", path);
-            compile_ir_file(
+            files += 1;
+            let result = compile_ir_file(
                 &path,
                 &output
                     .join(path.file_name().unwrap())
                     .with_extension("prog"),
-            )?;
+                context,
+                &mut cache,
+            );
+            // Programs that don't match the context are skipped instead of aborting the whole
+            // directory
+            match result {
+                Err(CliError::InvalidInput(e)) if context.is_some() => log::warn!("{}", e),
+                result => result?,
+            }
         }
     }
+    if context.is_none() {
+        log::info!("Compiled {} programs ({} unique)", files, cache.len());
+    }
 
     Ok(())
 }
 
-pub fn compile_ir(input: &PathBuf, output: &PathBuf) -> Result<()> {
+pub fn compile_ir(
+    input: &PathBuf,
+    output: &PathBuf,
+    strict_context: &Option<PathBuf>,
+) -> Result<()> {
+    let context = match strict_context {
+        Some(path) => {
+            let bytes = std::fs::read(path)?;
+            Some(postcard::from_bytes::<FullProgramContext>(&bytes)?)
+        }
+        None => None,
+    };
+
     if input.is_file() {
-        compile_ir_file(input, output)?;
+        compile_ir_file(input, output, context.as_ref(), &mut HashMap::new())?;
     } else if input.is_dir() && output.is_dir() {
-        compile_ir_dir(input, output)?;
+        compile_ir_dir(input, output, context.as_ref())?;
     } else {
         return Err(CliError::InvalidInput(
             "Invalid input or output".to_string(),
@@ -270,7 +792,13 @@ pub fn compile_ir(input: &PathBuf, output: &PathBuf) -> Result<()> {
 
 pub fn print_ir(input: &PathBuf, json: bool) -> Result<()> {
     let bytes = std::fs::read(input)?;
-    let program: Program = postcard::from_bytes(&bytes)?;
+    let Some(format) = CorpusFormat::detect(&bytes) else {
+        return Err(CliError::InvalidInput(format!(
+            "Unknown IR format: {}",
+            input.display()
+        )));
+    };
+    let program = format.decode(&bytes)?;
 
     if json {
         println!("{}", serde_json::to_string(&program)?);
@@ -280,27 +808,137 @@ pub fn print_ir(input: &PathBuf, json: bool) -> Result<()> {
     Ok(())
 }
 
+pub fn parse_ir_text(input: &PathBuf, output: &PathBuf) -> Result<()> {
+    let text = std::fs::read_to_string(input)?;
+    let program: Program = text.parse().map_err(|e| {
+        CliError::InvalidInput(format!("Failed to parse {}: {}", input.display(), e))
+    })?;
+    write_atomic(output, &program.to_bytes_versioned())?;
+
+    Ok(())
+}
+
+pub fn print_generation_stack(generator_log: &PathBuf, input: &PathBuf) -> Result<()> {
+    let bytes = std::fs::read(input)?;
+    let program = Program::from_bytes_versioned(&bytes)?;
+
+    let log: BTreeMap<String, Vec<GenerationEvent>> =
+        serde_json::from_slice(&std::fs::read(generator_log)?)?;
+    let file_name = input
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let Some(generation_stack) = log.get(&file_name) else {
+        return Err(CliError::InvalidInput(format!(
+            "No generation stack for {file_name} in {}",
+            generator_log.display()
+        )));
+    };
+
+    let meta = PerTestcaseMetadata {
+        generation_stack: generation_stack.clone(),
+        ..Default::default()
+    };
+
+    for event in &meta.generation_stack {
+        println!(
+            "{}: {}..{}",
+            event.generator_name, event.instruction_range.0, event.instruction_range.1
+        );
+    }
+    println!();
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        println!(
+            "{index:>5} {:<48} {}",
+            meta.generators_at_instruction(index).join(" > "),
+            instruction.operation
+        );
+    }
+
+    Ok(())
+}
+
+pub fn inspect_ir(input: &PathBuf) -> Result<()> {
+    let bytes = std::fs::read(input)?;
+    let program = Program::from_bytes_versioned(&bytes)?;
+
+    let mut compiler = Compiler::new();
+    let compiled = compiler
+        .compile(&program)
+        .map_err(|e| CliError::InvalidInput(format!("Failed to compile IR: {}", e)))?;
+
+    for message in compiled.to_bitcoin_messages() {
+        println!("{}", serde_json::to_string(&message)?);
+    }
+    Ok(())
+}
+
+/// Round trip postcard encoded IR through json and check that neither the postcard encoding nor
+/// the human readable representation changed
+fn roundtrip_ir_bytes(bytes: &[u8]) -> Result<()> {
+    let program = Program::from_bytes_current(bytes)?;
+    let json = serde_json::to_vec(&program)?;
+    let from_json: Program = serde_json::from_slice(&json)?;
+
+    if from_json.to_bytes_versioned() != bytes {
+        return Err(CliError::InvalidInput(
+            "postcard encoding changed after json round trip".to_string(),
+        ));
+    }
+    if program.to_string() != from_json.to_string() {
+        return Err(CliError::InvalidInput(
+            "printed program changed after json round trip".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn roundtrip_ir(input: &PathBuf) -> Result<()> {
+    if input.is_file() {
+        return roundtrip_ir_bytes(&std::fs::read(input)?);
+    }
+
+    if !input.is_dir() {
+        return Err(CliError::FileNotFound(input.display().to_string()));
+    }
+
+    let mut failures = 0;
+    for entry in input.read_dir()? {
+        let path = entry?.path();
+        if path.is_file()
+            && !path.file_name().unwrap().to_str().unwrap().starts_with(".")
+            && let Err(e) = roundtrip_ir_bytes(&std::fs::read(&path)?)
+        {
+            log::error!("Round trip failed for {:?}: {}", path, e);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        return Err(CliError::InvalidInput(format!(
+            "{} program(s) failed the round trip",
+            failures
+        )));
+    }
+
+    Ok(())
+}
+
 fn convert_ir_dir(
     from: &CorpusFormat,
     to: &CorpusFormat,
     input: &PathBuf,
     output: &PathBuf,
+    force_migrate: bool,
 ) -> Result<()> {
     for entry in input.read_dir()? {
         let path = entry?.path();
         if path.is_file() && !path.file_name().unwrap().to_str().unwrap().starts_with(".") {
             let mut new_path = output.join(path.file_name().unwrap().to_str().unwrap());
+            new_path.set_extension(to.extension());
 
-            match *to {
-                CorpusFormat::Postcard => {
-                    new_path.set_extension("ir");
-                }
-                CorpusFormat::Json => {
-                    new_path.set_extension("json");
-                }
-            }
-
-            if let Err(e) = convert_ir_file(from, to, &path, &new_path) {
+            if let Err(e) = convert_ir_file(from, to, &path, &new_path, force_migrate) {
                 log::warn!("Failed to convert from {:?} to {:?}: {}", path, new_path, e);
             }
         }
@@ -309,23 +947,31 @@ fn convert_ir_dir(
     Ok(())
 }
 
+/// Decode a program in the `from` format. Postcard encoded programs written with a different
+/// schema version are migrated to the current schema if `force_migrate` is set (see
+/// `Program::from_bytes_versioned`).
+fn decode_program(from: &CorpusFormat, bytes: &[u8], force_migrate: bool) -> Result<Program> {
+    if force_migrate && *from == CorpusFormat::Postcard {
+        return Ok(Program::from_bytes_versioned(bytes)?);
+    }
+
+    from.decode(bytes).map_err(|e| match e {
+        CliError::SchemaError(e) => CliError::InvalidInput(format!(
+            "{e} (the program might have been written with a different schema version, use --force-migrate to migrate it)"
+        )),
+        e => e,
+    })
+}
+
 fn convert_ir_file(
     from: &CorpusFormat,
     to: &CorpusFormat,
     input: &PathBuf,
     output: &PathBuf,
+    force_migrate: bool,
 ) -> Result<()> {
-    let bytes = std::fs::read(input)?;
-    let program: Program = match *from {
-        CorpusFormat::Postcard => postcard::from_bytes(&bytes)?,
-        CorpusFormat::Json => serde_json::from_slice(&bytes)?,
-    };
-
-    let bytes = match *to {
-        CorpusFormat::Postcard => postcard::to_allocvec(&program)?,
-        CorpusFormat::Json => serde_json::to_vec(&program)?,
-    };
-    std::fs::write(output, &bytes)?;
+    let program = decode_program(from, &std::fs::read(input)?, force_migrate)?;
+    std::fs::write(output, to.encode(&program)?)?;
 
     Ok(())
 }
@@ -335,11 +981,12 @@ pub fn convert_ir(
     to: &CorpusFormat,
     input: &PathBuf,
     output: &PathBuf,
+    force_migrate: bool,
 ) -> Result<()> {
     if input.is_file() {
-        convert_ir_file(from, to, input, output)?;
+        convert_ir_file(from, to, input, output, force_migrate)?;
     } else if input.is_dir() && output.is_dir() {
-        convert_ir_dir(from, to, input, output)?;
+        convert_ir_dir(from, to, input, output, force_migrate)?;
     } else {
         return Err(CliError::InvalidInput(
             "Invalid input or output".to_string(),
@@ -422,7 +1069,117 @@ fn print_size_scatter_plot(points: &[Point]) {
     println!("\nDensity: · (1 program)  : (2-3)  ⁘ (4-5)  ⬢ (>5 programs)");
 }
 
-pub fn analyze_ir(input: &PathBuf) -> Result<()> {
+/// Print the total serialized size of `programs` in every serialization format
+fn print_format_comparison(programs: &[Program]) -> Result<()> {
+    let mut sizes = Vec::new();
+    for format in CorpusFormat::SERIALIZATION_FORMATS {
+        let mut size = 0;
+        for program in programs {
+            size += format.encode(program)?.len();
+        }
+        sizes.push((format, size));
+    }
+    let postcard_size = sizes[0].1.max(1);
+
+    println!("{:<12} | {:>12} | Ratio vs Postcard", "Format", "Size");
+    println!("{:-<12}-|-{:->12}-|-{:-<17}", "", "", "");
+    for (format, size) in sizes {
+        println!(
+            "{:<12} | {:>12} | {:.2}",
+            format!("{format:?}"),
+            size,
+            size as f64 / postcard_size as f64
+        );
+    }
+
+    Ok(())
+}
+
+/// Statistics of a single corpus entry
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct EntryStats {
+    pub filename: String,
+    pub ir_bytes: usize,
+    pub instr_count: usize,
+    /// Number of messages sent (0 if the program does not compile)
+    pub send_count: usize,
+    /// Size of the compiled program (0 if the program does not compile)
+    pub compiled_bytes: usize,
+    pub unique_op_types: usize,
+    /// Whether the program is statically valid
+    pub valid: bool,
+    /// Number of instructions per operation type
+    pub operation_frequency: BTreeMap<String, u64>,
+}
+
+impl EntryStats {
+    fn new(filename: String, ir_bytes: usize, program: &Program) -> Self {
+        let operation_frequency: BTreeMap<_, _> = program
+            .count_by_operation_type()
+            .into_iter()
+            .map(|(operation, count)| (operation.to_string(), count))
+            .collect();
+
+        Self {
+            filename,
+            ir_bytes,
+            instr_count: program.instructions.len(),
+            send_count: 0,
+            compiled_bytes: 0,
+            unique_op_types: operation_frequency.len(),
+            valid: program.is_statically_valid(),
+            operation_frequency,
+        }
+    }
+}
+
+/// Per-entry statistics of a corpus
+#[derive(Debug, Default)]
+pub struct CorpusStats {
+    pub entries: Vec<EntryStats>,
+}
+
+/// Quote a csv field if necessary
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl CorpusStats {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "filename,ir_bytes,instr_count,send_count,compiled_bytes,unique_op_types,valid\n",
+        );
+        for entry in &self.entries {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                csv_field(&entry.filename),
+                entry.ir_bytes,
+                entry.instr_count,
+                entry.send_count,
+                entry.compiled_bytes,
+                entry.unique_op_types,
+                entry.valid
+            ));
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.entries)?)
+    }
+}
+
+pub fn analyze_ir(
+    input: &PathBuf,
+    compare_formats: bool,
+    export_csv: &Option<PathBuf>,
+    export_json: &Option<PathBuf>,
+    filter_invalid: bool,
+) -> Result<()> {
     const IR_BUCKET_SIZE: usize = 256;
     const COMPILED_BUCKET_SIZE: usize = 1024 * 75;
     const SENDS_BUCKET_SIZE: usize = 1;
@@ -435,6 +1192,9 @@ pub fn analyze_ir(input: &PathBuf) -> Result<()> {
     let mut scatter_points = vec![];
     let mut sends_per_program_hist = vec![];
     let mut instructions_hist = vec![];
+    let mut programs = vec![];
+    let mut stats = CorpusStats::default();
+    let mut compiler = fuzzamoto_ir::compiler::Compiler::new();
 
     // Process each file
     for entry in input.read_dir()? {
@@ -442,7 +1202,22 @@ pub fn analyze_ir(input: &PathBuf) -> Result<()> {
         if path.is_file() && !path.file_name().unwrap().to_str().unwrap().starts_with(".") {
             // Read and parse the IR file
             let bytes = std::fs::read(&path)?;
-            if let Ok(program) = postcard::from_bytes::<fuzzamoto_ir::Program>(&bytes) {
+            let program = Program::from_bytes_versioned(&bytes).ok();
+            if filter_invalid
+                && !program
+                    .as_ref()
+                    .is_some_and(|program| program.is_statically_valid())
+            {
+                continue;
+            }
+
+            if let Some(program) = program {
+                let mut entry = EntryStats::new(
+                    path.file_name().unwrap().to_string_lossy().to_string(),
+                    bytes.len(),
+                    &program,
+                );
+
                 // Count instructions
                 let instr_count = program.instructions.len();
                 let bucket = instr_count / INSTRUCTIONS_BUCKET_SIZE;
@@ -450,7 +1225,7 @@ pub fn analyze_ir(input: &PathBuf) -> Result<()> {
                 instructions_hist[bucket] += 1;
 
                 // Compile the program
-                let mut compiler = fuzzamoto_ir::compiler::Compiler::new();
+                compiler.reset();
                 if let Ok(compiled) = compiler.compile(&program) {
                     // Count total sends in this program
                     let sends = compiled
@@ -462,8 +1237,9 @@ pub fn analyze_ir(input: &PathBuf) -> Result<()> {
                                 fuzzamoto_ir::compiler::CompiledAction::SendRawMessage(..)
                             )
                         })
-                        .count()
-                        / SENDS_BUCKET_SIZE;
+                        .count();
+                    entry.send_count = sends;
+                    let sends = sends / SENDS_BUCKET_SIZE;
 
                     // Update histogram
                     sends_per_program_hist.resize(sends_per_program_hist.len().max(sends + 1), 0);
@@ -472,6 +1248,7 @@ pub fn analyze_ir(input: &PathBuf) -> Result<()> {
                     // Get compiled size
                     let compiled_bytes = postcard::to_allocvec(&compiled)?;
                     let compiled_size = compiled_bytes.len();
+                    entry.compiled_bytes = compiled_size;
                     let bucket = compiled_size / COMPILED_BUCKET_SIZE;
                     compiled_size_hist.resize(compiled_size_hist.len().max(bucket + 1), 0);
                     compiled_size_hist[bucket] += 1;
@@ -481,6 +1258,11 @@ pub fn analyze_ir(input: &PathBuf) -> Result<()> {
                         compiled_size,
                     });
                 }
+
+                stats.entries.push(entry);
+                if compare_formats {
+                    programs.push(program);
+                }
             }
 
             // Get IR file size for histogram
@@ -519,6 +1301,23 @@ pub fn analyze_ir(input: &PathBuf) -> Result<()> {
     println!("-------------------------------------------------");
     print_histogram(&compiled_size_hist, COMPILED_BUCKET_SIZE, "bytes");
 
+    stats.entries.sort_by(|a, b| a.filename.cmp(&b.filename));
+    if let Some(path) = export_csv {
+        write_atomic(path, stats.to_csv().as_bytes())?;
+    }
+    if let Some(path) = export_json {
+        write_atomic(path, stats.to_json()?.as_bytes())?;
+    }
+
+    if compare_formats {
+        println!(
+            "\nSerialized Corpus Size by Format ({} programs)",
+            programs.len()
+        );
+        println!("-----------------------------------------------");
+        print_format_comparison(&programs)?;
+    }
+
     Ok(())
 }
 
@@ -549,3 +1348,335 @@ fn print_histogram(hist: &[i32], bucket_size: usize, label: &str) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::{Operation, ProgramBuilder, ProgramContext, TxoType};
+
+    fn generate_with_seed(dir: &PathBuf, context: &PathBuf, seed: u64) -> Vec<(String, Vec<u8>)> {
+        std::fs::create_dir_all(dir).unwrap();
+        let generators = Some(
+            [
+                "AdvanceTimeGenerator",
+                "TxoGenerator",
+                "SingleTxGenerator",
+                "LongChainGenerator",
+                "GetAddrGenerator",
+            ]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+        );
+        generate_ir(
+            dir,
+            10,
+            10,
+            context,
+            &generators,
+            &Some(dir.join("generators.json")),
+            None,
+            Box::new(StdRng::seed_from_u64(seed)),
+        )
+        .unwrap();
+
+        let mut files: Vec<_> = dir
+            .read_dir()
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                (
+                    path.file_name().unwrap().to_string_lossy().to_string(),
+                    std::fs::read(&path).unwrap(),
+                )
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn roundtrip_non_utf8_bytes() {
+        let non_utf8: Vec<u8> = (0..32u8).map(|i| 0x80 | i).collect();
+        for bytes in [vec![0x00], vec![0xff], non_utf8] {
+            let mut builder = ProgramBuilder::new(ProgramContext {
+                num_nodes: 1,
+                num_connections: 1,
+                timestamp: 0,
+            });
+            builder.force_append_expect_output(vec![], Operation::LoadBytes(bytes));
+            let program = builder.finalize().unwrap();
+
+            roundtrip_ir_bytes(&program.to_bytes_versioned()).unwrap();
+        }
+    }
+
+    fn program() -> Program {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![0x00, 0xff]));
+        builder.force_append_expect_output(vec![], Operation::LoadMsgType(['a'; 12]));
+        builder.finalize().unwrap()
+    }
+
+    #[test]
+    fn roundtrip_message_pack_and_cbor() {
+        let program = program();
+        for format in [CorpusFormat::MessagePack, CorpusFormat::Cbor] {
+            let bytes = format.encode(&program).unwrap();
+            let decoded = format.decode(&bytes).unwrap();
+            assert_eq!(decoded, program, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn force_migrate_decodes_versioned_and_unversioned_postcard() {
+        let program = program();
+        for bytes in [
+            program.to_bytes_versioned(),
+            postcard::to_allocvec(&program).unwrap(),
+        ] {
+            let decoded = decode_program(&CorpusFormat::Postcard, &bytes, true).unwrap();
+            assert_eq!(decoded, program);
+        }
+
+        // Without `--force-migrate`, only the current schema version is accepted
+        let unversioned = postcard::to_allocvec(&program).unwrap();
+        assert!(decode_program(&CorpusFormat::Postcard, &unversioned, false).is_err());
+    }
+
+    #[test]
+    fn detects_serialization_formats() {
+        let program = program();
+        for format in CorpusFormat::SERIALIZATION_FORMATS {
+            let bytes = format.encode(&program).unwrap();
+            assert_eq!(CorpusFormat::detect(&bytes), Some(format));
+        }
+        assert_eq!(CorpusFormat::detect(&[0xff; 8]), None);
+    }
+
+    fn write_context(path: &PathBuf) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let context = FullProgramContext {
+            context: ProgramContext {
+                num_nodes: 1,
+                num_connections: 2,
+                timestamp: 1_296_688_602,
+            },
+            txos: vec![TxoType::P2WSH.random_txo(&mut rng)],
+            headers: vec![],
+            block_filters: vec![],
+        };
+        std::fs::write(path, postcard::to_allocvec(&context).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn fuzz_seed_covers_every_generator() {
+        let base =
+            std::env::temp_dir().join(format!("fuzzamoto-cli-fuzz-seed-{}", std::process::id()));
+        let output = base.join("corpus");
+        std::fs::create_dir_all(&output).unwrap();
+        let context_path = base.join("context.bin");
+        write_context(&context_path);
+
+        let names = ["AdvanceTimeGenerator", "TxoGenerator", "GetAddrGenerator"];
+        fuzz_seed(
+            &output,
+            &context_path,
+            3,
+            2,
+            &Some(names.iter().map(|name| name.to_string()).collect()),
+            Box::new(StdRng::seed_from_u64(0)),
+        )
+        .unwrap();
+
+        let mut programs: Vec<_> = output
+            .read_dir()
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().unwrap() == "ir")
+            .collect();
+        programs.sort();
+        assert_eq!(programs.len(), 3 * names.len());
+        assert_eq!(output.read_dir().unwrap().count(), 2 * 3 * names.len());
+
+        let mut sizes = Vec::new();
+        let mut produced_by = BTreeMap::new();
+        for path in &programs {
+            let bytes = std::fs::read(path).unwrap();
+            let program = Program::from_bytes_current(&bytes).unwrap();
+            assert!(program.instructions.len() >= 2);
+            sizes.push(bytes.len());
+
+            let generator = std::fs::read_to_string(path.with_extension("gen")).unwrap();
+            *produced_by.entry(generator.trim().to_string()).or_insert(0) += 1;
+        }
+        std::fs::remove_dir_all(&base).unwrap();
+
+        assert!(sizes.windows(2).all(|pair| pair[0] <= pair[1]));
+        for name in names {
+            assert_eq!(produced_by.get(name), Some(&3), "{name}");
+        }
+    }
+
+    /// Write a corpus of two valid programs and one statically invalid program to `dir`
+    fn write_corpus(dir: &PathBuf) {
+        std::fs::create_dir_all(dir).unwrap();
+        let invalid = Program::unchecked_new(
+            program().context,
+            vec![fuzzamoto_ir::Instruction {
+                inputs: vec![0, 1, 2],
+                operation: Operation::SendRawMessage,
+            }],
+        );
+        let mut larger = program();
+        larger.instructions.extend(program().instructions);
+        for (name, program) in [("a.ir", program()), ("b.ir", larger), ("c.ir", invalid)] {
+            std::fs::write(dir.join(name), program.to_bytes_versioned()).unwrap();
+        }
+    }
+
+    #[test]
+    fn analyze_exports_corpus_stats() {
+        let base =
+            std::env::temp_dir().join(format!("fuzzamoto-cli-analyze-{}", std::process::id()));
+        let corpus = base.join("corpus");
+        write_corpus(&corpus);
+
+        let json_path = base.join("stats.json");
+        let csv_path = base.join("stats.csv");
+        analyze_ir(
+            &corpus,
+            false,
+            &Some(csv_path.clone()),
+            &Some(json_path.clone()),
+            false,
+        )
+        .unwrap();
+        let entries: Vec<EntryStats> =
+            serde_json::from_slice(&std::fs::read(&json_path).unwrap()).unwrap();
+        let csv = std::fs::read_to_string(&csv_path).unwrap();
+
+        let filtered_path = base.join("filtered.json");
+        analyze_ir(&corpus, false, &None, &Some(filtered_path.clone()), true).unwrap();
+        let filtered: Vec<EntryStats> =
+            serde_json::from_slice(&std::fs::read(&filtered_path).unwrap()).unwrap();
+        let leftovers = base
+            .read_dir()
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".tmp")
+            })
+            .count();
+        std::fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].filename, "a.ir");
+        assert_eq!(entries[0].instr_count, 2);
+        assert_eq!(entries[0].unique_op_types, 2);
+        assert_eq!(entries[0].operation_frequency.get("LoadBytes"), Some(&1));
+        assert_eq!(entries[1].instr_count, 4);
+        assert_eq!(entries[1].operation_frequency.get("LoadMsgType"), Some(&2));
+        assert!(entries[0].valid && entries[1].valid);
+        assert!(!entries[2].valid);
+
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("filename,ir_bytes,instr_count,send_count,compiled_bytes,unique_op_types,valid")
+        );
+        assert_eq!(lines.count(), 3);
+
+        assert_eq!(filtered.len(), 2);
+        assert!(filtered.iter().all(|entry| entry.valid));
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn generate_with_seed_is_deterministic() {
+        let base = std::env::temp_dir().join(format!("fuzzamoto-cli-seed-{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+
+        let context_path = base.join("context.bin");
+        write_context(&context_path);
+
+        let first = generate_with_seed(&base.join("first"), &context_path, 42);
+        let second = generate_with_seed(&base.join("second"), &context_path, 42);
+        std::fs::remove_dir_all(&base).unwrap();
+
+        // 10 programs plus the generator log
+        assert_eq!(first.len(), 11);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn named_weights_are_matched_case_insensitively() {
+        let generators: Vec<Box<dyn Generator<StdRng>>> = vec![
+            Box::new(fuzzamoto_ir::AdvanceTimeGenerator::default()),
+            Box::new(fuzzamoto_ir::GetAddrGenerator::default()),
+        ];
+
+        let named = BTreeMap::from([("getaddrgenerator".to_string(), 4.0)]);
+        assert_eq!(
+            weights_from_names(&generators, &named).unwrap(),
+            vec![1.0, 4.0]
+        );
+
+        let named = BTreeMap::from([("NoSuchGenerator".to_string(), 1.0)]);
+        assert!(weights_from_names(&generators, &named).is_err());
+    }
+
+    #[test]
+    fn auto_weights_are_inversely_proportional_to_size() {
+        let weights = inverse_size_weights(&[Some(2.0), Some(8.0), None, Some(0.0)]);
+        assert_eq!(weights, vec![0.5, 0.125, 0.125, 1.0]);
+        assert_eq!(inverse_size_weights(&[None, None]), vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn weighted_generation_skips_zero_weight_generators() {
+        let base =
+            std::env::temp_dir().join(format!("fuzzamoto-cli-weighted-{}", std::process::id()));
+        let output = base.join("corpus");
+        std::fs::create_dir_all(&output).unwrap();
+        let context_path = base.join("context.bin");
+        write_context(&context_path);
+        let log_path = base.join("generators.json");
+
+        let names: Vec<String> = ["AdvanceTimeGenerator", "GetAddrGenerator"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        generate_ir(
+            &output,
+            10,
+            20,
+            &context_path,
+            &Some(names),
+            &Some(log_path.clone()),
+            Some(vec![0.0, 1.0]),
+            Box::new(StdRng::seed_from_u64(0)),
+        )
+        .unwrap();
+
+        let log: BTreeMap<String, Vec<GenerationEvent>> =
+            serde_json::from_slice(&std::fs::read(&log_path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&base).unwrap();
+
+        let events: Vec<&GenerationEvent> = log.values().flatten().collect();
+        assert!(!events.is_empty());
+        assert!(
+            events
+                .iter()
+                .all(|event| event.generator_name == "GetAddrGenerator")
+        );
+    }
+}
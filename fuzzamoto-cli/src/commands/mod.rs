@@ -1,5 +1,7 @@
 pub mod coverage;
 pub mod coverage_batch;
+pub mod coverage_diff;
+pub mod export;
 pub mod init;
 pub mod ir;
 
@@ -5,10 +5,14 @@ pub enum CliError {
     IoError(std::io::Error),
     JsonError(serde_json::Error),
     PostcardError(postcard::Error),
+    SchemaError(fuzzamoto_ir::SchemaError),
+    MessagePackError(String),
+    CborError(String),
     ProcessError(String),
     InvalidInput(String),
     ShareDirExists,
     FileNotFound(String),
+    CoverageBelowThreshold { coverage: f64, threshold: f64 },
 }
 
 impl fmt::Display for CliError {
@@ -17,10 +21,21 @@ impl fmt::Display for CliError {
             CliError::IoError(e) => write!(f, "IO error: {}", e),
             CliError::JsonError(e) => write!(f, "JSON error: {}", e),
             CliError::PostcardError(e) => write!(f, "Postcard error: {}", e),
+            CliError::SchemaError(e) => write!(f, "Schema error: {}", e),
+            CliError::MessagePackError(msg) => write!(f, "MessagePack error: {}", msg),
+            CliError::CborError(msg) => write!(f, "CBOR error: {}", msg),
             CliError::ProcessError(msg) => write!(f, "Process error: {}", msg),
             CliError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
             CliError::ShareDirExists => write!(f, "Share directory already exists"),
             CliError::FileNotFound(path) => write!(f, "File not found: {}", path),
+            CliError::CoverageBelowThreshold {
+                coverage,
+                threshold,
+            } => write!(
+                f,
+                "Line coverage {:.2}% is below the threshold of {:.2}%",
+                coverage, threshold
+            ),
         }
     }
 }
@@ -45,4 +60,34 @@ impl From<postcard::Error> for CliError {
     }
 }
 
+impl From<fuzzamoto_ir::SchemaError> for CliError {
+    fn from(error: fuzzamoto_ir::SchemaError) -> Self {
+        CliError::SchemaError(error)
+    }
+}
+
+impl From<rmp_serde::encode::Error> for CliError {
+    fn from(error: rmp_serde::encode::Error) -> Self {
+        CliError::MessagePackError(error.to_string())
+    }
+}
+
+impl From<rmp_serde::decode::Error> for CliError {
+    fn from(error: rmp_serde::decode::Error) -> Self {
+        CliError::MessagePackError(error.to_string())
+    }
+}
+
+impl<T: fmt::Debug> From<ciborium::ser::Error<T>> for CliError {
+    fn from(error: ciborium::ser::Error<T>) -> Self {
+        CliError::CborError(error.to_string())
+    }
+}
+
+impl<T: fmt::Debug> From<ciborium::de::Error<T>> for CliError {
+    fn from(error: ciborium::de::Error<T>) -> Self {
+        CliError::CborError(error.to_string())
+    }
+}
+
 pub type Result<T> = std::result::Result<T, CliError>;
@@ -6,14 +6,40 @@ use clap::{Parser, Subcommand};
 use commands::*;
 use error::Result;
 use std::path::PathBuf;
+use tracing_subscriber::filter::LevelFilter;
 
-use crate::commands::coverage_batch::CoverageBatchCommand;
+use crate::commands::{
+    coverage::{SummaryFormat, SummaryOptions},
+    coverage_batch::CoverageBatchCommand,
+};
+use crate::utils::logging::{LogFormat, init_logging};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Log level (off, error, warn, info, debug, trace), overrides RUST_LOG"
+    )]
+    log_level: Option<LevelFilter>,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "Format of the emitted log lines"
+    )]
+    log_format: LogFormat,
+    #[arg(
+        long,
+        global = true,
+        help = "Path to a file that logs should be written to (in addition to stderr)"
+    )]
+    log_file: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -51,6 +77,12 @@ enum Commands {
             help = "Path to the file with the RPC commands that should be copied into the share directory"
         )]
         rpc_path: Option<PathBuf>,
+
+        #[arg(
+            long,
+            help = "Test-run the scenario against bitcoind after setup, removing the share directory on failure"
+        )]
+        verify: bool,
     },
 
     /// Create a html coverage report for a given corpus
@@ -82,6 +114,31 @@ enum Commands {
             help = "Only execute the corpus testcases and write .profraw files; skip merging profraws and HTML report generation"
         )]
         run_only: bool,
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Print the line coverage summary (llvm-cov report) instead of generating the HTML report"
+        )]
+        line_count: bool,
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Print the function coverage summary (llvm-cov report) instead of generating the HTML report"
+        )]
+        function_count: bool,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = SummaryFormat::Text,
+            help = "Format of the printed coverage summary"
+        )]
+        output_format: SummaryFormat,
+        #[arg(
+            long,
+            value_name = "PCT",
+            help = "Exit with code 1 if the line coverage percentage is below this threshold"
+        )]
+        fail_under: Option<f64>,
     },
 
     /// Create a html coverage report for a given corpus, runs using multiple docker instances
@@ -117,13 +174,10 @@ enum Commands {
 }
 
 fn main() -> Result<()> {
-    // Log info by default
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
-
     let cli = Cli::parse();
 
+    init_logging(cli.log_level, cli.log_format, cli.log_file.as_deref())?;
+
     match &cli.command {
         Commands::Init {
             sharedir,
@@ -133,6 +187,7 @@ fn main() -> Result<()> {
             scenario,
             nyx_dir,
             rpc_path,
+            verify,
         } => InitCommand::execute(
             sharedir.clone(),
             crash_handler.clone(),
@@ -141,6 +196,7 @@ fn main() -> Result<()> {
             scenario.clone(),
             nyx_dir.clone(),
             rpc_path.clone(),
+            *verify,
         ),
         Commands::Coverage {
             output,
@@ -149,6 +205,10 @@ fn main() -> Result<()> {
             scenario,
             profraws,
             run_only,
+            line_count,
+            function_count,
+            output_format,
+            fail_under,
         } => CoverageCommand::execute(
             output.clone(),
             corpus.clone(),
@@ -156,6 +216,12 @@ fn main() -> Result<()> {
             scenario.clone(),
             profraws.clone(),
             *run_only,
+            SummaryOptions {
+                line_count: *line_count,
+                function_count: *function_count,
+                format: *output_format,
+                fail_under: *fail_under,
+            },
         ),
         Commands::CoverageBatch {
             output,
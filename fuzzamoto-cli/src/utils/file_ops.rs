@@ -14,6 +14,15 @@ pub fn copy_file_to_dir(src: &Path, dst_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Write `contents` to `path` atomically, by writing to `<path>.tmp` first and renaming it
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
 pub fn ensure_file_exists(path: &Path) -> Result<()> {
     if !path.exists() {
         return Err(CliError::FileNotFound(path.display().to_string()));
@@ -0,0 +1,48 @@
+use crate::error::Result;
+use std::{fs::File, path::Path};
+use tracing_subscriber::{
+    EnvFilter,
+    filter::LevelFilter,
+    fmt::writer::{BoxMakeWriter, MakeWriterExt},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable log lines
+    Text,
+    /// One JSON object per log line (e.g. for log aggregation in CI)
+    Json,
+}
+
+/// Install the global `tracing` subscriber. Logs go to stderr and, if `log_file` is given, are
+/// also written to that file. `level` overrides `RUST_LOG`, which defaults to `info`.
+///
+/// Records emitted through the `log` crate are forwarded to the subscriber as well.
+pub fn init_logging(
+    level: Option<LevelFilter>,
+    format: LogFormat,
+    log_file: Option<&Path>,
+) -> Result<()> {
+    let filter = match level {
+        Some(level) => EnvFilter::default().add_directive(level.into()),
+        None => EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy(),
+    };
+
+    let writer = match log_file {
+        Some(path) => BoxMakeWriter::new(std::io::stderr.and(File::create(path)?)),
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        // Don't write escape codes into the log file
+        .with_ansi(log_file.is_none());
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+    Ok(())
+}
@@ -1,3 +1,4 @@
 pub mod file_ops;
+pub mod logging;
 pub mod nyx;
 pub mod process;
@@ -1,6 +1,6 @@
 use crate::error::Result;
 use crate::utils::process::run_command_with_status;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub fn compile_packer_binaries(nyx_path: &Path) -> Result<()> {
     log::info!("Compiling packer binaries");
@@ -23,6 +23,30 @@ pub fn copy_packer_binaries(nyx_path: &Path, dst_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Path of the build config holding the Nyx installation used by `fuzzamoto-nyx-sys`'s
+/// `build-from-source` build (see `fuzzamoto-nyx-sys/build.rs`)
+fn build_config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_dir.join("fuzzamoto").join("nyx_dir"))
+}
+
+/// Record `nyx_path` for builds of the nyx agent that don't set `NYX_DIR`
+pub fn write_build_config(nyx_path: &Path) -> Result<()> {
+    let Some(config) = build_config_path() else {
+        log::warn!("Neither XDG_CONFIG_HOME nor HOME is set, not recording the nyx directory");
+        return Ok(());
+    };
+
+    let nyx_path = std::fs::canonicalize(nyx_path)?;
+    crate::utils::file_ops::create_dir_all(config.parent().unwrap())?;
+    crate::utils::file_ops::write_atomic(&config, nyx_path.to_string_lossy().as_bytes())?;
+
+    log::info!("Recorded nyx directory in {}", config.display());
+    Ok(())
+}
+
 pub fn generate_nyx_config(nyx_path: &Path, sharedir: &Path) -> Result<()> {
     log::info!("Generating nyx config");
 
@@ -1,6 +1,7 @@
 use crate::error::{CliError, Result};
 use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
 
 pub fn get_llvm_command(base: &str) -> String {
     match std::env::var("LLVM_V") {
@@ -77,3 +78,25 @@ pub fn run_scenario_command(
         Err(CliError::ProcessError("Scenario failed to run".to_string()))
     }
 }
+
+/// Wait for `child` to exit for at most `timeout`, killing it if it is still running afterwards.
+///
+/// Returns `None` if the child had to be killed.
+pub fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    kill_child(child);
+    Ok(None)
+}
+
+/// Kill `child` (if it is still running) and reap it
+pub fn kill_child(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
@@ -0,0 +1,94 @@
+//! Throughput of the `HavocMutator` (several stacked mutations per pass) compared to applying a
+//! single mutation per pass with the `InputMutator` or `OperationMutator`.
+
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use fuzzamoto_ir::{
+    HavocMutator, InputMutator, Mutator, Operation, OperationByteMutator, OperationMutator,
+    Program, ProgramBuilder, ProgramContext,
+};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+const PING: [char; 12] = [
+    'p', 'i', 'n', 'g', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+];
+
+/// Flips a random bit, standing in for the LibAFL byte mutators used during fuzzing
+struct BitFlipMutator(SmallRng);
+
+impl OperationByteMutator for BitFlipMutator {
+    fn mutate_bytes(&mut self, bytes: &mut Vec<u8>) {
+        if bytes.is_empty() {
+            bytes.push(0);
+        }
+        let index = self.0.gen_range(0..bytes.len());
+        bytes[index] ^= 1 << self.0.gen_range(0..8);
+    }
+}
+
+/// Build a program of `num_messages` raw messages on two connections, each followed by a time
+/// change
+fn program(num_messages: usize) -> Program {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut builder = ProgramBuilder::new(ProgramContext {
+        num_nodes: 1,
+        num_connections: 2,
+        timestamp: 0,
+    });
+
+    let connections = [
+        builder.force_append_expect_output(vec![], Operation::LoadConnection(0)),
+        builder.force_append_expect_output(vec![], Operation::LoadConnection(1)),
+    ];
+    for i in 0..num_messages {
+        let msg_type = builder.force_append_expect_output(vec![], Operation::LoadMsgType(PING));
+        let len = rng.gen_range(8..512);
+        let payload = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadBytes((0..len).map(|_| rng.r#gen()).collect()),
+        );
+        builder.force_append(
+            vec![connections[i % 2].index, msg_type.index, payload.index],
+            Operation::SendRawMessage,
+        );
+        let time = builder.force_append_expect_output(vec![], Operation::LoadTime(i as u64));
+        builder.force_append(vec![time.index], Operation::SetTime);
+    }
+    builder.finalize().unwrap()
+}
+
+fn mutators() -> Vec<(&'static str, Box<dyn Mutator<SmallRng>>)> {
+    let byte_mutator = || BitFlipMutator(SmallRng::seed_from_u64(1));
+    vec![
+        ("input", Box::new(InputMutator::new())),
+        ("operation", Box::new(OperationMutator::new(byte_mutator()))),
+        ("havoc", Box::new(HavocMutator::new(byte_mutator()))),
+    ]
+}
+
+fn mutation(c: &mut Criterion) {
+    let programs = [16, 256].map(|num_messages| (num_messages, program(num_messages)));
+
+    let mut group = c.benchmark_group("mutate");
+    for (num_messages, program) in &programs {
+        for (name, mut mutator) in mutators() {
+            let mut rng = SmallRng::seed_from_u64(0);
+            group.bench_with_input(
+                BenchmarkId::new(name, num_messages),
+                program,
+                |b, program| {
+                    b.iter(|| {
+                        let mut mutated = program.clone();
+                        let _ = mutator.mutate(&mut mutated, &mut rng, None);
+                        black_box(mutated)
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mutation);
+criterion_main!(benches);
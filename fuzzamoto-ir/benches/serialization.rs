@@ -0,0 +1,110 @@
+//! Serialization speed and size of programs with different encodings: plain postcard, postcard
+//! compressed with zstd (levels 1 and 3) and postcard compressed with lz4.
+
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use fuzzamoto_ir::{Operation, Program, ProgramBuilder, ProgramContext};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+const PING: [char; 12] = [
+    'p', 'i', 'n', 'g', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+];
+
+/// Build a program of `num_messages` raw messages with random payloads
+fn program(num_messages: usize) -> Program {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut builder = ProgramBuilder::new(ProgramContext {
+        num_nodes: 1,
+        num_connections: 1,
+        timestamp: 0,
+    });
+
+    let conn = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+    for _ in 0..num_messages {
+        let msg_type = builder.force_append_expect_output(vec![], Operation::LoadMsgType(PING));
+        let len = rng.gen_range(8..512);
+        let payload = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadBytes((0..len).map(|_| rng.gen_range(0..16)).collect()),
+        );
+        builder.force_append(
+            vec![conn.index, msg_type.index, payload.index],
+            Operation::SendRawMessage,
+        );
+    }
+    builder.finalize().unwrap()
+}
+
+struct Encoding {
+    name: &'static str,
+    encode: fn(&[u8]) -> Vec<u8>,
+    decode: fn(&[u8]) -> Vec<u8>,
+}
+
+const ENCODINGS: [Encoding; 4] = [
+    Encoding {
+        name: "postcard",
+        encode: <[u8]>::to_vec,
+        decode: <[u8]>::to_vec,
+    },
+    Encoding {
+        name: "postcard+zstd-1",
+        encode: |bytes| zstd::stream::encode_all(bytes, 1).unwrap(),
+        decode: |bytes| zstd::stream::decode_all(bytes).unwrap(),
+    },
+    Encoding {
+        name: "postcard+zstd-3",
+        encode: |bytes| zstd::stream::encode_all(bytes, 3).unwrap(),
+        decode: |bytes| zstd::stream::decode_all(bytes).unwrap(),
+    },
+    Encoding {
+        name: "postcard+lz4",
+        encode: lz4_flex::compress_prepend_size,
+        decode: |bytes| lz4_flex::decompress_size_prepended(bytes).unwrap(),
+    },
+];
+
+fn serialization(c: &mut Criterion) {
+    let programs = [16, 256, 2048].map(|num_messages| (num_messages, program(num_messages)));
+
+    let mut serialize = c.benchmark_group("serialize");
+    for (num_messages, program) in &programs {
+        for encoding in &ENCODINGS {
+            let size = (encoding.encode)(&postcard::to_allocvec(program).unwrap()).len();
+            println!("{} ({num_messages} messages): {size} bytes", encoding.name);
+
+            serialize.bench_with_input(
+                BenchmarkId::new(encoding.name, num_messages),
+                program,
+                |b, program| {
+                    b.iter(|| {
+                        (encoding.encode)(&postcard::to_allocvec(black_box(program)).unwrap())
+                    })
+                },
+            );
+        }
+    }
+    serialize.finish();
+
+    let mut deserialize = c.benchmark_group("deserialize");
+    for (num_messages, program) in &programs {
+        for encoding in &ENCODINGS {
+            let bytes = (encoding.encode)(&postcard::to_allocvec(program).unwrap());
+            deserialize.bench_with_input(
+                BenchmarkId::new(encoding.name, num_messages),
+                &bytes,
+                |b, bytes| {
+                    b.iter(|| {
+                        postcard::from_bytes::<Program>(&(encoding.decode)(black_box(bytes)))
+                            .unwrap()
+                    })
+                },
+            );
+        }
+    }
+    deserialize.finish();
+}
+
+criterion_group!(benches, serialization);
+criterion_main!(benches);
@@ -1,10 +1,13 @@
 /// Rust transplation from https://github.com/bitcoin/bitcoin/blob/master/src/common/bloom.cpp
 // why do we need this code? because `filterload` message require us that we send the filter itself. So we need to construct the bloom filter on the client (fuzzer) side too.
+use bitcoin::{Script, Transaction, consensus::serialize, hashes::Hash, script::Instruction};
 use murmurs::murmur3_x86_32;
 
 // https://github.com/bitcoin/bips/blob/master/bip-0037.mediawiki?plain=1#L51
 /// Maximum size for filterload filter
+#[cfg(feature = "generators")]
 pub(crate) const MAX_BLOOM_FILTER_SIZE: u32 = 36000;
+#[cfg(feature = "generators")]
 pub(crate) const MAX_HASH_FUNCS: u32 = 50;
 
 // Hash the data
@@ -26,3 +29,36 @@ pub fn filter_insert(data: &mut [u8], n_hash_funcs: u32, key: &[u8]) {
         data[index_into_data] |= 1 << bits_to_raise;
     }
 }
+
+pub fn filter_contains(data: &[u8], n_hash_funcs: u32, key: &[u8]) -> bool {
+    // An empty filter matches everything (avoids the division by zero of CVE-2013-5700)
+    if data.is_empty() {
+        return true;
+    }
+    (0..n_hash_funcs).all(|i| {
+        let index = hash(i, data.len(), key);
+        data[(index >> 3) as usize] & (1 << (7 & index)) != 0
+    })
+}
+
+/// Check whether a transaction is relevant to the filter, i.e. whether its txid, any of the data
+/// pushes in its scripts or any of the outpoints it spends match (as in
+/// `CBloomFilter::IsRelevantAndUpdate`, without updating the filter)
+pub fn filter_matches_tx(data: &[u8], n_hash_funcs: u32, tx: &Transaction) -> bool {
+    let contains = |key: &[u8]| filter_contains(data, n_hash_funcs, key);
+    let contains_push = |script: &Script| {
+        script.instructions().any(|instruction| match instruction {
+            Ok(Instruction::PushBytes(bytes)) => !bytes.is_empty() && contains(bytes.as_bytes()),
+            _ => false,
+        })
+    };
+
+    contains(tx.compute_txid().as_byte_array())
+        || tx
+            .output
+            .iter()
+            .any(|output| contains_push(&output.script_pubkey))
+        || tx.input.iter().any(|input| {
+            contains(&serialize(&input.previous_output)) || contains_push(&input.script_sig)
+        })
+}
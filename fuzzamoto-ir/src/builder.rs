@@ -3,10 +3,11 @@ use std::collections::HashSet;
 use rand::{Rng, RngCore, seq::IteratorRandom};
 
 use crate::{
-    Instruction, InstructionContext, Operation, Program, ProgramContext, ProgramValidationError,
-    Variable,
+    Instruction, InstructionContext, MAX_PROGRAM_INSTRUCTIONS, Operation, Program, ProgramContext,
+    ProgramValidationError, Variable,
 };
 
+#[derive(Clone)]
 pub struct Scope {
     pub begin: Option<usize>, // Index of begin op
     pub id: usize,            // Scope index
@@ -16,7 +17,7 @@ pub struct Scope {
 }
 
 /// Variable and its containing scope id
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ScopedVariable {
     pub var: Variable,
     pub scope_id: usize,
@@ -29,6 +30,7 @@ pub struct IndexedVariable {
     pub index: usize,
 }
 
+#[derive(Clone)]
 pub struct ProgramBuilder {
     // Context of the program to be created
     context: ProgramContext,
@@ -45,18 +47,23 @@ pub struct ProgramBuilder {
     pub instructions: Vec<Instruction>,
 
     contexts: Vec<InstructionContext>,
+
+    // Set if `context` exceeds the limits checked by `ProgramContext::validate`, in which case no
+    // instructions can be appended and finalizing fails
+    context_error: Option<ProgramValidationError>,
 }
 
 impl ProgramBuilder {
     pub fn new(context: ProgramContext) -> Self {
         let mut builder = Self {
-            context,
             active_scopes: Vec::new(),
             active_scopes_set: HashSet::new(),
             scope_counter: 0usize,
             variables: Vec::with_capacity(4096),
             instructions: Vec::with_capacity(4096),
             contexts: Vec::with_capacity(4096),
+            context_error: context.validate().err(),
+            context,
         };
 
         // Enter outer/global scope of the program (never exited)
@@ -123,6 +130,13 @@ impl ProgramBuilder {
         &mut self,
         instruction: Instruction,
     ) -> Result<Vec<IndexedVariable>, ProgramValidationError> {
+        if let Some(err) = &self.context_error {
+            return Err(err.clone());
+        }
+        if self.instructions.len() >= MAX_PROGRAM_INSTRUCTIONS {
+            return Err(ProgramValidationError::ProgramTooLong);
+        }
+
         // Check number of inputs first
         if instruction.operation.num_inputs() != instruction.inputs.len() {
             return Err(ProgramValidationError::InvalidNumberOfInputs {
@@ -322,6 +336,180 @@ impl ProgramBuilder {
             ))
     }
 
+    /// Insert a single instruction at `index`, shifting all subsequent instructions.
+    ///
+    /// The instruction is validated in the scope at `index` (instead of the scope at the end of
+    /// the program) and the inputs of all subsequent instructions are renumbered to account for
+    /// its output variables. Returns the new variables (like `append`). Fails if the instruction is
+    /// invalid at `index`, in which case the builder is left unchanged.
+    pub fn insert_at(
+        &mut self,
+        index: usize,
+        instruction: Instruction,
+    ) -> Result<Vec<IndexedVariable>, ProgramValidationError> {
+        self.insert_all_at(index, std::iter::once(instruction))
+    }
+
+    /// Insert a sequence of instructions at `index` (see `insert_at`).
+    ///
+    /// The sequence is validated as a whole, so it may open and close blocks.
+    pub fn insert_all_at(
+        &mut self,
+        index: usize,
+        instructions: impl Iterator<Item = Instruction>,
+    ) -> Result<Vec<IndexedVariable>, ProgramValidationError> {
+        if index > self.instructions.len() {
+            return Err(ProgramValidationError::InstructionNotFound(index));
+        }
+
+        let mut builder = ProgramBuilder::new(self.context.clone());
+        builder.append_all(self.instructions[..index].iter().cloned())?;
+
+        let variable_threshold = builder.variable_count();
+        let variables = builder.append_all(instructions)?;
+        let variable_offset = builder.variable_count() - variable_threshold;
+
+        builder.append_all(self.instructions[index..].iter().cloned().map(|mut i| {
+            for input in &mut i.inputs {
+                if *input >= variable_threshold {
+                    *input += variable_offset;
+                }
+            }
+            i
+        }))?;
+        *self = builder;
+
+        Ok(variables)
+    }
+
+    /// Remove the instruction at `index` and renumber the inputs of all subsequent instructions.
+    ///
+    /// Removing a block beginning (or end) also removes the matching block end (or beginning).
+    /// Fails with `VariableStillUsed` if a variable produced by a removed instruction is used by a
+    /// remaining instruction, in which case the builder is left unchanged.
+    ///
+    /// Instructions are removed in place, unless the removed block still contains instructions
+    /// (or is not closed yet), which are then re-validated in the enclosing scope.
+    pub fn remove_instruction(&mut self, index: usize) -> Result<(), ProgramValidationError> {
+        if index >= self.instructions.len() {
+            return Err(ProgramValidationError::InstructionNotFound(index));
+        }
+
+        let mut removed = vec![index];
+        if let Some(matching) = self.find_matching_block_instruction(index) {
+            removed.push(matching);
+            removed.sort_unstable();
+        }
+        let first = removed[0];
+        let last = removed[removed.len() - 1];
+
+        // Collect the variables of the removed instructions and check that they are unused
+        let num_variables = |instruction: &Instruction| {
+            instruction.operation.num_outputs() + instruction.operation.num_inner_outputs()
+        };
+        let mut variable = self.variables.len()
+            - self.instructions[first..]
+                .iter()
+                .map(num_variables)
+                .sum::<usize>();
+        let mut removed_variables = Vec::with_capacity(removed.len());
+        for (instr_index, instruction) in self.instructions.iter().enumerate().skip(first) {
+            let num_variables = num_variables(instruction);
+            if removed.contains(&instr_index) {
+                removed_variables.push(variable..variable + num_variables);
+            } else if let Some(input) = instruction
+                .inputs
+                .iter()
+                .find(|input| removed_variables.iter().any(|range| range.contains(*input)))
+            {
+                return Err(ProgramValidationError::VariableStillUsed(*input));
+            }
+            variable += num_variables;
+        }
+        let renumber = |input: usize| {
+            input
+                - removed_variables
+                    .iter()
+                    .filter(|range| range.end <= input)
+                    .map(|range| range.len())
+                    .sum::<usize>()
+        };
+
+        let is_open_block =
+            removed.len() == 1 && self.instructions[index].operation.is_block_begin();
+        if last - first + 1 == removed.len() && !is_open_block {
+            // The removed instructions are adjacent and don't affect the scope of any remaining
+            // instruction
+            self.instructions.drain(first..=last);
+            self.contexts.drain(first..=last);
+            self.variables
+                .drain(removed_variables[0].start..removed_variables[removed.len() - 1].end);
+            for instruction in &mut self.instructions[first..] {
+                for input in &mut instruction.inputs {
+                    *input = renumber(*input);
+                }
+            }
+            for scope in &mut self.active_scopes {
+                if let Some(begin) = &mut scope.begin
+                    && *begin > last
+                {
+                    *begin -= removed.len();
+                }
+            }
+            return Ok(());
+        }
+
+        let mut builder = ProgramBuilder::new(self.context.clone());
+        builder.append_all(
+            self.instructions
+                .iter()
+                .enumerate()
+                .filter(|(instr_index, _)| !removed.contains(instr_index))
+                .map(|(_, instruction)| {
+                    let mut instruction = instruction.clone();
+                    for input in &mut instruction.inputs {
+                        *input = renumber(*input);
+                    }
+                    instruction
+                }),
+        )?;
+        *self = builder;
+
+        Ok(())
+    }
+
+    /// Find the index of the block end (or beginning) matching the block beginning (or end) at
+    /// `index`.
+    fn find_matching_block_instruction(&self, index: usize) -> Option<usize> {
+        let operation = &self.instructions[index].operation;
+        let mut depth = 0usize;
+        if operation.is_block_begin() {
+            for (i, instruction) in self.instructions.iter().enumerate().skip(index + 1) {
+                if instruction.operation.is_block_begin() {
+                    depth += 1;
+                } else if instruction.operation.is_block_end() {
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                    depth -= 1;
+                }
+            }
+        } else if operation.is_block_end() {
+            for (i, instruction) in self.instructions[..index].iter().enumerate().rev() {
+                if instruction.operation.is_block_end() {
+                    depth += 1;
+                } else if instruction.operation.is_block_begin() {
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+
+        None
+    }
+
     /// Construct a `Program` from the builder
     pub fn finalize(&self) -> Result<Program, ProgramValidationError> {
         assert!(
@@ -329,6 +517,10 @@ impl ProgramBuilder {
             "Internal program scope accounting bug"
         );
 
+        if let Some(err) = &self.context_error {
+            return Err(err.clone());
+        }
+
         if self.active_scopes.len() != 1 {
             return Err(ProgramValidationError::ScopeStillOpen);
         }
@@ -409,6 +601,32 @@ impl ProgramBuilder {
             .last()
     }
 
+    /// Get all available (in the current scope) variables of a given type
+    pub fn get_all_variables(&self, find: Variable) -> Vec<IndexedVariable> {
+        self.variables
+            .iter()
+            .enumerate()
+            .filter(|(_, ScopedVariable { var, scope_id })| {
+                self.is_scope_active(*scope_id) && *var == find
+            })
+            .map(
+                |(index, ScopedVariable { var, scope_id: _ })| IndexedVariable {
+                    var: var.clone(),
+                    index,
+                },
+            )
+            .collect()
+    }
+
+    /// Like `get_all_variables`, but guaranteed to be sorted by variable index, so that the result
+    /// does not depend on how the variables are stored (e.g. for deterministic selection with a
+    /// seeded rng)
+    pub fn get_all_variables_sorted(&self, find: Variable) -> Vec<IndexedVariable> {
+        let mut variables = self.get_all_variables(find);
+        variables.sort_by_key(|variable| variable.index);
+        variables
+    }
+
     pub fn get_or_create_random_connection<R: RngCore>(&mut self, rng: &mut R) -> IndexedVariable {
         match self.get_random_variable(rng, Variable::Connection) {
             Some(v) => v,
@@ -492,14 +710,12 @@ impl ProgramBuilder {
             var_count += instruction.operation.num_inner_outputs();
         }
 
-        let all_utxos = utxos
-            .iter()
-            .filter(|index| self.is_variable_in_scope(**index))
-            .map(|index| {
-                let var = self.variables[*index].var.clone();
-                assert!(matches!(var, Variable::Txo));
-                IndexedVariable { var, index: *index }
-            });
+        // Select from the sorted txos, so that the selection only depends on the rng (e.g. for
+        // seeded generation)
+        let all_utxos = self
+            .get_all_variables_sorted(Variable::Txo)
+            .into_iter()
+            .filter(|txo| utxos.contains(&txo.index));
 
         let num_utxos = all_utxos.clone().count();
         if num_utxos == 0 {
@@ -510,3 +726,250 @@ impl ProgramBuilder {
         all_utxos.choose_multiple(rng, n + 1)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    fn build_test_program() -> Program {
+        let mut builder = test_utils::builder();
+
+        builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let time = builder.force_append_expect_output(vec![], Operation::LoadTime(0));
+        builder.force_append(vec![time.index], Operation::SetTime);
+        let mut_inventory =
+            builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+        builder.force_append_expect_output(vec![mut_inventory.index], Operation::EndBuildInventory);
+
+        builder.finalize().unwrap()
+    }
+
+    #[test]
+    fn insert_at_start_middle_and_end() {
+        let program = build_test_program();
+
+        // Insert at the start: all subsequent variables are shifted by one
+        let mut builder = ProgramBuilder::from_program(program.clone()).unwrap();
+        let variables = builder
+            .insert_at(
+                0,
+                Instruction {
+                    inputs: vec![],
+                    operation: Operation::LoadTime(5),
+                },
+            )
+            .unwrap();
+        assert_eq!(variables.len(), 1);
+        assert_eq!(variables[0].index, 0);
+        let inserted = builder.finalize().unwrap();
+        assert_eq!(inserted.instructions.len(), 6);
+        assert_eq!(inserted.instructions[0].operation, Operation::LoadTime(5));
+        assert_eq!(inserted.instructions[3].inputs, vec![2]);
+        assert_eq!(inserted.instructions[5].inputs, vec![3]);
+        assert!(inserted.is_statically_valid());
+
+        // Insert in the middle: only variables defined after the insertion point are shifted
+        let mut builder = ProgramBuilder::from_program(program.clone()).unwrap();
+        let variables = builder
+            .insert_at(
+                2,
+                Instruction {
+                    inputs: vec![],
+                    operation: Operation::LoadConnection(0),
+                },
+            )
+            .unwrap();
+        assert_eq!(variables[0].index, 2);
+        let inserted = builder.finalize().unwrap();
+        assert_eq!(inserted.instructions[3].inputs, vec![1]);
+        assert_eq!(inserted.instructions[5].inputs, vec![3]);
+        assert!(inserted.is_statically_valid());
+
+        // Insert at the end, using a variable defined earlier
+        let mut builder = ProgramBuilder::from_program(program.clone()).unwrap();
+        let variables = builder
+            .insert_at(
+                program.instructions.len(),
+                Instruction {
+                    inputs: vec![1],
+                    operation: Operation::SetTime,
+                },
+            )
+            .unwrap();
+        assert!(variables.is_empty());
+        let inserted = builder.finalize().unwrap();
+        assert_eq!(inserted.instructions.len(), 6);
+        assert_eq!(inserted.instructions[5].operation, Operation::SetTime);
+        assert!(inserted.is_statically_valid());
+    }
+
+    #[test]
+    fn remove_each_instruction() {
+        let program = build_test_program();
+        assert_eq!(program.instructions.len(), 5);
+
+        for index in 0..program.instructions.len() {
+            let mut builder = ProgramBuilder::from_program(program.clone()).unwrap();
+            let result = builder.remove_instruction(index);
+
+            let expected_len = match index {
+                // `LoadTime` output is used by `SetTime`
+                1 => {
+                    assert!(matches!(
+                        result,
+                        Err(ProgramValidationError::VariableStillUsed(1))
+                    ));
+                    program.instructions.len()
+                }
+                // Removing either end of the inventory block removes the whole block
+                3 | 4 => {
+                    assert!(result.is_ok());
+                    program.instructions.len() - 2
+                }
+                _ => {
+                    assert!(result.is_ok());
+                    program.instructions.len() - 1
+                }
+            };
+
+            let removed = builder.finalize().unwrap();
+            assert_eq!(removed.instructions.len(), expected_len);
+            assert!(removed.is_statically_valid());
+            // The builder state matches that of a builder created from the resulting program
+            assert_eq!(
+                builder.variable_count(),
+                ProgramBuilder::from_program(removed)
+                    .unwrap()
+                    .variable_count()
+            );
+        }
+
+        // Removing `SetTime` renumbers the inventory block
+        let mut builder = ProgramBuilder::from_program(program.clone()).unwrap();
+        builder.remove_instruction(2).unwrap();
+        assert_eq!(builder.instructions[3].inputs, vec![2]);
+        assert!(builder.remove_instruction(4).is_err());
+    }
+
+    #[test]
+    fn remove_block_with_instructions() {
+        let mut builder = test_utils::builder();
+        let txs = builder.force_append_expect_output(vec![], Operation::BeginBlockTransactions);
+        let time = builder.force_append_expect_output(vec![], Operation::LoadTime(0));
+        builder.force_append(vec![time.index], Operation::SetTime);
+        builder.force_append_expect_output(vec![txs.index], Operation::EndBlockTransactions);
+        let program = builder.finalize().unwrap();
+
+        // Removing either end of the block keeps its contents, which end up in the global scope
+        for index in [0, 3] {
+            let mut builder = ProgramBuilder::from_program(program.clone()).unwrap();
+            builder.remove_instruction(index).unwrap();
+            let removed = builder.finalize().unwrap();
+            assert_eq!(removed.instructions.len(), 2);
+            assert_eq!(removed.instructions[1].inputs, vec![0]);
+            assert!(builder.get_variable(0).is_some());
+            assert!(removed.is_statically_valid());
+        }
+    }
+
+    #[test]
+    fn insert_all_at_inserts_blocks() {
+        let program = build_test_program();
+        let mut builder = ProgramBuilder::from_program(program.clone()).unwrap();
+
+        // Insert a nested inventory block right before the end of the existing one
+        let variables = builder
+            .insert_all_at(
+                4,
+                [
+                    Instruction {
+                        inputs: vec![],
+                        operation: Operation::BeginBuildInventory,
+                    },
+                    Instruction {
+                        inputs: vec![3],
+                        operation: Operation::EndBuildInventory,
+                    },
+                ]
+                .into_iter(),
+            )
+            .unwrap();
+        assert_eq!(variables.len(), 1);
+        assert_eq!(variables[0].index, 4);
+        let inserted = builder.finalize().unwrap();
+        assert_eq!(inserted.instructions.len(), 7);
+        assert_eq!(inserted.instructions[6].inputs, vec![2]);
+        assert!(inserted.is_statically_valid());
+    }
+
+    #[test]
+    fn insert_at_validates_scope_at_index() {
+        let program = build_test_program();
+        let mut builder = ProgramBuilder::from_program(program.clone()).unwrap();
+
+        // The `LoadTime` output is not defined yet at index 1
+        assert!(matches!(
+            builder.insert_at(
+                1,
+                Instruction {
+                    inputs: vec![1],
+                    operation: Operation::SetTime,
+                },
+            ),
+            Err(ProgramValidationError::VariableNotDefined(1))
+        ));
+        assert!(matches!(
+            builder.insert_at(
+                program.instructions.len() + 1,
+                Instruction {
+                    inputs: vec![],
+                    operation: Operation::LoadTime(0),
+                },
+            ),
+            Err(ProgramValidationError::InstructionNotFound(_))
+        ));
+        assert_eq!(builder.finalize().unwrap(), program);
+    }
+
+    #[test]
+    fn context_exceeding_limits_is_rejected() {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: crate::MAX_NUM_CONNECTIONS + 1,
+            timestamp: 0,
+        });
+
+        assert!(matches!(
+            builder.append(Instruction {
+                inputs: vec![],
+                operation: Operation::LoadTime(0),
+            }),
+            Err(ProgramValidationError::ContextExceedsLimits {
+                field: "num_connections",
+                ..
+            })
+        ));
+        assert!(matches!(
+            builder.finalize(),
+            Err(ProgramValidationError::ContextExceedsLimits { .. })
+        ));
+    }
+
+    #[test]
+    fn program_too_long_is_rejected() {
+        let mut builder = test_utils::builder();
+        for _ in 0..MAX_PROGRAM_INSTRUCTIONS {
+            builder.force_append(vec![], Operation::LoadTime(0));
+        }
+
+        assert!(matches!(
+            builder.append(Instruction {
+                inputs: vec![],
+                operation: Operation::LoadTime(0),
+            }),
+            Err(ProgramValidationError::ProgramTooLong)
+        ));
+        assert!(builder.finalize().unwrap().is_statically_valid());
+    }
+}
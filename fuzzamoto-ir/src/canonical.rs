@@ -0,0 +1,189 @@
+use crate::{Instruction, Program};
+
+/// `CanonicalProgram` is a program in canonical form, for comparing programs independently of the
+/// order in which their variables were loaded.
+///
+/// Generators load variables (`Load*` operations) on demand, so the same program can end up with
+/// its loads in a different order depending on which generators ran first. This also changes the
+/// order in which e.g. `ProgramBuilder::get_all_variables` iterates the loaded variables. In
+/// canonical form, every run of consecutive loads is sorted by output variable type (see the `Ord`
+/// implementation of `Variable`) and then by the encoded operation, with the inputs of all later
+/// instructions remapped accordingly. Loads have no inputs and no side effects, so reordering them
+/// does not change what the program does.
+#[derive(Debug, Clone, Hash, PartialEq)]
+pub struct CanonicalProgram(Program);
+
+impl CanonicalProgram {
+    pub fn new(program: Program) -> Self {
+        let Program {
+            instructions,
+            context,
+        } = program;
+
+        // Index of the first variable defined by each instruction
+        let mut first_variables = Vec::with_capacity(instructions.len());
+        let mut variable_count = 0;
+        for instruction in &instructions {
+            first_variables.push(variable_count);
+            variable_count += instruction.operation.num_outputs();
+            variable_count += instruction.operation.num_inner_outputs();
+        }
+
+        let mut order: Vec<usize> = (0..instructions.len()).collect();
+        let mut start = 0;
+        while start < instructions.len() {
+            let run = instructions[start..]
+                .iter()
+                .take_while(|instruction| instruction.operation.is_load())
+                .count();
+            // Stable sort, identical loads keep their relative order
+            order[start..start + run].sort_by_cached_key(|index| {
+                let operation = &instructions[*index].operation;
+                (
+                    operation.get_output_variables(),
+                    postcard::to_allocvec(operation).unwrap_or_default(),
+                )
+            });
+            start += run.max(1);
+        }
+
+        let mut variable_mapping: Vec<usize> = (0..variable_count).collect();
+        let mut next_variable = 0;
+        for index in order.iter() {
+            let operation = &instructions[*index].operation;
+            for offset in 0..operation.num_outputs() + operation.num_inner_outputs() {
+                variable_mapping[first_variables[*index] + offset] = next_variable;
+                next_variable += 1;
+            }
+        }
+
+        let instructions = order
+            .into_iter()
+            .map(|index| Instruction {
+                inputs: instructions[index]
+                    .inputs
+                    .iter()
+                    .map(|input| variable_mapping.get(*input).copied().unwrap_or(*input))
+                    .collect(),
+                operation: instructions[index].operation.clone(),
+            })
+            .collect();
+
+        Self(Program {
+            instructions,
+            context,
+        })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.0
+    }
+
+    pub fn into_program(self) -> Program {
+        self.0
+    }
+}
+
+impl From<Program> for CanonicalProgram {
+    fn from(program: Program) -> Self {
+        Self::new(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Operation, ProgramBuilder, ProgramContext, Variable, test_utils};
+    use std::time::Duration;
+
+    fn build_program(load_time_first: bool) -> Program {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 2,
+            timestamp: 0,
+        });
+
+        let (time, duration) = if load_time_first {
+            let time = builder.force_append_expect_output(vec![], Operation::LoadTime(100));
+            builder.force_append_expect_output(vec![], Operation::LoadConnection(1));
+            let duration = builder.force_append_expect_output(
+                vec![],
+                Operation::LoadDuration(Duration::from_secs(5)),
+            );
+            builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+            (time, duration)
+        } else {
+            builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+            let duration = builder.force_append_expect_output(
+                vec![],
+                Operation::LoadDuration(Duration::from_secs(5)),
+            );
+            builder.force_append_expect_output(vec![], Operation::LoadConnection(1));
+            let time = builder.force_append_expect_output(vec![], Operation::LoadTime(100));
+            (time, duration)
+        };
+        let time = builder
+            .force_append_expect_output(vec![time.index, duration.index], Operation::AdvanceTime);
+        builder.force_append(vec![time.index], Operation::SetTime);
+
+        builder.finalize().unwrap()
+    }
+
+    #[test]
+    fn load_order_is_normalised() {
+        let a = build_program(true);
+        let b = build_program(false);
+        assert_ne!(a, b);
+
+        let canonical_a = CanonicalProgram::new(a);
+        let canonical_b = CanonicalProgram::new(b);
+        assert_eq!(canonical_a, canonical_b);
+        assert!(canonical_a.program().is_statically_valid());
+
+        // Loads are sorted by variable type first, then by operation
+        let loads: Vec<Operation> = canonical_a.program().instructions[..4]
+            .iter()
+            .map(|instruction| instruction.operation.clone())
+            .collect();
+        assert_eq!(
+            loads,
+            vec![
+                Operation::LoadConnection(0),
+                Operation::LoadConnection(1),
+                Operation::LoadDuration(Duration::from_secs(5)),
+                Operation::LoadTime(100),
+            ]
+        );
+        // `AdvanceTime` uses the remapped time and duration
+        assert_eq!(canonical_a.program().instructions[4].inputs, vec![3, 2]);
+
+        let builder = ProgramBuilder::from_program(canonical_b.into_program()).unwrap();
+        let connections: Vec<usize> = builder
+            .get_all_variables_sorted(Variable::Connection)
+            .iter()
+            .map(|connection| connection.index)
+            .collect();
+        assert_eq!(connections, vec![0, 1]);
+    }
+
+    #[test]
+    fn canonicalisation_is_stable() {
+        let mut builder = test_utils::builder();
+        builder.force_append_expect_output(vec![], Operation::LoadTime(7));
+        builder.force_append_expect_output(vec![], Operation::LoadTime(7));
+        let mut_inventory =
+            builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+        builder.force_append_expect_output(vec![mut_inventory.index], Operation::EndBuildInventory);
+        builder.force_append_expect_output(vec![], Operation::LoadTime(3));
+        let program = builder.finalize().unwrap();
+
+        // Already canonical: identical loads keep their order and loads aren't moved across other
+        // instructions
+        let canonical = CanonicalProgram::new(program.clone());
+        assert_eq!(canonical.program(), &program);
+        assert_eq!(
+            CanonicalProgram::new(canonical.clone().into_program()),
+            canonical
+        );
+    }
+}
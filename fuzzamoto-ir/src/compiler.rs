@@ -1,23 +1,26 @@
 use bitcoin::bip152::HeaderAndShortIds;
 use bitcoin::{
-    Amount, Block, CompactTarget, EcdsaSighashType, NetworkKind, OutPoint, PrivateKey, Script,
-    ScriptBuf, Sequence, Transaction, TxIn, TxMerkleNode, TxOut, Txid, WitnessMerkleNode, Wtxid,
+    Amount, Block, BlockHash, CompactTarget, EcdsaSighashType, MerkleBlock, NetworkKind, OutPoint,
+    PrivateKey, Script, ScriptBuf, Sequence, Transaction, TxIn, TxMerkleNode, TxOut, Txid,
+    WitnessMerkleNode, Wtxid,
     absolute::LockTime,
-    consensus::Encodable,
+    bip158::{BlockFilter, BlockFilterWriter, FilterHash, FilterHeader},
+    consensus::{Decodable, Encodable, encode::VarInt},
     ecdsa,
     hashes::{Hash, serde_macros::serde_details::SerdeHash, sha256},
+    hex::DisplayHex,
     key::{Secp256k1, TapTweak},
     opcodes::{
         OP_0, OP_TRUE,
-        all::{OP_PUSHNUM_1, OP_RETURN},
+        all::{OP_CHECKMULTISIG, OP_PUSHNUM_1, OP_RETURN},
     },
     p2p::{
         ServiceFlags,
         address::{AddrV2, AddrV2Message, Address},
-        message_blockdata::Inventory,
+        message_blockdata::{GetBlocksMessage, GetHeadersMessage, Inventory},
         message_bloom::{BloomFlags, FilterAdd, FilterLoad},
         message_compact_blocks::CmpctBlock,
-        message_filter::{GetCFCheckpt, GetCFHeaders, GetCFilters},
+        message_filter::{CFCheckpt, CFHeaders, CFilter, GetCFCheckpt, GetCFHeaders, GetCFilters},
     },
     script::PushBytesBuf,
     secp256k1::{self, Keypair, SecretKey},
@@ -31,10 +34,17 @@ use std::{any::Any, convert::TryInto, time::Duration};
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 
 use crate::{
-    AddrNetwork, AddrRecord, Instruction, Operation, Program, TaprootKeypair, TaprootLeaf,
-    TaprootSpendInfo, bloom::filter_insert, generators::block::Header,
+    AddrNetwork, AddrRecord, FullProgramContext, Header, Instruction, Operation, Program,
+    TaprootKeypair, TaprootLeaf, TaprootSpendInfo,
+    bloom::{filter_insert, filter_matches_tx},
+    msg_type_from_str,
 };
 
+/// Bits flipped by `Operation::RandomizeNonce`
+const NONCE_RANDOMIZATION_MASK: u64 = 0x9e37_79b9_7f4a_7c15;
+/// Number of nonces `Operation::CorruptBlockProofOfWork` tries before giving up
+const MAX_CORRUPT_PROOF_OF_WORK_ATTEMPTS: u32 = 1000;
+
 /// `Compiler` is responsible for compiling IR into a sequence of low-level actions to be performed
 /// on a node (i.e. mapping `fuzzamoto_ir::Program` -> `CompiledProgram`).
 pub struct Compiler {
@@ -61,6 +71,72 @@ pub struct CompiledProgram {
     pub metadata: CompiledMetadata,
 }
 
+/// Human-inspectable view of a message sent by a `CompiledProgram`
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct BitcoinMessage {
+    pub connection: usize,
+    pub command: String,
+    pub payload_hex: String,
+    /// Decoded payload for known message types (`None` if unknown or not decodable)
+    pub payload_decoded: Option<serde_json::Value>,
+}
+
+impl CompiledProgram {
+    /// Convert all `SendRawMessage` actions into `BitcoinMessage`s, decoding the payloads of
+    /// known message types.
+    pub fn to_bitcoin_messages(&self) -> Vec<BitcoinMessage> {
+        self.actions
+            .iter()
+            .filter_map(|action| match action {
+                CompiledAction::SendRawMessage(connection, command, payload) => {
+                    Some(BitcoinMessage {
+                        connection: *connection,
+                        command: command.clone(),
+                        payload_hex: payload.to_lower_hex_string(),
+                        payload_decoded: decode_payload(command, payload),
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn decode_payload(command: &str, payload: &[u8]) -> Option<serde_json::Value> {
+    // Message types of `SendRawMessage` are padded with null characters
+    match command.trim_end_matches('\0') {
+        "tx" => {
+            let tx: Transaction = bitcoin::consensus::deserialize(payload).ok()?;
+            serde_json::to_value(tx).ok()
+        }
+        "block" => {
+            let block: Block = bitcoin::consensus::deserialize(payload).ok()?;
+            serde_json::to_value(block).ok()
+        }
+        "headers" => {
+            // Each header is followed by a (zero) transaction count
+            let mut reader = payload;
+            let count = VarInt::consensus_decode(&mut reader).ok()?.0;
+            let mut headers = Vec::new();
+            for _ in 0..count {
+                headers.push(bitcoin::block::Header::consensus_decode(&mut reader).ok()?);
+                VarInt::consensus_decode(&mut reader).ok()?;
+            }
+            serde_json::to_value(headers).ok()
+        }
+        "inv" | "getdata" | "notfound" => {
+            let inventory: Vec<Inventory> = bitcoin::consensus::deserialize(payload).ok()?;
+            Some(serde_json::Value::Array(
+                inventory
+                    .iter()
+                    .map(|inv| serde_json::Value::String(format!("{:?}", inv)))
+                    .collect(),
+            ))
+        }
+        _ => None,
+    }
+}
+
 pub type VariableIndex = usize;
 
 pub type InstructionIndex = usize;
@@ -92,6 +168,14 @@ impl CompiledMetadata {
         }
     }
 
+    fn clear(&mut self) {
+        self.block_tx_var_map.clear();
+        self.connection_map.clear();
+        self.action_indices.clear();
+        self.variable_indices.clear();
+        self.instructions = 0;
+    }
+
     // Get the block variable index and list of transaction variable indices for a given block hash
     pub fn block_variables(
         &self,
@@ -123,6 +207,11 @@ pub enum CompilerError {
     IncorrectNumberOfInputs,
     VariableNotFound,
     IncorrectVariableType,
+    /// The program references an object that doesn't exist in the `FullProgramContext`
+    ContextMismatch {
+        field: &'static str,
+        value: String,
+    },
 }
 
 impl std::fmt::Display for CompilerError {
@@ -132,6 +221,13 @@ impl std::fmt::Display for CompilerError {
             CompilerError::IncorrectNumberOfInputs => write!(f, "Incorrect number of inputs"),
             CompilerError::VariableNotFound => write!(f, "Variable not found"),
             CompilerError::IncorrectVariableType => write!(f, "Incorrect variable type"),
+            CompilerError::ContextMismatch { field, value } => {
+                write!(
+                    f,
+                    "Context mismatch: {} {} not found in context",
+                    field, value
+                )
+            }
         }
     }
 }
@@ -159,6 +255,12 @@ enum SigningRequest {
         selected_leaf: Option<TaprootLeaf>,
         annex_var: Option<usize>,
     },
+    MultiSig {
+        operation: Operation,
+        script: Vec<u8>,
+        threshold: usize,
+        private_key_vars: Vec<usize>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -206,6 +308,13 @@ impl Txo {
             value: 0,
         }
     }
+
+    /// Consensus serialization of the outpoint, as matched by bloom filters
+    fn serialized_outpoint(&self) -> Vec<u8> {
+        let mut outpoint = self.prev_out.0.to_vec();
+        outpoint.extend_from_slice(&self.prev_out.1.to_le_bytes());
+        outpoint
+    }
 }
 
 #[derive(Clone)]
@@ -238,12 +347,15 @@ struct Tx {
 struct CoinbaseInput {
     sequence: usize,
     total_value: u64,
+    /// Custom scriptSig, the BIP34 height push is used if `None`
+    script_sig: Option<Vec<u8>>,
 }
 
 #[derive(Clone)]
 struct CoinbaseTx {
     tx: Tx,
     scripts: Vec<Scripts>,
+    script_sig: Option<Vec<u8>>,
 }
 
 #[derive(Clone, Debug)]
@@ -252,6 +364,30 @@ struct BlockTransactions {
     var_indices: Vec<usize>,
 }
 
+#[derive(Clone, Debug)]
+struct GetBlockTxnRequest {
+    /// Txids of the block's transactions, used to look up the indexes of requested transactions
+    txids: Vec<Txid>,
+    request: bitcoin::bip152::BlockTransactionsRequest,
+}
+
+#[derive(Clone, Debug)]
+struct MultiSig {
+    /// Number of signatures required by the script (M)
+    threshold: usize,
+    /// Number of keys declared by the script (N), not necessarily the number of added keys
+    num_keys: usize,
+    private_key_vars: Vec<usize>,
+    public_keys: Vec<bitcoin::PublicKey>,
+}
+
+#[derive(Clone, Debug)]
+struct BlockLocator {
+    hashes: Vec<BlockHash>,
+    /// Last block requested by `getblocks`/`getheaders` (all zeros to request up to the tip)
+    stop_hash: BlockHash,
+}
+
 #[derive(Clone, Debug)]
 struct AddrList {
     entries: Vec<(u32, Address)>,
@@ -266,6 +402,8 @@ struct Nop;
 
 impl Compiler {
     pub fn compile(&mut self, ir: &Program) -> CompilerResult {
+        let _span = tracing::info_span!("compile", instructions = ir.instructions.len()).entered();
+
         let probing_insts = ir
             .instructions
             .iter()
@@ -312,7 +450,12 @@ impl Compiler {
                 | Operation::LoadTaprootAnnex { .. }
                 | Operation::LoadFilterLoad { .. }
                 | Operation::LoadFilterAdd { .. }
-                | Operation::LoadNonce(..) => {
+                | Operation::LoadCFilter { .. }
+                | Operation::LoadNonce(..)
+                | Operation::LoadNonce64(..)
+                | Operation::LoadRawTransaction(..)
+                | Operation::LoadMsgTypeFromStr(..)
+                | Operation::LoadRawBytes(..) => {
                     self.handle_load_operations(&instruction)?;
                 }
                 Operation::TaprootScriptsUseAnnex | Operation::TaprootTxoUseAnnex => {
@@ -322,10 +465,16 @@ impl Compiler {
                     self.handle_build_taproot_tree(&instruction)?;
                 }
 
-                Operation::BuildCompactBlock => {
+                Operation::BuildCompactBlock | Operation::RandomizeNonce => {
                     self.handle_compact_block_building_operations(&instruction)?;
                 }
 
+                Operation::CorruptBlockMerkleRoot(_)
+                | Operation::CorruptBlockProofOfWork
+                | Operation::CorruptBlockCoinbaseValue(_) => {
+                    self.handle_block_corruption_operations(&instruction)?;
+                }
+
                 Operation::BeginBlockTransactions
                 | Operation::AddTx
                 | Operation::EndBlockTransactions
@@ -340,11 +489,19 @@ impl Compiler {
                 | Operation::AddTxidInv
                 | Operation::AddCompactBlockInv
                 | Operation::AddBlockInv
+                | Operation::AddBlockHeaderInv
                 | Operation::AddBlockWithWitnessInv
                 | Operation::AddFilteredBlockInv => {
                     self.handle_inventory_operations(&instruction)?;
                 }
 
+                Operation::BeginBuildGetBlocksLocator
+                | Operation::EndBuildGetBlocksLocator
+                | Operation::AddLocatorHash
+                | Operation::SetLocatorStopHash => {
+                    self.handle_block_locator_operations(&instruction)?;
+                }
+
                 Operation::BeginBuildAddrList
                 | Operation::BeginBuildAddrListV2
                 | Operation::EndBuildAddrList
@@ -372,12 +529,21 @@ impl Compiler {
                     self.handle_script_building_operations(&instruction)?;
                 }
 
+                Operation::BeginBuildMultiSig
+                | Operation::AddMultiSigKey
+                | Operation::EndBuildMultiSig
+                | Operation::EndBuildMultiSigScriptHash
+                | Operation::EndBuildMultiSigWitnessScriptHash => {
+                    self.handle_multisig_operations(&instruction)?;
+                }
+
                 Operation::BuildFilterAddFromTx
                 | Operation::BuildFilterAddFromTxo
                 | Operation::AddTxToFilter
                 | Operation::AddTxoToFilter
                 | Operation::BeginBuildFilterLoad
-                | Operation::EndBuildFilterLoad => {
+                | Operation::EndBuildFilterLoad
+                | Operation::BuildMerkleBlock => {
                     self.handle_filter_building_operations(&instruction)?;
                 }
 
@@ -397,6 +563,8 @@ impl Compiler {
                 Operation::BeginBuildCoinbaseTx
                 | Operation::EndBuildCoinbaseTx
                 | Operation::BuildCoinbaseTxInput
+                | Operation::LoadCoinbaseScriptSig
+                | Operation::BuildCoinbaseTxInputWithScriptSig
                 | Operation::BeginBuildCoinbaseTxOutputs
                 | Operation::EndBuildCoinbaseTxOutputs
                 | Operation::AddCoinbaseTxOutput => {
@@ -413,11 +581,19 @@ impl Compiler {
                     self.handle_bip152_blocktxn_operations(&instruction)?;
                 }
 
+                Operation::BeginBuildGetBlockTxn
+                | Operation::AddShortIdToReq
+                | Operation::EndBuildGetBlockTxn => {
+                    self.handle_bip152_getblocktxn_operations(&instruction)?;
+                }
+
                 Operation::SendRawMessage
                 | Operation::SendTxNoWit
                 | Operation::SendTx
                 | Operation::SendGetData
                 | Operation::SendInv
+                | Operation::SendGetBlocks
+                | Operation::SendGetHeaders
                 | Operation::SendGetAddr
                 | Operation::SendAddr
                 | Operation::SendAddrV2
@@ -430,8 +606,22 @@ impl Compiler {
                 | Operation::SendFilterLoad
                 | Operation::SendFilterAdd
                 | Operation::SendFilterClear
+                | Operation::SendMempoolMsg
+                | Operation::SendFeeFilter
+                | Operation::SendPingWithNonce
+                | Operation::SendPong
+                | Operation::SendNotFound
                 | Operation::SendCompactBlock
-                | Operation::SendBlockTxn => {
+                | Operation::SendMerkleBlock
+                | Operation::SendSendCmpct
+                | Operation::SendSendHeaders
+                | Operation::SendSendTxRcncl
+                | Operation::SendBlockTxn
+                | Operation::SendGetBlockTxn
+                | Operation::SendRawTransaction
+                | Operation::SendCFilter { .. }
+                | Operation::SendCFHeaders { .. }
+                | Operation::SendCFCheckpt { .. } => {
                     self.handle_message_sending_operations(&instruction)?;
                 }
 
@@ -462,10 +652,113 @@ impl Compiler {
         Ok(self.output.clone()) // TODO: do not clone
     }
 
+    /// Compile `ir` with a fresh `Compiler`, unless a program with the same content hash (see
+    /// `Program::hash_stable`) was compiled before, in which case its cached result is returned
+    #[tracing::instrument(skip_all, fields(cached = cache.len()))]
+    pub fn compile_cached(
+        ir: &Program,
+        cache: &mut HashMap<[u8; 32], CompiledProgram>,
+    ) -> CompilerResult {
+        let hash = ir.hash_stable();
+        if let Some(compiled) = cache.get(&hash) {
+            return Ok(compiled.clone());
+        }
+
+        let compiled = Compiler::with_seed(hash).compile(ir)?;
+        cache.insert(hash, compiled.clone());
+        Ok(compiled)
+    }
+
+    /// Like `Compiler::compile`, but first checks that all context objects the program loads
+    /// (txos, headers and connections) exist in `ctx`
+    #[tracing::instrument(skip_all)]
+    pub fn compile_with_context(
+        &mut self,
+        ir: &Program,
+        ctx: &FullProgramContext,
+    ) -> CompilerResult {
+        Self::validate_context(ir, ctx)?;
+        self.compile(ir)
+    }
+
+    fn validate_context(ir: &Program, ctx: &FullProgramContext) -> Result<(), CompilerError> {
+        for instruction in &ir.instructions {
+            match &instruction.operation {
+                Operation::LoadTxo { outpoint, .. } => {
+                    if !ctx.txos.iter().any(|txo| txo.outpoint == *outpoint) {
+                        return Err(CompilerError::ContextMismatch {
+                            field: "txo",
+                            value: format!("{}:{}", outpoint.0.to_lower_hex_string(), outpoint.1),
+                        });
+                    }
+                }
+                Operation::LoadHeader {
+                    prev,
+                    merkle_root,
+                    nonce,
+                    bits,
+                    time,
+                    version,
+                    height,
+                } => {
+                    let header = Header {
+                        prev: *prev,
+                        merkle_root: *merkle_root,
+                        nonce: *nonce,
+                        bits: *bits,
+                        time: *time,
+                        version: *version,
+                        height: *height,
+                    };
+                    let block_hash = header.block_hash();
+                    if !ctx
+                        .headers
+                        .iter()
+                        .any(|h| h.height == *height && h.block_hash() == block_hash)
+                    {
+                        return Err(CompilerError::ContextMismatch {
+                            field: "header",
+                            value: block_hash.to_string(),
+                        });
+                    }
+                }
+                Operation::LoadConnection(index) if *index >= ctx.context.num_connections => {
+                    return Err(CompilerError::ContextMismatch {
+                        field: "connection",
+                        value: index.to_string(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
     pub fn new() -> Self {
+        Self::with_secp_ctx(Secp256k1::new())
+    }
+
+    /// Create a compiler whose secp256k1 context is randomized with `seed` instead of the thread
+    /// rng. Note that signatures do not depend on the context randomization (ECDSA nonces are
+    /// derived as per RFC6979 and schnorr signatures use no auxiliary randomness), so this only
+    /// makes the internal state of the compiler reproducible.
+    pub fn with_seed(seed: [u8; 32]) -> Self {
+        let mut secp_ctx = Secp256k1::gen_new();
+        secp_ctx.seeded_randomize(&seed);
+        Self::with_secp_ctx(secp_ctx)
+    }
+
+    /// Clear the state of the previous compilation, such that the compiler (and its allocations,
+    /// including the secp256k1 context) can be reused for another program
+    pub fn reset(&mut self) {
+        self.variables.clear();
+        self.output.actions.clear();
+        self.output.metadata.clear();
+    }
+
+    fn with_secp_ctx(secp_ctx: Secp256k1<bitcoin::secp256k1::All>) -> Self {
         Self {
-            // TODO: make this deterministic
-            secp_ctx: Secp256k1::new(),
+            secp_ctx,
             variables: Vec::with_capacity(4096),
             output: CompiledProgram {
                 actions: Vec::with_capacity(4096),
@@ -534,6 +827,13 @@ impl Compiler {
                 let inventory_var = self.get_input_mut::<Vec<Inventory>>(&instruction.inputs, 0)?;
                 inventory_var.push(inv);
             }
+            Operation::AddBlockHeaderInv => {
+                let block_hash = self
+                    .get_input::<Header>(&instruction.inputs, 1)?
+                    .block_hash();
+                let inventory_var = self.get_input_mut::<Vec<Inventory>>(&instruction.inputs, 0)?;
+                inventory_var.push(Inventory::Block(block_hash));
+            }
             Operation::AddBlockWithWitnessInv => {
                 let block_var = self.get_input::<bitcoin::Block>(&instruction.inputs, 1)?;
                 let inv = Inventory::WitnessBlock(block_var.header.block_hash());
@@ -554,6 +854,44 @@ impl Compiler {
         Ok(())
     }
 
+    fn handle_block_locator_operations(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<(), CompilerError> {
+        match &instruction.operation {
+            Operation::BeginBuildGetBlocksLocator => {
+                self.append_variable(BlockLocator {
+                    hashes: Vec::new(),
+                    stop_hash: BlockHash::all_zeros(),
+                });
+            }
+            Operation::EndBuildGetBlocksLocator => {
+                let locator_var = self
+                    .get_input::<BlockLocator>(&instruction.inputs, 0)?
+                    .clone();
+                self.append_variable(locator_var);
+            }
+            Operation::AddLocatorHash => {
+                let block_hash = self
+                    .get_input::<Header>(&instruction.inputs, 1)?
+                    .block_hash();
+                let locator_var = self.get_input_mut::<BlockLocator>(&instruction.inputs, 0)?;
+                locator_var.hashes.push(block_hash);
+            }
+            Operation::SetLocatorStopHash => {
+                let block_hash = self
+                    .get_input::<Header>(&instruction.inputs, 1)?
+                    .block_hash();
+                let locator_var = self.get_input_mut::<BlockLocator>(&instruction.inputs, 0)?;
+                locator_var.stop_hash = block_hash;
+            }
+            _ => unreachable!(
+                "Non-block-locator operation passed to handle_block_locator_operations"
+            ),
+        }
+        Ok(())
+    }
+
     fn handle_addr_operations(&mut self, instruction: &Instruction) -> Result<(), CompilerError> {
         match &instruction.operation {
             Operation::BeginBuildAddrList => {
@@ -749,21 +1087,24 @@ impl Compiler {
                 self.append_variable(filter);
             }
             Operation::AddTxToFilter => {
-                let tx_as_array = self
-                    .get_input::<Tx>(&instruction.inputs, 1)?
-                    .id
-                    .as_raw_hash()
-                    .as_byte_array()
-                    .to_vec();
+                let tx_var = self.get_input::<Tx>(&instruction.inputs, 1)?;
+                // The txid matches the transaction itself, the outpoints of its outputs match the
+                // transactions spending them
+                let mut keys = vec![tx_var.id.as_raw_hash().as_byte_array().to_vec()];
+                keys.extend(tx_var.txos.iter().map(Txo::serialized_outpoint));
                 let mut_filter = self.get_input_mut::<FilterLoad>(&instruction.inputs, 0)?;
                 let n_hash_funcs = mut_filter.hash_funcs;
-                filter_insert(&mut mut_filter.filter, n_hash_funcs, &tx_as_array);
+                for key in keys {
+                    filter_insert(&mut mut_filter.filter, n_hash_funcs, &key);
+                }
             }
             Operation::AddTxoToFilter => {
-                let txo = self.get_input::<Txo>(&instruction.inputs, 1)?.prev_out.0;
+                let outpoint = self
+                    .get_input::<Txo>(&instruction.inputs, 1)?
+                    .serialized_outpoint();
                 let mut_filter = self.get_input_mut::<FilterLoad>(&instruction.inputs, 0)?;
                 let n_hash_funcs = mut_filter.hash_funcs;
-                filter_insert(&mut mut_filter.filter, n_hash_funcs, &txo);
+                filter_insert(&mut mut_filter.filter, n_hash_funcs, &outpoint);
             }
             Operation::EndBuildFilterLoad => {
                 let filter = self
@@ -788,6 +1129,21 @@ impl Compiler {
 
                 self.append_variable(filteradd);
             }
+            Operation::BuildMerkleBlock => {
+                let block = self.get_input::<bitcoin::Block>(&instruction.inputs, 0)?;
+                let filter = self.get_input::<FilterLoad>(&instruction.inputs, 1)?;
+
+                let matched_txids: Vec<_> = block
+                    .txdata
+                    .iter()
+                    .filter(|tx| filter_matches_tx(&filter.filter, filter.hash_funcs, tx))
+                    .map(Transaction::compute_txid)
+                    .collect();
+                let merkle_block = MerkleBlock::from_block_with_predicate(block, |txid| {
+                    matched_txids.contains(txid)
+                });
+                self.append_variable(merkle_block);
+            }
             _ => unreachable!(
                 "Non-filter-building operation passed to handle_filter_building_operations"
             ),
@@ -812,6 +1168,10 @@ impl Compiler {
                     compact_block: header_and_shortids,
                 });
             }
+            Operation::RandomizeNonce => {
+                let nonce = self.get_input::<u64>(&instruction.inputs, 0)?;
+                self.append_variable(*nonce ^ NONCE_RANDOMIZATION_MASK);
+            }
             _ => unreachable!(
                 "Non-compactblock-building operation passed to handle_compact_block_building_operations"
             ),
@@ -819,6 +1179,68 @@ impl Compiler {
         Ok(())
     }
 
+    fn handle_block_corruption_operations(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<(), CompilerError> {
+        let mut block = self
+            .get_input::<bitcoin::Block>(&instruction.inputs, 0)?
+            .clone();
+
+        match &instruction.operation {
+            Operation::CorruptBlockMerkleRoot(merkle_root) => {
+                block.header.merkle_root = TxMerkleNode::from_byte_array(*merkle_root);
+                fuzzamoto::test_utils::mining::fixup_proof_of_work(&mut block);
+            }
+            Operation::CorruptBlockProofOfWork => {
+                // Bump the nonce until the block no longer satisfies its target (which may never
+                // happen for very easy targets)
+                let original_nonce = block.header.nonce;
+                let corrupted = (1..=MAX_CORRUPT_PROOF_OF_WORK_ATTEMPTS).any(|attempt| {
+                    block.header.nonce = original_nonce.wrapping_add(attempt);
+                    !fuzzamoto::test_utils::mining::has_valid_proof_of_work(&block.header)
+                });
+                if !corrupted {
+                    return Err(CompilerError::MiscError(
+                        "Block target is met by every nonce".to_string(),
+                    ));
+                }
+            }
+            Operation::CorruptBlockCoinbaseValue(value) => {
+                let coinbase = block.txdata.first_mut().ok_or(CompilerError::MiscError(
+                    "Block has no coinbase".to_string(),
+                ))?;
+                let commitment_index =
+                    fuzzamoto::test_utils::mining::find_witness_commitment_output(coinbase);
+                match (0..coinbase.output.len()).find(|index| Some(*index) != commitment_index) {
+                    Some(index) => {
+                        let output = &mut coinbase.output[index];
+                        output.value = output
+                            .value
+                            .checked_add(Amount::from_sat(*value))
+                            .unwrap_or(Amount::MAX);
+                    }
+                    None => coinbase.output.insert(
+                        0,
+                        TxOut {
+                            value: Amount::from_sat(*value),
+                            script_pubkey: ScriptBuf::from_bytes(vec![OP_TRUE.to_u8()]),
+                        },
+                    ),
+                }
+
+                fuzzamoto::test_utils::mining::fixup_commitments(&mut block);
+                fuzzamoto::test_utils::mining::fixup_proof_of_work(&mut block);
+            }
+            _ => unreachable!(
+                "Non-block-corruption operation passed to handle_block_corruption_operations"
+            ),
+        }
+
+        self.append_variable(block);
+        Ok(())
+    }
+
     fn handle_taproot_conversions(
         &mut self,
         instruction: &Instruction,
@@ -1148,6 +1570,76 @@ impl Compiler {
         Ok(())
     }
 
+    fn handle_multisig_operations(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<(), CompilerError> {
+        match &instruction.operation {
+            Operation::BeginBuildMultiSig => {
+                let threshold = *self.get_input::<usize>(&instruction.inputs, 0)?;
+                let num_keys = *self.get_input::<usize>(&instruction.inputs, 1)?;
+
+                self.append_variable(MultiSig {
+                    threshold,
+                    num_keys,
+                    private_key_vars: Vec::new(),
+                    public_keys: Vec::new(),
+                });
+            }
+            Operation::AddMultiSigKey => {
+                let private_key_var = self.get_input::<[u8; 32]>(&instruction.inputs, 1)?;
+                let private_key = PrivateKey::from_slice(private_key_var, NetworkKind::Main)
+                    .map_err(|e| CompilerError::MiscError(format!("invalid private key: {e}")))?;
+                let public_key = private_key.public_key(&self.secp_ctx);
+
+                let multisig_var = self.get_input_mut::<MultiSig>(&instruction.inputs, 0)?;
+                multisig_var.private_key_vars.push(instruction.inputs[1]);
+                multisig_var.public_keys.push(public_key);
+            }
+            Operation::EndBuildMultiSig
+            | Operation::EndBuildMultiSigScriptHash
+            | Operation::EndBuildMultiSigWitnessScriptHash => {
+                let multisig_var = self.get_input::<MultiSig>(&instruction.inputs, 0)?.clone();
+
+                // OP_M <pubkeys> OP_N OP_CHECKMULTISIG
+                let mut script_builder =
+                    ScriptBuf::builder().push_int(multisig_var.threshold as i64);
+                for public_key in &multisig_var.public_keys {
+                    script_builder = script_builder.push_key(public_key);
+                }
+                let script = script_builder
+                    .push_int(multisig_var.num_keys as i64)
+                    .push_opcode(OP_CHECKMULTISIG)
+                    .into_script();
+
+                let script_pubkey = match &instruction.operation {
+                    Operation::EndBuildMultiSigScriptHash => {
+                        ScriptBuf::new_p2sh(&script.script_hash())
+                    }
+                    Operation::EndBuildMultiSigWitnessScriptHash => {
+                        ScriptBuf::new_p2wsh(&script.wscript_hash())
+                    }
+                    _ => script.clone(),
+                };
+
+                // scriptSig and witness are assembled when the spending transaction is signed
+                self.append_variable(Scripts {
+                    script_pubkey: script_pubkey.into_bytes(),
+                    script_sig: vec![],
+                    witness: Witness { stack: Vec::new() },
+                    requires_signing: Some(SigningRequest::MultiSig {
+                        operation: instruction.operation.clone(),
+                        script: script.into_bytes(),
+                        threshold: multisig_var.threshold,
+                        private_key_vars: multisig_var.private_key_vars,
+                    }),
+                });
+            }
+            _ => unreachable!("Non-multisig operation passed to handle_multisig_operations"),
+        }
+        Ok(())
+    }
+
     fn handle_transaction_building_operations(
         &mut self,
         instruction: &Instruction,
@@ -1295,6 +1787,7 @@ impl Compiler {
                 self.append_variable(CoinbaseTx {
                     tx: tx_var,
                     scripts: scripts_vec,
+                    script_sig: coinbase_input_var.script_sig,
                 });
             }
             Operation::BuildCoinbaseTxInput => {
@@ -1303,6 +1796,21 @@ impl Compiler {
                 self.append_variable(CoinbaseInput {
                     sequence: sequence_var as usize,
                     total_value: Amount::from_int_btc(25).to_sat(),
+                    script_sig: None,
+                });
+            }
+            Operation::LoadCoinbaseScriptSig => {
+                let bytes_var = self.get_input::<Vec<u8>>(&instruction.inputs, 0)?.clone();
+                self.append_variable(bytes_var);
+            }
+            Operation::BuildCoinbaseTxInputWithScriptSig => {
+                let sequence_var = *self.get_input::<u32>(&instruction.inputs, 0)?;
+                let script_sig_var = self.get_input::<Vec<u8>>(&instruction.inputs, 1)?.clone();
+
+                self.append_variable(CoinbaseInput {
+                    sequence: sequence_var as usize,
+                    total_value: Amount::from_int_btc(25).to_sat(),
+                    script_sig: Some(script_sig_var),
                 });
             }
             Operation::BeginBuildCoinbaseTxOutputs => {
@@ -1348,6 +1856,13 @@ impl Compiler {
                     .clone();
                 self.emit_send_message(*connection_var, "blocktxn", &blocktxn);
             }
+            Operation::SendGetBlockTxn => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                let request = self
+                    .get_input::<bitcoin::bip152::BlockTransactionsRequest>(&instruction.inputs, 1)?
+                    .clone();
+                self.emit_send_message(*connection_var, "getblocktxn", &request);
+            }
             Operation::SendRawMessage => {
                 let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
                 let message_type_var = self.get_input::<[char; 12]>(&instruction.inputs, 1)?;
@@ -1359,6 +1874,63 @@ impl Compiler {
                     bytes_var.clone(),
                 );
             }
+            Operation::SendRawTransaction => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                let bytes_var = self.get_input::<Vec<u8>>(&instruction.inputs, 1)?;
+
+                self.emit_send_raw_message(*connection_var, "tx", bytes_var.clone());
+            }
+            Operation::SendCFilter { filter_type } => {
+                let connection_var = *self.get_input::<usize>(&instruction.inputs, 0)?;
+                let header_var = self.get_input::<Header>(&instruction.inputs, 1)?;
+                let block_transactions_var =
+                    self.get_input::<BlockTransactions>(&instruction.inputs, 2)?;
+
+                let filter = self.compute_block_filter(header_var, block_transactions_var);
+                let cfilter = CFilter {
+                    filter_type: *filter_type,
+                    block_hash: header_var.block_hash(),
+                    filter: filter.content,
+                };
+                self.emit_send_message(connection_var, "cfilter", &cfilter);
+            }
+            Operation::SendCFHeaders {
+                filter_type,
+                prev_filter_header,
+            } => {
+                let connection_var = *self.get_input::<usize>(&instruction.inputs, 0)?;
+                let header_var = self.get_input::<Header>(&instruction.inputs, 1)?;
+                let block_transactions_var =
+                    self.get_input::<BlockTransactions>(&instruction.inputs, 2)?;
+
+                let filter = self.compute_block_filter(header_var, block_transactions_var);
+                let cfheaders = CFHeaders {
+                    filter_type: *filter_type,
+                    stop_hash: header_var.block_hash(),
+                    previous_filter_header: FilterHeader::from_byte_array(*prev_filter_header),
+                    filter_hashes: vec![FilterHash::hash(&filter.content)],
+                };
+                self.emit_send_message(connection_var, "cfheaders", &cfheaders);
+            }
+            Operation::SendCFCheckpt {
+                filter_type,
+                prev_filter_header,
+            } => {
+                let connection_var = *self.get_input::<usize>(&instruction.inputs, 0)?;
+                let header_var = self.get_input::<Header>(&instruction.inputs, 1)?;
+                let block_transactions_var =
+                    self.get_input::<BlockTransactions>(&instruction.inputs, 2)?;
+
+                let filter = self.compute_block_filter(header_var, block_transactions_var);
+                let cfcheckpt = CFCheckpt {
+                    filter_type: *filter_type,
+                    stop_hash: header_var.block_hash(),
+                    filter_headers: vec![
+                        filter.filter_header(&FilterHeader::from_byte_array(*prev_filter_header)),
+                    ],
+                };
+                self.emit_send_message(connection_var, "cfcheckpt", &cfcheckpt);
+            }
             Operation::SendTxNoWit | Operation::SendTx => {
                 let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
                 let tx_var = self.get_input::<Tx>(&instruction.inputs, 1)?;
@@ -1388,6 +1960,20 @@ impl Compiler {
                     bitcoin::consensus::encode::serialize(&inv_var),
                 );
             }
+            Operation::SendGetBlocks | Operation::SendGetHeaders => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                let locator_var = self.get_input::<BlockLocator>(&instruction.inputs, 1)?;
+
+                let locator_hashes = locator_var.hashes.clone();
+                let stop_hash = locator_var.stop_hash;
+                if matches!(instruction.operation, Operation::SendGetBlocks) {
+                    let message = GetBlocksMessage::new(locator_hashes, stop_hash);
+                    self.emit_send_message(*connection_var, "getblocks", &message);
+                } else {
+                    let message = GetHeadersMessage::new(locator_hashes, stop_hash);
+                    self.emit_send_message(*connection_var, "getheaders", &message);
+                }
+            }
             Operation::SendGetAddr => {
                 let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
                 self.emit_send_raw_message(*connection_var, "getaddr", vec![]);
@@ -1502,8 +2088,48 @@ impl Compiler {
             }
             Operation::SendFilterClear => {
                 let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
-                let empty: Vec<u8> = Vec::new();
-                self.emit_send_message(*connection_var, "filterclear", &empty);
+                // `filterclear` has no payload (serializing an empty vector would add its length)
+                self.emit_send_raw_message(*connection_var, "filterclear", vec![]);
+            }
+            Operation::SendMempoolMsg => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                self.emit_send_raw_message(*connection_var, "mempool", vec![]);
+            }
+            Operation::SendFeeFilter => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                // Fee rate in sat/kvB
+                let fee_rate = *self.get_input::<u64>(&instruction.inputs, 1)?;
+                self.emit_send_message(*connection_var, "feefilter", &fee_rate);
+            }
+            Operation::SendPingWithNonce => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                let nonce = *self.get_input::<u64>(&instruction.inputs, 1)?;
+                self.emit_send_message(*connection_var, "ping", &nonce);
+            }
+            Operation::SendPong => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                let nonce = *self.get_input::<u64>(&instruction.inputs, 1)?;
+                self.emit_send_message(*connection_var, "pong", &nonce);
+            }
+            Operation::SendNotFound => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                let inv_var = self.get_input::<Vec<Inventory>>(&instruction.inputs, 1)?;
+
+                // `notfound` shares the `inv` message layout
+                self.emit_send_raw_message(
+                    *connection_var,
+                    "notfound",
+                    bitcoin::consensus::encode::serialize(&inv_var),
+                );
+            }
+            Operation::SendMerkleBlock => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                let merkle_block = self.get_input::<MerkleBlock>(&instruction.inputs, 1)?;
+                self.emit_send_raw_message(
+                    *connection_var,
+                    "merkleblock",
+                    bitcoin::consensus::encode::serialize(merkle_block),
+                );
             }
             Operation::SendCompactBlock => {
                 let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
@@ -1516,6 +2142,31 @@ impl Compiler {
                     },
                 );
             }
+            Operation::SendSendCmpct => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                let announce = self.get_input::<Vec<u8>>(&instruction.inputs, 1)?;
+                let version = self.get_input::<usize>(&instruction.inputs, 2)?;
+
+                // Only the first byte is used as the announce flag, other values than 0 and 1 are
+                // kept to exercise the deserialization of the flag
+                let mut payload = vec![announce.first().copied().unwrap_or(0)];
+                payload.extend_from_slice(&(*version as u64).to_le_bytes());
+                self.emit_send_raw_message(*connection_var, "sendcmpct", payload);
+            }
+            Operation::SendSendHeaders => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                self.emit_send_raw_message(*connection_var, "sendheaders", vec![]);
+            }
+            Operation::SendSendTxRcncl => {
+                let connection_var = self.get_input::<usize>(&instruction.inputs, 0)?;
+                let version = self.get_input::<usize>(&instruction.inputs, 1)?;
+                let salt = self.get_input::<u64>(&instruction.inputs, 2)?;
+
+                // BIP-330: uint32 version, uint64 salt
+                let mut payload = (*version as u32).to_le_bytes().to_vec();
+                payload.extend_from_slice(&salt.to_le_bytes());
+                self.emit_send_raw_message(*connection_var, "sendtxrcncl", payload);
+            }
             _ => unreachable!(
                 "Non-message-sending operation passed to handle_message_sending_operations"
             ),
@@ -1555,6 +2206,55 @@ impl Compiler {
         Ok(())
     }
 
+    fn handle_bip152_getblocktxn_operations(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<(), CompilerError> {
+        match &instruction.operation {
+            Operation::BeginBuildGetBlockTxn => {
+                let block = self.get_input::<Block>(&instruction.inputs, 0)?;
+                let request = GetBlockTxnRequest {
+                    txids: block.txdata.iter().map(Transaction::compute_txid).collect(),
+                    request: bitcoin::bip152::BlockTransactionsRequest {
+                        block_hash: block.block_hash(),
+                        indexes: Vec::new(),
+                    },
+                };
+                self.append_variable(request);
+            }
+            Operation::AddShortIdToReq => {
+                let txid = self
+                    .get_input::<Tx>(&instruction.inputs, 1)?
+                    .tx
+                    .compute_txid();
+                let request = self.get_input_mut::<GetBlockTxnRequest>(&instruction.inputs, 0)?;
+                // Transactions that are not part of the block are requested by an out-of-bounds
+                // index
+                let index = request
+                    .txids
+                    .iter()
+                    .position(|id| *id == txid)
+                    .unwrap_or(request.txids.len());
+                request.request.indexes.push(index as u64);
+            }
+            Operation::EndBuildGetBlockTxn => {
+                let mut request = self
+                    .get_input::<GetBlockTxnRequest>(&instruction.inputs, 0)?
+                    .request
+                    .clone();
+                // Indexes are differentially encoded (BIP-152), which requires them to be unique
+                // and in ascending order
+                request.indexes.sort_unstable();
+                request.indexes.dedup();
+                self.append_variable(request);
+            }
+            _ => unreachable!(
+                "Non-getblocktxn operation passed to handle_bip152_getblocktxn_operations"
+            ),
+        }
+        Ok(())
+    }
+
     fn handle_load_operations(&mut self, instruction: &Instruction) -> Result<(), CompilerError> {
         match &instruction.operation {
             Operation::Nop {
@@ -1590,7 +2290,9 @@ impl Compiler {
                 self.handle_load_operation(*filter_type)
             }
             Operation::LoadMsgType(message_type) => self.handle_load_operation(*message_type),
-            Operation::LoadBytes(bytes) => self.handle_load_operation(bytes.clone()),
+            Operation::LoadBytes(bytes) | Operation::LoadRawBytes(bytes) => {
+                self.handle_load_operation(bytes.clone())
+            }
             Operation::LoadSize(size) => self.handle_load_operation(*size),
             Operation::LoadPrivateKey(private_key) => self.handle_load_operation(*private_key),
             Operation::LoadSigHashFlags(sig_hash_flags) => {
@@ -1659,10 +2361,30 @@ impl Compiler {
             Operation::LoadFilterAdd { data } => {
                 self.handle_load_operation(FilterAdd { data: data.clone() });
             }
-            Operation::LoadNonce(nonce) => self.handle_load_operation(*nonce),
+            Operation::LoadCFilter {
+                filter_type,
+                block_hash,
+                filter,
+            } => {
+                // `cfilter` payload: filter type, block hash and the length prefixed filter
+                let mut payload = vec![*filter_type];
+                payload.extend_from_slice(block_hash);
+                payload.extend(bitcoin::consensus::encode::serialize(filter));
+                self.handle_load_operation(payload);
+            }
+            Operation::LoadNonce(nonce) | Operation::LoadNonce64(nonce) => {
+                self.handle_load_operation(*nonce);
+            }
             Operation::LoadTaprootAnnex { annex } => {
                 self.handle_load_operation(annex.clone());
             }
+            Operation::LoadRawTransaction(bytes) => self.handle_load_operation(bytes.clone()),
+            Operation::LoadMsgTypeFromStr(msg_type) => {
+                let msg_type = msg_type_from_str(msg_type).ok_or_else(|| {
+                    CompilerError::MiscError(format!("Invalid message type: {:?}", msg_type))
+                })?;
+                self.handle_load_operation(msg_type)
+            }
             _ => unreachable!("Non-load operation passed to handle_load_operations"),
         }
         Ok(())
@@ -1802,6 +2524,65 @@ impl Compiler {
         );
     }
 
+    /// Compute the basic BIP-158 filter of the block with header `header`. If the block was built
+    /// earlier in the program its full transaction list (including the coinbase) is used, otherwise
+    /// the filter covers `block_transactions` only. Spent output scripts are included for all inputs
+    /// spending txos known to the compiler.
+    fn compute_block_filter(
+        &self,
+        header: &Header,
+        block_transactions: &BlockTransactions,
+    ) -> BlockFilter {
+        let block_hash = header.block_hash();
+        let block = self
+            .output
+            .metadata
+            .block_variables(&block_hash)
+            .and_then(|(_, block_var, _)| self.get_variable::<Block>(block_var).ok())
+            .cloned()
+            .unwrap_or_else(|| Block {
+                header: header.to_bitcoin_header(),
+                txdata: block_transactions
+                    .txs
+                    .iter()
+                    .map(|tx| tx.tx.clone())
+                    .collect(),
+            });
+
+        let mut spent_scripts = HashMap::new();
+        for var in &self.variables {
+            if let Some(txo) = var.downcast_ref::<Txo>() {
+                spent_scripts.insert(txo.prev_out, txo.scripts.script_pubkey.clone());
+            } else if let Some(tx) = var.downcast_ref::<Tx>() {
+                for txo in &tx.txos {
+                    spent_scripts.insert(txo.prev_out, txo.scripts.script_pubkey.clone());
+                }
+            }
+        }
+
+        let mut content = Vec::new();
+        let mut writer = BlockFilterWriter::new(&mut content, &block);
+        writer.add_output_scripts();
+        for input in block
+            .txdata
+            .iter()
+            .filter(|tx| !tx.is_coinbase())
+            .flat_map(|tx| tx.input.iter())
+        {
+            let prev_out = (
+                *input.previous_output.txid.as_byte_array(),
+                input.previous_output.vout,
+            );
+            if let Some(script) = spent_scripts.get(&prev_out).filter(|s| !s.is_empty()) {
+                writer.add_element(script);
+            }
+        }
+        writer
+            .finish()
+            .expect("writing a filter into memory should never fail");
+        BlockFilter::new(&content)
+    }
+
     fn build_block(&mut self, instruction: &Instruction) -> Result<(), CompilerError> {
         let mut coinbase_tx_var = self
             .get_input::<CoinbaseTx>(&instruction.inputs, 0)?
@@ -1813,11 +2594,14 @@ impl Compiler {
             .get_input::<BlockTransactions>(&instruction.inputs, 4)?
             .clone();
 
-        coinbase_tx_var.tx.tx.input[0].script_sig = ScriptBuf::builder()
-            .push_int((header_var.height + 1) as i64)
-            .push_int(0xFFFFFFFF)
-            .as_script()
-            .into();
+        coinbase_tx_var.tx.tx.input[0].script_sig = match &coinbase_tx_var.script_sig {
+            Some(script_sig) => ScriptBuf::from_bytes(script_sig.clone()),
+            None => ScriptBuf::builder()
+                .push_int((header_var.height + 1) as i64)
+                .push_int(0xFFFFFFFF)
+                .as_script()
+                .into(),
+        };
 
         let mut txdata = vec![coinbase_tx_var.tx.tx.clone()];
         txdata.extend(block_transactions_var.txs.iter().map(|tx| tx.tx.clone()));
@@ -1834,20 +2618,7 @@ impl Compiler {
             txdata,
         };
         fuzzamoto::test_utils::mining::fixup_commitments(&mut block);
-
-        if cfg!(feature = "reduced_pow") {
-            let mut block_hash = block.header.block_hash();
-            while block_hash.as_raw_hash()[31] & 0x80 != 0 {
-                block.header.nonce += 1;
-                block_hash = block.header.block_hash();
-            }
-            // log::info!("{:?} height={}", block_hash, header_var.height);
-        } else {
-            let target = block.header.target();
-            while block.header.validate_pow(target).is_err() {
-                block.header.nonce += 1;
-            }
-        }
+        fuzzamoto::test_utils::mining::fixup_proof_of_work(&mut block);
 
         let coinbase_txid = *coinbase_tx_var
             .tx
@@ -2128,6 +2899,73 @@ impl Compiler {
                             .witness
                             .push(signature.as_ref().to_vec());
                     }
+                    SigningRequest::MultiSig {
+                        operation,
+                        script,
+                        threshold,
+                        private_key_vars,
+                    } => {
+                        let script = Script::from_bytes(script);
+                        let sighash_type = EcdsaSighashType::All;
+                        let hash = match operation {
+                            Operation::EndBuildMultiSigWitnessScriptHash => cache
+                                .p2wsh_signature_hash(
+                                    idx,
+                                    script,
+                                    Amount::from_sat(txo_var.value),
+                                    sighash_type,
+                                )
+                                .map(|hash| *hash.as_byte_array())
+                                .ok(),
+                            _ => cache
+                                .legacy_signature_hash(idx, script, sighash_type.to_u32())
+                                .map(|hash| *hash.as_byte_array())
+                                .ok(),
+                        };
+                        let Some(hash) = hash else {
+                            continue;
+                        };
+
+                        // Sign with the first M keys, in the order they appear in the script
+                        let signatures: Vec<Vec<u8>> = private_key_vars
+                            .iter()
+                            .take(*threshold)
+                            .map(|private_key_var| {
+                                let private_key =
+                                    self.get_variable::<[u8; 32]>(*private_key_var).unwrap();
+                                ecdsa::Signature {
+                                    signature: self.secp_ctx.sign_ecdsa(
+                                        &secp256k1::Message::from_digest(hash),
+                                        &SecretKey::from_slice(private_key.as_slice()).unwrap(),
+                                    ),
+                                    sighash_type,
+                                }
+                                .to_vec()
+                            })
+                            .collect();
+
+                        // The leading empty element is popped by the off-by-one bug in
+                        // OP_CHECKMULTISIG
+                        if let Operation::EndBuildMultiSigWitnessScriptHash = operation {
+                            let witness = &mut tx_var.tx.input[idx].witness;
+                            witness.push(Vec::<u8>::new());
+                            for signature in signatures {
+                                witness.push(signature);
+                            }
+                            witness.push(script.as_bytes());
+                        } else {
+                            let mut script_sig_builder = ScriptBuf::builder().push_opcode(OP_0);
+                            for signature in signatures {
+                                script_sig_builder = script_sig_builder
+                                    .push_slice(PushBytesBuf::try_from(signature).unwrap());
+                            }
+                            if let Operation::EndBuildMultiSigScriptHash = operation {
+                                script_sig_builder = script_sig_builder
+                                    .push_slice(PushBytesBuf::try_from(script.to_bytes()).unwrap());
+                            }
+                            tx_var.tx.input[idx].script_sig = script_sig_builder.into_script();
+                        }
+                    }
                 }
             }
         }
@@ -2158,11 +2996,9 @@ impl Compiler {
 mod tests {
     use super::*;
     use crate::{
-        IndexedVariable, Operation, Program, ProgramBuilder, ProgramContext, TaprootLeafSpec,
-    };
-    use bitcoin::{
-        Transaction, consensus::Decodable, opcodes::all::OP_PUSHNUM_1, taproot::LeafVersion,
+        IndexedVariable, Operation, ProgramBuilder, ProgramContext, TaprootLeafSpec, test_utils,
     };
+    use bitcoin::{Transaction, opcodes::all::OP_PUSHNUM_1, taproot::LeafVersion};
 
     #[test]
     fn compile_send_getaddr_emits_getaddr_message() {
@@ -2194,6 +3030,431 @@ mod tests {
         }
     }
 
+    #[test]
+    fn compile_probe_is_not_counted_as_instruction() {
+        let mut builder = test_utils::builder();
+        builder.force_append(vec![], Operation::Probe);
+        let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        builder.force_append(vec![conn_var.index], Operation::SendGetAddr);
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new()
+            .compile(&program)
+            .expect("failed to compile program");
+
+        assert!(matches!(
+            compiled.actions.as_slice(),
+            [
+                CompiledAction::Probe,
+                CompiledAction::SendRawMessage(0, command, _),
+            ] if command == "getaddr"
+        ));
+        // Indices refer to the program without the leading `Probe`
+        assert_eq!(compiled.metadata.instruction_indices(), &[1]);
+        assert_eq!(compiled.metadata.variable_indices(), &[0]);
+    }
+
+    #[cfg(feature = "generators")]
+    #[test]
+    fn compile_with_seed_produces_same_signatures() {
+        use crate::{Generator, MultisigGenerator, P2TRTxoGenerator};
+        use rand::{SeedableRng, rngs::SmallRng};
+
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        P2TRTxoGenerator
+            .generate(&mut builder, &mut rng, None)
+            .unwrap();
+        MultisigGenerator
+            .generate(&mut builder, &mut rng, None)
+            .unwrap();
+        let program = builder.finalize().unwrap();
+
+        // Schnorr (taproot funding) and ECDSA (multisig) signatures don't depend on the context
+        let actions = |mut compiler: Compiler| {
+            postcard::to_allocvec(&compiler.compile(&program).unwrap().actions).unwrap()
+        };
+        let expected = actions(Compiler::new());
+        assert_eq!(actions(Compiler::with_seed([1; 32])), expected);
+        assert_eq!(actions(Compiler::with_seed([2; 32])), expected);
+    }
+
+    #[test]
+    fn reset_compiler_matches_fresh_compiler() {
+        let program = |payload: Vec<u8>| {
+            let mut builder = test_utils::builder();
+            let conn = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+            let msg_type = builder
+                .force_append_expect_output(vec![], Operation::LoadMsgTypeFromStr("ping".into()));
+            let bytes = builder.force_append_expect_output(vec![], Operation::LoadBytes(payload));
+            builder.force_append(
+                vec![conn.index, msg_type.index, bytes.index],
+                Operation::SendRawMessage,
+            );
+            builder.finalize().unwrap()
+        };
+
+        let mut compiler = Compiler::new();
+        compiler.compile(&program(vec![1])).unwrap();
+        compiler.reset();
+        let reused = compiler.compile(&program(vec![2])).unwrap();
+        let fresh = Compiler::new().compile(&program(vec![2])).unwrap();
+
+        assert_eq!(
+            postcard::to_allocvec(&reused).unwrap(),
+            postcard::to_allocvec(&fresh).unwrap()
+        );
+        assert_eq!(reused.metadata.instruction_indices(), &[3]);
+    }
+
+    #[test]
+    fn compile_cached_reuses_results() {
+        let program = |payload: Vec<u8>| {
+            let mut builder = test_utils::builder();
+            let conn = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+            let msg_type = builder.force_append_expect_output(
+                vec![],
+                Operation::LoadMsgType([
+                    'p', 'i', 'n', 'g', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+                ]),
+            );
+            let bytes = builder.force_append_expect_output(vec![], Operation::LoadBytes(payload));
+            builder.force_append(
+                vec![conn.index, msg_type.index, bytes.index],
+                Operation::SendRawMessage,
+            );
+            builder.finalize().unwrap()
+        };
+
+        let mut cache = HashMap::new();
+        let first = Compiler::compile_cached(&program(vec![1; 8]), &mut cache).unwrap();
+        let second = Compiler::compile_cached(&program(vec![1; 8]), &mut cache).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            postcard::to_allocvec(&first).unwrap(),
+            postcard::to_allocvec(&second).unwrap()
+        );
+
+        Compiler::compile_cached(&program(vec![2; 8]), &mut cache).unwrap();
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn compile_msg_type_from_str() {
+        let compile = |msg_type: &str| {
+            let mut builder = test_utils::builder();
+            let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+            let msg_type_var = builder.force_append_expect_output(
+                vec![],
+                Operation::LoadMsgTypeFromStr(msg_type.to_string()),
+            );
+            let bytes_var =
+                builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![]));
+            builder.force_append(
+                vec![conn_var.index, msg_type_var.index, bytes_var.index],
+                Operation::SendRawMessage,
+            );
+            Compiler::new().compile(&builder.finalize().unwrap())
+        };
+
+        let compiled = compile("sendheaders").expect("failed to compile program");
+        match &compiled.actions[..] {
+            [CompiledAction::SendRawMessage(0, command, payload)] => {
+                assert_eq!(command, "sendheaders\0");
+                assert!(payload.is_empty());
+            }
+            actions => panic!("unexpected actions: {:?}", actions),
+        }
+
+        assert!(compile("sendheadersxx").is_err());
+        assert!(compile("pïng").is_err());
+    }
+
+    #[test]
+    fn compile_randomized_nonce_ping() {
+        let mut builder = test_utils::builder();
+        let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let nonce_var = builder.force_append_expect_output(vec![], Operation::LoadNonce64(42));
+        let randomized_var =
+            builder.force_append_expect_output(vec![nonce_var.index], Operation::RandomizeNonce);
+        let restored_var = builder
+            .force_append_expect_output(vec![randomized_var.index], Operation::RandomizeNonce);
+        for var in [&nonce_var, &randomized_var, &restored_var] {
+            builder.force_append(
+                vec![conn_var.index, var.index],
+                Operation::SendPingWithNonce,
+            );
+        }
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new()
+            .compile(&program)
+            .expect("failed to compile program");
+
+        let nonces: Vec<u64> = compiled
+            .actions
+            .iter()
+            .map(|action| match action {
+                CompiledAction::SendRawMessage(_, command, payload) => {
+                    assert_eq!(command, "ping");
+                    u64::from_le_bytes(payload.as_slice().try_into().unwrap())
+                }
+                other => panic!("unexpected action {:?}", other),
+            })
+            .collect();
+        assert_eq!(nonces, vec![42, 42 ^ NONCE_RANDOMIZATION_MASK, 42]);
+    }
+
+    #[test]
+    fn compile_send_pong() {
+        let mut builder = test_utils::builder();
+        let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let nonce_var = builder.force_append_expect_output(vec![], Operation::LoadNonce64(42));
+        builder.force_append(vec![conn_var.index, nonce_var.index], Operation::SendPong);
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new()
+            .compile(&program)
+            .expect("failed to compile program");
+
+        assert!(matches!(
+            compiled.actions.as_slice(),
+            [CompiledAction::SendRawMessage(0, command, payload)]
+                if command == "pong" && payload.as_slice() == 42u64.to_le_bytes()
+        ));
+    }
+
+    #[test]
+    fn compile_send_sendtxrcncl() {
+        let mut builder = test_utils::builder();
+        let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let version_var = builder.force_append_expect_output(vec![], Operation::LoadSize(1));
+        let salt_var =
+            builder.force_append_expect_output(vec![], Operation::LoadNonce64(0x0102_0304_0506));
+        builder.force_append(
+            vec![conn_var.index, version_var.index, salt_var.index],
+            Operation::SendSendTxRcncl,
+        );
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new()
+            .compile(&program)
+            .expect("failed to compile program");
+
+        let mut expected = 1u32.to_le_bytes().to_vec();
+        expected.extend_from_slice(&0x0102_0304_0506u64.to_le_bytes());
+        assert!(matches!(
+            compiled.actions.as_slice(),
+            [CompiledAction::SendRawMessage(0, command, payload)]
+                if command == "sendtxrcncl" && *payload == expected
+        ));
+    }
+
+    #[test]
+    fn compile_send_filter_messages() {
+        let mut builder = test_utils::builder();
+        let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let filter_load_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadFilterLoad {
+                filter: vec![0xaa; 3],
+                hash_funcs: 5,
+                tweak: 7,
+                flags: 1,
+            },
+        );
+        let filter_add_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadFilterAdd {
+                data: vec![1, 2, 3],
+            },
+        );
+        builder.force_append(
+            vec![conn_var.index, filter_load_var.index],
+            Operation::SendFilterLoad,
+        );
+        builder.force_append(
+            vec![conn_var.index, filter_add_var.index],
+            Operation::SendFilterAdd,
+        );
+        builder.force_append(vec![conn_var.index], Operation::SendFilterClear);
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new()
+            .compile(&program)
+            .expect("failed to compile program");
+
+        let expected_filter_load = bitcoin::consensus::encode::serialize(&FilterLoad {
+            filter: vec![0xaa; 3],
+            hash_funcs: 5,
+            tweak: 7,
+            flags: BloomFlags::All,
+        });
+        let expected_filter_add = bitcoin::consensus::encode::serialize(&FilterAdd {
+            data: vec![1, 2, 3],
+        });
+        match compiled.actions.as_slice() {
+            [
+                CompiledAction::SendRawMessage(0, load_command, load_payload),
+                CompiledAction::SendRawMessage(0, add_command, add_payload),
+                CompiledAction::SendRawMessage(0, clear_command, clear_payload),
+            ] => {
+                assert_eq!(load_command, "filterload");
+                assert_eq!(*load_payload, expected_filter_load);
+                assert_eq!(add_command, "filteradd");
+                assert_eq!(*add_payload, expected_filter_add);
+                assert_eq!(clear_command, "filterclear");
+                assert!(clear_payload.is_empty());
+            }
+            other => panic!("unexpected actions {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compile_send_mempool_and_notfound() {
+        let mut builder = test_utils::builder();
+        let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let mut_inventory_var =
+            builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+        let inventory_var = builder.force_append_expect_output(
+            vec![mut_inventory_var.index],
+            Operation::EndBuildInventory,
+        );
+        builder.force_append(vec![conn_var.index], Operation::SendMempoolMsg);
+        builder.force_append(
+            vec![conn_var.index, inventory_var.index],
+            Operation::SendNotFound,
+        );
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new()
+            .compile(&program)
+            .expect("failed to compile program");
+
+        let messages: Vec<(&str, &[u8])> = compiled
+            .actions
+            .iter()
+            .map(|action| match action {
+                CompiledAction::SendRawMessage(_, command, payload) => {
+                    (command.as_str(), payload.as_slice())
+                }
+                other => panic!("unexpected action {:?}", other),
+            })
+            .collect();
+        // An empty inventory serialises to a single zero length byte
+        assert_eq!(
+            messages,
+            vec![("mempool", &[][..]), ("notfound", &[0u8][..])]
+        );
+    }
+
+    #[test]
+    fn compile_send_getblocks_and_getheaders() {
+        let mut builder = test_utils::builder();
+        let header = Header {
+            prev: [1u8; 32],
+            merkle_root: [2u8; 32],
+            nonce: 3,
+            bits: 0x207fffff,
+            time: 1_296_688_602,
+            version: 4,
+            height: 1,
+        };
+        let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let header_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadHeader {
+                prev: header.prev,
+                merkle_root: header.merkle_root,
+                nonce: header.nonce,
+                bits: header.bits,
+                time: header.time,
+                version: header.version,
+                height: header.height,
+            },
+        );
+        let mut_locator_var =
+            builder.force_append_expect_output(vec![], Operation::BeginBuildGetBlocksLocator);
+        builder.force_append(
+            vec![mut_locator_var.index, header_var.index],
+            Operation::AddLocatorHash,
+        );
+        builder.force_append(
+            vec![mut_locator_var.index, header_var.index],
+            Operation::SetLocatorStopHash,
+        );
+        let locator_var = builder.force_append_expect_output(
+            vec![mut_locator_var.index],
+            Operation::EndBuildGetBlocksLocator,
+        );
+        builder.force_append(
+            vec![conn_var.index, locator_var.index],
+            Operation::SendGetBlocks,
+        );
+        builder.force_append(
+            vec![conn_var.index, locator_var.index],
+            Operation::SendGetHeaders,
+        );
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new()
+            .compile(&program)
+            .expect("failed to compile program");
+
+        let expected = bitcoin::consensus::encode::serialize(&GetBlocksMessage::new(
+            vec![header.block_hash()],
+            header.block_hash(),
+        ));
+        let messages: Vec<(&str, &[u8])> = compiled
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                CompiledAction::SendRawMessage(_, command, payload) => {
+                    Some((command.as_str(), payload.as_slice()))
+                }
+                _ => None,
+            })
+            .collect();
+        // `getblocks` and `getheaders` share the same wire format
+        assert_eq!(
+            messages,
+            vec![
+                ("getblocks", expected.as_slice()),
+                ("getheaders", expected.as_slice())
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_send_getaddr_uses_input_connection() {
+        let context = ProgramContext {
+            num_nodes: 1,
+            num_connections: 2,
+            timestamp: 0,
+        };
+
+        let mut builder = ProgramBuilder::new(context);
+        let conn0_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let conn1_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(1));
+        builder.force_append(vec![conn1_var.index], Operation::SendGetAddr);
+        builder.force_append(vec![conn0_var.index], Operation::SendGetAddr);
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new()
+            .compile(&program)
+            .expect("failed to compile program");
+
+        let connections: Vec<usize> = compiled
+            .actions
+            .iter()
+            .map(|action| match action {
+                CompiledAction::SendRawMessage(conn, _, _) => *conn,
+                other => panic!("unexpected action {:?}", other),
+            })
+            .collect();
+        assert_eq!(connections, vec![1, 0]);
+    }
+
     #[test]
     fn compile_send_addr_emits_addr_message() {
         let context = ProgramContext {
@@ -2322,9 +3583,9 @@ mod tests {
     #[test]
     fn compile_taproot_key_path_with_annex_places_annex_first() {
         let annex = vec![0x50, 0xAA, 0xBB, 0xCC];
-        let program = build_annex_program(annex.clone());
+        let builder = annex_program_builder(annex.clone());
 
-        let tx = compiled_tx_at(&program, 1);
+        let tx: Transaction = test_utils::sent_messages(&builder, "tx").remove(1);
         assert_eq!(tx.input.len(), 1);
         assert!(tx.input[0].witness.len() >= 2);
         assert_eq!(tx.input[0].witness[0], annex);
@@ -2332,7 +3593,7 @@ mod tests {
 
     #[test]
     fn compile_taproot_key_path_produces_expected_tx() {
-        let mut builder = ProgramBuilder::new(test_context());
+        let mut builder = test_utils::builder();
         let connection = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
         let funding_txo = append_op_true_txo(&mut builder, [0x11; 32], 50_000);
         // Key-path only
@@ -2359,15 +3620,14 @@ mod tests {
         builder.force_append(vec![connection.index, parent_tx.index], Operation::SendTx);
         builder.force_append(vec![connection.index, child_tx.index], Operation::SendTx);
 
-        let program = builder.finalize().expect("valid program");
-        let tx = compiled_tx_at(&program, 1);
+        let tx: Transaction = test_utils::sent_messages(&builder, "tx").remove(1);
         assert_eq!(tx.input.len(), 1);
         assert_eq!(tx.input[0].witness.len(), 1);
     }
 
     #[test]
     fn compile_taproot_script_path_produces_expected_tx() {
-        let mut builder = ProgramBuilder::new(test_context());
+        let mut builder = test_utils::builder();
         let connection = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
 
         let funding_txo = append_op_true_txo(&mut builder, [0x22; 32], 60_000);
@@ -2401,8 +3661,7 @@ mod tests {
         builder.force_append(vec![connection.index, parent_tx.index], Operation::SendTx);
         builder.force_append(vec![connection.index, child_tx.index], Operation::SendTx);
 
-        let program = builder.finalize().expect("valid program");
-        let tx = compiled_tx_at(&program, 1);
+        let tx: Transaction = test_utils::sent_messages(&builder, "tx").remove(1);
         assert_eq!(tx.input.len(), 1);
         assert_eq!(tx.input[0].witness.len(), 3);
         assert_eq!(tx.input[0].witness[1], vec![OP_PUSHNUM_1.to_u8()]);
@@ -2412,7 +3671,7 @@ mod tests {
     fn compile_taproot_tree_with_hidden_node_exposes_branch_hash() {
         const HIDDEN_HASH: [u8; 32] = [0x42u8; 32];
 
-        let mut builder = ProgramBuilder::new(test_context());
+        let mut builder = test_utils::builder();
         let connection = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
         let funding_txo = append_op_true_txo(&mut builder, [0x11; 32], 40_000);
         // Script-path with one hidden node sibling
@@ -2443,15 +3702,7 @@ mod tests {
         builder.force_append(vec![connection.index, parent_tx.index], Operation::SendTx);
         builder.force_append(vec![connection.index, child_tx.index], Operation::SendTx);
 
-        let program = builder.finalize().expect("valid tree program");
-        let mut compiler = Compiler::new();
-        let compiled = compiler.compile(&program).expect("compile");
-
-        let child_payload = match compiled.actions.get(1) {
-            Some(CompiledAction::SendRawMessage(_, cmd, payload)) if cmd == "tx" => payload.clone(),
-            other => panic!("unexpected action {:?}", other),
-        };
-        let child_tx = Transaction::consensus_decode(&mut child_payload.as_slice()).unwrap();
+        let child_tx: Transaction = test_utils::sent_messages(&builder, "tx").remove(1);
         assert_eq!(child_tx.input.len(), 1);
         assert_eq!(child_tx.input[0].witness.len(), 3);
         assert_eq!(child_tx.input[0].witness[1], vec![0x50]);
@@ -2460,12 +3711,167 @@ mod tests {
         assert_eq!(&control_block[33..], &HIDDEN_HASH);
     }
 
-    fn build_annex_program(annex: Vec<u8>) -> Program {
-        let mut builder = ProgramBuilder::new(ProgramContext {
-            num_nodes: 1,
-            num_connections: 1,
-            timestamp: 0,
-        });
+    #[test]
+    fn compile_multisig_spends_sign_with_threshold_keys() {
+        let secret_keys = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        let secp_ctx = Secp256k1::new();
+        let public_keys: Vec<bitcoin::PublicKey> = secret_keys
+            .iter()
+            .map(|key| {
+                PrivateKey::from_slice(key, NetworkKind::Main)
+                    .unwrap()
+                    .public_key(&secp_ctx)
+            })
+            .collect();
+        let mut script_builder = ScriptBuf::builder().push_int(2);
+        for public_key in &public_keys {
+            script_builder = script_builder.push_key(public_key);
+        }
+        let script = script_builder
+            .push_int(3)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+
+        for end_operation in [
+            Operation::EndBuildMultiSig,
+            Operation::EndBuildMultiSigScriptHash,
+            Operation::EndBuildMultiSigWitnessScriptHash,
+        ] {
+            let mut builder = test_utils::builder();
+            let connection =
+                builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+            let funding_txo = append_op_true_txo(&mut builder, [0x55; 32], 50_000);
+
+            let threshold = builder.force_append_expect_output(vec![], Operation::LoadSize(2));
+            let num_keys = builder.force_append_expect_output(vec![], Operation::LoadSize(3));
+            let multisig = builder.force_append_expect_output(
+                vec![threshold.index, num_keys.index],
+                Operation::BeginBuildMultiSig,
+            );
+            for secret_key in secret_keys {
+                let private_key = builder
+                    .force_append_expect_output(vec![], Operation::LoadPrivateKey(secret_key));
+                builder.force_append(
+                    vec![multisig.index, private_key.index],
+                    Operation::AddMultiSigKey,
+                );
+            }
+            let scripts =
+                builder.force_append_expect_output(vec![multisig.index], end_operation.clone());
+
+            let parent_tx = build_single_output_tx_for_tests(
+                &mut builder,
+                funding_txo.index,
+                scripts.index,
+                50_000,
+            );
+            let produced =
+                builder.force_append_expect_output(vec![parent_tx.index], Operation::TakeTxo);
+            let child_tx = build_single_input_transaction(&mut builder, produced.index, 49_500);
+            builder.force_append(vec![connection.index, parent_tx.index], Operation::SendTx);
+            builder.force_append(vec![connection.index, child_tx.index], Operation::SendTx);
+
+            let [parent, child]: [Transaction; 2] = test_utils::sent_messages(&builder, "tx")
+                .try_into()
+                .expect("parent and child should be sent");
+            let mut cache = SighashCache::new(&child);
+
+            let (elements, sighash) = match end_operation {
+                Operation::EndBuildMultiSigWitnessScriptHash => {
+                    assert_eq!(
+                        parent.output[0].script_pubkey,
+                        ScriptBuf::new_p2wsh(&script.wscript_hash())
+                    );
+                    assert!(child.input[0].script_sig.is_empty());
+                    let mut elements: Vec<Vec<u8>> =
+                        child.input[0].witness.iter().map(|e| e.to_vec()).collect();
+                    assert_eq!(elements.pop().unwrap(), script.to_bytes());
+                    let sighash = cache
+                        .p2wsh_signature_hash(
+                            0,
+                            &script,
+                            Amount::from_sat(50_000),
+                            EcdsaSighashType::All,
+                        )
+                        .unwrap();
+                    (elements, *sighash.as_byte_array())
+                }
+                _ => {
+                    let mut elements: Vec<Vec<u8>> = child.input[0]
+                        .script_sig
+                        .instructions()
+                        .map(|instruction| match instruction.unwrap() {
+                            bitcoin::script::Instruction::PushBytes(bytes) => {
+                                bytes.as_bytes().to_vec()
+                            }
+                            op => panic!("unexpected opcode in scriptSig: {op:?}"),
+                        })
+                        .collect();
+                    if end_operation == Operation::EndBuildMultiSigScriptHash {
+                        assert_eq!(
+                            parent.output[0].script_pubkey,
+                            ScriptBuf::new_p2sh(&script.script_hash())
+                        );
+                        assert_eq!(elements.pop().unwrap(), script.to_bytes());
+                    } else {
+                        assert_eq!(parent.output[0].script_pubkey, script);
+                    }
+                    assert!(child.input[0].witness.is_empty());
+                    let sighash = cache
+                        .legacy_signature_hash(0, &script, EcdsaSighashType::All.to_u32())
+                        .unwrap();
+                    (elements, *sighash.as_byte_array())
+                }
+            };
+
+            // Dummy element followed by signatures from the first two keys, in script order
+            assert_eq!(elements.len(), 3);
+            assert!(elements[0].is_empty());
+            for (signature, public_key) in elements[1..].iter().zip(&public_keys) {
+                let signature = ecdsa::Signature::from_slice(signature).expect("valid signature");
+                assert_eq!(signature.sighash_type, EcdsaSighashType::All);
+                secp_ctx
+                    .verify_ecdsa(
+                        &secp256k1::Message::from_digest(sighash),
+                        &signature.signature,
+                        &public_key.inner,
+                    )
+                    .expect("signature should verify");
+            }
+        }
+    }
+
+    #[test]
+    fn to_bitcoin_messages_decodes_send_tx() {
+        let mut builder = test_utils::builder();
+        let connection = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let funding_txo = append_op_true_txo(&mut builder, [0x44; 32], 10_000);
+        let tx = build_single_input_transaction(&mut builder, funding_txo.index, 9_500);
+        builder.force_append(vec![connection.index, tx.index], Operation::SendTx);
+
+        let program = builder.finalize().expect("valid program");
+        let mut compiler = Compiler::new();
+        let compiled = compiler.compile(&program).expect("compile");
+
+        let messages = compiled.to_bitcoin_messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].connection, 0);
+        assert_eq!(messages[0].command, "tx");
+
+        let tx: Transaction = test_utils::sent_messages(&builder, "tx").remove(0);
+        assert_eq!(
+            messages[0].payload_hex,
+            bitcoin::consensus::encode::serialize_hex(&tx)
+        );
+        let decoded = messages[0]
+            .payload_decoded
+            .as_ref()
+            .expect("tx payload should be decoded");
+        assert_eq!(*decoded, serde_json::to_value(&tx).unwrap());
+    }
+
+    fn annex_program_builder(annex: Vec<u8>) -> ProgramBuilder {
+        let mut builder = test_utils::builder();
 
         let connection = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
         let funding_txo = append_op_true_txo(&mut builder, [0x33; 32], 50_000);
@@ -2498,27 +3904,7 @@ mod tests {
         let tx = build_single_input_transaction(&mut builder, spend_txo.index, 49_500);
         builder.force_append(vec![connection.index, parent_tx.index], Operation::SendTx);
         builder.force_append(vec![connection.index, tx.index], Operation::SendTx);
-
-        builder.finalize().expect("valid program")
-    }
-
-    fn compiled_tx_at(program: &Program, send_tx_index: usize) -> Transaction {
-        let mut compiler = Compiler::new();
-        let compiled = compiler.compile(program).expect("program compiles");
-        let bytes = compiled
-            .actions
-            .iter()
-            .filter_map(|a| {
-                if let CompiledAction::SendRawMessage(_, cmd, payload) = a {
-                    if cmd == "tx" {
-                        return Some(payload.clone());
-                    }
-                }
-                None
-            })
-            .nth(send_tx_index)
-            .unwrap_or_else(|| panic!("missing tx at index {}", send_tx_index));
-        Transaction::consensus_decode(&mut bytes.as_slice()).expect("tx decode")
+        builder
     }
 
     fn append_op_true_txo(
@@ -2617,6 +4003,82 @@ mod tests {
         )
     }
 
+    #[test]
+    fn compile_with_context_rejects_unknown_context_objects() {
+        let txo = crate::context::Txo {
+            outpoint: ([1; 32], 0),
+            value: 50_000,
+            script_pubkey: vec![0x51],
+            spending_script_sig: vec![],
+            spending_witness: vec![],
+        };
+        let header = Header {
+            prev: [0; 32],
+            merkle_root: [2; 32],
+            nonce: 0,
+            bits: 0x207f_ffff,
+            time: 1_296_688_602,
+            version: 1,
+            height: 0,
+        };
+        let ctx = FullProgramContext {
+            context: test_context(),
+            txos: vec![txo.clone()],
+            headers: vec![header.clone()],
+            block_filters: vec![],
+        };
+
+        let load_txo = |outpoint: ([u8; 32], u32)| Operation::LoadTxo {
+            outpoint,
+            value: txo.value,
+            script_pubkey: txo.script_pubkey.clone(),
+            spending_script_sig: vec![],
+            spending_witness: vec![],
+        };
+        let load_header = |nonce: u32| Operation::LoadHeader {
+            prev: header.prev,
+            merkle_root: header.merkle_root,
+            nonce,
+            bits: header.bits,
+            time: header.time,
+            version: header.version,
+            height: header.height,
+        };
+        let program = |operation: Operation| {
+            let mut builder = ProgramBuilder::new(ProgramContext {
+                num_connections: 2,
+                ..test_context()
+            });
+            builder.force_append(vec![], operation);
+            builder.finalize().unwrap()
+        };
+
+        for operation in [
+            load_txo(txo.outpoint),
+            load_header(header.nonce),
+            Operation::LoadConnection(0),
+        ] {
+            assert!(
+                Compiler::new()
+                    .compile_with_context(&program(operation), &ctx)
+                    .is_ok()
+            );
+        }
+
+        for (operation, expected_field) in [
+            (load_txo(([1; 32], 1)), "txo"),
+            (load_header(header.nonce + 1), "header"),
+            (Operation::LoadConnection(1), "connection"),
+        ] {
+            match Compiler::new().compile_with_context(&program(operation), &ctx) {
+                Err(CompilerError::ContextMismatch { field, .. }) => {
+                    assert_eq!(field, expected_field);
+                }
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
     fn test_context() -> ProgramContext {
         ProgramContext {
             num_nodes: 1,
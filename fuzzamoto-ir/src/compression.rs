@@ -0,0 +1,119 @@
+//! zstd compressed program encoding (see the `compress` feature).
+//!
+//! Compressed programs are schema versioned programs (see `Program::to_bytes_versioned`)
+//! compressed with zstd and prefixed with `COMPRESSED_MAGIC`, so that compressed and plain
+//! programs can be told apart when decoding.
+
+use std::{fmt, io::Read};
+
+use crate::{Program, SchemaError};
+
+/// Prefix of zstd compressed programs
+pub const COMPRESSED_MAGIC: [u8; 4] = *b"FZZ\x01";
+
+/// zstd compression level, favouring speed over size
+const COMPRESSION_LEVEL: i32 = 1;
+
+/// Error returned when decoding a (compressed) program fails
+#[derive(Debug)]
+pub enum DeserError {
+    Decompress(std::io::Error),
+    Decode(SchemaError),
+}
+
+impl fmt::Display for DeserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeserError::Decompress(e) => write!(f, "Decompression error: {}", e),
+            DeserError::Decode(e) => write!(f, "Decode error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DeserError {}
+
+impl Program {
+    /// Encode the program with `Program::to_bytes_versioned` and compress it with zstd
+    pub fn to_compressed_bytes(&self) -> Vec<u8> {
+        let bytes = self.to_bytes_versioned();
+        let mut compressed = COMPRESSED_MAGIC.to_vec();
+        compressed.extend(
+            zstd::stream::encode_all(bytes.as_slice(), COMPRESSION_LEVEL)
+                .expect("compressing into memory should never fail"),
+        );
+        compressed
+    }
+
+    /// Decode a program written by `Program::to_compressed_bytes`. Programs without the
+    /// `COMPRESSED_MAGIC` prefix are decoded as plain programs (see
+    /// `Program::from_bytes_versioned`).
+    pub fn from_compressed_bytes(bytes: &[u8]) -> Result<Program, DeserError> {
+        match bytes.strip_prefix(&COMPRESSED_MAGIC) {
+            Some(compressed) => {
+                let bytes = zstd::stream::decode_all(compressed).map_err(DeserError::Decompress)?;
+                Program::from_bytes_versioned(&bytes).map_err(DeserError::Decode)
+            }
+            None => Program::from_bytes_versioned(bytes).map_err(DeserError::Decode),
+        }
+    }
+
+    /// Like `Program::peek_instruction_count`, for programs written by
+    /// `Program::to_compressed_bytes` (or plain programs). Only the start of the program is
+    /// decompressed.
+    pub fn peek_compressed_instruction_count(mut reader: impl Read) -> Option<usize> {
+        let mut prefix = [0u8; COMPRESSED_MAGIC.len()];
+        reader.read_exact(&mut prefix).ok()?;
+        if prefix == COMPRESSED_MAGIC {
+            Self::peek_instruction_count(zstd::stream::read::Decoder::new(reader).ok()?)
+        } else {
+            Self::peek_instruction_count(prefix.as_slice().chain(reader))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Operation, ProgramBuilder, ProgramContext, test_utils};
+
+    fn program() -> Program {
+        let mut builder = test_utils::builder();
+        for _ in 0..64 {
+            builder.force_append(vec![], Operation::LoadBytes(vec![0x42; 256]));
+        }
+        builder.finalize().unwrap()
+    }
+
+    #[test]
+    fn compressed_roundtrip() {
+        let program = program();
+        let plain = program.to_bytes_versioned();
+        let compressed = program.to_compressed_bytes();
+
+        assert!(compressed.starts_with(&COMPRESSED_MAGIC));
+        assert!(compressed.len() < plain.len());
+        assert_eq!(
+            Program::from_compressed_bytes(&compressed).unwrap(),
+            program
+        );
+        // Uncompressed programs are decoded transparently
+        assert_eq!(Program::from_compressed_bytes(&plain).unwrap(), program);
+
+        for bytes in [&compressed, &plain] {
+            assert_eq!(
+                Program::peek_compressed_instruction_count(bytes.as_slice()),
+                Some(64)
+            );
+        }
+    }
+
+    #[test]
+    fn corrupted_compressed_program_is_rejected() {
+        let mut compressed = program().to_compressed_bytes();
+        compressed.truncate(COMPRESSED_MAGIC.len() + 4);
+        assert!(matches!(
+            Program::from_compressed_bytes(&compressed),
+            Err(DeserError::Decompress(_))
+        ));
+    }
+}
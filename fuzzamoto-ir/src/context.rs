@@ -0,0 +1,43 @@
+//! Types describing the snapshotted state programs are executed in (see `FullProgramContext`)
+
+use bitcoin::{BlockHash, hashes::Hash};
+
+/// Transaction output present in the snapshotted state, including the data required to spend it
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Txo {
+    pub outpoint: ([u8; 32], u32),
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+    pub spending_script_sig: Vec<u8>,
+    pub spending_witness: Vec<Vec<u8>>,
+}
+
+/// Block header present in the snapshotted state
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Header {
+    pub prev: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub nonce: u32,
+    pub bits: u32,
+    pub time: u32,
+    pub version: i32,
+    pub height: u32,
+}
+
+impl Header {
+    pub fn to_bitcoin_header(&self) -> bitcoin::block::Header {
+        bitcoin::block::Header {
+            version: bitcoin::block::Version::from_consensus(self.version),
+            prev_blockhash: bitcoin::BlockHash::from_slice(&self.prev).unwrap(),
+            merkle_root: bitcoin::TxMerkleNode::from_slice(&self.merkle_root).unwrap(),
+            bits: bitcoin::CompactTarget::from_consensus(self.bits),
+            nonce: self.nonce,
+            time: self.time,
+        }
+    }
+
+    pub fn block_hash(&self) -> BlockHash {
+        let bitcoin_header = self.to_bitcoin_header();
+        bitcoin_header.block_hash()
+    }
+}
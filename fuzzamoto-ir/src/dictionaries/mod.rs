@@ -0,0 +1,89 @@
+use fuzzamoto::dictionaries::BITCOIN_COMMANDS;
+use rand::{RngCore, seq::SliceRandom};
+
+/// Length of a p2p message type (null-padded)
+pub const MSG_TYPE_LEN: usize = 12;
+
+/// Convert `msg_type` into its `Operation::LoadMsgType` representation, i.e. pad it with null
+/// chars to `MSG_TYPE_LEN` chars. Returns `None` if `msg_type` is longer than `MSG_TYPE_LEN` or
+/// contains non-ASCII characters.
+pub fn msg_type_from_str(msg_type: &str) -> Option<[char; MSG_TYPE_LEN]> {
+    if msg_type.len() > MSG_TYPE_LEN || !msg_type.is_ascii() {
+        return None;
+    }
+
+    let mut chars = ['\0'; MSG_TYPE_LEN];
+    for (c, b) in chars.iter_mut().zip(msg_type.bytes()) {
+        *c = b as char;
+    }
+    Some(chars)
+}
+
+/// `MsgTypeDictionary` holds all known p2p message types (see
+/// `fuzzamoto::dictionaries::BITCOIN_COMMANDS`) in their `Operation::LoadMsgType` representation
+#[derive(Debug, Clone)]
+pub struct MsgTypeDictionary {
+    entries: Vec<[char; MSG_TYPE_LEN]>,
+}
+
+impl Default for MsgTypeDictionary {
+    fn default() -> Self {
+        Self {
+            entries: BITCOIN_COMMANDS
+                .iter()
+                .map(|command| {
+                    msg_type_from_str(command)
+                        .expect("known commands should be valid message types")
+                })
+                .collect(),
+        }
+    }
+}
+
+impl MsgTypeDictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[[char; MSG_TYPE_LEN]] {
+        &self.entries
+    }
+
+    /// Pick a random message type from the dictionary
+    pub fn choose<R: RngCore>(&self, rng: &mut R) -> [char; MSG_TYPE_LEN] {
+        *self
+            .entries
+            .choose(rng)
+            .expect("dictionary should not be empty")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_padded_commands() {
+        let dictionary = MsgTypeDictionary::new();
+        assert_eq!(dictionary.entries().len(), BITCOIN_COMMANDS.len());
+
+        for (entry, command) in dictionary.entries().iter().zip(BITCOIN_COMMANDS) {
+            let (name, padding) = entry.split_at(command.len());
+            assert_eq!(name.iter().collect::<String>(), *command);
+            assert!(padding.iter().all(|c| *c == '\0'));
+        }
+    }
+
+    #[test]
+    fn invalid_msg_types_are_rejected() {
+        assert_eq!(
+            msg_type_from_str("ping"),
+            Some([
+                'p', 'i', 'n', 'g', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0'
+            ])
+        );
+        assert!(msg_type_from_str("sendaddrv2xx").is_some());
+        assert!(msg_type_from_str("sendaddrv2xxx").is_none());
+        assert!(msg_type_from_str("pïng").is_none());
+    }
+}
@@ -19,6 +19,14 @@ pub enum ProgramValidationError {
         end: Operation,
     },
     ScopeStillOpen,
+    InstructionNotFound(usize),
+    VariableStillUsed(usize),
+    ContextExceedsLimits {
+        field: &'static str,
+        value: usize,
+        max: usize,
+    },
+    ProgramTooLong,
 }
 
 #[derive(Debug, Clone)]
@@ -29,3 +37,19 @@ pub enum ProgramSpliceError {
         actual: ProgramContext,
     },
 }
+
+/// Error returned when parsing the textual program format (see `Program::from_str`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Line (starting at 1) on which parsing failed
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,132 @@
+use rand::RngCore;
+
+use super::{
+    GeneratorError, send_tx,
+    tx::{OutputType, build_tx},
+};
+use crate::{Generator, GeneratorResult, PerTestcaseMetadata, ProgramBuilder};
+
+/// Value of the parent's P2WSH output, which is spent by the child to pay for the package
+const PARENT_AMOUNT: u64 = 100_000_000;
+/// Value of the parent's P2A output. Ephemeral anchors are dust and have to be spent in the same
+/// package.
+const ANCHOR_AMOUNT: u64 = 0;
+/// Approximate virtual size of the parent without inputs (one P2A and one P2WSH output)
+const PARENT_BASE_VSIZE: u64 = 70;
+/// Approximate virtual size of a P2WSH-OP_TRUE input
+const INPUT_VSIZE: u64 = 45;
+/// Approximate virtual size of the child (spending the P2A and the P2WSH output into a single
+/// P2WSH output)
+const CHILD_VSIZE: u64 = 140;
+/// Fee rate (in sat/vB) of the package, paid entirely by the child
+const PACKAGE_FEE_RATE: u64 = 10;
+
+/// TRUC (v3) transactions are the only ones allowed to carry ephemeral anchors
+const TRUC_VERSION: u32 = 3;
+
+/// `AnchorSpendingGenerator` generates instructions for a TRUC (v3) package with an ephemeral
+/// anchor: a parent with a zero-value P2A output (`OP_TRUE OP_PUSHBYTES_2 0x4e73`) and a P2WSH
+/// output, and a child spending the anchor. The child also spends the parent's P2WSH output to
+/// pay for the whole package at `PACKAGE_FEE_RATE`.
+///
+/// The child is sent first, so that the package is only accepted through 1p1c orphan resolution.
+///
+/// Note that the values of the funding outputs are not known at generation time, so the parent is
+/// only fee-less (as required for transactions with ephemeral dust) if they are worth exactly
+/// `PARENT_AMOUNT`.
+#[derive(Debug, Default)]
+pub struct AnchorSpendingGenerator;
+
+impl<R: RngCore> Generator<R> for AnchorSpendingGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let funding_txos = builder.get_random_utxos(rng);
+        if funding_txos.is_empty() {
+            return Err(GeneratorError::MissingVariables);
+        };
+
+        let (parent_tx_var, parent_outputs) = build_tx(
+            builder,
+            rng,
+            &funding_txos,
+            TRUC_VERSION,
+            &[
+                (ANCHOR_AMOUNT, OutputType::PayToAnchor),
+                (PARENT_AMOUNT, OutputType::PayToWitnessScriptHash),
+            ],
+        )?;
+
+        let package_vsize =
+            PARENT_BASE_VSIZE + INPUT_VSIZE * funding_txos.len() as u64 + CHILD_VSIZE;
+        let (child_tx_var, _) = build_tx(
+            builder,
+            rng,
+            &parent_outputs,
+            TRUC_VERSION,
+            &[(
+                PARENT_AMOUNT + ANCHOR_AMOUNT - PACKAGE_FEE_RATE * package_vsize,
+                OutputType::PayToWitnessScriptHash,
+            )],
+        )?;
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+        send_tx(builder, &conn_var, &child_tx_var);
+        send_tx(builder, &conn_var, &parent_tx_var);
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "AnchorSpendingGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoin::{Transaction, transaction::Version};
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    /// Transactions sent by the generator, in order
+    fn sent_txs() -> Vec<Transaction> {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        // Fund the parent with exactly `PARENT_AMOUNT`, so that the child pays for the package
+        test_utils::load_txo(&mut builder, PARENT_AMOUNT);
+        AnchorSpendingGenerator
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+        test_utils::sent_messages(&builder, "tx")
+    }
+
+    #[test]
+    fn parent_has_ephemeral_anchor() {
+        let txs = sent_txs();
+        assert_eq!(txs.len(), 2);
+
+        let parent = &txs[1];
+        assert_eq!(parent.version, Version(3));
+        let anchor = &parent.output[0];
+        assert_eq!(anchor.script_pubkey.as_bytes(), &[0x51, 0x02, 0x4e, 0x73]);
+        assert_eq!(anchor.value.to_sat(), ANCHOR_AMOUNT);
+    }
+
+    #[test]
+    fn child_spends_anchor() {
+        let txs = sent_txs();
+        let (child, parent) = (&txs[0], &txs[1]);
+        assert_eq!(child.version, Version(3));
+        assert_eq!(child.input[0].previous_output.txid, parent.compute_txid());
+        assert_eq!(child.input[0].previous_output.vout, 0);
+
+        let child_fee = parent.output.iter().map(|o| o.value.to_sat()).sum::<u64>()
+            - child.output[0].value.to_sat();
+        assert_eq!(child_fee % PACKAGE_FEE_RATE, 0);
+        assert!(child_fee > PACKAGE_FEE_RATE * CHILD_VSIZE);
+    }
+}
@@ -1,9 +1,10 @@
-use bitcoin::{BlockHash, hashes::Hash};
+use std::time::Duration;
+
 use rand::{Rng, RngCore, seq::SliceRandom};
 
 use super::GeneratorError;
 use crate::{
-    CoinbaseTxGenerator, Generator, GeneratorResult, IndexedVariable, Instruction,
+    CoinbaseTxGenerator, Generator, GeneratorResult, Header, IndexedVariable, Instruction,
     InstructionContext, Operation, PerTestcaseMetadata, ProgramBuilder, Variable,
 };
 /// `BlockGenerator` generates instructions for creating a new block and sending it to a node
@@ -104,10 +105,51 @@ pub fn build_block_from_header<R: RngCore>(
     let mut random_tx_vars = builder.get_random_variables(rng, Variable::ConstTx);
     random_tx_vars.sort_by_key(|tx| tx.index);
 
+    let block_and_header_var = build_block(
+        coinbase_generator,
+        builder,
+        rng,
+        header_var_index,
+        time_var.index,
+        &random_tx_vars,
+        meta,
+    )?;
+
+    let conn_var = builder.get_or_create_random_connection(rng);
+    builder.force_append(
+        vec![conn_var.index, block_and_header_var[0].index],
+        Operation::SendHeader,
+    );
+    builder.force_append(
+        vec![conn_var.index, block_and_header_var[1].index],
+        Operation::SendBlock,
+    );
+    builder.force_append(
+        vec![block_and_header_var[2].index],
+        Operation::TakeCoinbaseTxo,
+    );
+
+    Ok((
+        block_and_header_var[0].clone(),
+        block_and_header_var[1].clone(),
+    ))
+}
+
+/// Build a block (without sending it) on top of `header_var_index` containing `tx_vars`, returning
+/// the header, block and coinbase tx variables.
+pub fn build_block<R: RngCore>(
+    coinbase_generator: &CoinbaseTxGenerator,
+    builder: &mut ProgramBuilder,
+    rng: &mut R,
+    header_var_index: usize,
+    time_var_index: usize,
+    tx_vars: &[IndexedVariable],
+    meta: Option<&PerTestcaseMetadata>,
+) -> Result<Vec<IndexedVariable>, GeneratorError> {
     let begin_txs_var =
         builder.force_append_expect_output(vec![], Operation::BeginBlockTransactions);
 
-    for tx_var in random_tx_vars {
+    for tx_var in tx_vars {
         builder.force_append(vec![begin_txs_var.index, tx_var.index], Operation::AddTx);
     }
 
@@ -127,37 +169,18 @@ pub fn build_block_from_header<R: RngCore>(
                 .unwrap()
         };
 
-    let block_and_header_var = builder
+    Ok(builder
         .append(Instruction {
             inputs: vec![
                 coinbase_tx_var.index,
                 header_var_index,
-                time_var.index,
+                time_var_index,
                 block_version_var.index,
                 end_txs_var.index,
             ],
             operation: Operation::BuildBlock,
         })
-        .expect("Buildblock should not fail");
-
-    let conn_var = builder.get_or_create_random_connection(rng);
-    builder.force_append(
-        vec![conn_var.index, block_and_header_var[0].index],
-        Operation::SendHeader,
-    );
-    builder.force_append(
-        vec![conn_var.index, block_and_header_var[1].index],
-        Operation::SendBlock,
-    );
-    builder.force_append(
-        vec![block_and_header_var[2].index],
-        Operation::TakeCoinbaseTxo,
-    );
-
-    Ok((
-        block_and_header_var[0].clone(),
-        block_and_header_var[1].clone(),
-    ))
+        .expect("Buildblock should not fail"))
 }
 
 impl<R: RngCore> Generator<R> for BlockGenerator {
@@ -330,35 +353,6 @@ impl ReorgBlockGenerator {
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
-pub struct Header {
-    pub prev: [u8; 32],
-    pub merkle_root: [u8; 32],
-    pub nonce: u32,
-    pub bits: u32,
-    pub time: u32,
-    pub version: i32,
-    pub height: u32,
-}
-
-impl Header {
-    pub fn to_bitcoin_header(&self) -> bitcoin::block::Header {
-        bitcoin::block::Header {
-            version: bitcoin::block::Version::from_consensus(self.version),
-            prev_blockhash: bitcoin::BlockHash::from_slice(&self.prev).unwrap(),
-            merkle_root: bitcoin::TxMerkleNode::from_slice(&self.merkle_root).unwrap(),
-            bits: bitcoin::CompactTarget::from_consensus(self.bits),
-            nonce: self.nonce,
-            time: self.time,
-        }
-    }
-
-    pub fn block_hash(&self) -> BlockHash {
-        let bitcoin_header = self.to_bitcoin_header();
-        bitcoin_header.block_hash()
-    }
-}
-
 pub struct HeaderGenerator {
     pub headers: Vec<Header>,
 }
@@ -376,9 +370,14 @@ impl<R: RngCore> Generator<R> for HeaderGenerator {
         rng: &mut R,
         _meta: Option<&PerTestcaseMetadata>,
     ) -> GeneratorResult {
-        let header = self.headers.choose(rng).unwrap().clone();
-
-        builder.force_append(
+        let header = self
+            .headers
+            .choose(rng)
+            .ok_or(GeneratorError::MissingVariables)?
+            .clone();
+
+        let mut locator_header_vars = builder.get_random_variables(rng, Variable::Header);
+        let header_var = builder.force_append_expect_output(
             vec![],
             Operation::LoadHeader {
                 prev: header.prev,
@@ -390,6 +389,13 @@ impl<R: RngCore> Generator<R> for HeaderGenerator {
                 height: header.height,
             },
         );
+
+        // Occasionally request the headers (or blocks) following the loaded header, to exercise
+        // the node's side of a headers-first sync
+        if rng.gen_bool(0.3) {
+            locator_header_vars.insert(0, header_var);
+            send_block_locator(builder, rng, &locator_header_vars);
+        }
         Ok(())
     }
 
@@ -398,8 +404,71 @@ impl<R: RngCore> Generator<R> for HeaderGenerator {
     }
 }
 
-#[derive(Default)]
-pub struct SendBlockGenerator;
+/// Build a block locator from `header_vars` (most recent first), occasionally with a stop hash,
+/// and send it in a `getheaders` or `getblocks` message
+fn send_block_locator<R: RngCore>(
+    builder: &mut ProgramBuilder,
+    rng: &mut R,
+    header_vars: &[IndexedVariable],
+) {
+    let conn_var = builder.get_or_create_random_connection(rng);
+    let mut_locator_var =
+        builder.force_append_expect_output(vec![], Operation::BeginBuildGetBlocksLocator);
+    for header_var in header_vars {
+        builder.force_append(
+            vec![mut_locator_var.index, header_var.index],
+            Operation::AddLocatorHash,
+        );
+    }
+    if rng.gen_bool(0.2)
+        && let Some(stop_var) = builder.get_random_variable(rng, Variable::Header)
+    {
+        builder.force_append(
+            vec![mut_locator_var.index, stop_var.index],
+            Operation::SetLocatorStopHash,
+        );
+    }
+    let locator_var = builder.force_append_expect_output(
+        vec![mut_locator_var.index],
+        Operation::EndBuildGetBlocksLocator,
+    );
+
+    let send = if rng.gen_bool(0.5) {
+        Operation::SendGetHeaders
+    } else {
+        Operation::SendGetBlocks
+    };
+    builder.force_append(vec![conn_var.index, locator_var.index], send);
+}
+
+/// How `SendBlockGenerator` delivers a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendBlockMode {
+    /// Announce the block's header before sending the block
+    HeadersFirst,
+    /// Send the block without announcing its header first (i.e. as an unrequested block)
+    BlockBeforeHeaders,
+}
+
+/// `SendBlockGenerator` sends an existing block to the node. In multi-peer mode, the block is sent
+/// on all available connections within 10ms, exercising the deduplication of blocks that arrive
+/// from multiple peers at the same time.
+pub struct SendBlockGenerator {
+    multi_peer: bool,
+    mode: SendBlockMode,
+}
+
+impl SendBlockGenerator {
+    pub fn new(multi_peer: bool, mode: SendBlockMode) -> Self {
+        Self { multi_peer, mode }
+    }
+}
+
+impl Default for SendBlockGenerator {
+    fn default() -> Self {
+        Self::new(true, SendBlockMode::HeadersFirst)
+    }
+}
 
 impl<R: RngCore> Generator<R> for SendBlockGenerator {
     fn generate(
@@ -411,24 +480,78 @@ impl<R: RngCore> Generator<R> for SendBlockGenerator {
         let block_var = builder
             .get_random_variable(rng, Variable::Block)
             .ok_or(GeneratorError::MissingVariables)?;
-        let conn_var = builder.get_or_create_random_connection(rng);
 
-        if rng.gen_bool(0.95) {
-            builder.force_append(vec![conn_var.index, block_var.index], Operation::SendBlock);
+        let conn_vars = if self.multi_peer {
+            let vars = builder.get_all_variables(Variable::Connection);
+            if vars.is_empty() {
+                (0..builder.context().num_connections)
+                    .map(|id| {
+                        builder.force_append_expect_output(vec![], Operation::LoadConnection(id))
+                    })
+                    .collect()
+            } else {
+                vars
+            }
         } else {
-            builder.force_append(
-                vec![conn_var.index, block_var.index],
-                Operation::SendBlockNoWit,
-            );
+            vec![builder.get_or_create_random_connection(rng)]
+        };
+
+        // `BuildBlock` outputs the header right before the block
+        let header_var = block_var
+            .index
+            .checked_sub(1)
+            .and_then(|index| builder.get_variable(index))
+            .filter(|var| var.var == Variable::Header);
+
+        let send_block = if rng.gen_bool(0.95) {
+            Operation::SendBlock
+        } else {
+            Operation::SendBlockNoWit
+        };
+
+        for (i, conn_var) in conn_vars.iter().enumerate() {
+            if i == 1 {
+                // Deliver the block on the remaining connections 10ms later (mock time has a
+                // resolution of seconds, so this only takes effect once the duration is mutated)
+                advance_time(builder, Duration::from_millis(10));
+            }
+
+            if let (SendBlockMode::HeadersFirst, Some(header_var)) = (self.mode, &header_var) {
+                builder.force_append(
+                    vec![conn_var.index, header_var.index],
+                    Operation::SendHeader,
+                );
+            }
+            builder.force_append(vec![conn_var.index, block_var.index], send_block.clone());
         }
         Ok(())
     }
 
     fn name(&self) -> &'static str {
-        "SendBlockGenerator"
+        match self.mode {
+            SendBlockMode::HeadersFirst => "SendBlockGenerator",
+            SendBlockMode::BlockBeforeHeaders => "SendBlockBeforeHeadersGenerator",
+        }
     }
 }
 
+/// Advance the mock time by `duration`, starting from the most recent time variable (or the
+/// context's timestamp)
+pub(super) fn advance_time(builder: &mut ProgramBuilder, duration: Duration) {
+    let time_var = match builder.get_nearest_variable(Variable::Time) {
+        Some(v) => v,
+        None => builder
+            .force_append_expect_output(vec![], Operation::LoadTime(builder.context().timestamp)),
+    };
+    let duration_var =
+        builder.force_append_expect_output(vec![], Operation::LoadDuration(duration));
+    let new_time = builder.force_append_expect_output(
+        vec![time_var.index, duration_var.index],
+        Operation::AdvanceTime,
+    );
+    builder.force_append(vec![new_time.index], Operation::SetTime);
+}
+
 /// `AddTxToBlockGenerator` generates `AddTx` instructions, adding transactions to a block
 #[derive(Default)]
 pub struct AddTxToBlockGenerator;
@@ -459,3 +582,224 @@ impl<R: RngCore> Generator<R> for AddTxToBlockGenerator {
         InstructionContext::BlockTransactions
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ProgramContext,
+        compiler::{CompiledAction, Compiler},
+        generators::tx::{OutputType, build_tx},
+        test_utils,
+    };
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    /// Send a freshly built block with `generator` and return the commands sent per connection
+    fn send_block(generator: &SendBlockGenerator) -> Vec<(usize, String)> {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 3,
+            timestamp: 0,
+        });
+        let mut rng = SmallRng::seed_from_u64(0);
+        let header_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadHeader {
+                prev: [0; 32],
+                merkle_root: [0; 32],
+                nonce: 0,
+                bits: 0x207f_ffff,
+                time: 1_296_688_602,
+                version: 4,
+                height: 200,
+            },
+        );
+        let time_var =
+            builder.force_append_expect_output(vec![], Operation::LoadTime(1_296_688_700));
+        build_block(
+            &CoinbaseTxGenerator,
+            &mut builder,
+            &mut rng,
+            header_var.index,
+            time_var.index,
+            &[],
+            None,
+        )
+        .unwrap();
+
+        generator.generate(&mut builder, &mut rng, None).unwrap();
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new().compile(&program).unwrap();
+        assert!(
+            compiled
+                .actions
+                .iter()
+                .any(|action| matches!(action, CompiledAction::SetTime(_)))
+        );
+        compiled
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                CompiledAction::SendRawMessage(conn, command, _) => Some((*conn, command.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sends_block_on_all_connections() {
+        let messages = send_block(&SendBlockGenerator::default());
+        for conn in 0..3 {
+            let commands: Vec<&str> = messages
+                .iter()
+                .filter(|(c, _)| *c == conn)
+                .map(|(_, command)| command.as_str())
+                .collect();
+            assert_eq!(commands, ["headers", "block"]);
+        }
+    }
+
+    #[test]
+    fn sends_block_before_headers() {
+        let messages = send_block(&SendBlockGenerator::new(
+            true,
+            SendBlockMode::BlockBeforeHeaders,
+        ));
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|(_, command)| command == "block"));
+    }
+
+    #[test]
+    fn coinbase_txo_is_spendable() {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let header_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadHeader {
+                prev: [0; 32],
+                merkle_root: [0; 32],
+                nonce: 0,
+                bits: 0x207f_ffff,
+                time: 1_296_688_602,
+                version: 4,
+                height: 200,
+            },
+        );
+        let time_var =
+            builder.force_append_expect_output(vec![], Operation::LoadTime(1_296_688_700));
+        let block_vars = build_block(
+            &CoinbaseTxGenerator,
+            &mut builder,
+            &mut rng,
+            header_var.index,
+            time_var.index,
+            &[],
+            None,
+        )
+        .unwrap();
+        let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        builder.force_append(
+            vec![conn_var.index, block_vars[1].index],
+            Operation::SendBlock,
+        );
+        let txo_var = builder
+            .force_append_expect_output(vec![block_vars[2].index], Operation::TakeCoinbaseTxo);
+        let (tx_var, _) = build_tx(
+            &mut builder,
+            &mut rng,
+            &[txo_var],
+            2,
+            &[(1_000, OutputType::PayToWitnessScriptHash)],
+        )
+        .unwrap();
+        builder.force_append(vec![conn_var.index, tx_var.index], Operation::SendTx);
+
+        let messages = test_utils::sent_raw_messages(&builder);
+        let [(_, block_payload), (_, tx_payload)] = messages.as_slice() else {
+            panic!("expected a block and a tx, got {} messages", messages.len());
+        };
+        let block: bitcoin::Block = bitcoin::consensus::deserialize(block_payload).unwrap();
+        let tx: bitcoin::Transaction = bitcoin::consensus::deserialize(tx_payload).unwrap();
+
+        let coinbase = &block.txdata[0];
+        assert!(coinbase.is_coinbase());
+        assert_eq!(
+            tx.input[0].previous_output,
+            bitcoin::OutPoint::new(coinbase.compute_txid(), 0)
+        );
+    }
+
+    #[test]
+    fn coinbase_uses_custom_script_sig() {
+        let mut custom_script_sigs = 0;
+        for seed in 0..256 {
+            let mut builder = test_utils::builder();
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let header_var = builder.force_append_expect_output(
+                vec![],
+                Operation::LoadHeader {
+                    prev: [0; 32],
+                    merkle_root: [0; 32],
+                    nonce: 0,
+                    bits: 0x207f_ffff,
+                    time: 1_296_688_602,
+                    version: 4,
+                    height: 200,
+                },
+            );
+            let time_var =
+                builder.force_append_expect_output(vec![], Operation::LoadTime(1_296_688_700));
+            let block_vars = build_block(
+                &CoinbaseTxGenerator,
+                &mut builder,
+                &mut rng,
+                header_var.index,
+                time_var.index,
+                &[],
+                None,
+            )
+            .unwrap();
+            let conn_var = builder.get_or_create_random_connection(&mut rng);
+            builder.force_append(
+                vec![conn_var.index, block_vars[1].index],
+                Operation::SendBlock,
+            );
+
+            let block: bitcoin::Block = test_utils::sent_messages(&builder, "block").remove(0);
+            let program = builder.finalize().unwrap();
+            let script_sig = &block.txdata[0].input[0].script_sig;
+
+            let custom_script_sig =
+                program
+                    .instructions
+                    .iter()
+                    .enumerate()
+                    .find_map(|(index, instruction)| match instruction.operation {
+                        Operation::LoadCoinbaseScriptSig => {
+                            match &program.instructions[index - 1] {
+                                Instruction {
+                                    operation: Operation::LoadBytes(bytes),
+                                    ..
+                                } => Some(bytes.clone()),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    });
+            match custom_script_sig {
+                Some(bytes) => {
+                    assert_eq!(script_sig.as_bytes(), bytes.as_slice());
+                    custom_script_sigs += 1;
+                }
+                // BIP34 height push
+                None => assert!(
+                    script_sig
+                        .as_bytes()
+                        .starts_with(bitcoin::ScriptBuf::builder().push_int(201).as_bytes())
+                ),
+            }
+        }
+        assert!(custom_script_sigs > 0);
+    }
+}
@@ -0,0 +1,221 @@
+use bitcoin::BlockHash;
+use fuzzamoto::targets::bitcoin_core::BlockFilter;
+use rand::{Rng, RngCore, seq::SliceRandom};
+
+use super::{GeneratorError, block::build_block};
+use crate::{
+    CoinbaseTxGenerator, Generator, GeneratorResult, Header, Operation, PerTestcaseMetadata,
+    ProgramBuilder, Variable,
+};
+
+/// `BlockFilterGenerator` generates instructions for building a new block, sending it and then
+/// sending one of the BIP-158 filter responses (`cfilter`, `cfheaders` or `cfcheckpt`) for it. The
+/// filter itself is computed by the compiler from the block's transactions.
+///
+/// Blocks are preferably built on top of a snapshot block with a known filter, such that the sent
+/// filter headers chain onto the real filter header of the parent.
+pub struct BlockFilterGenerator {
+    coinbase_generator: CoinbaseTxGenerator,
+    // Snapshot headers with the filter header of their block
+    parents: Vec<(Header, [u8; 32])>,
+}
+
+impl BlockFilterGenerator {
+    pub fn new(headers: Vec<Header>, block_filters: Vec<(BlockHash, BlockFilter)>) -> Self {
+        let parents = block_filters
+            .iter()
+            .filter_map(|(hash, filter)| {
+                headers
+                    .iter()
+                    .find(|header| header.block_hash() == *hash)
+                    .map(|header| (header.clone(), filter.header))
+            })
+            .collect();
+
+        Self {
+            coinbase_generator: CoinbaseTxGenerator::default(),
+            parents,
+        }
+    }
+}
+
+impl<R: RngCore> Generator<R> for BlockFilterGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let (header_var, prev_filter_header) =
+            if let Some((header, filter_header)) = self.parents.choose(rng) {
+                let header_var = builder.force_append_expect_output(
+                    vec![],
+                    Operation::LoadHeader {
+                        prev: header.prev,
+                        merkle_root: header.merkle_root,
+                        nonce: header.nonce,
+                        bits: header.bits,
+                        time: header.time,
+                        version: header.version,
+                        height: header.height,
+                    },
+                );
+                (header_var, *filter_header)
+            } else {
+                let header_var = builder
+                    .get_random_variable(rng, Variable::Header)
+                    .ok_or(GeneratorError::MissingVariables)?;
+                (header_var, [0u8; 32])
+            };
+        let time_var = builder
+            .get_random_variable(rng, Variable::Time)
+            .ok_or(GeneratorError::MissingVariables)?;
+        let mut tx_vars = builder.get_random_variables(rng, Variable::ConstTx);
+        tx_vars.sort_by_key(|tx| tx.index);
+
+        let block_vars = build_block(
+            &self.coinbase_generator,
+            builder,
+            rng,
+            header_var.index,
+            time_var.index,
+            &tx_vars,
+            meta,
+        )?;
+        // The block transactions are the last input of the `BuildBlock` instruction
+        let block_txs_var = builder
+            .instructions
+            .last()
+            .and_then(|instruction| instruction.inputs.last().copied())
+            .expect("BuildBlock should have inputs");
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+        builder.force_append(
+            vec![conn_var.index, block_vars[0].index],
+            Operation::SendHeader,
+        );
+        builder.force_append(
+            vec![conn_var.index, block_vars[1].index],
+            Operation::SendBlock,
+        );
+
+        // Mostly basic filters, the only type defined by BIP-158
+        let filter_type = if rng.gen_bool(0.9) { 0 } else { rng.r#gen() };
+        let operation = match rng.gen_range(0..3) {
+            0 => Operation::SendCFilter { filter_type },
+            1 => Operation::SendCFHeaders {
+                filter_type,
+                prev_filter_header,
+            },
+            _ => Operation::SendCFCheckpt {
+                filter_type,
+                prev_filter_header,
+            },
+        };
+        builder.force_append(
+            vec![conn_var.index, block_vars[0].index, block_txs_var],
+            operation,
+        );
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "BlockFilterGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoin::{
+        Block, ScriptBuf,
+        bip158::{self, FilterHash, FilterHeader},
+        consensus::deserialize,
+        hashes::Hash,
+        p2p::message_filter::{CFCheckpt, CFHeaders, CFilter},
+    };
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn sends_filter_of_built_block() {
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let parent = Header {
+            prev: genesis.header.prev_blockhash.to_byte_array(),
+            merkle_root: genesis.header.merkle_root.to_byte_array(),
+            nonce: genesis.header.nonce,
+            bits: genesis.header.bits.to_consensus(),
+            time: genesis.header.time,
+            version: genesis.header.version.to_consensus(),
+            height: 0,
+        };
+        let parent_filter_header = [0x11; 32];
+        let generator = BlockFilterGenerator::new(
+            vec![parent],
+            vec![(
+                genesis.block_hash(),
+                BlockFilter {
+                    filter: vec![],
+                    header: parent_filter_header,
+                },
+            )],
+        );
+
+        let mut seen = [false; 3];
+        for seed in 0..32 {
+            let mut builder = test_utils::builder();
+            builder.force_append(vec![], Operation::LoadTime(genesis.header.time as u64 + 1));
+            let mut rng = SmallRng::seed_from_u64(seed);
+            generator.generate(&mut builder, &mut rng, None).unwrap();
+
+            let messages = test_utils::sent_raw_messages(&builder);
+            let block: Block = test_utils::sent_messages(&builder, "block").remove(0);
+            assert_eq!(block.header.prev_blockhash, genesis.block_hash());
+
+            // The block only contains the coinbase, so there are no spent output scripts
+            let expected = bip158::BlockFilter::new_script_filter(&block, |outpoint| {
+                Err::<ScriptBuf, _>(bip158::Error::UtxoMissing(*outpoint))
+            })
+            .unwrap();
+
+            let (command, payload) = messages.last().unwrap();
+            match command.as_str() {
+                "cfilter" => {
+                    let cfilter: CFilter = deserialize(payload).unwrap();
+                    assert_eq!(cfilter.block_hash, block.block_hash());
+                    assert_eq!(cfilter.filter, expected.content);
+                    seen[0] = true;
+                }
+                "cfheaders" => {
+                    let cfheaders: CFHeaders = deserialize(payload).unwrap();
+                    assert_eq!(cfheaders.stop_hash, block.block_hash());
+                    assert_eq!(
+                        cfheaders.previous_filter_header.to_byte_array(),
+                        parent_filter_header
+                    );
+                    assert_eq!(
+                        cfheaders.filter_hashes,
+                        vec![FilterHash::hash(&expected.content)]
+                    );
+                    seen[1] = true;
+                }
+                "cfcheckpt" => {
+                    let cfcheckpt: CFCheckpt = deserialize(payload).unwrap();
+                    assert_eq!(cfcheckpt.stop_hash, block.block_hash());
+                    assert_eq!(
+                        cfcheckpt.filter_headers,
+                        vec![
+                            expected.filter_header(&FilterHeader::from_byte_array(
+                                parent_filter_header
+                            ))
+                        ]
+                    );
+                    seen[2] = true;
+                }
+                command => panic!("unexpected {command} message"),
+            }
+        }
+        assert_eq!(seen, [true; 3]);
+    }
+}
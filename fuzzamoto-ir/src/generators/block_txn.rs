@@ -1,8 +1,11 @@
+use std::collections::HashMap;
+
+use super::compact_block::{build_compact_block, load_nonce, send_compact_block};
 use crate::{
     Generator, GeneratorError, GeneratorResult, Instruction, Operation, PerTestcaseMetadata,
     ProgramBuilder, Variable,
 };
-use rand::{Rng, RngCore};
+use rand::{Rng, RngCore, seq::SliceRandom};
 
 /// `BlockTxnGenerator` inserts `blocktxn` operation in response to the `getblocktxn` message
 #[derive(Debug, Copy, Clone, Default)]
@@ -144,3 +147,205 @@ impl BlockTxnGenerator {
         Self {}
     }
 }
+
+/// `GetBlockTxnGenerator` sends a block as `cmpctblock` and follows up with a `getblocktxn`
+/// request for 1-5 of the block's transactions (BIP-152).
+#[derive(Debug, Copy, Clone, Default)]
+pub struct GetBlockTxnGenerator;
+
+/// Transaction variables (in scope) that were added to the block `block_var` via `AddTx`
+fn block_tx_vars(builder: &ProgramBuilder, block_var: usize) -> Vec<usize> {
+    // Block transaction list variables (mutable and const) to the transactions added to them
+    let mut block_txs: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut next_var = 0;
+    for instruction in &builder.instructions {
+        match instruction.operation {
+            Operation::AddTx => block_txs
+                .entry(instruction.inputs[0])
+                .or_default()
+                .push(instruction.inputs[1]),
+            Operation::EndBlockTransactions => {
+                let txs = block_txs
+                    .get(&instruction.inputs[0])
+                    .cloned()
+                    .unwrap_or_default();
+                block_txs.insert(next_var, txs);
+            }
+            // `BuildBlock` outputs the header, block and coinbase variables
+            Operation::BuildBlock if next_var + 1 == block_var => {
+                return block_txs
+                    .remove(&instruction.inputs[4])
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|tx_var| builder.get_variable(*tx_var).is_some())
+                    .collect();
+            }
+            _ => {}
+        }
+        next_var += instruction.operation.num_outputs() + instruction.operation.num_inner_outputs();
+    }
+    Vec::new()
+}
+
+impl<R: RngCore> Generator<R> for GetBlockTxnGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let Some(block) = builder.get_random_variable(rng, Variable::Block) else {
+            return Err(GeneratorError::MissingVariables);
+        };
+        let connection_var = builder.get_or_create_random_connection(rng);
+
+        let nonce_var = load_nonce(builder, rng);
+        let cmpct_block = build_compact_block(builder, &block, &nonce_var);
+        send_compact_block(builder, &connection_var, &cmpct_block);
+
+        // Request transactions of the block, falling back to arbitrary transactions (requested by
+        // an out-of-bounds index) if the block has none
+        let mut tx_vars = block_tx_vars(builder, block.index);
+        if tx_vars.is_empty() {
+            tx_vars = builder
+                .get_random_variables(rng, Variable::ConstTx)
+                .into_iter()
+                .map(|tx_var| tx_var.index)
+                .collect();
+        }
+
+        let mut_request_var =
+            builder.force_append_expect_output(vec![block.index], Operation::BeginBuildGetBlockTxn);
+        if !tx_vars.is_empty() {
+            for _ in 0..rng.gen_range(1..=5) {
+                builder.force_append(
+                    vec![mut_request_var.index, *tx_vars.choose(rng).unwrap()],
+                    Operation::AddShortIdToReq,
+                );
+            }
+        }
+        let request_var = builder.force_append_expect_output(
+            vec![mut_request_var.index],
+            Operation::EndBuildGetBlockTxn,
+        );
+        builder.force_append(
+            vec![connection_var.index, request_var.index],
+            Operation::SendGetBlockTxn,
+        );
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "GetBlockTxnGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoin::{
+        bip152::{BlockTransactions, BlockTransactionsRequest},
+        consensus::deserialize,
+    };
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    /// Builder with a block (sent on connection 0) containing a single non-coinbase transaction
+    fn builder_with_block(rng: &mut SmallRng) -> ProgramBuilder {
+        let mut builder = test_utils::builder();
+        crate::TxoGenerator::new(vec![crate::Txo {
+            outpoint: ([1; 32], 0),
+            value: 100_000_000,
+            script_pubkey: vec![0x51],
+            spending_script_sig: vec![],
+            spending_witness: vec![],
+        }])
+        .generate(&mut builder, rng, None)
+        .unwrap();
+        crate::SingleTxGenerator::default()
+            .generate(&mut builder, rng, None)
+            .unwrap();
+        let tx_var = builder.get_random_variable(rng, Variable::ConstTx).unwrap();
+
+        let header_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadHeader {
+                prev: [0; 32],
+                merkle_root: [0; 32],
+                nonce: 0,
+                bits: 0x207f_ffff,
+                time: 1_296_688_602,
+                version: 4,
+                height: 200,
+            },
+        );
+        let time_var =
+            builder.force_append_expect_output(vec![], Operation::LoadTime(1_296_688_700));
+        let block_vars = crate::build_block(
+            &crate::CoinbaseTxGenerator,
+            &mut builder,
+            rng,
+            header_var.index,
+            time_var.index,
+            &[tx_var],
+            None,
+        )
+        .unwrap();
+        let conn_var = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        builder.force_append(
+            vec![conn_var.index, block_vars[1].index],
+            Operation::SendBlock,
+        );
+        builder
+    }
+
+    /// Commands and payloads of the messages sent after the block itself
+    fn sent_messages(builder: ProgramBuilder) -> (bitcoin::Block, Vec<(String, Vec<u8>)>) {
+        let mut messages = test_utils::sent_raw_messages(&builder).into_iter();
+        let (command, payload) = messages.next().unwrap();
+        assert_eq!(command, "block");
+        (deserialize(&payload).unwrap(), messages.collect())
+    }
+
+    #[test]
+    fn requests_transactions_of_compact_block() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut builder = builder_with_block(&mut rng);
+        GetBlockTxnGenerator
+            .generate(&mut builder, &mut rng, None)
+            .unwrap();
+
+        let (_, messages) = sent_messages(builder);
+        let commands: Vec<&str> = messages
+            .iter()
+            .map(|(command, _)| command.as_str())
+            .collect();
+        assert_eq!(commands, vec!["cmpctblock", "getblocktxn"]);
+
+        let request: BlockTransactionsRequest = deserialize(&messages[1].1).unwrap();
+        // The block's only non-coinbase transaction, requested (up to five times) by its index
+        assert_eq!(request.indexes, vec![1]);
+    }
+
+    #[test]
+    fn sends_transactions_of_block() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut builder = builder_with_block(&mut rng);
+        BlockTxnGenerator
+            .generate(&mut builder, &mut rng, None)
+            .unwrap();
+
+        let (block, messages) = sent_messages(builder);
+        let [(command, payload)] = messages.as_slice() else {
+            panic!(
+                "expected a single blocktxn, got {} messages",
+                messages.len()
+            );
+        };
+        assert_eq!(command, "blocktxn");
+        let block_txn: BlockTransactions = deserialize(payload).unwrap();
+        assert_eq!(block_txn.block_hash, block.block_hash());
+        assert_eq!(block_txn.transactions, vec![block.txdata[1].clone()]);
+    }
+}
@@ -9,7 +9,7 @@ use crate::{
 use bitcoin::constants::MAX_SCRIPT_ELEMENT_SIZE;
 use rand::{Rng, RngCore};
 
-use super::GeneratorResult;
+use super::{GeneratorError, GeneratorResult};
 
 #[derive(Debug, Default)]
 pub struct BloomFilterLoadGenerator;
@@ -245,3 +245,185 @@ impl<R: RngCore> Generator<R> for BloomFilterClearGenerator {
         "BloomFilterClearGenerator"
     }
 }
+
+/// `BloomFilterMerkleBlockGenerator` generates a `merkleblock` for an existing block, with the
+/// transactions matched against the nearest filter. If there is no filter yet, a new one is loaded
+/// first (`filterload` -> `merkleblock`).
+#[derive(Debug, Default)]
+pub struct BloomFilterMerkleBlockGenerator;
+
+impl<R: RngCore> Generator<R> for BloomFilterMerkleBlockGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let Some(block_var) = builder.get_random_variable(rng, Variable::Block) else {
+            return Err(GeneratorError::MissingVariables);
+        };
+        let connection_var = builder.get_or_create_random_connection(rng);
+
+        let filter_var = match builder.get_nearest_variable(Variable::ConstFilterLoad) {
+            Some(filter_var) => filter_var,
+            None => {
+                let filter_var = init_filter(builder, rng);
+                builder
+                    .append(Instruction {
+                        inputs: vec![connection_var.index, filter_var.index],
+                        operation: Operation::SendFilterLoad,
+                    })
+                    .expect("Inserting SendFilterLoad should always succeed");
+                filter_var
+            }
+        };
+
+        let merkle_block_var = builder
+            .append(Instruction {
+                inputs: vec![block_var.index, filter_var.index],
+                operation: Operation::BuildMerkleBlock,
+            })
+            .expect("Inserting BuildMerkleBlock should always succeed")
+            .pop()
+            .expect("BuildMerkleBlock should always produce a var");
+        builder
+            .append(Instruction {
+                inputs: vec![connection_var.index, merkle_block_var.index],
+                operation: Operation::SendMerkleBlock,
+            })
+            .expect("Inserting SendMerkleBlock should always succeed");
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "BloomFilterMerkleBlockGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloom::{filter_contains, filter_matches_tx};
+    use crate::{BlockGenerator, test_utils};
+    use bitcoin::{
+        MerkleBlock, OutPoint, Txid, consensus::serialize, hashes::Hash,
+        p2p::message_bloom::FilterLoad,
+    };
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    /// Block of the program and the `merkleblock` sent for it, using a filter with the given bytes
+    fn compile_merkle_block(filter: Vec<u8>) -> (bitcoin::Block, MerkleBlock) {
+        let mut builder = test_utils::builder_on_genesis();
+        let mut rng = SmallRng::seed_from_u64(0);
+        BlockGenerator::default()
+            .generate(&mut builder, &mut rng, None)
+            .expect("block generation should succeed");
+        builder.force_append(
+            vec![],
+            Operation::LoadFilterLoad {
+                filter,
+                hash_funcs: 10,
+                tweak: 0,
+                flags: 0,
+            },
+        );
+        BloomFilterMerkleBlockGenerator
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+
+        (
+            test_utils::sent_messages(&builder, "block").remove(0),
+            test_utils::sent_messages(&builder, "merkleblock").remove(0),
+        )
+    }
+
+    #[test]
+    fn built_filter_matches_tx_and_spends() {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        crate::TxoGenerator::new(vec![crate::Txo {
+            outpoint: ([1; 32], 0),
+            value: 100_000_000,
+            script_pubkey: vec![0x51],
+            spending_script_sig: vec![],
+            spending_witness: vec![],
+        }])
+        .generate(&mut builder, &mut rng, None)
+        .unwrap();
+        let txo_var = builder
+            .get_random_variable(&mut rng, Variable::Txo)
+            .unwrap();
+        crate::SingleTxGenerator::default()
+            .generate(&mut builder, &mut rng, None)
+            .unwrap();
+        let tx_var = builder
+            .get_random_variable(&mut rng, Variable::ConstTx)
+            .unwrap();
+
+        let filter_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadFilterLoad {
+                filter: vec![0; 512],
+                hash_funcs: 10,
+                tweak: 0,
+                flags: 0,
+            },
+        );
+        let filter_var =
+            populate_filter_from_txdata(&mut builder, filter_var, &[tx_var.clone()], &[txo_var]);
+        let conn_var = builder.get_or_create_random_connection(&mut rng);
+        builder.force_append(
+            vec![conn_var.index, filter_var.index],
+            Operation::SendFilterLoad,
+        );
+        // `SingleTxGenerator` only sends the transaction some of the time
+        builder.force_append(vec![conn_var.index, tx_var.index], Operation::SendTx);
+
+        let tx: bitcoin::Transaction = test_utils::sent_messages(&builder, "tx").remove(0);
+        let filter_load: FilterLoad = test_utils::sent_messages(&builder, "filterload").remove(0);
+        let contains = |key: &[u8]| filter_contains(&filter_load.filter, 10, key);
+        assert!(contains(tx.compute_txid().as_byte_array()));
+        // The spent txo's outpoint and the outpoints of the transaction's outputs
+        assert!(contains(&serialize(&OutPoint::new(
+            Txid::from_byte_array([1; 32]),
+            0
+        ))));
+        for vout in 0..tx.output.len() as u32 {
+            assert!(contains(&serialize(&OutPoint::new(
+                tx.compute_txid(),
+                vout
+            ))));
+        }
+        assert!(filter_matches_tx(&filter_load.filter, 10, &tx));
+    }
+
+    #[test]
+    fn empty_filter_matches_all_transactions() {
+        let (block, merkle_block) = compile_merkle_block(vec![]);
+        assert_eq!(merkle_block.header, block.header);
+
+        let mut matches = Vec::new();
+        let mut indexes = Vec::new();
+        let root = merkle_block
+            .txn
+            .extract_matches(&mut matches, &mut indexes)
+            .expect("valid partial merkle tree");
+        assert_eq!(root, block.header.merkle_root);
+        assert_eq!(matches.len(), block.txdata.len());
+    }
+
+    #[test]
+    fn cleared_filter_matches_no_transactions() {
+        let (block, merkle_block) = compile_merkle_block(vec![0; 64]);
+
+        let mut matches = Vec::new();
+        let mut indexes = Vec::new();
+        let root = merkle_block
+            .txn
+            .extract_matches(&mut matches, &mut indexes)
+            .expect("valid partial merkle tree");
+        assert_eq!(root, block.header.merkle_root);
+        assert!(matches.is_empty());
+    }
+}
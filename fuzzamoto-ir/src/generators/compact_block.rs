@@ -1,13 +1,89 @@
 use super::{GeneratorError, GeneratorResult};
 use crate::{
-    Instruction, Operation, PerTestcaseMetadata, Variable,
+    IndexedVariable, Instruction, Operation, PerTestcaseMetadata, Program, Variable,
     generators::{Generator, ProgramBuilder},
 };
-use rand::{Rng, RngCore};
+use rand::{Rng, RngCore, seq::IteratorRandom};
 
-/// `CompactBlockGenerator` generates a new `cmpctblock` message.
+/// BIP-152 relay mode negotiated via `sendcmpct` before sending compact blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactBlockMode {
+    /// `sendcmpct(announce=1)`, compact blocks are sent without prior announcement
+    HighBandwidth,
+    /// `sendcmpct(announce=0)`, blocks are announced via `inv` and the compact block is sent in
+    /// response to the expected `getdata`
+    LowBandwidth,
+}
+
+/// `CompactBlockGenerator` generates a new `cmpctblock` message for an existing block, preceded by
+/// a `sendcmpct` negotiation (version 1 or 2) for the chosen `CompactBlockMode`.
 #[derive(Debug, Default)]
-pub struct CompactBlockGenerator;
+pub struct CompactBlockGenerator {
+    /// Relay mode to use, chosen at random for every generated message if `None`
+    mode: Option<CompactBlockMode>,
+}
+
+impl CompactBlockGenerator {
+    pub fn new(mode: CompactBlockMode) -> Self {
+        Self { mode: Some(mode) }
+    }
+}
+
+pub(super) fn load_nonce<R: RngCore>(builder: &mut ProgramBuilder, rng: &mut R) -> IndexedVariable {
+    let nonce = rng.gen_range(0..u64::MAX);
+    builder
+        .append(Instruction {
+            inputs: vec![],
+            operation: Operation::LoadNonce(nonce),
+        })
+        .expect("Inserting LoadNonce should always succeed")
+        .pop()
+        .expect("LoadNonce should always produce a var")
+}
+
+pub(super) fn build_compact_block(
+    builder: &mut ProgramBuilder,
+    block: &IndexedVariable,
+    nonce_var: &IndexedVariable,
+) -> IndexedVariable {
+    builder
+        .append(Instruction {
+            inputs: vec![block.index, nonce_var.index],
+            operation: Operation::BuildCompactBlock,
+        })
+        .expect("Inserting BuildCompactBlock should always succeed")
+        .pop()
+        .expect("BuildCompactBlock should always produce a var")
+}
+
+pub(super) fn send_compact_block(
+    builder: &mut ProgramBuilder,
+    connection_var: &IndexedVariable,
+    cmpct_block: &IndexedVariable,
+) {
+    builder
+        .append(Instruction {
+            inputs: vec![connection_var.index, cmpct_block.index],
+            operation: Operation::SendCompactBlock,
+        })
+        .expect("Inserting SendCompactBlock should always succeed");
+}
+
+fn send_sendcmpct(
+    builder: &mut ProgramBuilder,
+    connection_var: usize,
+    announce: bool,
+    version: u64,
+) {
+    let announce_var =
+        builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![announce as u8]));
+    let version_var =
+        builder.force_append_expect_output(vec![], Operation::LoadSize(version as usize));
+    builder.force_append(
+        vec![connection_var, announce_var.index, version_var.index],
+        Operation::SendSendCmpct,
+    );
+}
 
 impl<R: RngCore> Generator<R> for CompactBlockGenerator {
     fn generate(
@@ -23,36 +99,259 @@ impl<R: RngCore> Generator<R> for CompactBlockGenerator {
 
         let connection_var = builder.get_or_create_random_connection(rng);
 
-        let nonce = rng.gen_range(0..u64::MAX);
-        let nonce_var = builder
-            .append(Instruction {
-                inputs: vec![],
-                operation: Operation::LoadNonce(nonce),
+        let mode = self.mode.unwrap_or_else(|| {
+            if rng.gen_bool(0.5) {
+                CompactBlockMode::HighBandwidth
+            } else {
+                CompactBlockMode::LowBandwidth
+            }
+        });
+        let version = rng.gen_range(1..=2);
+        send_sendcmpct(
+            builder,
+            connection_var.index,
+            mode == CompactBlockMode::HighBandwidth,
+            version,
+        );
+
+        if mode == CompactBlockMode::LowBandwidth {
+            let mut_inventory_var =
+                builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+            builder.force_append(
+                vec![mut_inventory_var.index, block.index],
+                Operation::AddCompactBlockInv,
+            );
+            let inventory_var = builder.force_append_expect_output(
+                vec![mut_inventory_var.index],
+                Operation::EndBuildInventory,
+            );
+            builder.force_append(
+                vec![connection_var.index, inventory_var.index],
+                Operation::SendInv,
+            );
+        }
+
+        // Short id nonce derived from a loaded one (see `Operation::RandomizeNonce`)
+        let nonce_var = load_nonce(builder, rng);
+        let nonce_var =
+            builder.force_append_expect_output(vec![nonce_var.index], Operation::RandomizeNonce);
+        let cmpct_block = build_compact_block(builder, &block, &nonce_var);
+        send_compact_block(builder, &connection_var, &cmpct_block);
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        match self.mode {
+            None => "CompactBlockGenerator",
+            Some(CompactBlockMode::HighBandwidth) => "HighBandwidthCompactBlockGenerator",
+            Some(CompactBlockMode::LowBandwidth) => "LowBandwidthCompactBlockGenerator",
+        }
+    }
+}
+
+/// `CompactBlockNonceGenerator` sends the same block as `cmpctblock` multiple times, each with a
+/// different short id nonce, to exercise the de-duplication of compact blocks.
+#[derive(Debug, Default)]
+pub struct CompactBlockNonceGenerator;
+
+impl<R: RngCore> Generator<R> for CompactBlockNonceGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let Some(block) = builder.get_random_variable(rng, Variable::Block) else {
+            return Err(GeneratorError::MissingVariables);
+        };
+
+        let connection_var = builder.get_or_create_random_connection(rng);
+        for _ in 0..rng.gen_range(2..=5) {
+            let nonce_var = load_nonce(builder, rng);
+            let cmpct_block = build_compact_block(builder, &block, &nonce_var);
+            send_compact_block(builder, &connection_var, &cmpct_block);
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "CompactBlockNonceGenerator"
+    }
+}
+
+/// `CompactBlockNegotiationGenerator` negotiates the block relay mode of a connection, i.e. it
+/// sends a `sendheaders` and/or a `sendcmpct` (high or low bandwidth, version 1 or 2). The
+/// negotiation is inserted before an existing `SendBlock` or `SendCompactBlock` if there is one, so
+/// that the block is relayed under the negotiated mode.
+#[derive(Debug, Default)]
+pub struct CompactBlockNegotiationGenerator;
+
+impl<R: RngCore> Generator<R> for CompactBlockNegotiationGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let connection_var = builder.get_or_create_random_connection(rng);
+
+        let send_headers = rng.gen_bool(0.5);
+        if send_headers {
+            builder.force_append(vec![connection_var.index], Operation::SendSendHeaders);
+        }
+        if !send_headers || rng.gen_bool(0.5) {
+            let version = rng.gen_range(1..=2);
+            send_sendcmpct(builder, connection_var.index, rng.gen_bool(0.5), version);
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "CompactBlockNegotiationGenerator"
+    }
+
+    fn choose_index(
+        &self,
+        program: &Program,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> Option<usize> {
+        program
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| {
+                matches!(
+                    instruction.operation,
+                    Operation::SendBlock | Operation::SendBlockNoWit | Operation::SendCompactBlock
+                )
             })
-            .expect("Inserting BeginBuildCompactBlock should always succeed")
-            .pop()
-            .expect("BeginBuildCompactBlock should always produce a var");
-
-        let cmpct_block = builder
-            .append(Instruction {
-                inputs: vec![block.index, nonce_var.index],
-                operation: Operation::BuildCompactBlock,
+            .map(|(index, _)| index)
+            .choose(rng)
+            .or_else(|| {
+                program.get_random_instruction_index(
+                    rng,
+                    <Self as Generator<R>>::requested_context(self),
+                )
             })
-            .expect("Inserting BuildCompactBlock should always succeed")
-            .pop()
-            .expect("BuildCompactBlock should always produce a var");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockGenerator, test_utils};
+    use bitcoin::p2p::message_compact_blocks::CmpctBlock;
+    use rand::{SeedableRng, rngs::SmallRng};
 
+    fn builder_with_block(rng: &mut SmallRng) -> ProgramBuilder {
+        let mut builder = test_utils::builder_on_genesis();
+        BlockGenerator::default()
+            .generate(&mut builder, rng, None)
+            .expect("block generation should succeed");
         builder
-            .append(Instruction {
-                inputs: vec![connection_var.index, cmpct_block.index],
-                operation: Operation::SendCompactBlock,
-            })
-            .expect("Inserting SendCompactBlock should always succeed");
+    }
 
-        Ok(())
+    fn generate<G: Generator<SmallRng>>(generator: G) -> ProgramBuilder {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut builder = builder_with_block(&mut rng);
+        generator
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+        builder
     }
 
-    fn name(&self) -> &'static str {
-        "CompactBlockGenerator"
+    /// Commands of the messages sent after the block itself
+    fn commands_after_block(builder: &ProgramBuilder) -> Vec<String> {
+        let commands: Vec<_> = test_utils::sent_raw_messages(builder)
+            .into_iter()
+            .map(|(command, _)| command)
+            .collect();
+        let start = commands.iter().position(|c| c == "block").unwrap() + 1;
+        commands[start..].to_vec()
+    }
+
+    fn sendcmpct_payload(builder: &ProgramBuilder) -> Vec<u8> {
+        test_utils::sent_raw_messages(builder)
+            .into_iter()
+            .find(|(command, _)| command == "sendcmpct")
+            .expect("sendcmpct should be sent")
+            .1
+    }
+
+    #[test]
+    fn high_bandwidth_sends_compact_block_directly() {
+        let builder = generate(CompactBlockGenerator::new(CompactBlockMode::HighBandwidth));
+        assert_eq!(
+            commands_after_block(&builder),
+            vec!["sendcmpct", "cmpctblock"]
+        );
+        let sendcmpct = sendcmpct_payload(&builder);
+        assert_eq!(sendcmpct[0], 1);
+        assert!((1..=2).contains(&u64::from_le_bytes(sendcmpct[1..].try_into().unwrap())));
+    }
+
+    #[test]
+    fn compact_block_matches_sent_block() {
+        let builder = generate(CompactBlockGenerator::new(CompactBlockMode::HighBandwidth));
+        let block = test_utils::sent_messages::<bitcoin::Block>(&builder, "block").remove(0);
+        let compact_block = test_utils::sent_messages::<CmpctBlock>(&builder, "cmpctblock")
+            .remove(0)
+            .compact_block;
+        assert_eq!(compact_block.header, block.header);
+        // Only the coinbase is prefilled, all other transactions are sent as short ids
+        assert_eq!(compact_block.prefilled_txs.len(), 1);
+        assert_eq!(compact_block.prefilled_txs[0].tx, block.txdata[0]);
+        assert_eq!(compact_block.short_ids.len(), block.txdata.len() - 1);
+    }
+
+    #[test]
+    fn low_bandwidth_announces_via_inv() {
+        let builder = generate(CompactBlockGenerator::new(CompactBlockMode::LowBandwidth));
+        assert_eq!(
+            commands_after_block(&builder),
+            vec!["sendcmpct", "inv", "cmpctblock"]
+        );
+        assert_eq!(sendcmpct_payload(&builder)[0], 0);
+    }
+
+    #[test]
+    fn negotiation_is_inserted_before_block() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let program = builder_with_block(&mut rng).finalize().unwrap();
+        let send_block_index = program
+            .instructions
+            .iter()
+            .position(|instruction| matches!(instruction.operation, Operation::SendBlock))
+            .unwrap();
+
+        for seed in 0..16 {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let generator = CompactBlockNegotiationGenerator;
+            assert_eq!(
+                generator.choose_index(&program, &mut rng, None),
+                Some(send_block_index)
+            );
+
+            let mut builder = ProgramBuilder::new(program.context.clone());
+            generator.generate(&mut builder, &mut rng, None).unwrap();
+            for (command, payload) in test_utils::sent_raw_messages(&builder) {
+                match command.as_str() {
+                    "sendheaders" => assert!(payload.is_empty()),
+                    "sendcmpct" => assert_eq!(payload.len(), 9),
+                    other => panic!("unexpected message {}", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nonce_generator_resends_block() {
+        let commands = commands_after_block(&generate(CompactBlockNonceGenerator));
+        assert!(commands.len() >= 2);
+        assert!(commands.iter().all(|c| c == "cmpctblock"));
     }
 }
@@ -1,6 +1,10 @@
+use bitcoin::{BlockHash, hashes::Hash};
+use fuzzamoto::targets::bitcoin_core::BlockFilter;
+
 use crate::{
     Operation, PerTestcaseMetadata, Variable,
     generators::{Generator, ProgramBuilder},
+    msg_type_from_str,
 };
 use rand::{Rng, RngCore, seq::SliceRandom};
 
@@ -67,3 +71,86 @@ impl<R: RngCore> Generator<R> for CompactFilterQueryGenerator {
         "CompactFilterQueryGenerator"
     }
 }
+
+/// `CFilterGenerator` generates instructions for sending a `cfilter` message for one of the
+/// compact block filters present in the snapshot.
+pub struct CFilterGenerator {
+    pub block_filters: Vec<(BlockHash, BlockFilter)>,
+}
+
+impl CFilterGenerator {
+    pub fn new(block_filters: Vec<(BlockHash, BlockFilter)>) -> Self {
+        Self { block_filters }
+    }
+}
+
+impl<R: RngCore> Generator<R> for CFilterGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let (block_hash, block_filter) = self
+            .block_filters
+            .choose(rng)
+            .ok_or(GeneratorError::MissingVariables)?;
+
+        let connection_var = builder.get_or_create_random_connection(rng);
+        let msg_type_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadMsgType(msg_type_from_str("cfilter").unwrap()),
+        );
+        let cfilter_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadCFilter {
+                filter_type: 0, // basic
+                block_hash: block_hash.to_byte_array(),
+                filter: block_filter.filter.clone(),
+            },
+        );
+        builder.force_append(
+            vec![connection_var.index, msg_type_var.index, cfilter_var.index],
+            Operation::SendRawMessage,
+        );
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "CFilterGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn sends_cfilter_payload() {
+        let block_hash = BlockHash::from_byte_array([7; 32]);
+        let generator = CFilterGenerator::new(vec![(
+            block_hash,
+            BlockFilter {
+                filter: vec![1, 2, 3],
+                header: [0; 32],
+            },
+        )]);
+
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        generator
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+
+        let mut expected = vec![0];
+        expected.extend_from_slice(&[7; 32]);
+        expected.extend_from_slice(&[3, 1, 2, 3]);
+        assert_eq!(
+            test_utils::sent_raw_messages(&builder).last(),
+            Some(&("cfilter".to_string(), expected))
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use rand::{Rng, RngCore};
+
+use super::send_raw_message;
+use crate::{Generator, GeneratorResult, Operation, PerTestcaseMetadata, ProgramBuilder};
+
+/// Maximum number of 32-bit short ids in a generated sketch or `reconcildiff`
+const MAX_SHORT_IDS: usize = 16;
+
+/// `ErlayGenerator` negotiates transaction reconciliation (BIP-330) on a random connection with
+/// `sendtxrcncl`, followed by a reconciliation round (`reqrecon`, `sketch`, `reqsketchext`,
+/// `reconcildiff`). Each message of the round is skipped with a small probability, so the node
+/// also sees rounds that end early or skip a step.
+///
+/// Note that `sendtxrcncl` is only expected before `verack`, so the node is also exercised on
+/// late negotiation attempts.
+#[derive(Debug, Default)]
+pub struct ErlayGenerator;
+
+impl<R: RngCore> Generator<R> for ErlayGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let conn_var = builder.get_or_create_random_connection(rng);
+
+        let version = if rng.gen_bool(0.9) {
+            1
+        } else {
+            rng.gen_range(0..4)
+        };
+        let version_var = builder.force_append_expect_output(vec![], Operation::LoadSize(version));
+        let salt_var =
+            builder.force_append_expect_output(vec![], Operation::LoadNonce64(rng.r#gen()));
+        builder.force_append(
+            vec![conn_var.index, version_var.index, salt_var.index],
+            Operation::SendSendTxRcncl,
+        );
+
+        // reqrecon: uint16 set_size, uint16 q
+        if rng.gen_bool(0.8) {
+            let mut payload = rng.gen_range(0u16..=64).to_le_bytes().to_vec();
+            payload.extend_from_slice(&rng.r#gen::<u16>().to_le_bytes());
+            send_raw_message(builder, conn_var.index, "reqrecon", payload);
+        }
+
+        // sketch: vector<uint8> of 32-bit sketch elements
+        if rng.gen_bool(0.8) {
+            let payload = random_short_ids(rng, true);
+            send_raw_message(builder, conn_var.index, "sketch", payload);
+        }
+
+        // reqsketchext: empty, followed by the extension of the sketch
+        if rng.gen_bool(0.3) {
+            send_raw_message(builder, conn_var.index, "reqsketchext", vec![]);
+            let payload = random_short_ids(rng, true);
+            send_raw_message(builder, conn_var.index, "sketch", payload);
+        }
+
+        // reconcildiff: uint8 success, vector<uint32> ask_shortids
+        if rng.gen_bool(0.8) {
+            let mut payload = vec![u8::from(rng.gen_bool(0.5))];
+            payload.extend(random_short_ids(rng, false));
+            send_raw_message(builder, conn_var.index, "reconcildiff", payload);
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "ErlayGenerator"
+    }
+}
+
+/// Serialize a random number of random 32-bit short ids, prefixed with their count (or their
+/// length in bytes if `byte_vector` is set, as for the `vector<uint8>` of a `sketch`)
+fn random_short_ids<R: RngCore>(rng: &mut R, byte_vector: bool) -> Vec<u8> {
+    let num_short_ids = rng.gen_range(0..=MAX_SHORT_IDS);
+    let length = if byte_vector {
+        num_short_ids * 4
+    } else {
+        num_short_ids
+    };
+
+    // Lengths below 0xfd are encoded as a single byte compact size
+    let mut payload = vec![length as u8];
+    for _ in 0..num_short_ids {
+        payload.extend_from_slice(&rng.r#gen::<u32>().to_le_bytes());
+    }
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn negotiates_before_reconciliation() {
+        for seed in 0..16 {
+            let mut builder = test_utils::builder();
+            let mut rng = SmallRng::seed_from_u64(seed);
+            ErlayGenerator
+                .generate(&mut builder, &mut rng, None)
+                .expect("generation should succeed");
+
+            let messages = test_utils::sent_raw_messages(&builder);
+            let (command, payload) = &messages[0];
+            assert_eq!(command, "sendtxrcncl");
+            assert_eq!(payload.len(), 12);
+
+            for (command, payload) in &messages[1..] {
+                match command.as_str() {
+                    "reqrecon" => assert_eq!(payload.len(), 4),
+                    "sketch" => assert_eq!(payload.len(), 1 + payload[0] as usize),
+                    "reqsketchext" => assert!(payload.is_empty()),
+                    "reconcildiff" => assert_eq!(payload.len(), 2 + 4 * payload[1] as usize),
+                    _ => panic!("unexpected message: {command}"),
+                }
+            }
+        }
+    }
+}
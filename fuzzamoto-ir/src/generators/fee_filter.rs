@@ -0,0 +1,129 @@
+use rand::{Rng, RngCore, seq::SliceRandom};
+
+use super::{
+    GeneratorError,
+    tx::{OutputType, build_tx},
+};
+use crate::{Generator, GeneratorResult, Operation, PerTestcaseMetadata, ProgramBuilder};
+
+/// Value of each of the parent's outputs spent by the children
+const PARENT_AMOUNT: u64 = 10_000_000;
+/// Approximate virtual size of a transaction spending a single P2WSH-OP_TRUE output into a single
+/// P2WSH output
+const CHILD_VSIZE: u64 = 110;
+/// Fee rates (in sat/vB) the children are built with
+const CHILD_FEE_RATES: [u64; 5] = [0, 1, 10, 100, 1_000];
+/// Fee filters (in sat/kvB) around the fee rates of the children
+const FEE_FILTERS: [u64; 6] = [0, 1_000, 9_999, 10_000, 100_001, 1_000_000];
+
+/// `FeeFilterGenerator` sends a `feefilter` on a random connection, followed by a parent
+/// transaction and children with varying fee rates (0 - 1000 sat/vB) spending its outputs. The
+/// children are sent on a random connection and a `mempool` request is sent on the filtered
+/// connection afterwards, to exercise the fee threshold of transaction announcements.
+///
+/// Note that the values of the funding outputs are not known at generation time, so the fee of the
+/// parent is whatever is left after its outputs.
+#[derive(Debug, Default)]
+pub struct FeeFilterGenerator;
+
+impl<R: RngCore> Generator<R> for FeeFilterGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let funding_txos = builder.get_random_utxos(rng);
+        if funding_txos.is_empty() {
+            return Err(GeneratorError::MissingVariables);
+        }
+
+        let filtered_conn_var = builder.get_or_create_random_connection(rng);
+        let fee_filter = if rng.gen_bool(0.9) {
+            *FEE_FILTERS.choose(rng).unwrap()
+        } else {
+            rng.r#gen()
+        };
+        let fee_filter_var =
+            builder.force_append_expect_output(vec![], Operation::LoadAmount(fee_filter));
+        builder.force_append(
+            vec![filtered_conn_var.index, fee_filter_var.index],
+            Operation::SendFeeFilter,
+        );
+
+        let num_children = rng.gen_range(1..=CHILD_FEE_RATES.len());
+        let parent_outputs: Vec<_> = (0..num_children)
+            .map(|_| (PARENT_AMOUNT, OutputType::PayToWitnessScriptHash))
+            .collect();
+        let (parent_tx_var, parent_txos) =
+            build_tx(builder, rng, &funding_txos, 2, &parent_outputs)?;
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+        builder.force_append(vec![conn_var.index, parent_tx_var.index], Operation::SendTx);
+
+        for (parent_txo, fee_rate) in parent_txos
+            .iter()
+            .zip(CHILD_FEE_RATES.choose_multiple(rng, num_children))
+        {
+            let (child_tx_var, _) = build_tx(
+                builder,
+                rng,
+                std::slice::from_ref(parent_txo),
+                2,
+                &[(
+                    PARENT_AMOUNT - fee_rate * CHILD_VSIZE,
+                    OutputType::PayToWitnessScriptHash,
+                )],
+            )?;
+            builder.force_append(vec![conn_var.index, child_tx_var.index], Operation::SendTx);
+        }
+
+        builder.force_append(vec![filtered_conn_var.index], Operation::SendMempoolMsg);
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "FeeFilterGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ProgramContext, test_utils};
+    use bitcoin::Transaction;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn sends_fee_filter_before_transactions() {
+        for seed in 0..16 {
+            let mut builder = ProgramBuilder::new(ProgramContext {
+                num_nodes: 1,
+                num_connections: 2,
+                timestamp: 0,
+            });
+            let mut rng = SmallRng::seed_from_u64(seed);
+            // Fund all of the parent's outputs, so that the children pay the intended fees
+            test_utils::load_txo(&mut builder, PARENT_AMOUNT * CHILD_FEE_RATES.len() as u64);
+            FeeFilterGenerator
+                .generate(&mut builder, &mut rng, None)
+                .expect("generation should succeed");
+
+            let messages = test_utils::sent_raw_messages(&builder);
+            let (command, payload) = &messages[0];
+            assert_eq!(command, "feefilter");
+            assert_eq!(payload.len(), 8);
+            assert_eq!(messages.last().unwrap().0, "mempool");
+
+            let txs: Vec<Transaction> = test_utils::sent_messages(&builder, "tx");
+            assert!(txs.len() >= 2);
+            let parent = &txs[0];
+            for child in &txs[1..] {
+                assert_eq!(child.input[0].previous_output.txid, parent.compute_txid());
+                let fee = PARENT_AMOUNT - child.output[0].value.to_sat();
+                assert!(CHILD_FEE_RATES.contains(&(fee / CHILD_VSIZE)));
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use rand::{Rng, RngCore};
+
+use super::{
+    GeneratorError, send_tx,
+    tx::{OutputType, build_tx},
+};
+use crate::{
+    Generator, GeneratorResult, IndexedVariable, Operation, PerTestcaseMetadata, ProgramBuilder,
+};
+
+/// Value of the parent's output spent by the child(ren)
+const PARENT_AMOUNT: u64 = 100_000_000;
+/// Approximate virtual size of a transaction spending a single P2WSH-OP_TRUE output into a single
+/// P2WSH output
+const CHILD_VSIZE: u64 = 110;
+/// Fee rate (in sat/vB) of the (first) child
+const CHILD_FEE_RATE: u64 = 1_000;
+/// Fee rate (in sat/vB) of the child replacing the first child in package RBF mode
+const REPLACEMENT_FEE_RATE: u64 = 2_000;
+
+/// Fee bumping strategy exercised by the `FeeRateBumpGenerator`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeRateBumpMode {
+    /// A high-feerate child pays for a low-feerate parent (CPFP)
+    ChildPaysForParent,
+    /// Like `ChildPaysForParent`, but the child is then replaced by a child with an even higher
+    /// fee rate (package RBF)
+    PackageReplacement,
+}
+
+/// `FeeRateBumpGenerator` generates instructions for a low-feerate parent (1 sat/vB) and a
+/// high-feerate child (1000 sat/vB) spending the parent's P2WSH output. Both are sent and the
+/// parent is requested via `getdata` afterwards, to probe whether it made it into the mempool.
+///
+/// In `FeeRateBumpMode::PackageReplacement` mode, a second child (2000 sat/vB) spending the same
+/// parent output is sent after the first child.
+///
+/// Note that the values of the funding outputs are not known at generation time, so the parent
+/// only pays ~1 sat/vB if they are worth about `PARENT_AMOUNT` (the parent's fee is whatever is
+/// left after its `PARENT_AMOUNT` output).
+#[derive(Debug, Default)]
+pub struct FeeRateBumpGenerator {
+    /// Mode to use, chosen at random for every generated package if `None`
+    mode: Option<FeeRateBumpMode>,
+}
+
+impl FeeRateBumpGenerator {
+    pub fn new(mode: FeeRateBumpMode) -> Self {
+        Self { mode: Some(mode) }
+    }
+}
+
+fn build_inventory(
+    builder: &mut ProgramBuilder,
+    tx_var: &IndexedVariable,
+    operation: Operation,
+) -> IndexedVariable {
+    let mut_inventory_var =
+        builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+    builder.force_append(vec![mut_inventory_var.index, tx_var.index], operation);
+    builder.force_append_expect_output(vec![mut_inventory_var.index], Operation::EndBuildInventory)
+}
+
+impl<R: RngCore> Generator<R> for FeeRateBumpGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let funding_txos = builder.get_random_utxos(rng);
+        if funding_txos.is_empty() {
+            return Err(GeneratorError::MissingVariables);
+        };
+
+        let mode = self.mode.unwrap_or_else(|| {
+            if rng.gen_bool(0.5) {
+                FeeRateBumpMode::ChildPaysForParent
+            } else {
+                FeeRateBumpMode::PackageReplacement
+            }
+        });
+
+        let (parent_tx_var, parent_outputs) = build_tx(
+            builder,
+            rng,
+            &funding_txos,
+            2,
+            &[(PARENT_AMOUNT, OutputType::PayToWitnessScriptHash)],
+        )?;
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+        send_tx(builder, &conn_var, &parent_tx_var);
+
+        let (child_tx_var, _) = build_tx(
+            builder,
+            rng,
+            &parent_outputs,
+            2,
+            &[(
+                PARENT_AMOUNT - CHILD_FEE_RATE * CHILD_VSIZE,
+                OutputType::PayToWitnessScriptHash,
+            )],
+        )?;
+        send_tx(builder, &conn_var, &child_tx_var);
+
+        if mode == FeeRateBumpMode::PackageReplacement {
+            let (replacement_tx_var, _) = build_tx(
+                builder,
+                rng,
+                &parent_outputs,
+                2,
+                &[(
+                    PARENT_AMOUNT - REPLACEMENT_FEE_RATE * CHILD_VSIZE,
+                    OutputType::PayToWitnessScriptHash,
+                )],
+            )?;
+            send_tx(builder, &conn_var, &replacement_tx_var);
+        }
+
+        // Probe whether the parent was accepted
+        let probe_inventory_var = build_inventory(builder, &parent_tx_var, Operation::AddTxidInv);
+        builder.force_append(
+            vec![conn_var.index, probe_inventory_var.index],
+            Operation::SendGetData,
+        );
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        match self.mode {
+            None => "FeeRateBumpGenerator",
+            Some(FeeRateBumpMode::ChildPaysForParent) => "CpfpFeeRateBumpGenerator",
+            Some(FeeRateBumpMode::PackageReplacement) => "PackageRbfFeeRateBumpGenerator",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoin::Transaction;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    fn generate(mode: FeeRateBumpMode) -> ProgramBuilder {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        // Fund the parent with a bit more than `PARENT_AMOUNT`, so that it pays a low fee
+        test_utils::load_txo(&mut builder, PARENT_AMOUNT + 1_000);
+        FeeRateBumpGenerator::new(mode)
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+        builder
+    }
+
+    #[test]
+    fn child_pays_for_parent() {
+        let builder = generate(FeeRateBumpMode::ChildPaysForParent);
+        let txs = test_utils::sent_messages::<Transaction>(&builder, "tx");
+        assert_eq!(txs.len(), 2);
+        let last = test_utils::sent_raw_messages(&builder).pop().unwrap();
+        assert_eq!(last.0, "getdata");
+
+        let (parent, child) = (&txs[0], &txs[1]);
+        assert_eq!(child.input[0].previous_output.txid, parent.compute_txid());
+        assert_eq!(
+            parent.output[0].value.to_sat() - child.output[0].value.to_sat(),
+            CHILD_FEE_RATE * CHILD_VSIZE
+        );
+    }
+
+    #[test]
+    fn package_replacement_sends_higher_fee_child() {
+        let builder = generate(FeeRateBumpMode::PackageReplacement);
+        let txs = test_utils::sent_messages::<Transaction>(&builder, "tx");
+        assert_eq!(txs.len(), 3);
+
+        let (child, replacement) = (&txs[1], &txs[2]);
+        assert_eq!(
+            child.input[0].previous_output,
+            replacement.input[0].previous_output
+        );
+        assert!(replacement.output[0].value < child.output[0].value);
+    }
+}
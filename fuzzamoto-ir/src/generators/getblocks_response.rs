@@ -0,0 +1,226 @@
+use bitcoin::consensus::encode::{VarInt, serialize};
+use rand::{Rng, RngCore};
+
+use super::{GeneratorError, send_raw_message};
+use crate::{
+    Generator, GeneratorResult, Header, IndexedVariable, Operation, PerTestcaseMetadata,
+    ProgramBuilder,
+};
+
+/// Maximum number of block hashes in an `inv` sent in response to `getblocks`
+const MAX_BLOCKS_TO_ANNOUNCE: usize = 500;
+/// Maximum number of headers in a `headers` message
+const MAX_HEADERS_RESULTS: usize = 2000;
+
+/// Pick a random window (sorted by height) of at most `max` headers and return it together with
+/// the header following the window (if any).
+fn header_window<'a, R: RngCore>(
+    headers: &'a [Header],
+    rng: &mut R,
+    max: usize,
+) -> Result<(Vec<&'a Header>, Option<&'a Header>), GeneratorError> {
+    if headers.is_empty() {
+        return Err(GeneratorError::MissingVariables);
+    }
+
+    let mut sorted: Vec<&Header> = headers.iter().collect();
+    sorted.sort_by_key(|h| h.height);
+
+    let start = rng.gen_range(0..sorted.len());
+    let len = rng.gen_range(1..=max.min(sorted.len() - start));
+    let next = sorted.get(start + len).copied();
+
+    Ok((sorted[start..start + len].to_vec(), next))
+}
+
+fn load_header(builder: &mut ProgramBuilder, header: &Header) -> IndexedVariable {
+    builder.force_append_expect_output(
+        vec![],
+        Operation::LoadHeader {
+            prev: header.prev,
+            merkle_root: header.merkle_root,
+            nonce: header.nonce,
+            bits: header.bits,
+            time: header.time,
+            version: header.version,
+            height: header.height,
+        },
+    )
+}
+
+/// `GetBlocksResponseGenerator` mimics the response to a `getblocks` request, i.e. an `inv`
+/// announcing up to 500 consecutive blocks from the context, followed by the header of the next
+/// block (to exercise the continuation logic).
+pub struct GetBlocksResponseGenerator {
+    headers: Vec<Header>,
+}
+
+impl GetBlocksResponseGenerator {
+    pub fn new(headers: Vec<Header>) -> Self {
+        Self { headers }
+    }
+}
+
+impl<R: RngCore> Generator<R> for GetBlocksResponseGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let (window, next) = header_window(&self.headers, rng, MAX_BLOCKS_TO_ANNOUNCE)?;
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+
+        let header_vars: Vec<_> = window
+            .into_iter()
+            .map(|header| load_header(builder, header))
+            .collect();
+
+        let mut_inventory_var =
+            builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+        for header_var in header_vars {
+            builder.force_append(
+                vec![mut_inventory_var.index, header_var.index],
+                Operation::AddBlockHeaderInv,
+            );
+        }
+        let inventory_var = builder.force_append_expect_output(
+            vec![mut_inventory_var.index],
+            Operation::EndBuildInventory,
+        );
+        builder.force_append(
+            vec![conn_var.index, inventory_var.index],
+            Operation::SendInv,
+        );
+
+        if let Some(next) = next {
+            let header_var = load_header(builder, next);
+            builder.force_append(
+                vec![conn_var.index, header_var.index],
+                Operation::SendHeader,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "GetBlocksResponseGenerator"
+    }
+}
+
+/// `GetHeadersResponseGenerator` mimics the response to a `getheaders` request, i.e. a `headers`
+/// message containing up to 2000 consecutive headers from the context.
+pub struct GetHeadersResponseGenerator {
+    headers: Vec<Header>,
+}
+
+impl GetHeadersResponseGenerator {
+    pub fn new(headers: Vec<Header>) -> Self {
+        Self { headers }
+    }
+}
+
+impl<R: RngCore> Generator<R> for GetHeadersResponseGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let (window, _) = header_window(&self.headers, rng, MAX_HEADERS_RESULTS)?;
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+
+        // Each header in a `headers` message is followed by a (zero) transaction count
+        let mut bytes = serialize(&VarInt(window.len() as u64));
+        for header in window {
+            bytes.extend(serialize(&header.to_bitcoin_header()));
+            bytes.push(0);
+        }
+        send_raw_message(builder, conn_var.index, "headers", bytes);
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "GetHeadersResponseGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoin::p2p::message_blockdata::Inventory;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    fn test_headers(count: u32) -> Vec<Header> {
+        (0..count)
+            .map(|height| Header {
+                prev: [height as u8; 32],
+                merkle_root: [0u8; 32],
+                nonce: height,
+                bits: 0x207fffff,
+                time: 1_296_688_602 + height,
+                version: 4,
+                height,
+            })
+            .collect()
+    }
+
+    fn generate<G: Generator<SmallRng>>(generator: &G) -> ProgramBuilder {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        generator
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+        builder
+    }
+
+    #[test]
+    fn getblocks_response_compiles_to_inv() {
+        let headers = test_headers(10);
+        let builder = generate(&GetBlocksResponseGenerator::new(headers.clone()));
+        let commands: Vec<_> = test_utils::sent_raw_messages(&builder)
+            .into_iter()
+            .map(|(command, _)| command)
+            .collect();
+        assert_eq!(commands[0], "inv");
+        assert!(commands[1..].iter().all(|c| c == "headers"));
+
+        // The inventory announces consecutive blocks of the context by their hash
+        let inventory = test_utils::sent_messages::<Vec<Inventory>>(&builder, "inv").remove(0);
+        let block_hashes: Vec<_> = headers.iter().map(Header::block_hash).collect();
+        let first = inventory
+            .first()
+            .and_then(|inv| {
+                block_hashes
+                    .iter()
+                    .position(|hash| *inv == Inventory::Block(*hash))
+            })
+            .expect("first inventory entry should be a context block");
+        for (inv, hash) in inventory.iter().zip(&block_hashes[first..]) {
+            assert_eq!(*inv, Inventory::Block(*hash));
+        }
+    }
+
+    #[test]
+    fn getheaders_response_compiles_to_headers() {
+        let builder = generate(&GetHeadersResponseGenerator::new(test_headers(10)));
+        let commands: Vec<_> = test_utils::sent_raw_messages(&builder)
+            .into_iter()
+            .map(|(command, _)| command)
+            .collect();
+        assert_eq!(commands, vec!["headers".to_string()]);
+    }
+
+    #[test]
+    fn empty_context_headers_are_missing_variables() {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let result = GetBlocksResponseGenerator::new(vec![]).generate(&mut builder, &mut rng, None);
+        assert!(matches!(result, Err(GeneratorError::MissingVariables)));
+    }
+}
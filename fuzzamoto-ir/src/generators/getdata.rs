@@ -1,11 +1,14 @@
-use rand::{RngCore, seq::SliceRandom};
+use bitcoin::{
+    Wtxid, consensus::encode::serialize, hashes::Hash, p2p::message_blockdata::Inventory,
+};
+use rand::{Rng, RngCore, seq::SliceRandom};
 
 use crate::{
     Generator, GeneratorResult, InstructionContext, Operation, PerTestcaseMetadata, ProgramBuilder,
     Variable,
 };
 
-use super::GeneratorError;
+use super::{GeneratorError, send_raw_message};
 
 /// `GetDataGenerator` generates `SendGetData` instructions into a global context
 #[derive(Default)]
@@ -37,6 +40,67 @@ impl<R: RngCore> Generator<R> for GetDataGenerator {
     }
 }
 
+/// `NotFoundGenerator` generates a `SendNotFound` instruction for an inventory of existing
+/// transactions and blocks, i.e. it answers a (possibly never sent) `getdata` with `notfound`
+#[derive(Default)]
+pub struct NotFoundGenerator;
+
+impl<R: RngCore> Generator<R> for NotFoundGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let tx_vars = builder.get_random_variables(rng, Variable::ConstTx);
+        let block_vars = builder.get_random_variables(rng, Variable::Block);
+        if tx_vars.is_empty() && block_vars.is_empty() {
+            return Err(GeneratorError::MissingVariables);
+        }
+
+        let mut_inventory_var =
+            builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+        for tx_var in tx_vars {
+            builder.force_append(
+                vec![mut_inventory_var.index, tx_var.index],
+                [
+                    Operation::AddTxidWithWitnessInv,
+                    Operation::AddTxidInv,
+                    Operation::AddWtxidInv,
+                ]
+                .choose(rng)
+                .unwrap()
+                .clone(),
+            );
+        }
+        for block_var in block_vars {
+            builder.force_append(
+                vec![mut_inventory_var.index, block_var.index],
+                [Operation::AddBlockWithWitnessInv, Operation::AddBlockInv]
+                    .choose(rng)
+                    .unwrap()
+                    .clone(),
+            );
+        }
+        let inventory_var = builder.force_append_expect_output(
+            vec![mut_inventory_var.index],
+            Operation::EndBuildInventory,
+        );
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+        builder.force_append(
+            vec![conn_var.index, inventory_var.index],
+            Operation::SendNotFound,
+        );
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "NotFoundGenerator"
+    }
+}
+
 /// `InventoryGenerator` generates `Add*Inv` instructions, adding new inventory
 /// elements to existing inventory variables
 #[derive(Default)]
@@ -95,3 +159,127 @@ impl<R: RngCore> Generator<R> for InventoryGenerator {
         InstructionContext::Inventory
     }
 }
+
+/// `RuntimeTxInventoryGenerator` generates `inv` or `getdata` messages for transactions that were
+/// observed in the mempool of the node under test (see `ObservedNodeState`)
+#[derive(Default)]
+pub struct RuntimeTxInventoryGenerator;
+
+impl<R: RngCore> Generator<R> for RuntimeTxInventoryGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let txids = meta
+            .and_then(|meta| meta.runtime())
+            .map(|runtime| runtime.txids.as_slice())
+            .unwrap_or_default();
+        if txids.is_empty() {
+            return Err(GeneratorError::MissingVariables);
+        }
+
+        let num_txids = rng.gen_range(1..=txids.len());
+        let inventory: Vec<Inventory> = txids
+            .choose_multiple(rng, num_txids)
+            .map(|txid| {
+                if rng.gen_bool(0.5) {
+                    Inventory::Transaction(*txid)
+                } else {
+                    // The wtxid is unknown, so the txid is used instead (which matches the wtxid
+                    // for transactions without witness data)
+                    Inventory::WTx(Wtxid::from_byte_array(txid.to_byte_array()))
+                }
+            })
+            .collect();
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+        let command = *["inv", "getdata"].choose(rng).unwrap();
+        send_raw_message(builder, conn_var.index, command, serialize(&inventory));
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "RuntimeTxInventoryGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ObservedNodeState, test_utils};
+    use bitcoin::Txid;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn not_found_announces_existing_txs() {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        assert!(matches!(
+            NotFoundGenerator.generate(&mut builder, &mut rng, None),
+            Err(GeneratorError::MissingVariables)
+        ));
+
+        crate::TxoGenerator::new(vec![crate::Txo {
+            outpoint: ([1; 32], 0),
+            value: 100_000_000,
+            script_pubkey: vec![0x51],
+            spending_script_sig: vec![],
+            spending_witness: vec![],
+        }])
+        .generate(&mut builder, &mut rng, None)
+        .unwrap();
+        crate::SingleTxGenerator
+            .generate(&mut builder, &mut rng, None)
+            .unwrap();
+        NotFoundGenerator
+            .generate(&mut builder, &mut rng, None)
+            .unwrap();
+
+        let (command, payload) = test_utils::sent_raw_messages(&builder)
+            .pop()
+            .expect("notfound should be sent last");
+        assert_eq!(command, "notfound");
+        let inventory: Vec<Inventory> = bitcoin::consensus::deserialize(&payload).unwrap();
+        assert_eq!(inventory.len(), 1);
+    }
+
+    #[test]
+    fn runtime_tx_inventory_requires_txids() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let result =
+            RuntimeTxInventoryGenerator.generate(&mut test_utils::builder(), &mut rng, None);
+        assert!(matches!(result, Err(GeneratorError::MissingVariables)));
+    }
+
+    #[test]
+    fn runtime_tx_inventory_uses_known_txids() {
+        let txid = Txid::from_byte_array([7u8; 32]);
+        let mut meta = PerTestcaseMetadata::new();
+        meta.add_runtime(ObservedNodeState {
+            txids: vec![txid],
+            ..Default::default()
+        });
+
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        RuntimeTxInventoryGenerator
+            .generate(&mut builder, &mut rng, Some(&meta))
+            .unwrap();
+
+        let program = builder.finalize().unwrap();
+        let bytes = program
+            .instructions
+            .iter()
+            .find_map(|instruction| match &instruction.operation {
+                Operation::LoadBytes(bytes) => Some(bytes.clone()),
+                _ => None,
+            })
+            .unwrap();
+        let inventory: Vec<Inventory> = bitcoin::consensus::deserialize(&bytes).unwrap();
+        assert_eq!(inventory.len(), 1);
+        assert!(serialize(&inventory[0]).ends_with(&txid.to_byte_array()));
+    }
+}
@@ -0,0 +1,342 @@
+use rand::{Rng, RngCore, seq::IteratorRandom};
+
+use super::{
+    GeneratorError,
+    block::{build_block, build_block_from_header},
+    tx::{OutputType, build_tx},
+};
+use crate::{
+    CoinbaseTxGenerator, Generator, GeneratorResult, IndexedVariable, Operation,
+    PerTestcaseMetadata, ProgramBuilder, Variable,
+};
+
+/// Maximum block subsidy (in satoshis), any coinbase paying more than this is invalid
+const MAX_SUBSIDY: u64 = 50 * 100_000_000;
+/// Minimum value of the txo spent twice in `DoubleSpend` blocks, so that both spending transactions
+/// can pay a distinct non-dust amount
+const MIN_DOUBLE_SPENT_VALUE: u64 = 1_000;
+
+/// The way in which a block generated by `InvalidBlockGenerator` is invalid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockInvalidityType {
+    /// The header commits to a random merkle root
+    WrongMerkleRoot,
+    /// The header does not satisfy its proof-of-work target
+    InvalidProofOfWork,
+    /// Two transactions in the block spend the same output
+    DoubleSpend,
+    /// The coinbase pays out more than the block subsidy
+    ExcessiveCoinbaseValue,
+    /// The same transaction is included twice
+    DuplicateTransactions,
+}
+
+impl BlockInvalidityType {
+    pub const ALL: [BlockInvalidityType; 5] = [
+        BlockInvalidityType::WrongMerkleRoot,
+        BlockInvalidityType::InvalidProofOfWork,
+        BlockInvalidityType::DoubleSpend,
+        BlockInvalidityType::ExcessiveCoinbaseValue,
+        BlockInvalidityType::DuplicateTransactions,
+    ];
+}
+
+/// `InvalidBlockGenerator` generates instructions for building a block that is invalid in exactly
+/// one way (see `BlockInvalidityType`) and sending it to a node.
+///
+/// The block is built like a valid block (see `BlockGenerator`), the invalidity is then introduced
+/// either through the block's transactions or by corrupting the built block.
+pub struct InvalidBlockGenerator {
+    invalidity: BlockInvalidityType,
+    coinbase_generator: CoinbaseTxGenerator,
+}
+
+impl InvalidBlockGenerator {
+    pub fn new(invalidity: BlockInvalidityType) -> Self {
+        Self {
+            invalidity,
+            coinbase_generator: CoinbaseTxGenerator::default(),
+        }
+    }
+}
+
+impl<R: RngCore> Generator<R> for InvalidBlockGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        build_invalid_block(
+            &self.coinbase_generator,
+            self.invalidity,
+            builder,
+            rng,
+            meta,
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        match self.invalidity {
+            BlockInvalidityType::WrongMerkleRoot => "InvalidMerkleRootBlockGenerator",
+            BlockInvalidityType::InvalidProofOfWork => "InvalidPowBlockGenerator",
+            BlockInvalidityType::DoubleSpend => "DoubleSpendBlockGenerator",
+            BlockInvalidityType::ExcessiveCoinbaseValue => "ExcessiveCoinbaseBlockGenerator",
+            BlockInvalidityType::DuplicateTransactions => "DuplicateTxBlockGenerator",
+        }
+    }
+}
+
+/// `MixedValidityBlockGenerator` generates instructions for either a valid block or a block that
+/// is invalid in a randomly chosen way.
+#[derive(Default)]
+pub struct MixedValidityBlockGenerator {
+    coinbase_generator: CoinbaseTxGenerator,
+}
+
+impl<R: RngCore> Generator<R> for MixedValidityBlockGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let choice = rng.gen_range(0..=BlockInvalidityType::ALL.len());
+        match BlockInvalidityType::ALL.get(choice) {
+            Some(invalidity) => {
+                build_invalid_block(&self.coinbase_generator, *invalidity, builder, rng, meta)
+            }
+            None => {
+                let header_var = choose_header(builder, rng)?;
+                build_block_from_header(
+                    &self.coinbase_generator,
+                    builder,
+                    rng,
+                    header_var.index,
+                    meta,
+                )?;
+                Ok(())
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "MixedValidityBlockGenerator"
+    }
+}
+
+fn choose_header<R: RngCore>(
+    builder: &ProgramBuilder,
+    rng: &mut R,
+) -> Result<IndexedVariable, GeneratorError> {
+    if rng.gen_bool(0.5) {
+        builder.get_random_variable(rng, Variable::Header)
+    } else {
+        builder.get_nearest_sent_header()
+    }
+    .ok_or(GeneratorError::MissingVariables)
+}
+
+/// Txo variables (available in the current scope) loaded by `LoadTxo`, together with their values
+fn loaded_txos(builder: &ProgramBuilder) -> Vec<(IndexedVariable, u64)> {
+    let mut txos = Vec::new();
+    let mut var_count = 0;
+    for instruction in &builder.instructions {
+        if let Operation::LoadTxo { value, .. } = instruction.operation
+            && let Some(txo_var) = builder.get_variable(var_count)
+        {
+            txos.push((txo_var, value));
+        }
+        var_count += instruction.operation.num_outputs();
+        var_count += instruction.operation.num_inner_outputs();
+    }
+    txos
+}
+
+fn build_invalid_block<R: RngCore>(
+    coinbase_generator: &CoinbaseTxGenerator,
+    invalidity: BlockInvalidityType,
+    builder: &mut ProgramBuilder,
+    rng: &mut R,
+    meta: Option<&PerTestcaseMetadata>,
+) -> GeneratorResult {
+    let header_var = choose_header(builder, rng)?;
+    let time_var = builder
+        .get_random_variable(rng, Variable::Time)
+        .ok_or(GeneratorError::MissingVariables)?;
+
+    let mut tx_vars = builder.get_random_variables(rng, Variable::ConstTx);
+    tx_vars.sort_by_key(|tx| tx.index);
+
+    match invalidity {
+        BlockInvalidityType::DoubleSpend => {
+            let (txo_var, value) = loaded_txos(builder)
+                .into_iter()
+                .filter(|(_, value)| *value >= MIN_DOUBLE_SPENT_VALUE)
+                .choose(rng)
+                .ok_or(GeneratorError::MissingVariables)?;
+            // Two conflicting transactions paying different amounts (so that their txids differ),
+            // neither of which spends more than the txo's value
+            let amount = rng.gen_range(value / 2..value);
+            for amount in [amount, amount - 1] {
+                let (tx_var, _) = build_tx(
+                    builder,
+                    rng,
+                    std::slice::from_ref(&txo_var),
+                    2,
+                    &[(amount, OutputType::PayToWitnessScriptHash)],
+                )?;
+                tx_vars.push(tx_var);
+            }
+        }
+        BlockInvalidityType::DuplicateTransactions => {
+            let duplicate = builder
+                .get_random_variable(rng, Variable::ConstTx)
+                .ok_or(GeneratorError::MissingVariables)?;
+            tx_vars.retain(|tx| tx.index != duplicate.index);
+            let position = tx_vars.partition_point(|tx| tx.index < duplicate.index);
+            tx_vars.insert(position, duplicate.clone());
+            tx_vars.insert(position, duplicate);
+        }
+        _ => {}
+    }
+
+    let block_vars = build_block(
+        coinbase_generator,
+        builder,
+        rng,
+        header_var.index,
+        time_var.index,
+        &tx_vars,
+        meta,
+    )?;
+    let mut block_var = block_vars[1].clone();
+
+    let corruption = match invalidity {
+        BlockInvalidityType::WrongMerkleRoot => {
+            Some(Operation::CorruptBlockMerkleRoot(rng.r#gen()))
+        }
+        BlockInvalidityType::InvalidProofOfWork => Some(Operation::CorruptBlockProofOfWork),
+        BlockInvalidityType::ExcessiveCoinbaseValue => Some(Operation::CorruptBlockCoinbaseValue(
+            rng.gen_range(MAX_SUBSIDY + 1..=2 * MAX_SUBSIDY),
+        )),
+        BlockInvalidityType::DoubleSpend | BlockInvalidityType::DuplicateTransactions => None,
+    };
+    if let Some(corruption) = corruption {
+        block_var = builder.force_append_expect_output(vec![block_var.index], corruption);
+    }
+
+    let conn_var = builder.get_or_create_random_connection(rng);
+    builder.force_append(vec![conn_var.index, block_var.index], Operation::SendBlock);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{P2TRTxoGenerator, test_utils};
+    use bitcoin::{Amount, Block};
+    use rand::{SeedableRng, rngs::SmallRng};
+    use std::collections::HashSet;
+
+    fn setup(rng: &mut SmallRng) -> ProgramBuilder {
+        let mut builder = test_utils::builder_on_genesis();
+        P2TRTxoGenerator
+            .generate(&mut builder, rng, None)
+            .expect("txo generation should succeed");
+        builder
+    }
+
+    fn generate_invalid_block(invalidity: BlockInvalidityType) -> Block {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut builder = setup(&mut rng);
+        // Ensure there are transactions available for the duplicate tx variant
+        InvalidBlockGenerator::new(BlockInvalidityType::DoubleSpend)
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+        InvalidBlockGenerator::new(invalidity)
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+        test_utils::sent_messages::<Block>(&builder, "block")
+            .pop()
+            .expect("a block should be sent")
+    }
+
+    #[test]
+    fn wrong_merkle_root() {
+        let block = generate_invalid_block(BlockInvalidityType::WrongMerkleRoot);
+        assert!(!block.check_merkle_root());
+    }
+
+    #[test]
+    fn invalid_proof_of_work() {
+        let block = generate_invalid_block(BlockInvalidityType::InvalidProofOfWork);
+        assert!(block.check_merkle_root());
+        if cfg!(feature = "reduced_pow") {
+            assert_ne!(block.block_hash().as_raw_hash()[31] & 0x80, 0);
+        } else {
+            assert!(block.header.validate_pow(block.header.target()).is_err());
+        }
+    }
+
+    #[test]
+    fn double_spend() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut builder = setup(&mut rng);
+        let txo_value = builder
+            .instructions
+            .iter()
+            .find_map(|instruction| match instruction.operation {
+                Operation::LoadTxo { value, .. } => Some(Amount::from_sat(value)),
+                _ => None,
+            })
+            .expect("setup loads a txo");
+        InvalidBlockGenerator::new(BlockInvalidityType::DoubleSpend)
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+        let block = test_utils::sent_messages::<Block>(&builder, "block")
+            .pop()
+            .expect("a block should be sent");
+
+        let mut spent = HashSet::new();
+        let has_double_spend = block.txdata[1..]
+            .iter()
+            .flat_map(|tx| tx.input.iter())
+            .any(|input| !spent.insert(input.previous_output));
+        assert!(has_double_spend);
+
+        // The block is only invalid due to the double spend, i.e. neither spend is overpaying
+        for tx in &block.txdata[1..] {
+            let output_value: Amount = tx.output.iter().map(|output| output.value).sum();
+            assert!(output_value < txo_value);
+        }
+    }
+
+    #[test]
+    fn excessive_coinbase_value() {
+        let block = generate_invalid_block(BlockInvalidityType::ExcessiveCoinbaseValue);
+        assert!(block.check_merkle_root());
+        let coinbase_value: Amount = block.txdata[0].output.iter().map(|o| o.value).sum();
+        assert!(coinbase_value > Amount::from_sat(MAX_SUBSIDY));
+    }
+
+    #[test]
+    fn duplicate_transactions() {
+        let block = generate_invalid_block(BlockInvalidityType::DuplicateTransactions);
+        let txids: HashSet<_> = block.txdata.iter().map(|tx| tx.compute_txid()).collect();
+        assert!(txids.len() < block.txdata.len());
+    }
+
+    #[test]
+    fn invalid_blocks_require_header() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut builder = test_utils::builder();
+        for invalidity in BlockInvalidityType::ALL {
+            assert!(matches!(
+                InvalidBlockGenerator::new(invalidity).generate(&mut builder, &mut rng, None),
+                Err(GeneratorError::MissingVariables)
+            ));
+        }
+    }
+}
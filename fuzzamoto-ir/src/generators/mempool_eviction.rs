@@ -0,0 +1,129 @@
+use rand::{Rng, RngCore};
+
+use super::{
+    GeneratorError,
+    tx::{OutputType, build_tx},
+};
+use crate::{
+    Generator, GeneratorResult, IndexedVariable, Operation, PerTestcaseMetadata, ProgramBuilder,
+};
+
+/// Value of the first output in the chain
+const CHAIN_START_AMOUNT: u64 = 100_000_000;
+/// Fee paid by each transaction in the chain
+const CHAIN_FEE: u64 = 1_000;
+/// Fee paid by the final transaction spending the tip of the chain
+const HIGH_FEE: u64 = 10_000_000;
+
+/// `MempoolEvictionGenerator` generates instructions for a transaction chain exceeding the default
+/// ancestor/descendant limit (25) of Bitcoin Core, followed by a high-feerate transaction spending
+/// the tip of the chain. Afterwards, the first (lowest-feerate) transaction of the chain is
+/// requested via `getdata` to probe whether it is still in the mempool.
+#[derive(Default)]
+pub struct MempoolEvictionGenerator;
+
+impl<R: RngCore> Generator<R> for MempoolEvictionGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let mut funding_txos = builder.get_random_utxos(rng);
+        if funding_txos.is_empty() {
+            return Err(GeneratorError::MissingVariables);
+        };
+
+        let chain_len = rng.gen_range(26..50);
+        let mut tx_vars = Vec::new();
+        let mut amount = CHAIN_START_AMOUNT;
+        for _ in 0..chain_len {
+            let (tx_var, outputs) = build_tx(
+                builder,
+                rng,
+                &funding_txos,
+                2,
+                &[(amount, OutputType::PayToWitnessScriptHash)],
+            )?;
+            tx_vars.push(tx_var);
+            funding_txos = outputs;
+            amount -= CHAIN_FEE;
+        }
+
+        // High-feerate transaction spending the tip of the chain
+        let (high_fee_tx_var, _) = build_tx(
+            builder,
+            rng,
+            &funding_txos,
+            2,
+            &[(amount - HIGH_FEE, OutputType::PayToWitnessScriptHash)],
+        )?;
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+
+        let build_inventory = |builder: &mut ProgramBuilder, tx_var: &IndexedVariable| {
+            let mut_inventory_var =
+                builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+            builder.force_append(
+                vec![mut_inventory_var.index, tx_var.index],
+                Operation::AddWtxidInv,
+            );
+            builder.force_append_expect_output(
+                vec![mut_inventory_var.index],
+                Operation::EndBuildInventory,
+            )
+        };
+
+        for tx_var in tx_vars.iter().chain(std::iter::once(&high_fee_tx_var)) {
+            let const_inventory_var = build_inventory(builder, tx_var);
+            builder.force_append(
+                vec![conn_var.index, const_inventory_var.index],
+                Operation::SendInv,
+            );
+            builder.force_append(vec![conn_var.index, tx_var.index], Operation::SendTx);
+        }
+
+        // Probe whether the first transaction of the chain got evicted
+        let probe_inventory_var = build_inventory(builder, &tx_vars[0]);
+        builder.force_append(
+            vec![conn_var.index, probe_inventory_var.index],
+            Operation::SendGetData,
+        );
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "MempoolEvictionGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{P2TRTxoGenerator, test_utils};
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn mempool_eviction_sends_long_chain() {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        P2TRTxoGenerator
+            .generate(&mut builder, &mut rng, None)
+            .expect("txo generation should succeed");
+        MempoolEvictionGenerator
+            .generate(&mut builder, &mut rng, None)
+            .expect("generation should succeed");
+
+        let txs = test_utils::sent_messages::<bitcoin::Transaction>(&builder, "tx");
+        assert!(txs.len() >= 26);
+    }
+
+    #[test]
+    fn mempool_eviction_requires_utxos() {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let result = MempoolEvictionGenerator.generate(&mut builder, &mut rng, None);
+        assert!(matches!(result, Err(GeneratorError::MissingVariables)));
+    }
+}
@@ -0,0 +1,104 @@
+use rand::{Rng, RngCore, seq::SliceRandom};
+
+use crate::{
+    Generator, GeneratorResult, Operation, PerTestcaseMetadata, ProgramBuilder, Variable,
+    bloom::MAX_HASH_FUNCS,
+};
+
+/// `MempoolRequestGenerator` generates a `mempool` request, optionally preceded by a `filterload`
+/// (which enables `mempool` responses for peers without whitelisting) and followed by a `getdata`
+/// for transactions expected to be announced in the node's `inv` response.
+#[derive(Debug, Default)]
+pub struct MempoolRequestGenerator;
+
+impl<R: RngCore> Generator<R> for MempoolRequestGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let conn_var = builder.get_or_create_random_connection(rng);
+
+        if rng.gen_bool(0.5) {
+            // A filter with all bits set matches every transaction in the mempool
+            let size = rng.gen_range(1..=64);
+            let filter_var = builder.force_append_expect_output(
+                vec![],
+                Operation::LoadFilterLoad {
+                    filter: vec![0xff; size],
+                    hash_funcs: rng.gen_range(1..MAX_HASH_FUNCS),
+                    tweak: rng.gen_range(0..=u32::MAX),
+                    flags: rng.gen_range(0..=2),
+                },
+            );
+            builder.force_append(
+                vec![conn_var.index, filter_var.index],
+                Operation::SendFilterLoad,
+            );
+        }
+
+        builder.force_append(vec![conn_var.index], Operation::SendMempoolMsg);
+
+        // Request the transactions the node is expected to announce in response
+        let tx_vars = builder.get_random_variables(rng, Variable::ConstTx);
+        if tx_vars.is_empty() {
+            return Ok(());
+        }
+
+        let mut_inventory_var =
+            builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+        for tx_var in tx_vars {
+            builder.force_append(
+                vec![mut_inventory_var.index, tx_var.index],
+                [
+                    Operation::AddTxidInv,
+                    Operation::AddTxidWithWitnessInv,
+                    Operation::AddWtxidInv,
+                ]
+                .choose(rng)
+                .unwrap()
+                .clone(),
+            );
+        }
+        let const_inventory_var = builder.force_append_expect_output(
+            vec![mut_inventory_var.index],
+            Operation::EndBuildInventory,
+        );
+        builder.force_append(
+            vec![conn_var.index, const_inventory_var.index],
+            Operation::SendGetData,
+        );
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "MempoolRequestGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn sends_empty_mempool_message() {
+        for seed in 0..16 {
+            let mut builder = test_utils::builder();
+            let mut rng = SmallRng::seed_from_u64(seed);
+            MempoolRequestGenerator
+                .generate(&mut builder, &mut rng, None)
+                .unwrap();
+
+            let messages = test_utils::sent_raw_messages(&builder);
+
+            let (command, payload) = messages.last().unwrap();
+            assert_eq!(command, "mempool");
+            assert!(payload.is_empty());
+            assert!(messages.len() == 1 || messages[0].0 == "filterload");
+        }
+    }
+}
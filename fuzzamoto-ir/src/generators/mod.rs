@@ -1,36 +1,67 @@
 pub mod address;
 pub mod advance_time;
+pub mod anchor;
 pub mod block;
+pub mod block_filter;
 pub mod block_txn;
 pub mod bloom_filter;
 pub mod compact_block;
 pub mod compact_filters;
+pub mod erlay;
+pub mod fee_filter;
+pub mod fee_rate_bump;
 pub mod getaddr;
+pub mod getblocks_response;
 pub mod getdata;
+pub mod invalid_block;
+pub mod mempool_eviction;
+pub mod mempool_request;
+pub mod multisig;
+pub mod ping;
+pub mod raw_tx;
 pub mod send_raw_message;
+pub mod timelock;
 pub mod tx;
 pub mod txo;
 pub mod witness;
 
 pub use address::*;
 pub use advance_time::*;
+pub use anchor::*;
 pub use block::*;
+pub use block_filter::*;
 pub use block_txn::*;
 pub use bloom_filter::*;
 pub use compact_block::*;
 pub use compact_filters::*;
+pub use erlay::*;
+pub use fee_filter::*;
+pub use fee_rate_bump::*;
 pub use getaddr::*;
+pub use getblocks_response::*;
 pub use getdata::*;
+pub use invalid_block::*;
+pub use mempool_eviction::*;
+pub use mempool_request::*;
+pub use multisig::*;
+pub use ping::*;
+pub use raw_tx::*;
 pub use send_raw_message::*;
+pub use timelock::*;
 pub use tx::*;
 pub use txo::*;
 pub use witness::*;
 
 use crate::{
-    InstructionContext, PerTestcaseMetadata, Program, ProgramBuilder, ProgramContext,
-    ProgramValidationError,
+    FullProgramContext, GenerationEvent, IndexedVariable, InstructionContext, Operation,
+    PerTestcaseMetadata, Program, ProgramBuilder, ProgramContext, ProgramValidationError,
+    msg_type_from_str,
+};
+use rand::{
+    Rng, RngCore,
+    distributions::{Distribution, WeightedIndex},
+    seq::SliceRandom,
 };
-use rand::RngCore;
 
 #[derive(Debug, Clone)]
 pub enum GeneratorError {
@@ -49,6 +80,22 @@ pub trait Generator<R: RngCore> {
         meta: Option<&PerTestcaseMetadata>,
     ) -> GeneratorResult;
 
+    /// Like `generate`, but returns a `GenerationEvent` describing the range of instructions
+    /// produced by the generator
+    fn generate_tracked(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        meta: Option<&PerTestcaseMetadata>,
+    ) -> Result<GenerationEvent, GeneratorError> {
+        let start = builder.instructions.len();
+        self.generate(builder, rng, meta)?;
+        Ok(GenerationEvent {
+            generator_name: self.name().to_string(),
+            instruction_range: (start, builder.instructions.len()),
+        })
+    }
+
     /// Name of the generator
     fn name(&self) -> &'static str;
 
@@ -71,3 +118,182 @@ pub trait Generator<R: RngCore> {
         program.get_random_instruction_index(rng, self.requested_context())
     }
 }
+
+/// Generators that can be used to create programs from scratch, given the full context of the
+/// snapshot
+pub fn default_generators<R: RngCore>(context: &FullProgramContext) -> Vec<Box<dyn Generator<R>>> {
+    vec![
+        Box::new(AdvanceTimeGenerator::default()),
+        Box::new(HeaderGenerator::new(context.headers.clone())),
+        Box::new(BlockGenerator::default()),
+        Box::new(BloomFilterLoadGenerator::default()),
+        Box::new(BloomFilterAddGenerator::default()),
+        Box::new(BloomFilterClearGenerator::default()),
+        Box::new(BloomFilterMerkleBlockGenerator::default()),
+        Box::new(CompactFilterQueryGenerator::default()),
+        Box::new(CFilterGenerator::new(context.block_filters.clone())),
+        Box::new(BlockFilterGenerator::new(
+            context.headers.clone(),
+            context.block_filters.clone(),
+        )),
+        Box::new(GetDataGenerator::default()),
+        Box::new(NotFoundGenerator::default()),
+        Box::new(InventoryGenerator::default()),
+        Box::new(SendBlockGenerator::default()),
+        Box::new(SendBlockGenerator::new(
+            true,
+            SendBlockMode::BlockBeforeHeaders,
+        )),
+        Box::new(AddTxToBlockGenerator::default()),
+        Box::new(SendMessageGenerator::default()),
+        Box::new(RawTxGenerator::default()),
+        Box::new(WitnessGenerator::new()),
+        Box::new(SingleTxGenerator::default()),
+        Box::new(OneParentOneChildGenerator::default()),
+        Box::new(LongChainGenerator::default()),
+        Box::new(LargeTxGenerator::default()),
+        Box::new(TimelockGenerator::default()),
+        Box::new(CltvGenerator::new(context.headers.clone())),
+        Box::new(TxoGenerator::new(context.txos.clone())),
+        Box::new(AddrRelayGenerator::default()),
+        Box::new(AddrRelayV2Generator::default()),
+        Box::new(GetAddrGenerator::default()),
+        Box::new(PingPongGenerator::default()),
+    ]
+}
+
+/// Generate a program from scratch by invoking up to `iterations` randomly chosen `generators`.
+///
+/// Returns the program and metadata holding the generation stack of all successful generator
+/// invocations.
+pub fn generate_program<R: RngCore>(
+    context: &ProgramContext,
+    generators: &[Box<dyn Generator<R>>],
+    iterations: usize,
+    rng: &mut R,
+) -> (Program, PerTestcaseMetadata) {
+    generate_weighted_program(context, generators, None, iterations, rng)
+}
+
+/// Like `generate_program`, but chooses generators according to `weights` (indexed like
+/// `generators`) instead of uniformly, if given.
+pub fn generate_weighted_program<R: RngCore>(
+    context: &ProgramContext,
+    generators: &[Box<dyn Generator<R>>],
+    weights: Option<&WeightedIndex<f64>>,
+    iterations: usize,
+    rng: &mut R,
+) -> (Program, PerTestcaseMetadata) {
+    let mut meta = PerTestcaseMetadata::new();
+    let mut program = ProgramBuilder::new(context.clone());
+
+    let mut insertion_index = 0;
+    for _i in 0..rng.gen_range(1..iterations) {
+        let mut builder = ProgramBuilder::new(context.clone());
+        builder
+            .append_all(program.instructions[..insertion_index].iter().cloned())
+            .unwrap();
+
+        let generator = match weights {
+            Some(weights) => &generators[weights.sample(rng)],
+            None => generators.choose(rng).unwrap(),
+        };
+        let Ok(event) = generator.generate_tracked(&mut builder, rng, None) else {
+            continue;
+        };
+        meta.record_generation(event);
+
+        program
+            .insert_all_at(
+                insertion_index,
+                builder.instructions.drain(insertion_index..),
+            )
+            .unwrap();
+
+        insertion_index = program
+            .finalize()
+            .unwrap()
+            .get_random_instruction_index(rng, InstructionContext::Global)
+            .unwrap()
+            .max(1);
+    }
+
+    (program.finalize().unwrap(), meta)
+}
+
+/// Send `bytes` as a raw message of type `command` on the connection `conn_var`
+fn send_raw_message(builder: &mut ProgramBuilder, conn_var: usize, command: &str, bytes: Vec<u8>) {
+    let msg_type_var = builder.force_append_expect_output(
+        vec![],
+        Operation::LoadMsgType(msg_type_from_str(command).unwrap()),
+    );
+    let bytes_var = builder.force_append_expect_output(vec![], Operation::LoadBytes(bytes));
+    builder.force_append(
+        vec![conn_var, msg_type_var.index, bytes_var.index],
+        Operation::SendRawMessage,
+    );
+}
+
+/// Announce `tx_var` by its wtxid on the connection `conn_var` and send it right after
+fn send_tx(builder: &mut ProgramBuilder, conn_var: &IndexedVariable, tx_var: &IndexedVariable) {
+    let mut_inventory_var =
+        builder.force_append_expect_output(vec![], Operation::BeginBuildInventory);
+    builder.force_append(
+        vec![mut_inventory_var.index, tx_var.index],
+        Operation::AddWtxidInv,
+    );
+    let const_inventory_var = builder
+        .force_append_expect_output(vec![mut_inventory_var.index], Operation::EndBuildInventory);
+
+    builder.force_append(
+        vec![conn_var.index, const_inventory_var.index],
+        Operation::SendInv,
+    );
+    builder.force_append(vec![conn_var.index, tx_var.index], Operation::SendTx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn weighted_generation_follows_weights() {
+        let generators: Vec<Box<dyn Generator<SmallRng>>> = vec![
+            Box::new(AdvanceTimeGenerator::default()),
+            Box::new(PingPongGenerator::default()),
+        ];
+        let context = ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        };
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let count_invocations = |weights: &[f64], rng: &mut SmallRng| {
+            let weights = WeightedIndex::new(weights).unwrap();
+            let mut counts = [0usize; 2];
+            for _ in 0..200 {
+                let (_, meta) =
+                    generate_weighted_program(&context, &generators, Some(&weights), 10, rng);
+                for event in &meta.generation_stack {
+                    let index = generators
+                        .iter()
+                        .position(|g| g.name() == event.generator_name)
+                        .unwrap();
+                    counts[index] += 1;
+                }
+            }
+            counts
+        };
+
+        let [advance_time, ping] = count_invocations(&[0.0, 1.0], &mut rng);
+        assert_eq!(advance_time, 0);
+        assert!(ping > 0);
+
+        // Expect a 1:3 ratio of invocations
+        let [advance_time, ping] = count_invocations(&[1.0, 3.0], &mut rng);
+        let ratio = ping as f64 / advance_time as f64;
+        assert!((2.5..3.5).contains(&ratio), "unexpected ratio {ratio}");
+    }
+}
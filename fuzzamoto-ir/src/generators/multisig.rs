@@ -0,0 +1,121 @@
+use rand::{Rng, RngCore};
+
+use super::{
+    GeneratorError,
+    tx::{OutputType, build_tx},
+};
+use crate::{Generator, GeneratorResult, Operation, PerTestcaseMetadata, ProgramBuilder};
+
+/// Value of each multisig output created by the funding transaction
+const MULTISIG_AMOUNT: u64 = 10_000_000;
+/// Fee paid by the transaction spending the multisig outputs
+const SPEND_FEE: u64 = 10_000;
+
+/// `MultisigGenerator` creates a transaction with 1-3 M-of-N multisig outputs (bare P2MS,
+/// P2SH-P2MS or P2WSH-P2MS) and a second transaction spending all of them, exercising
+/// `OP_CHECKMULTISIG` validation.
+#[derive(Debug, Default)]
+pub struct MultisigGenerator;
+
+impl<R: RngCore> Generator<R> for MultisigGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let funding_txos = builder.get_random_utxos(rng);
+        if funding_txos.is_empty() {
+            return Err(GeneratorError::MissingVariables);
+        }
+
+        let num_outputs = rng.gen_range(1..=3);
+        let outputs: Vec<_> = (0..num_outputs)
+            .map(|_| {
+                let output_type = match rng.gen_range(0..3) {
+                    0 => OutputType::PayToMultiSig,
+                    1 => OutputType::PayToScriptHashMultiSig,
+                    _ => OutputType::PayToWitnessScriptHashMultiSig,
+                };
+                (MULTISIG_AMOUNT, output_type)
+            })
+            .collect();
+        let (funding_tx_var, multisig_txos) = build_tx(builder, rng, &funding_txos, 2, &outputs)?;
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+        builder.force_append(
+            vec![conn_var.index, funding_tx_var.index],
+            Operation::SendTx,
+        );
+
+        let (spend_tx_var, _) = build_tx(
+            builder,
+            rng,
+            &multisig_txos,
+            2,
+            &[(
+                MULTISIG_AMOUNT * num_outputs - SPEND_FEE,
+                OutputType::PayToWitnessScriptHash,
+            )],
+        )?;
+        builder.force_append(vec![conn_var.index, spend_tx_var.index], Operation::SendTx);
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "MultisigGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoin::Transaction;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn spends_all_multisig_outputs() {
+        for seed in 0..16 {
+            let mut builder = test_utils::builder();
+            let mut rng = SmallRng::seed_from_u64(seed);
+            // Fund up to three multisig outputs
+            builder.force_append(
+                vec![],
+                Operation::LoadTxo {
+                    outpoint: ([1; 32], 0),
+                    value: 3 * MULTISIG_AMOUNT,
+                    script_pubkey: vec![0x51],
+                    spending_script_sig: vec![],
+                    spending_witness: vec![],
+                },
+            );
+            MultisigGenerator
+                .generate(&mut builder, &mut rng, None)
+                .expect("generation should succeed");
+
+            let txs: Vec<Transaction> = test_utils::sent_messages(&builder, "tx");
+
+            let [funding, spend] = txs.as_slice() else {
+                panic!("expected two transactions, got {}", txs.len());
+            };
+            assert_eq!(spend.input.len(), funding.output.len());
+            for (index, input) in spend.input.iter().enumerate() {
+                assert_eq!(input.previous_output.txid, funding.compute_txid());
+                assert_eq!(input.previous_output.vout, index as u32);
+                // Every multisig spend starts with the dummy element for OP_CHECKMULTISIG
+                let script_pubkey = &funding.output[index].script_pubkey;
+                if script_pubkey.is_p2wsh() {
+                    assert!(input.witness.nth(0).unwrap().is_empty());
+                } else {
+                    assert!(input.script_sig.as_bytes().starts_with(&[0x00]));
+                }
+            }
+            assert_eq!(
+                spend.output[0].value.to_sat(),
+                MULTISIG_AMOUNT * funding.output.len() as u64 - SPEND_FEE
+            );
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use rand::{Rng, RngCore};
+
+use crate::{Generator, GeneratorResult, Operation, PerTestcaseMetadata, ProgramBuilder};
+
+/// `PingPongGenerator` sends `ping` messages with a 64-bit nonce on a random connection. The
+/// nonce is optionally re-used or randomized for a second `ping`, to exercise the node's nonce
+/// handling. The node's `pong` is received by the scenario when it synchronizes with the
+/// connection after the program was executed. Optionally, an unsolicited `pong` carrying the same
+/// nonce is sent as well.
+#[derive(Debug, Default)]
+pub struct PingPongGenerator;
+
+impl<R: RngCore> Generator<R> for PingPongGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let conn_var = builder.get_or_create_random_connection(rng);
+
+        let nonce_var =
+            builder.force_append_expect_output(vec![], Operation::LoadNonce64(rng.r#gen()));
+        builder.force_append(
+            vec![conn_var.index, nonce_var.index],
+            Operation::SendPingWithNonce,
+        );
+
+        if rng.gen_bool(0.5) {
+            let nonce_var = if rng.gen_bool(0.5) {
+                builder.force_append_expect_output(vec![nonce_var.index], Operation::RandomizeNonce)
+            } else {
+                nonce_var.clone()
+            };
+            builder.force_append(
+                vec![conn_var.index, nonce_var.index],
+                Operation::SendPingWithNonce,
+            );
+        }
+
+        if rng.gen_bool(0.25) {
+            builder.force_append(vec![conn_var.index, nonce_var.index], Operation::SendPong);
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "PingPongGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn sends_pings_with_nonce() {
+        for seed in 0..16 {
+            let mut builder = test_utils::builder();
+            let mut rng = SmallRng::seed_from_u64(seed);
+            PingPongGenerator
+                .generate(&mut builder, &mut rng, None)
+                .unwrap();
+
+            let program = builder.finalize().unwrap();
+            let nonce = program
+                .instructions
+                .iter()
+                .find_map(|instruction| match instruction.operation {
+                    Operation::LoadNonce64(nonce) => Some(nonce),
+                    _ => None,
+                })
+                .unwrap();
+
+            let mut pings = Vec::new();
+            for (command, payload) in test_utils::sent_raw_messages(&builder) {
+                if command == "ping" {
+                    pings.push(u64::from_le_bytes(payload.as_slice().try_into().unwrap()));
+                } else {
+                    assert_eq!(command, "pong");
+                    assert_eq!(payload.len(), 8);
+                }
+            }
+            assert!(matches!(pings.len(), 1 | 2));
+            assert_eq!(pings[0], nonce);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use rand::{Rng, RngCore};
+
+use crate::{Generator, GeneratorResult, Operation, PerTestcaseMetadata, ProgramBuilder};
+
+/// Maximum number of random bytes following the transaction version
+const MAX_PAYLOAD_LEN: usize = 256;
+
+/// `RawTxGenerator` sends malformed transactions: a plausible transaction version (optionally
+/// followed by the segwit marker and flag) with a random payload of random length. The bytes are
+/// sent as-is through `SendRawTransaction`, bypassing the structured transaction building
+/// operations.
+#[derive(Debug, Default)]
+pub struct RawTxGenerator;
+
+impl<R: RngCore> Generator<R> for RawTxGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let version: u32 = match rng.gen_range(0..4) {
+            0 => 1,
+            1 => 2,
+            2 => 3,
+            _ => rng.r#gen(),
+        };
+        let mut bytes = version.to_le_bytes().to_vec();
+        if rng.gen_bool(0.5) {
+            // Segwit marker and flag
+            bytes.extend_from_slice(&[0x00, 0x01]);
+        }
+
+        let mut payload = vec![0u8; rng.gen_range(0..=MAX_PAYLOAD_LEN)];
+        rng.fill_bytes(&mut payload);
+        bytes.extend(payload);
+
+        let conn_var = builder.get_or_create_random_connection(rng);
+        let raw_tx_var =
+            builder.force_append_expect_output(vec![], Operation::LoadRawTransaction(bytes));
+        builder.force_append(
+            vec![conn_var.index, raw_tx_var.index],
+            Operation::SendRawTransaction,
+        );
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "RawTxGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn sends_raw_bytes_as_tx() {
+        for seed in 0..16 {
+            let mut builder = test_utils::builder();
+            let mut rng = SmallRng::seed_from_u64(seed);
+            RawTxGenerator
+                .generate(&mut builder, &mut rng, None)
+                .unwrap();
+
+            let program = builder.finalize().unwrap();
+            let bytes = program
+                .instructions
+                .iter()
+                .find_map(|instruction| match &instruction.operation {
+                    Operation::LoadRawTransaction(bytes) => Some(bytes.clone()),
+                    _ => None,
+                })
+                .unwrap();
+            assert!(bytes.len() >= 4);
+
+            assert_eq!(
+                test_utils::sent_raw_messages(&builder),
+                vec![("tx".to_string(), bytes)]
+            );
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::time::Duration;
+
+use bitcoin::{
+    absolute::LOCK_TIME_THRESHOLD,
+    opcodes::{
+        OP_TRUE,
+        all::{OP_CLTV, OP_CSV, OP_DROP},
+    },
+    script::Builder,
+};
+use rand::{Rng, RngCore};
+
+use super::{GeneratorError, block::advance_time};
+use crate::{
+    Generator, GeneratorResult, Header, IndexedVariable, Operation, PerTestcaseMetadata,
+    ProgramBuilder,
+};
+
+/// BIP-68: the relative lock time is measured in units of 512 seconds (instead of blocks)
+const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+/// BIP-68: granularity of time based relative lock times
+const SEQUENCE_LOCKTIME_GRANULARITY: u64 = 512;
+/// Expected time between blocks, used to advance the mock time past block based lock times
+const BLOCK_INTERVAL_SECS: u64 = 600;
+
+const FUNDING_AMOUNT: u64 = 100_000_000;
+const SPENDING_AMOUNT: u64 = 99_990_000;
+
+/// Build P2WSH scripts for the witness script `script` (spendable with an empty witness stack)
+fn build_p2wsh(builder: &mut ProgramBuilder, script: Vec<u8>) -> IndexedVariable {
+    let script_var = builder.force_append_expect_output(vec![], Operation::LoadBytes(script));
+    let mut_witness_stack_var =
+        builder.force_append_expect_output(vec![], Operation::BeginWitnessStack);
+    let witness_stack_var = builder.force_append_expect_output(
+        vec![mut_witness_stack_var.index],
+        Operation::EndWitnessStack,
+    );
+    builder.force_append_expect_output(
+        vec![script_var.index, witness_stack_var.index],
+        Operation::BuildPayToWitnessScriptHash,
+    )
+}
+
+/// Build a version 2 transaction with `lock_time`, spending `funding_txos` (with `sequence`) into
+/// a single output of `amount` paid to `scripts_var`. Returns the transaction and its output.
+fn build_single_output_tx(
+    builder: &mut ProgramBuilder,
+    funding_txos: &[IndexedVariable],
+    lock_time: u32,
+    sequence: u32,
+    scripts_var: &IndexedVariable,
+    amount: u64,
+) -> (IndexedVariable, IndexedVariable) {
+    let tx_version_var = builder.force_append_expect_output(vec![], Operation::LoadTxVersion(2));
+    let lock_time_var =
+        builder.force_append_expect_output(vec![], Operation::LoadLockTime(lock_time));
+    let mut_tx_var = builder.force_append_expect_output(
+        vec![tx_version_var.index, lock_time_var.index],
+        Operation::BeginBuildTx,
+    );
+
+    let mut_inputs_var = builder.force_append_expect_output(vec![], Operation::BeginBuildTxInputs);
+    for funding_txo in funding_txos {
+        let sequence_var =
+            builder.force_append_expect_output(vec![], Operation::LoadSequence(sequence));
+        builder.force_append(
+            vec![mut_inputs_var.index, funding_txo.index, sequence_var.index],
+            Operation::AddTxInput,
+        );
+    }
+    let inputs_var =
+        builder.force_append_expect_output(vec![mut_inputs_var.index], Operation::EndBuildTxInputs);
+
+    let mut_outputs_var =
+        builder.force_append_expect_output(vec![inputs_var.index], Operation::BeginBuildTxOutputs);
+    let amount_var = builder.force_append_expect_output(vec![], Operation::LoadAmount(amount));
+    builder.force_append(
+        vec![mut_outputs_var.index, scripts_var.index, amount_var.index],
+        Operation::AddTxOutput,
+    );
+    let outputs_var = builder
+        .force_append_expect_output(vec![mut_outputs_var.index], Operation::EndBuildTxOutputs);
+
+    let tx_var = builder.force_append_expect_output(
+        vec![mut_tx_var.index, inputs_var.index, outputs_var.index],
+        Operation::EndBuildTx,
+    );
+    let txo_var = builder.force_append_expect_output(vec![tx_var.index], Operation::TakeTxo);
+    (tx_var, txo_var)
+}
+
+/// Fund an output locked by `lock_script`, advance the mock time by `lock_duration` and spend the
+/// output with `lock_time` and `sequence`, sending both transactions to the node
+fn fund_and_spend_locked_output<R: RngCore>(
+    builder: &mut ProgramBuilder,
+    rng: &mut R,
+    lock_script: Vec<u8>,
+    lock_time: u32,
+    sequence: u32,
+    lock_duration: Duration,
+) -> GeneratorResult {
+    let funding_txos = builder.get_random_utxos(rng);
+    if funding_txos.is_empty() {
+        return Err(GeneratorError::MissingVariables);
+    }
+
+    let locked_scripts_var = build_p2wsh(builder, lock_script);
+    let (funding_tx_var, locked_txo_var) = build_single_output_tx(
+        builder,
+        &funding_txos,
+        0,
+        0xffff_ffff,
+        &locked_scripts_var,
+        FUNDING_AMOUNT,
+    );
+
+    let spending_scripts_var = build_p2wsh(builder, vec![OP_TRUE.to_u8()]);
+    let (spending_tx_var, _) = build_single_output_tx(
+        builder,
+        &[locked_txo_var],
+        lock_time,
+        sequence,
+        &spending_scripts_var,
+        SPENDING_AMOUNT,
+    );
+
+    let conn_var = builder.get_or_create_random_connection(rng);
+    builder.force_append(
+        vec![conn_var.index, funding_tx_var.index],
+        Operation::SendTx,
+    );
+    advance_time(builder, lock_duration);
+    builder.force_append(
+        vec![conn_var.index, spending_tx_var.index],
+        Operation::SendTx,
+    );
+
+    Ok(())
+}
+
+/// `TimelockGenerator` generates a transaction with an output locked by a relative lock time
+/// (`<n> OP_CHECKSEQUENCEVERIFY`, BIP-68/BIP-112) and a transaction spending it with a matching
+/// `nSequence`
+#[derive(Debug, Default)]
+pub struct TimelockGenerator;
+
+impl<R: RngCore> Generator<R> for TimelockGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let (sequence, lock_duration) = if rng.gen_bool(0.75) {
+            // Block based lock time (type flag clear, block count in bits 0-15)
+            let blocks = if rng.gen_bool(0.9) {
+                rng.gen_range(1..=16)
+            } else {
+                rng.gen_range(1..=u16::MAX)
+            };
+            (
+                u32::from(blocks),
+                Duration::from_secs(u64::from(blocks) * BLOCK_INTERVAL_SECS),
+            )
+        } else {
+            // Time based lock time, in units of 512 seconds
+            let units: u16 = rng.gen_range(1..=16);
+            (
+                SEQUENCE_LOCKTIME_TYPE_FLAG | u32::from(units),
+                Duration::from_secs(u64::from(units) * SEQUENCE_LOCKTIME_GRANULARITY + 1),
+            )
+        };
+
+        let lock_script = Builder::new()
+            .push_int(i64::from(sequence))
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_TRUE)
+            .into_bytes();
+
+        fund_and_spend_locked_output(builder, rng, lock_script, 0, sequence, lock_duration)
+    }
+
+    fn name(&self) -> &'static str {
+        "TimelockGenerator"
+    }
+}
+
+/// `CltvGenerator` generates a transaction with an output locked by an absolute lock time
+/// (`<n> OP_CHECKLOCKTIMEVERIFY`, BIP-65) and a transaction spending it with a matching
+/// `nLockTime`
+pub struct CltvGenerator {
+    headers: Vec<Header>,
+}
+
+impl CltvGenerator {
+    pub fn new(headers: Vec<Header>) -> Self {
+        Self { headers }
+    }
+}
+
+impl<R: RngCore> Generator<R> for CltvGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let (lock_time, lock_duration) = if rng.gen_bool(0.5) {
+            // Height based lock time, close to the tip of the snapshotted chain
+            let tip_height = self.headers.iter().map(|h| h.height).max().unwrap_or(0);
+            let blocks = rng.gen_range(0..=10);
+            (
+                tip_height + blocks,
+                Duration::from_secs(u64::from(blocks) * BLOCK_INTERVAL_SECS),
+            )
+        } else {
+            // Timestamp based lock time (at or above `LOCK_TIME_THRESHOLD`)
+            let now = u32::try_from(builder.context().timestamp)
+                .unwrap_or(u32::MAX)
+                .max(LOCK_TIME_THRESHOLD);
+            let secs = rng.gen_range(0..=3600);
+            (
+                now.saturating_add(secs),
+                Duration::from_secs(u64::from(secs) + 1),
+            )
+        };
+
+        let lock_script = Builder::new()
+            .push_int(i64::from(lock_time))
+            .push_opcode(OP_CLTV)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_TRUE)
+            .into_bytes();
+
+        // A final input sequence would disable the lock time check
+        fund_and_spend_locked_output(
+            builder,
+            rng,
+            lock_script,
+            lock_time,
+            0xffff_fffe,
+            lock_duration,
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        "CltvGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ProgramContext, Txo, TxoGenerator,
+        compiler::{CompiledAction, Compiler},
+    };
+    use bitcoin::{Transaction, consensus::deserialize};
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    /// Run `generator` on a program with a single funding txo and return the sent transactions
+    fn sent_txs<G: Generator<SmallRng>>(generator: &G, seed: u64) -> Vec<Transaction> {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 1_296_688_700,
+        });
+        let mut rng = SmallRng::seed_from_u64(seed);
+        TxoGenerator::new(vec![Txo {
+            outpoint: ([1; 32], 0),
+            value: 5_000_000_000,
+            script_pubkey: vec![0x51],
+            spending_script_sig: vec![],
+            spending_witness: vec![],
+        }])
+        .generate(&mut builder, &mut rng, None)
+        .unwrap();
+        generator.generate(&mut builder, &mut rng, None).unwrap();
+
+        let program = builder.finalize().unwrap();
+        let compiled = Compiler::new().compile(&program).unwrap();
+        assert!(
+            compiled
+                .actions
+                .iter()
+                .any(|action| matches!(action, CompiledAction::SetTime(_)))
+        );
+        compiled
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                CompiledAction::SendRawMessage(_, command, payload) if command == "tx" => {
+                    Some(deserialize(payload).unwrap())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn csv_spend_uses_locked_sequence() {
+        for seed in 0..16 {
+            let txs = sent_txs(&TimelockGenerator, seed);
+            assert_eq!(txs.len(), 2);
+            let (funding, spending) = (&txs[0], &txs[1]);
+
+            let input = &spending.input[0];
+            assert_eq!(input.previous_output.txid, funding.compute_txid());
+            assert!(spending.version.0 >= 2);
+            // Relative lock times are enabled (disable flag clear)
+            assert_eq!(input.sequence.0 & (1 << 31), 0);
+
+            let witness_script = bitcoin::Script::from_bytes(input.witness.last().unwrap());
+            let mut instructions = witness_script.instructions();
+            let locked = instructions.next().unwrap().unwrap();
+            assert_eq!(
+                locked.script_num(),
+                Some(i64::from(input.sequence.0)),
+                "seed {seed}"
+            );
+            assert_eq!(instructions.next().unwrap().unwrap().opcode(), Some(OP_CSV));
+        }
+    }
+
+    #[test]
+    fn cltv_spend_uses_locked_lock_time() {
+        let headers = vec![Header {
+            prev: [0; 32],
+            merkle_root: [0; 32],
+            nonce: 0,
+            bits: 0x207f_ffff,
+            time: 1_296_688_602,
+            version: 4,
+            height: 600,
+        }];
+        let generator = CltvGenerator::new(headers);
+        for seed in 0..16 {
+            let txs = sent_txs(&generator, seed);
+            assert_eq!(txs.len(), 2);
+            let spending = &txs[1];
+
+            let lock_time = spending.lock_time.to_consensus_u32();
+            assert!(lock_time >= 600);
+            assert!(lock_time < 611 || lock_time >= LOCK_TIME_THRESHOLD);
+            assert!(!spending.input[0].sequence.is_final());
+
+            let witness_script =
+                bitcoin::Script::from_bytes(spending.input[0].witness.last().unwrap());
+            let mut instructions = witness_script.instructions();
+            assert_eq!(
+                instructions.next().unwrap().unwrap().script_num(),
+                Some(i64::from(lock_time))
+            );
+            assert_eq!(
+                instructions.next().unwrap().unwrap().opcode(),
+                Some(OP_CLTV)
+            );
+        }
+    }
+}
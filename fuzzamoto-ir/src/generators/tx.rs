@@ -13,7 +13,11 @@ use rand::{Rng, RngCore, seq::SliceRandom};
 
 use super::{GeneratorError, GeneratorResult};
 
-enum OutputType {
+/// Coinbase scriptSigs are limited to 2-100 bytes, generated custom scriptSigs may be slightly
+/// larger
+const MAX_COINBASE_SCRIPT_SIG_LEN: usize = 110;
+
+pub(super) enum OutputType {
     PayToWitnessScriptHash,
     PayToScriptHash,
     PayToAnchor,
@@ -21,6 +25,9 @@ enum OutputType {
     PayToPubKeyHash,
     PayToWitnessPubKeyHash,
     PayToTaproot,
+    PayToMultiSig,
+    PayToScriptHashMultiSig,
+    PayToWitnessScriptHashMultiSig,
     OpReturn,
 }
 
@@ -114,6 +121,19 @@ fn build_outputs<R: RngCore>(
                 )
             }
             OutputType::PayToTaproot => build_taproot_scripts(builder, rng),
+            OutputType::PayToMultiSig
+            | OutputType::PayToScriptHashMultiSig
+            | OutputType::PayToWitnessScriptHashMultiSig => {
+                let end_operation = match output_type {
+                    OutputType::PayToMultiSig => Operation::EndBuildMultiSig,
+                    OutputType::PayToScriptHashMultiSig => Operation::EndBuildMultiSigScriptHash,
+                    OutputType::PayToWitnessScriptHashMultiSig => {
+                        Operation::EndBuildMultiSigWitnessScriptHash
+                    }
+                    _ => unreachable!(),
+                };
+                build_multisig_scripts(builder, rng, end_operation)
+            }
         };
 
         let amount_var = builder.force_append_expect_output(vec![], Operation::LoadAmount(*amount));
@@ -133,7 +153,7 @@ fn build_outputs<R: RngCore>(
     Ok(())
 }
 
-fn build_tx<R: RngCore>(
+pub(super) fn build_tx<R: RngCore>(
     builder: &mut ProgramBuilder,
     rng: &mut R,
     funding_txos: &[IndexedVariable],
@@ -490,8 +510,27 @@ impl<R: RngCore> Generator<R> for CoinbaseTxGenerator {
         let sequence_var =
             builder.force_append_expect_output(vec![], Operation::LoadSequence(0xffffffff));
 
-        let coinbase_input_var = builder
-            .force_append_expect_output(vec![sequence_var.index], Operation::BuildCoinbaseTxInput);
+        let coinbase_input_var = if rng.gen_bool(0.05) {
+            // Custom scriptSig (e.g. without a BIP34 height push or outside of the allowed size
+            // range)
+            let mut script_sig = vec![0u8; rng.gen_range(0..=MAX_COINBASE_SCRIPT_SIG_LEN)];
+            rng.fill_bytes(&mut script_sig);
+            let bytes_var =
+                builder.force_append_expect_output(vec![], Operation::LoadBytes(script_sig));
+            let script_sig_var = builder.force_append_expect_output(
+                vec![bytes_var.index],
+                Operation::LoadCoinbaseScriptSig,
+            );
+            builder.force_append_expect_output(
+                vec![sequence_var.index, script_sig_var.index],
+                Operation::BuildCoinbaseTxInputWithScriptSig,
+            )
+        } else {
+            builder.force_append_expect_output(
+                vec![sequence_var.index],
+                Operation::BuildCoinbaseTxInput,
+            )
+        };
 
         let mut_outputs_var = builder.force_append_expect_output(
             vec![coinbase_input_var.index],
@@ -560,6 +599,36 @@ fn build_taproot_scripts<R: RngCore>(builder: &mut ProgramBuilder, rng: &mut R)
     builder.force_append_expect_output(vec![spend_info_var.index], Operation::BuildPayToTaproot)
 }
 
+/// Build M-of-N multisig scripts with fresh keys. N is at most 3, which is the limit for bare
+/// multisig outputs to be standard.
+fn build_multisig_scripts<R: RngCore>(
+    builder: &mut ProgramBuilder,
+    rng: &mut R,
+    end_operation: Operation,
+) -> IndexedVariable {
+    let num_keys = rng.gen_range(1..=3);
+    let threshold = rng.gen_range(1..=num_keys);
+
+    let threshold_var = builder.force_append_expect_output(vec![], Operation::LoadSize(threshold));
+    let num_keys_var = builder.force_append_expect_output(vec![], Operation::LoadSize(num_keys));
+    let mut_multisig_var = builder.force_append_expect_output(
+        vec![threshold_var.index, num_keys_var.index],
+        Operation::BeginBuildMultiSig,
+    );
+    for _ in 0..num_keys {
+        let private_key_var = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadPrivateKey(gen_secret_key_bytes(rng)),
+        );
+        builder.force_append(
+            vec![mut_multisig_var.index, private_key_var.index],
+            Operation::AddMultiSigKey,
+        );
+    }
+
+    builder.force_append_expect_output(vec![mut_multisig_var.index], end_operation)
+}
+
 /// Generate a merkle path to simulate additional leaves in the taproot tree.
 fn random_merkle_path<R: RngCore>(rng: &mut R) -> Vec<[u8; 32]> {
     let depth = rng.gen_range(0..=4);
@@ -1,19 +1,98 @@
 use std::path::PathBuf;
 
 use super::{Generator, GeneratorError, GeneratorResult};
-use crate::{Operation, PerTestcaseMetadata, ProgramBuilder};
-use rand::{Rng, RngCore};
+use crate::{Operation, PerTestcaseMetadata, ProgramBuilder, Txo};
+use bitcoin::{
+    PubkeyHash, Script, ScriptBuf, WPubkeyHash, hashes::Hash, opcodes::OP_TRUE,
+    script::PushBytesBuf,
+};
+use rand::{Rng, RngCore, seq::SliceRandom};
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct Txo {
-    pub outpoint: ([u8; 32], u32),
-    pub value: u64,
-    pub script_pubkey: Vec<u8>,
-    pub spending_script_sig: Vec<u8>,
-    pub spending_witness: Vec<Vec<u8>>,
+/// Output types for synthetic (i.e. not present in the snapshot) transaction outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxoType {
+    P2WSH,
+    P2TR,
+    P2PKH,
+    P2SH,
+    P2WPKH,
+}
+
+impl TxoType {
+    pub const ALL: [TxoType; 5] = [
+        TxoType::P2WSH,
+        TxoType::P2TR,
+        TxoType::P2PKH,
+        TxoType::P2SH,
+        TxoType::P2WPKH,
+    ];
+
+    /// Create a synthetic `Txo` of this type with a random outpoint and value, together with
+    /// dummy spending data of the right shape.
+    pub fn random_txo<R: RngCore>(&self, rng: &mut R) -> Txo {
+        let op_true_bytes = [OP_TRUE.to_u8()];
+        let op_true = Script::from_bytes(&op_true_bytes);
+        let dummy_signature = vec![0u8; 72];
+        let dummy_pubkey = vec![0x02u8; 33];
+
+        let (script_pubkey, spending_script_sig, spending_witness) = match self {
+            TxoType::P2WSH => (op_true.to_p2wsh(), vec![], vec![op_true.to_bytes()]),
+            TxoType::P2TR => {
+                let mut script_pubkey = vec![0x51, 0x20];
+                script_pubkey.extend(rng.r#gen::<[u8; 32]>());
+                // Single (dummy) schnorr signature for a key-path spend
+                (
+                    ScriptBuf::from_bytes(script_pubkey),
+                    vec![],
+                    vec![vec![0u8; 64]],
+                )
+            }
+            TxoType::P2PKH => {
+                let mut script_sig = ScriptBuf::new();
+                script_sig.push_slice(PushBytesBuf::try_from(dummy_signature).unwrap());
+                script_sig.push_slice(PushBytesBuf::try_from(dummy_pubkey).unwrap());
+                (
+                    ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array(rng.r#gen())),
+                    script_sig.into_bytes(),
+                    vec![],
+                )
+            }
+            TxoType::P2SH => {
+                let mut script_sig = ScriptBuf::new();
+                script_sig.push_slice(PushBytesBuf::try_from(op_true.to_bytes()).unwrap());
+                (op_true.to_p2sh(), script_sig.into_bytes(), vec![])
+            }
+            TxoType::P2WPKH => (
+                ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array(rng.r#gen())),
+                vec![],
+                vec![dummy_signature, dummy_pubkey],
+            ),
+        };
+
+        Txo {
+            outpoint: (rng.r#gen(), rng.gen_range(0..4)),
+            value: rng.gen_range(1_000..100_000_000),
+            script_pubkey: script_pubkey.into_bytes(),
+            spending_script_sig,
+            spending_witness,
+        }
+    }
+}
+
+fn load_txo_operation(txo: &Txo) -> Operation {
+    Operation::LoadTxo {
+        outpoint: txo.outpoint,
+        value: txo.value,
+        script_pubkey: txo.script_pubkey.clone(),
+        spending_script_sig: txo.spending_script_sig.clone(),
+        spending_witness: txo.spending_witness.clone(),
+    }
 }
 
 /// `TxoGenerator` generates a new `LoadTxo` instruction into a program.
+///
+/// Mostly transaction outputs present in the snapshot are loaded, but occasionally a synthetic
+/// output of a random `TxoType` is created instead.
 pub struct TxoGenerator {
     available_txos: Vec<Txo>,
 }
@@ -41,17 +120,12 @@ impl<R: RngCore> Generator<R> for TxoGenerator {
             return Err(GeneratorError::MissingVariables);
         }
 
-        let available_txo = &self.available_txos[rng.gen_range(0..self.available_txos.len())];
-        builder.force_append(
-            vec![],
-            Operation::LoadTxo {
-                outpoint: available_txo.outpoint.clone(),
-                value: available_txo.value,
-                script_pubkey: available_txo.script_pubkey.clone(),
-                spending_script_sig: available_txo.spending_script_sig.clone(),
-                spending_witness: available_txo.spending_witness.clone(),
-            },
-        );
+        let txo = if rng.gen_bool(0.1) {
+            TxoType::ALL.choose(rng).unwrap().random_txo(rng)
+        } else {
+            self.available_txos[rng.gen_range(0..self.available_txos.len())].clone()
+        };
+        builder.force_append(vec![], load_txo_operation(&txo));
         Ok(())
     }
 
@@ -59,3 +133,103 @@ impl<R: RngCore> Generator<R> for TxoGenerator {
         "TxoGenerator"
     }
 }
+
+/// `P2TRTxoGenerator` generates a `LoadTxo` instruction for a synthetic Taproot output spent via
+/// the key path.
+#[derive(Default)]
+pub struct P2TRTxoGenerator;
+
+impl<R: RngCore> Generator<R> for P2TRTxoGenerator {
+    fn generate(
+        &self,
+        builder: &mut ProgramBuilder,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> GeneratorResult {
+        let txo = TxoType::P2TR.random_txo(rng);
+        builder.force_append(vec![], load_txo_operation(&txo));
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "P2TRTxoGenerator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generators::SingleTxGenerator, test_utils};
+    use bitcoin::Transaction;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn random_txos_match_their_type() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for txo_type in TxoType::ALL {
+            let txo = txo_type.random_txo(&mut rng);
+            let script_pubkey = Script::from_bytes(&txo.script_pubkey);
+            let is_type = match txo_type {
+                TxoType::P2WSH => script_pubkey.is_p2wsh(),
+                TxoType::P2TR => script_pubkey.is_p2tr(),
+                TxoType::P2PKH => script_pubkey.is_p2pkh(),
+                TxoType::P2SH => script_pubkey.is_p2sh(),
+                TxoType::P2WPKH => script_pubkey.is_p2wpkh(),
+            };
+            assert!(is_type, "{txo_type:?} has script_pubkey {script_pubkey}");
+            assert!((1_000..100_000_000).contains(&txo.value));
+            assert!(txo.outpoint.1 < 4);
+
+            let is_segwit = matches!(txo_type, TxoType::P2WSH | TxoType::P2TR | TxoType::P2WPKH);
+            assert_eq!(txo.spending_witness.is_empty(), !is_segwit);
+            assert_eq!(txo.spending_script_sig.is_empty(), is_segwit);
+        }
+    }
+
+    #[test]
+    fn p2tr_txo_generator_emits_key_path_txo() {
+        let mut sent_txs = 0;
+        for seed in 0..16 {
+            let mut builder = test_utils::builder();
+            let mut rng = SmallRng::seed_from_u64(seed);
+            P2TRTxoGenerator
+                .generate(&mut builder, &mut rng, None)
+                .expect("generation should succeed");
+
+            assert_eq!(builder.instructions.len(), 1);
+            let Operation::LoadTxo {
+                script_pubkey,
+                spending_script_sig,
+                spending_witness,
+                ..
+            } = &builder.instructions[0].operation
+            else {
+                panic!("expected LoadTxo");
+            };
+            assert!(Script::from_bytes(script_pubkey).is_p2tr());
+            assert!(spending_script_sig.is_empty());
+            assert_eq!(spending_witness, &vec![vec![0u8; 64]]);
+
+            // The loaded txo is usable as funding for generated transactions
+            SingleTxGenerator
+                .generate(&mut builder, &mut rng, None)
+                .expect("generation should succeed");
+            for tx in test_utils::sent_messages::<Transaction>(&builder, "tx") {
+                assert_eq!(tx.input[0].witness.to_vec(), vec![vec![0u8; 64]]);
+                sent_txs += 1;
+            }
+        }
+        assert!(sent_txs > 0);
+    }
+
+    #[test]
+    fn txo_generator_requires_available_txos() {
+        let mut builder = test_utils::builder();
+        let mut rng = SmallRng::seed_from_u64(0);
+        assert!(matches!(
+            TxoGenerator::new(vec![]).generate(&mut builder, &mut rng, None),
+            Err(GeneratorError::MissingVariables)
+        ));
+        assert!(builder.instructions.is_empty());
+    }
+}
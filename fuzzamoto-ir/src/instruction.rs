@@ -1,12 +1,70 @@
-use crate::Operation;
+use std::fmt;
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Hash)]
+use crate::{Operation, Variable};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Hash, PartialEq)]
 pub struct Instruction {
     pub inputs: Vec<usize>,
     pub operation: Operation,
 }
 
 impl Instruction {
+    /// Format the instruction with the type of each variable, e.g.
+    /// `BeginBuildTx(v1:TxVersion, v2:LockTime) -> v3:MutTx`.
+    ///
+    /// `variables` holds the index and type of all variables defined up to (and including) this
+    /// instruction, i.e. the instruction's outputs followed by its inner outputs are the last
+    /// entries.
+    pub fn display_with_variables(
+        &self,
+        variables: &[(usize, Variable)],
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        let write_var = |f: &mut fmt::Formatter, index: usize| match variables
+            .iter()
+            .rev()
+            .find(|(i, _)| *i == index)
+        {
+            Some((_, var)) => write!(f, "v{}:{:?}", index, var),
+            None => write!(f, "v{}:?", index),
+        };
+        let write_vars = |f: &mut fmt::Formatter, indices: &mut dyn Iterator<Item = usize>| {
+            for (n, index) in indices.enumerate() {
+                if n > 0 {
+                    write!(f, ", ")?;
+                }
+                write_var(f, index)?;
+            }
+            Ok(())
+        };
+
+        let num_inner_outputs = self.operation.num_inner_outputs();
+        let new_variables = &variables[variables
+            .len()
+            .saturating_sub(self.operation.num_outputs() + num_inner_outputs)..];
+        let (outputs, inner_outputs) =
+            new_variables.split_at(new_variables.len().saturating_sub(num_inner_outputs));
+
+        if !outputs.is_empty() {
+            write_vars(f, &mut outputs.iter().map(|(i, _)| *i))?;
+            write!(f, " <- ")?;
+        }
+        write!(f, "{}", self.operation)?;
+
+        if !self.inputs.is_empty() {
+            write!(f, "(")?;
+            write_vars(f, &mut self.inputs.iter().copied())?;
+            write!(f, ")")?;
+        }
+
+        if !inner_outputs.is_empty() {
+            write!(f, " -> ")?;
+            write_vars(f, &mut inner_outputs.iter().map(|(i, _)| *i))?;
+        }
+
+        Ok(())
+    }
+
     pub fn is_input_mutable(&self) -> bool {
         assert!(self.inputs.len() == self.operation.num_inputs());
 
@@ -18,6 +76,11 @@ impl Instruction {
             | Operation::EndBuildTxOutputs
             | Operation::BeginBuildInventory
             | Operation::EndBuildInventory
+            | Operation::BeginBuildGetBlocksLocator
+            | Operation::EndBuildGetBlocksLocator
+            | Operation::EndBuildMultiSig
+            | Operation::EndBuildMultiSigScriptHash
+            | Operation::EndBuildMultiSigWitnessScriptHash
             | Operation::EndBuildAddrList
             | Operation::EndBuildAddrListV2
             | Operation::BeginBlockTransactions
@@ -52,6 +115,9 @@ impl Instruction {
             | Operation::BuildPayToPubKey
             | Operation::BuildPayToPubKeyHash
             | Operation::BuildPayToWitnessPubKeyHash
+            | Operation::EndBuildMultiSig
+            | Operation::EndBuildMultiSigScriptHash
+            | Operation::EndBuildMultiSigWitnessScriptHash
             | Operation::LoadBlockHeight(_)
             | Operation::AddTxidWithWitnessInv
             | Operation::AddTxidInv
@@ -64,7 +130,12 @@ impl Instruction {
             | Operation::SendTx
             | Operation::AddAddrV2
             | Operation::LoadBytes(_)
+            | Operation::LoadRawBytes(_)
+            | Operation::LoadRawTransaction(_)
             | Operation::LoadTaprootAnnex { .. }
+            | Operation::LoadHeader { .. }
+            | Operation::LoadTxo { .. }
+            | Operation::LoadMsgType(_)
             | Operation::BuildPayToTaproot
             | Operation::TaprootScriptsUseAnnex
             | Operation::TaprootTxoUseAnnex => true,
@@ -75,7 +146,9 @@ impl Instruction {
     pub fn is_noppable(&self) -> bool {
         match self.operation {
             Operation::LoadBytes(_)
+            | Operation::LoadRawBytes(_)
             | Operation::LoadMsgType(_)
+            | Operation::LoadMsgTypeFromStr(_)
             | Operation::LoadNode(_)
             | Operation::LoadConnection(_)
             | Operation::LoadConnectionType(_)
@@ -97,6 +170,7 @@ impl Instruction {
             | Operation::BuildPayToWitnessPubKeyHash
             | Operation::BuildPayToTaproot
             | Operation::BuildTaprootTree { .. }
+            | Operation::AddMultiSigKey
             | Operation::AddTxToFilter
             | Operation::AddTxoToFilter
             | Operation::BuildFilterAddFromTx
@@ -113,11 +187,16 @@ impl Instruction {
             | Operation::LoadSequence(..)
             | Operation::LoadSize(..)
             | Operation::LoadNonce(..)
+            | Operation::LoadNonce64(..)
+            | Operation::RandomizeNonce
             | Operation::LoadFilterLoad { .. }
             | Operation::LoadFilterAdd { .. }
+            | Operation::LoadCFilter { .. }
+            | Operation::LoadRawTransaction(_)
             | Operation::AddWitness
             | Operation::SendTx
             | Operation::SendTxNoWit
+            | Operation::SendRawTransaction
             | Operation::AddTxInput
             | Operation::AddTxOutput
             | Operation::AddTxidInv
@@ -125,18 +204,29 @@ impl Instruction {
             | Operation::AddTxidWithWitnessInv
             | Operation::AddCompactBlockInv
             | Operation::AddBlockInv
+            | Operation::AddBlockHeaderInv
             | Operation::AddBlockWithWitnessInv
             | Operation::AddFilteredBlockInv
+            | Operation::AddLocatorHash
+            | Operation::SetLocatorStopHash
             | Operation::AddAddr
             | Operation::AddAddrV2
             | Operation::BuildBlock
+            | Operation::CorruptBlockMerkleRoot(_)
+            | Operation::CorruptBlockProofOfWork
+            | Operation::CorruptBlockCoinbaseValue(_)
             | Operation::AddTx
             | Operation::BuildCoinbaseTxInput
+            | Operation::LoadCoinbaseScriptSig
+            | Operation::BuildCoinbaseTxInputWithScriptSig
             | Operation::AddCoinbaseTxOutput
             | Operation::AddTxToBlockTxn
+            | Operation::AddShortIdToReq
             | Operation::SendGetData
             | Operation::SendGetAddr
             | Operation::SendInv
+            | Operation::SendGetBlocks
+            | Operation::SendGetHeaders
             | Operation::SendAddr
             | Operation::SendAddrV2
             | Operation::SendHeader
@@ -148,8 +238,21 @@ impl Instruction {
             | Operation::SendFilterLoad
             | Operation::SendFilterAdd
             | Operation::SendFilterClear
+            | Operation::SendMempoolMsg
+            | Operation::SendFeeFilter
+            | Operation::SendPingWithNonce
+            | Operation::SendPong
+            | Operation::SendNotFound
             | Operation::SendCompactBlock
+            | Operation::SendMerkleBlock
+            | Operation::SendSendCmpct
+            | Operation::SendSendHeaders
+            | Operation::SendSendTxRcncl
             | Operation::SendBlockTxn
+            | Operation::SendGetBlockTxn
+            | Operation::SendCFilter { .. }
+            | Operation::SendCFHeaders { .. }
+            | Operation::SendCFCheckpt { .. }
             | Operation::TakeCoinbaseTxo
             | Operation::TaprootScriptsUseAnnex
             | Operation::TaprootTxoUseAnnex
@@ -165,6 +268,12 @@ impl Instruction {
             | Operation::BeginWitnessStack
             | Operation::BeginBuildInventory
             | Operation::EndBuildInventory
+            | Operation::BeginBuildGetBlocksLocator
+            | Operation::EndBuildGetBlocksLocator
+            | Operation::BeginBuildMultiSig
+            | Operation::EndBuildMultiSig
+            | Operation::EndBuildMultiSigScriptHash
+            | Operation::EndBuildMultiSigWitnessScriptHash
             | Operation::BeginBuildAddrList
             | Operation::EndBuildAddrList
             | Operation::BeginBuildAddrListV2
@@ -175,12 +284,15 @@ impl Instruction {
             | Operation::BeginBuildFilterLoad
             | Operation::EndBuildFilterLoad
             | Operation::BuildCompactBlock
+            | Operation::BuildMerkleBlock
             | Operation::BeginBuildCoinbaseTx
             | Operation::EndBuildCoinbaseTx
             | Operation::BeginBuildCoinbaseTxOutputs
             | Operation::EndBuildCoinbaseTxOutputs
             | Operation::BeginBuildBlockTxn
             | Operation::EndBuildBlockTxn
+            | Operation::BeginBuildGetBlockTxn
+            | Operation::EndBuildGetBlockTxn
             | Operation::Probe => false,
         }
     }
@@ -195,12 +307,15 @@ impl Instruction {
                 Operation::BeginBuildTxOutputs => Some(InstructionContext::BuildTxOutputs),
                 Operation::BeginWitnessStack => Some(InstructionContext::WitnessStack),
                 Operation::BeginBuildInventory => Some(InstructionContext::Inventory),
+                Operation::BeginBuildGetBlocksLocator => Some(InstructionContext::BlockLocator),
+                Operation::BeginBuildMultiSig => Some(InstructionContext::BuildMultiSig),
                 Operation::BeginBuildAddrList => Some(InstructionContext::AddrList),
                 Operation::BeginBuildAddrListV2 => Some(InstructionContext::AddrListV2),
                 Operation::BeginBlockTransactions => Some(InstructionContext::BlockTransactions),
                 Operation::BeginBuildFilterLoad => Some(InstructionContext::BuildFilter),
                 Operation::BeginBuildCoinbaseTx => Some(InstructionContext::BuildCoinbaseTx),
                 Operation::BeginBuildBlockTxn => Some(InstructionContext::BuildBlockTxn),
+                Operation::BeginBuildGetBlockTxn => Some(InstructionContext::BuildGetBlockTxn),
                 Operation::BeginBuildCoinbaseTxOutputs => {
                     Some(InstructionContext::BuildCoinbaseTxOutputs)
                 }
@@ -230,6 +345,8 @@ pub enum InstructionContext {
     BuildTxOutputs,
     WitnessStack,
     Inventory,
+    BlockLocator,
+    BuildMultiSig,
     AddrList,
     AddrListV2,
     BlockTransactions,
@@ -237,4 +354,5 @@ pub enum InstructionContext {
     BuildCoinbaseTx,
     BuildCoinbaseTxOutputs,
     BuildBlockTxn,
+    BuildGetBlockTxn,
 }
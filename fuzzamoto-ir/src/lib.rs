@@ -1,38 +1,109 @@
 pub mod bloom;
 pub mod builder;
+pub mod canonical;
 pub mod compiler;
+#[cfg(feature = "compress")]
+pub mod compression;
+pub mod context;
+pub mod dictionaries;
 pub mod errors;
+#[cfg(feature = "generators")]
 pub mod generators;
 pub mod instruction;
 pub mod metadata;
 pub mod minimizers;
+#[cfg(feature = "generators")]
 pub mod mutators;
 pub mod operation;
+mod parse;
+pub mod schema;
+#[cfg(test)]
+mod test_utils;
 pub mod variable;
 
 use crate::errors::*;
 pub use bloom::*;
 pub use builder::*;
+pub use canonical::*;
+#[cfg(feature = "compress")]
+pub use compression::*;
+pub use context::*;
+pub use dictionaries::*;
+#[cfg(feature = "generators")]
 pub use generators::*;
 pub use instruction::*;
 pub use metadata::*;
 pub use minimizers::*;
+#[cfg(feature = "generators")]
 pub use mutators::*;
 pub use operation::*;
+pub use schema::*;
 
+use bitcoin::hashes::{Hash as _, HashEngine, sha256};
 pub use fuzzamoto::taproot::*;
+use fuzzamoto::{
+    connections::Transport,
+    scenarios::generic::GenericScenario,
+    targets::{
+        HasGetBlock, HasGetBlockFilter, HasScanTxOutSet, HasTipInfo, Target,
+        bitcoin_core::BlockFilter,
+    },
+};
 use rand::{RngCore, seq::IteratorRandom};
+use rustc_hash::FxHasher;
 pub use variable::*;
 
-use std::{collections::HashMap, fmt, hash::Hash};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+/// `Hasher` adapter for SHA-256, see `Program::hash_stable`
+#[derive(Default, Clone)]
+struct Sha256Hasher(sha256::HashEngine);
+
+impl Hasher for Sha256Hasher {
+    fn finish(&self) -> u64 {
+        let hash = sha256::Hash::from_engine(self.0.clone()).to_byte_array();
+        u64::from_le_bytes(hash[..8].try_into().unwrap())
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.input(bytes);
+    }
+}
 
 /// Program represent a sequence of operations to perform on target nodes.
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Hash)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Hash, PartialEq)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
     pub context: ProgramContext,
 }
 
+/// Maximum number of nodes a program can be executed against
+pub const MAX_NUM_NODES: usize = 16;
+/// Maximum number of pre-existing connections in a program context
+pub const MAX_NUM_CONNECTIONS: usize = 32;
+/// Maximum number of instructions in a program
+pub const MAX_PROGRAM_INSTRUCTIONS: usize = 10_000;
+/// Maximum size of a p2p message payload (Bitcoin Core's `MAX_PROTOCOL_MESSAGE_LENGTH`), bounds
+/// the payload of `Operation::LoadRawBytes`
+pub const MAX_PROTOCOL_MESSAGE_LENGTH: usize = 4 * 1000 * 1000;
+
+/// Estimated execution cost (in milliseconds) of any program, see
+/// `Program::estimate_execution_time_ms`
+const BASE_EXECUTION_TIME_MS: u64 = 1;
+/// Estimated execution cost of mining a block (`BuildBlock`)
+const BUILD_BLOCK_TIME_MS: u64 = 100;
+/// Estimated execution cost of sending a transaction
+const SEND_TX_TIME_MS: u64 = 10;
+/// Estimated execution cost of sending a block
+const SEND_BLOCK_TIME_MS: u64 = 5;
+/// Estimated execution cost of a timeout-prone instruction sequence
+const TIMEOUT_PRONE_TIME_MS: u64 = 50;
+
 /// `ProgramContext` provides a summary of the context in which a program is executed, describing
 /// the snapshot state of the VM.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Hash)]
@@ -45,6 +116,49 @@ pub struct ProgramContext {
     pub timestamp: u64,
 }
 
+impl ProgramContext {
+    /// Check that the context does not exceed `MAX_NUM_NODES` and `MAX_NUM_CONNECTIONS`
+    pub fn validate(&self) -> Result<(), ProgramValidationError> {
+        if self.num_nodes > MAX_NUM_NODES {
+            return Err(ProgramValidationError::ContextExceedsLimits {
+                field: "num_nodes",
+                value: self.num_nodes,
+                max: MAX_NUM_NODES,
+            });
+        }
+        if self.num_connections > MAX_NUM_CONNECTIONS {
+            return Err(ProgramValidationError::ContextExceedsLimits {
+                field: "num_connections",
+                value: self.num_connections,
+                max: MAX_NUM_CONNECTIONS,
+            });
+        }
+        Ok(())
+    }
+
+    /// Capture the context of a running `target`, i.e. a single node without pre-existing
+    /// connections that executes programs at the time of its chain tip
+    pub fn from_running_target<T: HasTipInfo + HasGetBlock>(
+        target: &T,
+    ) -> Result<ProgramContext, String> {
+        let (tip, _) = target
+            .get_tip_info()
+            .ok_or_else(|| "Failed to fetch tip info".to_string())?;
+        let block = target
+            .get_block(tip)
+            .ok_or_else(|| format!("Failed to fetch tip block {}", tip))?;
+
+        Ok(ProgramContext {
+            num_nodes: 1,
+            num_connections: 0,
+            timestamp: u64::from(block.header.time),
+        })
+    }
+}
+
+/// Number of confirmations after which coinbase outputs can be spent
+const COINBASE_MATURITY: u64 = 100;
+
 /// `FullProgramContext` holds the full context in which a program is executed, i.e. information
 /// about the state present in the VM snapshot.
 ///
@@ -62,6 +176,87 @@ pub struct FullProgramContext {
     pub txos: Vec<Txo>,
     /// List of headers present in the snapshotted state
     pub headers: Vec<Header>,
+    /// Compact block filters (BIP-158) of blocks present in the snapshotted state
+    pub block_filters: Vec<(bitcoin::BlockHash, BlockFilter)>,
+}
+
+impl FullProgramContext {
+    /// Fetch the basic compact block filters of `blocks` from `target`
+    pub fn populate_filters<T: HasGetBlockFilter>(
+        &mut self,
+        target: &T,
+        blocks: &[bitcoin::BlockHash],
+    ) -> Result<(), String> {
+        for hash in blocks {
+            let filter = target.getblockfilter(*hash, "basic")?;
+            self.block_filters.push((*hash, filter));
+        }
+        Ok(())
+    }
+
+    /// Capture the full context of a running `target` with `num_connections` pre-existing
+    /// connections. The spendable (i.e. non-coinbase or mature) P2WSH-OP_TRUE outputs of the
+    /// target's UTXO set become the context's txos.
+    pub fn from_running_target<T: HasTipInfo + HasGetBlock + HasScanTxOutSet>(
+        target: &T,
+        num_connections: usize,
+    ) -> Result<FullProgramContext, String> {
+        use bitcoin::hashes::Hash as _;
+
+        let mut context = ProgramContext::from_running_target(target)?;
+        context.num_connections = num_connections;
+
+        let (_, tip_height) = target
+            .get_tip_info()
+            .ok_or_else(|| "Failed to fetch tip info".to_string())?;
+
+        let witness_script = [bitcoin::opcodes::OP_TRUE.to_u8()];
+        let address = bitcoin::Address::p2wsh(
+            bitcoin::Script::from_bytes(&witness_script),
+            bitcoin::Network::Regtest,
+        );
+        let txos = target
+            .scan_tx_out_set(&[format!("addr({})", address)])?
+            .into_iter()
+            .filter(|utxo| {
+                !utxo.coinbase || tip_height.saturating_sub(utxo.height) + 1 >= COINBASE_MATURITY
+            })
+            .map(|utxo| Txo {
+                outpoint: (utxo.txid.to_byte_array(), utxo.vout),
+                value: utxo.amount.to_sat(),
+                script_pubkey: utxo.script_pubkey,
+                spending_script_sig: vec![],
+                spending_witness: vec![witness_script.to_vec()],
+            })
+            .collect();
+
+        Ok(FullProgramContext {
+            context,
+            txos,
+            headers: Vec::new(),
+            block_filters: Vec::new(),
+        })
+    }
+}
+
+/// Capture of the `FullProgramContext` of a scenario, before the VM snapshot is taken
+pub trait DumpContext {
+    fn dump_context(&self) -> Result<FullProgramContext, String>;
+}
+
+impl<TX, T> DumpContext for GenericScenario<TX, T>
+where
+    TX: Transport,
+    T: Target<TX> + HasTipInfo + HasGetBlock + HasScanTxOutSet,
+{
+    /// Capture the full context of the scenario's target, executing programs at the scenario's
+    /// current mocktime over all of its connections
+    fn dump_context(&self) -> Result<FullProgramContext, String> {
+        let mut full_context =
+            FullProgramContext::from_running_target(&self.target, self.num_connections())?;
+        full_context.context.timestamp = self.time;
+        Ok(full_context)
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -150,6 +345,19 @@ pub enum AddrRecord {
     },
 }
 
+/// Read a LEB128 varint (as used by postcard for lengths and integers) from `reader`
+fn read_varint(reader: impl std::io::Read) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, byte) in reader.bytes().take(10).enumerate() {
+        let byte = byte.ok()?;
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
 impl Program {
     pub fn unchecked_new(context: ProgramContext, instructions: Vec<Instruction>) -> Self {
         Self {
@@ -172,6 +380,99 @@ impl Program {
         }
     }
 
+    /// Count how often each operation type (see `Operation::type_name`) occurs in the program
+    pub fn count_by_operation_type(&self) -> HashMap<&'static str, u64> {
+        let mut counts = HashMap::new();
+        for instr in &self.instructions {
+            *counts.entry(instr.operation.type_name()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Read the number of instructions of a program (see `Program::to_bytes_versioned`) from the
+    /// start of `reader`, without reading or decoding the rest of the program
+    pub fn peek_instruction_count(mut reader: impl std::io::Read) -> Option<usize> {
+        // Unversioned programs are at least as long as `SCHEMA_MAGIC` (instruction count and
+        // context)
+        let mut prefix = [0u8; SCHEMA_MAGIC.len()];
+        reader.read_exact(&mut prefix).ok()?;
+        let count = if prefix == SCHEMA_MAGIC {
+            read_varint(&mut reader)?;
+            read_varint(reader)?
+        } else {
+            read_varint(std::io::Read::chain(prefix.as_slice(), reader))?
+        };
+        usize::try_from(count).ok()
+    }
+
+    /// Hash the structure of the program: the operation type and the input variables of every
+    /// instruction. Operand values (e.g. the payload of `LoadBytes`) are ignored, so programs that
+    /// only differ in their operands share the same structural hash.
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        for instr in &self.instructions {
+            std::mem::discriminant(&instr.operation).hash(&mut hasher);
+            instr.inputs.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Hash the operations of the program including their operand values, ignoring the input
+    /// variables. Complements `Program::structural_hash`.
+    pub fn operand_hash(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        for instr in &self.instructions {
+            instr.operation.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Content hash (SHA-256) of the program, stable across platforms and Rust versions (see
+    /// `Operation::hash_stable`). Programs with the same context and instructions share the same
+    /// hash, regardless of how they were built.
+    pub fn hash_stable(&self) -> [u8; 32] {
+        let mut hasher = Sha256Hasher::default();
+        operation::hash_serialized(&self.context, &mut hasher);
+        operation::hash_serialized(&self.instructions.len(), &mut hasher);
+        for instr in &self.instructions {
+            operation::hash_serialized(&instr.inputs, &mut hasher);
+            instr.operation.hash_stable(&mut hasher);
+        }
+        sha256::Hash::from_engine(hasher.0).to_byte_array()
+    }
+
+    /// Heuristic estimate of the time it takes to execute the program (in milliseconds): 1ms plus
+    /// 100ms per mined block (`BuildBlock`), 10ms per sent transaction and 5ms per sent block.
+    ///
+    /// Compact block filter requests following a sent block add another 50ms each, as the node
+    /// blocks until its filter index caught up with the new block before responding (which is
+    /// prone to timeouts).
+    pub fn estimate_execution_time_ms(&self) -> u64 {
+        let mut block_sent = false;
+        let mut estimate = BASE_EXECUTION_TIME_MS;
+        for instr in &self.instructions {
+            estimate += match instr.operation {
+                Operation::BuildBlock => BUILD_BLOCK_TIME_MS,
+                Operation::SendTx | Operation::SendTxNoWit | Operation::SendRawTransaction => {
+                    SEND_TX_TIME_MS
+                }
+                Operation::SendBlock | Operation::SendBlockNoWit | Operation::SendCompactBlock => {
+                    block_sent = true;
+                    SEND_BLOCK_TIME_MS
+                }
+                Operation::SendGetCFilters
+                | Operation::SendGetCFHeaders
+                | Operation::SendGetCFCheckpt
+                    if block_sent =>
+                {
+                    TIMEOUT_PRONE_TIME_MS
+                }
+                _ => 0,
+            };
+        }
+        estimate
+    }
+
     pub fn remove_nops(&mut self) {
         debug_assert!(self.is_statically_valid());
 
@@ -208,6 +509,147 @@ impl Program {
         debug_assert!(self.is_statically_valid());
     }
 
+    /// Reorder the program's instructions while preserving all data-flow dependencies, the
+    /// relative order of instructions with side effects (e.g. sends) and the block structure.
+    ///
+    /// Ready instructions are emitted in the order of their earliest dependent (using Kahn's
+    /// algorithm), i.e. instructions feeding early sends move towards the front and instructions
+    /// without any dependents sink to the end of their block, where they are easier to cut.
+    pub fn topological_sort(&self) -> Result<Program, ProgramValidationError> {
+        ProgramBuilder::from_program(self.clone())?.finalize()?;
+
+        let predecessors = self.instruction_predecessors();
+
+        // Build the block tree, sort it and remap the variables
+        let mut stack = vec![Vec::new()];
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            if instruction.operation.is_block_end() {
+                let body = stack.pop().unwrap();
+                let SortNode::Block { end, body: b, .. } = stack
+                    .last_mut()
+                    .unwrap()
+                    .last_mut()
+                    .expect("Block end requires a block begin")
+                else {
+                    unreachable!("Block end requires a block begin");
+                };
+                *end = index;
+                *b = body;
+            }
+            if instruction.operation.is_block_begin() {
+                stack.last_mut().unwrap().push(SortNode::Block {
+                    begin: index,
+                    body: Vec::new(),
+                    end: index,
+                });
+                stack.push(Vec::new());
+            } else if !instruction.operation.is_block_end() {
+                stack.last_mut().unwrap().push(SortNode::Instruction(index));
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.instructions.len());
+        sort_nodes(stack.pop().unwrap(), &predecessors, &mut order);
+
+        let sorted = self.reordered(&order);
+        debug_assert!(sorted.is_statically_valid());
+        Ok(sorted)
+    }
+
+    /// Collect the instructions each instruction depends on, i.e. the instructions that define
+    /// its inputs, previous users of variables it mutates and (for instructions with side
+    /// effects) the previous instruction with side effects.
+    pub(crate) fn instruction_predecessors(&self) -> Vec<HashSet<usize>> {
+        let (defined_by, _) = self.variable_definitions();
+
+        let mutated: HashSet<usize> = self
+            .instructions
+            .iter()
+            .flat_map(|instruction| {
+                instruction
+                    .inputs
+                    .iter()
+                    .enumerate()
+                    .filter(|(nth, _)| instruction.operation.mutates_nth_input(*nth))
+                    .map(|(_, input)| *input)
+            })
+            .collect();
+
+        let mut predecessors = vec![HashSet::new(); self.instructions.len()];
+        let mut last_user: HashMap<usize, usize> = HashMap::new();
+        let mut last_side_effect = None;
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            let operation = &instruction.operation;
+            for input in &instruction.inputs {
+                predecessors[index].insert(defined_by[*input]);
+
+                // Keep all users of a mutated variable in their original order
+                if mutated.contains(input) {
+                    if let Some(user) = last_user.get(input) {
+                        predecessors[index].insert(*user);
+                    }
+                    last_user.insert(*input, index);
+                }
+            }
+
+            // Instructions that neither produce nor mutate variables have side effects (e.g.
+            // sending a message), so they keep their original order.
+            let has_side_effects = operation.num_outputs() == 0
+                && operation.num_inner_outputs() == 0
+                && !(0..operation.num_inputs()).any(|nth| operation.mutates_nth_input(nth));
+            if has_side_effects {
+                if let Some(previous) = last_side_effect {
+                    predecessors[index].insert(previous);
+                }
+                last_side_effect = Some(index);
+            }
+            predecessors[index].remove(&index);
+        }
+
+        predecessors
+    }
+
+    /// Map each variable to the instruction defining it and each instruction to the index of its
+    /// first variable
+    fn variable_definitions(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut defined_by = Vec::new();
+        let mut variable_offsets = Vec::with_capacity(self.instructions.len());
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            variable_offsets.push(defined_by.len());
+            let num_variables =
+                instruction.operation.num_outputs() + instruction.operation.num_inner_outputs();
+            defined_by.extend(std::iter::repeat_n(index, num_variables));
+        }
+        (defined_by, variable_offsets)
+    }
+
+    /// Create a copy of the program with its instructions in the given `order` (a permutation of
+    /// the instruction indices) and the variables renumbered accordingly. The caller is
+    /// responsible for ensuring that the order respects the dependencies between instructions.
+    pub(crate) fn reordered(&self, order: &[usize]) -> Program {
+        let (defined_by, variable_offsets) = self.variable_definitions();
+
+        let mut variable_mapping = vec![0usize; defined_by.len()];
+        let mut variable_count = 0;
+        let mut instructions = Vec::with_capacity(self.instructions.len());
+        for &index in order {
+            let mut instruction = self.instructions[index].clone();
+            for input in &mut instruction.inputs {
+                *input = variable_mapping[*input];
+            }
+
+            let num_variables =
+                instruction.operation.num_outputs() + instruction.operation.num_inner_outputs();
+            for offset in 0..num_variables {
+                variable_mapping[variable_offsets[index] + offset] = variable_count;
+                variable_count += 1;
+            }
+            instructions.push(instruction);
+        }
+
+        Program::unchecked_new(self.context.clone(), instructions)
+    }
+
     pub fn get_random_instruction_index<R: RngCore>(
         &self,
         rng: &mut R,
@@ -255,8 +697,127 @@ impl Program {
     }
 }
 
-impl fmt::Display for Program {
+/// Node in the block tree of a program, used by `Program::topological_sort`
+enum SortNode {
+    Instruction(usize),
+    Block {
+        begin: usize,
+        body: Vec<SortNode>,
+        end: usize,
+    },
+}
+
+impl SortNode {
+    fn first_index(&self) -> usize {
+        match self {
+            SortNode::Instruction(index) => *index,
+            SortNode::Block { begin, .. } => *begin,
+        }
+    }
+
+    fn collect_indices(&self, indices: &mut Vec<usize>) {
+        match self {
+            SortNode::Instruction(index) => indices.push(*index),
+            SortNode::Block { begin, body, end } => {
+                indices.push(*begin);
+                for node in body {
+                    node.collect_indices(indices);
+                }
+                indices.push(*end);
+            }
+        }
+    }
+}
+
+/// Sort sibling nodes (i.e. nodes in the same block) using Kahn's algorithm and append the
+/// resulting instruction order to `order`
+fn sort_nodes(nodes: Vec<SortNode>, predecessors: &[HashSet<usize>], order: &mut Vec<usize>) {
+    let mut owner = HashMap::new();
+    let mut node_indices = Vec::with_capacity(nodes.len());
+    for (node_index, node) in nodes.iter().enumerate() {
+        let mut indices = Vec::new();
+        node.collect_indices(&mut indices);
+        for index in &indices {
+            owner.insert(*index, node_index);
+        }
+        node_indices.push(indices);
+    }
+
+    // Lift the instruction dependencies to dependencies between sibling nodes
+    let mut node_predecessors = vec![HashSet::new(); nodes.len()];
+    let mut node_successors = vec![HashSet::new(); nodes.len()];
+    for (node_index, indices) in node_indices.iter().enumerate() {
+        for index in indices {
+            for predecessor in &predecessors[*index] {
+                if let Some(&other) = owner.get(predecessor)
+                    && other != node_index
+                {
+                    node_predecessors[node_index].insert(other);
+                    node_successors[other].insert(node_index);
+                }
+            }
+        }
+    }
+
+    let priority = |node_index: usize| {
+        let earliest_dependent = node_successors[node_index]
+            .iter()
+            .map(|successor| nodes[*successor].first_index())
+            .min()
+            .unwrap_or(usize::MAX);
+        Reverse((
+            earliest_dependent,
+            nodes[node_index].first_index(),
+            node_index,
+        ))
+    };
+
+    let mut ready: BinaryHeap<_> = (0..nodes.len())
+        .filter(|node_index| node_predecessors[*node_index].is_empty())
+        .map(priority)
+        .collect();
+    let mut sorted = Vec::with_capacity(nodes.len());
+    while let Some(Reverse((_, _, node_index))) = ready.pop() {
+        sorted.push(node_index);
+        for successor in &node_successors[node_index] {
+            node_predecessors[*successor].remove(&node_index);
+            if node_predecessors[*successor].is_empty() {
+                ready.push(priority(*successor));
+            }
+        }
+    }
+    debug_assert_eq!(sorted.len(), nodes.len());
+
+    let mut nodes: Vec<Option<SortNode>> = nodes.into_iter().map(Some).collect();
+    for node_index in sorted {
+        match nodes[node_index].take().unwrap() {
+            SortNode::Instruction(index) => order.push(index),
+            SortNode::Block { begin, body, end } => {
+                order.push(begin);
+                sort_nodes(body, predecessors, order);
+                order.push(end);
+            }
+        }
+    }
+}
+
+/// Wrapper displaying a program in the annotated format (see `Program::to_annotated_string`)
+struct AnnotatedProgram<'a>(&'a Program);
+
+impl fmt::Display for AnnotatedProgram<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_program(f, true)
+    }
+}
+
+impl Program {
+    /// Render the program with the type of each variable, e.g.
+    /// `BeginBuildTx(v1:TxVersion, v2:LockTime) -> v3:MutTx`
+    pub fn to_annotated_string(&self) -> String {
+        AnnotatedProgram(self).to_string()
+    }
+
+    fn fmt_program(&self, f: &mut fmt::Formatter<'_>, annotated: bool) -> fmt::Result {
         write!(
             f,
             "// Context: nodes={} connections={} timestamp={}\n",
@@ -264,6 +825,7 @@ impl fmt::Display for Program {
         )?;
         let mut var_counter = 0;
         let mut indent_counter = 0;
+        let mut variables = Vec::new();
 
         for instruction in &self.instructions {
             if indent_counter > 0 {
@@ -275,40 +837,53 @@ impl fmt::Display for Program {
                 write!(f, "{}", "  ".repeat(indent_counter - offset))?;
             }
 
-            if instruction.operation.num_outputs() > 0 {
-                for _ in 0..(instruction.operation.num_outputs() - 1) {
-                    write!(f, "v{}, ", var_counter)?;
+            if annotated {
+                for var in instruction
+                    .operation
+                    .get_output_variables()
+                    .into_iter()
+                    .chain(instruction.operation.get_inner_output_variables())
+                {
+                    variables.push((variables.len(), var));
+                }
+                instruction.display_with_variables(&variables, f)?;
+                write!(f, "\n")?;
+            } else {
+                if instruction.operation.num_outputs() > 0 {
+                    for _ in 0..(instruction.operation.num_outputs() - 1) {
+                        write!(f, "v{}, ", var_counter)?;
+                        var_counter += 1;
+                    }
+                    write!(f, "v{}", var_counter)?;
                     var_counter += 1;
+                    write!(f, " <- ")?;
                 }
-                write!(f, "v{}", var_counter)?;
-                var_counter += 1;
-                write!(f, " <- ")?;
-            }
-            write!(f, "{}", instruction.operation)?;
+                write!(f, "{}", instruction.operation)?;
 
-            if instruction.operation.num_inputs() > 0 {
-                write!(f, "(")?;
-                for input in &instruction.inputs[..instruction.operation.num_inputs() - 1] {
-                    write!(f, "v{}, ", input)?;
+                if instruction.operation.num_inputs() > 0 {
+                    write!(f, "(")?;
+                    for input in &instruction.inputs[..instruction.operation.num_inputs() - 1] {
+                        write!(f, "v{}, ", input)?;
+                    }
+                    write!(
+                        f,
+                        "v{}",
+                        instruction.inputs[instruction.operation.num_inputs() - 1]
+                    )?;
+                    write!(f, ")")?;
                 }
-                write!(
-                    f,
-                    "v{}",
-                    instruction.inputs[instruction.operation.num_inputs() - 1]
-                )?;
-                write!(f, ")")?;
-            }
 
-            if instruction.operation.num_inner_outputs() > 0 {
-                write!(f, " -> ")?;
-                for _ in 0..(instruction.operation.num_inner_outputs() - 1) {
-                    write!(f, "v{}, ", var_counter)?;
+                if instruction.operation.num_inner_outputs() > 0 {
+                    write!(f, " -> ")?;
+                    for _ in 0..(instruction.operation.num_inner_outputs() - 1) {
+                        write!(f, "v{}, ", var_counter)?;
+                        var_counter += 1;
+                    }
+                    write!(f, "v{}", var_counter)?;
                     var_counter += 1;
                 }
-                write!(f, "v{}", var_counter)?;
-                var_counter += 1;
+                write!(f, "\n")?;
             }
-            write!(f, "\n")?;
 
             if instruction.operation.is_block_begin() {
                 indent_counter += 1;
@@ -321,6 +896,12 @@ impl fmt::Display for Program {
     }
 }
 
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_program(f, std::env::var("FUZZAMOTO_VERBOSE_DISPLAY").is_ok())
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct GetBlockTxn {
     /// Variable index of the connection
@@ -348,6 +929,14 @@ pub enum ProbeResult {
     RecentBlockes {
         result: Vec<RecentBlock>,
     },
+    Runtime {
+        runtime: ObservedNodeState,
+    },
+    /// Commands and payload lengths of all messages the node under test sent in response to the
+    /// testcase (excluding `pong`s)
+    ReceivedMessages {
+        messages: Vec<(String, usize)>,
+    },
 }
 
 pub type ProbeResults = Vec<ProbeResult>;
@@ -380,3 +969,328 @@ impl Ord for RecentBlock {
         self.height.cmp(&other.height)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::test_utils;
+
+    struct MockTarget {
+        tip: bitcoin::Block,
+        height: u64,
+        unspents: Vec<fuzzamoto::targets::bitcoin_core::UnspentOutput>,
+    }
+
+    impl HasTipInfo for MockTarget {
+        fn get_tip_info(&self) -> Option<(bitcoin::BlockHash, u64)> {
+            Some((self.tip.block_hash(), self.height))
+        }
+    }
+
+    impl HasGetBlock for MockTarget {
+        fn get_block(&self, hash: bitcoin::BlockHash) -> Option<bitcoin::Block> {
+            (hash == self.tip.block_hash()).then(|| self.tip.clone())
+        }
+    }
+
+    impl HasScanTxOutSet for MockTarget {
+        fn scan_tx_out_set(
+            &self,
+            descriptors: &[String],
+        ) -> Result<Vec<fuzzamoto::targets::bitcoin_core::UnspentOutput>, String> {
+            assert_eq!(descriptors.len(), 1);
+            assert!(descriptors[0].starts_with("addr(bcrt1"));
+            Ok(self.unspents.clone())
+        }
+    }
+
+    fn mock_target() -> MockTarget {
+        use bitcoin::hashes::Hash as _;
+
+        let mut tip = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        tip.header.time = 1_296_688_802;
+
+        let unspent = |index: u8, coinbase: bool, height: u64| {
+            fuzzamoto::targets::bitcoin_core::UnspentOutput {
+                txid: bitcoin::Txid::from_byte_array([index; 32]),
+                vout: u32::from(index),
+                script_pubkey: vec![0x00, 0x20],
+                amount: bitcoin::Amount::from_sat(u64::from(index) * 1000),
+                coinbase,
+                height,
+            }
+        };
+
+        MockTarget {
+            tip,
+            height: 200,
+            unspents: vec![
+                unspent(1, true, 101),
+                unspent(2, true, 102),
+                unspent(3, false, 200),
+            ],
+        }
+    }
+
+    #[test]
+    fn program_context_from_running_target() {
+        let context = ProgramContext::from_running_target(&mock_target()).unwrap();
+        assert_eq!(
+            context,
+            ProgramContext {
+                num_nodes: 1,
+                num_connections: 0,
+                timestamp: 1_296_688_802,
+            }
+        );
+    }
+
+    #[test]
+    fn full_program_context_skips_immature_coinbases() {
+        let full_context = FullProgramContext::from_running_target(&mock_target(), 3).unwrap();
+        assert_eq!(full_context.context.num_connections, 3);
+        assert!(full_context.headers.is_empty());
+
+        // The coinbase at height 102 only has 99 confirmations
+        let outpoints: Vec<([u8; 32], u32)> =
+            full_context.txos.iter().map(|txo| txo.outpoint).collect();
+        assert_eq!(outpoints, vec![([1; 32], 1), ([3; 32], 3)]);
+        assert_eq!(full_context.txos[0].value, 1000);
+        assert_eq!(full_context.txos[0].spending_witness, vec![vec![0x51]]);
+    }
+
+    #[test]
+    fn execution_time_estimate() {
+        let program = |operations: Vec<Operation>| {
+            Program::unchecked_new(
+                ProgramContext {
+                    num_nodes: 1,
+                    num_connections: 1,
+                    timestamp: 0,
+                },
+                operations
+                    .into_iter()
+                    .map(|operation| Instruction {
+                        inputs: vec![],
+                        operation,
+                    })
+                    .collect(),
+            )
+        };
+
+        assert_eq!(program(vec![]).estimate_execution_time_ms(), 1);
+        assert_eq!(
+            program(vec![
+                Operation::LoadTime(0),
+                Operation::SendTx,
+                Operation::SendTxNoWit
+            ])
+            .estimate_execution_time_ms(),
+            21
+        );
+        // Filter requests are only timeout-prone once a block was sent
+        assert_eq!(
+            program(vec![
+                Operation::SendGetCFilters,
+                Operation::BuildBlock,
+                Operation::SendBlock,
+                Operation::SendGetCFHeaders,
+                Operation::SendGetCFCheckpt,
+            ])
+            .estimate_execution_time_ms(),
+            1 + 100 + 5 + 50 + 50
+        );
+    }
+
+    #[test]
+    fn annotated_display_includes_variable_types() {
+        let mut builder = test_utils::builder();
+        let version = builder.force_append_expect_output(vec![], Operation::LoadTxVersion(2));
+        let lock_time = builder.force_append_expect_output(vec![], Operation::LoadLockTime(0));
+        builder.force_append(
+            vec![version.index, lock_time.index],
+            Operation::BeginBuildTx,
+        );
+        let program =
+            Program::unchecked_new(builder.context().clone(), builder.instructions.clone());
+
+        let annotated = program.to_annotated_string();
+        let lines: Vec<&str> = annotated.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "v0:TxVersion <- LoadTxVersion(2)",
+                "v1:LockTime <- LoadLockTime(0)",
+                "BeginBuildTx(v0:TxVersion, v1:LockTime) -> v2:MutTx",
+            ]
+        );
+    }
+
+    #[test]
+    fn topological_sort_swaps_independent_loads() {
+        let mut builder = test_utils::builder();
+        let conn = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let msg_type =
+            builder.force_append_expect_output(vec![], Operation::LoadMsgType(['a'; 12]));
+        let first = builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![1]));
+        let second = builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![2]));
+        builder.force_append(
+            vec![conn.index, msg_type.index, second.index],
+            Operation::SendRawMessage,
+        );
+        builder.force_append(
+            vec![conn.index, msg_type.index, first.index],
+            Operation::SendRawMessage,
+        );
+        let program = builder.finalize().unwrap();
+
+        let sorted = program.topological_sort().unwrap();
+        assert!(sorted.is_statically_valid());
+
+        let position = |program: &Program, bytes: Vec<u8>| {
+            program
+                .instructions
+                .iter()
+                .position(|instruction| {
+                    instruction.operation == Operation::LoadBytes(bytes.clone())
+                })
+                .unwrap()
+        };
+        assert!(position(&program, vec![1]) < position(&program, vec![2]));
+        assert!(position(&sorted, vec![2]) < position(&sorted, vec![1]));
+
+        let original_actions = Compiler::new().compile(&program).unwrap().actions;
+        let sorted_actions = Compiler::new().compile(&sorted).unwrap().actions;
+        assert_eq!(
+            format!("{:?}", original_actions),
+            format!("{:?}", sorted_actions)
+        );
+    }
+
+    #[test]
+    fn count_by_operation_type() {
+        let mut builder = test_utils::builder();
+        builder.force_append(vec![], Operation::LoadBytes(vec![1]));
+        builder.force_append(vec![], Operation::LoadBytes(vec![2]));
+        builder.force_append(vec![], Operation::LoadConnection(0));
+        builder.force_append(
+            vec![],
+            Operation::Nop {
+                outputs: 0,
+                inner_outputs: 0,
+            },
+        );
+        let counts = builder.finalize().unwrap().count_by_operation_type();
+
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts["LoadBytes"], 2);
+        assert_eq!(counts["LoadConnection"], 1);
+        assert_eq!(counts["Nop"], 1);
+    }
+
+    #[test]
+    fn peek_instruction_count() {
+        let mut builder = test_utils::builder();
+        for i in 0..200 {
+            builder.force_append(vec![], Operation::LoadTime(i));
+        }
+        let program = builder.finalize().unwrap();
+
+        for bytes in [
+            program.to_bytes_versioned(),
+            postcard::to_allocvec(&program).unwrap(),
+        ] {
+            assert_eq!(Program::peek_instruction_count(bytes.as_slice()), Some(200));
+            assert_eq!(Program::peek_instruction_count(&bytes[..1]), None);
+        }
+    }
+
+    #[test]
+    fn structural_hash_ignores_operands() {
+        let program = |amount: u64| {
+            let mut builder = test_utils::builder();
+            builder.force_append(vec![], Operation::LoadAmount(amount));
+            builder.force_append(vec![], Operation::LoadConnection(0));
+            builder.finalize().unwrap()
+        };
+        let (first, second) = (program(1_000), program(2_000));
+
+        assert_eq!(first.structural_hash(), second.structural_hash());
+        assert_ne!(first.operand_hash(), second.operand_hash());
+    }
+
+    #[test]
+    fn hash_stable_ignores_history() {
+        let context = ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        };
+
+        let mut builder = ProgramBuilder::new(context.clone());
+        let time = builder.force_append_expect_output(vec![], Operation::LoadTime(1_000));
+        let duration = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadDuration(std::time::Duration::from_secs(60)),
+        );
+        builder.force_append(vec![time.index, duration.index], Operation::AdvanceTime);
+        let direct = builder.finalize().unwrap();
+
+        // Same instructions, but built with a since removed instruction in between
+        let mut builder = ProgramBuilder::new(context);
+        let time = builder.force_append_expect_output(vec![], Operation::LoadTime(1_000));
+        builder.force_append(vec![], Operation::LoadBytes(vec![0xff; 32]));
+        let duration = builder.force_append_expect_output(
+            vec![],
+            Operation::LoadDuration(std::time::Duration::from_secs(60)),
+        );
+        builder.force_append(vec![time.index, duration.index], Operation::AdvanceTime);
+        let mut rebuilt = builder.finalize().unwrap();
+        rebuilt.instructions[1].operation = Operation::Nop {
+            outputs: 1,
+            inner_outputs: 0,
+        };
+        rebuilt.remove_nops();
+
+        assert_eq!(direct, rebuilt);
+        assert_eq!(direct.hash_stable(), rebuilt.hash_stable());
+
+        // Operands, inputs and the context are part of the hash
+        let mut changed = direct.clone();
+        changed.instructions[0].operation = Operation::LoadTime(1_001);
+        assert_ne!(direct.hash_stable(), changed.hash_stable());
+        let mut changed = direct.clone();
+        changed.instructions[2].inputs.swap(0, 1);
+        assert_ne!(direct.hash_stable(), changed.hash_stable());
+        let mut changed = direct.clone();
+        changed.context.timestamp = 1;
+        assert_ne!(direct.hash_stable(), changed.hash_stable());
+    }
+
+    #[test]
+    fn operation_hash_stable_is_encoding() {
+        let mut bytes = Vec::new();
+        Operation::LoadTime(300).hash_stable(&mut BytesHasher(&mut bytes));
+        // Variant index followed by the varint encoded operand
+        assert_eq!(bytes[1..], [0xac, 0x02]);
+
+        let mut other = Vec::new();
+        Operation::LoadAmount(300).hash_stable(&mut BytesHasher(&mut other));
+        assert_eq!(bytes[1..], other[1..]);
+        assert_ne!(bytes[0], other[0]);
+    }
+
+    /// Hasher recording the hashed bytes
+    struct BytesHasher<'a>(&'a mut Vec<u8>);
+
+    impl Hasher for BytesHasher<'_> {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.0.extend_from_slice(bytes);
+        }
+    }
+}
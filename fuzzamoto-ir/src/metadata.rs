@@ -1,12 +1,71 @@
+use bitcoin::{BlockHash, Txid};
 use serde::{Deserialize, Serialize};
 
 use crate::{GetBlockTxn, RecentBlock};
 
+/// State of the node under test observed at the end of an execution
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObservedNodeState {
+    /// Txids of the transactions in the mempool
+    pub txids: Vec<Txid>,
+    /// Hashes of the most recent blocks in the active chain
+    pub block_hashes: Vec<BlockHash>,
+    /// Number of connected peers
+    pub peer_count: usize,
+    /// Number of transactions in the mempool
+    pub mempool_size: u64,
+}
+
+impl ObservedNodeState {
+    /// Combine the metadata of two executions. Txids and block hashes are deduplicated, while the
+    /// counters keep the maximum observed value.
+    pub fn merge(mut self, other: ObservedNodeState) -> ObservedNodeState {
+        for txid in other.txids {
+            if !self.txids.contains(&txid) {
+                self.txids.push(txid);
+            }
+        }
+        for block_hash in other.block_hashes {
+            if !self.block_hashes.contains(&block_hash) {
+                self.block_hashes.push(block_hash);
+            }
+        }
+        self.peer_count = self.peer_count.max(other.peer_count);
+        self.mempool_size = self.mempool_size.max(other.mempool_size);
+        self
+    }
+}
+
+/// Instructions of a program that were produced by a single generator invocation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenerationEvent {
+    pub generator_name: String,
+    /// Range of instruction indices (`start..end`) produced by the generator
+    pub instruction_range: (usize, usize),
+}
+
+impl GenerationEvent {
+    pub fn contains(&self, index: usize) -> bool {
+        (self.instruction_range.0..self.instruction_range.1).contains(&index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.instruction_range.1 - self.instruction_range.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 /// The runtime data observed during the course of harness execution
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct PerTestcaseMetadata {
     pub block_txn_request: Vec<GetBlockTxn>,
     pub recent_blocks: Vec<RecentBlock>,
+    pub runtime: Option<ObservedNodeState>,
+    /// Generator invocations that contributed to the testcase, in the order they happened
+    pub generation_stack: Vec<GenerationEvent>,
 }
 
 impl PerTestcaseMetadata {
@@ -14,6 +73,8 @@ impl PerTestcaseMetadata {
         Self {
             block_txn_request: Vec::new(),
             recent_blocks: Vec::new(),
+            runtime: None,
+            generation_stack: Vec::new(),
         }
     }
 
@@ -25,6 +86,10 @@ impl PerTestcaseMetadata {
         &self.recent_blocks
     }
 
+    pub fn runtime(&self) -> Option<&ObservedNodeState> {
+        self.runtime.as_ref()
+    }
+
     pub fn add_block_tx_request(&mut self, req: GetBlockTxn) {
         self.block_txn_request.push(req);
     }
@@ -33,4 +98,89 @@ impl PerTestcaseMetadata {
         self.recent_blocks = blocks;
         self.recent_blocks.sort();
     }
+
+    /// Record a generator invocation. Previously recorded ranges at or after the insertion point
+    /// are shifted by the number of inserted instructions.
+    pub fn record_generation(&mut self, event: GenerationEvent) {
+        let (start, _) = event.instruction_range;
+        let inserted = event.len();
+        for previous in &mut self.generation_stack {
+            if previous.instruction_range.0 >= start {
+                previous.instruction_range.0 += inserted;
+                previous.instruction_range.1 += inserted;
+            } else if previous.instruction_range.1 > start {
+                // Instructions were inserted in the middle of a previous invocation
+                previous.instruction_range.1 += inserted;
+            }
+        }
+        self.generation_stack.push(event);
+    }
+
+    /// Names of the generators (outermost first) that produced the instruction at `index`
+    pub fn generators_at_instruction(&self, index: usize) -> Vec<&str> {
+        self.generation_stack
+            .iter()
+            .filter(|event| event.contains(index))
+            .map(|event| event.generator_name.as_str())
+            .collect()
+    }
+
+    pub fn add_runtime(&mut self, runtime: ObservedNodeState) {
+        self.runtime = Some(match self.runtime.take() {
+            Some(existing) => existing.merge(runtime),
+            None => runtime,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    #[test]
+    fn merge_deduplicates_and_keeps_max_counts() {
+        let txid = |b: u8| Txid::from_byte_array([b; 32]);
+        let first = ObservedNodeState {
+            txids: vec![txid(1), txid(2)],
+            block_hashes: vec![BlockHash::from_byte_array([1; 32])],
+            peer_count: 3,
+            mempool_size: 2,
+        };
+        let second = ObservedNodeState {
+            txids: vec![txid(2), txid(3)],
+            block_hashes: vec![BlockHash::from_byte_array([1; 32])],
+            peer_count: 1,
+            mempool_size: 5,
+        };
+
+        let merged = first.merge(second);
+        assert_eq!(merged.txids, vec![txid(1), txid(2), txid(3)]);
+        assert_eq!(merged.block_hashes.len(), 1);
+        assert_eq!(merged.peer_count, 3);
+        assert_eq!(merged.mempool_size, 5);
+    }
+
+    #[test]
+    fn generation_stack_tracks_shifted_ranges() {
+        let event = |name: &str, start, end| GenerationEvent {
+            generator_name: name.to_string(),
+            instruction_range: (start, end),
+        };
+
+        let mut meta = PerTestcaseMetadata::new();
+        meta.record_generation(event("A", 0, 4));
+        meta.record_generation(event("B", 4, 6));
+        // Inserted in the middle of A, in front of B
+        meta.record_generation(event("C", 2, 5));
+
+        assert_eq!(
+            meta.generation_stack,
+            vec![event("A", 0, 7), event("B", 7, 9), event("C", 2, 5)]
+        );
+        assert_eq!(meta.generators_at_instruction(1), vec!["A"]);
+        assert_eq!(meta.generators_at_instruction(3), vec!["A", "C"]);
+        assert_eq!(meta.generators_at_instruction(8), vec!["B"]);
+        assert!(meta.generators_at_instruction(9).is_empty());
+    }
 }
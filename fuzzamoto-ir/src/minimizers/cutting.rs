@@ -1,9 +1,21 @@
-use crate::Program;
+use std::{collections::HashMap, ops::Range};
+
+use crate::{Program, ProgramBuilder};
 
 use super::Minimizer;
 
+/// `CuttingMinimizer` minimizes a program by cutting off its tail, binary searching for the
+/// shortest prefix that is still interesting.
+///
+/// Candidates are created by removing the tail from a builder of the shortest interesting prefix
+/// (see `ProgramBuilder::remove_instruction`), instead of rebuilding and re-validating every
+/// candidate from scratch.
 pub struct CuttingMinimizer {
     original: Program,
+    // Builder of the shortest interesting prefix (`None` if the original program is invalid)
+    builder: Option<ProgramBuilder>,
+    // Builder of the last candidate
+    candidate: Option<ProgramBuilder>,
 
     current: usize,
     chopped: usize,
@@ -12,6 +24,8 @@ pub struct CuttingMinimizer {
 impl Minimizer for CuttingMinimizer {
     fn new(program: Program) -> Self {
         Self {
+            builder: ProgramBuilder::from_program(program.clone()).ok(),
+            candidate: None,
             original: program.clone(),
             current: (program.instructions.len() as f64 / 2.0) as usize,
             chopped: program.instructions.len(),
@@ -19,27 +33,116 @@ impl Minimizer for CuttingMinimizer {
     }
 
     fn success(&mut self) {
+        if let Some(candidate) = self.candidate.take() {
+            self.builder = Some(candidate);
+        }
         self.chopped = self.current;
         self.current = (self.current as f64 / 2.0) as usize;
     }
 
     fn failure(&mut self) {
+        self.candidate = None;
         self.current += (((self.chopped - self.current) as f64 / 2.0) as usize).max(1);
     }
 }
 
+impl CuttingMinimizer {
+    /// Cut the instructions from `index` onwards from the shortest interesting prefix, returning
+    /// `None` if the cut splits a block.
+    fn cut_tail(&self, index: usize) -> Option<ProgramBuilder> {
+        let mut builder = self.builder.clone()?;
+
+        // Iterate backwards, such that only the last instruction (or an empty block) is removed
+        // at a time. Block ends are skipped, they are removed along with their beginning.
+        let mut open_blocks = 0usize;
+        for instr_index in (index..builder.instructions.len()).rev() {
+            let operation = &builder.instructions[instr_index].operation;
+            if operation.is_block_end() {
+                open_blocks += 1;
+                continue;
+            }
+            if operation.is_block_begin() {
+                open_blocks -= 1;
+            }
+            builder.remove_instruction(instr_index).ok()?;
+        }
+
+        (open_blocks == 0).then_some(builder)
+    }
+
+    /// Cut the instructions in `range` from the original program, returning `None` if the result
+    /// is not statically valid (e.g. if the cut splits a block).
+    pub fn try_cut(&self, range: Range<usize>) -> Option<Program> {
+        let program = self.cut(range);
+        program.is_statically_valid().then_some(program)
+    }
+
+    /// Cut the instructions in `range` from the original program without validating the result.
+    ///
+    /// Instructions after the cut that (transitively) depend on variables produced by the cut
+    /// instructions are removed as well, and the inputs of all remaining instructions are
+    /// renumbered to account for the removed variables.
+    fn cut(&self, range: Range<usize>) -> Program {
+        // Map variable indices of the original program to indices in the cut program. Variables
+        // produced by removed instructions have no entry.
+        let mut remapping: HashMap<usize, usize> = HashMap::new();
+        let mut variable_count = 0;
+        let mut variable_count_cut = 0;
+        let mut instructions = Vec::with_capacity(self.original.instructions.len());
+
+        for (index, instruction) in self.original.instructions.iter().enumerate() {
+            let num_variables =
+                instruction.operation.num_outputs() + instruction.operation.num_inner_outputs();
+
+            let inputs: Option<Vec<usize>> = instruction
+                .inputs
+                .iter()
+                .map(|input| remapping.get(input).copied())
+                .collect();
+            match inputs {
+                Some(inputs) if !range.contains(&index) => {
+                    for variable in variable_count..variable_count + num_variables {
+                        remapping.insert(variable, variable_count_cut);
+                        variable_count_cut += 1;
+                    }
+
+                    let mut instruction = instruction.clone();
+                    instruction.inputs = inputs;
+                    instructions.push(instruction);
+                }
+                // Cut, or depends on a variable that no longer exists
+                _ => {}
+            }
+            variable_count += num_variables;
+        }
+
+        Program::unchecked_new(self.original.context.clone(), instructions)
+    }
+}
+
 impl Iterator for CuttingMinimizer {
     type Item = Program;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current == self.chopped || self.current > self.original.instructions.len() {
-            return None;
-        }
+        loop {
+            if self.current == self.chopped || self.current > self.original.instructions.len() {
+                return None;
+            }
 
-        Some(Program::unchecked_new(
-            self.original.context.clone(),
-            self.original.instructions[..self.current].to_vec(),
-        ))
+            let program = if self.builder.is_some() {
+                self.candidate = self.cut_tail(self.current);
+                self.candidate
+                    .as_ref()
+                    .and_then(|builder| builder.finalize().ok())
+            } else {
+                self.try_cut(self.current..self.original.instructions.len())
+            };
+            match program {
+                Some(program) => return Some(program),
+                // Cutting here results in an invalid program, which can't be interesting
+                None => self.failure(),
+            }
+        }
     }
 }
 
@@ -48,7 +151,7 @@ mod tests {
     use std::collections::HashMap;
 
     use super::*;
-    use crate::{Instruction, Operation};
+    use crate::{Instruction, Operation, ProgramBuilder};
     use rand::Rng;
 
     fn create_test_program(size: usize) -> Program {
@@ -92,4 +195,99 @@ mod tests {
             }
         }
     }
+
+    /// 10 instruction program with data-flow dependencies across the program and a block
+    fn create_dependent_program() -> Program {
+        let mut builder = ProgramBuilder::new(crate::ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        let conn = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let msg_type =
+            builder.force_append_expect_output(vec![], Operation::LoadMsgType(['a'; 12]));
+        let bytes = builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![1]));
+        builder.force_append(
+            vec![conn.index, msg_type.index, bytes.index],
+            Operation::SendRawMessage,
+        );
+        let time = builder.force_append_expect_output(vec![], Operation::LoadTime(1));
+        builder.force_append(vec![time.index], Operation::SetTime);
+        let txs = builder.force_append_expect_output(vec![], Operation::BeginBlockTransactions);
+        builder.force_append(vec![txs.index], Operation::EndBlockTransactions);
+        let bytes = builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![2]));
+        builder.force_append(
+            vec![conn.index, msg_type.index, bytes.index],
+            Operation::SendRawMessage,
+        );
+
+        let program = builder.finalize().unwrap();
+        assert_eq!(program.instructions.len(), 10);
+        program
+    }
+
+    #[test]
+    fn every_cut_is_statically_valid() {
+        let program = create_dependent_program();
+        let minimizer = CuttingMinimizer::new(program.clone());
+
+        let mut rejected_cuts = 0;
+        for start in 0..=program.instructions.len() {
+            for end in start..=program.instructions.len() {
+                let range = start..end;
+                let cut = minimizer.cut(range.clone());
+                assert!(cut.instructions.len() <= program.instructions.len() - (end - start));
+
+                // Cutting a block beginning also removes its end (the end uses the beginning's
+                // output), but cutting the end alone leaves the block open
+                let splits_block = range.contains(&7) && !range.contains(&6);
+                assert_eq!(
+                    cut.is_statically_valid(),
+                    !splits_block,
+                    "cut {start}..{end}:\n{cut}"
+                );
+                assert_eq!(minimizer.try_cut(range).is_some(), !splits_block);
+                if splits_block {
+                    rejected_cuts += 1;
+                }
+            }
+        }
+        // Ranges `start..end` with `start <= 7 < end` and `start > 6`
+        assert_eq!(rejected_cuts, 3);
+
+        // Cutting nothing leaves the program untouched
+        assert_eq!(minimizer.try_cut(0..0).unwrap(), program);
+        // Cutting the bytes of the first message removes that message, the second message
+        // refers to renumbered variables
+        let cut = minimizer.try_cut(2..3).unwrap();
+        assert_eq!(cut.instructions.len(), 8);
+        assert_eq!(cut.instructions[7].inputs, vec![0, 1, 5]);
+        // Cutting the message type removes both messages that depend on it
+        assert_eq!(minimizer.try_cut(1..2).unwrap().instructions.len(), 7);
+        // Cutting the end of a block without its beginning is invalid
+        assert!(minimizer.try_cut(7..8).is_none());
+    }
+
+    #[test]
+    fn tail_cuts_match_range_cuts() {
+        let program = create_dependent_program();
+        let len = program.instructions.len();
+        let mut minimizer = CuttingMinimizer::new(program.clone());
+
+        for index in 0..=len {
+            let cut = minimizer
+                .cut_tail(index)
+                .map(|builder| builder.finalize().unwrap());
+            assert_eq!(cut, minimizer.try_cut(index..len), "cut {index}..");
+        }
+
+        // Cuts are made from the shortest interesting prefix
+        assert_eq!(minimizer.next().unwrap().instructions.len(), 5);
+        minimizer.success();
+        assert_eq!(minimizer.builder.as_ref().unwrap().instructions.len(), 5);
+        assert_eq!(
+            minimizer.cut_tail(2).unwrap().finalize().unwrap(),
+            minimizer.try_cut(2..len).unwrap()
+        );
+    }
 }
@@ -1,28 +1,108 @@
 use super::Minimizer;
 use crate::Program;
 
+/// Configuration of the `InstrBlockMinimizer`
+#[derive(Debug, Clone)]
+pub struct InstrBlockMinimizerConfig {
+    /// Give up after this many failed attempts in a row
+    pub max_consecutive_failures: usize,
+    /// Attempt to remove larger (i.e. outer) blocks before smaller (i.e. inner) blocks, or the
+    /// other way around if `false`. Blocks of equal size are attempted from the end of the
+    /// program towards its beginning.
+    pub prefer_large_blocks: bool,
+}
+
+impl Default for InstrBlockMinimizerConfig {
+    fn default() -> Self {
+        Self {
+            max_consecutive_failures: 1000,
+            prefer_large_blocks: true,
+        }
+    }
+}
+
 /// `BlockMinimizer` is a minimizer that removes entire blocks of instructions from the program.
+///
+/// Blocks (matching `Begin*`/`End*` pairs) are detected at every nesting depth and removed by
+/// nopping all of their instructions. Nops keep the number of (inner) outputs of the original
+/// instructions, so no variables have to be remapped. After every successful removal, the
+/// program is rescanned for blocks that have not been attempted yet.
 pub struct InstrBlockMinimizer {
+    config: InstrBlockMinimizerConfig,
     last_good: Program,
     current: Program,
-    current_index: usize,
+    /// Blocks of `last_good` that are yet to be attempted, the next one is at the end
+    candidates: Vec<(usize, usize)>,
+    /// Blocks (inclusive instruction ranges) that were already attempted
+    removed_ranges: Vec<(usize, usize)>,
+    consecutive_failures: usize,
 }
 
-impl Minimizer for InstrBlockMinimizer {
-    fn new(program: Program) -> Self {
-        Self {
+/// Find all blocks in `program`, as inclusive `(begin, end)` instruction ranges
+fn find_blocks(program: &Program) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    let mut open_blocks = Vec::new();
+    for (i, instruction) in program.instructions.iter().enumerate() {
+        if instruction.operation.is_block_begin() {
+            open_blocks.push(i);
+        } else if instruction.operation.is_block_end() {
+            let Some(begin) = open_blocks.pop() else {
+                continue;
+            };
+            if instruction
+                .operation
+                .is_matching_block_begin(&program.instructions[begin].operation)
+            {
+                blocks.push((begin, i));
+            }
+        }
+    }
+    blocks
+}
+
+impl InstrBlockMinimizer {
+    pub fn with_config(program: Program, config: InstrBlockMinimizerConfig) -> Self {
+        let mut minimizer = Self {
+            config,
             last_good: program.clone(),
-            current_index: program.instructions.len().max(1) - 1,
             current: program,
+            candidates: Vec::new(),
+            removed_ranges: Vec::new(),
+            consecutive_failures: 0,
+        };
+        minimizer.rescan();
+        minimizer
+    }
+
+    fn rescan(&mut self) {
+        let mut candidates: Vec<_> = find_blocks(&self.last_good)
+            .into_iter()
+            .filter(|block| !self.removed_ranges.contains(block))
+            .collect();
+        // Candidates are popped from the end
+        if self.config.prefer_large_blocks {
+            candidates.sort_by_key(|(begin, end)| (end - begin, *end));
+        } else {
+            candidates.sort_by_key(|(begin, end)| (std::cmp::Reverse(end - begin), *end));
         }
+        self.candidates = candidates;
+    }
+}
+
+impl Minimizer for InstrBlockMinimizer {
+    fn new(program: Program) -> Self {
+        Self::with_config(program, InstrBlockMinimizerConfig::default())
     }
 
     fn success(&mut self) {
         self.last_good = self.current.clone();
+        self.consecutive_failures = 0;
+        self.rescan();
     }
 
     fn failure(&mut self) {
         self.current = self.last_good.clone();
+        self.consecutive_failures += 1;
     }
 }
 
@@ -30,33 +110,17 @@ impl Iterator for InstrBlockMinimizer {
     type Item = Program;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current_index == 0 {
+        if self.consecutive_failures >= self.config.max_consecutive_failures {
             return None;
         }
 
-        let mut found_block_end = None;
-        let mut found_block_begin = None;
-        for i in (0..=self.current_index).rev() {
-            if found_block_end.is_none() && self.current.instructions[i].operation.is_block_end() {
-                found_block_end = Some(i);
-                continue;
-            }
-
-            if let Some(block_end) = found_block_end.as_ref() {
-                if self.current.instructions[*block_end]
-                    .operation
-                    .is_matching_block_begin(&self.current.instructions[i].operation)
-                {
-                    found_block_begin = Some(i);
-                    self.current_index = i;
-                    break;
-                }
+        let (block_begin, block_end) = loop {
+            let block = self.candidates.pop()?;
+            if !self.removed_ranges.contains(&block) {
+                break block;
             }
-        }
-
-        let (Some(block_begin), Some(block_end)) = (found_block_begin, found_block_end) else {
-            return None;
         };
+        self.removed_ranges.push((block_begin, block_end));
 
         // Replace the whole block with nop operations
         for i in block_begin..=block_end {
@@ -66,3 +130,105 @@ impl Iterator for InstrBlockMinimizer {
         Some(self.current.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction, Operation, ProgramContext};
+
+    fn instruction(operation: Operation) -> Instruction {
+        Instruction {
+            inputs: vec![],
+            operation,
+        }
+    }
+
+    /// Three nested blocks surrounded by unrelated instructions
+    fn nested_program() -> Program {
+        Program::unchecked_new(
+            ProgramContext {
+                num_nodes: 1,
+                num_connections: 1,
+                timestamp: 0,
+            },
+            vec![
+                instruction(Operation::LoadTime(0)),
+                instruction(Operation::BeginBuildTx),
+                instruction(Operation::BeginBuildTxInputs),
+                instruction(Operation::BeginWitnessStack),
+                instruction(Operation::LoadBytes(vec![1])),
+                instruction(Operation::EndWitnessStack),
+                instruction(Operation::EndBuildTxInputs),
+                instruction(Operation::EndBuildTx),
+                instruction(Operation::LoadTime(1)),
+            ],
+        )
+    }
+
+    fn is_block(instruction: &Instruction) -> bool {
+        instruction.operation.is_block_begin() || instruction.operation.is_block_end()
+    }
+
+    /// Run the minimizer to completion, accepting every attempt. Returns the final program and
+    /// the number of successful attempts.
+    fn minimize(mut minimizer: InstrBlockMinimizer) -> (Program, usize) {
+        let mut program = None;
+        let mut successes = 0;
+        while let Some(attempt) = minimizer.next() {
+            program = Some(attempt);
+            minimizer.success();
+            successes += 1;
+        }
+        (program.expect("at least one attempt"), successes)
+    }
+
+    #[test]
+    fn finds_nested_blocks() {
+        assert_eq!(find_blocks(&nested_program()), vec![(3, 5), (2, 6), (1, 7)]);
+    }
+
+    #[test]
+    fn removes_nested_blocks_in_at_most_three_steps() {
+        for prefer_large_blocks in [true, false] {
+            let minimizer = InstrBlockMinimizer::with_config(
+                nested_program(),
+                InstrBlockMinimizerConfig {
+                    prefer_large_blocks,
+                    ..Default::default()
+                },
+            );
+            let (program, successes) = minimize(minimizer);
+
+            assert!(successes <= 3);
+            assert!(!program.instructions.iter().any(is_block));
+            assert_eq!(program.instructions[0].operation, Operation::LoadTime(0));
+            assert_eq!(program.instructions[8].operation, Operation::LoadTime(1));
+            assert_eq!(successes, if prefer_large_blocks { 1 } else { 3 });
+        }
+    }
+
+    #[test]
+    fn does_not_retry_failed_blocks() {
+        let mut minimizer = InstrBlockMinimizer::new(nested_program());
+        let mut attempts = 0;
+        while minimizer.next().is_some() {
+            minimizer.failure();
+            attempts += 1;
+        }
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn gives_up_after_consecutive_failures() {
+        let mut minimizer = InstrBlockMinimizer::with_config(
+            nested_program(),
+            InstrBlockMinimizerConfig {
+                max_consecutive_failures: 1,
+                prefer_large_blocks: true,
+            },
+        );
+        assert!(minimizer.next().is_some());
+        minimizer.failure();
+        assert!(minimizer.next().is_none());
+    }
+}
@@ -1,6 +1,7 @@
 pub mod cutting;
 pub mod instr_block;
 pub mod nopping;
+pub mod topo_sort;
 
 use crate::Program;
 
@@ -0,0 +1,106 @@
+use super::Minimizer;
+use crate::Program;
+
+/// `TopologicalSortMinimizer` is a minimizer that attempts a single reordering of the program into
+/// its topologically sorted form (see `Program::topological_sort`).
+///
+/// Sorting does not remove any instructions but groups dependent instructions together, which
+/// makes it more likely for the following minimizers (e.g. contiguous cuts) to remove complete
+/// dependency chains. No attempt is made if the program is already sorted or can't be sorted.
+pub struct TopologicalSortMinimizer {
+    program: Program,
+    /// Whether the sorted program was already handed out
+    attempted: bool,
+    /// Whether the sorted program was accepted
+    accepted: bool,
+}
+
+impl TopologicalSortMinimizer {
+    /// Whether the sorted program was accepted
+    pub fn accepted(&self) -> bool {
+        self.accepted
+    }
+}
+
+impl Minimizer for TopologicalSortMinimizer {
+    fn new(program: Program) -> Self {
+        Self {
+            program,
+            attempted: false,
+            accepted: false,
+        }
+    }
+
+    fn success(&mut self) {
+        self.accepted = true;
+    }
+
+    fn failure(&mut self) {}
+}
+
+impl Iterator for TopologicalSortMinimizer {
+    type Item = Program;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.attempted {
+            return None;
+        }
+        self.attempted = true;
+
+        let sorted = self.program.topological_sort().ok()?;
+        // Re-offering an unchanged program would be accepted on every minimization pass
+        (sorted != self.program).then_some(sorted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Operation, compiler::Compiler, test_utils};
+
+    fn unsorted_program() -> Program {
+        let mut builder = test_utils::builder();
+        let conn = builder.force_append_expect_output(vec![], Operation::LoadConnection(0));
+        let msg_type =
+            builder.force_append_expect_output(vec![], Operation::LoadMsgType(['a'; 12]));
+        let first = builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![1]));
+        let second = builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![2]));
+        builder.force_append(
+            vec![conn.index, msg_type.index, second.index],
+            Operation::SendRawMessage,
+        );
+        builder.force_append(
+            vec![conn.index, msg_type.index, first.index],
+            Operation::SendRawMessage,
+        );
+        builder.finalize().unwrap()
+    }
+
+    #[test]
+    fn sorted_program_compiles_identically() {
+        let program = unsorted_program();
+        let mut minimizer = TopologicalSortMinimizer::new(program.clone());
+
+        let sorted = minimizer.next().expect("program is not sorted yet");
+        assert_ne!(sorted, program);
+        minimizer.success();
+        assert!(minimizer.accepted());
+        assert!(minimizer.next().is_none());
+
+        let original_actions = Compiler::new().compile(&program).unwrap().actions;
+        let sorted_actions = Compiler::new().compile(&sorted).unwrap().actions;
+        assert_eq!(
+            format!("{:?}", original_actions),
+            format!("{:?}", sorted_actions)
+        );
+    }
+
+    #[test]
+    fn sorted_program_is_not_attempted_again() {
+        let sorted = unsorted_program().topological_sort().unwrap();
+        let mut minimizer = TopologicalSortMinimizer::new(sorted);
+        assert!(minimizer.next().is_none());
+        minimizer.failure();
+        assert!(!minimizer.accepted());
+    }
+}
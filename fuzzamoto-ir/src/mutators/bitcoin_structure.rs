@@ -0,0 +1,216 @@
+use bitcoin::{
+    Amount, BlockHash, CompactTarget, Sequence, Transaction, TxMerkleNode,
+    absolute::LockTime,
+    block::{Header, Version},
+    consensus::{deserialize, deserialize_partial, serialize},
+    hashes::Hash,
+    transaction,
+};
+use rand::{
+    Rng, RngCore,
+    seq::{IteratorRandom, SliceRandom},
+};
+
+use super::{Mutator, MutatorError, MutatorResult, OperationByteMutator};
+use crate::{Operation, PerTestcaseMetadata, Program};
+
+/// Boundary values that are likely to hit edge cases in field validation (e.g. the lock time
+/// threshold between block heights and timestamps, or the sequence disable/final flags).
+const INTERESTING_U32: [u32; 8] = [
+    0,
+    1,
+    499_999_999,
+    500_000_000,
+    0x7fff_ffff,
+    0x8000_0000,
+    0xffff_fffe,
+    u32::MAX,
+];
+
+/// `BitcoinStructureMutator` mutates `LoadBytes` payloads that decode as a known Bitcoin
+/// structure (a transaction or a block header) by changing a single field of the decoded
+/// structure, instead of random bytes.
+///
+/// Transactions may be followed by trailing bytes (which are kept as is), block headers have to
+/// match exactly. Payloads that don't decode are mutated with the fallback byte mutator.
+pub struct BitcoinStructureMutator<M> {
+    byte_array_mutator: M,
+}
+
+impl<M: OperationByteMutator> BitcoinStructureMutator<M> {
+    pub fn new(byte_array_mutator: M) -> Self {
+        Self { byte_array_mutator }
+    }
+}
+
+fn interesting_u32<R: RngCore>(rng: &mut R) -> u32 {
+    if rng.gen_bool(0.5) {
+        *INTERESTING_U32.choose(rng).unwrap()
+    } else {
+        rng.r#gen()
+    }
+}
+
+fn mutate_transaction<R: RngCore>(tx: &mut Transaction, rng: &mut R) {
+    match rng.gen_range(0..5) {
+        0 => tx.version = transaction::Version(interesting_u32(rng) as i32),
+        1 => tx.lock_time = LockTime::from_consensus(interesting_u32(rng)),
+        2 => {
+            if let Some(input) = tx.input.iter_mut().choose(rng) {
+                input.sequence = Sequence(interesting_u32(rng));
+            }
+        }
+        3 => {
+            if let Some(input) = tx.input.iter_mut().choose(rng) {
+                input.previous_output.vout = interesting_u32(rng);
+            }
+        }
+        _ => {
+            if let Some(output) = tx.output.iter_mut().choose(rng) {
+                output.value = Amount::from_sat(
+                    *[0, 1, 21_000_000 * 100_000_000, u64::MAX, rng.r#gen()]
+                        .choose(rng)
+                        .unwrap(),
+                );
+            }
+        }
+    }
+}
+
+fn mutate_header<R: RngCore>(header: &mut Header, rng: &mut R) {
+    match rng.gen_range(0..6) {
+        0 => header.version = Version::from_consensus(interesting_u32(rng) as i32),
+        1 => header.bits = CompactTarget::from_consensus(interesting_u32(rng)),
+        2 => header.time = interesting_u32(rng),
+        3 => header.nonce = interesting_u32(rng),
+        4 => header.prev_blockhash = BlockHash::from_byte_array(rng.r#gen()),
+        _ => header.merkle_root = TxMerkleNode::from_byte_array(rng.r#gen()),
+    }
+}
+
+impl<M: OperationByteMutator> BitcoinStructureMutator<M> {
+    /// Apply a structure-aware mutation to `bytes`, falling back to byte-level mutations if
+    /// `bytes` don't decode as a known structure.
+    fn mutate_structure<R: RngCore>(&mut self, bytes: &mut Vec<u8>, rng: &mut R) {
+        if let Ok(mut header) = deserialize::<Header>(bytes) {
+            mutate_header(&mut header, rng);
+            *bytes = serialize(&header);
+            return;
+        }
+
+        if let Ok((mut tx, consumed)) = deserialize_partial::<Transaction>(bytes) {
+            mutate_transaction(&mut tx, rng);
+            let mut mutated = serialize(&tx);
+            mutated.extend_from_slice(&bytes[consumed..]);
+            *bytes = mutated;
+            return;
+        }
+
+        self.byte_array_mutator.mutate_bytes(bytes);
+    }
+}
+
+impl<R: RngCore, M: OperationByteMutator> Mutator<R> for BitcoinStructureMutator<M> {
+    fn mutate(
+        &mut self,
+        program: &mut Program,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> MutatorResult {
+        let Some(instruction) = program
+            .instructions
+            .iter_mut()
+            .filter(|instr| matches!(instr.operation, Operation::LoadBytes(_)))
+            .choose(rng)
+        else {
+            return Err(MutatorError::NoMutationsAvailable);
+        };
+
+        let Operation::LoadBytes(bytes) = &mut instruction.operation else {
+            unreachable!("only LoadBytes instructions are considered");
+        };
+        self.mutate_structure(bytes, rng);
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "BitcoinStructureMutator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{OutPoint, ScriptBuf, TxIn, TxOut, Witness};
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    /// Byte mutator that records whether it was used
+    #[derive(Default)]
+    struct FlagByteMutator {
+        used: bool,
+    }
+
+    impl OperationByteMutator for FlagByteMutator {
+        fn mutate_bytes(&mut self, bytes: &mut Vec<u8>) {
+            self.used = true;
+            bytes.push(0);
+        }
+    }
+
+    fn transaction() -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn header_stays_decodable() {
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut mutator = BitcoinStructureMutator::new(FlagByteMutator::default());
+        for _ in 0..100 {
+            let mut bytes = serialize(&genesis.header);
+            mutator.mutate_structure(&mut bytes, &mut rng);
+            assert!(deserialize::<Header>(&bytes).is_ok());
+        }
+        assert!(!mutator.byte_array_mutator.used);
+    }
+
+    #[test]
+    fn transaction_keeps_trailing_bytes() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut mutator = BitcoinStructureMutator::new(FlagByteMutator::default());
+        for _ in 0..100 {
+            let mut bytes = serialize(&transaction());
+            bytes.extend_from_slice(&[0xde, 0xad]);
+            mutator.mutate_structure(&mut bytes, &mut rng);
+
+            let (_, consumed) = deserialize_partial::<Transaction>(&bytes).unwrap();
+            assert_eq!(&bytes[consumed..], &[0xde, 0xad]);
+        }
+        assert!(!mutator.byte_array_mutator.used);
+    }
+
+    #[test]
+    fn falls_back_to_byte_mutations() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut mutator = BitcoinStructureMutator::new(FlagByteMutator::default());
+        for bytes in [vec![], vec![0xff; 3], vec![0x01, 0x00, 0x00]] {
+            let mut bytes = bytes;
+            mutator.mutate_structure(&mut bytes, &mut rng);
+        }
+        assert!(mutator.byte_array_mutator.used);
+    }
+}
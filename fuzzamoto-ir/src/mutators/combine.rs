@@ -1,10 +1,13 @@
 use super::{Mutator, MutatorError, MutatorResult, Splicer};
-use crate::{InstructionContext, PerTestcaseMetadata, Program, ProgramBuilder};
-use rand::RngCore;
+use crate::{PerTestcaseMetadata, Program, ProgramBuilder};
+use rand::{RngCore, seq::IteratorRandom};
 
 // `CombineMutator` takes two programs and combines them by splicing the second program into the
-// first at a random point.
-pub struct CombineMutator;
+// first at a random point outside of any block.
+pub struct CombineMutator {
+    attempts: usize,
+    successes: usize,
+}
 
 impl<R: RngCore> Mutator<R> for CombineMutator {
     fn mutate(
@@ -28,9 +31,71 @@ impl<R: RngCore> Splicer<R> for CombineMutator {
         splice_with: &Program,
         rng: &mut R,
     ) -> MutatorResult {
-        let combine_index = program
-            .get_random_instruction_index(rng, InstructionContext::Global)
-            .expect("Global instruction index should always exist");
+        self.attempts += 1;
+        let result = self.try_splice(program, splice_with, rng);
+        if result.is_ok() {
+            self.successes += 1;
+        }
+        result
+    }
+}
+
+/// Scope depth (number of open blocks) before each instruction of `program`, plus the depth after
+/// the last instruction.
+fn scope_depths(program: &Program) -> Vec<usize> {
+    let mut depths = Vec::with_capacity(program.instructions.len() + 1);
+    let mut depth = 0usize;
+    depths.push(depth);
+    for instruction in program.instructions.iter() {
+        if instruction.operation.is_block_end() {
+            depth = depth.saturating_sub(1);
+        }
+        if instruction.operation.is_block_begin() {
+            depth += 1;
+        }
+        depths.push(depth);
+    }
+    depths
+}
+
+impl CombineMutator {
+    pub fn new() -> Self {
+        Self {
+            attempts: 0,
+            successes: 0,
+        }
+    }
+
+    /// Fraction of splices that produced a valid program
+    pub fn success_rate(&self) -> f64 {
+        if self.attempts == 0 {
+            return 0.0;
+        }
+        self.successes as f64 / self.attempts as f64
+    }
+
+    fn try_splice<R: RngCore>(
+        &self,
+        program: &mut Program,
+        splice_with: &Program,
+        rng: &mut R,
+    ) -> MutatorResult {
+        // `splice_with` is inserted as a whole, so it has to leave the scope depth unchanged
+        if scope_depths(splice_with).last().copied() != Some(0) {
+            return Err(MutatorError::CreatedInvalidProgram);
+        }
+
+        // Only splice at points outside of any block, to avoid referencing variables from
+        // incompatible scopes
+        let Some(combine_index) = scope_depths(program)
+            .into_iter()
+            .enumerate()
+            .filter(|(_, depth)| *depth == 0)
+            .map(|(index, _)| index)
+            .choose(rng)
+        else {
+            return Err(MutatorError::NoMutationsAvailable);
+        };
 
         let mut builder = ProgramBuilder::new(program.context.clone());
 
@@ -63,15 +128,82 @@ impl<R: RngCore> Splicer<R> for CombineMutator {
                 builder.variable_count() - prev_var_count,
             )
             .map_err(|_| MutatorError::CreatedInvalidProgram)?;
-        *program = builder
+        let spliced = builder
             .finalize()
             .map_err(|_| MutatorError::CreatedInvalidProgram)?;
+        ProgramBuilder::from_program(spliced.clone())
+            .map_err(|_| MutatorError::CreatedInvalidProgram)?;
+
+        *program = spliced;
         Ok(())
     }
 }
 
-impl CombineMutator {
-    pub fn new() -> Self {
-        Self {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Operation, test_utils};
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    fn build_tx_program(version: u32) -> Program {
+        let mut builder = test_utils::builder();
+        let version_var =
+            builder.force_append_expect_output(vec![], Operation::LoadTxVersion(version));
+        let lock_time_var = builder.force_append_expect_output(vec![], Operation::LoadLockTime(0));
+        let mut_tx_var = builder.force_append_expect_output(
+            vec![version_var.index, lock_time_var.index],
+            Operation::BeginBuildTx,
+        );
+        let mut_inputs_var =
+            builder.force_append_expect_output(vec![], Operation::BeginBuildTxInputs);
+        let inputs_var = builder
+            .force_append_expect_output(vec![mut_inputs_var.index], Operation::EndBuildTxInputs);
+        let mut_outputs_var = builder
+            .force_append_expect_output(vec![inputs_var.index], Operation::BeginBuildTxOutputs);
+        let outputs_var = builder
+            .force_append_expect_output(vec![mut_outputs_var.index], Operation::EndBuildTxOutputs);
+        builder.force_append_expect_output(
+            vec![mut_tx_var.index, inputs_var.index, outputs_var.index],
+            Operation::EndBuildTx,
+        );
+        builder.finalize().unwrap()
+    }
+
+    #[test]
+    fn splice_tx_blocks_at_depth_zero() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut mutator = CombineMutator::new();
+        let splice_with = build_tx_program(2);
+
+        for _ in 0..32 {
+            let mut program = build_tx_program(1);
+            mutator
+                .splice(&mut program, &splice_with, &mut rng)
+                .unwrap();
+
+            assert!(program.is_statically_valid());
+            assert_eq!(
+                program.instructions.len(),
+                2 * splice_with.instructions.len()
+            );
+            assert_eq!(scope_depths(&program).last(), Some(&0));
+        }
+        assert_eq!(mutator.success_rate(), 1.0);
+    }
+
+    #[test]
+    fn splice_rejects_unbalanced_program() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut mutator = CombineMutator::new();
+        let mut splice_with = build_tx_program(2);
+        splice_with.instructions.pop();
+
+        let mut program = build_tx_program(1);
+        assert!(
+            mutator
+                .splice(&mut program, &splice_with, &mut rng)
+                .is_err()
+        );
+        assert_eq!(mutator.success_rate(), 0.0);
     }
 }
@@ -0,0 +1,197 @@
+use super::{
+    InputMutator, Mutator, MutatorError, MutatorResult, OperationByteMutator, OperationMutator,
+};
+use crate::{PerTestcaseMetadata, Program};
+
+use rand::{Rng, RngCore, seq::SliceRandom};
+
+/// Success probability of the geometric distribution used to pick the number of stacked
+/// mutations (i.e. on average ~3 mutations are applied per pass).
+const STACK_PROBABILITY: f64 = 0.3;
+
+/// `HavocMutator` applies a series of small mutations to a program in a single pass.
+///
+/// The number of stacked mutations follows a geometric distribution and is capped by
+/// `max_mutations`. Mutations that fail are skipped and the remaining ones are still applied,
+/// which allows reaching states that require several coordinated changes at once.
+pub struct HavocMutator<R> {
+    mutators: Vec<Box<dyn Mutator<R>>>,
+    pub max_mutations: usize,
+}
+
+impl<R: RngCore> Mutator<R> for HavocMutator<R> {
+    fn mutate(
+        &mut self,
+        program: &mut Program,
+        rng: &mut R,
+        meta: Option<&PerTestcaseMetadata>,
+    ) -> MutatorResult {
+        let mut num_mutations = 1;
+        while num_mutations < self.max_mutations && !rng.gen_bool(STACK_PROBABILITY) {
+            num_mutations += 1;
+        }
+
+        let mut mutated = program.clone();
+        let mut applied = 0;
+        for _ in 0..num_mutations {
+            let Some(mutator) = self.mutators.choose_mut(rng) else {
+                return Err(MutatorError::NoMutationsAvailable);
+            };
+
+            // Mutate a scratch copy, so that a failed mutation does not leave a partially mutated
+            // program behind.
+            let mut attempt = mutated.clone();
+            if mutator.mutate(&mut attempt, rng, meta).is_ok() {
+                mutated = attempt;
+                applied += 1;
+            }
+        }
+
+        if applied == 0 {
+            return Err(MutatorError::NoMutationsAvailable);
+        }
+
+        *program = mutated;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "HavocMutator"
+    }
+}
+
+impl<R: RngCore> HavocMutator<R> {
+    /// Create a `HavocMutator` stacking `InputMutator` and `OperationMutator` mutations. The
+    /// `OperationMutator` also covers sighash flags, time and sequence mutations.
+    pub fn new<M: OperationByteMutator + 'static>(byte_array_mutator: M) -> Self {
+        Self::with_mutators(vec![
+            Box::new(InputMutator::new()),
+            Box::new(OperationMutator::new(byte_array_mutator)),
+        ])
+    }
+
+    pub fn with_mutators(mutators: Vec<Box<dyn Mutator<R>>>) -> Self {
+        Self {
+            mutators,
+            max_mutations: 8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Operation, ProgramBuilder, ProgramContext};
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    struct InvertByteMutator;
+
+    impl OperationByteMutator for InvertByteMutator {
+        fn mutate_bytes(&mut self, bytes: &mut Vec<u8>) {
+            bytes.iter_mut().for_each(|b| *b = !*b);
+        }
+    }
+
+    struct FailingMutator;
+
+    impl<R: RngCore> Mutator<R> for FailingMutator {
+        fn mutate(
+            &mut self,
+            program: &mut Program,
+            _rng: &mut R,
+            _meta: Option<&PerTestcaseMetadata>,
+        ) -> MutatorResult {
+            // Leave a partially mutated program behind
+            program.instructions.pop();
+            Err(MutatorError::CreatedInvalidProgram)
+        }
+
+        fn name(&self) -> &'static str {
+            "FailingMutator"
+        }
+    }
+
+    /// Raw messages and time changes on two connections
+    fn message_program() -> Program {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 2,
+            timestamp: 0,
+        });
+        let connections = [
+            builder.force_append_expect_output(vec![], Operation::LoadConnection(0)),
+            builder.force_append_expect_output(vec![], Operation::LoadConnection(1)),
+        ];
+        for i in 0..4u8 {
+            let msg_type =
+                builder.force_append_expect_output(vec![], Operation::LoadMsgType(['a'; 12]));
+            let bytes = builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![i]));
+            builder.force_append(
+                vec![
+                    connections[usize::from(i % 2)].index,
+                    msg_type.index,
+                    bytes.index,
+                ],
+                Operation::SendRawMessage,
+            );
+            let time =
+                builder.force_append_expect_output(vec![], Operation::LoadTime(u64::from(i)));
+            builder.force_append(vec![time.index], Operation::SetTime);
+        }
+        builder.finalize().unwrap()
+    }
+
+    #[test]
+    fn stacked_mutations_produce_valid_programs() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut mutator = HavocMutator::new(InvertByteMutator);
+        let program = message_program();
+
+        let mut max_changed = 0;
+        for _ in 0..1000 {
+            let mut mutated = program.clone();
+            if mutator.mutate(&mut mutated, &mut rng, None).is_err() {
+                assert_eq!(mutated, program);
+                continue;
+            }
+            assert!(mutated.is_statically_valid(), "{mutated}");
+            let changed = program
+                .instructions
+                .iter()
+                .zip(&mutated.instructions)
+                .filter(|(original, mutated)| original != mutated)
+                .count();
+            max_changed = max_changed.max(changed);
+        }
+        // Several mutations were stacked onto the same program
+        assert!(max_changed > 1);
+    }
+
+    #[test]
+    fn failed_mutations_are_skipped() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let program = message_program();
+
+        let mut mutator = HavocMutator::with_mutators(vec![
+            Box::new(FailingMutator),
+            Box::new(OperationMutator::new(InvertByteMutator)),
+        ]);
+        for _ in 0..100 {
+            let mut mutated = program.clone();
+            if mutator.mutate(&mut mutated, &mut rng, None).is_ok() {
+                assert_eq!(mutated.instructions.len(), program.instructions.len());
+                assert!(mutated.is_statically_valid());
+            } else {
+                assert_eq!(mutated, program);
+            }
+        }
+
+        let mut only_failing = HavocMutator::with_mutators(vec![Box::new(FailingMutator)]);
+        let mut mutated = program.clone();
+        assert!(matches!(
+            only_failing.mutate(&mut mutated, &mut rng, None),
+            Err(MutatorError::NoMutationsAvailable)
+        ));
+        assert_eq!(mutated, program);
+    }
+}
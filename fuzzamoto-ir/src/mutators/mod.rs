@@ -1,14 +1,20 @@
+pub mod bitcoin_structure;
 pub mod combine;
 pub mod concat;
+pub mod havoc;
 pub mod input;
 pub mod operation;
+pub mod shuffle;
 
 use crate::{PerTestcaseMetadata, Program};
+pub use bitcoin_structure::*;
 pub use combine::*;
 pub use concat::*;
+pub use havoc::*;
 pub use input::*;
 pub use operation::*;
 use rand::RngCore;
+pub use shuffle::*;
 
 #[derive(Debug)]
 pub enum MutatorError {
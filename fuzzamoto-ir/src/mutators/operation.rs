@@ -3,7 +3,8 @@ use std::{time::Duration, u64};
 use super::{Mutator, MutatorResult};
 use crate::PerTestcaseMetadata;
 use crate::{
-    AddrNetwork, AddrRecord, Operation, Program,
+    AddrNetwork, AddrRecord, MAX_PROTOCOL_MESSAGE_LENGTH, MSG_TYPE_LEN, MsgTypeDictionary,
+    Operation, Program,
     generators::address::{
         MAX_UNKNOWN_ADDR_PAYLOAD, ipv4_to_ipv6_mapped, random_addr_network, random_global_ipv6,
         random_payload_for_network, random_port, random_public_ipv4, random_services, random_time,
@@ -27,6 +28,7 @@ pub trait OperationByteMutator {
 /// Only instructions for which `is_operation_mutable` returns true are considered.
 pub struct OperationMutator<M> {
     byte_array_mutator: M,
+    msg_types: MsgTypeDictionary,
 }
 
 impl<R: RngCore, M: OperationByteMutator> Mutator<R> for OperationMutator<M> {
@@ -36,11 +38,21 @@ impl<R: RngCore, M: OperationByteMutator> Mutator<R> for OperationMutator<M> {
         rng: &mut R,
         _meta: Option<&PerTestcaseMetadata>,
     ) -> MutatorResult {
+        // `LoadRawBytes` payloads are the primary target of byte-level mutations, so half of the
+        // time only they are considered (if there are any)
+        let only_raw_bytes = program
+            .instructions
+            .iter()
+            .any(|instr| matches!(instr.operation, Operation::LoadRawBytes(_)))
+            && rng.gen_bool(0.5);
         let Some(candidate_instruction) = program
             .instructions
             .iter_mut()
             .enumerate()
             .filter(|(_, instr)| instr.is_operation_mutable())
+            .filter(|(_, instr)| {
+                !only_raw_bytes || matches!(instr.operation, Operation::LoadRawBytes(_))
+            })
             .choose(rng)
         else {
             return Err(super::MutatorError::NoMutationsAvailable);
@@ -118,6 +130,28 @@ impl<R: RngCore, M: OperationByteMutator> Mutator<R> for OperationMutator<M> {
                     .clone()
             }
 
+            Operation::EndBuildMultiSig => [
+                Operation::EndBuildMultiSigScriptHash,
+                Operation::EndBuildMultiSigWitnessScriptHash,
+            ]
+            .choose(rng)
+            .unwrap()
+            .clone(),
+            Operation::EndBuildMultiSigScriptHash => [
+                Operation::EndBuildMultiSig,
+                Operation::EndBuildMultiSigWitnessScriptHash,
+            ]
+            .choose(rng)
+            .unwrap()
+            .clone(),
+            Operation::EndBuildMultiSigWitnessScriptHash => [
+                Operation::EndBuildMultiSig,
+                Operation::EndBuildMultiSigScriptHash,
+            ]
+            .choose(rng)
+            .unwrap()
+            .clone(),
+
             Operation::LoadPrivateKey(current_key) => {
                 let mut new_key: Vec<u8> = current_key.into();
                 let mut valid_key = false;
@@ -312,6 +346,32 @@ impl<R: RngCore, M: OperationByteMutator> Mutator<R> for OperationMutator<M> {
                 self.byte_array_mutator.mutate_bytes(bytes);
                 Operation::LoadBytes(bytes.clone()) // TODO this clone is not needed
             }
+            Operation::LoadRawTransaction(bytes) => {
+                self.byte_array_mutator.mutate_bytes(bytes);
+                Operation::LoadRawTransaction(bytes.clone())
+            }
+            Operation::LoadRawBytes(bytes) => {
+                self.byte_array_mutator.mutate_bytes(bytes);
+                bytes.truncate(MAX_PROTOCOL_MESSAGE_LENGTH);
+                Operation::LoadRawBytes(bytes.clone())
+            }
+            Operation::LoadMsgType(msg_type) => {
+                // Byte mutations mostly produce unknown message types, so half of the time a known
+                // message type is picked instead
+                if rng.gen_bool(0.5) {
+                    Operation::LoadMsgType(self.msg_types.choose(rng))
+                } else {
+                    Operation::LoadMsgType(mutate_msg_type(msg_type, &mut self.byte_array_mutator))
+                }
+            }
+            op @ Operation::LoadHeader { .. } => {
+                LoadHeaderFieldMutator::random(rng).mutate(op, rng, &mut self.byte_array_mutator);
+                op.clone()
+            }
+            op @ Operation::LoadTxo { .. } => {
+                LoadTxoFieldMutator::random(rng).mutate(op, rng, &mut self.byte_array_mutator);
+                op.clone()
+            }
             op => op.clone(),
         };
 
@@ -325,7 +385,249 @@ impl<R: RngCore, M: OperationByteMutator> Mutator<R> for OperationMutator<M> {
 
 impl<M: OperationByteMutator> OperationMutator<M> {
     pub fn new(byte_array_mutator: M) -> Self {
-        Self { byte_array_mutator }
+        Self {
+            byte_array_mutator,
+            msg_types: MsgTypeDictionary::new(),
+        }
+    }
+}
+
+/// Compact difficulty targets (`bits`): regtest and nearby targets. The compiler grinds the nonce
+/// of built blocks until their hash meets the target, so harder targets (e.g. mainnet's) would
+/// stall compilation.
+const HEADER_BITS: [u32; 5] = [
+    0x207f_ffff,
+    0x207f_fffe,
+    0x2040_0000,
+    0x2000_ffff,
+    // Above the regtest proof of work limit
+    0x2100_ffff,
+];
+/// Block versions: pre-BIP34/66/65 versions, a negative version and the BIP9 base version
+const HEADER_VERSIONS: [i32; 5] = [1, 2, 4, -1, 0x2000_0000];
+/// Maximum amount of time (in seconds) a block's timestamp may be ahead of a node's time
+const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+/// Mutate the bytes of `msg_type`, keeping it `MSG_TYPE_LEN` ASCII chars long (message types are
+/// sent as their UTF-8 encoding, which has to fit into the 12 bytes of the message header)
+fn mutate_msg_type<M: OperationByteMutator>(
+    msg_type: &[char; MSG_TYPE_LEN],
+    byte_mutator: &mut M,
+) -> [char; MSG_TYPE_LEN] {
+    let mut bytes: Vec<u8> = msg_type.iter().map(|c| *c as u8).collect();
+    byte_mutator.mutate_bytes(&mut bytes);
+
+    let mut mutated = ['\0'; MSG_TYPE_LEN];
+    for (c, b) in mutated.iter_mut().zip(bytes) {
+        *c = (b & 0x7f) as char;
+    }
+    mutated
+}
+
+/// Pick one of `candidates` that differs from `current`
+fn pick_other<R: RngCore, T: Copy + PartialEq>(current: T, candidates: &[T], rng: &mut R) -> T {
+    *candidates
+        .iter()
+        .filter(|candidate| **candidate != current)
+        .choose(rng)
+        .unwrap_or(&current)
+}
+
+fn mutate_hash<M: OperationByteMutator>(hash: &mut [u8; 32], byte_mutator: &mut M) {
+    let mut bytes = hash.to_vec();
+    byte_mutator.mutate_bytes(&mut bytes);
+    bytes.resize(32, 0);
+    *hash = bytes.try_into().unwrap();
+}
+
+/// Field of a `LoadHeader` operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadHeaderField {
+    Prev,
+    MerkleRoot,
+    Nonce,
+    Bits,
+    Time,
+    Version,
+    Height,
+}
+
+impl LoadHeaderField {
+    pub const ALL: [LoadHeaderField; 7] = [
+        LoadHeaderField::Prev,
+        LoadHeaderField::MerkleRoot,
+        LoadHeaderField::Nonce,
+        LoadHeaderField::Bits,
+        LoadHeaderField::Time,
+        LoadHeaderField::Version,
+        LoadHeaderField::Height,
+    ];
+}
+
+/// `LoadHeaderFieldMutator` mutates a single field of a `LoadHeader` operation with a mutation
+/// targeted at that field (e.g. known difficulty targets for `bits` or timestamps around the
+/// future block time limit for `time`). Hashes are mutated with the byte mutator.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadHeaderFieldMutator {
+    field: LoadHeaderField,
+}
+
+impl LoadHeaderFieldMutator {
+    pub fn new(field: LoadHeaderField) -> Self {
+        Self { field }
+    }
+
+    pub fn random<R: RngCore>(rng: &mut R) -> Self {
+        Self::new(*LoadHeaderField::ALL.choose(rng).unwrap())
+    }
+
+    /// Mutate the field of `operation`, which is left untouched if it isn't a `LoadHeader`
+    pub fn mutate<R: RngCore, M: OperationByteMutator>(
+        &self,
+        operation: &mut Operation,
+        rng: &mut R,
+        byte_mutator: &mut M,
+    ) {
+        let Operation::LoadHeader {
+            prev,
+            merkle_root,
+            nonce,
+            bits,
+            time,
+            version,
+            height,
+        } = operation
+        else {
+            return;
+        };
+
+        match self.field {
+            LoadHeaderField::Prev => mutate_hash(prev, byte_mutator),
+            LoadHeaderField::MerkleRoot => mutate_hash(merkle_root, byte_mutator),
+            LoadHeaderField::Nonce => {
+                *nonce = pick_other(
+                    *nonce,
+                    &[0, nonce.wrapping_add(1), u32::MAX, rng.r#gen()],
+                    rng,
+                );
+            }
+            LoadHeaderField::Bits => *bits = pick_other(*bits, &HEADER_BITS, rng),
+            LoadHeaderField::Time => {
+                *time = pick_other(
+                    *time,
+                    &[
+                        0,
+                        time.wrapping_sub(1),
+                        time.wrapping_add(1),
+                        time.saturating_add(MAX_FUTURE_BLOCK_TIME),
+                        time.saturating_add(MAX_FUTURE_BLOCK_TIME + 1),
+                        u32::MAX,
+                    ],
+                    rng,
+                );
+            }
+            LoadHeaderField::Version => *version = pick_other(*version, &HEADER_VERSIONS, rng),
+            LoadHeaderField::Height => {
+                *height = pick_other(
+                    *height,
+                    &[
+                        0,
+                        height.saturating_sub(1),
+                        height.saturating_add(1),
+                        rng.r#gen(),
+                    ],
+                    rng,
+                );
+            }
+        }
+    }
+}
+
+/// Field of a `LoadTxo` operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadTxoField {
+    Outpoint,
+    Value,
+    ScriptPubkey,
+    SpendingWitness,
+}
+
+impl LoadTxoField {
+    pub const ALL: [LoadTxoField; 4] = [
+        LoadTxoField::Outpoint,
+        LoadTxoField::Value,
+        LoadTxoField::ScriptPubkey,
+        LoadTxoField::SpendingWitness,
+    ];
+}
+
+/// `LoadTxoFieldMutator` mutates a single field of a `LoadTxo` operation: the outpoint's txid or
+/// output index, the value, the script pubkey or one of the spending witness stack elements.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadTxoFieldMutator {
+    field: LoadTxoField,
+}
+
+impl LoadTxoFieldMutator {
+    pub fn new(field: LoadTxoField) -> Self {
+        Self { field }
+    }
+
+    pub fn random<R: RngCore>(rng: &mut R) -> Self {
+        Self::new(*LoadTxoField::ALL.choose(rng).unwrap())
+    }
+
+    /// Mutate the field of `operation`, which is left untouched if it isn't a `LoadTxo`
+    pub fn mutate<R: RngCore, M: OperationByteMutator>(
+        &self,
+        operation: &mut Operation,
+        rng: &mut R,
+        byte_mutator: &mut M,
+    ) {
+        let Operation::LoadTxo {
+            outpoint,
+            value,
+            script_pubkey,
+            spending_witness,
+            ..
+        } = operation
+        else {
+            return;
+        };
+
+        match self.field {
+            LoadTxoField::Outpoint => {
+                if rng.gen_bool(0.5) {
+                    mutate_hash(&mut outpoint.0, byte_mutator);
+                } else {
+                    outpoint.1 =
+                        pick_other(outpoint.1, &[0, outpoint.1.wrapping_add(1), u32::MAX], rng);
+                }
+            }
+            LoadTxoField::Value => {
+                *value = pick_other(
+                    *value,
+                    &[
+                        0,
+                        1,
+                        *value / 2,
+                        value.saturating_add(1),
+                        21_000_000 * 100_000_000,
+                        u64::MAX,
+                    ],
+                    rng,
+                );
+            }
+            LoadTxoField::ScriptPubkey => byte_mutator.mutate_bytes(script_pubkey),
+            LoadTxoField::SpendingWitness => match spending_witness.iter_mut().choose(rng) {
+                Some(element) => byte_mutator.mutate_bytes(element),
+                None => {
+                    let mut element = Vec::new();
+                    byte_mutator.mutate_bytes(&mut element);
+                    spending_witness.push(element);
+                }
+            },
+        }
     }
 }
 
@@ -468,3 +770,242 @@ fn mutate_addr_payload<R: RngCore, M: OperationByteMutator>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{CompactTarget, Target};
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    /// Inverts all bytes, so that every byte mutation is observable
+    struct InvertByteMutator;
+
+    impl OperationByteMutator for InvertByteMutator {
+        fn mutate_bytes(&mut self, bytes: &mut Vec<u8>) {
+            bytes.iter_mut().for_each(|b| *b = !*b);
+            bytes.push(0xff);
+        }
+    }
+
+    fn header() -> Operation {
+        Operation::LoadHeader {
+            prev: [1; 32],
+            merkle_root: [2; 32],
+            nonce: 3,
+            bits: 0x207f_ffff,
+            time: 1_296_688_602,
+            version: 0x2000_0000,
+            height: 100,
+        }
+    }
+
+    fn txo() -> Operation {
+        Operation::LoadTxo {
+            outpoint: ([1; 32], 0),
+            value: 100_000_000,
+            script_pubkey: vec![0x51],
+            spending_script_sig: vec![],
+            spending_witness: vec![],
+        }
+    }
+
+    /// Names of the fields that differ between two `LoadHeader` operations
+    fn changed_header_fields(a: &Operation, b: &Operation) -> Vec<LoadHeaderField> {
+        let (
+            Operation::LoadHeader {
+                prev: a_prev,
+                merkle_root: a_merkle_root,
+                nonce: a_nonce,
+                bits: a_bits,
+                time: a_time,
+                version: a_version,
+                height: a_height,
+            },
+            Operation::LoadHeader {
+                prev,
+                merkle_root,
+                nonce,
+                bits,
+                time,
+                version,
+                height,
+            },
+        ) = (a, b)
+        else {
+            panic!("expected LoadHeader operations");
+        };
+        [
+            (a_prev != prev, LoadHeaderField::Prev),
+            (a_merkle_root != merkle_root, LoadHeaderField::MerkleRoot),
+            (a_nonce != nonce, LoadHeaderField::Nonce),
+            (a_bits != bits, LoadHeaderField::Bits),
+            (a_time != time, LoadHeaderField::Time),
+            (a_version != version, LoadHeaderField::Version),
+            (a_height != height, LoadHeaderField::Height),
+        ]
+        .into_iter()
+        .filter_map(|(changed, field)| changed.then_some(field))
+        .collect()
+    }
+
+    /// Blocks built on top of a mutated header need to be mined by the compiler
+    #[test]
+    fn header_bits_are_cheap_to_mine() {
+        let hardest = Target::from_compact(CompactTarget::from_consensus(0x2000_ffff));
+        for bits in HEADER_BITS {
+            assert!(Target::from_compact(CompactTarget::from_consensus(bits)) >= hardest);
+        }
+    }
+
+    #[test]
+    fn header_mutations_change_only_the_selected_field() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for field in LoadHeaderField::ALL {
+            for _ in 0..20 {
+                let mut mutated = header();
+                LoadHeaderFieldMutator::new(field).mutate(
+                    &mut mutated,
+                    &mut rng,
+                    &mut InvertByteMutator,
+                );
+                assert_eq!(changed_header_fields(&header(), &mutated), vec![field]);
+
+                let Operation::LoadHeader { bits, version, .. } = mutated else {
+                    unreachable!();
+                };
+                assert!(HEADER_BITS.contains(&bits));
+                assert!(HEADER_VERSIONS.contains(&version));
+            }
+        }
+    }
+
+    #[test]
+    fn txo_mutations_change_only_the_selected_field() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for field in LoadTxoField::ALL {
+            let mut mutated = txo();
+            LoadTxoFieldMutator::new(field).mutate(&mut mutated, &mut rng, &mut InvertByteMutator);
+
+            let (
+                Operation::LoadTxo {
+                    outpoint: a_outpoint,
+                    value: a_value,
+                    script_pubkey: a_script_pubkey,
+                    spending_witness: a_spending_witness,
+                    ..
+                },
+                Operation::LoadTxo {
+                    outpoint,
+                    value,
+                    script_pubkey,
+                    spending_witness,
+                    ..
+                },
+            ) = (txo(), mutated)
+            else {
+                unreachable!();
+            };
+            let changed: Vec<_> = [
+                (a_outpoint != outpoint, LoadTxoField::Outpoint),
+                (a_value != value, LoadTxoField::Value),
+                (a_script_pubkey != script_pubkey, LoadTxoField::ScriptPubkey),
+                (
+                    a_spending_witness != spending_witness,
+                    LoadTxoField::SpendingWitness,
+                ),
+            ]
+            .into_iter()
+            .filter_map(|(changed, field)| changed.then_some(field))
+            .collect();
+            assert_eq!(changed, vec![field]);
+        }
+    }
+
+    #[test]
+    fn operation_mutator_mutates_headers() {
+        let mut builder = crate::ProgramBuilder::new(crate::ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        builder.force_append(vec![], header());
+        let program = builder.finalize().unwrap();
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut mutator = OperationMutator::new(InvertByteMutator);
+        let mut mutated = program.clone();
+        mutator.mutate(&mut mutated, &mut rng, None).unwrap();
+        assert_eq!(
+            changed_header_fields(
+                &program.instructions[0].operation,
+                &mutated.instructions[0].operation
+            )
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn msg_type_mutations_stay_ascii() {
+        let mut builder = crate::ProgramBuilder::new(crate::ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        builder.force_append(vec![], Operation::LoadMsgType(['\u{7f}'; MSG_TYPE_LEN]));
+        let program = builder.finalize().unwrap();
+
+        let dictionary = MsgTypeDictionary::new();
+        let mut mutator = OperationMutator::new(InvertByteMutator);
+        let (mut known, mut mutated) = (0, 0);
+        for seed in 0..32 {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut program = program.clone();
+            mutator.mutate(&mut program, &mut rng, None).unwrap();
+
+            let Operation::LoadMsgType(msg_type) = program.instructions[0].operation else {
+                panic!("expected LoadMsgType");
+            };
+            assert!(msg_type.iter().all(char::is_ascii));
+            if dictionary.entries().contains(&msg_type) {
+                known += 1;
+            } else {
+                // All bytes inverted and masked to ASCII
+                assert_eq!(msg_type, ['\0'; MSG_TYPE_LEN]);
+                mutated += 1;
+            }
+        }
+        assert!(known > 0 && mutated > 0);
+    }
+
+    #[test]
+    fn raw_bytes_are_preferred_mutation_targets() {
+        let mut builder = crate::ProgramBuilder::new(crate::ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        builder.force_append(vec![], Operation::LoadRawBytes(vec![0u8; 8]));
+        for _ in 0..15 {
+            builder.force_append(vec![], Operation::LoadBytes(vec![0u8; 8]));
+        }
+        let program = builder.finalize().unwrap();
+
+        let mut mutator = OperationMutator::new(InvertByteMutator);
+        let mut raw_bytes_mutated = 0;
+        for seed in 0..32 {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut mutated = program.clone();
+            mutator.mutate(&mut mutated, &mut rng, None).unwrap();
+            if mutated.instructions[0] != program.instructions[0] {
+                assert_eq!(
+                    mutated.instructions[0].operation,
+                    Operation::LoadRawBytes([vec![0xff; 8], vec![0xff]].concat())
+                );
+                raw_bytes_mutated += 1;
+            }
+        }
+        // Uniform selection would only pick the raw bytes in ~1/16 of the runs
+        assert!(raw_bytes_mutated >= 8);
+    }
+}
@@ -0,0 +1,177 @@
+use super::{Mutator, MutatorError, MutatorResult};
+use crate::{PerTestcaseMetadata, Program, ProgramBuilder};
+
+use rand::{RngCore, seq::SliceRandom};
+
+/// `ShuffleMutator` reorders independent instructions without changing the semantics of the
+/// program.
+///
+/// Maximal contiguous ranges of mutually independent instructions (no data-flow edge, no shared
+/// mutated variable and at most one instruction with side effects) at global scope are randomly
+/// permuted. The `swap_adjacent_independent` variant only swaps a single pair of adjacent
+/// independent instructions.
+pub struct ShuffleMutator {
+    swap_only: bool,
+}
+
+impl<R: RngCore> Mutator<R> for ShuffleMutator {
+    fn mutate(
+        &mut self,
+        program: &mut Program,
+        rng: &mut R,
+        _meta: Option<&PerTestcaseMetadata>,
+    ) -> MutatorResult {
+        let ranges = independent_ranges(program);
+
+        let mut order: Vec<usize> = (0..program.instructions.len()).collect();
+        if self.swap_only {
+            let Some(&(first, _)) = ranges
+                .iter()
+                .flat_map(|range| range.windows(2).map(|pair| (pair[0], pair[1])))
+                .collect::<Vec<_>>()
+                .choose(rng)
+            else {
+                return Err(MutatorError::NoMutationsAvailable);
+            };
+            order.swap(first, first + 1);
+        } else {
+            if ranges.is_empty() {
+                return Err(MutatorError::NoMutationsAvailable);
+            }
+            for range in ranges {
+                let mut shuffled = range.clone();
+                shuffled.shuffle(rng);
+                order[range[0]..range[0] + range.len()].copy_from_slice(&shuffled);
+            }
+        }
+
+        let reordered = program.reordered(&order);
+        ProgramBuilder::from_program(reordered.clone())
+            .map_err(|_| MutatorError::CreatedInvalidProgram)?;
+
+        *program = reordered;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        if self.swap_only {
+            "SwapAdjacentMutator"
+        } else {
+            "ShuffleMutator"
+        }
+    }
+}
+
+impl ShuffleMutator {
+    pub fn new() -> Self {
+        Self { swap_only: false }
+    }
+
+    /// Create a `ShuffleMutator` that only swaps two adjacent independent instructions
+    pub fn swap_adjacent_independent() -> Self {
+        Self { swap_only: true }
+    }
+}
+
+/// Find the maximal contiguous ranges (of at least two instructions) of mutually independent
+/// instructions at global scope
+fn independent_ranges(program: &Program) -> Vec<Vec<usize>> {
+    let predecessors = program.instruction_predecessors();
+
+    let mut ranges = Vec::new();
+    let mut current: Vec<usize> = Vec::new();
+    let mut depth = 0usize;
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        let is_block =
+            instruction.operation.is_block_begin() || instruction.operation.is_block_end();
+        let eligible = depth == 0 && !is_block;
+
+        // Instructions in a contiguous range can only depend on each other directly, so checking
+        // the direct predecessors is sufficient
+        let independent = current
+            .iter()
+            .all(|other| !predecessors[index].contains(other));
+        if !eligible || !independent {
+            if current.len() > 1 {
+                ranges.push(std::mem::take(&mut current));
+            }
+            current.clear();
+        }
+        if eligible {
+            current.push(index);
+        }
+
+        if instruction.operation.is_block_end() {
+            depth -= 1;
+        }
+        if instruction.operation.is_block_begin() {
+            depth += 1;
+        }
+    }
+    if current.len() > 1 {
+        ranges.push(current);
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Operation, test_utils};
+    use rand::{SeedableRng, rngs::SmallRng};
+    use std::collections::HashMap;
+
+    fn independent_program() -> Program {
+        let mut builder = test_utils::builder();
+        for i in 0..5 {
+            builder.force_append_expect_output(vec![], Operation::LoadBytes(vec![i]));
+        }
+        builder.finalize().unwrap()
+    }
+
+    fn ordering(program: &Program) -> Vec<u8> {
+        program
+            .instructions
+            .iter()
+            .map(|instruction| match &instruction.operation {
+                Operation::LoadBytes(bytes) => bytes[0],
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shuffle_produces_all_orderings_uniformly() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut mutator = ShuffleMutator::new();
+        let program = independent_program();
+
+        let iterations = 120 * 100;
+        let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+        for _ in 0..iterations {
+            let mut shuffled = program.clone();
+            mutator.mutate(&mut shuffled, &mut rng, None).unwrap();
+            *counts.entry(ordering(&shuffled)).or_default() += 1;
+        }
+
+        assert_eq!(counts.len(), 120);
+        // Each ordering is expected ~100 times
+        assert!(counts.values().all(|count| (50..=150).contains(count)));
+    }
+
+    #[test]
+    fn swap_keeps_dependent_instructions_in_order() {
+        let mut builder = test_utils::builder();
+        let time_var = builder.force_append_expect_output(vec![], Operation::LoadTime(1));
+        builder.force_append(vec![time_var.index], Operation::SetTime);
+        let mut program = builder.finalize().unwrap();
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut mutator = ShuffleMutator::swap_adjacent_independent();
+        assert!(matches!(
+            mutator.mutate(&mut program, &mut rng, None),
+            Err(MutatorError::NoMutationsAvailable)
+        ));
+    }
+}
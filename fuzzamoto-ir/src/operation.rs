@@ -1,6 +1,6 @@
 use crate::{AddrRecord, ProgramValidationError, Variable};
 
-use std::{fmt, time::Duration};
+use std::{fmt, hash::Hasher, time::Duration};
 
 /// Configuration for a single spendable Taproot leaf in `BuildTaprootTree`.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Hash, PartialEq)]
@@ -87,8 +87,6 @@ pub enum Operation {
     /// Script building operations
     BuildRawScripts,
     BuildPayToWitnessScriptHash,
-    // TODO: BuildPayToTaproot,
-    // TODO: BuildPayToBareMulti, BeginMultiSig, EndMultiSig
     BuildPayToPubKey,
     BuildPayToPubKeyHash,
     BuildPayToWitnessPubKeyHash,
@@ -187,9 +185,97 @@ pub enum Operation {
         /// None = key-path only spend; Some = script-path with one spendable leaf
         script_leaf: Option<TaprootLeafSpec>,
     },
-    // TODO: SendGetBlockTxn
-    // TODO: SendGetBlocks
-    // TODO: SendGetHeaders
+
+    /// Block corruption operations (produce an invalid copy of a block)
+    CorruptBlockMerkleRoot([u8; 32]),
+    CorruptBlockProofOfWork,
+    CorruptBlockCoinbaseValue(u64),
+
+    /// getblocktxn building operations
+    BeginBuildGetBlockTxn,
+    AddShortIdToReq,
+    EndBuildGetBlockTxn,
+    SendGetBlockTxn,
+
+    /// Load raw transaction bytes (not required to decode as a valid transaction)
+    LoadRawTransaction(Vec<u8>),
+    /// Send raw transaction bytes in a `tx` message without any validity checking
+    SendRawTransaction,
+
+    /// BIP-158 compact block filter responses, computed from a block's header and transactions
+    SendCFilter {
+        filter_type: u8,
+    },
+    SendCFHeaders {
+        filter_type: u8,
+        /// Filter header the block's filter hash is chained onto
+        prev_filter_header: [u8; 32],
+    },
+    SendCFCheckpt {
+        filter_type: u8,
+        /// Filter header the block's filter header is derived from
+        prev_filter_header: [u8; 32],
+    },
+
+    /// Load a message type given as a string, the compiler validates it and pads it to 12 chars
+    LoadMsgTypeFromStr(String),
+
+    /// Load a large payload (up to `MAX_PROTOCOL_MESSAGE_LENGTH` bytes), e.g. for
+    /// `SendRawMessage`. Unlike `LoadBytes`, the payload is a primary target of byte-level
+    /// mutations.
+    LoadRawBytes(Vec<u8>),
+
+    /// Build a `merkleblock` from a block, matching its transactions against a bloom filter
+    BuildMerkleBlock,
+    SendMerkleBlock,
+
+    /// Use the given bytes as the coinbase scriptSig, instead of the BIP34 height push
+    LoadCoinbaseScriptSig,
+    BuildCoinbaseTxInputWithScriptSig,
+
+    /// Block locator building (for `getblocks`/`getheaders`)
+    BeginBuildGetBlocksLocator,
+    EndBuildGetBlocksLocator,
+    AddLocatorHash,
+    SendGetBlocks,
+    SendGetHeaders,
+
+    SendPong,
+    SendSendCmpct,
+    SendSendHeaders,
+    SendFeeFilter,
+    SetLocatorStopHash,
+
+    /// Multisig script building operations (`OP_M <pubkeys> OP_N OP_CHECKMULTISIG`)
+    BeginBuildMultiSig,
+    AddMultiSigKey,
+    /// Bare multisig (P2MS)
+    EndBuildMultiSig,
+    /// Multisig wrapped in P2SH
+    EndBuildMultiSigScriptHash,
+    /// Multisig wrapped in P2WSH
+    EndBuildMultiSigWitnessScriptHash,
+
+    SendSendTxRcncl,
+
+    /// Block by the hash of a header without witness (e.g. for blocks only known by their header)
+    AddBlockHeaderInv,
+
+    /// Same as `LoadNonce`, for nonces that are used as full 64-bit values (e.g. `ping`)
+    LoadNonce64(u64),
+    /// Derive a new nonce from an existing one by flipping a fixed set of its bits
+    RandomizeNonce,
+    SendPingWithNonce,
+
+    SendMempoolMsg,
+    SendNotFound,
+
+    /// Payload of a `cfilter` message for a compact block filter present in the snapshot
+    LoadCFilter {
+        filter_type: u8,
+        block_hash: [u8; 32],
+        filter: Vec<u8>,
+    },
 }
 
 impl fmt::Display for Operation {
@@ -248,6 +334,13 @@ impl fmt::Display for Operation {
             Operation::BuildOpReturnScripts => write!(f, "BuildOpReturnScripts"),
             Operation::BuildPayToAnchor => write!(f, "BuildPayToAnchor"),
             Operation::BuildPayToTaproot => write!(f, "BuildPayToTaproot"),
+            Operation::BeginBuildMultiSig => write!(f, "BeginBuildMultiSig"),
+            Operation::AddMultiSigKey => write!(f, "AddMultiSigKey"),
+            Operation::EndBuildMultiSig => write!(f, "EndBuildMultiSig"),
+            Operation::EndBuildMultiSigScriptHash => write!(f, "EndBuildMultiSigScriptHash"),
+            Operation::EndBuildMultiSigWitnessScriptHash => {
+                write!(f, "EndBuildMultiSigWitnessScriptHash")
+            }
             Operation::BuildPayToPubKey => write!(f, "BuildPayToPubKey"),
             Operation::BuildPayToPubKeyHash => write!(f, "BuildPayToPubKeyHash"),
             Operation::BuildPayToWitnessPubKeyHash => write!(f, "BuildPayToWitnessPubKeyHash"),
@@ -319,12 +412,30 @@ impl fmt::Display for Operation {
             Operation::LoadFilterAdd { data } => {
                 write!(f, "LoadFilterAdd({})", hex_string(data))
             }
+            Operation::LoadCFilter {
+                filter_type,
+                block_hash,
+                filter,
+            } => write!(
+                f,
+                "LoadCFilter({}, {}, {})",
+                filter_type,
+                hex_string(block_hash),
+                hex_string(filter)
+            ),
             Operation::LoadNonce(nonce) => {
                 write!(f, "LoadNonce({})", nonce)
             }
+            Operation::LoadNonce64(nonce) => {
+                write!(f, "LoadNonce64({})", nonce)
+            }
+            Operation::RandomizeNonce => write!(f, "RandomizeNonce"),
             Operation::BeginBuildBlockTxn => write!(f, "BeginBuildBlockTxn"),
             Operation::AddTxToBlockTxn => write!(f, "AddTxToBlockTxn"),
             Operation::EndBuildBlockTxn => write!(f, "EndBuildBlockTxn"),
+            Operation::BeginBuildGetBlockTxn => write!(f, "BeginBuildGetBlockTxn"),
+            Operation::AddShortIdToReq => write!(f, "AddShortIdToReq"),
+            Operation::EndBuildGetBlockTxn => write!(f, "EndBuildGetBlockTxn"),
             Operation::BeginBuildFilterLoad => write!(f, "BeginBuildFilterLoad"),
             Operation::EndBuildFilterLoad => write!(f, "EndBuildFilterLoad"),
             Operation::AddTxToFilter => write!(f, "AddTxToFilter"),
@@ -351,6 +462,10 @@ impl fmt::Display for Operation {
             Operation::BeginBuildCoinbaseTx => write!(f, "BeginBuildCoinbaseTx"),
             Operation::EndBuildCoinbaseTx => write!(f, "EndBuildCoinbaseTx"),
             Operation::BuildCoinbaseTxInput => write!(f, "BuildCoinbaseTxInput"),
+            Operation::LoadCoinbaseScriptSig => write!(f, "LoadCoinbaseScriptSig"),
+            Operation::BuildCoinbaseTxInputWithScriptSig => {
+                write!(f, "BuildCoinbaseTxInputWithScriptSig")
+            }
             Operation::BeginBuildCoinbaseTxOutputs => write!(f, "BeginBuildCoinbaseTxOutputs"),
             Operation::EndBuildCoinbaseTxOutputs => write!(f, "EndBuildCoinbaseTxOutputs"),
             Operation::AddCoinbaseTxOutput => write!(f, "AddCoinbaseTxOutput"),
@@ -362,8 +477,13 @@ impl fmt::Display for Operation {
             Operation::AddTxidWithWitnessInv => write!(f, "AddTxidWithWitnessInv"),
             Operation::AddWtxidInv => write!(f, "AddWtxidInv"),
             Operation::AddBlockInv => write!(f, "AddBlockInv"),
+            Operation::AddBlockHeaderInv => write!(f, "AddBlockHeaderInv"),
             Operation::AddBlockWithWitnessInv => write!(f, "AddBlockWithWitnessInv"),
             Operation::AddFilteredBlockInv => write!(f, "AddFilteredBlockInv"),
+            Operation::BeginBuildGetBlocksLocator => write!(f, "BeginBuildGetBlocksLocator"),
+            Operation::EndBuildGetBlocksLocator => write!(f, "EndBuildGetBlocksLocator"),
+            Operation::AddLocatorHash => write!(f, "AddLocatorHash"),
+            Operation::SetLocatorStopHash => write!(f, "SetLocatorStopHash"),
             Operation::BeginBuildAddrList => write!(f, "BeginBuildAddrList"),
             Operation::EndBuildAddrList => write!(f, "EndBuildAddrList"),
             Operation::AddAddr => write!(f, "AddAddr"),
@@ -377,6 +497,8 @@ impl fmt::Display for Operation {
             Operation::AddTx => write!(f, "AddTx"),
 
             Operation::SendGetData => write!(f, "SendGetData"),
+            Operation::SendGetBlocks => write!(f, "SendGetBlocks"),
+            Operation::SendGetHeaders => write!(f, "SendGetHeaders"),
             Operation::SendInv => write!(f, "SendInv"),
             Operation::SendGetAddr => write!(f, "SendGetAddr"),
             Operation::SendAddr => write!(f, "SendAddr"),
@@ -392,8 +514,49 @@ impl fmt::Display for Operation {
             Operation::SendFilterLoad => write!(f, "SendFilterLoad"),
             Operation::SendFilterAdd => write!(f, "SendFilterAdd"),
             Operation::SendFilterClear => write!(f, "SendFilterClear"),
+            Operation::SendMempoolMsg => write!(f, "SendMempoolMsg"),
+            Operation::SendFeeFilter => write!(f, "SendFeeFilter"),
+            Operation::SendPingWithNonce => write!(f, "SendPingWithNonce"),
+            Operation::SendPong => write!(f, "SendPong"),
+            Operation::SendNotFound => write!(f, "SendNotFound"),
             Operation::SendCompactBlock => write!(f, "SendCompactBlock"),
+            Operation::BuildMerkleBlock => write!(f, "BuildMerkleBlock"),
+            Operation::SendMerkleBlock => write!(f, "SendMerkleBlock"),
+            Operation::SendSendCmpct => write!(f, "SendSendCmpct"),
+            Operation::SendSendHeaders => write!(f, "SendSendHeaders"),
+            Operation::SendSendTxRcncl => write!(f, "SendSendTxRcncl"),
             Operation::SendBlockTxn => write!(f, "SendBlockTxn"),
+            Operation::SendGetBlockTxn => write!(f, "SendGetBlockTxn"),
+            Operation::LoadRawTransaction(bytes) => {
+                write!(f, "LoadRawTransaction(\"{}\")", hex_string(bytes))
+            }
+            Operation::SendRawTransaction => write!(f, "SendRawTransaction"),
+            Operation::SendCFilter { filter_type } => write!(f, "SendCFilter({})", filter_type),
+            Operation::SendCFHeaders {
+                filter_type,
+                prev_filter_header,
+            } => write!(
+                f,
+                "SendCFHeaders({}, {})",
+                filter_type,
+                hex_string(prev_filter_header)
+            ),
+            Operation::SendCFCheckpt {
+                filter_type,
+                prev_filter_header,
+            } => write!(
+                f,
+                "SendCFCheckpt({}, {})",
+                filter_type,
+                hex_string(prev_filter_header)
+            ),
+            Operation::LoadMsgTypeFromStr(msg_type) => {
+                write!(f, "LoadMsgTypeFromStr(\"{}\")", msg_type.escape_default())
+            }
+            Operation::LoadRawBytes(bytes) => {
+                // only print the length, the payload may be megabytes large
+                write!(f, "LoadRawBytes((payload)(len: {}))", bytes.len())
+            }
 
             Operation::Probe => write!(f, "Probe"),
 
@@ -415,6 +578,13 @@ impl fmt::Display for Operation {
                 }
                 write!(f, ")")
             }
+            Operation::CorruptBlockMerkleRoot(merkle_root) => {
+                write!(f, "CorruptBlockMerkleRoot({})", hex_string(merkle_root))
+            }
+            Operation::CorruptBlockProofOfWork => write!(f, "CorruptBlockProofOfWork"),
+            Operation::CorruptBlockCoinbaseValue(value) => {
+                write!(f, "CorruptBlockCoinbaseValue({})", value)
+            }
         }
     }
 }
@@ -430,7 +600,233 @@ fn hex_witness_stack(witness: &[Vec<u8>]) -> String {
     witness.iter().map(|b| hex_string(b)).collect::<String>()
 }
 
+/// `postcard` serialization flavor that feeds the serialized bytes into a `Hasher`
+struct HasherFlavor<'a, H: Hasher>(&'a mut H);
+
+impl<H: Hasher> postcard::ser_flavors::Flavor for HasherFlavor<'_, H> {
+    type Output = ();
+
+    fn try_extend(&mut self, data: &[u8]) -> postcard::Result<()> {
+        self.0.write(data);
+        Ok(())
+    }
+
+    fn try_push(&mut self, data: u8) -> postcard::Result<()> {
+        self.0.write(&[data]);
+        Ok(())
+    }
+
+    fn finalize(self) -> postcard::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash the `postcard` encoding of `value`. Unlike `std::hash::Hash`, the encoding is independent
+/// of the platform and the Rust version (integers are varint encoded in little endian order and
+/// enums are encoded as their variant index followed by their fields).
+pub(crate) fn hash_serialized<T: serde::Serialize + ?Sized>(value: &T, hasher: &mut impl Hasher) {
+    postcard::serialize_with_flavor(value, HasherFlavor(hasher)).expect("hashing should not fail");
+}
+
 impl Operation {
+    /// Hash the operation in a stable way, i.e. the variant index followed by the bytes of its
+    /// fields (see `hash_serialized`). Only `Hasher::write` is used, so the hash is as stable as
+    /// `hasher` itself.
+    pub fn hash_stable(&self, hasher: &mut impl Hasher) {
+        hash_serialized(self, hasher);
+    }
+
+    /// Name of the operation's variant without any of its parameters (e.g. `LoadBytes`)
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Operation::Nop { .. } => "Nop",
+            Operation::LoadBytes(..) => "LoadBytes",
+            Operation::LoadMsgType(..) => "LoadMsgType",
+            Operation::LoadNode(..) => "LoadNode",
+            Operation::LoadConnection(..) => "LoadConnection",
+            Operation::LoadConnectionType(..) => "LoadConnectionType",
+            Operation::LoadDuration(..) => "LoadDuration",
+            Operation::LoadAddr(..) => "LoadAddr",
+            Operation::LoadTime(..) => "LoadTime",
+            Operation::LoadAmount(..) => "LoadAmount",
+            Operation::LoadSize(..) => "LoadSize",
+            Operation::LoadTxVersion(..) => "LoadTxVersion",
+            Operation::LoadBlockVersion(..) => "LoadBlockVersion",
+            Operation::LoadLockTime(..) => "LoadLockTime",
+            Operation::LoadSequence(..) => "LoadSequence",
+            Operation::LoadBlockHeight(..) => "LoadBlockHeight",
+            Operation::LoadCompactFilterType(..) => "LoadCompactFilterType",
+            Operation::LoadPrivateKey(..) => "LoadPrivateKey",
+            Operation::LoadSigHashFlags(..) => "LoadSigHashFlags",
+            Operation::LoadNonce(..) => "LoadNonce",
+            Operation::LoadTxo { .. } => "LoadTxo",
+            Operation::LoadTaprootAnnex { .. } => "LoadTaprootAnnex",
+            Operation::LoadHeader { .. } => "LoadHeader",
+            Operation::LoadFilterLoad { .. } => "LoadFilterLoad",
+            Operation::LoadFilterAdd { .. } => "LoadFilterAdd",
+            Operation::BeginBuildBlockTxn => "BeginBuildBlockTxn",
+            Operation::AddTxToBlockTxn => "AddTxToBlockTxn",
+            Operation::EndBuildBlockTxn => "EndBuildBlockTxn",
+            Operation::SendRawMessage => "SendRawMessage",
+            Operation::AdvanceTime => "AdvanceTime",
+            Operation::SetTime => "SetTime",
+            Operation::BuildRawScripts => "BuildRawScripts",
+            Operation::BuildPayToWitnessScriptHash => "BuildPayToWitnessScriptHash",
+            Operation::BuildPayToPubKey => "BuildPayToPubKey",
+            Operation::BuildPayToPubKeyHash => "BuildPayToPubKeyHash",
+            Operation::BuildPayToWitnessPubKeyHash => "BuildPayToWitnessPubKeyHash",
+            Operation::BuildPayToScriptHash => "BuildPayToScriptHash",
+            Operation::BuildOpReturnScripts => "BuildOpReturnScripts",
+            Operation::BuildPayToAnchor => "BuildPayToAnchor",
+            Operation::BuildPayToTaproot => "BuildPayToTaproot",
+            Operation::BuildCompactBlock => "BuildCompactBlock",
+            Operation::BeginBuildFilterLoad => "BeginBuildFilterLoad",
+            Operation::AddTxToFilter => "AddTxToFilter",
+            Operation::AddTxoToFilter => "AddTxoToFilter",
+            Operation::EndBuildFilterLoad => "EndBuildFilterLoad",
+            Operation::BuildFilterAddFromTx => "BuildFilterAddFromTx",
+            Operation::BuildFilterAddFromTxo => "BuildFilterAddFromTxo",
+            Operation::BeginWitnessStack => "BeginWitnessStack",
+            Operation::EndWitnessStack => "EndWitnessStack",
+            Operation::AddWitness => "AddWitness",
+            Operation::BeginBuildTx => "BeginBuildTx",
+            Operation::EndBuildTx => "EndBuildTx",
+            Operation::BeginBuildTxInputs => "BeginBuildTxInputs",
+            Operation::EndBuildTxInputs => "EndBuildTxInputs",
+            Operation::BeginBuildTxOutputs => "BeginBuildTxOutputs",
+            Operation::EndBuildTxOutputs => "EndBuildTxOutputs",
+            Operation::AddTxOutput => "AddTxOutput",
+            Operation::AddTxInput => "AddTxInput",
+            Operation::TakeTxo => "TakeTxo",
+            Operation::TakeCoinbaseTxo => "TakeCoinbaseTxo",
+            Operation::BeginBuildCoinbaseTx => "BeginBuildCoinbaseTx",
+            Operation::EndBuildCoinbaseTx => "EndBuildCoinbaseTx",
+            Operation::BuildCoinbaseTxInput => "BuildCoinbaseTxInput",
+            Operation::BeginBuildCoinbaseTxOutputs => "BeginBuildCoinbaseTxOutputs",
+            Operation::EndBuildCoinbaseTxOutputs => "EndBuildCoinbaseTxOutputs",
+            Operation::AddCoinbaseTxOutput => "AddCoinbaseTxOutput",
+            Operation::BeginBlockTransactions => "BeginBlockTransactions",
+            Operation::EndBlockTransactions => "EndBlockTransactions",
+            Operation::BuildBlock => "BuildBlock",
+            Operation::AddTx => "AddTx",
+            Operation::BeginBuildInventory => "BeginBuildInventory",
+            Operation::EndBuildInventory => "EndBuildInventory",
+            Operation::AddCompactBlockInv => "AddCompactBlockInv",
+            Operation::AddTxidInv => "AddTxidInv",
+            Operation::AddTxidWithWitnessInv => "AddTxidWithWitnessInv",
+            Operation::AddWtxidInv => "AddWtxidInv",
+            Operation::AddBlockInv => "AddBlockInv",
+            Operation::AddBlockWithWitnessInv => "AddBlockWithWitnessInv",
+            Operation::AddFilteredBlockInv => "AddFilteredBlockInv",
+            Operation::BeginBuildAddrList => "BeginBuildAddrList",
+            Operation::EndBuildAddrList => "EndBuildAddrList",
+            Operation::AddAddr => "AddAddr",
+            Operation::BeginBuildAddrListV2 => "BeginBuildAddrListV2",
+            Operation::EndBuildAddrListV2 => "EndBuildAddrListV2",
+            Operation::AddAddrV2 => "AddAddrV2",
+            Operation::Probe => "Probe",
+            Operation::SendGetData => "SendGetData",
+            Operation::SendInv => "SendInv",
+            Operation::SendGetAddr => "SendGetAddr",
+            Operation::SendAddr => "SendAddr",
+            Operation::SendAddrV2 => "SendAddrV2",
+            Operation::SendTx => "SendTx",
+            Operation::SendTxNoWit => "SendTxNoWit",
+            Operation::SendHeader => "SendHeader",
+            Operation::SendBlock => "SendBlock",
+            Operation::SendBlockNoWit => "SendBlockNoWit",
+            Operation::SendGetCFilters => "SendGetCFilters",
+            Operation::SendGetCFHeaders => "SendGetCFHeaders",
+            Operation::SendGetCFCheckpt => "SendGetCFCheckpt",
+            Operation::SendFilterLoad => "SendFilterLoad",
+            Operation::SendFilterAdd => "SendFilterAdd",
+            Operation::SendFilterClear => "SendFilterClear",
+            Operation::SendCompactBlock => "SendCompactBlock",
+            Operation::SendBlockTxn => "SendBlockTxn",
+            Operation::TaprootScriptsUseAnnex => "TaprootScriptsUseAnnex",
+            Operation::TaprootTxoUseAnnex => "TaprootTxoUseAnnex",
+            Operation::BuildTaprootTree { .. } => "BuildTaprootTree",
+            Operation::CorruptBlockMerkleRoot(..) => "CorruptBlockMerkleRoot",
+            Operation::CorruptBlockProofOfWork => "CorruptBlockProofOfWork",
+            Operation::CorruptBlockCoinbaseValue(..) => "CorruptBlockCoinbaseValue",
+            Operation::BeginBuildGetBlockTxn => "BeginBuildGetBlockTxn",
+            Operation::AddShortIdToReq => "AddShortIdToReq",
+            Operation::EndBuildGetBlockTxn => "EndBuildGetBlockTxn",
+            Operation::SendGetBlockTxn => "SendGetBlockTxn",
+            Operation::LoadRawTransaction(..) => "LoadRawTransaction",
+            Operation::SendRawTransaction => "SendRawTransaction",
+            Operation::SendCFilter { .. } => "SendCFilter",
+            Operation::SendCFHeaders { .. } => "SendCFHeaders",
+            Operation::SendCFCheckpt { .. } => "SendCFCheckpt",
+            Operation::LoadMsgTypeFromStr(..) => "LoadMsgTypeFromStr",
+            Operation::LoadRawBytes(..) => "LoadRawBytes",
+            Operation::BuildMerkleBlock => "BuildMerkleBlock",
+            Operation::SendMerkleBlock => "SendMerkleBlock",
+            Operation::LoadCoinbaseScriptSig => "LoadCoinbaseScriptSig",
+            Operation::BuildCoinbaseTxInputWithScriptSig => "BuildCoinbaseTxInputWithScriptSig",
+            Operation::BeginBuildGetBlocksLocator => "BeginBuildGetBlocksLocator",
+            Operation::EndBuildGetBlocksLocator => "EndBuildGetBlocksLocator",
+            Operation::AddLocatorHash => "AddLocatorHash",
+            Operation::SendGetBlocks => "SendGetBlocks",
+            Operation::SendGetHeaders => "SendGetHeaders",
+            Operation::SendPong => "SendPong",
+            Operation::SendSendCmpct => "SendSendCmpct",
+            Operation::SendSendHeaders => "SendSendHeaders",
+            Operation::SendFeeFilter => "SendFeeFilter",
+            Operation::SetLocatorStopHash => "SetLocatorStopHash",
+            Operation::BeginBuildMultiSig => "BeginBuildMultiSig",
+            Operation::AddMultiSigKey => "AddMultiSigKey",
+            Operation::EndBuildMultiSig => "EndBuildMultiSig",
+            Operation::EndBuildMultiSigScriptHash => "EndBuildMultiSigScriptHash",
+            Operation::EndBuildMultiSigWitnessScriptHash => "EndBuildMultiSigWitnessScriptHash",
+            Operation::SendSendTxRcncl => "SendSendTxRcncl",
+            Operation::AddBlockHeaderInv => "AddBlockHeaderInv",
+            Operation::LoadNonce64(..) => "LoadNonce64",
+            Operation::RandomizeNonce => "RandomizeNonce",
+            Operation::SendPingWithNonce => "SendPingWithNonce",
+            Operation::SendMempoolMsg => "SendMempoolMsg",
+            Operation::SendNotFound => "SendNotFound",
+            Operation::LoadCFilter { .. } => "LoadCFilter",
+        }
+    }
+
+    /// Whether the operation is one of the `Load*` operations, which have no inputs and no side
+    /// effects
+    pub fn is_load(&self) -> bool {
+        matches!(
+            self,
+            Operation::LoadBytes(..)
+                | Operation::LoadMsgType(..)
+                | Operation::LoadNode(..)
+                | Operation::LoadConnection(..)
+                | Operation::LoadConnectionType(..)
+                | Operation::LoadDuration(..)
+                | Operation::LoadAddr(..)
+                | Operation::LoadTime(..)
+                | Operation::LoadAmount(..)
+                | Operation::LoadSize(..)
+                | Operation::LoadTxVersion(..)
+                | Operation::LoadBlockVersion(..)
+                | Operation::LoadLockTime(..)
+                | Operation::LoadSequence(..)
+                | Operation::LoadBlockHeight(..)
+                | Operation::LoadCompactFilterType(..)
+                | Operation::LoadPrivateKey(..)
+                | Operation::LoadSigHashFlags(..)
+                | Operation::LoadNonce(..)
+                | Operation::LoadNonce64(..)
+                | Operation::LoadTxo { .. }
+                | Operation::LoadTaprootAnnex { .. }
+                | Operation::LoadHeader { .. }
+                | Operation::LoadFilterLoad { .. }
+                | Operation::LoadFilterAdd { .. }
+                | Operation::LoadCFilter { .. }
+                | Operation::LoadRawTransaction(..)
+                | Operation::LoadMsgTypeFromStr(..)
+                | Operation::LoadRawBytes(..)
+        )
+    }
+
     pub fn mutates_nth_input(&self, index: usize) -> bool {
         match self {
             Operation::AddTxInput if index == 0 => true,
@@ -442,9 +838,13 @@ impl Operation {
             Operation::AddTxidInv if index == 0 => true,
             Operation::AddTxidWithWitnessInv if index == 0 => true,
             Operation::AddWtxidInv if index == 0 => true,
+            Operation::AddLocatorHash if index == 0 => true,
+            Operation::SetLocatorStopHash if index == 0 => true,
+            Operation::AddMultiSigKey if index == 0 => true,
             Operation::AddTx if index == 0 => true,
             Operation::AddAddr if index == 0 => true,
             Operation::AddAddrV2 if index == 0 => true,
+            Operation::AddShortIdToReq if index == 0 => true,
             _ => false,
         }
     }
@@ -462,6 +862,9 @@ impl Operation {
             | Operation::BeginBuildFilterLoad
             | Operation::BeginBuildCoinbaseTx
             | Operation::BeginBuildBlockTxn
+            | Operation::BeginBuildGetBlockTxn
+            | Operation::BeginBuildGetBlocksLocator
+            | Operation::BeginBuildMultiSig
             | Operation::BeginBuildCoinbaseTxOutputs => true,
             // Exhaustive match to fail when new ops are added
             Operation::Nop { .. }
@@ -485,6 +888,10 @@ impl Operation {
             | Operation::BuildOpReturnScripts
             | Operation::BuildPayToAnchor
             | Operation::BuildPayToTaproot
+            | Operation::AddMultiSigKey
+            | Operation::EndBuildMultiSig
+            | Operation::EndBuildMultiSigScriptHash
+            | Operation::EndBuildMultiSigWitnessScriptHash
             | Operation::BuildPayToPubKey
             | Operation::BuildPayToPubKeyHash
             | Operation::BuildPayToWitnessPubKeyHash
@@ -500,13 +907,17 @@ impl Operation {
             | Operation::LoadSigHashFlags(..)
             | Operation::LoadFilterLoad { .. }
             | Operation::LoadFilterAdd { .. }
+            | Operation::LoadCFilter { .. }
             | Operation::EndBuildFilterLoad
             | Operation::AddTxToFilter
             | Operation::AddTxoToFilter
             | Operation::BuildFilterAddFromTx
             | Operation::BuildFilterAddFromTxo
             | Operation::BuildCompactBlock
+            | Operation::BuildMerkleBlock
             | Operation::LoadNonce(..)
+            | Operation::LoadNonce64(..)
+            | Operation::RandomizeNonce
             | Operation::AddTxToBlockTxn
             | Operation::EndBuildBlockTxn
             | Operation::EndBuildTx
@@ -519,10 +930,15 @@ impl Operation {
             | Operation::AddTxidInv
             | Operation::AddTxidWithWitnessInv
             | Operation::AddWtxidInv
+            | Operation::EndBuildGetBlocksLocator
+            | Operation::AddLocatorHash
+            | Operation::SetLocatorStopHash
             | Operation::AddAddr
             | Operation::AddAddrV2
             | Operation::SendGetData
             | Operation::SendInv
+            | Operation::SendGetBlocks
+            | Operation::SendGetHeaders
             | Operation::SendGetAddr
             | Operation::SendAddr
             | Operation::SendAddrV2
@@ -534,6 +950,7 @@ impl Operation {
             | Operation::AddWitness
             | Operation::BuildBlock
             | Operation::AddBlockInv
+            | Operation::AddBlockHeaderInv
             | Operation::AddBlockWithWitnessInv
             | Operation::AddFilteredBlockInv
             | Operation::AddTx
@@ -548,17 +965,41 @@ impl Operation {
             | Operation::SendFilterLoad
             | Operation::SendFilterAdd
             | Operation::SendFilterClear
+            | Operation::SendMempoolMsg
+            | Operation::SendFeeFilter
+            | Operation::SendPingWithNonce
+            | Operation::SendPong
+            | Operation::SendNotFound
             | Operation::SendBlockNoWit
             | Operation::SendCompactBlock
+            | Operation::SendMerkleBlock
+            | Operation::SendSendCmpct
+            | Operation::SendSendHeaders
+            | Operation::SendSendTxRcncl
             | Operation::EndBuildCoinbaseTx
             | Operation::EndBuildCoinbaseTxOutputs
             | Operation::BuildCoinbaseTxInput
+            | Operation::LoadCoinbaseScriptSig
+            | Operation::BuildCoinbaseTxInputWithScriptSig
             | Operation::AddCoinbaseTxOutput
             | Operation::SendBlockTxn
+            | Operation::AddShortIdToReq
+            | Operation::EndBuildGetBlockTxn
+            | Operation::SendGetBlockTxn
             | Operation::Probe
             | Operation::TaprootScriptsUseAnnex
             | Operation::TaprootTxoUseAnnex
-            | Operation::BuildTaprootTree { .. } => false,
+            | Operation::BuildTaprootTree { .. }
+            | Operation::CorruptBlockMerkleRoot(_)
+            | Operation::CorruptBlockProofOfWork
+            | Operation::CorruptBlockCoinbaseValue(_)
+            | Operation::LoadRawTransaction(_)
+            | Operation::SendRawTransaction
+            | Operation::SendCFilter { .. }
+            | Operation::SendCFHeaders { .. }
+            | Operation::SendCFCheckpt { .. }
+            | Operation::LoadMsgTypeFromStr(_)
+            | Operation::LoadRawBytes(_) => false,
         }
     }
 
@@ -575,6 +1016,10 @@ impl Operation {
             | (Operation::BeginBuildTxInputs, Operation::EndBuildTxInputs)
             | (Operation::BeginBuildTxOutputs, Operation::EndBuildTxOutputs)
             | (Operation::BeginBuildInventory, Operation::EndBuildInventory)
+            | (Operation::BeginBuildGetBlocksLocator, Operation::EndBuildGetBlocksLocator)
+            | (Operation::BeginBuildMultiSig, Operation::EndBuildMultiSig)
+            | (Operation::BeginBuildMultiSig, Operation::EndBuildMultiSigScriptHash)
+            | (Operation::BeginBuildMultiSig, Operation::EndBuildMultiSigWitnessScriptHash)
             | (Operation::BeginBuildAddrList, Operation::EndBuildAddrList)
             | (Operation::BeginBuildAddrListV2, Operation::EndBuildAddrListV2)
             | (Operation::BeginWitnessStack, Operation::EndWitnessStack)
@@ -582,7 +1027,8 @@ impl Operation {
             | (Operation::BeginBuildFilterLoad, Operation::EndBuildFilterLoad)
             | (Operation::BeginBuildCoinbaseTx, Operation::EndBuildCoinbaseTx)
             | (Operation::BeginBuildCoinbaseTxOutputs, Operation::EndBuildCoinbaseTxOutputs)
-            | (Operation::BeginBuildBlockTxn, Operation::EndBuildBlockTxn) => true,
+            | (Operation::BeginBuildBlockTxn, Operation::EndBuildBlockTxn)
+            | (Operation::BeginBuildGetBlockTxn, Operation::EndBuildGetBlockTxn) => true,
             _ => false,
         }
     }
@@ -600,6 +1046,11 @@ impl Operation {
             | Operation::EndBuildFilterLoad
             | Operation::EndBuildCoinbaseTx
             | Operation::EndBuildBlockTxn
+            | Operation::EndBuildGetBlockTxn
+            | Operation::EndBuildGetBlocksLocator
+            | Operation::EndBuildMultiSig
+            | Operation::EndBuildMultiSigScriptHash
+            | Operation::EndBuildMultiSigWitnessScriptHash
             | Operation::EndBuildCoinbaseTxOutputs => true,
             // Exhaustive match to fail when new ops are added
             Operation::Nop { .. }
@@ -623,6 +1074,8 @@ impl Operation {
             | Operation::BuildOpReturnScripts
             | Operation::BuildPayToAnchor
             | Operation::BuildPayToTaproot
+            | Operation::BeginBuildMultiSig
+            | Operation::AddMultiSigKey
             | Operation::BuildPayToPubKey
             | Operation::BuildPayToPubKeyHash
             | Operation::BuildPayToWitnessPubKeyHash
@@ -638,12 +1091,18 @@ impl Operation {
             | Operation::LoadSigHashFlags(..)
             | Operation::LoadFilterLoad { .. }
             | Operation::LoadFilterAdd { .. }
+            | Operation::LoadCFilter { .. }
             | Operation::LoadNonce(..)
+            | Operation::LoadNonce64(..)
+            | Operation::RandomizeNonce
             | Operation::BeginBuildBlockTxn
             | Operation::AddTxToBlockTxn
             | Operation::TaprootScriptsUseAnnex
             | Operation::TaprootTxoUseAnnex
             | Operation::BuildTaprootTree { .. }
+            | Operation::CorruptBlockMerkleRoot(_)
+            | Operation::CorruptBlockProofOfWork
+            | Operation::CorruptBlockCoinbaseValue(_)
             | Operation::BeginBuildTx
             | Operation::BeginBuildTxInputs
             | Operation::BeginBuildTxOutputs
@@ -660,16 +1119,22 @@ impl Operation {
             | Operation::AddTxidInv
             | Operation::AddTxidWithWitnessInv
             | Operation::AddWtxidInv
+            | Operation::BeginBuildGetBlocksLocator
+            | Operation::AddLocatorHash
+            | Operation::SetLocatorStopHash
             | Operation::AddAddr
             | Operation::AddAddrV2
             | Operation::BuildBlock
             | Operation::AddBlockInv
+            | Operation::AddBlockHeaderInv
             | Operation::AddBlockWithWitnessInv
             | Operation::AddFilteredBlockInv
             | Operation::AddTx
             | Operation::BeginBlockTransactions
             | Operation::SendGetData
             | Operation::SendInv
+            | Operation::SendGetBlocks
+            | Operation::SendGetHeaders
             | Operation::SendGetAddr
             | Operation::SendAddr
             | Operation::SendAddrV2
@@ -687,15 +1152,37 @@ impl Operation {
             | Operation::BuildFilterAddFromTx
             | Operation::BuildFilterAddFromTxo
             | Operation::BuildCompactBlock
+            | Operation::BuildMerkleBlock
             | Operation::SendFilterLoad
             | Operation::SendFilterAdd
             | Operation::SendFilterClear
+            | Operation::SendMempoolMsg
+            | Operation::SendFeeFilter
+            | Operation::SendPingWithNonce
+            | Operation::SendPong
+            | Operation::SendNotFound
             | Operation::SendCompactBlock
+            | Operation::SendMerkleBlock
+            | Operation::SendSendCmpct
+            | Operation::SendSendHeaders
+            | Operation::SendSendTxRcncl
             | Operation::BeginBuildCoinbaseTx
             | Operation::BeginBuildCoinbaseTxOutputs
             | Operation::BuildCoinbaseTxInput
+            | Operation::LoadCoinbaseScriptSig
+            | Operation::BuildCoinbaseTxInputWithScriptSig
             | Operation::AddCoinbaseTxOutput
             | Operation::SendBlockTxn
+            | Operation::BeginBuildGetBlockTxn
+            | Operation::AddShortIdToReq
+            | Operation::SendGetBlockTxn
+            | Operation::LoadRawTransaction(_)
+            | Operation::SendRawTransaction
+            | Operation::SendCFilter { .. }
+            | Operation::SendCFHeaders { .. }
+            | Operation::SendCFCheckpt { .. }
+            | Operation::LoadMsgTypeFromStr(_)
+            | Operation::LoadRawBytes(_)
             | Operation::Probe => false,
         }
     }
@@ -760,6 +1247,11 @@ impl Operation {
             Operation::BuildOpReturnScripts => vec![Variable::Scripts],
             Operation::BuildPayToAnchor => vec![Variable::Scripts],
             Operation::BuildPayToTaproot => vec![Variable::Scripts],
+            Operation::BeginBuildMultiSig => vec![],
+            Operation::AddMultiSigKey => vec![],
+            Operation::EndBuildMultiSig
+            | Operation::EndBuildMultiSigScriptHash
+            | Operation::EndBuildMultiSigWitnessScriptHash => vec![Variable::Scripts],
             Operation::BuildPayToPubKey => vec![Variable::Scripts],
             Operation::BuildPayToPubKeyHash => vec![Variable::Scripts],
             Operation::BuildPayToWitnessPubKeyHash => vec![Variable::Scripts],
@@ -777,9 +1269,15 @@ impl Operation {
             Operation::LoadHeader { .. } => vec![Variable::Header],
             Operation::LoadFilterLoad { .. } => vec![Variable::ConstFilterLoad],
             Operation::LoadFilterAdd { .. } => vec![Variable::FilterAdd],
+            Operation::LoadCFilter { .. } => vec![Variable::Bytes],
+            Operation::LoadRawTransaction(_) => vec![Variable::RawTransaction],
+            Operation::LoadMsgTypeFromStr(_) => vec![Variable::MsgType],
+            Operation::LoadRawBytes(_) => vec![Variable::Bytes],
             Operation::LoadPrivateKey(..) => vec![Variable::PrivateKey],
             Operation::LoadSigHashFlags(..) => vec![Variable::SigHashFlags],
             Operation::LoadNonce(..) => vec![Variable::Nonce],
+            Operation::LoadNonce64(..) => vec![Variable::Nonce],
+            Operation::RandomizeNonce => vec![Variable::Nonce],
             Operation::BeginBuildTx => vec![],
             Operation::EndBuildTx => vec![Variable::ConstTx],
             Operation::BeginBuildTxInputs => vec![],
@@ -792,6 +1290,9 @@ impl Operation {
             Operation::BeginBuildBlockTxn => vec![],
             Operation::AddTxToBlockTxn => vec![],
             Operation::EndBuildBlockTxn => vec![Variable::ConstBlockTxn],
+            Operation::BeginBuildGetBlockTxn => vec![],
+            Operation::AddShortIdToReq => vec![],
+            Operation::EndBuildGetBlockTxn => vec![Variable::ConstGetBlockTxnReq],
 
             Operation::BeginBuildFilterLoad => vec![],
             Operation::AddTxToFilter => vec![],
@@ -799,6 +1300,7 @@ impl Operation {
             Operation::EndBuildFilterLoad => vec![Variable::ConstFilterLoad],
 
             Operation::BuildCompactBlock => vec![Variable::CompactBlock],
+            Operation::BuildMerkleBlock => vec![Variable::MerkleBlock],
 
             Operation::BuildFilterAddFromTx => vec![Variable::FilterAdd],
             Operation::BuildFilterAddFromTxo => vec![Variable::FilterAdd],
@@ -806,6 +1308,8 @@ impl Operation {
             Operation::BeginBuildCoinbaseTx => vec![],
             Operation::EndBuildCoinbaseTx => vec![Variable::CoinbaseTx],
             Operation::BuildCoinbaseTxInput => vec![Variable::CoinbaseInput],
+            Operation::LoadCoinbaseScriptSig => vec![Variable::CoinbaseScriptSig],
+            Operation::BuildCoinbaseTxInputWithScriptSig => vec![Variable::CoinbaseInput],
             Operation::BeginBuildCoinbaseTxOutputs => vec![],
             Operation::EndBuildCoinbaseTxOutputs => vec![Variable::ConstTxOutputs],
             Operation::AddCoinbaseTxOutput => vec![],
@@ -817,9 +1321,15 @@ impl Operation {
             Operation::AddTxidWithWitnessInv => vec![],
             Operation::AddWtxidInv => vec![],
             Operation::AddBlockInv => vec![],
+            Operation::AddBlockHeaderInv => vec![],
             Operation::AddBlockWithWitnessInv => vec![],
             Operation::AddFilteredBlockInv => vec![],
 
+            Operation::BeginBuildGetBlocksLocator => vec![],
+            Operation::EndBuildGetBlocksLocator => vec![Variable::ConstBlockLocator],
+            Operation::AddLocatorHash => vec![],
+            Operation::SetLocatorStopHash => vec![],
+
             Operation::BeginBuildAddrList => vec![],
             Operation::EndBuildAddrList => vec![Variable::ConstAddrList],
             Operation::AddAddr => vec![],
@@ -834,6 +1344,9 @@ impl Operation {
             Operation::TaprootScriptsUseAnnex => vec![Variable::Scripts],
             Operation::TaprootTxoUseAnnex => vec![Variable::Txo],
             Operation::BuildTaprootTree { .. } => vec![Variable::TaprootSpendInfo],
+            Operation::CorruptBlockMerkleRoot(_)
+            | Operation::CorruptBlockProofOfWork
+            | Operation::CorruptBlockCoinbaseValue(_) => vec![Variable::Block],
 
             Operation::BeginBlockTransactions => vec![],
             Operation::AddTx => vec![],
@@ -846,6 +1359,8 @@ impl Operation {
             Operation::SendTxNoWit => vec![],
             Operation::SendGetData => vec![],
             Operation::SendInv => vec![],
+            Operation::SendGetBlocks => vec![],
+            Operation::SendGetHeaders => vec![],
             Operation::SendGetAddr => vec![],
             Operation::SendAddr => vec![],
             Operation::SendAddrV2 => vec![],
@@ -858,8 +1373,22 @@ impl Operation {
             Operation::SendFilterLoad => vec![],
             Operation::SendFilterAdd => vec![],
             Operation::SendFilterClear => vec![],
+            Operation::SendMempoolMsg => vec![],
+            Operation::SendFeeFilter => vec![],
+            Operation::SendPingWithNonce => vec![],
+            Operation::SendPong => vec![],
+            Operation::SendNotFound => vec![],
             Operation::SendCompactBlock => vec![],
+            Operation::SendMerkleBlock => vec![],
+            Operation::SendSendCmpct => vec![],
+            Operation::SendSendHeaders => vec![],
+            Operation::SendSendTxRcncl => vec![],
             Operation::SendBlockTxn => vec![],
+            Operation::SendGetBlockTxn => vec![],
+            Operation::SendRawTransaction => vec![],
+            Operation::SendCFilter { .. }
+            | Operation::SendCFHeaders { .. }
+            | Operation::SendCFCheckpt { .. } => vec![],
             Operation::Probe => vec![],
         }
     }
@@ -887,6 +1416,11 @@ impl Operation {
                 vec![Variable::PrivateKey, Variable::SigHashFlags]
             }
             Operation::BuildPayToTaproot => vec![Variable::TaprootSpendInfo],
+            Operation::BeginBuildMultiSig => vec![Variable::Size, Variable::Size],
+            Operation::AddMultiSigKey => vec![Variable::MutMultiSigBuilder, Variable::PrivateKey],
+            Operation::EndBuildMultiSig
+            | Operation::EndBuildMultiSigScriptHash
+            | Operation::EndBuildMultiSigWitnessScriptHash => vec![Variable::MutMultiSigBuilder],
             Operation::BeginBuildTx => vec![Variable::TxVersion, Variable::LockTime],
             Operation::EndBuildTx => vec![
                 Variable::MutTx,
@@ -909,6 +1443,10 @@ impl Operation {
                 Variable::ConstTxOutputs,
             ],
             Operation::BuildCoinbaseTxInput => vec![Variable::Sequence],
+            Operation::LoadCoinbaseScriptSig => vec![Variable::Bytes],
+            Operation::BuildCoinbaseTxInputWithScriptSig => {
+                vec![Variable::Sequence, Variable::CoinbaseScriptSig]
+            }
             Operation::BeginBuildCoinbaseTxOutputs => vec![Variable::CoinbaseInput],
             Operation::EndBuildCoinbaseTxOutputs => vec![Variable::MutTxOutputs],
             Operation::AddCoinbaseTxOutput => vec![
@@ -923,7 +1461,19 @@ impl Operation {
             Operation::SendTx | Operation::SendTxNoWit => {
                 vec![Variable::Connection, Variable::ConstTx]
             }
+            Operation::SendRawTransaction => vec![Variable::Connection, Variable::RawTransaction],
+            Operation::SendCFilter { .. }
+            | Operation::SendCFHeaders { .. }
+            | Operation::SendCFCheckpt { .. } => vec![
+                Variable::Connection,
+                Variable::Header,
+                Variable::ConstBlockTransactions,
+            ],
             Operation::EndBuildInventory => vec![Variable::MutInventory],
+            Operation::EndBuildGetBlocksLocator => vec![Variable::MutBlockLocator],
+            Operation::AddLocatorHash | Operation::SetLocatorStopHash => {
+                vec![Variable::MutBlockLocator, Variable::Header]
+            }
             Operation::EndBuildAddrList => vec![Variable::MutAddrList],
             Operation::EndBuildAddrListV2 => vec![Variable::MutAddrListV2],
             Operation::AddCompactBlockInv => vec![Variable::MutInventory, Variable::Block],
@@ -935,6 +1485,7 @@ impl Operation {
             | Operation::AddFilteredBlockInv => {
                 vec![Variable::MutInventory, Variable::Block]
             }
+            Operation::AddBlockHeaderInv => vec![Variable::MutInventory, Variable::Header],
             Operation::AddAddr => vec![Variable::MutAddrList, Variable::AddrRecord],
             Operation::AddAddrV2 => vec![Variable::MutAddrListV2, Variable::AddrRecord],
             Operation::BuildBlock => vec![
@@ -949,6 +1500,9 @@ impl Operation {
             Operation::SendGetData | Operation::SendInv => {
                 vec![Variable::Connection, Variable::ConstInventory]
             }
+            Operation::SendGetBlocks | Operation::SendGetHeaders => {
+                vec![Variable::Connection, Variable::ConstBlockLocator]
+            }
             Operation::SendGetAddr => vec![Variable::Connection],
             Operation::SendAddr => vec![Variable::Connection, Variable::ConstAddrList],
             Operation::SendAddrV2 => vec![Variable::Connection, Variable::ConstAddrListV2],
@@ -978,6 +1532,14 @@ impl Operation {
             Operation::BeginBuildBlockTxn => vec![Variable::Block],
             Operation::AddTxToBlockTxn => vec![Variable::MutBlockTxn, Variable::ConstTx],
             Operation::EndBuildBlockTxn => vec![Variable::MutBlockTxn],
+            Operation::BeginBuildGetBlockTxn => vec![Variable::Block],
+            Operation::AddShortIdToReq => {
+                vec![Variable::MutGetBlockTxnReq, Variable::ConstTx]
+            }
+            Operation::EndBuildGetBlockTxn => vec![Variable::MutGetBlockTxnReq],
+            Operation::SendGetBlockTxn => {
+                vec![Variable::Connection, Variable::ConstGetBlockTxnReq]
+            }
 
             Operation::BeginBuildFilterLoad => vec![Variable::ConstFilterLoad],
             Operation::AddTxToFilter => vec![Variable::MutFilterLoad, Variable::ConstTx],
@@ -987,11 +1549,28 @@ impl Operation {
             Operation::BuildFilterAddFromTxo => vec![Variable::Txo],
 
             Operation::BuildCompactBlock => vec![Variable::Block, Variable::Nonce],
+            Operation::BuildMerkleBlock => vec![Variable::Block, Variable::ConstFilterLoad],
+            Operation::RandomizeNonce => vec![Variable::Nonce],
+            Operation::CorruptBlockMerkleRoot(_)
+            | Operation::CorruptBlockProofOfWork
+            | Operation::CorruptBlockCoinbaseValue(_) => vec![Variable::Block],
 
             Operation::SendFilterLoad => vec![Variable::Connection, Variable::ConstFilterLoad],
             Operation::SendFilterAdd => vec![Variable::Connection, Variable::FilterAdd],
             Operation::SendFilterClear => vec![Variable::Connection],
+            Operation::SendMempoolMsg => vec![Variable::Connection],
+            Operation::SendFeeFilter => vec![Variable::Connection, Variable::ConstAmount],
+            Operation::SendPingWithNonce | Operation::SendPong => {
+                vec![Variable::Connection, Variable::Nonce]
+            }
+            Operation::SendNotFound => vec![Variable::Connection, Variable::ConstInventory],
             Operation::SendCompactBlock => vec![Variable::Connection, Variable::CompactBlock],
+            Operation::SendMerkleBlock => vec![Variable::Connection, Variable::MerkleBlock],
+            Operation::SendSendCmpct => vec![Variable::Connection, Variable::Bytes, Variable::Size],
+            Operation::SendSendHeaders => vec![Variable::Connection],
+            Operation::SendSendTxRcncl => {
+                vec![Variable::Connection, Variable::Size, Variable::Nonce]
+            }
             Operation::TaprootScriptsUseAnnex => {
                 vec![Variable::Scripts, Variable::TaprootAnnex]
             }
@@ -999,7 +1578,9 @@ impl Operation {
             // Operations with no inputs
             Operation::Nop { .. }
             | Operation::LoadBytes(_)
+            | Operation::LoadRawBytes(_)
             | Operation::LoadMsgType(_)
+            | Operation::LoadMsgTypeFromStr(_)
             | Operation::LoadNode(_)
             | Operation::LoadConnection(_)
             | Operation::LoadConnectionType(_)
@@ -1022,9 +1603,13 @@ impl Operation {
             | Operation::LoadSigHashFlags(..)
             | Operation::LoadFilterLoad { .. }
             | Operation::LoadFilterAdd { .. }
+            | Operation::LoadCFilter { .. }
+            | Operation::LoadRawTransaction(_)
             | Operation::LoadNonce(..)
+            | Operation::LoadNonce64(..)
             | Operation::BeginBuildTxInputs
             | Operation::BeginBuildInventory
+            | Operation::BeginBuildGetBlocksLocator
             | Operation::BeginBuildAddrList
             | Operation::BeginBuildAddrListV2
             | Operation::BeginBlockTransactions
@@ -1041,6 +1626,8 @@ impl Operation {
             Operation::BeginBuildTxOutputs => vec![Variable::MutTxOutputs],
             Operation::BeginWitnessStack => vec![Variable::MutWitnessStack],
             Operation::BeginBuildInventory => vec![Variable::MutInventory],
+            Operation::BeginBuildGetBlocksLocator => vec![Variable::MutBlockLocator],
+            Operation::BeginBuildMultiSig => vec![Variable::MutMultiSigBuilder],
             Operation::BeginBuildAddrList => vec![Variable::MutAddrList],
             Operation::BeginBuildAddrListV2 => vec![Variable::MutAddrListV2],
             Operation::BeginBlockTransactions => vec![Variable::MutBlockTransactions],
@@ -1048,6 +1635,7 @@ impl Operation {
             Operation::BeginBuildCoinbaseTx => vec![Variable::MutTx],
             Operation::BeginBuildCoinbaseTxOutputs => vec![Variable::MutTxOutputs],
             Operation::BeginBuildBlockTxn => vec![Variable::MutBlockTxn],
+            Operation::BeginBuildGetBlockTxn => vec![Variable::MutGetBlockTxnReq],
             Operation::Nop {
                 outputs: _,
                 inner_outputs,
@@ -1072,6 +1660,10 @@ impl Operation {
             | Operation::BuildOpReturnScripts
             | Operation::BuildPayToAnchor
             | Operation::BuildPayToTaproot
+            | Operation::AddMultiSigKey
+            | Operation::EndBuildMultiSig
+            | Operation::EndBuildMultiSigScriptHash
+            | Operation::EndBuildMultiSigWitnessScriptHash
             | Operation::BuildPayToPubKey
             | Operation::BuildPayToPubKeyHash
             | Operation::BuildPayToWitnessPubKeyHash
@@ -1083,6 +1675,9 @@ impl Operation {
             | Operation::LoadTxo { .. }
             | Operation::LoadTaprootAnnex { .. }
             | Operation::BuildTaprootTree { .. }
+            | Operation::CorruptBlockMerkleRoot(_)
+            | Operation::CorruptBlockProofOfWork
+            | Operation::CorruptBlockCoinbaseValue(_)
             | Operation::LoadHeader { .. }
             | Operation::LoadAmount(..)
             | Operation::LoadTxVersion(..)
@@ -1094,8 +1689,12 @@ impl Operation {
             | Operation::LoadSigHashFlags(..)
             | Operation::LoadFilterLoad { .. }
             | Operation::LoadFilterAdd { .. }
+            | Operation::LoadCFilter { .. }
             | Operation::LoadNonce(..)
+            | Operation::LoadNonce64(..)
+            | Operation::RandomizeNonce
             | Operation::BuildCompactBlock
+            | Operation::BuildMerkleBlock
             | Operation::TaprootScriptsUseAnnex
             | Operation::TaprootTxoUseAnnex
             | Operation::EndBuildTx
@@ -1114,9 +1713,13 @@ impl Operation {
             | Operation::AddTxidInv
             | Operation::AddTxidWithWitnessInv
             | Operation::AddWtxidInv
+            | Operation::EndBuildGetBlocksLocator
+            | Operation::AddLocatorHash
+            | Operation::SetLocatorStopHash
             | Operation::AddAddr
             | Operation::AddAddrV2
             | Operation::AddBlockInv
+            | Operation::AddBlockHeaderInv
             | Operation::AddBlockWithWitnessInv
             | Operation::AddFilteredBlockInv
             | Operation::BuildBlock
@@ -1124,6 +1727,8 @@ impl Operation {
             | Operation::EndBlockTransactions
             | Operation::SendGetData
             | Operation::SendInv
+            | Operation::SendGetBlocks
+            | Operation::SendGetHeaders
             | Operation::SendGetAddr
             | Operation::SendAddr
             | Operation::SendAddrV2
@@ -1138,14 +1743,35 @@ impl Operation {
             | Operation::SendFilterLoad
             | Operation::SendFilterAdd
             | Operation::SendFilterClear
+            | Operation::SendMempoolMsg
+            | Operation::SendFeeFilter
+            | Operation::SendPingWithNonce
+            | Operation::SendPong
+            | Operation::SendNotFound
             | Operation::SendCompactBlock
+            | Operation::SendMerkleBlock
+            | Operation::SendSendCmpct
+            | Operation::SendSendHeaders
+            | Operation::SendSendTxRcncl
             | Operation::EndBuildCoinbaseTx
             | Operation::BuildCoinbaseTxInput
+            | Operation::LoadCoinbaseScriptSig
+            | Operation::BuildCoinbaseTxInputWithScriptSig
             | Operation::EndBuildCoinbaseTxOutputs
             | Operation::AddCoinbaseTxOutput
             | Operation::EndBuildBlockTxn
             | Operation::AddTxToBlockTxn
             | Operation::SendBlockTxn
+            | Operation::AddShortIdToReq
+            | Operation::EndBuildGetBlockTxn
+            | Operation::SendGetBlockTxn
+            | Operation::LoadRawTransaction(_)
+            | Operation::SendRawTransaction
+            | Operation::SendCFilter { .. }
+            | Operation::SendCFHeaders { .. }
+            | Operation::SendCFCheckpt { .. }
+            | Operation::LoadMsgTypeFromStr(_)
+            | Operation::LoadRawBytes(_)
             | Operation::Probe => vec![],
         }
     }
@@ -0,0 +1,755 @@
+//! Parser for the textual program format produced by `Program`'s `Display` implementation, e.g.:
+//!
+//! ```text
+//! // Context: nodes=1 connections=2 timestamp=1296688602
+//! v0 <- LoadConnection(0)
+//! v1 <- LoadTxVersion(2)
+//! v2 <- LoadLockTime(0)
+//! v3 <- BeginBuildTx(v1, v2)
+//!   ...
+//! ```
+//!
+//! Indentation is ignored (it is implied by the block structure), as are the type annotations of
+//! the annotated format (`v3:MutTx`). Variables are renamed in order of definition, so lines can
+//! be removed from a printed program as long as the remaining ones stay valid.
+//!
+//! `Display` elides some operation fields, these are filled with zeros when parsing:
+//! `LoadFilterLoad` and `LoadRawBytes` (only the payload length is printed), `LoadAddr`/
+//! `LoadAddrV2` (no address), `BuildTaprootTree` (only the merkle path length is printed) and
+//! `LoadTxo` (the witness stack is printed as one concatenated element).
+
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use serde::{
+    Deserialize,
+    de::{
+        IntoDeserializer,
+        value::{Error as DeError, StrDeserializer},
+    },
+};
+
+use crate::{
+    AddrNetwork, AddrRecord, Instruction, MAX_PROTOCOL_MESSAGE_LENGTH, Operation, Program,
+    ProgramBuilder, ProgramContext, TaprootLeafSpec, errors::ParseError,
+};
+
+/// Instruction as printed, i.e. with the variable names used in the text
+struct ParsedInstruction {
+    outputs: Vec<usize>,
+    operation: Operation,
+    inputs: Vec<usize>,
+    inner_outputs: Vec<usize>,
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at '{}'", token, self.rest))
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let end = self
+            .rest
+            .find(|c: char| !predicate(c))
+            .unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        taken
+    }
+
+    fn ident(&mut self) -> &'a str {
+        self.take_while(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, String> {
+        let digits = self.take_while(|c| c.is_ascii_digit() || c == '-');
+        digits
+            .parse()
+            .map_err(|_| format!("invalid number '{}'", digits))
+    }
+
+    fn hex(&mut self) -> Result<Vec<u8>, String> {
+        let digits = self.take_while(|c| c.is_ascii_hexdigit());
+        if !digits.len().is_multiple_of(2) {
+            return Err(format!("odd number of hex digits in '{}'", digits));
+        }
+        Ok((0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect())
+    }
+
+    fn hex_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let bytes = self.hex()?;
+        bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| format!("expected {} bytes, got {}", N, bytes.len()))
+    }
+
+    fn quoted_hex(&mut self) -> Result<Vec<u8>, String> {
+        self.expect("\"")?;
+        let bytes = self.hex()?;
+        self.expect("\"")?;
+        Ok(bytes)
+    }
+
+    /// Unescaped string, which may contain quotes itself. Only used for operations without any
+    /// inputs or inner outputs, so the string ends at the last `")` on the line.
+    fn raw_string(&mut self) -> Result<&'a str, String> {
+        self.expect("\"")?;
+        let end = self
+            .rest
+            .rfind("\")")
+            .ok_or_else(|| "unterminated string".to_string())?;
+        let string = &self.rest[..end];
+        self.rest = &self.rest[end + 1..];
+        Ok(string)
+    }
+
+    /// String escaped with `str::escape_default`
+    fn escaped_string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut string = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(string);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('n') => string.push('\n'),
+                    Some('u') => {
+                        let start = i + 3;
+                        let end = self.rest[start..]
+                            .find('}')
+                            .map(|end| start + end)
+                            .ok_or_else(|| "unterminated unicode escape".to_string())?;
+                        let c = u32::from_str_radix(&self.rest[start..end], 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| "invalid unicode escape".to_string())?;
+                        string.push(c);
+                        while chars.next().is_some_and(|(i, _)| i < end) {}
+                    }
+                    Some(c) => string.push(c),
+                    None => break,
+                },
+                c => string.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+
+    /// Variable name (`v3`), optionally annotated with its type (`v3:MutTx`)
+    fn variable(&mut self) -> Result<usize, String> {
+        self.expect("v")?;
+        let index = self.number()?;
+        if self.eat(":") {
+            self.take_while(|c| c.is_ascii_alphanumeric() || c == '?');
+        }
+        Ok(index)
+    }
+
+    fn variables(&mut self) -> Result<Vec<usize>, String> {
+        let mut variables = vec![self.variable()?];
+        while self.eat(", ") {
+            variables.push(self.variable()?);
+        }
+        Ok(variables)
+    }
+
+    /// Parse `(<args>)` using `args`
+    fn args<T>(&mut self, args: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        self.expect("(")?;
+        let value = args(self)?;
+        self.expect(")")?;
+        Ok(value)
+    }
+}
+
+fn parse_network(network: &str) -> Result<AddrNetwork, String> {
+    Ok(match network {
+        "ipv4" => AddrNetwork::IPv4,
+        "ipv6" => AddrNetwork::IPv6,
+        "torv2" => AddrNetwork::TorV2,
+        "torv3" => AddrNetwork::TorV3,
+        "i2p" => AddrNetwork::I2p,
+        "cjdns" => AddrNetwork::Cjdns,
+        "yggdrasil" => AddrNetwork::Yggdrasil,
+        other => {
+            let id = other
+                .strip_prefix("unknown(0x")
+                .and_then(|id| id.strip_suffix(')'))
+                .and_then(|id| u8::from_str_radix(id, 16).ok())
+                .ok_or_else(|| format!("unknown network '{}'", other))?;
+            AddrNetwork::Unknown(id)
+        }
+    })
+}
+
+/// Parse the operation `name` and its parameters (inverse of `Operation`'s `Display`). `Nop`s are
+/// returned without outputs, as their number is only known once the whole line is parsed.
+fn parse_operation(name: &str, c: &mut Cursor) -> Result<Operation, String> {
+    Ok(match name {
+        "Nop" => Operation::Nop {
+            outputs: 0,
+            inner_outputs: 0,
+        },
+        "LoadBytes" => Operation::LoadBytes(c.args(Cursor::quoted_hex)?),
+        "LoadMsgType" => {
+            let msg_type = c.args(Cursor::raw_string)?;
+            let mut chars = ['\0'; 12];
+            if msg_type.chars().count() > chars.len() {
+                return Err(format!("message type '{}' is too long", msg_type));
+            }
+            for (slot, c) in chars.iter_mut().zip(msg_type.chars()) {
+                *slot = c;
+            }
+            Operation::LoadMsgType(chars)
+        }
+        "LoadNode" => Operation::LoadNode(c.args(Cursor::number)?),
+        "LoadConnection" => Operation::LoadConnection(c.args(Cursor::number)?),
+        "LoadConnectionType" => {
+            Operation::LoadConnectionType(c.args(Cursor::raw_string)?.to_string())
+        }
+        "LoadDuration" => Operation::LoadDuration(Duration::from_secs(c.args(Cursor::number)?)),
+        "LoadAddr" => c.args(|c| {
+            let time = c.number()?;
+            c.expect(", ")?;
+            let services = c.number()?;
+            c.expect(", ")?;
+            let port = c.number()?;
+            Ok(Operation::LoadAddr(AddrRecord::V1 {
+                time,
+                services,
+                ip: [0u8; 16],
+                port,
+            }))
+        })?,
+        "LoadAddrV2" => c.args(|c| {
+            let time = c.number()?;
+            c.expect(", ")?;
+            let services = c.number()?;
+            c.expect(", ")?;
+            let network = parse_network(c.take_while(|c| c != ','))?;
+            c.expect(", ")?;
+            let port = c.number()?;
+            let payload = vec![0u8; network.expected_payload_len().unwrap_or_default()];
+            Ok(Operation::LoadAddr(AddrRecord::V2 {
+                time,
+                services,
+                network,
+                payload,
+                port,
+            }))
+        })?,
+        "LoadBlockHeight" => Operation::LoadBlockHeight(c.args(Cursor::number)?),
+        "LoadCompactFilterType" => Operation::LoadCompactFilterType(c.args(Cursor::number)?),
+        "LoadTime" => Operation::LoadTime(c.args(Cursor::number)?),
+        "LoadTxo" => c.args(|c| {
+            let txid = c.hex_array()?;
+            c.expect(":")?;
+            let vout = c.number()?;
+            c.expect(", ")?;
+            let value = c.number()?;
+            c.expect(", ")?;
+            let script_pubkey = c.hex()?;
+            c.expect(", ")?;
+            let spending_script_sig = c.hex()?;
+            c.expect(", ")?;
+            let witness = c.hex()?;
+            Ok(Operation::LoadTxo {
+                outpoint: (txid, vout),
+                value,
+                script_pubkey,
+                spending_script_sig,
+                spending_witness: if witness.is_empty() {
+                    vec![]
+                } else {
+                    vec![witness]
+                },
+            })
+        })?,
+        "LoadTaprootAnnex" => Operation::LoadTaprootAnnex {
+            annex: c.args(Cursor::hex)?,
+        },
+        "LoadHeader" => c.args(|c| {
+            let prev = c.hex_array()?;
+            c.expect(", ")?;
+            let merkle_root = c.hex_array()?;
+            c.expect(", ")?;
+            let nonce = c.number()?;
+            c.expect(", ")?;
+            let bits = c.number()?;
+            c.expect(", ")?;
+            let time = c.number()?;
+            c.expect(", ")?;
+            let version = c.number()?;
+            c.expect(", ")?;
+            let height = c.number()?;
+            Ok(Operation::LoadHeader {
+                prev,
+                merkle_root,
+                nonce,
+                bits,
+                time,
+                version,
+                height,
+            })
+        })?,
+        "LoadAmount" => Operation::LoadAmount(c.args(Cursor::number)?),
+        "LoadTxVersion" => Operation::LoadTxVersion(c.args(Cursor::number)?),
+        "LoadBlockVersion" => Operation::LoadBlockVersion(c.args(Cursor::number)?),
+        "LoadLockTime" => Operation::LoadLockTime(c.args(Cursor::number)?),
+        "LoadSequence" => Operation::LoadSequence(c.args(Cursor::number)?),
+        "LoadSize" => Operation::LoadSize(c.args(Cursor::number)?),
+        "LoadPrivateKey" => Operation::LoadPrivateKey(c.args(Cursor::hex_array)?),
+        "LoadSigHashFlags" => Operation::LoadSigHashFlags(c.args(Cursor::number)?),
+        "LoadFilterLoad" => c.args(|c| {
+            c.expect("(payload)(len: ")?;
+            let len = c.number()?;
+            c.expect("), ")?;
+            let hash_funcs = c.number()?;
+            c.expect(", ")?;
+            let tweak = c.number()?;
+            c.expect(", ")?;
+            let flags = c.number()?;
+            Ok(Operation::LoadFilterLoad {
+                filter: vec![0u8; len],
+                hash_funcs,
+                tweak,
+                flags,
+            })
+        })?,
+        "LoadFilterAdd" => Operation::LoadFilterAdd {
+            data: c.args(Cursor::hex)?,
+        },
+        "LoadCFilter" => c.args(|c| {
+            let filter_type = c.number()?;
+            c.expect(", ")?;
+            let block_hash = c.hex_array()?;
+            c.expect(", ")?;
+            let filter = c.hex()?;
+            Ok(Operation::LoadCFilter {
+                filter_type,
+                block_hash,
+                filter,
+            })
+        })?,
+        "LoadNonce" => Operation::LoadNonce(c.args(Cursor::number)?),
+        "LoadNonce64" => Operation::LoadNonce64(c.args(Cursor::number)?),
+        "LoadRawTransaction" => Operation::LoadRawTransaction(c.args(Cursor::quoted_hex)?),
+        "LoadRawBytes" => c.args(|c| {
+            c.expect("(payload)(len: ")?;
+            let len: usize = c.number()?;
+            c.expect(")")?;
+            let len = len.min(MAX_PROTOCOL_MESSAGE_LENGTH);
+            Ok(Operation::LoadRawBytes(vec![0u8; len]))
+        })?,
+        "SendCFilter" => Operation::SendCFilter {
+            filter_type: c.args(Cursor::number)?,
+        },
+        "SendCFHeaders" | "SendCFCheckpt" => {
+            let (filter_type, prev_filter_header) = c.args(|c| {
+                let filter_type = c.number()?;
+                c.expect(", ")?;
+                Ok((filter_type, c.hex_array()?))
+            })?;
+            if name == "SendCFHeaders" {
+                Operation::SendCFHeaders {
+                    filter_type,
+                    prev_filter_header,
+                }
+            } else {
+                Operation::SendCFCheckpt {
+                    filter_type,
+                    prev_filter_header,
+                }
+            }
+        }
+        "LoadMsgTypeFromStr" => Operation::LoadMsgTypeFromStr(c.args(Cursor::escaped_string)?),
+        "BuildTaprootTree" => c.args(|c| {
+            c.expect("key=")?;
+            let secret_key = c.hex_array()?;
+            let script_leaf = if c.eat(", leaf={script=") {
+                let script = c.hex()?;
+                c.expect(", ver=0x")?;
+                let version = u8::from_str_radix(c.take_while(|c| c.is_ascii_hexdigit()), 16)
+                    .map_err(|e| format!("invalid leaf version: {}", e))?;
+                c.expect(", path=")?;
+                let path_len = c.number()?;
+                c.expect("}")?;
+                Some(TaprootLeafSpec {
+                    script,
+                    version,
+                    merkle_path: vec![[0u8; 32]; path_len],
+                })
+            } else {
+                None
+            };
+            Ok(Operation::BuildTaprootTree {
+                secret_key,
+                script_leaf,
+            })
+        })?,
+        "CorruptBlockMerkleRoot" => Operation::CorruptBlockMerkleRoot(c.args(Cursor::hex_array)?),
+        "CorruptBlockCoinbaseValue" => {
+            Operation::CorruptBlockCoinbaseValue(c.args(Cursor::number)?)
+        }
+        // All remaining operations are unit variants, printed as their variant name
+        name => {
+            let deserializer: StrDeserializer<DeError> = name.into_deserializer();
+            Operation::deserialize(deserializer)
+                .map_err(|_| format!("unknown operation '{}'", name))?
+        }
+    })
+}
+
+/// Parse a single (trimmed) instruction line, e.g. `v3 <- BeginBuildTx(v1, v2)`
+fn parse_instruction(line: &str) -> Result<ParsedInstruction, String> {
+    let mut c = Cursor { rest: line };
+
+    let outputs = if c.rest.starts_with('v') {
+        let outputs = c.variables()?;
+        c.expect(" <- ")?;
+        outputs
+    } else {
+        vec![]
+    };
+
+    let name = c.ident();
+    let mut operation = parse_operation(name, &mut c)?;
+
+    let inputs = if c.eat("(") {
+        let inputs = c.variables()?;
+        c.expect(")")?;
+        inputs
+    } else {
+        vec![]
+    };
+    let inner_outputs = if c.eat(" -> ") {
+        c.variables()?
+    } else {
+        vec![]
+    };
+    if !c.rest.is_empty() {
+        return Err(format!("unexpected '{}'", c.rest));
+    }
+
+    if let Operation::Nop {
+        outputs: nop_outputs,
+        inner_outputs: nop_inner_outputs,
+    } = &mut operation
+    {
+        *nop_outputs = outputs.len();
+        *nop_inner_outputs = inner_outputs.len();
+    }
+
+    Ok(ParsedInstruction {
+        outputs,
+        operation,
+        inputs,
+        inner_outputs,
+    })
+}
+
+/// Parse the context comment (without the leading `// Context:`), e.g.
+/// `nodes=1 connections=2 timestamp=1296688602`
+fn parse_context(context: &str) -> Result<ProgramContext, String> {
+    let mut fields = HashMap::new();
+    for field in context.split_whitespace() {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| format!("invalid context field '{}'", field))?;
+        fields.insert(key, value);
+    }
+    let field = |key: &str| {
+        fields
+            .get(key)
+            .ok_or_else(|| format!("context is missing '{}'", key))?
+            .parse::<u64>()
+            .map_err(|_| format!("invalid context value for '{}'", key))
+    };
+
+    Ok(ProgramContext {
+        num_nodes: field("nodes")? as usize,
+        num_connections: field("connections")? as usize,
+        timestamp: field("timestamp")?,
+    })
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    /// Parse a program from the textual format produced by `Display` (see the module
+    /// documentation). The program is validated while parsing.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut builder: Option<ProgramBuilder> = None;
+        // Variable names in the text to variable indices in the parsed program
+        let mut variables = HashMap::new();
+
+        for (index, line) in s.lines().enumerate() {
+            let error = |message: String| ParseError {
+                line: index + 1,
+                message,
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(comment) = line.strip_prefix("//") {
+                if let Some(context) = comment.trim().strip_prefix("Context:") {
+                    if builder.is_some() {
+                        return Err(error("duplicate context".to_string()));
+                    }
+                    builder = Some(ProgramBuilder::new(parse_context(context).map_err(error)?));
+                }
+                continue;
+            }
+
+            let Some(builder) = builder.as_mut() else {
+                return Err(error(
+                    "expected '// Context: ...' before the first instruction".to_string(),
+                ));
+            };
+
+            let instruction = parse_instruction(line).map_err(error)?;
+            let operation = instruction.operation;
+            if operation.num_outputs() != instruction.outputs.len()
+                || operation.num_inner_outputs() != instruction.inner_outputs.len()
+            {
+                return Err(error(format!(
+                    "{} has {} outputs and {} inner outputs",
+                    operation.type_name(),
+                    operation.num_outputs(),
+                    operation.num_inner_outputs()
+                )));
+            }
+            let inputs = instruction
+                .inputs
+                .iter()
+                .map(|input| {
+                    variables
+                        .get(input)
+                        .copied()
+                        .ok_or_else(|| error(format!("v{} is not defined", input)))
+                })
+                .collect::<Result<Vec<usize>, ParseError>>()?;
+
+            let defined = builder
+                .append(Instruction { inputs, operation })
+                .map_err(|e| error(format!("invalid instruction: {:?}", e)))?;
+            for (name, variable) in instruction
+                .outputs
+                .into_iter()
+                .chain(instruction.inner_outputs)
+                .zip(defined)
+            {
+                if variables.insert(name, variable.index).is_some() {
+                    return Err(error(format!("v{} is defined twice", name)));
+                }
+            }
+        }
+
+        let line = s.lines().count();
+        builder
+            .ok_or_else(|| ParseError {
+                line,
+                message: "missing '// Context: ...'".to_string(),
+            })?
+            .finalize()
+            .map_err(|e| ParseError {
+                line,
+                message: format!("invalid program: {:?}", e),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Variable;
+
+    fn context() -> ProgramContext {
+        ProgramContext {
+            num_nodes: 1,
+            num_connections: 2,
+            timestamp: 1296688602,
+        }
+    }
+
+    fn roundtrip(program: &Program) {
+        let text = program.to_string();
+        let parsed: Program = text.parse().unwrap();
+        assert_eq!(parsed.to_string(), text);
+        let annotated: Program = program.to_annotated_string().parse().unwrap();
+        assert_eq!(annotated.to_string(), text);
+    }
+
+    #[test]
+    fn parse_blocks() {
+        let mut builder = ProgramBuilder::new(context());
+        let connection = builder.force_append_expect_output(vec![], Operation::LoadConnection(1));
+        let version = builder.force_append_expect_output(vec![], Operation::LoadTxVersion(2));
+        let lock_time = builder.force_append_expect_output(vec![], Operation::LoadLockTime(0));
+        let mut_tx = builder.force_append_expect_output(
+            vec![version.index, lock_time.index],
+            Operation::BeginBuildTx,
+        );
+        let mut_inputs = builder.force_append_expect_output(vec![], Operation::BeginBuildTxInputs);
+        let inputs =
+            builder.force_append_expect_output(vec![mut_inputs.index], Operation::EndBuildTxInputs);
+        let mut_outputs =
+            builder.force_append_expect_output(vec![inputs.index], Operation::BeginBuildTxOutputs);
+        let outputs = builder
+            .force_append_expect_output(vec![mut_outputs.index], Operation::EndBuildTxOutputs);
+        let tx = builder.force_append_expect_output(
+            vec![mut_tx.index, inputs.index, outputs.index],
+            Operation::EndBuildTx,
+        );
+        builder.force_append(vec![connection.index, tx.index], Operation::SendTx);
+        builder.force_append(
+            vec![],
+            Operation::Nop {
+                outputs: 1,
+                inner_outputs: 2,
+            },
+        );
+        builder.force_append(
+            vec![],
+            Operation::LoadMsgTypeFromStr("a \"b\"\n\u{7f}".to_string()),
+        );
+        builder.force_append(
+            vec![],
+            Operation::LoadMsgType([
+                'p', 'i', 'n', 'g', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+            ]),
+        );
+        builder.force_append(
+            vec![],
+            Operation::LoadConnectionType("outbound".to_string()),
+        );
+        let program = builder.finalize().unwrap();
+
+        roundtrip(&program);
+        let parsed: Program = program.to_string().parse().unwrap();
+        assert_eq!(parsed.instructions, program.instructions);
+    }
+
+    #[test]
+    fn parse_operation_parameters() {
+        let mut builder = ProgramBuilder::new(context());
+        for operation in [
+            Operation::LoadBytes(vec![0xde, 0xad]),
+            Operation::LoadDuration(Duration::from_secs(600)),
+            Operation::LoadAddr(AddrRecord::V2 {
+                time: 1,
+                services: 9,
+                network: AddrNetwork::Unknown(0x42),
+                payload: vec![],
+                port: 8333,
+            }),
+            Operation::LoadTxo {
+                outpoint: ([7u8; 32], 3),
+                value: 5000,
+                script_pubkey: vec![0x51],
+                spending_script_sig: vec![],
+                spending_witness: vec![vec![1, 2]],
+            },
+            Operation::LoadHeader {
+                prev: [1u8; 32],
+                merkle_root: [2u8; 32],
+                nonce: 3,
+                bits: 4,
+                time: 5,
+                version: -6,
+                height: 7,
+            },
+            Operation::LoadFilterLoad {
+                filter: vec![0u8; 3],
+                hash_funcs: 1,
+                tweak: 2,
+                flags: 3,
+            },
+            Operation::LoadCFilter {
+                filter_type: 0,
+                block_hash: [3u8; 32],
+                filter: vec![4, 5],
+            },
+            Operation::LoadRawTransaction(vec![]),
+            Operation::LoadRawBytes(vec![0u8; 5]),
+            Operation::LoadPrivateKey([9u8; 32]),
+        ] {
+            builder.force_append(vec![], operation);
+        }
+        let program = builder.finalize().unwrap();
+
+        roundtrip(&program);
+        let parsed: Program = program.to_string().parse().unwrap();
+        assert_eq!(parsed.instructions, program.instructions);
+    }
+
+    #[test]
+    fn variables_are_renamed() {
+        let text = "// Context: nodes=1 connections=2 timestamp=0\n\
+                    v7 <- LoadTime(100)\n\
+                    v3:Duration <- LoadDuration(5)\n\
+                    \n\
+                    // advance the time\n\
+                    v9 <- AdvanceTime(v7, v3)\n    \
+                    SetTime(v9)\n";
+        let program: Program = text.parse().unwrap();
+        assert_eq!(program.instructions[2].inputs, vec![0, 1]);
+        assert_eq!(program.instructions[3].inputs, vec![2]);
+        assert_eq!(
+            program.instructions[1].operation.get_output_variables(),
+            vec![Variable::Duration]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text: &str| text.parse::<Program>().unwrap_err();
+
+        assert_eq!(error("v0 <- LoadTime(1)").line, 1);
+        assert_eq!(error("// Context: nodes=1 connections=0").line, 1);
+
+        let undefined = error("// Context: nodes=1 connections=0 timestamp=0\nSetTime(v0)");
+        assert_eq!(undefined.line, 2);
+        assert_eq!(undefined.message, "v0 is not defined");
+
+        assert_eq!(
+            error("// Context: nodes=1 connections=0 timestamp=0\nv0 <- Frobnicate").message,
+            "unknown operation 'Frobnicate'"
+        );
+        assert_eq!(
+            error("// Context: nodes=1 connections=0 timestamp=0\nLoadTime(1)").line,
+            2
+        );
+        assert_eq!(
+            error("// Context: nodes=1 connections=0 timestamp=0\nv0 <- BeginBuildInventory").line,
+            2
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Versioned (de)serialization of programs.
+//!
+//! Adding, removing or reordering `Operation` variants (or changing their fields) changes the
+//! postcard encoding of programs, making previously written programs undecodable. Programs
+//! encoded with `Program::to_bytes_versioned` start with `SCHEMA_MAGIC` followed by the
+//! `Program::SCHEMA_VERSION` they were written with, so that such incompatibilities are detected
+//! and older encodings can be migrated (see `LegacyProgram`).
+
+pub mod v0;
+
+use std::fmt;
+
+use crate::Program;
+
+/// Prefix of schema versioned programs, followed by the schema version.
+///
+/// Unversioned (version 0) programs start with their instruction count, which could otherwise be
+/// mistaken for a schema version.
+pub const SCHEMA_MAGIC: [u8; 4] = *b"FZIR";
+
+/// Error returned when a program can't be decoded with the current or any legacy schema
+#[derive(Debug, Clone)]
+pub struct SchemaError {
+    /// Schema version read from the encoded program (0 for unversioned encodings)
+    pub version_found: u32,
+    pub version_expected: u32,
+    /// Error returned by the decoder of the schema the program was written with
+    pub inner: postcard::Error,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to decode program with schema version {} (expected {}): {}",
+            self.version_found, self.version_expected, self.inner
+        )
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Program encodings of previous schema versions.
+///
+/// When bumping `Program::SCHEMA_VERSION`, the previous schema's types should be frozen into a new
+/// module (like `v0`) and added as a variant here, together with their migration to the current
+/// `Program`.
+#[derive(Debug)]
+pub enum LegacyProgram {
+    /// Version 0: postcard encoded `Program` without a schema prefix, as written by the fuzzer and
+    /// all tools predating schema versioning
+    Unversioned(v0::Program),
+}
+
+impl LegacyProgram {
+    /// Decode `bytes` (without the schema prefix) written with the legacy schema `version`
+    pub fn from_bytes(version: u32, bytes: &[u8]) -> Result<Self, postcard::Error> {
+        match version {
+            0 => decode_exact(bytes).map(LegacyProgram::Unversioned),
+            _ => Err(postcard::Error::DeserializeBadEncoding),
+        }
+    }
+
+    /// Migrate the legacy program to the current schema
+    pub fn migrate(self) -> Program {
+        match self {
+            LegacyProgram::Unversioned(program) => program.into(),
+        }
+    }
+}
+
+/// Decode a `T` from `bytes`, rejecting any trailing bytes
+fn decode_exact<'a, T: serde::Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, postcard::Error> {
+    let (value, rest) = postcard::take_from_bytes(bytes)?;
+    if !rest.is_empty() {
+        return Err(postcard::Error::DeserializeBadEncoding);
+    }
+    Ok(value)
+}
+
+/// Split an encoded program into its schema version and the encoded program itself. Programs
+/// without `SCHEMA_MAGIC` are unversioned (version 0).
+fn split_version(bytes: &[u8]) -> Result<(u32, &[u8]), SchemaError> {
+    let Some(versioned) = bytes.strip_prefix(&SCHEMA_MAGIC) else {
+        return Ok((0, bytes));
+    };
+    postcard::take_from_bytes::<u32>(versioned).map_err(|inner| SchemaError {
+        version_found: 0,
+        version_expected: Program::SCHEMA_VERSION,
+        inner,
+    })
+}
+
+impl Program {
+    /// Version of the program encoding, to be bumped with every breaking change to the encoding
+    /// of `Program` (e.g. when adding `Operation` variants anywhere but at the end)
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// Encode the program with postcard, prefixed by `SCHEMA_MAGIC` and `Program::SCHEMA_VERSION`
+    pub fn to_bytes_versioned(&self) -> Vec<u8> {
+        postcard::to_extend(&(Self::SCHEMA_VERSION, self), SCHEMA_MAGIC.to_vec())
+            .expect("serialization should never fail")
+    }
+
+    /// Decode a program written by `Program::to_bytes_versioned` with the current schema version
+    pub fn from_bytes_current(bytes: &[u8]) -> Result<Program, SchemaError> {
+        let (version, program) = split_version(bytes)?;
+        let inner = if version == Self::SCHEMA_VERSION {
+            match decode_exact(program) {
+                Ok(program) => return Ok(program),
+                Err(inner) => inner,
+            }
+        } else {
+            postcard::Error::DeserializeBadEncoding
+        };
+
+        Err(SchemaError {
+            version_found: version,
+            version_expected: Self::SCHEMA_VERSION,
+            inner,
+        })
+    }
+
+    /// Decode a program written by `Program::to_bytes_versioned` with the current or any legacy
+    /// schema version (including unversioned programs). Programs written with a legacy schema
+    /// are migrated to the current one.
+    pub fn from_bytes_versioned(bytes: &[u8]) -> Result<Program, SchemaError> {
+        let (version, program) = split_version(bytes)?;
+        if version == Self::SCHEMA_VERSION {
+            return Self::from_bytes_current(bytes);
+        }
+
+        LegacyProgram::from_bytes(version, program)
+            .map(LegacyProgram::migrate)
+            .map_err(|inner| SchemaError {
+                version_found: version,
+                version_expected: Self::SCHEMA_VERSION,
+                inner,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddrNetwork, AddrRecord, Operation, TaprootLeafSpec, test_utils};
+
+    /// `LoadConnection(0)`, `LoadTime(7)` with a single node and connection
+    const PROGRAM: [u8; 10] = [2, 0, 4, 0, 0, 8, 7, 1, 1, 0];
+
+    /// Unversioned program written before schema versioning, using `LoadTxo` (discriminant 20) and
+    /// `BuildTaprootTree` (discriminant 106, the last version 0 operation)
+    const PROGRAM_V0: [u8; 100] = [
+        4, // Instructions
+        0, 20, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 2, 172, 2, 1, 81, 0, 1, 1, 170, // LoadTxo
+        0, 7, 1, 1, 9, 3, 2, 187, 187, 141, 65, // LoadAddr
+        0, 106, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+        3, 3, 3, 3, 1, 1, 81, 192, 0, // BuildTaprootTree
+        0, 8, 7, // LoadTime
+        1, 1, 0, // Context
+    ];
+
+    fn expected_program() -> Program {
+        let mut builder = test_utils::builder();
+        builder.force_append(vec![], Operation::LoadConnection(0));
+        builder.force_append(vec![], Operation::LoadTime(7));
+        builder.finalize().unwrap()
+    }
+
+    fn versioned(version: u8, program: &[u8]) -> Vec<u8> {
+        let mut blob = SCHEMA_MAGIC.to_vec();
+        blob.push(version);
+        blob.extend_from_slice(program);
+        blob
+    }
+
+    /// If this fails, the encoding of `Program` changed and `Program::SCHEMA_VERSION` needs a bump
+    #[test]
+    fn versioned_blob_decodes() {
+        let blob = versioned(1, &PROGRAM);
+
+        let program = Program::from_bytes_versioned(&blob).unwrap();
+        assert_eq!(program, expected_program());
+        assert_eq!(Program::from_bytes_current(&blob).unwrap(), program);
+        assert_eq!(program.to_bytes_versioned(), blob);
+    }
+
+    #[test]
+    fn unversioned_blob_is_migrated() {
+        let program = Program::from_bytes_versioned(&PROGRAM).unwrap();
+        assert_eq!(program, expected_program());
+
+        let program = Program::from_bytes_versioned(&PROGRAM_V0).unwrap();
+        let operations: Vec<Operation> = program
+            .instructions
+            .into_iter()
+            .map(|instruction| instruction.operation)
+            .collect();
+        assert_eq!(
+            operations,
+            vec![
+                Operation::LoadTxo {
+                    outpoint: ([1; 32], 2),
+                    value: 300,
+                    script_pubkey: vec![0x51],
+                    spending_script_sig: vec![],
+                    spending_witness: vec![vec![0xaa]],
+                },
+                Operation::LoadAddr(AddrRecord::V2 {
+                    time: 1,
+                    services: 9,
+                    network: AddrNetwork::TorV3,
+                    payload: vec![0xbb; 2],
+                    port: 8333,
+                }),
+                Operation::BuildTaprootTree {
+                    secret_key: [3; 32],
+                    script_leaf: Some(TaprootLeafSpec {
+                        script: vec![0x51],
+                        version: 0xc0,
+                        merkle_path: vec![],
+                    }),
+                },
+                Operation::LoadTime(7),
+            ]
+        );
+
+        // Unversioned programs are never decoded with the current schema
+        assert_eq!(
+            Program::from_bytes_current(&PROGRAM_V0)
+                .unwrap_err()
+                .version_found,
+            0
+        );
+    }
+
+    #[test]
+    fn unversioned_blob_is_not_mistaken_for_schema_version() {
+        // A single instruction program starts with the same byte as an encoded
+        // `Program::SCHEMA_VERSION`
+        let blob = [1, 0, 8, 7, 1, 1, 0];
+        let program = Program::from_bytes_versioned(&blob).unwrap();
+        assert_eq!(program.instructions.len(), 1);
+        assert_eq!(program.instructions[0].operation, Operation::LoadTime(7));
+    }
+
+    #[test]
+    fn operations_added_after_version_0_are_rejected_in_unversioned_blobs() {
+        // Discriminant 107 is the first operation added after version 0
+        let blob = [1, 0, 107, 1, 1, 0];
+        let error = Program::from_bytes_versioned(&blob).unwrap_err();
+        assert_eq!(error.version_found, 0);
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let blob = versioned(Program::SCHEMA_VERSION as u8 + 1, &PROGRAM);
+
+        let error = Program::from_bytes_versioned(&blob).unwrap_err();
+        assert_eq!(error.version_found, Program::SCHEMA_VERSION + 1);
+        assert_eq!(error.version_expected, Program::SCHEMA_VERSION);
+        assert!(Program::from_bytes_current(&blob).is_err());
+    }
+}
@@ -0,0 +1,444 @@
+//! Version 0 of the program encoding: postcard encoded `Program`s without a schema prefix, as
+//! written by the fuzzer and all tools predating schema versioning.
+//!
+//! The types in this module are frozen copies of the types as of version 0 and must never be
+//! changed, otherwise programs written with version 0 are decoded incorrectly.
+
+use std::time::Duration;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    pub context: ProgramContext,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ProgramContext {
+    pub num_nodes: usize,
+    pub num_connections: usize,
+    pub timestamp: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Instruction {
+    pub inputs: Vec<usize>,
+    pub operation: Operation,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub enum AddrNetwork {
+    IPv4,
+    IPv6,
+    TorV2,
+    TorV3,
+    I2p,
+    Cjdns,
+    Yggdrasil,
+    Unknown(u8),
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub enum AddrRecord {
+    V1 {
+        time: u32,
+        services: u64,
+        ip: [u8; 16],
+        port: u16,
+    },
+    V2 {
+        time: u32,
+        services: u64,
+        network: AddrNetwork,
+        payload: Vec<u8>,
+        port: u16,
+    },
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct TaprootLeafSpec {
+    pub script: Vec<u8>,
+    pub version: u8,
+    pub merkle_path: Vec<[u8; 32]>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub enum Operation {
+    Nop {
+        outputs: usize,
+        inner_outputs: usize,
+    },
+    LoadBytes(Vec<u8>),
+    LoadMsgType([char; 12]),
+    LoadNode(usize),
+    LoadConnection(usize),
+    LoadConnectionType(String),
+    LoadDuration(Duration),
+    LoadAddr(AddrRecord),
+    LoadTime(u64),
+    LoadAmount(u64),
+    LoadSize(usize),
+    LoadTxVersion(u32),
+    LoadBlockVersion(i32),
+    LoadLockTime(u32),
+    LoadSequence(u32),
+    LoadBlockHeight(u32),
+    LoadCompactFilterType(u8),
+    LoadPrivateKey([u8; 32]),
+    LoadSigHashFlags(u8),
+    LoadNonce(u64),
+    LoadTxo {
+        outpoint: ([u8; 32], u32),
+        value: u64,
+        script_pubkey: Vec<u8>,
+        spending_script_sig: Vec<u8>,
+        spending_witness: Vec<Vec<u8>>,
+    },
+    LoadTaprootAnnex {
+        annex: Vec<u8>,
+    },
+    LoadHeader {
+        prev: [u8; 32],
+        merkle_root: [u8; 32],
+        nonce: u32,
+        bits: u32,
+        time: u32,
+        version: i32,
+        height: u32,
+    },
+    LoadFilterLoad {
+        filter: Vec<u8>,
+        hash_funcs: u32,
+        tweak: u32,
+        flags: u8,
+    },
+    LoadFilterAdd {
+        data: Vec<u8>,
+    },
+    BeginBuildBlockTxn,
+    AddTxToBlockTxn,
+    EndBuildBlockTxn,
+    SendRawMessage,
+    AdvanceTime,
+    SetTime,
+    BuildRawScripts,
+    BuildPayToWitnessScriptHash,
+    BuildPayToPubKey,
+    BuildPayToPubKeyHash,
+    BuildPayToWitnessPubKeyHash,
+    BuildPayToScriptHash,
+    BuildOpReturnScripts,
+    BuildPayToAnchor,
+    BuildPayToTaproot,
+    BuildCompactBlock,
+    BeginBuildFilterLoad,
+    AddTxToFilter,
+    AddTxoToFilter,
+    EndBuildFilterLoad,
+    BuildFilterAddFromTx,
+    BuildFilterAddFromTxo,
+    BeginWitnessStack,
+    EndWitnessStack,
+    AddWitness,
+    BeginBuildTx,
+    EndBuildTx,
+    BeginBuildTxInputs,
+    EndBuildTxInputs,
+    BeginBuildTxOutputs,
+    EndBuildTxOutputs,
+    AddTxOutput,
+    AddTxInput,
+    TakeTxo,
+    TakeCoinbaseTxo,
+    BeginBuildCoinbaseTx,
+    EndBuildCoinbaseTx,
+    BuildCoinbaseTxInput,
+    BeginBuildCoinbaseTxOutputs,
+    EndBuildCoinbaseTxOutputs,
+    AddCoinbaseTxOutput,
+    BeginBlockTransactions,
+    EndBlockTransactions,
+    BuildBlock,
+    AddTx,
+    BeginBuildInventory,
+    EndBuildInventory,
+    AddCompactBlockInv,
+    AddTxidInv,
+    AddTxidWithWitnessInv,
+    AddWtxidInv,
+    AddBlockInv,
+    AddBlockWithWitnessInv,
+    AddFilteredBlockInv,
+    BeginBuildAddrList,
+    EndBuildAddrList,
+    AddAddr,
+    BeginBuildAddrListV2,
+    EndBuildAddrListV2,
+    AddAddrV2,
+    Probe,
+    SendGetData,
+    SendInv,
+    SendGetAddr,
+    SendAddr,
+    SendAddrV2,
+    SendTx,
+    SendTxNoWit,
+    SendHeader,
+    SendBlock,
+    SendBlockNoWit,
+    SendGetCFilters,
+    SendGetCFHeaders,
+    SendGetCFCheckpt,
+    SendFilterLoad,
+    SendFilterAdd,
+    SendFilterClear,
+    SendCompactBlock,
+    SendBlockTxn,
+    TaprootScriptsUseAnnex,
+    TaprootTxoUseAnnex,
+    BuildTaprootTree {
+        secret_key: [u8; 32],
+        script_leaf: Option<TaprootLeafSpec>,
+    },
+}
+
+impl From<Program> for crate::Program {
+    fn from(program: Program) -> Self {
+        crate::Program::unchecked_new(
+            crate::ProgramContext {
+                num_nodes: program.context.num_nodes,
+                num_connections: program.context.num_connections,
+                timestamp: program.context.timestamp,
+            },
+            program
+                .instructions
+                .into_iter()
+                .map(|instruction| crate::Instruction {
+                    inputs: instruction.inputs,
+                    operation: instruction.operation.into(),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl From<AddrNetwork> for crate::AddrNetwork {
+    fn from(network: AddrNetwork) -> Self {
+        match network {
+            AddrNetwork::IPv4 => crate::AddrNetwork::IPv4,
+            AddrNetwork::IPv6 => crate::AddrNetwork::IPv6,
+            AddrNetwork::TorV2 => crate::AddrNetwork::TorV2,
+            AddrNetwork::TorV3 => crate::AddrNetwork::TorV3,
+            AddrNetwork::I2p => crate::AddrNetwork::I2p,
+            AddrNetwork::Cjdns => crate::AddrNetwork::Cjdns,
+            AddrNetwork::Yggdrasil => crate::AddrNetwork::Yggdrasil,
+            AddrNetwork::Unknown(id) => crate::AddrNetwork::Unknown(id),
+        }
+    }
+}
+
+impl From<AddrRecord> for crate::AddrRecord {
+    fn from(addr: AddrRecord) -> Self {
+        match addr {
+            AddrRecord::V1 {
+                time,
+                services,
+                ip,
+                port,
+            } => crate::AddrRecord::V1 {
+                time,
+                services,
+                ip,
+                port,
+            },
+            AddrRecord::V2 {
+                time,
+                services,
+                network,
+                payload,
+                port,
+            } => crate::AddrRecord::V2 {
+                time,
+                services,
+                network: network.into(),
+                payload,
+                port,
+            },
+        }
+    }
+}
+
+impl From<TaprootLeafSpec> for crate::TaprootLeafSpec {
+    fn from(leaf: TaprootLeafSpec) -> Self {
+        crate::TaprootLeafSpec {
+            script: leaf.script,
+            version: leaf.version,
+            merkle_path: leaf.merkle_path,
+        }
+    }
+}
+
+impl From<Operation> for crate::Operation {
+    fn from(operation: Operation) -> Self {
+        match operation {
+            Operation::Nop {
+                outputs,
+                inner_outputs,
+            } => crate::Operation::Nop {
+                outputs,
+                inner_outputs,
+            },
+            Operation::LoadBytes(value) => crate::Operation::LoadBytes(value),
+            Operation::LoadMsgType(value) => crate::Operation::LoadMsgType(value),
+            Operation::LoadNode(value) => crate::Operation::LoadNode(value),
+            Operation::LoadConnection(value) => crate::Operation::LoadConnection(value),
+            Operation::LoadConnectionType(value) => crate::Operation::LoadConnectionType(value),
+            Operation::LoadDuration(value) => crate::Operation::LoadDuration(value),
+            Operation::LoadAddr(addr) => crate::Operation::LoadAddr(addr.into()),
+            Operation::LoadTime(value) => crate::Operation::LoadTime(value),
+            Operation::LoadAmount(value) => crate::Operation::LoadAmount(value),
+            Operation::LoadSize(value) => crate::Operation::LoadSize(value),
+            Operation::LoadTxVersion(value) => crate::Operation::LoadTxVersion(value),
+            Operation::LoadBlockVersion(value) => crate::Operation::LoadBlockVersion(value),
+            Operation::LoadLockTime(value) => crate::Operation::LoadLockTime(value),
+            Operation::LoadSequence(value) => crate::Operation::LoadSequence(value),
+            Operation::LoadBlockHeight(value) => crate::Operation::LoadBlockHeight(value),
+            Operation::LoadCompactFilterType(value) => {
+                crate::Operation::LoadCompactFilterType(value)
+            }
+            Operation::LoadPrivateKey(value) => crate::Operation::LoadPrivateKey(value),
+            Operation::LoadSigHashFlags(value) => crate::Operation::LoadSigHashFlags(value),
+            Operation::LoadNonce(value) => crate::Operation::LoadNonce(value),
+            Operation::LoadTxo {
+                outpoint,
+                value,
+                script_pubkey,
+                spending_script_sig,
+                spending_witness,
+            } => crate::Operation::LoadTxo {
+                outpoint,
+                value,
+                script_pubkey,
+                spending_script_sig,
+                spending_witness,
+            },
+            Operation::LoadTaprootAnnex { annex } => crate::Operation::LoadTaprootAnnex { annex },
+            Operation::LoadHeader {
+                prev,
+                merkle_root,
+                nonce,
+                bits,
+                time,
+                version,
+                height,
+            } => crate::Operation::LoadHeader {
+                prev,
+                merkle_root,
+                nonce,
+                bits,
+                time,
+                version,
+                height,
+            },
+            Operation::LoadFilterLoad {
+                filter,
+                hash_funcs,
+                tweak,
+                flags,
+            } => crate::Operation::LoadFilterLoad {
+                filter,
+                hash_funcs,
+                tweak,
+                flags,
+            },
+            Operation::LoadFilterAdd { data } => crate::Operation::LoadFilterAdd { data },
+            Operation::BeginBuildBlockTxn => crate::Operation::BeginBuildBlockTxn,
+            Operation::AddTxToBlockTxn => crate::Operation::AddTxToBlockTxn,
+            Operation::EndBuildBlockTxn => crate::Operation::EndBuildBlockTxn,
+            Operation::SendRawMessage => crate::Operation::SendRawMessage,
+            Operation::AdvanceTime => crate::Operation::AdvanceTime,
+            Operation::SetTime => crate::Operation::SetTime,
+            Operation::BuildRawScripts => crate::Operation::BuildRawScripts,
+            Operation::BuildPayToWitnessScriptHash => crate::Operation::BuildPayToWitnessScriptHash,
+            Operation::BuildPayToPubKey => crate::Operation::BuildPayToPubKey,
+            Operation::BuildPayToPubKeyHash => crate::Operation::BuildPayToPubKeyHash,
+            Operation::BuildPayToWitnessPubKeyHash => crate::Operation::BuildPayToWitnessPubKeyHash,
+            Operation::BuildPayToScriptHash => crate::Operation::BuildPayToScriptHash,
+            Operation::BuildOpReturnScripts => crate::Operation::BuildOpReturnScripts,
+            Operation::BuildPayToAnchor => crate::Operation::BuildPayToAnchor,
+            Operation::BuildPayToTaproot => crate::Operation::BuildPayToTaproot,
+            Operation::BuildCompactBlock => crate::Operation::BuildCompactBlock,
+            Operation::BeginBuildFilterLoad => crate::Operation::BeginBuildFilterLoad,
+            Operation::AddTxToFilter => crate::Operation::AddTxToFilter,
+            Operation::AddTxoToFilter => crate::Operation::AddTxoToFilter,
+            Operation::EndBuildFilterLoad => crate::Operation::EndBuildFilterLoad,
+            Operation::BuildFilterAddFromTx => crate::Operation::BuildFilterAddFromTx,
+            Operation::BuildFilterAddFromTxo => crate::Operation::BuildFilterAddFromTxo,
+            Operation::BeginWitnessStack => crate::Operation::BeginWitnessStack,
+            Operation::EndWitnessStack => crate::Operation::EndWitnessStack,
+            Operation::AddWitness => crate::Operation::AddWitness,
+            Operation::BeginBuildTx => crate::Operation::BeginBuildTx,
+            Operation::EndBuildTx => crate::Operation::EndBuildTx,
+            Operation::BeginBuildTxInputs => crate::Operation::BeginBuildTxInputs,
+            Operation::EndBuildTxInputs => crate::Operation::EndBuildTxInputs,
+            Operation::BeginBuildTxOutputs => crate::Operation::BeginBuildTxOutputs,
+            Operation::EndBuildTxOutputs => crate::Operation::EndBuildTxOutputs,
+            Operation::AddTxOutput => crate::Operation::AddTxOutput,
+            Operation::AddTxInput => crate::Operation::AddTxInput,
+            Operation::TakeTxo => crate::Operation::TakeTxo,
+            Operation::TakeCoinbaseTxo => crate::Operation::TakeCoinbaseTxo,
+            Operation::BeginBuildCoinbaseTx => crate::Operation::BeginBuildCoinbaseTx,
+            Operation::EndBuildCoinbaseTx => crate::Operation::EndBuildCoinbaseTx,
+            Operation::BuildCoinbaseTxInput => crate::Operation::BuildCoinbaseTxInput,
+            Operation::BeginBuildCoinbaseTxOutputs => crate::Operation::BeginBuildCoinbaseTxOutputs,
+            Operation::EndBuildCoinbaseTxOutputs => crate::Operation::EndBuildCoinbaseTxOutputs,
+            Operation::AddCoinbaseTxOutput => crate::Operation::AddCoinbaseTxOutput,
+            Operation::BeginBlockTransactions => crate::Operation::BeginBlockTransactions,
+            Operation::EndBlockTransactions => crate::Operation::EndBlockTransactions,
+            Operation::BuildBlock => crate::Operation::BuildBlock,
+            Operation::AddTx => crate::Operation::AddTx,
+            Operation::BeginBuildInventory => crate::Operation::BeginBuildInventory,
+            Operation::EndBuildInventory => crate::Operation::EndBuildInventory,
+            Operation::AddCompactBlockInv => crate::Operation::AddCompactBlockInv,
+            Operation::AddTxidInv => crate::Operation::AddTxidInv,
+            Operation::AddTxidWithWitnessInv => crate::Operation::AddTxidWithWitnessInv,
+            Operation::AddWtxidInv => crate::Operation::AddWtxidInv,
+            Operation::AddBlockInv => crate::Operation::AddBlockInv,
+            Operation::AddBlockWithWitnessInv => crate::Operation::AddBlockWithWitnessInv,
+            Operation::AddFilteredBlockInv => crate::Operation::AddFilteredBlockInv,
+            Operation::BeginBuildAddrList => crate::Operation::BeginBuildAddrList,
+            Operation::EndBuildAddrList => crate::Operation::EndBuildAddrList,
+            Operation::AddAddr => crate::Operation::AddAddr,
+            Operation::BeginBuildAddrListV2 => crate::Operation::BeginBuildAddrListV2,
+            Operation::EndBuildAddrListV2 => crate::Operation::EndBuildAddrListV2,
+            Operation::AddAddrV2 => crate::Operation::AddAddrV2,
+            Operation::Probe => crate::Operation::Probe,
+            Operation::SendGetData => crate::Operation::SendGetData,
+            Operation::SendInv => crate::Operation::SendInv,
+            Operation::SendGetAddr => crate::Operation::SendGetAddr,
+            Operation::SendAddr => crate::Operation::SendAddr,
+            Operation::SendAddrV2 => crate::Operation::SendAddrV2,
+            Operation::SendTx => crate::Operation::SendTx,
+            Operation::SendTxNoWit => crate::Operation::SendTxNoWit,
+            Operation::SendHeader => crate::Operation::SendHeader,
+            Operation::SendBlock => crate::Operation::SendBlock,
+            Operation::SendBlockNoWit => crate::Operation::SendBlockNoWit,
+            Operation::SendGetCFilters => crate::Operation::SendGetCFilters,
+            Operation::SendGetCFHeaders => crate::Operation::SendGetCFHeaders,
+            Operation::SendGetCFCheckpt => crate::Operation::SendGetCFCheckpt,
+            Operation::SendFilterLoad => crate::Operation::SendFilterLoad,
+            Operation::SendFilterAdd => crate::Operation::SendFilterAdd,
+            Operation::SendFilterClear => crate::Operation::SendFilterClear,
+            Operation::SendCompactBlock => crate::Operation::SendCompactBlock,
+            Operation::SendBlockTxn => crate::Operation::SendBlockTxn,
+            Operation::TaprootScriptsUseAnnex => crate::Operation::TaprootScriptsUseAnnex,
+            Operation::TaprootTxoUseAnnex => crate::Operation::TaprootTxoUseAnnex,
+            Operation::BuildTaprootTree {
+                secret_key,
+                script_leaf,
+            } => crate::Operation::BuildTaprootTree {
+                secret_key,
+                script_leaf: script_leaf.map(Into::into),
+            },
+        }
+    }
+}
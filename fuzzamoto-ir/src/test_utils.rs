@@ -0,0 +1,83 @@
+//! Helpers shared by the unit tests of this crate.
+
+use bitcoin::consensus::{Decodable, deserialize};
+#[cfg(feature = "generators")]
+use bitcoin::hashes::Hash;
+
+#[cfg(feature = "generators")]
+use crate::Operation;
+use crate::{
+    ProgramBuilder, ProgramContext,
+    compiler::{CompiledAction, Compiler},
+};
+
+/// Builder for a program with a single node and a single connection
+pub fn builder() -> ProgramBuilder {
+    ProgramBuilder::new(ProgramContext {
+        num_nodes: 1,
+        num_connections: 1,
+        timestamp: 0,
+    })
+}
+
+/// Builder with the regtest genesis header and a time just after it loaded
+#[cfg(feature = "generators")]
+pub fn builder_on_genesis() -> ProgramBuilder {
+    let mut builder = builder();
+    let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+    builder.force_append(
+        vec![],
+        Operation::LoadHeader {
+            prev: genesis.header.prev_blockhash.to_byte_array(),
+            merkle_root: genesis.header.merkle_root.to_byte_array(),
+            nonce: genesis.header.nonce,
+            bits: genesis.header.bits.to_consensus(),
+            time: genesis.header.time,
+            version: genesis.header.version.to_consensus(),
+            height: 0,
+        },
+    );
+    builder.force_append(vec![], Operation::LoadTime(genesis.header.time as u64 + 1));
+    builder
+}
+
+/// Load an `OP_TRUE` txo worth `value` sats
+#[cfg(feature = "generators")]
+pub fn load_txo(builder: &mut ProgramBuilder, value: u64) {
+    builder.force_append(
+        vec![],
+        Operation::LoadTxo {
+            outpoint: ([1; 32], 0),
+            value,
+            script_pubkey: vec![0x51],
+            spending_script_sig: vec![],
+            spending_witness: vec![],
+        },
+    );
+}
+
+/// Commands and payloads of all messages sent by the program in `builder`
+pub fn sent_raw_messages(builder: &ProgramBuilder) -> Vec<(String, Vec<u8>)> {
+    let program = builder.finalize().expect("valid program");
+    let compiled = Compiler::new().compile(&program).expect("compile");
+    compiled
+        .actions
+        .into_iter()
+        .filter_map(|action| match action {
+            // Raw message types are padded with null characters
+            CompiledAction::SendRawMessage(_, command, payload) => {
+                Some((command.trim_end_matches('\0').to_string(), payload))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Payloads of all `command` messages sent by the program in `builder`, decoded as `T`
+pub fn sent_messages<T: Decodable>(builder: &ProgramBuilder, command: &str) -> Vec<T> {
+    sent_raw_messages(builder)
+        .into_iter()
+        .filter(|(sent, _)| sent == command)
+        .map(|(_, payload)| deserialize(&payload).expect("valid message payload"))
+        .collect()
+}
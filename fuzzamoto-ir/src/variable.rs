@@ -1,5 +1,8 @@
 /// `Variable` represents a variable types in the IR
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Variables are ordered by their declaration order below, which gives a canonical order for
+/// sorting variables by type (see `CanonicalProgram`).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Variable {
     Nop, // Output type for no-op instructions
 
@@ -25,6 +28,7 @@ pub enum Variable {
     ConstWitnessStack,
     PrivateKey,
     SigHashFlags,
+    MutMultiSigBuilder, // Multisig script under construction
 
     Txo, // Existing transaction output (maybe confirmed)
 
@@ -34,6 +38,7 @@ pub enum Variable {
     ConstTxInputs,  // Finalized tx inputs
     MutTxOutputs,   // Mutable tx outputs
     ConstTxOutputs, // Finalized tx outputs
+    RawTransaction, // Unvalidated transaction bytes
 
     ConstAmount, // bitcoin amount in sats
 
@@ -47,6 +52,9 @@ pub enum Variable {
     MutInventory,
     ConstInventory,
 
+    MutBlockLocator,
+    ConstBlockLocator,
+
     MutBlockTransactions,
     ConstBlockTransactions,
     Block,
@@ -57,12 +65,16 @@ pub enum Variable {
     MutFilterLoad, // Mutable filter (under construction)
     ConstFilterLoad,
     FilterAdd,
+    MerkleBlock,
 
     CoinbaseInput,
+    CoinbaseScriptSig,
     CoinbaseTx,
 
     MutBlockTxn,
     ConstBlockTxn,
+    MutGetBlockTxnReq,
+    ConstGetBlockTxnReq,
     ConstCoinbaseTx,
 
     TaprootSpendInfo,
@@ -0,0 +1,158 @@
+//! Property-based tests for the invariants enforced by `ProgramBuilder`.
+
+use std::time::Duration;
+
+use fuzzamoto_ir::{
+    Instruction, Operation, Program, ProgramBuilder, ProgramContext, errors::ProgramValidationError,
+};
+use proptest::{collection::vec, prelude::*};
+
+prop_compose! {
+    /// Random `ProgramContext` with at least one node
+    fn program_context()(
+        num_nodes in 1..4usize,
+        num_connections in 0..8usize,
+        timestamp in any::<u64>(),
+    ) -> ProgramContext {
+        ProgramContext {
+            num_nodes,
+            num_connections,
+            timestamp,
+        }
+    }
+}
+
+prop_compose! {
+    /// Random leaf operation, i.e. an operation that does not take any inputs and does not open
+    /// a new scope.
+    ///
+    /// Node and connection indices may be out of bounds for a given context, in which case the
+    /// builder is expected to reject the instruction.
+    fn leaf_operation()(
+        kind in 0..16u8,
+        value in any::<u64>(),
+        index in 0..8usize,
+        bytes in vec(any::<u8>(), 0..32),
+    ) -> Operation {
+        match kind {
+            0 => Operation::Nop {
+                outputs: index % 3,
+                inner_outputs: (value % 3) as usize,
+            },
+            1 => Operation::LoadBytes(bytes),
+            2 => Operation::LoadNode(index),
+            3 => Operation::LoadConnection(index),
+            4 => Operation::LoadConnectionType(
+                ["outbound", "inbound", "feeler"][index % 3].to_string(),
+            ),
+            5 => Operation::LoadDuration(Duration::from_secs(value % 100_000)),
+            6 => Operation::LoadTime(value),
+            7 => Operation::LoadAmount(value),
+            8 => Operation::LoadSize(index),
+            9 => Operation::LoadTxVersion(value as u32),
+            10 => Operation::LoadLockTime(value as u32),
+            11 => Operation::LoadSequence(value as u32),
+            12 => Operation::LoadBlockHeight(value as u32),
+            13 => Operation::LoadPrivateKey([value as u8; 32]),
+            14 => Operation::LoadSigHashFlags(value as u8),
+            _ => Operation::LoadNonce(value),
+        }
+    }
+}
+
+/// Append all `operations` (skipping the ones the builder rejects) and finalize the program
+fn build_program(context: ProgramContext, operations: Vec<Operation>) -> Program {
+    let mut builder = ProgramBuilder::new(context);
+    for operation in operations {
+        let _ = builder.append(Instruction {
+            inputs: vec![],
+            operation,
+        });
+    }
+    builder
+        .finalize()
+        .expect("leaf operations never leave a scope open")
+}
+
+proptest! {
+    #[test]
+    fn appended_programs_are_valid(
+        context in program_context(),
+        operations in vec(leaf_operation(), 0..64),
+    ) {
+        let program = build_program(context, operations);
+        prop_assert!(program.is_statically_valid());
+    }
+
+    #[test]
+    fn removing_nops_preserves_validity(
+        context in program_context(),
+        operations in vec(leaf_operation(), 0..64),
+    ) {
+        let mut program = build_program(context, operations);
+        program.remove_nops();
+        prop_assert!(program.is_statically_valid());
+        prop_assert!(
+            program
+                .instructions
+                .iter()
+                .all(|instr| !matches!(instr.operation, Operation::Nop { .. })),
+            "nops remain after removing them"
+        );
+    }
+
+    #[test]
+    fn builder_roundtrip(
+        context in program_context(),
+        operations in vec(leaf_operation(), 0..64),
+    ) {
+        let program = build_program(context, operations);
+        let roundtripped = ProgramBuilder::from_program(program.clone())
+            .expect("valid program")
+            .finalize()
+            .expect("valid program");
+        prop_assert_eq!(roundtripped, program);
+    }
+
+    #[test]
+    fn display_parse_roundtrip(
+        context in program_context(),
+        operations in vec(leaf_operation(), 0..64),
+    ) {
+        let program = build_program(context, operations);
+        let parsed = program
+            .to_string()
+            .parse::<Program>()
+            .expect("printed program should parse");
+        prop_assert_eq!(format!("{}", program), format!("{}", parsed));
+    }
+
+    #[test]
+    fn empty_builder_finalizes(context in program_context()) {
+        // The global scope is never exited, so an empty program is complete
+        let program = ProgramBuilder::new(context.clone())
+            .finalize()
+            .expect("empty program should be valid");
+        prop_assert!(program.instructions.is_empty());
+        prop_assert_eq!(program.context, context);
+    }
+
+    #[test]
+    fn unclosed_scope_is_rejected(
+        context in program_context(),
+        operations in vec(leaf_operation(), 0..16),
+    ) {
+        let mut builder = ProgramBuilder::new(context);
+        builder.force_append(vec![], Operation::BeginBuildInventory);
+        for operation in operations {
+            let _ = builder.append(Instruction {
+                inputs: vec![],
+                operation,
+            });
+        }
+        prop_assert!(matches!(
+            builder.finalize(),
+            Err(ProgramValidationError::ScopeStillOpen)
+        ));
+    }
+}
@@ -1,21 +1,30 @@
-use std::{borrow::Cow, rc::Rc};
+pub mod recv;
+pub use recv::*;
+
+use std::{borrow::Cow, rc::Rc, time::Duration};
 use std::{cell::RefCell, fmt::Debug};
 
-use libafl_bolts::{Error, Named};
+use libafl_bolts::{
+    Error, Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
 
 use libafl::{
     HasMetadata,
     corpus::Testcase,
     executors::ExitKind,
     feedbacks::{Feedback, StateInitializer},
+    observers::TimeObserver,
     state::HasCorpus,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::input::IrInput;
 use crate::stages::TimeoutsToVerify;
 
 /// A Feedback that captures all timeouts and stores them in State for re-evaluation later.
-/// Use in conjunction with `VerifyTimeoutsStage`
+/// Use in conjunction with `VerifyTimeoutsStage`, which disables the capturing while re-running
+/// the captured timeouts (timeouts are then reported as interesting).
 #[derive(Debug)]
 pub struct CaptureTimeoutFeedback {
     enabled: Rc<RefCell<bool>>,
@@ -50,7 +59,12 @@ where
         _observers: &OT,
         exit_kind: &ExitKind,
     ) -> Result<bool, Error> {
-        if *self.enabled.borrow() && matches!(exit_kind, ExitKind::Timeout) {
+        if !*self.enabled.borrow() {
+            // Re-run by `VerifyTimeoutsStage`, which classifies the outcome
+            state
+                .metadata_or_insert_with(TimeoutsToVerify::new)
+                .set_rerun_exit_kind(*exit_kind);
+        } else if matches!(exit_kind, ExitKind::Timeout) {
             let timeouts = state.metadata_or_insert_with(TimeoutsToVerify::new);
             log::info!("Timeout detected, adding to verification queue!");
             timeouts.push(input.clone());
@@ -69,3 +83,164 @@ where
         Ok(())
     }
 }
+
+/// Predicted (see `Program::estimate_execution_time_ms`) and actual execution time of a testcase
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionTimePredictionMetadata {
+    pub predicted_ms: u64,
+    pub actual_ms: u64,
+    /// Actual divided by predicted execution time
+    pub error_ratio: f64,
+}
+impl_serdeany!(ExecutionTimePredictionMetadata);
+
+impl ExecutionTimePredictionMetadata {
+    pub fn new(predicted_ms: u64, actual: Duration) -> Self {
+        Self {
+            predicted_ms,
+            actual_ms: actual.as_millis() as u64,
+            error_ratio: actual.as_secs_f64() * 1000.0 / predicted_ms.max(1) as f64,
+        }
+    }
+
+    /// Check if the prediction is within `factor` of the actual execution time (in either
+    /// direction)
+    pub fn is_within(&self, factor: f64) -> bool {
+        self.error_ratio <= factor && self.error_ratio >= 1.0 / factor
+    }
+}
+
+/// Running mean of the `ExecutionTimePredictionMetadata::error_ratio` of all corpus entries, used
+/// to calibrate the (machine independent) execution time predictions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecutionTimeCorrection {
+    samples: u64,
+    mean_error_ratio: f64,
+}
+impl_serdeany!(ExecutionTimeCorrection);
+
+impl ExecutionTimeCorrection {
+    pub fn record(&mut self, error_ratio: f64) {
+        self.samples += 1;
+        self.mean_error_ratio += (error_ratio - self.mean_error_ratio) / self.samples as f64;
+    }
+
+    /// Factor the predictions are multiplied with (1 until the first sample is recorded)
+    pub fn factor(&self) -> f64 {
+        if self.samples == 0 {
+            1.0
+        } else {
+            self.mean_error_ratio
+        }
+    }
+
+    /// Calibrated execution time for a prediction of `predicted_ms`
+    pub fn corrected(&self, predicted_ms: u64) -> Duration {
+        Duration::from_secs_f64(predicted_ms as f64 * self.factor() / 1000.0)
+    }
+}
+
+/// A Feedback that records the `ExecutionTimePredictionMetadata` of new corpus entries and
+/// calibrates the predictions with the measured execution times (see `ExecutionTimeCorrection`).
+///
+/// The execution time used for scheduling (recorded by `TimeFeedback`) is raised to the corrected
+/// prediction, such that a single fast measurement of an expensive program (e.g. one mining many
+/// blocks) does not make it look cheap. Has to be evaluated after `TimeFeedback`.
+#[derive(Debug)]
+pub struct ExecutionTimePredictionFeedback {
+    observer_handle: Handle<TimeObserver>,
+}
+
+impl ExecutionTimePredictionFeedback {
+    /// Create a new [`ExecutionTimePredictionFeedback`].
+    pub fn new(observer: &TimeObserver) -> Self {
+        Self {
+            observer_handle: observer.handle(),
+        }
+    }
+}
+
+impl Named for ExecutionTimePredictionFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ExecutionTimePredictionFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for ExecutionTimePredictionFeedback {}
+
+impl<EM, OT, S> Feedback<EM, IrInput, OT, S> for ExecutionTimePredictionFeedback
+where
+    OT: MatchName,
+    S: HasMetadata,
+{
+    #[inline]
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IrInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<IrInput>,
+    ) -> Result<(), Error> {
+        let observer = observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("TimeObserver not found"))?;
+        let (Some(actual), Some(input)) = (*observer.last_runtime(), testcase.input()) else {
+            return Ok(());
+        };
+
+        let prediction =
+            ExecutionTimePredictionMetadata::new(input.ir().estimate_execution_time_ms(), actual);
+        let correction = state.metadata_or_insert_with(ExecutionTimeCorrection::default);
+        correction.record(prediction.error_ratio);
+        let corrected = correction.corrected(prediction.predicted_ms);
+
+        if testcase.exec_time().is_none_or(|time| time < corrected) {
+            *testcase.exec_time_mut() = Some(corrected.max(actual));
+        }
+        testcase.add_metadata(prediction);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prediction_error_ratio() {
+        let prediction = ExecutionTimePredictionMetadata::new(100, Duration::from_millis(150));
+        assert_eq!(prediction.actual_ms, 150);
+        assert_eq!(prediction.error_ratio, 1.5);
+        assert!(prediction.is_within(2.0));
+
+        let prediction = ExecutionTimePredictionMetadata::new(100, Duration::from_millis(40));
+        assert!(!prediction.is_within(2.0));
+        let prediction = ExecutionTimePredictionMetadata::new(100, Duration::from_millis(201));
+        assert!(!prediction.is_within(2.0));
+    }
+
+    #[test]
+    fn correction_is_mean_error_ratio() {
+        let mut correction = ExecutionTimeCorrection::default();
+        assert_eq!(correction.corrected(100), Duration::from_millis(100));
+
+        correction.record(0.5);
+        correction.record(1.5);
+        correction.record(4.0);
+        assert_eq!(correction.factor(), 2.0);
+        assert_eq!(correction.corrected(100), Duration::from_millis(200));
+    }
+}
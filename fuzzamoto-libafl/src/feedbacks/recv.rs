@@ -0,0 +1,274 @@
+use std::{borrow::Cow, collections::BTreeSet};
+
+use fuzzamoto_ir::ProbeResult;
+use libafl::{
+    HasMetadata,
+    corpus::{Corpus, CorpusId, Testcase},
+    executors::ExitKind,
+    feedbacks::{Feedback, StateInitializer},
+    observers::StdOutObserver,
+};
+use libafl_bolts::{
+    Error, Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{input::IrInput, stages::decode_probe_results};
+
+/// Commands and payload lengths of the messages the node sent in response to the last execution
+/// (see `ProbeResult::ReceivedMessages`)
+fn received_messages<OT: MatchName>(
+    observers: &OT,
+    handle: &Handle<StdOutObserver>,
+) -> Result<Vec<(String, usize)>, Error> {
+    let observer = observers
+        .get(handle)
+        .ok_or_else(|| Error::key_not_found("StdOutObserver not found"))?;
+    let Some(output) = observer.output.as_ref() else {
+        return Ok(Vec::new());
+    };
+
+    Ok(decode_probe_results(output)
+        .into_iter()
+        .flatten()
+        .filter_map(|result| match result {
+            ProbeResult::ReceivedMessages { messages } => Some(messages),
+            _ => None,
+        })
+        .flatten()
+        .collect())
+}
+
+/// Logarithmic bucket of a payload length: 0 for empty payloads, `n + 1` for lengths in
+/// `[2^n, 2^(n+1))`
+pub fn payload_length_bucket(len: usize) -> u8 {
+    match len {
+        0 => 0,
+        len => len.ilog2() as u8 + 1,
+    }
+}
+
+/// Response message commands observed for a testcase
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecvFeedbackMetadata {
+    pub commands_seen: BTreeSet<String>,
+}
+impl_serdeany!(RecvFeedbackMetadata);
+
+/// Response messages observed across all testcases, used by `RecvFeedback` and
+/// `RecvMessageTypeFeedback` to detect novel responses
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RecvNoveltyMetadata {
+    pub commands: BTreeSet<String>,
+    pub command_sizes: BTreeSet<(String, u8)>,
+}
+impl_serdeany!(RecvNoveltyMetadata);
+
+/// Up to `n` corpus entries with the most diverse responses (i.e. the most distinct commands in
+/// their `RecvFeedbackMetadata`), sorted by descending diversity
+pub fn most_diverse_testcases<C: Corpus<IrInput>>(
+    corpus: &C,
+    n: usize,
+) -> Result<Vec<(CorpusId, RecvFeedbackMetadata)>, Error> {
+    let mut testcases = Vec::new();
+    for idx in 0..corpus.count() + corpus.count_disabled() {
+        let id = corpus.nth_from_all(idx);
+        if let Ok(meta) = corpus
+            .get_from_all(id)?
+            .borrow()
+            .metadata::<RecvFeedbackMetadata>()
+        {
+            testcases.push((id, meta.clone()));
+        }
+    }
+
+    testcases.sort_by(|(a_id, a), (b_id, b)| {
+        b.commands_seen
+            .len()
+            .cmp(&a.commands_seen.len())
+            .then_with(|| a_id.cmp(b_id))
+    });
+    testcases.truncate(n);
+    Ok(testcases)
+}
+
+/// A Feedback that rates inputs as interesting if the node responds with a message command it
+/// has not responded with before. The observed commands are stored in the testcase's
+/// `RecvFeedbackMetadata`.
+///
+/// Responses are only reported by the scenario if every sent message is followed by a ping
+/// round-trip (`force_send_and_ping`).
+#[derive(Debug)]
+pub struct RecvFeedback {
+    observer_handle: Handle<StdOutObserver>,
+}
+
+impl RecvFeedback {
+    /// Create a new [`RecvFeedback`].
+    pub fn new(observer: &StdOutObserver) -> Self {
+        Self {
+            observer_handle: observer.handle(),
+        }
+    }
+}
+
+impl Named for RecvFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("RecvFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for RecvFeedback {}
+
+impl<EM, OT, S> Feedback<EM, IrInput, OT, S> for RecvFeedback
+where
+    OT: MatchName,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &IrInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let messages = received_messages(observers, &self.observer_handle)?;
+        let novelty = state.metadata_or_insert_with(RecvNoveltyMetadata::default);
+
+        let mut interesting = false;
+        for (command, _) in messages {
+            interesting |= novelty.commands.insert(command);
+        }
+        Ok(interesting)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<IrInput>,
+    ) -> Result<(), Error> {
+        let commands_seen = received_messages(observers, &self.observer_handle)?
+            .into_iter()
+            .map(|(command, _)| command)
+            .collect();
+        testcase.add_metadata(RecvFeedbackMetadata { commands_seen });
+        Ok(())
+    }
+}
+
+/// A Feedback that rates inputs as interesting if the node responds with a novel combination of
+/// message command and payload length bucket (see `payload_length_bucket`), e.g. an `inv` with
+/// many more entries than observed before.
+#[derive(Debug)]
+pub struct RecvMessageTypeFeedback {
+    observer_handle: Handle<StdOutObserver>,
+}
+
+impl RecvMessageTypeFeedback {
+    /// Create a new [`RecvMessageTypeFeedback`].
+    pub fn new(observer: &StdOutObserver) -> Self {
+        Self {
+            observer_handle: observer.handle(),
+        }
+    }
+}
+
+impl Named for RecvMessageTypeFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("RecvMessageTypeFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for RecvMessageTypeFeedback {}
+
+impl<EM, OT, S> Feedback<EM, IrInput, OT, S> for RecvMessageTypeFeedback
+where
+    OT: MatchName,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &IrInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let messages = received_messages(observers, &self.observer_handle)?;
+        let novelty = state.metadata_or_insert_with(RecvNoveltyMetadata::default);
+
+        let mut interesting = false;
+        for (command, len) in messages {
+            interesting |= novelty
+                .command_sizes
+                .insert((command, payload_length_bucket(len)));
+        }
+        Ok(interesting)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::{Operation, ProgramBuilder, ProgramContext};
+    use libafl::corpus::InMemoryCorpus;
+
+    #[test]
+    fn payload_length_buckets() {
+        assert_eq!(payload_length_bucket(0), 0);
+        assert_eq!(payload_length_bucket(1), 1);
+        assert_eq!(payload_length_bucket(2), 2);
+        assert_eq!(payload_length_bucket(3), 2);
+        assert_eq!(payload_length_bucket(4), 3);
+        assert_eq!(payload_length_bucket(1000), 10);
+        assert_eq!(payload_length_bucket(1024), 11);
+    }
+
+    #[test]
+    fn most_diverse_testcases_are_sorted() {
+        let mut corpus = InMemoryCorpus::<IrInput>::new();
+        for commands in [
+            vec!["inv"],
+            vec![],
+            vec!["inv", "getdata", "headers"],
+            vec!["pong", "inv"],
+        ] {
+            let mut builder = ProgramBuilder::new(ProgramContext {
+                num_nodes: 1,
+                num_connections: 1,
+                timestamp: 0,
+            });
+            builder.force_append(vec![], Operation::LoadTime(0));
+            let mut testcase = Testcase::new(IrInput::new(builder.finalize().unwrap()));
+            testcase.add_metadata(RecvFeedbackMetadata {
+                commands_seen: commands.into_iter().map(String::from).collect(),
+            });
+            corpus.add(testcase).unwrap();
+        }
+        corpus
+            .add(Testcase::new(IrInput::new(
+                ProgramBuilder::new(ProgramContext {
+                    num_nodes: 1,
+                    num_connections: 1,
+                    timestamp: 0,
+                })
+                .finalize()
+                .unwrap(),
+            )))
+            .unwrap();
+
+        let diversity: Vec<usize> = most_diverse_testcases(&corpus, 3)
+            .unwrap()
+            .iter()
+            .map(|(_, meta)| meta.commands_seen.len())
+            .collect();
+        assert_eq!(diversity, vec![3, 2, 1]);
+        assert_eq!(most_diverse_testcases(&corpus, 10).unwrap().len(), 4);
+    }
+}
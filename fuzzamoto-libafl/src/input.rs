@@ -1,6 +1,13 @@
-use std::{fs::File, hash::Hash, io::Read, path::PathBuf};
+use std::{
+    ffi::OsString,
+    fmt,
+    fs::File,
+    hash::Hash,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
 
-use fuzzamoto_ir::Program;
+use fuzzamoto_ir::{ObservedNodeState, Program, SchemaError};
 
 use libafl::inputs::{HasTargetBytes, Input};
 use libafl_bolts::{HasLen, ownedref::OwnedSlice};
@@ -10,7 +17,87 @@ pub struct IrInput {
     ir: Program,
 }
 
-impl Input for IrInput {}
+impl Input for IrInput {
+    /// Write the schema versioned (see `Program::to_bytes_versioned`, and with the `compress`
+    /// feature, zstd compressed) program to `path`.
+    ///
+    /// The program is written to a hidden temporary file first, which is synced to disk and then
+    /// renamed to `path`. An interrupted write therefore never leaves a partially written input at
+    /// `path`.
+    fn to_file<P>(&self, path: P) -> Result<(), libafl::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        #[cfg(feature = "compress")]
+        let bytes = self.ir().to_compressed_bytes();
+        #[cfg(not(feature = "compress"))]
+        let bytes = self.ir().to_bytes_versioned();
+
+        write_atomic(path, &bytes)?;
+        Ok(())
+    }
+
+    /// Read a program from `path`, migrating programs written with a legacy schema (see
+    /// `Program::from_bytes_versioned`). With the `compress` feature, zstd compressed programs
+    /// (see `Program::to_compressed_bytes`) are read as well.
+    fn from_file<P>(path: P) -> Result<Self, libafl::Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            ir: read_program(path.as_ref())?,
+        })
+    }
+}
+
+/// Hidden temporary file next to `path`, that `IrInput::to_file` writes to before renaming it to
+/// `path` (hidden files are skipped when scanning corpus directories)
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".tmp");
+    path.with_file_name(file_name)
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp_path = tmp_path(path);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)
+}
+
+/// Hidden sidecar file next to the input at `path`, holding the node state observed while
+/// probing the input
+fn observed_state_path(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".runtime");
+    path.with_file_name(file_name)
+}
+
+/// Write the node state observed while executing the input at `path` to its sidecar file
+pub fn write_observed_state(path: &Path, observed: &ObservedNodeState) -> Result<(), IrInputError> {
+    let bytes = postcard::to_allocvec(observed)?;
+    write_atomic(&observed_state_path(path), &bytes)?;
+    Ok(())
+}
+
+/// Read the node state recorded for the input at `path` (see `write_observed_state`)
+pub fn read_observed_state(path: &Path) -> Result<ObservedNodeState, IrInputError> {
+    let bytes = std::fs::read(observed_state_path(path))?;
+    Ok(postcard::from_bytes(&bytes)?)
+}
+
+fn read_program(path: &Path) -> Result<Program, IrInputError> {
+    let bytes = std::fs::read(path)?;
+    #[cfg(feature = "compress")]
+    let program = Program::from_compressed_bytes(&bytes)?;
+    #[cfg(not(feature = "compress"))]
+    let program = Program::from_bytes_versioned(&bytes)?;
+    Ok(program)
+}
 
 impl IrInput {
     pub fn new(ir: Program) -> Self {
@@ -25,13 +112,67 @@ impl IrInput {
         &mut self.ir
     }
 
-    pub fn unparse(path: &PathBuf) -> Self {
-        let mut file = File::open(path).unwrap();
-        let mut bytes = vec![];
-        file.read_to_end(&mut bytes).unwrap();
-        let program = postcard::from_bytes(&bytes).unwrap();
+    /// Like `IrInput::from_file`, but panics if the input can't be read
+    pub fn unparse(path: &Path) -> Self {
+        Self::from_file(path)
+            .unwrap_or_else(|e| panic!("Failed to read input {}: {}", path.display(), e))
+    }
+}
+
+#[derive(Debug)]
+pub enum IrInputError {
+    Io(io::Error),
+    Decode(postcard::Error),
+    Schema(SchemaError),
+}
 
-        Self { ir: program }
+impl fmt::Display for IrInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrInputError::Io(e) => write!(f, "IO error: {}", e),
+            IrInputError::Decode(e) => write!(f, "Decode error: {}", e),
+            IrInputError::Schema(e) => write!(f, "Schema error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IrInputError {}
+
+impl From<io::Error> for IrInputError {
+    fn from(error: io::Error) -> Self {
+        IrInputError::Io(error)
+    }
+}
+
+impl From<postcard::Error> for IrInputError {
+    fn from(error: postcard::Error) -> Self {
+        IrInputError::Decode(error)
+    }
+}
+
+impl From<SchemaError> for IrInputError {
+    fn from(error: SchemaError) -> Self {
+        IrInputError::Schema(error)
+    }
+}
+
+impl From<IrInputError> for libafl::Error {
+    fn from(error: IrInputError) -> Self {
+        match error {
+            IrInputError::Io(e) => e.into(),
+            IrInputError::Decode(e) => e.into(),
+            IrInputError::Schema(e) => libafl::Error::serialize(e.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "compress")]
+impl From<fuzzamoto_ir::DeserError> for IrInputError {
+    fn from(error: fuzzamoto_ir::DeserError) -> Self {
+        match error {
+            fuzzamoto_ir::DeserError::Decompress(e) => IrInputError::Io(e),
+            fuzzamoto_ir::DeserError::Decode(e) => IrInputError::Schema(e),
+        }
     }
 }
 
@@ -63,8 +204,7 @@ impl HasTargetBytes for IrInput {
 
         #[cfg(feature = "compile_in_vm")]
         {
-            let mut bytes =
-                postcard::to_allocvec(self.ir()).expect("serialization should never fail");
+            let mut bytes = self.ir().to_bytes_versioned();
             log::trace!("Input size: {}", bytes.len());
             if bytes.len() > 1 * 1024 * 1024 {
                 bytes = Vec::new();
@@ -73,3 +213,142 @@ impl HasTargetBytes for IrInput {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::{Operation, ProgramBuilder, ProgramContext};
+    use libafl::corpus::{Corpus, OnDiskCorpus, Testcase};
+
+    fn input(num_instructions: usize) -> IrInput {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        for i in 0..num_instructions {
+            builder.force_append(vec![], Operation::LoadTime(i as u64));
+        }
+        IrInput::new(builder.finalize().unwrap())
+    }
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzamoto-input-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn interrupted_write_keeps_previous_input() {
+        let dir = test_dir("interrupted");
+        let path = dir.join("input.ir");
+        input(3).to_file(&path).unwrap();
+
+        // A write that was interrupted before the rename only leaves a partial temporary file
+        let partial = input(10).ir().to_bytes_versioned();
+        std::fs::write(tmp_path(&path), &partial[..partial.len() / 2]).unwrap();
+        assert_eq!(IrInput::from_file(&path).unwrap().len(), 3);
+
+        // The next write replaces the stale temporary file
+        input(10).to_file(&path).unwrap();
+        assert_eq!(IrInput::from_file(&path).unwrap().len(), 10);
+        assert!(!tmp_path(&path).exists());
+
+        // Leftover temporary files are hidden, so they aren't mistaken for corpus entries
+        let tmp_name = tmp_path(&path)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        assert!(tmp_name.starts_with('.'));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// The corpus stores its entries with `Input::to_file` and reloads them (e.g. after eviction
+    /// from the cache) with `Input::from_file`, so entries written by the corpus and by the
+    /// stages/tools need to use the same encoding
+    #[test]
+    fn corpus_entries_use_the_input_encoding() {
+        let dir = test_dir("corpus");
+        let mut corpus = OnDiskCorpus::<IrInput>::new(&dir).unwrap();
+        let id = corpus.add(Testcase::from(input(3))).unwrap();
+        let path = corpus
+            .get(id)
+            .unwrap()
+            .borrow()
+            .file_path()
+            .clone()
+            .unwrap();
+
+        #[cfg(feature = "compress")]
+        assert!(
+            std::fs::read(&path)
+                .unwrap()
+                .starts_with(&fuzzamoto_ir::COMPRESSED_MAGIC)
+        );
+        #[cfg(not(feature = "compress"))]
+        assert!(
+            std::fs::read(&path)
+                .unwrap()
+                .starts_with(&fuzzamoto_ir::SCHEMA_MAGIC)
+        );
+        assert_eq!(IrInput::unparse(&path).len(), 3);
+
+        // Overwrite the entry like `IrMinimizerStage` does and reload it through the corpus
+        input(2).to_file(&path).unwrap();
+        let mut testcase = corpus.get(id).unwrap().borrow_mut();
+        *testcase.input_mut() = None;
+        corpus.load_input_into(&mut testcase).unwrap();
+        assert_eq!(testcase.input().as_ref().unwrap().len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn observed_state_is_stored_in_a_hidden_sidecar() {
+        let dir = test_dir("observed");
+        let path = dir.join("input.ir");
+        input(3).to_file(&path).unwrap();
+        assert!(matches!(
+            read_observed_state(&path),
+            Err(IrInputError::Io(_))
+        ));
+
+        let observed = ObservedNodeState {
+            peer_count: 2,
+            mempool_size: 1,
+            ..Default::default()
+        };
+        write_observed_state(&path, &observed).unwrap();
+        assert_eq!(read_observed_state(&path).unwrap(), observed);
+        assert_eq!(IrInput::from_file(&path).unwrap().len(), 3);
+
+        let sidecar_name = observed_state_path(&path)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        assert!(sidecar_name.starts_with('.'));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn from_file_reports_typed_errors() {
+        let dir = test_dir("errors");
+
+        let missing = read_program(&dir.join("missing.ir"));
+        assert!(matches!(missing, Err(IrInputError::Io(_))));
+
+        let truncated = dir.join("truncated.ir");
+        std::fs::write(&truncated, [5u8]).unwrap();
+        assert!(matches!(
+            read_program(&truncated),
+            Err(IrInputError::Schema(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
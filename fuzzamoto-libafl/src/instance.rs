@@ -1,18 +1,36 @@
-use std::{borrow::Cow, cell::RefCell, marker::PhantomData, process, rc::Rc, time::Duration};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
+use fuzzamoto::targets::BitcoinCoreTarget;
 use fuzzamoto_ir::{
     AddTxToBlockGenerator, AddrRelayGenerator, AddrRelayV2Generator, AdvanceTimeGenerator,
-    BlockGenerator, BlockTxnGenerator, BloomFilterAddGenerator, BloomFilterClearGenerator,
-    BloomFilterLoadGenerator, CombineMutator, CompactBlockGenerator, CompactFilterQueryGenerator,
-    GetAddrGenerator, GetDataGenerator, HeaderGenerator, InputMutator, InventoryGenerator,
-    LargeTxGenerator, LongChainGenerator, OneParentOneChildGenerator, OperationMutator, Program,
-    ReorgBlockGenerator, SendBlockGenerator, SendMessageGenerator, SingleTxGenerator,
-    TipBlockGenerator, TxoGenerator, WitnessGenerator, cutting::CuttingMinimizer,
-    instr_block::InstrBlockMinimizer, nopping::NoppingMinimizer,
+    AnchorSpendingGenerator, BitcoinStructureMutator, BlockFilterGenerator, BlockGenerator,
+    BlockInvalidityType, BlockTxnGenerator, BloomFilterAddGenerator, BloomFilterClearGenerator,
+    BloomFilterLoadGenerator, BloomFilterMerkleBlockGenerator, CFilterGenerator, CltvGenerator,
+    CombineMutator, CompactBlockGenerator, CompactBlockNegotiationGenerator,
+    CompactBlockNonceGenerator, CompactFilterQueryGenerator, ErlayGenerator, FeeFilterGenerator,
+    FeeRateBumpGenerator, GetAddrGenerator, GetBlockTxnGenerator, GetBlocksResponseGenerator,
+    GetDataGenerator, GetHeadersResponseGenerator, HavocMutator, HeaderGenerator, InputMutator,
+    InvalidBlockGenerator, InventoryGenerator, LargeTxGenerator, LongChainGenerator,
+    MempoolEvictionGenerator, MempoolRequestGenerator, MixedValidityBlockGenerator,
+    MultisigGenerator, NotFoundGenerator, OneParentOneChildGenerator, OperationMutator,
+    P2TRTxoGenerator, PingPongGenerator, Program, RawTxGenerator, ReorgBlockGenerator,
+    RuntimeTxInventoryGenerator, SendBlockGenerator, SendBlockMode, SendMessageGenerator,
+    ShuffleMutator, SingleTxGenerator, TimelockGenerator, TipBlockGenerator, TxoGenerator,
+    WitnessGenerator, cutting::CuttingMinimizer, instr_block::InstrBlockMinimizer,
+    nopping::NoppingMinimizer, topo_sort::TopologicalSortMinimizer,
 };
 
 use libafl::{
-    Error, NopFuzzer,
+    Error, HasMetadata, NopFuzzer,
     corpus::{CachedOnDiskCorpus, Corpus, CorpusId, OnDiskCorpus, Testcase},
     events::{
         ClientDescription, EventFirer, EventReceiver, EventRestarter, NopEventManager,
@@ -22,6 +40,7 @@ use libafl::{
     feedback_and, feedback_and_fast, feedback_or, feedback_or_fast,
     feedbacks::{ConstFeedback, CrashFeedback, HasObserverHandle, MaxMapFeedback, TimeFeedback},
     fuzzer::{Evaluator, Fuzzer, StdFuzzer},
+    inputs::Input,
     mutators::{ComposedByMutations, TuneableScheduledMutator},
     observers::{CanTrack, HitcountsMapObserver, StdMapObserver, StdOutObserver, TimeObserver},
     schedulers::{
@@ -46,12 +65,22 @@ use rand::{SeedableRng, rngs::SmallRng};
 use typed_builder::TypedBuilder;
 
 use crate::{
-    feedbacks::CaptureTimeoutFeedback,
+    feedbacks::{
+        CaptureTimeoutFeedback, ExecutionTimePredictionFeedback, RecvFeedback,
+        RecvMessageTypeFeedback,
+    },
     input::IrInput,
+    minimize::{self, minimize_crash},
     mutators::{IrGenerator, IrMutator, IrSpliceMutator, LibAflByteMutator},
     options::FuzzerOptions,
     schedulers::SupportedSchedulers,
-    stages::{IrMinimizerStage, ProbingStage, StabilityCheckStage, VerifyTimeoutsStage},
+    seeds::{InitialSeedGenerationMetadata, clear_seeds, generate_initial_seeds, has_seeds},
+    stages::{
+        AdaptiveWeightStage, CorpusHealthMetadata, CorpusHealthStage, CorpusSyncStage,
+        IrMinimizerStage, ProbingStage, ProgramHashStage, StabilityCheckStage, TimeoutsToVerify,
+        VerifyTimeoutsStage,
+    },
+    state_validator::FuzzerStateValidator,
 };
 
 #[cfg(feature = "bench")]
@@ -71,6 +100,26 @@ macro_rules! weighted_mutations {
 pub type ClientState =
     StdState<CachedOnDiskCorpus<IrInput>, IrInput, StdRand, OnDiskCorpus<IrInput>>;
 
+/// Inputs that consistently time out without crashing the node (see `VerifyTimeoutsStage`),
+/// stored separately from the crashes
+#[derive(Debug)]
+pub struct HangsCorpus<I> {
+    corpus: OnDiskCorpus<I>,
+}
+
+impl<I: Input> HangsCorpus<I> {
+    pub fn new(dir: PathBuf) -> Result<Self, Error> {
+        Ok(Self {
+            corpus: OnDiskCorpus::new(dir)?,
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub fn add(&mut self, input: I) -> Result<CorpusId, Error> {
+        self.corpus.add(Testcase::from(input))
+    }
+}
+
 #[derive(TypedBuilder)]
 pub struct Instance<'a, EM> {
     options: &'a FuzzerOptions,
@@ -112,6 +161,7 @@ where
         + SendExiting
         + EventReceiver<IrInput, ClientState>,
 {
+    #[tracing::instrument(skip_all, fields(core = self.client_description.core_id().0))]
     pub fn run(mut self, state: Option<ClientState>) -> Result<(), Error> {
         let parent_cpu_id = self
             .options
@@ -180,6 +230,20 @@ where
             ),
             // Time feedback
             TimeFeedback::new(&time_observer),
+            // Execution time prediction (has to come after the time feedback)
+            ExecutionTimePredictionFeedback::new(&time_observer),
+            // Response diversity feedback (the responses of new corpus entries are recorded even
+            // if it is disabled). Gated like the coverage feedback, as it records the responses
+            // it has seen when evaluating inputs.
+            feedback_and_fast!(
+                ConstFeedback::new(self.options.recv_feedback),
+                ConstFeedback::new(!self.options.static_corpus),
+                ConstFeedback::new(self.options.minimize_input.is_none()),
+                feedback_or!(
+                    RecvFeedback::new(&stdout_observer),
+                    RecvMessageTypeFeedback::new(&stdout_observer)
+                )
+            ),
         );
 
         let enable_capture_timeouts = Rc::new(RefCell::new(true));
@@ -191,25 +255,60 @@ where
                 enable_capture_timeouts,
                 Duration::from_millis(self.options.timeout as u64),
                 self.options.hang_multiple,
+                HangsCorpus::new(self.options.hangs_dir(self.client_description.core_id()))?,
+                // The target runs inside of the Nyx VM and can't be reached from here, confirmed
+                // timeouts are therefore kept as solutions
+                None::<Arc<Mutex<BitcoinCoreTarget>>>,
             )),
         );
 
         // A feedback to choose if an input is a solution or not
         let mut objective = feedback_and!(
+            // The timeout feedback comes first, it records the exit kinds of re-runs for
+            // `VerifyTimeoutsStage`
             feedback_or_fast!(
-                CrashFeedback::new(),
                 feedback_and!(
                     ConstFeedback::new(!self.options.ignore_hangs),
                     capture_timeout_feedback,
-                )
+                ),
+                CrashFeedback::new()
             ),
             // Only store objective if it triggers new coverage (compared to other solutions)
             MaxMapFeedback::with_name("mapfeedback_metadata_objective", &trace_observer)
         );
 
+        // Discard the restored state if its corpus or crashes directory was modified in the
+        // meantime (e.g. by external corpus pruning), the corpus is then reinitialised from the
+        // stashed corpus directory
+        let core_id = self.client_description.core_id();
+        let validator = FuzzerStateValidator::new(
+            self.options.queue_dir(core_id),
+            self.options.crashes_dir(core_id),
+        );
+        let mut stale_corpus_dir = None;
+        let state = match state {
+            Some(state) => match validator.validate(state.corpus()) {
+                Ok(()) => Some(state),
+                Err(e) => {
+                    log::warn!("Restored fuzzer state is stale ({}), reinitialising", e);
+                    stale_corpus_dir = Some(validator.stash_corpus()?);
+                    None
+                }
+            },
+            None => None,
+        };
+
         // If not restarting, create a State from scratch
         let mut state = match state {
-            Some(x) => x,
+            Some(mut x) => {
+                if self.options.on_restart_clear_timeouts
+                    && let Ok(timeouts) = x.metadata_mut::<TimeoutsToVerify>()
+                {
+                    log::info!("Clearing {} timeouts to verify", timeouts.count());
+                    *timeouts = TimeoutsToVerify::new();
+                }
+                x
+            }
             None => {
                 StdState::new(
                     // RNG
@@ -279,22 +378,44 @@ where
         let full_program_context: fuzzamoto_ir::FullProgramContext =
             postcard::from_bytes(&bytes).expect("could not deser ir context");
 
-        if self
-            .options
-            .input_dir()
-            .read_dir()
-            .unwrap()
-            .next()
-            .is_none()
-        {
-            let initial_input = IrInput::new(Program::unchecked_new(
-                full_program_context.context.clone(),
-                vec![],
-            ));
-            let bytes = postcard::to_allocvec(&initial_input).unwrap();
-
-            let file_path = self.options.input_dir().join("initial_input");
-            std::fs::write(&file_path, bytes).unwrap();
+        // Only the first client populates the corpus with generated seeds and only when starting
+        // from scratch (i.e. not when restarting after a crash)
+        let input_dir = self.options.input_dir();
+        let is_first_client = self.client_description.id() == 0;
+        let generate_seeds = (self.options.static_corpus || self.options.force_regenerate_seeds)
+            && is_first_client
+            && state.must_load_initial_inputs();
+        if generate_seeds && self.options.force_regenerate_seeds {
+            clear_seeds(&input_dir)?;
+        }
+
+        if !is_first_client {
+            // The first client populates an empty input directory, the other clients wait for it
+            // instead of racing it
+            if !has_seeds(&input_dir)? {
+                println!("Waiting for the first client to populate {input_dir:?}");
+            }
+            while !has_seeds(&input_dir)? {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        } else if !has_seeds(&input_dir)? {
+            if generate_seeds {
+                let mut seed_rng = SmallRng::seed_from_u64(state.rand_mut().next());
+                let generated = generate_initial_seeds(
+                    &input_dir,
+                    &full_program_context,
+                    self.options.initial_seeds,
+                    &mut seed_rng,
+                )?;
+                println!("Generated {generated} initial seeds in {input_dir:?}");
+                state.add_metadata(InitialSeedGenerationMetadata { generated });
+            } else {
+                let initial_input = IrInput::new(Program::unchecked_new(
+                    full_program_context.context.clone(),
+                    vec![],
+                ));
+                initial_input.to_file(input_dir.join("initial_input"))?;
+            }
         }
 
         let rng = SmallRng::seed_from_u64(state.rand_mut().next());
@@ -309,6 +430,22 @@ where
                 1000.0,
                 IrMutator::new(OperationMutator::new(LibAflByteMutator::new()), rng.clone())
             ),
+            (
+                30.0,
+                IrMutator::new(HavocMutator::new(LibAflByteMutator::new()), rng.clone())
+            ),
+            (
+                25.0,
+                IrMutator::new(
+                    BitcoinStructureMutator::new(LibAflByteMutator::new()),
+                    rng.clone()
+                )
+            ),
+            (50.0, IrMutator::new(ShuffleMutator::new(), rng.clone())),
+            (
+                50.0,
+                IrMutator::new(ShuffleMutator::swap_adjacent_independent(), rng.clone())
+            ),
             (
                 100.0,
                 IrGenerator::new(
@@ -335,6 +472,7 @@ where
                 40.0,
                 IrGenerator::new(SendMessageGenerator::default(), rng.clone())
             ),
+            (10.0, IrGenerator::new(RawTxGenerator, rng.clone())),
             (
                 50.0,
                 IrGenerator::new(SingleTxGenerator::default(), rng.clone())
@@ -343,6 +481,11 @@ where
                 50.0,
                 IrGenerator::new(LongChainGenerator::default(), rng.clone())
             ),
+            (
+                15.0,
+                IrGenerator::new(MempoolEvictionGenerator, rng.clone())
+            ),
+            (20.0, IrGenerator::new(MempoolRequestGenerator, rng.clone())),
             (
                 50.0,
                 IrGenerator::new(LargeTxGenerator::default(), rng.clone())
@@ -351,6 +494,14 @@ where
                 50.0,
                 IrGenerator::new(OneParentOneChildGenerator::default(), rng.clone())
             ),
+            (
+                30.0,
+                IrGenerator::new(FeeRateBumpGenerator::default(), rng.clone())
+            ),
+            (20.0, IrGenerator::new(FeeFilterGenerator, rng.clone())),
+            (10.0, IrGenerator::new(ErlayGenerator, rng.clone())),
+            (20.0, IrGenerator::new(AnchorSpendingGenerator, rng.clone())),
+            (20.0, IrGenerator::new(MultisigGenerator, rng.clone())),
             (
                 20.0,
                 IrGenerator::new(
@@ -358,9 +509,15 @@ where
                     rng.clone()
                 )
             ),
+            (10.0, IrGenerator::new(P2TRTxoGenerator, rng.clone())),
             (20.0, IrGenerator::new(WitnessGenerator::new(), rng.clone())),
             (20.0, IrGenerator::new(InventoryGenerator, rng.clone())),
             (20.0, IrGenerator::new(GetDataGenerator, rng.clone())),
+            (10.0, IrGenerator::new(NotFoundGenerator, rng.clone())),
+            (
+                10.0,
+                IrGenerator::new(RuntimeTxInventoryGenerator, rng.clone())
+            ),
             (
                 50.0,
                 IrGenerator::new(BlockGenerator::default(), rng.clone())
@@ -372,7 +529,73 @@ where
                     rng.clone()
                 )
             ),
-            (50.0, IrGenerator::new(SendBlockGenerator, rng.clone())),
+            (
+                20.0,
+                IrGenerator::new(
+                    CFilterGenerator::new(full_program_context.block_filters.clone()),
+                    rng.clone()
+                )
+            ),
+            (
+                15.0,
+                IrGenerator::new(
+                    BlockFilterGenerator::new(
+                        full_program_context.headers.clone(),
+                        full_program_context.block_filters.clone()
+                    ),
+                    rng.clone()
+                )
+            ),
+            (
+                50.0,
+                IrGenerator::new(SendBlockGenerator::default(), rng.clone())
+            ),
+            (
+                25.0,
+                IrGenerator::new(
+                    SendBlockGenerator::new(true, SendBlockMode::BlockBeforeHeaders),
+                    rng.clone()
+                )
+            ),
+            (
+                5.0,
+                IrGenerator::new(
+                    InvalidBlockGenerator::new(BlockInvalidityType::WrongMerkleRoot),
+                    rng.clone()
+                )
+            ),
+            (
+                5.0,
+                IrGenerator::new(
+                    InvalidBlockGenerator::new(BlockInvalidityType::InvalidProofOfWork),
+                    rng.clone()
+                )
+            ),
+            (
+                5.0,
+                IrGenerator::new(
+                    InvalidBlockGenerator::new(BlockInvalidityType::DoubleSpend),
+                    rng.clone()
+                )
+            ),
+            (
+                5.0,
+                IrGenerator::new(
+                    InvalidBlockGenerator::new(BlockInvalidityType::ExcessiveCoinbaseValue),
+                    rng.clone()
+                )
+            ),
+            (
+                5.0,
+                IrGenerator::new(
+                    InvalidBlockGenerator::new(BlockInvalidityType::DuplicateTransactions),
+                    rng.clone()
+                )
+            ),
+            (
+                10.0,
+                IrGenerator::new(MixedValidityBlockGenerator::default(), rng.clone())
+            ),
             (50.0, IrGenerator::new(AddTxToBlockGenerator, rng.clone())),
             (
                 10.0,
@@ -387,6 +610,10 @@ where
                 20.0,
                 IrGenerator::new(BloomFilterClearGenerator, rng.clone())
             ),
+            (
+                10.0,
+                IrGenerator::new(BloomFilterMerkleBlockGenerator, rng.clone())
+            ),
             (
                 20.0,
                 IrGenerator::new(AddrRelayGenerator::default(), rng.clone())
@@ -396,14 +623,46 @@ where
                 IrGenerator::new(AddrRelayV2Generator::default(), rng.clone())
             ),
             (10.0, IrGenerator::new(GetAddrGenerator, rng.clone())),
+            (10.0, IrGenerator::new(PingPongGenerator, rng.clone())),
+            (
+                20.0,
+                IrGenerator::new(
+                    GetBlocksResponseGenerator::new(full_program_context.headers.clone()),
+                    rng.clone()
+                )
+            ),
+            (
+                20.0,
+                IrGenerator::new(
+                    GetHeadersResponseGenerator::new(full_program_context.headers.clone()),
+                    rng.clone()
+                )
+            ),
             (
                 200.0,
                 IrGenerator::new(CompactBlockGenerator::default(), rng.clone())
             ),
+            (
+                20.0,
+                IrGenerator::new(CompactBlockNonceGenerator, rng.clone())
+            ),
+            (
+                20.0,
+                IrGenerator::new(CompactBlockNegotiationGenerator, rng.clone())
+            ),
             (
                 200.0,
                 IrGenerator::new(BlockTxnGenerator::default(), rng.clone())
             ),
+            (25.0, IrGenerator::new(GetBlockTxnGenerator, rng.clone())),
+            (15.0, IrGenerator::new(TimelockGenerator, rng.clone())),
+            (
+                15.0,
+                IrGenerator::new(
+                    CltvGenerator::new(full_program_context.headers.clone()),
+                    rng.clone()
+                )
+            ),
         ];
         log_weights(
             &self.options,
@@ -412,6 +671,15 @@ where
             &weights,
         )?;
 
+        let adaptive_weights = AdaptiveWeightStage::new(
+            mutations
+                .names()
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            weights.to_vec(),
+        );
+
         let tuneable_mutator = TuneableScheduledMutator::new(&mut state, mutations);
         let sum = weights.iter().sum::<f32>();
         debug_assert_eq!(tuneable_mutator.mutations().len(), weights.len());
@@ -430,6 +698,10 @@ where
         let mutator = tuneable_mutator;
 
         let minimizing_crash = self.options.minimize_input.is_some();
+        let minimize_deadline = self
+            .options
+            .minimize_timeout_secs
+            .map(|secs| Instant::now() + Duration::from_secs(secs));
 
         // Counter holding the number of successful minimizations in the last round
         let continue_minimizing = RefCell::new(1u64);
@@ -444,13 +716,20 @@ where
             }),
             WhileStage::new(
                 |_, _, _, _| Ok((!self.options.static_corpus || minimizing_crash)
-                    && *continue_minimizing.borrow() > 0),
+                    && *continue_minimizing.borrow() > 0
+                    && !minimize_deadline.is_some_and(|deadline| Instant::now() >= deadline)),
                 tuple_list!(
                     ClosureStage::new(|_a: &mut _, _b: &mut _, _c: &mut _, _d: &mut _| {
                         // Reset the minimization counter
                         *continue_minimizing.borrow_mut() = 0;
                         Ok(())
                     }),
+                    IrMinimizerStage::<TopologicalSortMinimizer, _, _>::new(
+                        trace_handle.clone(),
+                        200,
+                        minimizing_crash,
+                        &continue_minimizing
+                    ),
                     IrMinimizerStage::<CuttingMinimizer, _, _>::new(
                         trace_handle.clone(),
                         200,
@@ -471,16 +750,98 @@ where
                     ),
                 )
             ),
+            ProgramHashStage,
             stability,
             probing,
+            IfStage::new(
+                |_, _, _, _| Ok(
+                    !self.options.disable_adaptive_weights && self.options.minimize_input.is_none()
+                ),
+                tuple_list!(adaptive_weights)
+            ),
             IfStage::new(
                 |_, _, _, _| Ok(self.options.minimize_input.is_none()),
                 tuple_list!(TuneableMutationalStage::new(&mut state, mutator))
             ),
+            IfStage::new(
+                |_, _, _, _| Ok(
+                    !self.options.disable_corpus_sync && self.options.minimize_input.is_none()
+                ),
+                tuple_list!(CorpusSyncStage::new(
+                    PathBuf::from(&self.options.output),
+                    self.options.queue_dir(self.client_description.core_id()),
+                    self.options.corpus_sync_interval,
+                ))
+            ),
+            IfStage::new(
+                |_, _, _, _| Ok(self.options.report_interval_secs.is_some()),
+                tuple_list!(CorpusHealthStage::new(Duration::from_secs(
+                    self.options.report_interval_secs.unwrap_or_default()
+                )))
+            ),
             timeout_verify_stage,
             bench_stats_stage,
         );
-        self.fuzz(&mut state, &mut fuzzer, &mut executor, &mut stages)
+        if let Some(minimize_input) = self.options.minimize_input.clone() {
+            return self.minimize(
+                &minimize_input,
+                minimize_deadline,
+                &mut state,
+                &mut fuzzer,
+                &mut executor,
+                &mut stages,
+            );
+        }
+        self.fuzz(
+            &mut state,
+            &mut fuzzer,
+            &mut executor,
+            &mut stages,
+            stale_corpus_dir,
+        )
+    }
+
+    /// Minimize the crashing input at `input_path` by running the stages on it until they no
+    /// longer find a smaller crashing input
+    fn minimize<Z, E, ST>(
+        &mut self,
+        input_path: &Path,
+        deadline: Option<Instant>,
+        state: &mut ClientState,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        stages: &mut ST,
+    ) -> Result<(), Error>
+    where
+        Z: Fuzzer<E, EM, IrInput, ClientState, ST>,
+        ST: StagesTuple<E, EM, ClientState, Z>,
+    {
+        let output = self
+            .options
+            .minimize_output
+            .clone()
+            .unwrap_or_else(|| minimize::default_output(input_path));
+
+        let input = IrInput::unparse(&input_path.to_path_buf());
+        let minimized = minimize_crash(input, deadline, |input| {
+            let id = state.corpus_mut().add(Testcase::from(input))?;
+            fuzzer.fuzz_one(stages, executor, state, &mut self.mgr)?;
+            let reduced = state.corpus().cloned_input_for_id(id)?;
+
+            // The minimization stages skip testcases that were scheduled before, so every round
+            // starts from a fresh corpus entry
+            state.corpus_mut().remove(id)?;
+            *state.corpus_mut().current_mut() = None;
+            Ok(reduced)
+        })?;
+        minimized.to_file(&output)?;
+
+        println!(
+            "[MINIMIZE] minimized input ({} instructions) written to {:?}",
+            minimized.len(),
+            output
+        );
+        Ok(())
     }
 
     fn fuzz<Z, E, ST>(
@@ -489,18 +850,18 @@ where
         fuzzer: &mut Z,
         executor: &mut E,
         stages: &mut ST,
+        stale_corpus_dir: Option<PathBuf>,
     ) -> Result<(), Error>
     where
         Z: Fuzzer<E, EM, IrInput, ClientState, ST> + Evaluator<E, EM, IrInput, ClientState>,
         ST: StagesTuple<E, EM, ClientState, Z>,
     {
-        let corpus_dirs = [self.options.input_dir()];
+        let mut corpus_dirs = vec![self.options.input_dir()];
+        corpus_dirs.extend(stale_corpus_dir.clone());
 
         if state.must_load_initial_inputs() {
-            if let Some(minimize_input) = &self.options.minimize_input {
-                let input = IrInput::unparse(minimize_input);
-                state.corpus_mut().add(Testcase::from(input)).unwrap();
-            } else if self.options.static_corpus {
+            // Entries of a stale state are reloaded unconditionally, they were interesting before
+            if self.options.static_corpus || stale_corpus_dir.is_some() {
                 state
                     .load_initial_inputs_forced(fuzzer, executor, &mut self.mgr, &corpus_dirs)
                     .unwrap_or_else(|_| {
@@ -522,7 +883,16 @@ where
                     .map(|idx| corpus.nth_from_all(idx + corpus.count()))
                     .collect();
                 for id in disabled_corpus_ids.iter() {
+                    let file_path = corpus.get_from_all(*id)?.borrow().file_path().clone();
                     let _ = corpus.remove(*id);
+                    // Removing the entry from the corpus does not remove its file, which would
+                    // otherwise be loaded again on the next start
+                    if let Some(path) = file_path
+                        && let Err(e) = std::fs::remove_file(&path)
+                        && e.kind() != std::io::ErrorKind::NotFound
+                    {
+                        log::warn!("Failed to remove pruned input {}: {}", path.display(), e);
+                    }
                 }
                 println!(
                     "Pruned {} disabled inputs from corpus",
@@ -531,11 +901,19 @@ where
             }
 
             println!("We imported {} inputs from disk", state.corpus().count());
+
+            if let Some(dir) = stale_corpus_dir
+                && let Err(e) = std::fs::remove_dir_all(&dir)
+            {
+                log::warn!("Failed to remove stale corpus {}: {}", dir.display(), e);
+            }
+
+            let health = CorpusHealthMetadata::compute(state.corpus())?;
+            println!("[CORPUS] {}", health);
+            state.add_metadata(health);
         }
 
-        if self.options.minimize_input.is_some() {
-            fuzzer.fuzz_one(stages, executor, state, &mut self.mgr)?;
-        } else if let Some(iters) = self.options.iterations {
+        if let Some(iters) = self.options.iterations {
             fuzzer.fuzz_loop_for(stages, executor, state, &mut self.mgr, iters)?;
 
             // It's important, that we store the state before restarting!
@@ -9,6 +9,8 @@ mod input;
 #[cfg(target_os = "linux")]
 mod instance;
 #[cfg(target_os = "linux")]
+mod minimize;
+#[cfg(target_os = "linux")]
 mod monitor;
 #[cfg(target_os = "linux")]
 mod mutators;
@@ -17,14 +19,18 @@ mod options;
 #[cfg(target_os = "linux")]
 mod schedulers;
 #[cfg(target_os = "linux")]
+mod seeds;
+#[cfg(target_os = "linux")]
 mod stages;
+#[cfg(target_os = "linux")]
+mod state_validator;
 
 #[cfg(target_os = "linux")]
 use crate::fuzzer::Fuzzer;
 
 #[cfg(target_os = "linux")]
 pub fn main() {
-    env_logger::init();
+    tracing_subscriber::fmt::init();
     Fuzzer::new().fuzz().unwrap();
 }
 
@@ -0,0 +1,113 @@
+//! Standalone crash minimization (`--minimize-input`)
+
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use libafl::Error;
+use libafl_bolts::HasLen;
+
+use crate::input::IrInput;
+
+/// Path the minimized input is written to by default: `<input>.min`, such that the crashing input
+/// is kept
+pub fn default_output(input_path: &Path) -> PathBuf {
+    let mut path = input_path.as_os_str().to_owned();
+    path.push(".min");
+    PathBuf::from(path)
+}
+
+/// Repeatedly minimize a crashing `input` until no further reduction is possible or the
+/// `deadline` has passed.
+///
+/// `minimize_once` runs one minimization round on the given input and returns the smallest
+/// crashing input it found. Nothing is written to disk, the caller stores the returned input.
+pub fn minimize_crash<F>(
+    mut input: IrInput,
+    deadline: Option<Instant>,
+    mut minimize_once: F,
+) -> Result<IrInput, Error>
+where
+    F: FnMut(IrInput) -> Result<IrInput, Error>,
+{
+    loop {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            println!("[MINIMIZE] timeout reached");
+            break;
+        }
+
+        let before = input.len();
+        let reduced = minimize_once(input.clone())?;
+        if reduced.len() >= before {
+            break;
+        }
+
+        println!(
+            "[MINIMIZE] {} instructions -> {} instructions",
+            before,
+            reduced.len()
+        );
+        input = reduced;
+    }
+
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::{Operation, ProgramBuilder, ProgramContext};
+
+    fn input(num_instructions: usize) -> IrInput {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        for i in 0..num_instructions {
+            builder.force_append(vec![], Operation::LoadTime(i as u64));
+        }
+        IrInput::new(builder.finalize().unwrap())
+    }
+
+    /// Mock executor: inputs "crash" as long as they contain the first three instructions. Each
+    /// round only manages to remove a single instruction.
+    fn remove_last_instruction(mut input: IrInput) -> Result<IrInput, Error> {
+        if input.len() > 3 {
+            input.ir_mut().instructions.pop();
+        }
+        Ok(input)
+    }
+
+    #[test]
+    fn minimizes_until_no_further_reduction() {
+        let mut rounds = 0;
+        let minimized = minimize_crash(input(10), None, |input| {
+            rounds += 1;
+            remove_last_instruction(input)
+        })
+        .unwrap();
+
+        assert_eq!(minimized.len(), 3);
+        // Seven reductions and a final round without progress
+        assert_eq!(rounds, 8);
+    }
+
+    #[test]
+    fn output_defaults_to_min_suffix() {
+        assert_eq!(
+            default_output(Path::new("crashes/abc")),
+            PathBuf::from("crashes/abc.min")
+        );
+    }
+
+    #[test]
+    fn stops_at_deadline() {
+        let minimized = minimize_crash(input(10), Some(Instant::now()), |_| {
+            panic!("no round should run after the deadline")
+        })
+        .unwrap();
+        assert_eq!(minimized.len(), 10);
+    }
+}
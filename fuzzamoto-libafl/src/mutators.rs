@@ -17,7 +17,10 @@ use libafl_bolts::{
 };
 use rand::RngCore;
 
-use crate::{input::IrInput, stages::RuntimeMetadata};
+use crate::{
+    input::IrInput,
+    stages::{IrGeneratorWeights, RuntimeMetadata},
+};
 
 /// Instruction limit for mutated IR programs
 const MAX_INSTRUCTIONS: usize = 4096;
@@ -87,9 +90,10 @@ where
     }
 
     #[inline]
-    fn post_exec(&mut self, state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
         let rt_data = runtime_metadata_mut(state);
         rt_data.reset_idx();
+        rt_data.finish_generation(new_corpus_id);
 
         Ok(())
     }
@@ -168,9 +172,10 @@ where
     }
 
     #[inline]
-    fn post_exec(&mut self, state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
         let rt_data = runtime_metadata_mut(state);
         rt_data.reset_idx();
+        rt_data.finish_generation(new_corpus_id);
 
         Ok(())
     }
@@ -240,13 +245,12 @@ where
 
         let prev_var_count = builder.variable_count();
 
-        if self
-            .generator
-            .generate(&mut builder, &mut self.rng, tc_data.as_deref())
-            .is_err()
-        {
+        let Ok(event) =
+            self.generator
+                .generate_tracked(&mut builder, &mut self.rng, tc_data.as_deref())
+        else {
             return Ok(MutationResult::Skipped);
-        }
+        };
 
         let second_half = Program::unchecked_new(
             input.ir().context.clone(),
@@ -269,15 +273,27 @@ where
             return Ok(MutationResult::Skipped);
         }
 
+        state
+            .metadata_or_insert_with(IrGeneratorWeights::default)
+            .record(
+                &self.name,
+                new_program
+                    .instructions
+                    .len()
+                    .saturating_sub(input.ir().instructions.len()),
+            );
+
         *input.ir_mut() = new_program;
+        runtime_metadata_mut(state).record_generation(current_id, event);
 
         Ok(MutationResult::Mutated)
     }
 
     #[inline]
-    fn post_exec(&mut self, state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
         let rt_data = runtime_metadata_mut(state);
         rt_data.reset_idx();
+        rt_data.finish_generation(new_corpus_id);
 
         Ok(())
     }
@@ -64,11 +64,46 @@ pub struct FuzzerOptions {
 
     #[arg(
         long,
-        help = "Remove disabled corpus entries after intial load",
+        help = "Number of seeds to generate if the corpus is empty in static corpus mode",
+        env = "FUZZAMOTO_INITIAL_SEEDS",
+        default_value_t = 100
+    )]
+    pub initial_seeds: usize,
+
+    #[arg(
+        long,
+        help = "Wipe the input corpus and regenerate initial seeds",
+        default_value_t = false
+    )]
+    pub force_regenerate_seeds: bool,
+
+    #[arg(
+        long,
+        help = "Remove disabled corpus entries (and their files) after intial load",
         default_value_t = false
     )]
     pub prune_disabled: bool,
 
+    #[arg(
+        long,
+        help = "Drop the timeouts queued for verification when restarting (e.g. after a crash)",
+        default_value_t = false
+    )]
+    pub on_restart_clear_timeouts: bool,
+
+    #[arg(
+        long,
+        help = "Add inputs to the corpus that make the node respond with novel message types or sizes (requires a scenario built with force_send_and_ping)",
+        default_value_t = false
+    )]
+    pub recv_feedback: bool,
+
+    #[arg(
+        long,
+        help = "Interval in seconds at which the corpus health report is printed while fuzzing"
+    )]
+    pub report_interval_secs: Option<u64>,
+
     #[arg(
         long,
         help = "Pushover token",
@@ -77,6 +112,27 @@ pub struct FuzzerOptions {
     )]
     pub pushover_token: Option<String>,
 
+    #[arg(
+        long,
+        help = "Don't import the queue entries of the other fuzzer clients",
+        default_value_t = false
+    )]
+    pub disable_corpus_sync: bool,
+
+    #[arg(
+        long,
+        help = "Don't adapt the generator weights to the number of instructions the generators add",
+        default_value_t = false
+    )]
+    pub disable_adaptive_weights: bool,
+
+    #[arg(
+        long,
+        help = "Number of executions between two imports of the other clients' queue entries",
+        default_value_t = 5000
+    )]
+    pub corpus_sync_interval: u64,
+
     #[arg(
         long,
         help = "Pushover user",
@@ -111,6 +167,20 @@ pub struct FuzzerOptions {
     #[arg(short = 'm', long, help = "An input to minimize")]
     pub minimize_input: Option<PathBuf>,
 
+    #[arg(
+        long,
+        requires = "minimize_input",
+        help = "Stop minimizing after this many seconds"
+    )]
+    pub minimize_timeout_secs: Option<u64>,
+
+    #[arg(
+        long,
+        requires = "minimize_input",
+        help = "Path the minimized input is written to once minimization is done (defaults to <input>.min)"
+    )]
+    pub minimize_output: Option<PathBuf>,
+
     #[arg(
         long,
         value_delimiter = ',',
@@ -203,6 +273,12 @@ impl FuzzerOptions {
         dir
     }
 
+    pub fn hangs_dir(&self, core_id: CoreId) -> PathBuf {
+        let mut dir = self.output_dir(core_id).clone();
+        dir.push("hangs");
+        dir
+    }
+
     /// Returns the weight for a mutator/generator, or 0.0 if it's disabled
     pub fn mutator_weight<R: RngCore>(&self, name: &str, weight: f32, rng: &mut R) -> f32 {
         let weight = if self.swarm < 1.0 {
@@ -0,0 +1,121 @@
+//! Automatic population of the initial corpus with generated seeds.
+
+use std::path::Path;
+
+use fuzzamoto_ir::{FullProgramContext, default_generators, generate_program};
+use libafl::{Error, inputs::Input};
+use libafl_bolts::impl_serdeany;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::input::IrInput;
+
+/// Max number of generator invocations per generated seed
+const SEED_GENERATOR_ITERATIONS: usize = 20;
+
+/// Records how many seeds were generated to populate an empty initial corpus
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct InitialSeedGenerationMetadata {
+    pub generated: usize,
+}
+impl_serdeany!(InitialSeedGenerationMetadata);
+
+/// Remove all files from the corpus directory `dir`
+pub fn clear_seeds(dir: &Path) -> Result<(), Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check if the corpus directory `dir` contains any inputs (hidden files, e.g. inputs that are
+/// still being written, are ignored)
+pub fn has_seeds(dir: &Path) -> Result<bool, Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && !entry.file_name().to_string_lossy().starts_with('.') {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Generate `count` seeds into the corpus directory `dir` using all default generators.
+///
+/// Seeds are written atomically (see `IrInput::to_file`). Returns the number of seeds written.
+pub fn generate_initial_seeds<R: RngCore>(
+    dir: &Path,
+    context: &FullProgramContext,
+    count: usize,
+    rng: &mut R,
+) -> Result<usize, Error> {
+    std::fs::create_dir_all(dir)?;
+
+    let generators = default_generators(context);
+    for index in 0..count {
+        let (program, _) = generate_program(
+            &context.context,
+            &generators,
+            SEED_GENERATOR_ITERATIONS,
+            rng,
+        );
+        IrInput::new(program).to_file(dir.join(format!("seed_{index:05}")))?;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::ProgramContext;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn empty_corpus_is_populated() {
+        let dir = std::env::temp_dir().join(format!("fuzzamoto-seeds-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        clear_seeds(&dir).unwrap();
+        assert!(!has_seeds(&dir).unwrap());
+        // Inputs that are still being written don't count
+        std::fs::write(dir.join(".seed_00000.tmp"), b"").unwrap();
+        assert!(!has_seeds(&dir).unwrap());
+
+        let context = FullProgramContext {
+            context: ProgramContext {
+                num_nodes: 1,
+                num_connections: 2,
+                timestamp: 1_296_688_602,
+            },
+            txos: vec![],
+            headers: vec![],
+            block_filters: vec![],
+        };
+        let mut rng = SmallRng::seed_from_u64(0);
+        let generated = generate_initial_seeds(&dir, &context, 10, &mut rng).unwrap();
+        assert_eq!(generated, 10);
+
+        assert!(has_seeds(&dir).unwrap());
+        let seeds: Vec<_> = dir
+            .read_dir()
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|path| !path.file_name().unwrap().to_string_lossy().starts_with('.'))
+            .collect();
+        assert_eq!(seeds.len(), 10);
+        for seed in &seeds {
+            let input = IrInput::unparse(seed);
+            assert_eq!(input.ir().context, context.context);
+        }
+
+        // Regenerating wipes the previous seeds
+        clear_seeds(&dir).unwrap();
+        generate_initial_seeds(&dir, &context, 3, &mut rng).unwrap();
+        assert_eq!(dir.read_dir().unwrap().count(), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
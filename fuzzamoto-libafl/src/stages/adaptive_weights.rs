@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+
+use libafl::{
+    HasMetadata,
+    mutators::TuneableScheduledMutatorMetadata,
+    stages::{Restartable, Stage},
+    state::HasExecutions,
+};
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+
+/// Number of executions between two weight updates of the `AdaptiveWeightStage`
+const ADAPTIVE_WEIGHT_INTERVAL: u64 = 10_000;
+/// Number of executions after which the `AdaptiveWeightStage` switches from corpus building to
+/// exploitation
+const EXPLOITATION_EXECUTIONS: u64 = 1_000_000;
+/// Bounds of the factor a generator's weight is scaled by
+const MIN_WEIGHT_FACTOR: f32 = 0.25;
+const MAX_WEIGHT_FACTOR: f32 = 4.0;
+
+/// Number of invocations and added instructions of a generator
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeneratorStats {
+    pub invocations: u64,
+    pub instructions_added: u64,
+}
+
+impl GeneratorStats {
+    /// Average number of instructions added per invocation
+    pub fn average_increase(&self) -> f64 {
+        self.instructions_added as f64 / self.invocations.max(1) as f64
+    }
+}
+
+/// Instruction count increase per generator invocation (recorded by `IrGenerator`), used by the
+/// `AdaptiveWeightStage` to tune the generator weights
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IrGeneratorWeights {
+    pub generators: BTreeMap<String, GeneratorStats>,
+}
+impl_serdeany!(IrGeneratorWeights);
+
+impl IrGeneratorWeights {
+    /// Record a successful invocation of `generator` that added `instructions` instructions
+    pub fn record(&mut self, generator: &str, instructions: usize) {
+        let stats = self.generators.entry(generator.to_string()).or_default();
+        stats.invocations += 1;
+        stats.instructions_added += instructions as u64;
+    }
+
+    /// Scale the `base_weights` of the mutations `names`: generators producing smaller programs
+    /// than the average generator get higher weights while building the corpus, generators
+    /// producing larger programs get higher weights during exploitation. The weights of mutators
+    /// and generators without any recorded invocations are kept as is.
+    pub fn adapt(&self, names: &[String], base_weights: &[f32], exploitation: bool) -> Vec<f32> {
+        let mean = if self.generators.is_empty() {
+            0.0
+        } else {
+            self.generators
+                .values()
+                .map(GeneratorStats::average_increase)
+                .sum::<f64>()
+                / self.generators.len() as f64
+        };
+
+        names
+            .iter()
+            .zip(base_weights.iter())
+            .map(|(name, weight)| {
+                let Some(stats) = self.generators.get(name) else {
+                    return *weight;
+                };
+                let ratio = ((stats.average_increase() + 1.0) / (mean + 1.0)) as f32;
+                let factor = if exploitation { ratio } else { 1.0 / ratio };
+                weight * factor.clamp(MIN_WEIGHT_FACTOR, MAX_WEIGHT_FACTOR)
+            })
+            .collect()
+    }
+}
+
+/// Set the mutation probabilities of the `TuneableScheduledMutator` to the normalised `weights`
+/// (like `TuneableScheduledMutator::set_mutation_probabilities`, which is not accessible once the
+/// mutator is owned by its stage)
+fn set_mutation_probabilities<S: HasMetadata>(
+    state: &mut S,
+    weights: &[f32],
+) -> Result<(), libafl::Error> {
+    let sum = weights.iter().sum::<f32>();
+    if sum <= 0.0 {
+        return Ok(());
+    }
+
+    let mut cumulative = 0.0;
+    let mut probabilities: Vec<f32> = weights
+        .iter()
+        .map(|weight| {
+            cumulative += weight / sum;
+            cumulative
+        })
+        .collect();
+    // Guard against rounding errors, the last mutation has to be selectable by any coin flip
+    if let Some(last) = probabilities.last_mut() {
+        *last = 1.0;
+    }
+
+    let metadata = state.metadata_mut::<TuneableScheduledMutatorMetadata>()?;
+    metadata.mutation_probabilities_cumulative = probabilities;
+    Ok(())
+}
+
+/// `AdaptiveWeightStage` periodically re-weights the generators of the `TuneableScheduledMutator`
+/// based on the `IrGeneratorWeights` (see `IrGeneratorWeights::adapt`)
+#[derive(Debug)]
+pub struct AdaptiveWeightStage {
+    names: Vec<String>,
+    base_weights: Vec<f32>,
+    last_update: u64,
+}
+
+impl AdaptiveWeightStage {
+    /// Create a new `AdaptiveWeightStage` for the mutations `names` (in the order of the
+    /// mutator's tuple), with their initial weights `base_weights`
+    pub fn new(names: Vec<String>, base_weights: Vec<f32>) -> Self {
+        Self {
+            names,
+            base_weights,
+            last_update: 0,
+        }
+    }
+}
+
+impl<S> Restartable<S> for AdaptiveWeightStage {
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, libafl::Error> {
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), libafl::Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, S, Z> Stage<E, EM, S, Z> for AdaptiveWeightStage
+where
+    S: HasMetadata + HasExecutions,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), libafl::Error> {
+        let executions = *state.executions();
+        if executions.saturating_sub(self.last_update) < ADAPTIVE_WEIGHT_INTERVAL {
+            return Ok(());
+        }
+        self.last_update = executions;
+
+        let Ok(generator_weights) = state.metadata::<IrGeneratorWeights>() else {
+            return Ok(());
+        };
+        let exploitation = executions >= EXPLOITATION_EXECUTIONS;
+        let weights = generator_weights.adapt(&self.names, &self.base_weights, exploitation);
+        log::debug!(
+            "Adapted generator weights (exploitation: {}): {:?}",
+            exploitation,
+            self.names.iter().zip(weights.iter()).collect::<Vec<_>>()
+        );
+
+        set_mutation_probabilities(state, &weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        [
+            "InputMutator",
+            "SmallGenerator",
+            "LargeGenerator",
+            "IdleGenerator",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+    }
+
+    fn simulated_weights() -> IrGeneratorWeights {
+        let mut weights = IrGeneratorWeights::default();
+        for _ in 0..10 {
+            weights.record("SmallGenerator", 2);
+            weights.record("LargeGenerator", 40);
+        }
+        weights
+    }
+
+    #[test]
+    fn weights_follow_program_size() {
+        let weights = simulated_weights();
+        let base = vec![100.0, 10.0, 10.0, 10.0];
+
+        let building = weights.adapt(&names(), &base, false);
+        assert_eq!(building[0], 100.0);
+        assert!(building[1] > 10.0);
+        assert!(building[2] < 10.0);
+        assert_eq!(building[3], 10.0);
+
+        let exploitation = weights.adapt(&names(), &base, true);
+        assert_eq!(exploitation[0], 100.0);
+        assert!(exploitation[1] < 10.0);
+        assert!(exploitation[2] > 10.0);
+        assert_eq!(exploitation[3], 10.0);
+
+        // Disabled generators stay disabled
+        let disabled = weights.adapt(&names(), &[100.0, 0.0, 0.0, 10.0], false);
+        assert_eq!(&disabled[1..3], &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn weight_factors_are_bounded() {
+        let mut weights = IrGeneratorWeights::default();
+        weights.record("SmallGenerator", 0);
+        weights.record("LargeGenerator", 100_000);
+
+        assert_eq!(
+            weights.adapt(&names(), &[1.0; 4], false)[1],
+            MAX_WEIGHT_FACTOR
+        );
+        assert_eq!(
+            weights.adapt(&names(), &[1.0; 4], true)[1],
+            MIN_WEIGHT_FACTOR
+        );
+    }
+
+    #[test]
+    fn probabilities_are_cumulative() {
+        let mut state = libafl::state::NopState::<crate::input::IrInput>::new();
+        state.add_metadata(TuneableScheduledMutatorMetadata::default());
+
+        set_mutation_probabilities(&mut state, &[1.0, 3.0]).unwrap();
+        let probabilities = &state
+            .metadata::<TuneableScheduledMutatorMetadata>()
+            .unwrap()
+            .mutation_probabilities_cumulative;
+        assert_eq!(probabilities, &vec![0.25, 1.0]);
+    }
+}
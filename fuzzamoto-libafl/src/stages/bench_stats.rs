@@ -1,25 +1,130 @@
 use std::{
-    fs::OpenOptions,
+    collections::HashMap,
+    fs::{File, OpenOptions},
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
 
 use libafl::{
     Evaluator, ExecutesInput, HasNamedMetadata,
-    corpus::Corpus,
+    corpus::{Corpus, CorpusId},
     events::EventFirer,
     executors::{Executor, HasObservers},
     feedbacks::MapFeedbackMetadata,
     observers::ObserversTuple,
     stages::{Restartable, Stage},
-    state::{HasCorpus, HasExecutions, HasSolutions},
+    state::{HasCorpus, HasCurrentTestcase, HasExecutions, HasSolutions},
 };
 
-use crate::input::IrInput;
+use crate::{
+    feedbacks::{RecvFeedbackMetadata, most_diverse_testcases},
+    input::IrInput,
+};
+
+/// Number of testcases listed in the response diversity CSV written by `BenchStatsStage`
+const MOST_DIVERSE_TESTCASES: usize = 10;
+
+/// Row of the per-operation CSV written by `BenchStatsStage`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperationFrequencyEntry {
+    pub operation: String,
+    /// Number of times the operation occurred in executed testcases
+    pub count: u64,
+    pub rate_per_sec: f64,
+}
+
+impl OperationFrequencyEntry {
+    /// Create entries (sorted by descending count) from cumulative operation counts
+    pub fn from_counts(counts: &HashMap<&'static str, u64>, elapsed_secs: f64) -> Vec<Self> {
+        let mut entries: Vec<Self> = counts
+            .iter()
+            .map(|(operation, count)| Self {
+                operation: operation.to_string(),
+                count: *count,
+                rate_per_sec: if elapsed_secs > 0.0 {
+                    *count as f64 / elapsed_secs
+                } else {
+                    0.0
+                },
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.operation.cmp(&b.operation))
+        });
+        entries
+    }
+}
+
+/// Write `entries` to `path` as CSV, replacing any previous contents
+fn write_operation_frequencies(
+    path: &Path,
+    entries: &[OperationFrequencyEntry],
+) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "operation,count,rate_per_sec")?;
+    for entry in entries {
+        writeln!(
+            file,
+            "{},{},{:.2}",
+            entry.operation, entry.count, entry.rate_per_sec
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the most diverse testcases (see `most_diverse_testcases`) to `path` as CSV, replacing any
+/// previous contents. The commands of each testcase are separated by spaces.
+fn write_most_diverse_testcases(
+    path: &Path,
+    testcases: &[(CorpusId, RecvFeedbackMetadata)],
+) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "testcase,num_commands,commands")?;
+    for (id, meta) in testcases {
+        writeln!(
+            file,
+            "{},{},{}",
+            id.0,
+            meta.commands_seen.len(),
+            meta.commands_seen
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ")
+        )?;
+    }
+    Ok(())
+}
+
+/// Path of the CSV named `<stem><suffix>.csv` belonging to the stats file at `stats_file_path`
+fn companion_file_path(stats_file_path: &Path, suffix: &str) -> PathBuf {
+    let stem = stats_file_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    stats_file_path.with_file_name(format!("{stem}{suffix}.csv"))
+}
+
+/// Path of the per-operation CSV belonging to the stats file at `stats_file_path`
+fn operations_file_path(stats_file_path: &Path) -> PathBuf {
+    companion_file_path(stats_file_path, "-operations")
+}
+
+/// Path of the response diversity CSV belonging to the stats file at `stats_file_path`
+fn recv_diversity_file_path(stats_file_path: &Path) -> PathBuf {
+    companion_file_path(stats_file_path, "-recv-diversity")
+}
 
 /// Stage for collecting fuzzer stats useful for benchmarking.
 ///
+/// Besides the overall stats, the stage counts how often each operation type occurs in the
+/// executed testcases and periodically writes these counts to `<stats file stem>-operations.csv`.
+/// The corpus entries with the most diverse responses (see `RecvFeedbackMetadata`) are written to
+/// `<stats file stem>-recv-diversity.csv`.
+///
 /// Note: `feedback_name` must match the name used to register `MapFeedbackMetadata`
 /// (i.e., the feedback's name), which may differ from the observer's name.
 pub struct BenchStatsStage {
@@ -35,6 +140,11 @@ pub struct BenchStatsStage {
 
     stats_file_path: PathBuf,
     csv_header_written: bool,
+
+    // Cumulative number of occurrences per operation type in executed testcases
+    operation_counts: HashMap<&'static str, u64>,
+    operations_file_path: PathBuf,
+    recv_diversity_file_path: PathBuf,
 }
 
 impl BenchStatsStage {
@@ -54,8 +164,11 @@ impl BenchStatsStage {
             last_update,
             update_interval,
             last_execs: 0,
+            operations_file_path: operations_file_path(&stats_file_path),
+            recv_diversity_file_path: recv_diversity_file_path(&stats_file_path),
             stats_file_path,
             csv_header_written: false,
+            operation_counts: HashMap::new(),
         }
     }
 }
@@ -72,7 +185,11 @@ impl<S> Restartable<S> for BenchStatsStage {
 
 impl<E, EM, S, Z, OT> Stage<E, EM, S, Z> for BenchStatsStage
 where
-    S: HasCorpus<IrInput> + HasExecutions + HasSolutions<IrInput> + HasNamedMetadata,
+    S: HasCorpus<IrInput>
+        + HasCurrentTestcase<IrInput>
+        + HasExecutions
+        + HasSolutions<IrInput>
+        + HasNamedMetadata,
     E: Executor<EM, IrInput, S, Z> + HasObservers<Observers = OT>,
     EM: EventFirer<IrInput, S>,
     Z: Evaluator<E, EM, IrInput, S> + ExecutesInput<E, EM, IrInput, S>,
@@ -85,6 +202,12 @@ where
         state: &mut S,
         _manager: &mut EM,
     ) -> Result<(), libafl::Error> {
+        if let Ok(input) = state.current_input_cloned() {
+            for (operation, count) in input.ir().count_by_operation_type() {
+                *self.operation_counts.entry(operation).or_insert(0) += count;
+            }
+        }
+
         let now = Instant::now();
         if now < self.last_update + self.update_interval {
             return Ok(());
@@ -186,6 +309,109 @@ where
             );
         }
 
+        let entries = OperationFrequencyEntry::from_counts(&self.operation_counts, elapsed);
+        if write_operation_frequencies(&self.operations_file_path, &entries).is_err() {
+            log::warn!(
+                "bench_stats: cpu={} failed to write operation CSV to {}",
+                self.cpu_id,
+                self.operations_file_path.display()
+            );
+        }
+
+        let most_diverse = most_diverse_testcases(state.corpus(), MOST_DIVERSE_TESTCASES)?;
+        if write_most_diverse_testcases(&self.recv_diversity_file_path, &most_diverse).is_err() {
+            log::warn!(
+                "bench_stats: cpu={} failed to write response diversity CSV to {}",
+                self.cpu_id,
+                self.recv_diversity_file_path.display()
+            );
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::{Operation, ProgramBuilder, ProgramContext};
+
+    fn input(operations: Vec<Operation>) -> IrInput {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        for operation in operations {
+            builder.force_append(vec![], operation);
+        }
+        IrInput::new(builder.finalize().unwrap())
+    }
+
+    #[test]
+    fn operation_csv_matches_corpus() {
+        let corpus = [
+            input(vec![
+                Operation::LoadBytes(vec![1]),
+                Operation::LoadBytes(vec![2]),
+                Operation::LoadConnection(0),
+            ]),
+            input(vec![Operation::LoadBytes(vec![3]), Operation::LoadTime(1)]),
+            input(vec![Operation::LoadConnection(0)]),
+        ];
+
+        let mut counts = HashMap::new();
+        for input in &corpus {
+            for (operation, count) in input.ir().count_by_operation_type() {
+                *counts.entry(operation).or_insert(0) += count;
+            }
+        }
+
+        let entries = OperationFrequencyEntry::from_counts(&counts, 2.0);
+        let dir = std::env::temp_dir().join(format!("fuzzamoto-bench-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = operations_file_path(&dir.join("bench-cpu_000.csv"));
+        assert_eq!(path, dir.join("bench-cpu_000-operations.csv"));
+
+        write_operation_frequencies(&path, &entries).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            csv,
+            "operation,count,rate_per_sec\n\
+             LoadBytes,3,1.50\n\
+             LoadConnection,2,1.00\n\
+             LoadTime,1,0.50\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recv_diversity_csv() {
+        let testcases = [
+            (
+                CorpusId(3),
+                RecvFeedbackMetadata {
+                    commands_seen: ["inv", "getdata"].into_iter().map(String::from).collect(),
+                },
+            ),
+            (CorpusId(0), RecvFeedbackMetadata::default()),
+        ];
+
+        let dir = std::env::temp_dir().join(format!("fuzzamoto-recv-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = recv_diversity_file_path(&dir.join("bench-cpu_000.csv"));
+        assert_eq!(path, dir.join("bench-cpu_000-recv-diversity.csv"));
+
+        write_most_diverse_testcases(&path, &testcases).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            csv,
+            "testcase,num_commands,commands\n\
+             3,2,getdata inv\n\
+             0,0,\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    fmt,
+    fs::File,
+    io::BufReader,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use fuzzamoto_ir::Program;
+use libafl::{
+    HasMetadata,
+    corpus::{Corpus, CorpusId},
+    stages::{Restartable, Stage},
+    state::HasCorpus,
+};
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+
+use crate::input::IrInput;
+
+/// Summary of the corpus composition, see `CorpusHealthMetadata::compute`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorpusHealthMetadata {
+    pub total: usize,
+    pub disabled: usize,
+    pub enabled: usize,
+    pub min_instructions: usize,
+    pub max_instructions: usize,
+    pub avg_instructions: f64,
+}
+impl_serdeany!(CorpusHealthMetadata);
+
+/// Read the number of instructions of the program stored at `path`, decoding only the start of
+/// the file
+fn instruction_count(path: &Path) -> Option<usize> {
+    let reader = BufReader::new(File::open(path).ok()?);
+    #[cfg(feature = "compress")]
+    {
+        Program::peek_compressed_instruction_count(reader)
+    }
+    #[cfg(not(feature = "compress"))]
+    {
+        Program::peek_instruction_count(reader)
+    }
+}
+
+impl CorpusHealthMetadata {
+    /// Compute the health metrics of `corpus`. Testcases are not loaded into memory, their
+    /// instruction counts are read from the start of their files on disk instead (testcases
+    /// without a file are not considered for the instruction metrics).
+    pub fn compute<C: Corpus<IrInput>>(corpus: &C) -> Result<Self, libafl::Error> {
+        let enabled = corpus.count();
+        let disabled = corpus.count_disabled();
+
+        let mut counts = Vec::with_capacity(enabled + disabled);
+        for idx in 0..enabled + disabled {
+            let id: CorpusId = corpus.nth_from_all(idx);
+            let testcase = corpus.get_from_all(id)?.borrow();
+            if let Some(count) = testcase
+                .file_path()
+                .as_ref()
+                .and_then(|path| instruction_count(path))
+            {
+                counts.push(count);
+            }
+        }
+
+        Ok(Self {
+            total: enabled + disabled,
+            disabled,
+            enabled,
+            min_instructions: counts.iter().copied().min().unwrap_or(0),
+            max_instructions: counts.iter().copied().max().unwrap_or(0),
+            avg_instructions: if counts.is_empty() {
+                0.0
+            } else {
+                counts.iter().sum::<usize>() as f64 / counts.len() as f64
+            },
+        })
+    }
+}
+
+impl fmt::Display for CorpusHealthMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{ total: {}, disabled: {}, enabled: {}, min_instructions: {}, max_instructions: {}, avg_instructions: {:.1} }}",
+            self.total,
+            self.disabled,
+            self.enabled,
+            self.min_instructions,
+            self.max_instructions,
+            self.avg_instructions
+        )
+    }
+}
+
+/// `CorpusHealthStage` periodically recomputes and prints the `CorpusHealthMetadata` of the
+/// corpus
+#[derive(Debug)]
+pub struct CorpusHealthStage {
+    interval: Duration,
+    last_report: Instant,
+}
+
+impl CorpusHealthStage {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_report: Instant::now(),
+        }
+    }
+}
+
+impl<S> Restartable<S> for CorpusHealthStage {
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, libafl::Error> {
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), libafl::Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, S, Z> Stage<E, EM, S, Z> for CorpusHealthStage
+where
+    S: HasCorpus<IrInput> + HasMetadata,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), libafl::Error> {
+        if self.last_report.elapsed() < self.interval {
+            return Ok(());
+        }
+        self.last_report = Instant::now();
+
+        let health = CorpusHealthMetadata::compute(state.corpus())?;
+        println!("[CORPUS] {}", health);
+        state.add_metadata(health);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::{Operation, ProgramBuilder, ProgramContext};
+    use libafl::corpus::{OnDiskCorpus, Testcase};
+
+    fn input(len: u64) -> IrInput {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        for i in 0..len {
+            builder.force_append(vec![], Operation::LoadTime(i));
+        }
+        IrInput::new(builder.finalize().unwrap())
+    }
+
+    #[test]
+    fn health_of_pre_populated_corpus() {
+        let dir = std::env::temp_dir().join(format!("fuzzamoto-health-{}", std::process::id()));
+        let mut corpus = OnDiskCorpus::<IrInput>::new(&dir).unwrap();
+        for len in [2, 4, 9] {
+            corpus.add(Testcase::new(input(len))).unwrap();
+        }
+        corpus.add_disabled(Testcase::new(input(1))).unwrap();
+
+        let health = CorpusHealthMetadata::compute(&corpus).unwrap();
+        assert_eq!(
+            health,
+            CorpusHealthMetadata {
+                total: 4,
+                disabled: 1,
+                enabled: 3,
+                min_instructions: 1,
+                max_instructions: 9,
+                avg_instructions: 4.0,
+            }
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
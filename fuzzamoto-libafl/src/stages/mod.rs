@@ -3,12 +3,24 @@ pub mod bench_stats;
 #[cfg(feature = "bench")]
 pub use bench_stats::*;
 
+pub mod adaptive_weights;
+pub use adaptive_weights::*;
+
+pub mod corpus_health;
+pub use corpus_health::*;
+
 pub mod probe;
 pub use probe::*;
 
+pub mod program_hash;
+pub use program_hash::*;
+
 pub mod stability_check;
 pub use stability_check::*;
 
+pub mod sync;
+pub use sync::*;
+
 pub mod verify_timeouts;
 
 pub use verify_timeouts::*;
@@ -91,6 +103,13 @@ where
         state: &mut S,
         manager: &mut EM,
     ) -> Result<(), libafl::Error> {
+        let _span = tracing::info_span!(
+            "minimize",
+            minimizer = std::any::type_name::<M>(),
+            crash = self.minimizing_crash
+        )
+        .entered();
+
         if state.current_testcase()?.scheduled_count() > 0 {
             // Already minimized
             return Ok(());
@@ -106,6 +125,16 @@ where
         let mut success = false;
         let mut current_ir = state.current_input_cloned()?;
 
+        // Inputs exceeding the program limits (see `ProgramContext::validate`) can't be rebuilt
+        // and thus not be minimized
+        if !current_ir.ir().is_statically_valid() {
+            log::info!(
+                "{} skipping statically invalid ir",
+                std::any::type_name::<M>()
+            );
+            return Ok(());
+        }
+
         log::info!(
             "{} reducing ir: {} instrs",
             std::any::type_name::<M>(),
@@ -162,6 +191,11 @@ where
                 std::any::type_name::<M>(),
                 current_ir.ir().instructions.len()
             );
+            log::debug!(
+                "{} reduced ir:\n{}",
+                std::any::type_name::<M>(),
+                current_ir.ir().to_annotated_string()
+            );
 
             let mut testcase = state.current_testcase_mut()?;
             testcase.set_input(current_ir);
@@ -1,6 +1,8 @@
-use crate::input::IrInput;
+use crate::input::{IrInput, write_observed_state};
 use core::marker::PhantomData;
-use fuzzamoto_ir::{Instruction, Operation};
+use fuzzamoto_ir::{
+    GenerationEvent, Instruction, ObservedNodeState, Operation, PerTestcaseMetadata,
+};
 use fuzzamoto_ir::{ProbeResult, ProbeResults};
 use libafl::ExecutesInput;
 use libafl::{
@@ -40,6 +42,9 @@ pub struct RuntimeMetadata {
     // TODO: If you want to add another metadata, then add it to `PerTestcaseMetadata` (not here!)
     metadatas: HashMap<CorpusId, fuzzamoto_ir::PerTestcaseMetadata>,
     mutation_idx: usize,
+    // Generation stack of the input currently being mutated (`None` if no generator was invoked
+    // yet), moved to the new corpus entry by `finish_generation`
+    pending_generation: Option<PerTestcaseMetadata>,
 }
 
 impl RuntimeMetadata {
@@ -47,6 +52,14 @@ impl RuntimeMetadata {
         self.metadatas.get_mut(&id)
     }
 
+    /// Merge the node state observed while executing corpus entry `id` into its metadata and
+    /// return the merged state
+    pub fn add_runtime(&mut self, id: CorpusId, runtime: ObservedNodeState) -> &ObservedNodeState {
+        let meta = self.metadatas.entry(id).or_default();
+        meta.add_runtime(runtime);
+        meta.runtime().expect("runtime was just added")
+    }
+
     pub fn increment_idx(&mut self) {
         self.mutation_idx += 1;
     }
@@ -58,10 +71,57 @@ impl RuntimeMetadata {
     pub fn mutation_idx(&self) -> usize {
         self.mutation_idx
     }
+
+    /// Record a generator invocation on the input currently being mutated. The first invocation
+    /// inherits the generation stack of the `parent` testcase.
+    pub fn record_generation(&mut self, parent: Option<CorpusId>, event: GenerationEvent) {
+        let pending = self
+            .pending_generation
+            .get_or_insert_with(|| PerTestcaseMetadata {
+                generation_stack: parent
+                    .and_then(|id| self.metadatas.get(&id))
+                    .map(|meta| meta.generation_stack.clone())
+                    .unwrap_or_default(),
+                ..Default::default()
+            });
+        pending.record_generation(event);
+    }
+
+    /// Attach the recorded generation stack to `new_corpus_id` (if the mutated input was added to
+    /// the corpus) and reset it for the next input.
+    pub fn finish_generation(&mut self, new_corpus_id: Option<CorpusId>) {
+        if let Some(pending) = self.pending_generation.take()
+            && let Some(id) = new_corpus_id
+        {
+            self.metadatas.entry(id).or_default().generation_stack = pending.generation_stack;
+        }
+    }
 }
 
 impl_serdeany!(RuntimeMetadata);
 
+/// Decode the probe results printed by the scenario, i.e. one base64 encoded `ProbeResults` per
+/// line of `buffer`
+pub fn decode_probe_results(buffer: &[u8]) -> Vec<ProbeResults> {
+    use base64::prelude::{BASE64_STANDARD, Engine};
+
+    let mut decoded_results = Vec::new();
+    for chunk in buffer.split(|b| *b == b'\n') {
+        if chunk.is_empty() {
+            continue;
+        }
+
+        if let Ok(decoded) = BASE64_STANDARD.decode(chunk)
+            && let Ok(results) = postcard::from_bytes::<ProbeResults>(&decoded)
+        {
+            decoded_results.push(results);
+        } else {
+            log::info!("Failed to decode the message from the target!");
+        }
+    }
+    decoded_results
+}
+
 /// Parse the incoming message from the other peer and process it
 pub fn process_probe_results<S>(state: &mut S, results: &ProbeResults)
 where
@@ -94,6 +154,22 @@ where
                     txvec.add_recent_blocks(result.clone())
                 }
             }
+            ProbeResult::Runtime { runtime } => {
+                let current = *state.corpus().current();
+                if let Some(cur) = current
+                    && let Ok(meta) = state.metadata_mut::<RuntimeMetadata>()
+                {
+                    let observed = meta.add_runtime(cur, runtime.clone()).clone();
+                    if let Ok(testcase) = state.corpus().get(cur)
+                        && let Some(path) = testcase.borrow().file_path()
+                        && let Err(e) = write_observed_state(path, &observed)
+                    {
+                        log::warn!("Failed to write observed node state: {}", e);
+                    }
+                }
+            }
+            // Evaluated by `RecvFeedback` and `RecvMessageTypeFeedback` on every execution
+            ProbeResult::ReceivedMessages { .. } => {}
         }
     }
 }
@@ -165,26 +241,8 @@ where
             .output
             .as_ref()
             .ok_or(libafl::Error::illegal_state("StdOutObserver has no stdout"))?;
-        if !buffer.is_empty() {
-            let chunks: Vec<Vec<u8>> = buffer
-                .split(|b| *b == b'\n')
-                .map(|slice| slice.to_vec())
-                .collect();
-
-            for chunk in chunks {
-                if chunk.is_empty() {
-                    continue;
-                }
-
-                use base64::prelude::{BASE64_STANDARD, Engine};
-                if let Ok(decoded) = BASE64_STANDARD.decode(&chunk)
-                    && let Ok(results) = postcard::from_bytes::<ProbeResults>(&decoded)
-                {
-                    process_probe_results(state, &results);
-                } else {
-                    log::info!("Failed to decode the message from the target!");
-                }
-            }
+        for results in decode_probe_results(buffer) {
+            process_probe_results(state, &results);
         }
 
         post.post_exec(state, None)?;
@@ -0,0 +1,64 @@
+use libafl::{
+    HasMetadata,
+    stages::{Restartable, Stage},
+    state::HasCurrentTestcase,
+};
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+
+use crate::input::IrInput;
+
+/// Hashes of a testcase's program (see `Program::structural_hash` and `Program::operand_hash`).
+/// Together they form a key for deduplicating corpus entries without comparing the programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProgramHashMetadata {
+    pub structural: u64,
+    pub operand: u64,
+}
+impl_serdeany!(ProgramHashMetadata);
+
+impl ProgramHashMetadata {
+    pub fn new(program: &fuzzamoto_ir::Program) -> Self {
+        Self {
+            structural: program.structural_hash(),
+            operand: program.operand_hash(),
+        }
+    }
+
+    /// Two-component deduplication key
+    pub fn key(&self) -> (u64, u64) {
+        (self.structural, self.operand)
+    }
+}
+
+/// `ProgramHashStage` attaches (or refreshes) the `ProgramHashMetadata` of the current testcase.
+/// It should run after the minimization stages, as those may replace the testcase's program.
+#[derive(Debug, Default)]
+pub struct ProgramHashStage;
+
+impl<S> Restartable<S> for ProgramHashStage {
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, libafl::Error> {
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), libafl::Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, S, Z> Stage<E, EM, S, Z> for ProgramHashStage
+where
+    S: HasCurrentTestcase<IrInput>,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), libafl::Error> {
+        let metadata = ProgramHashMetadata::new(state.current_input_cloned()?.ir());
+        state.current_testcase_mut()?.add_metadata(metadata);
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use libafl::{
+    Evaluator, HasMetadata,
+    inputs::Input,
+    stages::{Restartable, Stage},
+    state::HasExecutions,
+};
+use libafl_bolts::impl_serdeany;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::{IrInput, read_observed_state},
+    stages::RuntimeMetadata,
+};
+
+/// Number of times a peer file that failed to decode is retried before it is given up on
+const MAX_DECODE_RETRIES: u32 = 1;
+
+/// Peer corpus files that were already evaluated (and imported if interesting) by the
+/// `CorpusSyncStage`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SyncedFilesMetadata {
+    pub seen: BTreeSet<PathBuf>,
+    /// Number of failed decoding attempts of peer files that are not yet `seen`
+    pub decode_failures: BTreeMap<PathBuf, u32>,
+}
+impl_serdeany!(SyncedFilesMetadata);
+
+impl SyncedFilesMetadata {
+    /// Record a failed attempt to decode `file`. Once its retries are exhausted the file is marked
+    /// as seen, so that it is not read again on every sync. Returns whether the file was given up
+    /// on.
+    pub fn record_decode_failure(&mut self, file: &Path) -> bool {
+        let failures = self.decode_failures.entry(file.to_path_buf()).or_default();
+        *failures += 1;
+        if *failures <= MAX_DECODE_RETRIES {
+            return false;
+        }
+
+        self.decode_failures.remove(file);
+        self.seen.insert(file.to_path_buf());
+        true
+    }
+
+    /// Mark `file` as evaluated
+    pub fn mark_seen(&mut self, file: PathBuf) {
+        self.decode_failures.remove(&file);
+        self.seen.insert(file);
+    }
+}
+
+/// `CorpusSyncStage` periodically imports the queue entries of the other fuzzer clients sharing
+/// the same output directory (`<output>/cpu_*/queue`). Imported inputs are evaluated like any
+/// other input, i.e. they are only added to the local corpus if they are interesting to this
+/// client.
+#[derive(Debug)]
+pub struct CorpusSyncStage {
+    output_dir: PathBuf,
+    own_queue: PathBuf,
+    /// Number of executions between two syncs
+    interval: u64,
+    last_sync: u64,
+}
+
+impl CorpusSyncStage {
+    pub fn new(output_dir: PathBuf, own_queue: PathBuf, interval: u64) -> Self {
+        Self {
+            output_dir,
+            own_queue,
+            interval,
+            last_sync: 0,
+        }
+    }
+}
+
+/// Collect the files in the queues of all clients in `output_dir` (except for `own_queue`) that
+/// are neither in `seen` nor (by name) in `own_queue`.
+///
+/// LibAFL names corpus files after the hash of their input, so a peer file with a name that
+/// already exists in `own_queue` holds an input this client already has.
+pub fn unseen_peer_files(
+    output_dir: &Path,
+    own_queue: &Path,
+    seen: &BTreeSet<PathBuf>,
+) -> Vec<PathBuf> {
+    let Ok(clients) = std::fs::read_dir(output_dir) else {
+        return Vec::new();
+    };
+
+    let mut files = Vec::new();
+    for client in clients.flatten() {
+        if !client.file_name().to_string_lossy().starts_with("cpu_") {
+            continue;
+        }
+        let queue = client.path().join("queue");
+        if queue == own_queue {
+            continue;
+        }
+        let Ok(entries) = std::fs::read_dir(&queue) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name_str = name.to_string_lossy();
+            // Skip LibAFL's metadata and lock files as well as partially written inputs
+            if name_str.starts_with('.') || name_str.ends_with(".tmp") {
+                continue;
+            }
+
+            let path = entry.path();
+            if !path.is_file() || seen.contains(&path) || own_queue.join(&name).exists() {
+                continue;
+            }
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    files
+}
+
+impl<S> Restartable<S> for CorpusSyncStage {
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, libafl::Error> {
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), libafl::Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, S, Z> Stage<E, EM, S, Z> for CorpusSyncStage
+where
+    S: HasMetadata + HasExecutions,
+    Z: Evaluator<E, EM, IrInput, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), libafl::Error> {
+        let executions = *state.executions();
+        if executions.saturating_sub(self.last_sync) < self.interval {
+            return Ok(());
+        }
+        self.last_sync = executions;
+
+        // Collect the candidates up front, so that no metadata is borrowed while executing them
+        let files = unseen_peer_files(
+            &self.output_dir,
+            &self.own_queue,
+            &state
+                .metadata_or_insert_with(SyncedFilesMetadata::default)
+                .seen,
+        );
+
+        let mut imported = 0usize;
+        for file in files {
+            let input = match IrInput::from_file(&file) {
+                Ok(input) => input,
+                Err(e) => {
+                    // Peers write their inputs atomically, but we might have raced with the
+                    // creation of the file. Retry on the next sync, unless it already failed
+                    // before (e.g. because it was written by an incompatible version).
+                    if state
+                        .metadata_mut::<SyncedFilesMetadata>()?
+                        .record_decode_failure(&file)
+                    {
+                        log::warn!("Skipping undecodable input {}: {}", file.display(), e);
+                    } else {
+                        log::debug!("Failed to import {}: {}", file.display(), e);
+                    }
+                    continue;
+                }
+            };
+
+            let (_, corpus_id) = fuzzer.evaluate_input(state, executor, manager, &input)?;
+            if let Some(id) = corpus_id {
+                imported += 1;
+                // Make the node state the peer observed available to the generators right away
+                if let Ok(observed) = read_observed_state(&file) {
+                    state
+                        .metadata_or_insert_with(RuntimeMetadata::default)
+                        .add_runtime(id, observed);
+                }
+            }
+
+            state.metadata_mut::<SyncedFilesMetadata>()?.mark_seen(file);
+        }
+
+        if imported > 0 {
+            log::info!("Imported {} inputs from other clients", imported);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzamoto-sync-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_queue(output: &Path, client: &str, files: &[&str]) -> PathBuf {
+        let queue = output.join(client).join("queue");
+        std::fs::create_dir_all(&queue).unwrap();
+        for file in files {
+            std::fs::write(queue.join(file), [0u8]).unwrap();
+        }
+        queue
+    }
+
+    #[test]
+    fn only_unseen_peer_files_are_synced() {
+        let output = test_dir("unseen");
+        let own_queue = write_queue(&output, "cpu_000", &["shared", "own", ".own.metadata"]);
+        let peer_queue = write_queue(
+            &output,
+            "cpu_001",
+            &[
+                "shared",
+                "peer_a",
+                "peer_b",
+                ".peer_a.lafl_lock",
+                "peer_c.ir.tmp",
+            ],
+        );
+        std::fs::create_dir_all(output.join("bench")).unwrap();
+
+        let files = unseen_peer_files(&output, &own_queue, &BTreeSet::new());
+        assert_eq!(
+            files,
+            vec![peer_queue.join("peer_a"), peer_queue.join("peer_b")]
+        );
+
+        let seen = BTreeSet::from([peer_queue.join("peer_a")]);
+        let files = unseen_peer_files(&output, &own_queue, &seen);
+        assert_eq!(files, vec![peer_queue.join("peer_b")]);
+
+        // The peer sees our non-overlapping entry
+        let files = unseen_peer_files(&output, &peer_queue, &BTreeSet::new());
+        assert_eq!(files, vec![own_queue.join("own")]);
+
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+
+    #[test]
+    fn undecodable_files_are_retried_once() {
+        let output = test_dir("undecodable");
+        let own_queue = write_queue(&output, "cpu_000", &[]);
+        let peer_queue = write_queue(&output, "cpu_001", &["broken", "racy"]);
+        let (broken, racy) = (peer_queue.join("broken"), peer_queue.join("racy"));
+
+        let mut metadata = SyncedFilesMetadata::default();
+        assert!(!metadata.record_decode_failure(&broken));
+        assert!(!metadata.record_decode_failure(&racy));
+        assert_eq!(
+            unseen_peer_files(&output, &own_queue, &metadata.seen),
+            vec![broken.clone(), racy.clone()]
+        );
+
+        // The retry of `racy` succeeds, `broken` still fails to decode and is given up on
+        assert!(metadata.record_decode_failure(&broken));
+        metadata.mark_seen(racy);
+        assert!(metadata.decode_failures.is_empty());
+        assert!(unseen_peer_files(&output, &own_queue, &metadata.seen).is_empty());
+
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+}
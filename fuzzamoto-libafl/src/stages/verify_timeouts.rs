@@ -1,48 +1,126 @@
-use std::{cell::RefCell, fmt::Debug, marker::PhantomData, time::Duration};
-use std::{collections::VecDeque, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::Debug,
+    marker::PhantomData,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
+use fuzzamoto::targets::TargetNode;
 use libafl_bolts::Error;
 use serde::{Deserialize, Serialize};
 
 use libafl::{
-    Evaluator, HasMetadata,
-    executors::{Executor, HasObservers, HasTimeout, SetTimeout},
-    observers::ObserversTuple,
+    HasMetadata,
+    corpus::Corpus,
+    executors::{ExitKind, HasTimeout, SetTimeout},
+    fuzzer::Evaluator,
     stages::{Restartable, Stage},
+    state::HasSolutions,
 };
 
-use crate::input::IrInput;
+use crate::{feedbacks::ExecutionTimeCorrection, input::IrInput, instance::HangsCorpus};
+
+/// Outcome of re-running a timed out input with the extended timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutClassification {
+    /// The input timed out again while the node stayed alive
+    GenuineHang,
+    /// The node crashed, either during the re-run or as detected by the liveness check
+    NodeCrash,
+    /// The input timed out again and the node's liveness could not be checked
+    NodeUnresponsive,
+    /// The input finished within the extended timeout
+    Fluke,
+}
+
+impl TimeoutClassification {
+    /// Classify a re-run's `exit_kind`. Timeouts are further classified by checking if `target`
+    /// (if available) is still alive.
+    pub fn classify<T: TargetNode>(exit_kind: ExitKind, target: Option<&Mutex<T>>) -> Self {
+        match exit_kind {
+            ExitKind::Ok => TimeoutClassification::Fluke,
+            ExitKind::Crash => TimeoutClassification::NodeCrash,
+            _ => match target {
+                None => TimeoutClassification::NodeUnresponsive,
+                Some(target) => match target.lock().unwrap().is_alive() {
+                    Ok(()) => TimeoutClassification::GenuineHang,
+                    Err(e) => {
+                        log::info!("Node is not alive after timeout: {}", e);
+                        TimeoutClassification::NodeCrash
+                    }
+                },
+            },
+        }
+    }
+}
 
 /// Stage that re-runs inputs deemed as timeouts with a multiple of the timeout to assert that they
 /// are not false positives.
+///
+/// Timeouts are re-run through the fuzzer's regular evaluation, i.e. flukes that reach new
+/// coverage are added to the corpus and confirmed timeouts or crashes are subject to the
+/// objective. The outcome is then classified (see `TimeoutClassification`) and confirmed timeouts
+/// of a node that is still alive are moved from the solutions to the `HangsCorpus`. The fuzzer
+/// usually has no access to the target (e.g. when it runs inside of a Nyx VM), in which case
+/// `target` is `None`, the node's liveness is not checked and timeouts remain solutions.
+///
+/// Inputs that are predicted to run longer than the configured timeout (see
+/// `ExecutionTimeCorrection`) are re-run with the same multiple of their predicted execution time
+/// instead, such that expensive programs are not misclassified as hangs.
 #[derive(Debug)]
-pub struct VerifyTimeoutsStage<E, S> {
+pub struct VerifyTimeoutsStage<E, S, T> {
+    multiple: u32,
     multiple_of_timeout: Duration,
     original_timeout: Duration,
     capture_timeouts: Rc<RefCell<bool>>,
+    hangs: HangsCorpus<IrInput>,
+    target: Option<Arc<Mutex<T>>>,
     phantom: PhantomData<(E, S)>,
 }
 
-impl<E, S> VerifyTimeoutsStage<E, S> {
+impl<E, S, T> VerifyTimeoutsStage<E, S, T> {
     /// Create a `VerifyTimeoutsStage`
     pub fn new(
         capture_timeouts: Rc<RefCell<bool>>,
         configured_timeout: Duration,
         multiple: u32,
+        hangs: HangsCorpus<IrInput>,
+        target: Option<Arc<Mutex<T>>>,
     ) -> Self {
         Self {
             capture_timeouts,
+            multiple,
             multiple_of_timeout: configured_timeout * multiple,
             original_timeout: configured_timeout,
+            hangs,
+            target,
             phantom: PhantomData,
         }
     }
 }
 
+/// Timeout for re-running `input`: `multiple` times the larger of `configured_timeout` and the
+/// corrected execution time prediction of `input`
+fn rerun_timeout(
+    configured_timeout: Duration,
+    multiple: u32,
+    input: &IrInput,
+    correction: &ExecutionTimeCorrection,
+) -> Duration {
+    let predicted = correction.corrected(input.ir().estimate_execution_time_ms());
+    configured_timeout.max(predicted) * multiple
+}
+
 /// Timeouts that `VerifyTimeoutsStage` will read from
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct TimeoutsToVerify {
     inputs: VecDeque<IrInput>,
+    /// Exit kind of the last re-run, recorded by `CaptureTimeoutFeedback`
+    #[serde(skip)]
+    rerun_exit_kind: Option<ExitKind>,
 }
 
 libafl_bolts::impl_serdeany!(TimeoutsToVerify);
@@ -53,6 +131,7 @@ impl TimeoutsToVerify {
     pub fn new() -> Self {
         Self {
             inputs: VecDeque::new(),
+            rerun_exit_kind: None,
         }
     }
 
@@ -71,14 +150,24 @@ impl TimeoutsToVerify {
     pub fn count(&self) -> usize {
         self.inputs.len()
     }
+
+    /// Record the exit kind of a re-run
+    pub fn set_rerun_exit_kind(&mut self, exit_kind: ExitKind) {
+        self.rerun_exit_kind = Some(exit_kind);
+    }
+
+    /// Take the exit kind of the last re-run
+    pub fn take_rerun_exit_kind(&mut self) -> Option<ExitKind> {
+        self.rerun_exit_kind.take()
+    }
 }
 
-impl<E, EM, S, Z> Stage<E, EM, S, Z> for VerifyTimeoutsStage<E, S>
+impl<E, EM, S, T, Z> Stage<E, EM, S, Z> for VerifyTimeoutsStage<E, S, T>
 where
-    E::Observers: ObserversTuple<IrInput, S>,
-    E: Executor<EM, IrInput, S, Z> + HasObservers + HasTimeout + SetTimeout,
+    E: HasTimeout + SetTimeout,
+    S: HasMetadata + HasSolutions<IrInput>,
+    T: TargetNode,
     Z: Evaluator<E, EM, IrInput, S>,
-    S: HasMetadata,
 {
     fn perform(
         &mut self,
@@ -92,10 +181,33 @@ where
             return Ok(());
         }
         log::info!("Verifying {} timeouts!", timeouts.count());
-        executor.set_timeout(self.multiple_of_timeout);
+        let correction = state
+            .metadata::<ExecutionTimeCorrection>()
+            .cloned()
+            .unwrap_or_default();
         *self.capture_timeouts.borrow_mut() = false;
         while let Some(input) = timeouts.pop() {
+            let timeout = rerun_timeout(self.original_timeout, self.multiple, &input, &correction);
+            if timeout > self.multiple_of_timeout {
+                log::info!("Re-running timeout with predicted timeout of {:?}", timeout);
+            }
+            executor.set_timeout(timeout);
+            let num_solutions = state.solutions().count();
             fuzzer.evaluate_input(state, executor, manager, &input)?;
+            let exit_kind = state
+                .metadata_mut::<TimeoutsToVerify>()?
+                .take_rerun_exit_kind()
+                .unwrap_or(ExitKind::Ok);
+            let classification = TimeoutClassification::classify(exit_kind, self.target.as_deref());
+            log::info!("Timeout classified as {:?}", classification);
+
+            if classification == TimeoutClassification::GenuineHang
+                && state.solutions().count() > num_solutions
+                && let Some(id) = state.solutions().last()
+            {
+                state.solutions_mut().remove(id)?;
+                self.hangs.add(input)?;
+            }
         }
         executor.set_timeout(self.original_timeout);
         *self.capture_timeouts.borrow_mut() = true;
@@ -105,7 +217,7 @@ where
     }
 }
 
-impl<E, S> Restartable<S> for VerifyTimeoutsStage<E, S> {
+impl<E, S, T> Restartable<S> for VerifyTimeoutsStage<E, S, T> {
     fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
         Ok(true)
     }
@@ -114,3 +226,93 @@ impl<E, S> Restartable<S> for VerifyTimeoutsStage<E, S> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::{Operation, ProgramBuilder, ProgramContext};
+
+    /// Target whose liveness check returns a fixed result
+    struct MockTarget {
+        alive: bool,
+    }
+
+    impl TargetNode for MockTarget {
+        fn from_path(_path: &str) -> Result<Self, String> {
+            Ok(Self { alive: true })
+        }
+
+        fn set_mocktime(&mut self, _time: u64) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn is_alive(&self) -> Result<(), String> {
+            if self.alive {
+                Ok(())
+            } else {
+                Err("connection refused".to_string())
+            }
+        }
+    }
+
+    fn classify(exit_kind: ExitKind, alive: Option<bool>) -> TimeoutClassification {
+        let target = alive.map(|alive| Mutex::new(MockTarget { alive }));
+        TimeoutClassification::classify(exit_kind, target.as_ref())
+    }
+
+    #[test]
+    fn classifies_rerun_exit_kinds() {
+        assert_eq!(
+            classify(ExitKind::Ok, Some(true)),
+            TimeoutClassification::Fluke
+        );
+        assert_eq!(classify(ExitKind::Ok, None), TimeoutClassification::Fluke);
+        assert_eq!(
+            classify(ExitKind::Crash, Some(true)),
+            TimeoutClassification::NodeCrash
+        );
+    }
+
+    #[test]
+    fn classifies_timeouts_by_liveness() {
+        assert_eq!(
+            classify(ExitKind::Timeout, Some(true)),
+            TimeoutClassification::GenuineHang
+        );
+        assert_eq!(
+            classify(ExitKind::Timeout, Some(false)),
+            TimeoutClassification::NodeCrash
+        );
+        assert_eq!(
+            classify(ExitKind::Timeout, None),
+            TimeoutClassification::NodeUnresponsive
+        );
+    }
+
+    #[test]
+    fn rerun_timeout_follows_prediction() {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        builder.force_append(vec![], Operation::LoadTime(0));
+        let input = IrInput::new(builder.finalize().unwrap());
+        // Predicted to take 1ms
+        assert_eq!(input.ir().estimate_execution_time_ms(), 1);
+
+        let configured = Duration::from_millis(10);
+        let mut correction = ExecutionTimeCorrection::default();
+        assert_eq!(
+            rerun_timeout(configured, 3, &input, &correction),
+            Duration::from_millis(30)
+        );
+
+        // Executions take 20 times longer than predicted
+        correction.record(20.0);
+        assert_eq!(
+            rerun_timeout(configured, 3, &input, &correction),
+            Duration::from_millis(60)
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Validation of fuzzer states restored after a restart.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use libafl::{
+    Error,
+    corpus::{Corpus, CorpusId},
+};
+
+use crate::input::IrInput;
+
+/// Error returned by `FuzzerStateValidator::validate` if a restored state no longer matches the
+/// files on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateValidationError {
+    /// Number of corpus entries in the state whose file no longer exists
+    pub missing_corpus_files: usize,
+    /// Number of files in the corpus directory that are not part of the state
+    pub extra_corpus_files: usize,
+    /// The crashes directory no longer exists
+    pub missing_crashes_dir: bool,
+}
+
+impl fmt::Display for StateValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} missing corpus files, {} extra corpus files",
+            self.missing_corpus_files, self.extra_corpus_files
+        )?;
+        if self.missing_crashes_dir {
+            write!(f, ", missing crashes directory")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StateValidationError {}
+
+/// `FuzzerStateValidator` checks that a fuzzer state restored after a restart (e.g. after an OOM)
+/// is still in sync with its corpus and crashes directories, which might have been modified
+/// externally (e.g. by pruning the corpus) in the meantime.
+#[derive(Debug, Clone)]
+pub struct FuzzerStateValidator {
+    corpus_dir: PathBuf,
+    crashes_dir: PathBuf,
+}
+
+/// Names of the non-hidden files in `dir` (LibAFL stores its metadata and lock files as hidden
+/// files next to the corpus entries)
+fn corpus_file_names(dir: &Path) -> Result<HashSet<OsString>, Error> {
+    let mut names = HashSet::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.file_type()?.is_file() && !name.to_string_lossy().starts_with('.') {
+            names.insert(name);
+        }
+    }
+    Ok(names)
+}
+
+impl FuzzerStateValidator {
+    pub fn new(corpus_dir: PathBuf, crashes_dir: PathBuf) -> Self {
+        Self {
+            corpus_dir,
+            crashes_dir,
+        }
+    }
+
+    /// Check that the files of all (enabled and disabled) entries of `corpus` exist in the corpus
+    /// directory, that the corpus directory contains no other entries and that the crashes
+    /// directory still exists
+    pub fn validate<C: Corpus<IrInput>>(&self, corpus: &C) -> Result<(), StateValidationError> {
+        let mut on_disk = corpus_file_names(&self.corpus_dir).unwrap_or_default();
+
+        let mut missing_corpus_files = 0;
+        for idx in 0..corpus.count() + corpus.count_disabled() {
+            let id: CorpusId = corpus.nth_from_all(idx);
+            let Ok(testcase) = corpus.get_from_all(id) else {
+                missing_corpus_files += 1;
+                continue;
+            };
+            let name = testcase
+                .borrow()
+                .file_path()
+                .as_ref()
+                .and_then(|path| path.file_name().map(OsString::from));
+            if !name.is_some_and(|name| on_disk.remove(&name)) {
+                missing_corpus_files += 1;
+            }
+        }
+
+        let error = StateValidationError {
+            missing_corpus_files,
+            extra_corpus_files: on_disk.len(),
+            missing_crashes_dir: !self.crashes_dir.is_dir(),
+        };
+        if error.missing_corpus_files > 0
+            || error.extra_corpus_files > 0
+            || error.missing_crashes_dir
+        {
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    /// Move the corpus directory out of the way (to `<corpus dir>.stale`), such that a fresh state
+    /// can be created in its place and reinitialised from the moved entries. If a previous stash
+    /// still exists (e.g. the fuzzer was killed while reinitialising), the entries are merged into
+    /// it instead. The metadata and lock files of the discarded state are removed. Returns the new
+    /// location of the entries.
+    pub fn stash_corpus(&self) -> Result<PathBuf, Error> {
+        let mut stale_name = self
+            .corpus_dir
+            .file_name()
+            .map(OsString::from)
+            .unwrap_or_default();
+        stale_name.push(".stale");
+        let stale_dir = self.corpus_dir.with_file_name(stale_name);
+
+        if !stale_dir.exists() {
+            if self.corpus_dir.exists() {
+                std::fs::rename(&self.corpus_dir, &stale_dir)?;
+            } else {
+                std::fs::create_dir_all(&stale_dir)?;
+            }
+        } else if self.corpus_dir.exists() {
+            for entry in std::fs::read_dir(&self.corpus_dir)? {
+                let entry = entry?;
+                let name = entry.file_name();
+                let mut target = stale_dir.join(&name);
+                let mut suffix = 1;
+                while target.exists() {
+                    target = stale_dir.join(format!("{}-{}", name.to_string_lossy(), suffix));
+                    suffix += 1;
+                }
+                std::fs::rename(entry.path(), target)?;
+            }
+            std::fs::remove_dir(&self.corpus_dir)?;
+        }
+
+        for entry in std::fs::read_dir(&stale_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && entry.file_name().to_string_lossy().starts_with('.')
+            {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(stale_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzamoto_ir::{Operation, ProgramBuilder, ProgramContext};
+    use libafl::corpus::{OnDiskCorpus, Testcase};
+
+    fn input(len: u64) -> IrInput {
+        let mut builder = ProgramBuilder::new(ProgramContext {
+            num_nodes: 1,
+            num_connections: 1,
+            timestamp: 0,
+        });
+        for i in 0..len {
+            builder.force_append(vec![], Operation::LoadTime(i));
+        }
+        IrInput::new(builder.finalize().unwrap())
+    }
+
+    /// Create a corpus with three entries (one disabled) in `<dir>/queue` and a crashes directory
+    fn saved_state(dir: &Path) -> (OnDiskCorpus<IrInput>, FuzzerStateValidator) {
+        let _ = std::fs::remove_dir_all(dir);
+        let corpus_dir = dir.join("queue");
+        let crashes_dir = dir.join("crashes");
+        std::fs::create_dir_all(&crashes_dir).unwrap();
+
+        let mut corpus = OnDiskCorpus::<IrInput>::new(&corpus_dir).unwrap();
+        corpus.add(Testcase::new(input(1))).unwrap();
+        corpus.add(Testcase::new(input(2))).unwrap();
+        corpus.add_disabled(Testcase::new(input(3))).unwrap();
+
+        (corpus, FuzzerStateValidator::new(corpus_dir, crashes_dir))
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fuzzamoto-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn unchanged_state_is_valid() {
+        let dir = test_dir("state-unchanged");
+        let (corpus, validator) = saved_state(&dir);
+        assert_eq!(validator.validate(&corpus), Ok(()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn external_changes_are_detected() {
+        let dir = test_dir("state-changed");
+        let (corpus, validator) = saved_state(&dir);
+
+        // Prune one entry and add two new ones
+        let id = corpus.nth_from_all(0);
+        let path = corpus
+            .get_from_all(id)
+            .unwrap()
+            .borrow()
+            .file_path()
+            .clone();
+        std::fs::remove_file(path.unwrap()).unwrap();
+        std::fs::write(dir.join("queue/new_a"), b"a").unwrap();
+        std::fs::write(dir.join("queue/new_b"), b"b").unwrap();
+        std::fs::remove_dir_all(dir.join("crashes")).unwrap();
+
+        assert_eq!(
+            validator.validate(&corpus),
+            Err(StateValidationError {
+                missing_corpus_files: 1,
+                extra_corpus_files: 2,
+                missing_crashes_dir: true,
+            })
+        );
+
+        let stale_dir = validator.stash_corpus().unwrap();
+        assert_eq!(stale_dir, dir.join("queue.stale"));
+        assert!(!dir.join("queue").exists());
+        assert_eq!(corpus_file_names(&stale_dir).unwrap().len(), 4);
+        assert_eq!(std::fs::read_dir(&stale_dir).unwrap().count(), 4);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn previous_stash_is_merged() {
+        let dir = test_dir("state-merged");
+        let (_, validator) = saved_state(&dir);
+        std::fs::write(dir.join("queue/same_name"), b"old").unwrap();
+        let stale_dir = validator.stash_corpus().unwrap();
+
+        // The stash was not reimported before the next stash
+        std::fs::create_dir_all(dir.join("queue")).unwrap();
+        std::fs::write(dir.join("queue/same_name"), b"new").unwrap();
+        std::fs::write(dir.join("queue/.same_name.metadata"), b"{}").unwrap();
+        std::fs::write(dir.join("queue/other"), b"other").unwrap();
+        assert_eq!(validator.stash_corpus().unwrap(), stale_dir);
+        assert!(!dir.join("queue").exists());
+
+        // The previous entries are kept, entries with the same name are kept under a new name
+        let names = corpus_file_names(&stale_dir).unwrap();
+        assert_eq!(names.len(), 6);
+        assert!(names.contains(&OsString::from("other")));
+        assert_eq!(std::fs::read(stale_dir.join("same_name")).unwrap(), b"old");
+        assert_eq!(
+            std::fs::read(stale_dir.join("same_name-1")).unwrap(),
+            b"new"
+        );
+        assert_eq!(std::fs::read_dir(&stale_dir).unwrap().count(), 6);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,4 +1,10 @@
-use std::{path::PathBuf, process::Command};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Nyx installation used if `NYX_DIR` is not set
+const DEFAULT_NYX_DIR: &str = "/usr/local/nyx";
 
 // Get the afl coverage map size of the given binary
 fn get_map_size(binary: PathBuf) -> Option<String> {
@@ -15,10 +21,48 @@ fn get_map_size(binary: PathBuf) -> Option<String> {
     (!output.is_empty()).then_some(output)
 }
 
-fn main() {
+/// Path of the build config written by `fuzzamoto-cli init` (see
+/// `fuzzamoto-cli/src/utils/nyx.rs`), holding the `--nyx-dir` of the last `init` run
+fn build_config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_dir.join("fuzzamoto").join("nyx_dir"))
+}
+
+/// Find the Nyx installation: `NYX_DIR`, then `DEFAULT_NYX_DIR`, then the `--nyx-dir` recorded by
+/// `fuzzamoto-cli init`
+fn find_nyx_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("NYX_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+
+    let default_dir = PathBuf::from(DEFAULT_NYX_DIR);
+    if default_dir.is_dir() {
+        return Some(default_dir);
+    }
+
+    let config = build_config_path()?;
+    println!("cargo:rerun-if-changed={}", config.display());
+    std::fs::read_to_string(config)
+        .ok()
+        .map(|dir| PathBuf::from(dir.trim()))
+        .filter(|dir| dir.is_dir())
+}
+
+fn build_from_source() {
     let mut build = cc::Build::new();
     build.file("src/nyx-agent.c").define("NO_PT_NYX", None);
 
+    match find_nyx_dir() {
+        Some(nyx_dir) => {
+            build.include(nyx_dir.join("packer/packer"));
+        }
+        None => println!(
+            "cargo:warning=No Nyx installation found (set NYX_DIR), using the bundled nyx.h only"
+        ),
+    }
+
     let _ = std::env::var("BITCOIND_PATH").map(|path| {
         if let Some(size) = get_map_size(path.into()) {
             build.define("TARGET_MAP_SIZE", &*size);
@@ -28,5 +72,30 @@ fn main() {
     build.compile("nyx_agent");
 
     println!("cargo:rerun-if-changed=src/nyx-agent.c");
+    println!("cargo:rerun-if-changed=src/nyx.h");
+    println!("cargo:rerun-if-env-changed=NYX_DIR");
     println!("cargo:rerun-if-env-changed=BITCOIND_PATH");
 }
+
+fn link_prebuilt() {
+    println!("cargo:rerun-if-env-changed=NYX_AGENT_LIB_DIR");
+
+    match std::env::var("NYX_AGENT_LIB_DIR") {
+        Ok(dir) => {
+            println!("cargo:rustc-link-search=native={dir}");
+            println!("cargo:rustc-link-lib=static=nyx_agent");
+        }
+        // The agent's symbols have to be provided when linking the final binary
+        Err(_) => println!(
+            "cargo:warning=NYX_AGENT_LIB_DIR is not set, not linking a pre-built nyx agent (enable the `build-from-source` feature to compile it instead)"
+        ),
+    }
+}
+
+fn main() {
+    if std::env::var_os("CARGO_FEATURE_BUILD_FROM_SOURCE").is_some() {
+        build_from_source();
+    } else {
+        link_prebuilt();
+    }
+}
@@ -0,0 +1,270 @@
+use fuzzamoto::{
+    connections::{Transport, V2Transport},
+    fuzzamoto_main,
+    scenarios::{Scenario, ScenarioInput, ScenarioResult, generic::GenericScenario},
+    targets::{BitcoinCoreTarget, HasTipInfo, Target, TargetNode},
+    test_utils,
+};
+
+use arbitrary::{Arbitrary, Unstructured};
+use bitcoin::{Block, BlockHash, consensus::encode};
+
+// Transport type alias based on feature flag
+#[cfg(not(feature = "v2transport"))]
+type ScenarioTransport = fuzzamoto::connections::V1Transport;
+#[cfg(feature = "v2transport")]
+type ScenarioTransport = fuzzamoto::connections::V2Transport;
+
+/// Maximum number of blocks mined on top of the current tip (chain A)
+const MAX_CHAIN_A_LENGTH: u8 = 10;
+/// Maximum number of blocks mined on top of the fork point (chain B)
+const MAX_CHAIN_B_LENGTH: u8 = 15;
+/// Seconds the mocktime is advanced by between steps (if enabled)
+const TIME_STEP: u64 = 10 * 60;
+
+#[derive(Arbitrary, Debug, Clone)]
+struct TestCase {
+    /// Number of blocks below the current tip at which chain B forks off
+    fork_depth: u8,
+    chain_a_length: u8,
+    chain_b_length: u8,
+    advance_time_between_steps: bool,
+}
+
+impl ScenarioInput<'_> for TestCase {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut unstructured = Unstructured::new(bytes);
+        TestCase::arbitrary(&mut unstructured).map_err(|e| e.to_string())
+    }
+}
+
+/// Two competing chains built on top of the initial chain
+struct ReorgChains {
+    chain_a: Vec<Block>,
+    chain_b: Vec<Block>,
+    /// Hash of the tip the node is expected to end up with
+    expected_tip: BlockHash,
+}
+
+fn mine_chain(
+    mut prev_hash: BlockHash,
+    start_height: u32,
+    length: u8,
+    start_time: u64,
+) -> Result<Vec<Block>, String> {
+    let mut blocks = Vec::new();
+    for i in 0..length as u32 {
+        let block = test_utils::mining::mine_block(
+            prev_hash,
+            start_height + i,
+            (start_time + i as u64) as u32,
+        )?;
+        prev_hash = block.block_hash();
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+/// Build chain A on top of the current tip and chain B on top of the block `fork_depth` blocks
+/// below the tip. `chain` contains the hashes of the initial chain indexed by height.
+fn build_reorg_chains(
+    chain: &[BlockHash],
+    testcase: &TestCase,
+    time: u64,
+) -> Result<ReorgChains, String> {
+    let tip_height = chain.len() as u32 - 1;
+    let fork_depth = (testcase.fork_depth as u32 % MAX_CHAIN_B_LENGTH as u32).min(tip_height);
+    let fork_height = tip_height - fork_depth;
+    let chain_a_length = 1 + testcase.chain_a_length % MAX_CHAIN_A_LENGTH;
+    let chain_b_length = 1 + testcase.chain_b_length % MAX_CHAIN_B_LENGTH;
+
+    // Distinct timestamps ensure the two chains never contain identical blocks
+    let chain_a = mine_chain(
+        chain[tip_height as usize],
+        tip_height + 1,
+        chain_a_length,
+        time + 1,
+    )?;
+    let chain_b = mine_chain(
+        chain[fork_height as usize],
+        fork_height + 1,
+        chain_b_length,
+        time + 1 + MAX_CHAIN_A_LENGTH as u64,
+    )?;
+
+    // All blocks have the same difficulty, so the longer chain wins and ties are won by the chain
+    // seen first (chain A)
+    let chain_a_height = tip_height + chain_a_length as u32;
+    let chain_b_height = fork_height + chain_b_length as u32;
+    let expected_tip = if chain_b_height > chain_a_height {
+        chain_b.last().unwrap().block_hash()
+    } else {
+        chain_a.last().unwrap().block_hash()
+    };
+
+    Ok(ReorgChains {
+        chain_a,
+        chain_b,
+        expected_tip,
+    })
+}
+
+/// `BlockReorgScenario` is a scenario that tests the handling of chain reorganizations.
+///
+/// The scenario setup creates a couple of connections to the target node and mines a chain of 200
+/// blocks. Each testcase describes two competing chains:
+///
+/// 1. Chain A (up to 10 blocks) is mined on top of the current tip and sent via connection 0
+/// 2. Chain B (up to 15 blocks) is mined on top of a fork point below the tip and sent via
+///    connection 1, with its tip being sent last (triggering a reorg if chain B is longer)
+/// 3. The node's tip is compared to the tip of the longer chain
+struct BlockReorgScenario<TX: Transport, T: Target<TX>> {
+    inner: GenericScenario<TX, T>,
+}
+
+impl<TX: Transport, T: Target<TX>> BlockReorgScenario<TX, T> {
+    fn send_blocks(&mut self, connection: usize, blocks: &[Block]) {
+        let num_connections = self.inner.connections.len();
+        let Some(connection) = self.inner.connections.get_mut(connection % num_connections) else {
+            return;
+        };
+        for block in blocks {
+            let _ = connection.send(&("block".to_string(), encode::serialize(block)));
+        }
+    }
+
+    fn advance_time(&mut self, enabled: bool) {
+        if enabled {
+            self.inner.time += TIME_STEP;
+            let _ = self.inner.target.set_mocktime(self.inner.time);
+        }
+    }
+}
+
+impl<TX: Transport, T: Target<TX> + Target<V2Transport> + HasTipInfo> Scenario<'_, TestCase>
+    for BlockReorgScenario<TX, T>
+{
+    fn new(args: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            inner: GenericScenario::new(args)?,
+        })
+    }
+
+    fn run(&mut self, testcase: TestCase) -> ScenarioResult {
+        let mut blocks: Vec<(u32, BlockHash)> = self
+            .inner
+            .block_tree
+            .iter()
+            .map(|(hash, (_, height))| (*height, *hash))
+            .collect();
+        blocks.sort_by_key(|(height, _)| *height);
+
+        let genesis_block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let chain: Vec<BlockHash> = std::iter::once(genesis_block.block_hash())
+            .chain(blocks.into_iter().map(|(_, hash)| hash))
+            .collect();
+
+        let Ok(chains) = build_reorg_chains(&chain, &testcase, self.inner.time) else {
+            return ScenarioResult::Skip;
+        };
+
+        // Step 1: extend the current tip with chain A
+        self.send_blocks(0, &chains.chain_a);
+        self.advance_time(testcase.advance_time_between_steps);
+
+        // Step 2: send chain B (except for its tip) from the fork point
+        let (chain_b_tip, chain_b) = chains.chain_b.split_last().unwrap();
+        self.send_blocks(1, chain_b);
+        self.advance_time(testcase.advance_time_between_steps);
+
+        // Step 3: send chain B's tip, possibly triggering the reorg
+        self.send_blocks(1, std::slice::from_ref(chain_b_tip));
+
+        for connection in self.inner.connections.iter_mut() {
+            let _ = connection.ping();
+        }
+
+        if let Err(e) = self.inner.target.is_alive() {
+            return ScenarioResult::Fail(format!("Target is not alive: {}", e));
+        }
+
+        if let Some((tip, _)) = self.inner.target.get_tip_info()
+            && tip != chains.expected_tip
+        {
+            return ScenarioResult::Fail(format!(
+                "Unexpected tip after reorg: {} (expected {})",
+                tip, chains.expected_tip
+            ));
+        }
+
+        ScenarioResult::Ok
+    }
+}
+
+fuzzamoto_main!(
+    BlockReorgScenario::<ScenarioTransport, BitcoinCoreTarget>,
+    TestCase
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn initial_chain(length: u32) -> Vec<BlockHash> {
+        let genesis_block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let mut chain = vec![genesis_block.block_hash()];
+        let blocks = mine_chain(
+            chain[0],
+            1,
+            length as u8,
+            genesis_block.header.time as u64 + 1,
+        )
+        .unwrap();
+        chain.extend(blocks.iter().map(|block| block.block_hash()));
+        chain
+    }
+
+    #[test]
+    fn five_block_reorg() {
+        let chain = initial_chain(10);
+        let testcase = TestCase {
+            fork_depth: 4,
+            chain_a_length: 0,
+            chain_b_length: 5,
+            advance_time_between_steps: false,
+        };
+
+        let chains = build_reorg_chains(&chain, &testcase, 1_296_688_700).unwrap();
+
+        // Chain A extends the tip by one block (height 11), chain B forks off at height 6 and
+        // reaches height 12, reorging the top 5 blocks (heights 7 to 11).
+        assert_eq!(chains.chain_a.len(), 1);
+        assert_eq!(chains.chain_a[0].header.prev_blockhash, chain[10]);
+        assert_eq!(chains.chain_b.len(), 6);
+        assert_eq!(chains.chain_b[0].header.prev_blockhash, chain[6]);
+        for pair in chains.chain_b.windows(2) {
+            assert_eq!(pair[1].header.prev_blockhash, pair[0].block_hash());
+        }
+        assert_eq!(
+            chains.expected_tip,
+            chains.chain_b.last().unwrap().block_hash()
+        );
+    }
+
+    #[test]
+    fn shorter_fork_keeps_chain_a() {
+        let chain = initial_chain(10);
+        let testcase = TestCase {
+            fork_depth: 5,
+            chain_a_length: 4,
+            chain_b_length: 4,
+            advance_time_between_steps: true,
+        };
+
+        let chains = build_reorg_chains(&chain, &testcase, 1_296_688_700).unwrap();
+        assert_eq!(
+            chains.expected_tip,
+            chains.chain_a.last().unwrap().block_hash()
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use fuzzamoto::{
-    connections::Transport,
+    connections::{Transport, V2Transport},
     fuzzamoto_main,
     scenarios::{Scenario, ScenarioInput, ScenarioResult, generic::GenericScenario},
     targets::{BitcoinCoreTarget, Target},
@@ -204,7 +204,9 @@ impl<TX: Transport, T: Target<TX>> CompactBlocksScenario<TX, T> {
     }
 }
 
-impl<TX: Transport, T: Target<TX>> Scenario<'_, TestCase> for CompactBlocksScenario<TX, T> {
+impl<TX: Transport, T: Target<TX> + Target<V2Transport>> Scenario<'_, TestCase>
+    for CompactBlocksScenario<TX, T>
+{
     fn new(args: &[String]) -> Result<Self, String> {
         let inner = GenericScenario::new(args)?;
 
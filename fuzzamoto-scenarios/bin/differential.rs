@@ -0,0 +1,152 @@
+use fuzzamoto::{
+    connections::Transport,
+    fuzzamoto_main,
+    scenarios::{Scenario, ScenarioInput, ScenarioResult},
+    targets::{
+        BitcoinCoreTarget, DifferentialTarget, HasBlockChainInterface, Target, TargetNode,
+        differential::compare_chain_state,
+    },
+};
+
+use fuzzamoto_ir::{
+    Program,
+    compiler::{CompiledAction, CompiledProgram, Compiler},
+};
+
+// Transport type alias based on feature flag
+#[cfg(not(feature = "v2transport"))]
+type ScenarioTransport = fuzzamoto::connections::V1Transport;
+#[cfg(feature = "v2transport")]
+type ScenarioTransport = fuzzamoto::connections::V2Transport;
+
+pub struct TestCase {
+    program: CompiledProgram,
+}
+
+impl<'a> ScenarioInput<'a> for TestCase {
+    fn decode(bytes: &'a [u8]) -> Result<Self, String> {
+        let program = if cfg!(feature = "compile_in_vm") {
+            let program = Program::from_bytes_versioned(bytes).map_err(|e| e.to_string())?;
+            let mut compiler = Compiler::new();
+            compiler.compile(&program).map_err(|e| e.to_string())?
+        } else {
+            postcard::from_bytes(bytes).map_err(|e| e.to_string())?
+        };
+        Ok(Self { program })
+    }
+}
+
+/// `DifferentialScenario` applies the same IR program to two targets (e.g. Bitcoin Core and
+/// another implementation) and reports a failure if they respond differently or end up with a
+/// different chain state.
+///
+/// All messages are sent over a single connection per target, irrespective of the connection
+/// they were sent on in the program.
+struct DifferentialScenario<TX1, TX2, T1, T2>
+where
+    TX1: Transport,
+    TX2: Transport,
+    T1: Target<TX1>,
+    T2: Target<TX2>,
+{
+    target: DifferentialTarget<TX1, TX2, T1, T2>,
+}
+
+impl<TX1, TX2, T1, T2> Scenario<'_, TestCase> for DifferentialScenario<TX1, TX2, T1, T2>
+where
+    TX1: Transport,
+    TX2: Transport,
+    T1: Target<TX1> + HasBlockChainInterface,
+    T2: Target<TX2> + HasBlockChainInterface,
+{
+    fn new(args: &[String]) -> Result<Self, String> {
+        if args.len() < 3 {
+            return Err("Usage: <primary target> <secondary target>".to_string());
+        }
+
+        let mut target =
+            DifferentialTarget::new(T1::from_path(&args[1])?, T2::from_path(&args[2])?);
+        let genesis_block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        target.set_mocktime(genesis_block.header.time as u64)?;
+
+        Ok(Self { target })
+    }
+
+    fn run(&mut self, testcase: TestCase) -> ScenarioResult {
+        let mut messages = Vec::new();
+        for action in testcase.program.actions {
+            match action {
+                CompiledAction::SendRawMessage(_, command, message) => {
+                    messages.push((command, message));
+                }
+                CompiledAction::SetTime(time) => {
+                    // Deliver the messages sent so far before advancing the time
+                    if let Err(e) = self.run_messages(&mut messages) {
+                        return e;
+                    }
+                    let _ = self.target.set_mocktime(time);
+                }
+                _ => {}
+            }
+        }
+        if let Err(e) = self.run_messages(&mut messages) {
+            return e;
+        }
+
+        match compare_chain_state(&self.target.primary, &self.target.secondary) {
+            Ok(diff) if !diff.is_consistent() => {
+                return ScenarioResult::Fail(format!("Chain states diverged: {:?}", diff));
+            }
+            Err(e) => log::warn!("Failed to compare chain states: {}", e),
+            _ => {}
+        }
+
+        if let Err(e) = self.target.is_alive() {
+            return ScenarioResult::Fail(format!("Target is not alive: {}", e));
+        }
+
+        ScenarioResult::Ok
+    }
+}
+
+impl<TX1, TX2, T1, T2> DifferentialScenario<TX1, TX2, T1, T2>
+where
+    TX1: Transport,
+    TX2: Transport,
+    T1: Target<TX1>,
+    T2: Target<TX2>,
+{
+    fn run_messages(
+        &mut self,
+        messages: &mut Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ScenarioResult> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let result = self
+            .target
+            .run_differential(messages)
+            .map_err(|e| ScenarioResult::Fail(format!("Failed to run messages: {}", e)))?;
+        messages.clear();
+
+        if let Some(divergence) = result.divergence {
+            return Err(ScenarioResult::Fail(format!(
+                "Responses diverged: {:?}",
+                divergence
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+fuzzamoto_main!(
+    DifferentialScenario::<
+        ScenarioTransport,
+        ScenarioTransport,
+        BitcoinCoreTarget,
+        BitcoinCoreTarget,
+    >,
+    TestCase
+);
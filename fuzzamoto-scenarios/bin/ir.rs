@@ -3,12 +3,13 @@ use std::time::{Duration, Instant};
 
 use bitcoin::{bip152::BlockTransactionsRequest, consensus::Decodable, hashes::Hash};
 use fuzzamoto::{
-    connections::Transport,
+    connections::{Transport, V2Transport},
     fuzzamoto_main,
     oracles::{CrashOracle, Oracle, OracleResult},
     scenarios::{Scenario, ScenarioInput, ScenarioResult, generic::GenericScenario},
     targets::{
-        BitcoinCoreTarget, ConnectableTarget, GenerateToAddress, HasBlockChainInterface, Target,
+        BitcoinCoreTarget, ConnectableTarget, GenerateToAddress, HasBlockChainInterface,
+        HasGetBlockFilter, Target,
     },
 };
 
@@ -31,7 +32,8 @@ use fuzzamoto::oracles::{NetSplitContext, NetSplitOracle};
 use fuzzamoto::oracles::{ConsensusContext, ConsensusOracle};
 
 use fuzzamoto_ir::{
-    ProbeResult, ProbeResults, Program, ProgramContext, RecentBlock,
+    FullProgramContext, ObservedNodeState, ProbeResult, ProbeResults, Program, ProgramContext,
+    RecentBlock,
     compiler::{CompiledAction, CompiledMetadata, CompiledProgram, Compiler},
 };
 
@@ -56,6 +58,8 @@ struct IrScenario<TX: Transport, T: Target<TX> + ConnectableTarget> {
     inner: GenericScenario<TX, T>,
     recording_received_messages: bool,
     probe_results: ProbeResults,
+    /// Commands and payload lengths of the messages received during the current testcase
+    received_messages: Vec<(String, usize)>,
     #[cfg(any(feature = "oracle_netsplit", feature = "oracle_consensus"))]
     second: T,
     futurest: u64,
@@ -124,7 +128,7 @@ fn probe_result_mapper(
 impl<'a> ScenarioInput<'a> for TestCase {
     fn decode(bytes: &'a [u8]) -> Result<Self, String> {
         let program = if cfg!(feature = "compile_in_vm") {
-            let program: Program = postcard::from_bytes(bytes).map_err(|e| e.to_string())?;
+            let program = Program::from_bytes_versioned(bytes).map_err(|e| e.to_string())?;
             let mut compiler = Compiler::new();
             compiler.compile(&program).map_err(|e| e.to_string())?
         } else {
@@ -137,13 +141,17 @@ impl<'a> ScenarioInput<'a> for TestCase {
 impl<TX, T> IrScenario<TX, T>
 where
     TX: Transport,
-    T: Target<TX> + ConnectableTarget + HasBlockChainInterface + GenerateToAddress,
+    T: Target<TX>
+        + ConnectableTarget
+        + HasBlockChainInterface
+        + HasGetBlockFilter
+        + GenerateToAddress,
 {
     /// Build the IR program context
     fn build_program_context(inner: &GenericScenario<TX, T>) -> ProgramContext {
         ProgramContext {
             num_nodes: 1,
-            num_connections: inner.connections.len(),
+            num_connections: inner.num_connections(),
             timestamp: inner.time,
         }
     }
@@ -195,18 +203,20 @@ where
             .collect()
     }
 
+    /// Hashes of the late blocks (height > 190), i.e. the blocks whose headers are part of the
+    /// program context
+    fn late_block_hashes(inner: &GenericScenario<TX, T>) -> Vec<bitcoin::BlockHash> {
+        inner
+            .block_tree
+            .values()
+            .filter(|(_, height)| *height > LATE_BLOCK_HEIGHT_LIMIT)
+            .map(|(block, _)| block.block_hash())
+            .collect()
+    }
+
     /// Dump the full program context either to Nyx host or to a file
-    fn dump_context(
-        context: ProgramContext,
-        txos: Vec<fuzzamoto_ir::Txo>,
-        headers: Vec<fuzzamoto_ir::Header>,
-    ) -> Result<(), String> {
-        let full_context = postcard::to_allocvec(&fuzzamoto_ir::FullProgramContext {
-            context,
-            txos,
-            headers,
-        })
-        .map_err(|e| e.to_string())?;
+    fn dump_context(full_context: &FullProgramContext) -> Result<(), String> {
+        let full_context = postcard::to_allocvec(full_context).map_err(|e| e.to_string())?;
 
         #[cfg(feature = "nyx")]
         {
@@ -275,33 +285,40 @@ where
         for action in program.actions.drain(..) {
             match action {
                 CompiledAction::SendRawMessage(from, command, message) => {
-                    if self.inner.connections.is_empty() {
+                    let num_connections = self.inner.num_connections();
+                    if num_connections == 0 {
                         return;
                     }
 
-                    let num_connections = self.inner.connections.len();
                     let dst = from % num_connections;
 
-                    if let Some(connection) = self.inner.connections.get_mut(dst) {
-                        if cfg!(feature = "force_send_and_ping") {
-                            if let Ok(received) = connection.send_and_recv(
-                                &(command, message),
-                                self.recording_received_messages,
-                            ) {
-                                self.probe_results.extend(
-                                    received
-                                        .into_iter()
-                                        .filter(message_filter)
-                                        .map(|(s, v)| (dst, s, v))
-                                        .map(probe_result_mapper(
-                                            non_probe_action_count,
-                                            &program.metadata,
-                                        )),
-                                );
-                            }
-                        } else {
-                            let _ = connection.send(&(command, message));
+                    if cfg!(feature = "force_send_and_ping") {
+                        // Received messages are always recorded (see
+                        // `ProbeResult::ReceivedMessages`), but only mapped to other probe results
+                        // if probing is enabled
+                        let recording = self.recording_received_messages;
+                        if let Ok(received) =
+                            self.inner.send_and_recv(dst, &(command, message), true)
+                        {
+                            self.received_messages.extend(
+                                received
+                                    .iter()
+                                    .map(|(command, payload)| (command.clone(), payload.len())),
+                            );
+                            self.probe_results.extend(
+                                received
+                                    .into_iter()
+                                    .filter(|_| recording)
+                                    .filter(message_filter)
+                                    .map(|(s, v)| (dst, s, v))
+                                    .map(probe_result_mapper(
+                                        non_probe_action_count,
+                                        &program.metadata,
+                                    )),
+                            );
                         }
+                    } else {
+                        let _ = self.inner.send(dst, &(command, message));
                     }
                     non_probe_action_count += 1;
                 }
@@ -334,9 +351,7 @@ where
     }
 
     fn ping_connections(&mut self) {
-        for connection in self.inner.connections.iter_mut() {
-            let _ = connection.ping();
-        }
+        self.inner.ping_connections();
     }
 
     fn evaluate_oracles(&mut self) -> ScenarioResult {
@@ -400,22 +415,27 @@ where
 
 const NUM_RECENT_BLOCKS: u64 = 10;
 
-pub fn probe_recent_block_hashes<T: HasBlockChainInterface>(
+/// Walk back from the tip and collect the heights and hashes of the `NUM_RECENT_BLOCKS` most
+/// recent blocks
+fn recent_block_hashes<T: HasBlockChainInterface>(
     target: &T,
-    meta: &CompiledMetadata,
-) -> Option<ProbeResult> {
-    // get current height
+) -> Option<Vec<(u64, bitcoin::BlockHash)>> {
     let mut hashes = Vec::new();
     let (mut hash, height) = target.get_tip_info()?;
-    for back in 0..NUM_RECENT_BLOCKS {
-        let new_height = height - back;
-        hashes.push((new_height, hash));
+    for back in 0..NUM_RECENT_BLOCKS.min(height + 1) {
+        hashes.push((height - back, hash));
         let block = target.get_block(hash)?;
         hash = block.header.prev_blockhash;
     }
+    Some(hashes)
+}
 
+pub fn probe_recent_block_hashes(
+    hashes: &[(u64, bitcoin::BlockHash)],
+    meta: &CompiledMetadata,
+) -> ProbeResult {
     let mut result = Vec::new();
-    for (height, hash) in &hashes {
+    for (height, hash) in hashes {
         if let Some((header, _, _)) = meta.block_variables(&hash)
             && let Some(inst) = meta.variable_indices().get(header)
         {
@@ -425,13 +445,40 @@ pub fn probe_recent_block_hashes<T: HasBlockChainInterface>(
             })
         }
     }
-    return Some(ProbeResult::RecentBlockes { result: result });
+    return ProbeResult::RecentBlockes { result: result };
+}
+
+pub fn probe_runtime_metadata<T: HasBlockChainInterface>(
+    target: &T,
+    hashes: &[(u64, bitcoin::BlockHash)],
+    peer_count: usize,
+) -> Option<ProbeResult> {
+    let txids: Vec<_> = target
+        .get_mempool_entries()
+        .ok()?
+        .iter()
+        .map(|entry| *entry.txid())
+        .collect();
+
+    Some(ProbeResult::Runtime {
+        runtime: ObservedNodeState {
+            mempool_size: txids.len() as u64,
+            txids,
+            block_hashes: hashes.iter().map(|(_, hash)| *hash).collect(),
+            peer_count,
+        },
+    })
 }
 
 impl<TX, T> Scenario<'_, TestCase> for IrScenario<TX, T>
 where
     TX: Transport,
-    T: Target<TX> + ConnectableTarget + HasBlockChainInterface + GenerateToAddress,
+    T: Target<TX>
+        + Target<V2Transport>
+        + ConnectableTarget
+        + HasBlockChainInterface
+        + HasGetBlockFilter
+        + GenerateToAddress,
 {
     fn new(args: &[String]) -> Result<Self, String> {
         let inner: GenericScenario<TX, T> = GenericScenario::new(args)?;
@@ -439,9 +486,20 @@ where
         let context = Self::build_program_context(&inner);
         log::info!("IR context: {:?}", context);
 
-        let txos = Self::build_txos(&inner);
-        let headers = Self::build_headers(&inner);
-        Self::dump_context(context, txos, headers)?;
+        let mut full_context = FullProgramContext {
+            context,
+            txos: Self::build_txos(&inner),
+            headers: Self::build_headers(&inner),
+            block_filters: Vec::new(),
+        };
+        // Compact block filters are optional, e.g. the block filter index might still be syncing
+        if let Err(e) =
+            full_context.populate_filters(&inner.target, &Self::late_block_hashes(&inner))
+        {
+            log::warn!("Failed to load compact block filters: {}", e);
+            full_context.block_filters.clear();
+        }
+        Self::dump_context(&full_context)?;
 
         #[cfg(any(feature = "oracle_netsplit", feature = "oracle_consensus"))]
         let second = Self::create_and_sync_second_target(args, &inner.target)?;
@@ -454,6 +512,7 @@ where
             inner,
             recording_received_messages: false,
             probe_results: Vec::new(),
+            received_messages: Vec::new(),
             #[cfg(any(feature = "oracle_netsplit", feature = "oracle_consensus"))]
             second,
             futurest: genesis_time as u64,
@@ -465,12 +524,24 @@ where
         self.process_actions(testcase.program);
         self.ping_connections();
 
-        if self.recording_received_messages {
-            if let Some(ret) = probe_recent_block_hashes(&self.inner.target, &metadata) {
+        if self.recording_received_messages
+            && let Some(hashes) = recent_block_hashes(&self.inner.target)
+        {
+            self.probe_results
+                .push(probe_recent_block_hashes(&hashes, &metadata));
+            if let Some(ret) =
+                probe_runtime_metadata(&self.inner.target, &hashes, self.inner.num_connections())
+            {
                 self.probe_results.push(ret);
             }
         }
 
+        if !self.received_messages.is_empty() {
+            self.probe_results.push(ProbeResult::ReceivedMessages {
+                messages: std::mem::take(&mut self.received_messages),
+            });
+        }
+
         self.print_received();
         self.evaluate_oracles()
     }
@@ -0,0 +1,314 @@
+use std::collections::VecDeque;
+
+use fuzzamoto::{
+    connections::{Transport, V2Transport},
+    fuzzamoto_main,
+    oracles::{FeeRateContext, FeeRateOracle, Oracle, OracleResult},
+    scenarios::{Scenario, ScenarioInput, ScenarioResult, generic::GenericScenario},
+    targets::{BitcoinCoreTarget, HasEstimateSmartFee, HasGetRawMempoolEntries, Target},
+    test_utils,
+};
+
+use arbitrary::{Arbitrary, Unstructured};
+use bitcoin::{
+    Amount, BlockHash, OutPoint, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+    blockdata::opcodes::{OP_0, OP_TRUE},
+    consensus::encode,
+    hashes::{Hash, sha256},
+    script::ScriptBuf,
+    transaction,
+};
+
+// Transport type alias based on feature flag
+#[cfg(not(feature = "v2transport"))]
+type ScenarioTransport = fuzzamoto::connections::V1Transport;
+#[cfg(feature = "v2transport")]
+type ScenarioTransport = fuzzamoto::connections::V2Transport;
+
+/// Coinbase outputs only become spendable after 100 confirmations
+const COINBASE_MATURITY: u32 = 100;
+/// Fee rate (in sat/vB) of the transactions sent by `FloodMempoolAtLowFee`
+const LOW_FEE_RATE: u64 = 1;
+/// Outputs below this value (in sats) are considered dust for P2WSH scripts
+const DUST_LIMIT: Amount = Amount::from_sat(330);
+
+#[derive(Arbitrary, Debug, Clone)]
+enum MempoolPolicyAction {
+    /// Build and send a transaction paying `feerate_sat_vb` sat/vB
+    SendTxAtFeerate { feerate_sat_vb: u16 },
+    /// Ask the node not to relay transactions below `feerate_msat_vb` msat/vB (i.e. sat/kvB) to
+    /// us, via `feefilter`
+    SetMinRelayFee { feerate_msat_vb: u32 },
+    /// Query `estimatesmartfee` for `target_blocks` and check the estimate with the
+    /// `FeeRateOracle`
+    QueryEstimateFee { target_blocks: u8 },
+    /// Send `count` transactions paying `LOW_FEE_RATE` to test eviction
+    FloodMempoolAtLowFee { count: u8 },
+    /// Mine a block confirming the sent transactions that made it into the mempool, giving the
+    /// fee estimator data to work with
+    MineBlock,
+}
+
+#[derive(Arbitrary, Debug, Clone)]
+struct TestCase {
+    txs: Vec<MempoolPolicyAction>,
+}
+
+impl ScenarioInput<'_> for TestCase {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut unstructured = Unstructured::new(bytes);
+        let txs = Vec::arbitrary(&mut unstructured).map_err(|e| e.to_string())?;
+        Ok(Self { txs })
+    }
+}
+
+fn p2wsh_op_true_spk() -> ScriptBuf {
+    let mut spk = vec![OP_0.to_u8(), 32];
+    spk.extend(sha256::Hash::hash(&[OP_TRUE.to_u8()]).as_byte_array());
+    spk.into()
+}
+
+/// Create a transaction spending the P2WSH-OP_TRUE output `outpoint` (worth `value`) into two
+/// P2WSH-OP_TRUE outputs of (almost) equal value, paying `fee_rate` sat/vB.
+fn split_op_true(outpoint: OutPoint, value: Amount, fee_rate: u64) -> Result<Transaction, String> {
+    let mut witness = Witness::new();
+    witness.push([OP_TRUE.to_u8()]);
+
+    let output = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: p2wsh_op_true_spk(),
+    };
+    let mut tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness,
+        }],
+        output: vec![output.clone(), output],
+    };
+
+    let fee = Amount::from_sat(fee_rate * tx.vsize() as u64);
+    let remaining = value
+        .checked_sub(fee)
+        .ok_or_else(|| "Insufficient funds to pay for transaction fee".to_string())?;
+    let half = remaining / 2;
+    if half < DUST_LIMIT {
+        return Err("Outputs would be dust".to_string());
+    }
+    tx.output[0].value = half;
+    tx.output[1].value = remaining - half;
+    Ok(tx)
+}
+
+/// `MempoolPolicyScenario` is a scenario that tests fee estimation and mempool policy.
+///
+/// The scenario setup creates a couple of connections to the target node and mines a chain of 200
+/// blocks. Testcases are a series of actions, each performed on the next connection (round
+/// robin):
+///
+/// 1. Send a transaction at a fuzzed fee rate, splitting one of the available outputs (the mature
+///    coinbases and the outputs of previously sent transactions)
+/// 2. Set the fee filter of the connection (Bitcoin Core's minimum relay fee can only be
+///    configured on startup, `feefilter` is the per-peer equivalent for relay to that peer)
+/// 3. Query `estimatesmartfee`, checking the estimate with the `FeeRateOracle`
+/// 4. Flood the mempool with low fee rate transactions
+/// 5. Mine a block with the sent transactions that are in the mempool
+struct MempoolPolicyScenario<TX: Transport, T: Target<TX>> {
+    inner: GenericScenario<TX, T>,
+
+    /// Outputs that can be spent by the next transaction (oldest first, to keep the chains of
+    /// unconfirmed transactions short)
+    utxos: VecDeque<(OutPoint, Amount)>,
+    /// Sent transactions that have not been mined yet, in the order they were sent
+    unconfirmed: Vec<Transaction>,
+    /// Fee rates (in sat/kvB) of all sent transactions
+    fee_rates: Vec<u64>,
+    /// Hash and height of the current tip
+    tip: (BlockHash, u32),
+}
+
+impl<TX: Transport, T: Target<TX> + HasGetRawMempoolEntries> MempoolPolicyScenario<TX, T> {
+    fn send_tx(&mut self, connection: usize, fee_rate: u64) {
+        let Some((outpoint, value)) = self.utxos.pop_front() else {
+            return;
+        };
+        let Ok(tx) = split_op_true(outpoint, value, fee_rate) else {
+            return;
+        };
+
+        let _ =
+            self.inner.connections[connection].send(&("tx".to_string(), encode::serialize(&tx)));
+
+        let txid = tx.compute_txid();
+        for (vout, output) in tx.output.iter().enumerate() {
+            self.utxos
+                .push_back((OutPoint::new(txid, vout as u32), output.value));
+        }
+        self.fee_rates.push(fee_rate * 1000);
+        self.unconfirmed.push(tx);
+    }
+
+    fn mine_block(&mut self, connection: usize) {
+        let mempool: Vec<Txid> = match self.inner.target.get_mempool_entries() {
+            Ok(entries) => entries.iter().map(|entry| *entry.txid()).collect(),
+            Err(_) => return,
+        };
+
+        self.inner.time += 1;
+        let (prev_hash, height) = self.tip;
+        let Ok(mut block) =
+            test_utils::mining::mine_block(prev_hash, height + 1, self.inner.time as u32)
+        else {
+            return;
+        };
+
+        // Transactions were sent after their parents, so the block is topologically sorted
+        let (confirmed, unconfirmed): (Vec<Transaction>, Vec<Transaction>) =
+            std::mem::take(&mut self.unconfirmed)
+                .into_iter()
+                .partition(|tx| mempool.contains(&tx.compute_txid()));
+        self.unconfirmed = unconfirmed;
+        block.txdata.extend(confirmed);
+
+        test_utils::mining::fixup_commitments(&mut block);
+        test_utils::mining::fixup_proof_of_work(&mut block);
+
+        let _ = self.inner.target.set_mocktime(self.inner.time);
+        let _ = self.inner.connections[connection]
+            .send(&("block".to_string(), encode::serialize(&block)));
+        self.tip = (block.block_hash(), height + 1);
+    }
+}
+
+impl<
+    TX: Transport,
+    T: Target<TX> + Target<V2Transport> + HasEstimateSmartFee + HasGetRawMempoolEntries,
+> Scenario<'_, TestCase> for MempoolPolicyScenario<TX, T>
+{
+    fn new(args: &[String]) -> Result<Self, String> {
+        let inner = GenericScenario::new(args)?;
+
+        let utxos = inner
+            .block_tree
+            .values()
+            .filter(|(_, height)| *height <= COINBASE_MATURITY)
+            .map(|(block, _)| {
+                let coinbase = &block.txdata[0];
+                (
+                    OutPoint::new(coinbase.compute_txid(), 0),
+                    coinbase.output[0].value,
+                )
+            })
+            .collect();
+        let tip = inner
+            .block_tree
+            .iter()
+            .map(|(hash, (_, height))| (*hash, *height))
+            .max_by_key(|(_, height)| *height)
+            .ok_or_else(|| "Scenario requires an initial chain".to_string())?;
+
+        Ok(Self {
+            inner,
+            utxos,
+            unconfirmed: Vec::new(),
+            fee_rates: Vec::new(),
+            tip,
+        })
+    }
+
+    fn run(&mut self, testcase: TestCase) -> ScenarioResult {
+        let num_connections = self.inner.connections.len();
+        if num_connections == 0 {
+            return ScenarioResult::Skip;
+        }
+        for (index, action) in testcase.txs.into_iter().enumerate() {
+            let connection = index % num_connections;
+            match action {
+                MempoolPolicyAction::SendTxAtFeerate { feerate_sat_vb } => {
+                    self.send_tx(connection, feerate_sat_vb as u64);
+                }
+                MempoolPolicyAction::SetMinRelayFee { feerate_msat_vb } => {
+                    // `feefilter` fee rates are in sat/kvB, which is the same as msat/vB
+                    let fee_filter = encode::serialize(&(feerate_msat_vb as i64));
+                    let _ = self.inner.connections[connection]
+                        .send(&("feefilter".to_string(), fee_filter));
+                }
+                MempoolPolicyAction::QueryEstimateFee { target_blocks } => {
+                    // Make sure all sent transactions have been processed
+                    let _ = self.inner.connections[connection].ping();
+
+                    let mut context = FeeRateContext {
+                        target: &self.inner.target,
+                        conf_target: (target_blocks as u16).max(1),
+                        fee_rates: &self.fee_rates,
+                    };
+                    let oracle = FeeRateOracle::<TX>::default();
+                    if let OracleResult::Fail(e) = oracle.evaluate(&mut context) {
+                        return ScenarioResult::Fail(format!("{} failed: {}", oracle.name(), e));
+                    }
+                }
+                MempoolPolicyAction::FloodMempoolAtLowFee { count } => {
+                    for _ in 0..count {
+                        self.send_tx(connection, LOW_FEE_RATE);
+                    }
+                }
+                MempoolPolicyAction::MineBlock => {
+                    // The mempool has to be up to date before selecting the block's transactions
+                    for connection in self.inner.connections.iter_mut() {
+                        let _ = connection.ping();
+                    }
+                    self.mine_block(connection);
+                }
+            }
+        }
+
+        for connection in self.inner.connections.iter_mut() {
+            let _ = connection.ping();
+        }
+
+        if let Err(e) = self.inner.target.is_alive() {
+            return ScenarioResult::Fail(format!("Target is not alive: {}", e));
+        }
+
+        ScenarioResult::Ok
+    }
+}
+
+fuzzamoto_main!(
+    MempoolPolicyScenario::<ScenarioTransport, BitcoinCoreTarget>,
+    TestCase
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_pays_fee_rate() {
+        let block = test_utils::mining::mine_block(BlockHash::all_zeros(), 1, 2).unwrap();
+        let coinbase = &block.txdata[0];
+        let outpoint = OutPoint::new(coinbase.compute_txid(), 0);
+
+        let tx = split_op_true(outpoint, coinbase.output[0].value, 7).unwrap();
+        assert_eq!(tx.input[0].previous_output, outpoint);
+        assert_eq!(tx.output.len(), 2);
+        let total: Amount = tx.output.iter().map(|output| output.value).sum();
+        assert_eq!(
+            coinbase.output[0].value - total,
+            Amount::from_sat(7 * tx.vsize() as u64)
+        );
+        assert!(tx.output[1].value - tx.output[0].value <= Amount::from_sat(1));
+    }
+
+    #[test]
+    fn split_rejects_dust_and_insufficient_funds() {
+        let outpoint = OutPoint::new(Txid::all_zeros(), 0);
+        assert!(split_op_true(outpoint, Amount::from_sat(100), 1).is_err());
+        assert!(split_op_true(outpoint, Amount::from_sat(800), 1).is_err());
+        assert!(split_op_true(outpoint, Amount::from_sat(10_000), 1).is_ok());
+        assert!(split_op_true(outpoint, Amount::from_sat(10_000), u16::MAX as u64).is_err());
+    }
+}
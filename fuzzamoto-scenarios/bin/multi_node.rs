@@ -0,0 +1,379 @@
+use std::time::{Duration, Instant};
+
+use fuzzamoto::{
+    connections::{Connection, ConnectionType, HandshakeOpts, Transport},
+    fuzzamoto_main,
+    scenarios::{Scenario, ScenarioInput, ScenarioResult},
+    targets::{BitcoinCoreTarget, ConnectableTarget, Target, TargetNode},
+    test_utils,
+};
+
+use arbitrary::{Arbitrary, Unstructured};
+use bitcoin::consensus::encode;
+use serde::Deserialize;
+
+// Transport type alias based on feature flag
+#[cfg(not(feature = "v2transport"))]
+type ScenarioTransport = fuzzamoto::connections::V1Transport;
+#[cfg(feature = "v2transport")]
+type ScenarioTransport = fuzzamoto::connections::V2Transport;
+
+/// Number of blocks mined on top of the genesis block during the setup
+const INITIAL_BLOCKS: u32 = 200;
+/// Maximum time to wait for the nodes to establish the connections of the topology
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Message types the testcase's messages can be sent as
+const MESSAGE_TYPES: [&str; 28] = [
+    "addr",
+    "block",
+    "blocktxn",
+    "cmpctblock",
+    "feefilter",
+    "filteradd",
+    "filterclear",
+    "filterload",
+    "getblocks",
+    "getblocktxn",
+    "getdata",
+    "getheaders",
+    "headers",
+    "inv",
+    "mempool",
+    "merkleblock",
+    "notfound",
+    "ping",
+    "pong",
+    "sendcmpct",
+    "tx",
+    "getcfilters",
+    "cfilter",
+    "getcfheaders",
+    "cfheaders",
+    "getcfcheckpt",
+    "cfcheckpt",
+    "addrv2",
+];
+
+/// `MultiNodeScenarioConfig` describes the nodes spawned by `MultiNodeScenario` and how they are
+/// connected to each other
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct MultiNodeScenarioConfig {
+    num_primaries: usize,
+    num_secondaries: usize,
+    /// Pairs of node indices `(from, to)`, each making node `from` open an outbound connection
+    /// to node `to`. Primaries are indexed before secondaries.
+    topology: Vec<(usize, usize)>,
+}
+
+impl Default for MultiNodeScenarioConfig {
+    fn default() -> Self {
+        Self {
+            num_primaries: 1,
+            num_secondaries: 1,
+            topology: vec![(0, 1)],
+        }
+    }
+}
+
+impl MultiNodeScenarioConfig {
+    /// Parse and validate a JSON encoded config
+    fn from_json(json: &str) -> Result<Self, String> {
+        let config: Self = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read the config from the JSON file at `FUZZAMOTO_MULTI_NODE_CONFIG`, falling back to the
+    /// default config (a single primary connected to a single secondary) if the variable is unset
+    fn from_env() -> Result<Self, String> {
+        match std::env::var("FUZZAMOTO_MULTI_NODE_CONFIG") {
+            Ok(path) => {
+                let json = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read config {}: {}", path, e))?;
+                Self::from_json(&json)
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.num_primaries == 0 {
+            return Err("At least one primary node is required".to_string());
+        }
+
+        let num_nodes = self.num_nodes();
+        if let Some((from, to)) = self
+            .topology
+            .iter()
+            .find(|(from, to)| from == to || *from >= num_nodes || *to >= num_nodes)
+        {
+            return Err(format!(
+                "Invalid connection in topology: {} -> {}",
+                from, to
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn num_nodes(&self) -> usize {
+        self.num_primaries + self.num_secondaries
+    }
+}
+
+/// Message sent by the fuzzer to one of the nodes
+#[derive(Arbitrary, Debug, Clone)]
+struct DualNodeMessage {
+    /// Index of the receiving node
+    receiver: u8,
+    /// Send on the connection the receiver opened to the fuzzer instead of the one the fuzzer
+    /// opened to the receiver (only secondaries have such an outbound connection)
+    outbound: bool,
+    /// Index into `MESSAGE_TYPES`
+    message_type: u8,
+    payload: Vec<u8>,
+}
+
+#[derive(Arbitrary, Debug, Clone)]
+struct DualNodeTestCase {
+    messages: Vec<DualNodeMessage>,
+}
+
+impl ScenarioInput<'_> for DualNodeTestCase {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut unstructured = Unstructured::new(bytes);
+        DualNodeTestCase::arbitrary(&mut unstructured).map_err(|e| e.to_string())
+    }
+}
+
+/// Connections between the fuzzer and one of the nodes
+struct NodeConnections<TX: Transport> {
+    /// Connection opened by the fuzzer to the node
+    inbound: Connection<TX>,
+    /// Connection opened by the node to the fuzzer (secondaries only)
+    outbound: Option<Connection<TX>>,
+}
+
+/// `MultiNodeScenario` is a scenario that tests message processing and relay between multiple
+/// nodes connected in a configurable topology (see `MultiNodeScenarioConfig`), e.g. a relay node
+/// in between the fuzzer and another node.
+///
+/// The scenario setup spawns the primary (`args[1]`) and secondary (`args[2]`, defaulting to
+/// `args[1]`) nodes, establishes the connections of the topology with
+/// `ConnectableTarget::connect_grid` and connects the fuzzer to all nodes. Secondaries
+/// additionally open an outbound connection to the fuzzer, i.e. with the default topology the
+/// fuzzer reaches the primary directly, through the secondary and via the secondary's outbound
+/// connection. A chain of 200 blocks is mined and sent to all nodes. Each testcase:
+///
+/// 1. Sends a sequence of messages, each to the node and over the connection it selects
+/// 2. Ensures all messages were processed through a ping/pong roundtrip on all connections
+/// 3. Checks that all nodes are still alive
+struct MultiNodeScenario<TX: Transport, T: Target<TX>> {
+    nodes: Vec<T>,
+    connections: Vec<NodeConnections<TX>>,
+}
+
+impl<TX: Transport, T: Target<TX> + ConnectableTarget> MultiNodeScenario<TX, T> {
+    fn spawn_nodes(args: &[String], config: &MultiNodeScenarioConfig) -> Result<Vec<T>, String> {
+        let secondary_path = args.get(2).unwrap_or(&args[1]);
+        let mut nodes = Vec::with_capacity(config.num_nodes());
+        for _ in 0..config.num_primaries {
+            nodes.push(T::from_path(&args[1])?);
+        }
+        for _ in 0..config.num_secondaries {
+            nodes.push(T::from_path(secondary_path)?);
+        }
+        Ok(nodes)
+    }
+
+    /// Wait until all connections of `topology` have been established
+    fn wait_for_topology(nodes: &[T], topology: &[(usize, usize)]) -> Result<(), String> {
+        let start = Instant::now();
+        while start.elapsed() < CONNECT_TIMEOUT {
+            if topology
+                .iter()
+                .all(|(from, to)| nodes[*from].is_connected_to(&nodes[*to]))
+            {
+                return Ok(());
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        Err("Nodes failed to establish the topology".to_string())
+    }
+
+    fn with_config(mut nodes: Vec<T>, config: &MultiNodeScenarioConfig) -> Result<Self, String> {
+        let genesis_block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let mut time = genesis_block.header.time as u64;
+        for node in nodes.iter_mut() {
+            node.set_mocktime(time)?;
+        }
+
+        T::connect_grid::<TX>(&mut nodes, &config.topology)?;
+        Self::wait_for_topology(&nodes, &config.topology)?;
+
+        let handshake_opts = |time: u64| HandshakeOpts {
+            time: time as i64,
+            relay: true,
+            starting_height: 0,
+            wtxidrelay: true,
+            addrv2: true,
+            erlay: false,
+        };
+        let mut connections = Vec::with_capacity(nodes.len());
+        for (index, node) in nodes.iter_mut().enumerate() {
+            let mut inbound = node.connect(ConnectionType::Inbound)?;
+            inbound.version_handshake(handshake_opts(time))?;
+
+            let outbound = if index >= config.num_primaries {
+                let mut outbound = node.connect(ConnectionType::Outbound)?;
+                outbound.version_handshake(handshake_opts(time))?;
+                Some(outbound)
+            } else {
+                None
+            };
+
+            connections.push(NodeConnections { inbound, outbound });
+        }
+
+        let mut prev_hash = genesis_block.block_hash();
+        for height in 1..=INITIAL_BLOCKS {
+            time += 1;
+
+            let block = test_utils::mining::mine_block(prev_hash, height, time as u32)?;
+            let message = ("block".to_string(), encode::serialize(&block));
+            for (node, connections) in nodes.iter_mut().zip(connections.iter_mut()) {
+                connections.inbound.send(&message)?;
+                node.set_mocktime(time)?;
+            }
+
+            prev_hash = block.block_hash();
+        }
+
+        let mut scenario = Self { nodes, connections };
+        scenario.ping_connections();
+
+        Ok(scenario)
+    }
+
+    /// Ping all connections, ensuring all previously sent messages have been processed
+    fn ping_connections(&mut self) {
+        for connections in self.connections.iter_mut() {
+            let _ = connections.inbound.ping();
+            if let Some(outbound) = connections.outbound.as_mut() {
+                let _ = outbound.ping();
+            }
+        }
+    }
+
+    fn send(&mut self, message: &DualNodeMessage) -> Result<(), String> {
+        let connections = &mut self.connections[message.receiver as usize % self.nodes.len()];
+        let connection = match connections.outbound.as_mut() {
+            Some(outbound) if message.outbound => outbound,
+            _ => &mut connections.inbound,
+        };
+
+        let message_type = MESSAGE_TYPES[message.message_type as usize % MESSAGE_TYPES.len()];
+        connection.send(&(message_type.to_string(), message.payload.clone()))
+    }
+}
+
+impl<TX: Transport, T: Target<TX> + ConnectableTarget> Scenario<'_, DualNodeTestCase>
+    for MultiNodeScenario<TX, T>
+{
+    fn new(args: &[String]) -> Result<Self, String> {
+        let config = MultiNodeScenarioConfig::from_env()?;
+        log::info!("Multi node config: {:?}", config);
+
+        let nodes = Self::spawn_nodes(args, &config)?;
+        Self::with_config(nodes, &config)
+    }
+
+    fn run(&mut self, testcase: DualNodeTestCase) -> ScenarioResult {
+        for message in &testcase.messages {
+            let _ = self.send(message);
+        }
+
+        self.ping_connections();
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            if let Err(e) = node.is_alive() {
+                return ScenarioResult::Fail(format!("Node {} is not alive: {}", index, e));
+            }
+        }
+
+        ScenarioResult::Ok
+    }
+}
+
+fuzzamoto_main!(
+    MultiNodeScenario::<ScenarioTransport, BitcoinCoreTarget>,
+    DualNodeTestCase
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = MultiNodeScenarioConfig::from_json(
+            r#"{ "num_primaries": 1, "num_secondaries": 2, "topology": [[0, 1], [1, 2]] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            MultiNodeScenarioConfig {
+                num_primaries: 1,
+                num_secondaries: 2,
+                topology: vec![(0, 1), (1, 2)],
+            }
+        );
+
+        for invalid in [
+            r#"{ "num_primaries": 0, "num_secondaries": 2, "topology": [] }"#,
+            r#"{ "num_primaries": 1, "num_secondaries": 1, "topology": [[0, 2]] }"#,
+            r#"{ "num_primaries": 1, "num_secondaries": 1, "topology": [[1, 1]] }"#,
+            r#"{ "num_primaries": 1, "num_secondaries": 1 }"#,
+        ] {
+            assert!(MultiNodeScenarioConfig::from_json(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn decode_testcase() {
+        let testcase = DualNodeTestCase::decode(&[1, 1, 0, 20, 2, 0xaa, 0xbb, 0]).unwrap();
+        assert!(!testcase.messages.is_empty());
+        assert!(DualNodeTestCase::decode(&[]).unwrap().messages.is_empty());
+    }
+
+    /// Spawns real nodes, run with `BITCOIND_PATH` pointing to a `bitcoind` binary
+    #[test]
+    #[ignore = "requires a bitcoind binary (BITCOIND_PATH)"]
+    fn relay_through_secondary() {
+        let bitcoind = std::env::var("BITCOIND_PATH").expect("BITCOIND_PATH should be set");
+        let args = vec!["scenario-multi-node".to_string(), bitcoind];
+        let config = MultiNodeScenarioConfig::default();
+
+        let nodes =
+            MultiNodeScenario::<ScenarioTransport, BitcoinCoreTarget>::spawn_nodes(&args, &config)
+                .unwrap();
+        let mut scenario =
+            MultiNodeScenario::<ScenarioTransport, BitcoinCoreTarget>::with_config(nodes, &config)
+                .unwrap();
+        assert!(scenario.nodes[0].is_connected_to(&scenario.nodes[1]));
+
+        let testcase = DualNodeTestCase {
+            messages: (0..4)
+                .map(|receiver| DualNodeMessage {
+                    receiver,
+                    outbound: receiver % 2 == 0,
+                    message_type: 17, // ping
+                    payload: vec![0; 8],
+                })
+                .collect(),
+        };
+        assert!(matches!(scenario.run(testcase), ScenarioResult::Ok));
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use fuzzamoto::{
+    connections::Transport,
+    fuzzamoto_main,
+    scenarios::{Scenario, ScenarioInput, ScenarioResult, generic::GenericScenario},
+    targets::{BitcoinCoreTarget, Target},
+    test_utils,
+};
+
+use arbitrary::{Arbitrary, Unstructured};
+use bitcoin::{
+    Amount, Block, OutPoint, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+    blockdata::opcodes::{OP_0, OP_TRUE},
+    consensus::encode,
+    hashes::{Hash, sha256},
+    script::ScriptBuf,
+    transaction,
+};
+
+// Transport type alias based on feature flag
+#[cfg(not(feature = "v2transport"))]
+type ScenarioTransport = fuzzamoto::connections::V1Transport;
+#[cfg(feature = "v2transport")]
+type ScenarioTransport = fuzzamoto::connections::V2Transport;
+
+/// Coinbase outputs only become spendable after 100 confirmations
+const COINBASE_MATURITY: u32 = 100;
+/// Maximum number of orphans Bitcoin Core keeps before evicting, `FloodOrphans` always sends more
+const MAX_ORPHANS: usize = 100;
+/// Value of the (non-existent) outputs spent by flooded orphans
+const FLOOD_ORPHAN_VALUE: Amount = Amount::from_sat(100_000);
+
+#[derive(Arbitrary, Debug, Clone)]
+enum OrphanAction {
+    /// Send a transaction spending the (not yet sent) parent identified by `parent_hash`
+    SendOrphan { parent_hash: [u8; 32] },
+    /// Send the parent identified by `parent_hash`, resolving its orphans
+    SendParent { parent_hash: [u8; 32] },
+    /// Send `MAX_ORPHANS + 1 + count` orphans with missing parents, triggering evictions
+    FloodOrphans { count: u8 },
+    /// Advance the mocktime of the target node (orphans expire after 20 minutes)
+    AdvanceTime { secs: u16 },
+}
+
+#[derive(Arbitrary, Debug, Clone)]
+struct TestCase {
+    actions: Vec<OrphanAction>,
+}
+
+impl ScenarioInput<'_> for TestCase {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut unstructured = Unstructured::new(bytes);
+        let actions = Vec::arbitrary(&mut unstructured).map_err(|e| e.to_string())?;
+        Ok(Self { actions })
+    }
+}
+
+fn p2wsh_op_true_spk() -> ScriptBuf {
+    let mut spk = vec![OP_0.to_u8(), 32];
+    spk.extend(sha256::Hash::hash(&[OP_TRUE.to_u8()]).as_byte_array());
+    spk.into()
+}
+
+/// Create a transaction spending the P2WSH-OP_TRUE output `outpoint` (worth `value`) into a single
+/// P2WSH-OP_TRUE output, paying 1 sat/vB.
+fn spend_op_true(outpoint: OutPoint, value: Amount) -> Result<Transaction, String> {
+    let mut witness = Witness::new();
+    witness.push([OP_TRUE.to_u8()]);
+
+    let mut tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness,
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: p2wsh_op_true_spk(),
+        }],
+    };
+
+    let fee = Amount::from_sat(tx.vsize() as u64);
+    tx.output[0].value = value
+        .checked_sub(fee)
+        .ok_or_else(|| "Insufficient funds to pay for transaction fee".to_string())?;
+    Ok(tx)
+}
+
+/// Create the parent identified by `parent_hash`: a consolidation of the coinbase of one of the
+/// `mature_blocks` (chosen by `parent_hash`)
+fn build_parent(mature_blocks: &[&Block], parent_hash: &[u8; 32]) -> Result<Transaction, String> {
+    if mature_blocks.is_empty() {
+        return Err("No mature coinbase to spend".to_string());
+    }
+    let index = u64::from_le_bytes(parent_hash[..8].try_into().unwrap());
+    let coinbase = &mature_blocks[(index % mature_blocks.len() as u64) as usize].txdata[0];
+    test_utils::create_consolidation_tx(&[(
+        OutPoint::new(coinbase.compute_txid(), 0),
+        coinbase.output[0].value,
+    )])
+}
+
+/// Create an orphan spending the output of `parent`
+fn build_orphan(parent: &Transaction) -> Result<Transaction, String> {
+    spend_op_true(
+        OutPoint::new(parent.compute_txid(), 0),
+        parent.output[0].value,
+    )
+}
+
+/// Create the `nonce`-th flooded orphan, spending an output of a parent that does not exist
+fn build_flood_orphan(nonce: u64) -> Transaction {
+    let missing_parent =
+        Txid::from_byte_array(sha256::Hash::hash(&nonce.to_le_bytes()).to_byte_array());
+    spend_op_true(OutPoint::new(missing_parent, 0), FLOOD_ORPHAN_VALUE)
+        .expect("flood orphans should be able to pay their fee")
+}
+
+/// `OrphanHandlingScenario` is a scenario that tests the handling of orphan transactions (i.e.
+/// transactions with missing parents), which Bitcoin Core keeps in its orphanage until the parents
+/// arrive, they expire or they are evicted.
+///
+/// The scenario setup creates a couple of connections to the target node and mines a chain of 200
+/// blocks. Testcases are a series of actions, each performed on the next connection (round
+/// robin):
+///
+/// 1. Send an orphan spending a parent (a consolidation of a mature coinbase) identified by a hash
+/// 2. Send the parent identified by a hash, resolving its orphans
+/// 3. Flood the node with more orphans than the orphanage can hold (checking that the node is
+///    still alive afterwards)
+/// 4. Advance the mocktime of the target node
+struct OrphanHandlingScenario<TX: Transport, T: Target<TX>> {
+    inner: GenericScenario<TX, T>,
+
+    /// Parents created by `SendOrphan` or `SendParent`, by their identifying hash
+    parents: HashMap<[u8; 32], Transaction>,
+    /// Orphans whose parent has not been sent yet, by the hash identifying their parent
+    orphans: HashMap<[u8; 32], Transaction>,
+    /// Number of orphans sent by `FloodOrphans`
+    flooded: u64,
+}
+
+impl<TX: Transport, T: Target<TX>> OrphanHandlingScenario<TX, T> {
+    fn get_or_build_parent(&mut self, parent_hash: [u8; 32]) -> Option<Transaction> {
+        if let Some(parent) = self.parents.get(&parent_hash) {
+            return Some(parent.clone());
+        }
+
+        let mature_blocks: Vec<&Block> = self
+            .inner
+            .block_tree
+            .values()
+            .filter(|(_, height)| *height <= COINBASE_MATURITY)
+            .map(|(block, _)| block)
+            .collect();
+        let parent = build_parent(&mature_blocks, &parent_hash).ok()?;
+        self.parents.insert(parent_hash, parent.clone());
+        Some(parent)
+    }
+
+    fn send_tx(&mut self, connection: usize, tx: &Transaction) {
+        let _ = self.inner.connections[connection].send(&("tx".to_string(), encode::serialize(tx)));
+    }
+}
+
+impl<TX: Transport, T: Target<TX>> Scenario<'_, TestCase> for OrphanHandlingScenario<TX, T> {
+    fn new(args: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            inner: GenericScenario::new(args)?,
+            parents: HashMap::new(),
+            orphans: HashMap::new(),
+            flooded: 0,
+        })
+    }
+
+    fn run(&mut self, testcase: TestCase) -> ScenarioResult {
+        let num_connections = self.inner.connections.len();
+        if num_connections == 0 {
+            return ScenarioResult::Skip;
+        }
+        for (index, action) in testcase.actions.into_iter().enumerate() {
+            let connection = index % num_connections;
+            match action {
+                OrphanAction::SendOrphan { parent_hash } => {
+                    let Some(parent) = self.get_or_build_parent(parent_hash) else {
+                        continue;
+                    };
+                    let Ok(orphan) = build_orphan(&parent) else {
+                        continue;
+                    };
+                    self.send_tx(connection, &orphan);
+                    self.orphans.insert(parent_hash, orphan);
+                }
+                OrphanAction::SendParent { parent_hash } => {
+                    let Some(parent) = self.get_or_build_parent(parent_hash) else {
+                        continue;
+                    };
+                    self.send_tx(connection, &parent);
+                    self.orphans.remove(&parent_hash);
+                }
+                OrphanAction::FloodOrphans { count } => {
+                    for _ in 0..MAX_ORPHANS + 1 + count as usize {
+                        let orphan = build_flood_orphan(self.flooded);
+                        self.flooded += 1;
+                        self.send_tx(connection, &orphan);
+                    }
+                    let _ = self.inner.connections[connection].ping();
+
+                    if let Err(e) = self.inner.target.is_alive() {
+                        return ScenarioResult::Fail(format!(
+                            "Target is not alive after orphan flood: {}",
+                            e
+                        ));
+                    }
+                }
+                OrphanAction::AdvanceTime { secs } => {
+                    self.inner.time += secs as u64;
+                    let _ = self.inner.target.set_mocktime(self.inner.time);
+                }
+            }
+        }
+
+        for connection in self.inner.connections.iter_mut() {
+            let _ = connection.ping();
+        }
+
+        if let Err(e) = self.inner.target.is_alive() {
+            return ScenarioResult::Fail(format!("Target is not alive: {}", e));
+        }
+
+        ScenarioResult::Ok
+    }
+}
+
+fuzzamoto_main!(
+    OrphanHandlingScenario::<ScenarioTransport, BitcoinCoreTarget>,
+    TestCase
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::BlockHash;
+    use std::collections::HashSet;
+
+    #[test]
+    fn orphan_spends_parent() {
+        let block = test_utils::mining::mine_block(BlockHash::all_zeros(), 1, 2).unwrap();
+        let parent = build_parent(&[&block], &[7; 32]).unwrap();
+        assert_eq!(
+            parent.input[0].previous_output,
+            OutPoint::new(block.txdata[0].compute_txid(), 0)
+        );
+
+        let orphan = build_orphan(&parent).unwrap();
+        assert_eq!(
+            orphan.input[0].previous_output,
+            OutPoint::new(parent.compute_txid(), 0)
+        );
+        assert!(orphan.output[0].value < parent.output[0].value);
+
+        assert!(build_parent(&[], &[7; 32]).is_err());
+    }
+
+    #[test]
+    fn flood_orphans_are_unique() {
+        let orphans: HashSet<Txid> = (0..(MAX_ORPHANS + 1 + u8::MAX as usize) as u64)
+            .map(|nonce| build_flood_orphan(nonce).compute_txid())
+            .collect();
+        assert_eq!(orphans.len(), MAX_ORPHANS + 1 + u8::MAX as usize);
+    }
+}
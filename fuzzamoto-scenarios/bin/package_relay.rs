@@ -0,0 +1,227 @@
+use fuzzamoto::{
+    connections::{Transport, V2Transport},
+    fuzzamoto_main,
+    oracles::{Oracle, OracleResult, SubmitPackageContext, SubmitPackageOracle},
+    scenarios::{Scenario, ScenarioInput, ScenarioResult, generic::GenericScenario},
+    targets::{BitcoinCoreTarget, HasGetRawMempoolEntries, HasSubmitPackage, Target, TargetNode},
+};
+
+use arbitrary::{Arbitrary, Unstructured};
+use bitcoin::{
+    Amount, Block, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness,
+    blockdata::opcodes::{OP_0, OP_TRUE},
+    consensus::encode,
+    hashes::{Hash, sha256},
+    script::ScriptBuf,
+    transaction,
+};
+
+// Transport type alias based on feature flag
+#[cfg(not(feature = "v2transport"))]
+type ScenarioTransport = fuzzamoto::connections::V1Transport;
+#[cfg(feature = "v2transport")]
+type ScenarioTransport = fuzzamoto::connections::V2Transport;
+
+/// Coinbase outputs only become spendable after 100 confirmations
+const COINBASE_MATURITY: u32 = 100;
+/// Maximum fee rate (in sat/vB) of the parent transaction
+const MAX_PARENT_FEE_RATE: u64 = 3;
+/// Maximum fee rate (in sat/vB) of the child transaction
+const MAX_CHILD_FEE_RATE: u64 = 100;
+
+#[derive(Arbitrary, Debug, Clone)]
+struct TestCase {
+    /// Index of the (mature) coinbase output spent by the parent
+    coinbase_index: u8,
+    parent_fee_rate: u8,
+    child_fee_rate: u8,
+    /// Connection the transactions are relayed on
+    connection: u8,
+    /// Relay the child before the parent
+    child_first: bool,
+}
+
+impl ScenarioInput<'_> for TestCase {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut unstructured = Unstructured::new(bytes);
+        TestCase::arbitrary(&mut unstructured).map_err(|e| e.to_string())
+    }
+}
+
+fn p2wsh_op_true_spk() -> ScriptBuf {
+    let mut spk = vec![OP_0.to_u8(), 32];
+    spk.extend(sha256::Hash::hash(&[OP_TRUE.to_u8()]).as_byte_array());
+    spk.into()
+}
+
+/// Create a transaction spending the P2WSH-OP_TRUE output `outpoint` (worth `value`) into a single
+/// P2WSH-OP_TRUE output, paying `fee_rate` sat/vB.
+fn spend_op_true(outpoint: OutPoint, value: Amount, fee_rate: u64) -> Result<Transaction, String> {
+    let mut witness = Witness::new();
+    witness.push([OP_TRUE.to_u8()]);
+
+    let mut tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness,
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: p2wsh_op_true_spk(),
+        }],
+    };
+
+    let fee = Amount::from_sat(fee_rate * tx.vsize() as u64);
+    tx.output[0].value = value
+        .checked_sub(fee)
+        .ok_or_else(|| "Insufficient funds to pay for transaction fee".to_string())?;
+    Ok(tx)
+}
+
+/// Build a parent spending the coinbase of `block` and a child spending the parent
+fn build_package(block: &Block, testcase: &TestCase) -> Result<Vec<Transaction>, String> {
+    let coinbase = &block.txdata[0];
+    let parent = spend_op_true(
+        OutPoint::new(coinbase.compute_txid(), 0),
+        coinbase.output[0].value,
+        testcase.parent_fee_rate as u64 % (MAX_PARENT_FEE_RATE + 1),
+    )?;
+    let child = spend_op_true(
+        OutPoint::new(parent.compute_txid(), 0),
+        parent.output[0].value,
+        testcase.child_fee_rate as u64 % (MAX_CHILD_FEE_RATE + 1),
+    )?;
+    Ok(vec![parent, child])
+}
+
+/// `PackageRelayScenario` is a scenario that tests the consistency of package relay.
+///
+/// The scenario setup creates a couple of connections to the target node and mines a chain of 200
+/// blocks. Each testcase creates a parent/child package with fuzzed fee rates (the parent may pay
+/// less than the minimum relay fee), relays both transactions individually over p2p and then
+/// resubmits them via `submitpackage`, checking the results with the `SubmitPackageOracle`.
+struct PackageRelayScenario<TX: Transport, T: Target<TX>> {
+    inner: GenericScenario<TX, T>,
+}
+
+impl<
+    TX: Transport,
+    T: Target<TX> + Target<V2Transport> + HasSubmitPackage + HasGetRawMempoolEntries,
+> Scenario<'_, TestCase> for PackageRelayScenario<TX, T>
+{
+    fn new(args: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            inner: GenericScenario::new(args)?,
+        })
+    }
+
+    fn run(&mut self, testcase: TestCase) -> ScenarioResult {
+        let mature_blocks: Vec<&Block> = self
+            .inner
+            .block_tree
+            .values()
+            .filter(|(_, height)| *height <= COINBASE_MATURITY)
+            .map(|(block, _)| block)
+            .collect();
+        if mature_blocks.is_empty() {
+            return ScenarioResult::Skip;
+        }
+        let block = mature_blocks[testcase.coinbase_index as usize % mature_blocks.len()];
+
+        let Ok(package) = build_package(block, &testcase) else {
+            return ScenarioResult::Skip;
+        };
+
+        let num_connections = self.inner.connections.len();
+        let connection =
+            &mut self.inner.connections[testcase.connection as usize % num_connections];
+        let mut relay_order: Vec<&Transaction> = package.iter().collect();
+        if testcase.child_first {
+            relay_order.reverse();
+        }
+        for tx in relay_order {
+            let _ = connection.send(&("tx".to_string(), encode::serialize(tx)));
+        }
+
+        for connection in self.inner.connections.iter_mut() {
+            let _ = connection.ping();
+        }
+
+        if let Err(e) = self.inner.target.is_alive() {
+            return ScenarioResult::Fail(format!("Target is not alive: {}", e));
+        }
+
+        let mut context = SubmitPackageContext {
+            target: &self.inner.target,
+            transactions: &package,
+            package_only: Vec::new(),
+        };
+        let oracle = SubmitPackageOracle::<TX>::default();
+        if let OracleResult::Fail(e) = oracle.evaluate(&mut context) {
+            return ScenarioResult::Fail(format!("{} failed: {}", oracle.name(), e));
+        }
+
+        ScenarioResult::Ok
+    }
+}
+
+fuzzamoto_main!(
+    PackageRelayScenario::<ScenarioTransport, BitcoinCoreTarget>,
+    TestCase
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::BlockHash;
+    use fuzzamoto::test_utils;
+
+    fn testcase(parent_fee_rate: u8, child_fee_rate: u8) -> TestCase {
+        TestCase {
+            coinbase_index: 0,
+            parent_fee_rate,
+            child_fee_rate,
+            connection: 0,
+            child_first: false,
+        }
+    }
+
+    #[test]
+    fn child_spends_parent() {
+        let block = test_utils::mining::mine_block(BlockHash::all_zeros(), 1, 2).unwrap();
+        let package = build_package(&block, &testcase(0, 10)).unwrap();
+
+        let [parent, child] = package.as_slice() else {
+            panic!("package should contain a parent and a child");
+        };
+        assert_eq!(
+            parent.input[0].previous_output,
+            OutPoint::new(block.txdata[0].compute_txid(), 0)
+        );
+        assert_eq!(
+            child.input[0].previous_output,
+            OutPoint::new(parent.compute_txid(), 0)
+        );
+        // The parent pays no fee, the child pays 10 sat/vB
+        assert_eq!(parent.output[0].value, block.txdata[0].output[0].value);
+        assert_eq!(
+            parent.output[0].value - child.output[0].value,
+            Amount::from_sat(10 * child.vsize() as u64)
+        );
+    }
+
+    #[test]
+    fn fee_rates_are_bounded() {
+        let block = test_utils::mining::mine_block(BlockHash::all_zeros(), 1, 2).unwrap();
+        let package = build_package(&block, &testcase(u8::MAX, u8::MAX)).unwrap();
+
+        let parent_fee = block.txdata[0].output[0].value - package[0].output[0].value;
+        assert!(parent_fee.to_sat() <= MAX_PARENT_FEE_RATE * package[0].vsize() as u64);
+        let child_fee = package[0].output[0].value - package[1].output[0].value;
+        assert!(child_fee.to_sat() <= MAX_CHILD_FEE_RATE * package[1].vsize() as u64);
+    }
+}
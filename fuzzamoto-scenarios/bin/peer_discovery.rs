@@ -0,0 +1,345 @@
+use fuzzamoto::{
+    connections::{Transport, V2Transport},
+    fuzzamoto_main,
+    scenarios::{Scenario, ScenarioInput, ScenarioResult, generic::GenericScenario},
+    targets::{BitcoinCoreTarget, HasGetPeerInfo, Target, TargetNode},
+};
+
+use arbitrary::{Arbitrary, Unstructured};
+use bitcoin::{
+    consensus::encode,
+    p2p::{
+        ServiceFlags,
+        address::{AddrV2, AddrV2Message, Address},
+    },
+};
+use std::{
+    collections::HashSet,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+};
+
+// Transport type alias based on feature flag
+#[cfg(not(feature = "v2transport"))]
+type ScenarioTransport = fuzzamoto::connections::V1Transport;
+#[cfg(feature = "v2transport")]
+type ScenarioTransport = fuzzamoto::connections::V2Transport;
+
+/// Maximum number of addresses per `addr`/`addrv2` message accepted by Bitcoin Core
+const MAX_ADDR_TO_SEND: usize = 1000;
+/// OnionCat prefix used to embed Tor v2 addresses in IPv6 addresses
+const ONIONCAT_PREFIX: [u8; 6] = [0xfd, 0x87, 0xd8, 0x7e, 0xeb, 0x43];
+
+/// Address range of an address gossiped via `addr`
+#[derive(Arbitrary, Debug, Clone, Copy)]
+enum AddrRange {
+    /// Publicly routable address
+    Routable,
+    /// 127.0.0.0/8 or ::1
+    Loopback,
+    /// RFC 1918 (10.0.0.0/8) or RFC 4193 (fc00::/7) private address
+    Private,
+    /// 0.0.0.0 or ::
+    Unspecified,
+    /// 224.0.0.0/4 or ff00::/8
+    Multicast,
+    /// Tor v2 address embedded in IPv6 via OnionCat (fd87:d87e:eb43::/48)
+    OnionCat,
+}
+
+/// Address sent in an `addr` message
+#[derive(Arbitrary, Debug, Clone)]
+struct ArbitraryAddrV1 {
+    range: AddrRange,
+    ipv6: bool,
+    /// Host part of the address
+    host: [u8; 10],
+    port: u16,
+    services: u64,
+    /// Seconds the address' timestamp lies in the past (in the future if negative)
+    age: i32,
+}
+
+impl ArbitraryAddrV1 {
+    fn ip(&self) -> Ipv6Addr {
+        let h = self.host;
+        if !self.ipv6 && !matches!(self.range, AddrRange::OnionCat) {
+            let ipv4 = match self.range {
+                AddrRange::Routable => Ipv4Addr::new(1 + h[0] % 9, h[1], h[2], h[3]),
+                AddrRange::Loopback => Ipv4Addr::new(127, h[0], h[1], h[2]),
+                AddrRange::Private => Ipv4Addr::new(10, h[0], h[1], h[2]),
+                AddrRange::Unspecified => Ipv4Addr::UNSPECIFIED,
+                AddrRange::Multicast => Ipv4Addr::new(224 | (h[0] & 0x0f), h[1], h[2], h[3]),
+                AddrRange::OnionCat => unreachable!(),
+            };
+            return ipv4.to_ipv6_mapped();
+        }
+
+        let mut octets = [0u8; 16];
+        octets[6..].copy_from_slice(&h);
+        match self.range {
+            // 2000::/3
+            AddrRange::Routable => octets[..6].copy_from_slice(&[0x20, 0x01, 0x0d, 0xb9, 0, 1]),
+            AddrRange::Loopback => return Ipv6Addr::LOCALHOST,
+            AddrRange::Private => octets[0] = 0xfc,
+            AddrRange::Unspecified => return Ipv6Addr::UNSPECIFIED,
+            AddrRange::Multicast => octets[0] = 0xff,
+            AddrRange::OnionCat => octets[..6].copy_from_slice(&ONIONCAT_PREFIX),
+        }
+        Ipv6Addr::from(octets)
+    }
+
+    fn to_address(&self, time: u64) -> (u32, Address) {
+        let ip = self.ip();
+        let socket = match ip.to_ipv4_mapped() {
+            Some(ipv4) => SocketAddr::V4(SocketAddrV4::new(ipv4, self.port)),
+            None => SocketAddr::V6(SocketAddrV6::new(ip, self.port, 0, 0)),
+        };
+        (
+            addr_time(time, self.age),
+            Address::new(&socket, ServiceFlags::from(self.services)),
+        )
+    }
+}
+
+/// Network address sent in an `addrv2` message (BIP-155)
+#[derive(Arbitrary, Debug, Clone)]
+enum ArbitraryNetworkAddr {
+    Ipv4([u8; 4]),
+    Ipv6([u8; 16]),
+    TorV3([u8; 32]),
+    I2p([u8; 32]),
+    /// CJDNS addresses are only valid in fc00::/8, the prefix is replaced if `valid` is set
+    Cjdns {
+        address: [u8; 16],
+        valid: bool,
+    },
+    /// Network with an unknown (or invalid) BIP-155 network id
+    Unknown(u8, Vec<u8>),
+}
+
+/// Address sent in an `addrv2` message
+#[derive(Arbitrary, Debug, Clone)]
+struct ArbitraryAddrV2 {
+    addr: ArbitraryNetworkAddr,
+    port: u16,
+    services: u64,
+    /// Seconds the address' timestamp lies in the past (in the future if negative)
+    age: i32,
+}
+
+impl ArbitraryAddrV2 {
+    fn to_message(&self, time: u64) -> AddrV2Message {
+        let addr = match &self.addr {
+            ArbitraryNetworkAddr::Ipv4(octets) => AddrV2::Ipv4(Ipv4Addr::from(*octets)),
+            ArbitraryNetworkAddr::Ipv6(octets) => AddrV2::Ipv6(Ipv6Addr::from(*octets)),
+            ArbitraryNetworkAddr::TorV3(key) => AddrV2::TorV3(*key),
+            ArbitraryNetworkAddr::I2p(hash) => AddrV2::I2p(*hash),
+            ArbitraryNetworkAddr::Cjdns { address, valid } => {
+                let mut address = *address;
+                if *valid {
+                    address[0] = 0xfc;
+                }
+                AddrV2::Cjdns(Ipv6Addr::from(address))
+            }
+            ArbitraryNetworkAddr::Unknown(network, payload) => {
+                AddrV2::Unknown(*network, payload.clone())
+            }
+        };
+        AddrV2Message {
+            time: addr_time(time, self.age),
+            services: ServiceFlags::from(self.services),
+            addr,
+            port: self.port,
+        }
+    }
+}
+
+fn addr_time(time: u64, age: i32) -> u32 {
+    (time as i64 - age as i64).clamp(0, u32::MAX as i64) as u32
+}
+
+#[derive(Arbitrary, Debug, Clone)]
+enum AddrAction {
+    SendGetAddr,
+    SendAddr { addrs: Vec<ArbitraryAddrV1> },
+    SendAddrV2 { addrs: Vec<ArbitraryAddrV2> },
+}
+
+impl AddrAction {
+    fn to_message(&self, time: u64) -> (String, Vec<u8>) {
+        match self {
+            AddrAction::SendGetAddr => ("getaddr".to_string(), vec![]),
+            AddrAction::SendAddr { addrs } => {
+                let addrs: Vec<(u32, Address)> = addrs
+                    .iter()
+                    .take(MAX_ADDR_TO_SEND)
+                    .map(|addr| addr.to_address(time))
+                    .collect();
+                ("addr".to_string(), encode::serialize(&addrs))
+            }
+            AddrAction::SendAddrV2 { addrs } => {
+                let addrs: Vec<AddrV2Message> = addrs
+                    .iter()
+                    .take(MAX_ADDR_TO_SEND)
+                    .map(|addr| addr.to_message(time))
+                    .collect();
+                ("addrv2".to_string(), encode::serialize(&addrs))
+            }
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug, Clone)]
+struct TestCase {
+    /// Actions and the connections they are performed on
+    actions: Vec<(u8, AddrAction)>,
+}
+
+impl ScenarioInput<'_> for TestCase {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut unstructured = Unstructured::new(bytes);
+        TestCase::arbitrary(&mut unstructured).map_err(|e| e.to_string())
+    }
+}
+
+/// `PeerDiscoveryScenario` is a scenario that tests address gossip (`addr` and `addrv2`).
+///
+/// The scenario setup creates a couple of connections to the target node and mines a chain of 200
+/// blocks. Each testcase:
+///
+/// 1. Sends `getaddr` on all connections
+/// 2. Sends a sequence of `getaddr`, `addr` and `addrv2` messages mixing valid, invalid and
+///    reserved IPv4, IPv6, Tor, I2P and CJDNS addresses
+/// 3. Checks via `getpeerinfo` that the node did not open connections to any of the gossiped
+///    addresses (there is no one listening on them, connections only come from the harness)
+/// 4. Checks that the node is still alive
+struct PeerDiscoveryScenario<TX: Transport, T: Target<TX>> {
+    inner: GenericScenario<TX, T>,
+}
+
+impl<TX: Transport, T: Target<TX> + Target<V2Transport> + HasGetPeerInfo> Scenario<'_, TestCase>
+    for PeerDiscoveryScenario<TX, T>
+{
+    fn new(args: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            inner: GenericScenario::new(args)?,
+        })
+    }
+
+    fn run(&mut self, testcase: TestCase) -> ScenarioResult {
+        let Ok(initial_peers) = self.inner.target.get_peer_info() else {
+            return ScenarioResult::Skip;
+        };
+        let initial_peers: HashSet<u64> = initial_peers.iter().map(|peer| peer.id).collect();
+
+        let num_connections = self.inner.num_connections();
+        for connection in 0..num_connections {
+            let _ = self
+                .inner
+                .send(connection, &AddrAction::SendGetAddr.to_message(0));
+        }
+
+        for (connection, action) in &testcase.actions {
+            let message = action.to_message(self.inner.time);
+            let _ = self
+                .inner
+                .send(*connection as usize % num_connections, &message);
+        }
+
+        self.inner.ping_connections();
+
+        if let Err(e) = self.inner.target.is_alive() {
+            return ScenarioResult::Fail(format!("Target is not alive: {}", e));
+        }
+
+        match self.inner.target.get_peer_info() {
+            Ok(peers) => {
+                if let Some(peer) = peers
+                    .iter()
+                    .find(|peer| !peer.inbound && !initial_peers.contains(&peer.id))
+                {
+                    return ScenarioResult::Fail(format!(
+                        "Node connected to gossiped address: {}",
+                        peer.addr
+                    ));
+                }
+            }
+            Err(e) => return ScenarioResult::Fail(format!("getpeerinfo failed: {}", e)),
+        }
+
+        ScenarioResult::Ok
+    }
+}
+
+fuzzamoto_main!(
+    PeerDiscoveryScenario::<ScenarioTransport, BitcoinCoreTarget>,
+    TestCase
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr_v1(range: AddrRange, ipv6: bool) -> ArbitraryAddrV1 {
+        ArbitraryAddrV1 {
+            range,
+            ipv6,
+            host: [7; 10],
+            port: 8333,
+            services: 0,
+            age: 0,
+        }
+    }
+
+    #[test]
+    fn v1_addresses_are_in_range() {
+        let ipv4 = |range| addr_v1(range, false).ip().to_ipv4_mapped().unwrap();
+        assert!(ipv4(AddrRange::Loopback).is_loopback());
+        assert!(ipv4(AddrRange::Private).is_private());
+        assert!(ipv4(AddrRange::Multicast).is_multicast());
+        assert!(ipv4(AddrRange::Unspecified).is_unspecified());
+        assert!(!ipv4(AddrRange::Routable).is_private());
+
+        let ipv6 = |range| addr_v1(range, true).ip();
+        assert!(ipv6(AddrRange::Loopback).is_loopback());
+        assert!(ipv6(AddrRange::Multicast).is_multicast());
+        assert_eq!(ipv6(AddrRange::Private).octets()[0], 0xfc);
+        assert_eq!(ipv6(AddrRange::OnionCat).octets()[..6], ONIONCAT_PREFIX[..]);
+        // Tor v2 addresses only exist in their OnionCat form
+        assert_eq!(
+            addr_v1(AddrRange::OnionCat, false).ip().octets()[..6],
+            ONIONCAT_PREFIX[..]
+        );
+    }
+
+    #[test]
+    fn addr_messages_are_capped() {
+        let action = AddrAction::SendAddr {
+            addrs: vec![addr_v1(AddrRange::Routable, false); MAX_ADDR_TO_SEND + 1],
+        };
+        let (command, payload) = action.to_message(1_000);
+        assert_eq!(command, "addr");
+        let addrs: Vec<(u32, Address)> = encode::deserialize(&payload).unwrap();
+        assert_eq!(addrs.len(), MAX_ADDR_TO_SEND);
+        assert_eq!(addrs[0].0, 1_000);
+    }
+
+    #[test]
+    fn addrv2_cjdns_prefix() {
+        let message = ArbitraryAddrV2 {
+            addr: ArbitraryNetworkAddr::Cjdns {
+                address: [0; 16],
+                valid: true,
+            },
+            port: 8333,
+            services: 0,
+            age: 10,
+        }
+        .to_message(100);
+        assert_eq!(message.time, 90);
+        let AddrV2::Cjdns(address) = message.addr else {
+            panic!("expected a cjdns address");
+        };
+        assert_eq!(address.octets()[0], 0xfc);
+    }
+}
@@ -261,6 +261,12 @@ impl<'a> Scenario<'a, TestCase> for RpcScenario {
 
             log::info!("\t-> {}", result);
 
+            // Txids returned by `sendrawtransaction` are plain strings, so they need to be added to
+            // the pool as hex params explicitly
+            if rpc_name == "sendrawtransaction" && result.is_string() {
+                self.param_pool.add("hex", result.clone());
+            }
+
             // Add result values to the `RpcParamPool`
             self.param_pool.add_rpc_result(result);
         }
@@ -1,6 +1,45 @@
 use std::collections::BTreeSet;
 use std::io::Write;
 
+/// All message types known to Bitcoin Core's p2p protocol
+pub const BITCOIN_COMMANDS: &[&str] = &[
+    "version",
+    "verack",
+    "addr",
+    "addrv2",
+    "sendaddrv2",
+    "inv",
+    "getdata",
+    "merkleblock",
+    "getblocks",
+    "getheaders",
+    "tx",
+    "headers",
+    "block",
+    "getaddr",
+    "mempool",
+    "ping",
+    "pong",
+    "notfound",
+    "filterload",
+    "filteradd",
+    "filterclear",
+    "sendheaders",
+    "feefilter",
+    "sendcmpct",
+    "cmpctblock",
+    "getblocktxn",
+    "blocktxn",
+    "getcfilters",
+    "cfilter",
+    "getcfheaders",
+    "cfheaders",
+    "getcfcheckpt",
+    "cfcheckpt",
+    "wtxidrelay",
+    "sendtxrcncl",
+];
+
 pub trait Dictionary {
     fn add(&mut self, value: &[u8]);
 }
@@ -55,6 +94,20 @@ mod tests {
         assert_eq!(result, "\"hello\"\n\"world\\x00\\x01\\x02\"\n");
     }
 
+    #[test]
+    fn test_bitcoin_commands() {
+        let mut seen = BTreeSet::new();
+        for command in BITCOIN_COMMANDS {
+            assert!(!command.is_empty() && command.len() <= 12);
+            assert!(
+                command
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+            );
+            assert!(seen.insert(command), "duplicate command {}", command);
+        }
+    }
+
     #[test]
     fn test_add_duplicate() {
         let mut dictionary = FileDictionary::new();
@@ -1,18 +1,28 @@
 use crate::{
     connections::Transport,
     targets::{
-        ConnectableTarget, GenerateToAddress, HasBlockTemplate, HasTipInfo, HasTxOutSetInfo,
-        Target, bitcoin_core::TxOutSetInfo,
+        ConnectableTarget, GenerateToAddress, HasBlockTemplate, HasEstimateSmartFee,
+        HasGetRawMempoolEntries, HasSubmitPackage, HasTipInfo, HasTxOutSetInfo, Target,
+        bitcoin_core::{FeeEstimate, TxOutSetInfo},
     },
 };
+use bitcoin::{Transaction, Txid};
 use std::{
     marker::PhantomData,
     time::{Duration, Instant},
 };
 
+pub mod response_time;
+pub use response_time::*;
+
 pub enum OracleResult {
     Pass,
     Fail(String),
+    /// The target responded significantly slower than its baseline (see `ResponseTimeOracle`)
+    PerformanceAnomaly {
+        baseline_p99_ms: u64,
+        current_ms: u64,
+    },
 }
 
 pub trait Oracle<C> {
@@ -271,6 +281,41 @@ mod tests {
             assert_eq!(total, expected_total);
         }
     }
+
+    #[test]
+    fn fee_estimate_range() {
+        let oracle = FeeRateOracle::<()>::default();
+        let estimate = |sat_per_kvb| FeeEstimate {
+            sat_per_kvb,
+            blocks: 2,
+        };
+
+        assert!(
+            oracle
+                .check_estimate(&estimate(5_000), &[2_000, 5_000])
+                .is_ok()
+        );
+        assert!(
+            oracle
+                .check_estimate(&estimate(10_000), &[2_000, 5_000])
+                .is_ok()
+        );
+        assert!(
+            oracle
+                .check_estimate(&estimate(10_001), &[2_000, 5_000])
+                .is_err()
+        );
+        assert!(
+            oracle
+                .check_estimate(&estimate(0), &[2_000, 5_000])
+                .is_err()
+        );
+        // Transactions below the minimum relay fee rate can still result in estimates at the
+        // minimum relay fee rate
+        assert!(oracle.check_estimate(&estimate(1_000), &[0]).is_ok());
+        // The target only ever sees the fuzzed transactions
+        assert!(oracle.check_estimate(&estimate(1_000), &[]).is_err());
+    }
 }
 
 pub struct BlockTemplateOracle<TX>(PhantomData<TX>);
@@ -297,3 +342,152 @@ where
         "BlockTemplateOracle"
     }
 }
+
+/// `SubmitPackageContext` is the context for the `SubmitPackageOracle`
+pub struct SubmitPackageContext<'a, T> {
+    pub target: &'a T,
+    /// Transactions sent to the target during the testcase, topologically sorted
+    pub transactions: &'a [Transaction],
+    /// Transactions that were rejected when relayed individually but accepted as part of the
+    /// package (filled in by the oracle)
+    pub package_only: Vec<Txid>,
+}
+
+/// `SubmitPackageOracle` resubmits all transactions sent during a testcase via `submitpackage` and
+/// checks that the package evaluation is consistent with the individual relay results.
+///
+/// Transactions that did not make it into the mempool individually but are accepted as part of
+/// the package are recorded as interesting in `SubmitPackageContext::package_only`. Transactions
+/// that are in the mempool but rejected by `submitpackage` are reported as a failure.
+pub struct SubmitPackageOracle<TX>(PhantomData<TX>);
+
+impl<TX> Default for SubmitPackageOracle<TX> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<'a, T, TX> Oracle<SubmitPackageContext<'a, T>> for SubmitPackageOracle<TX>
+where
+    TX: Transport,
+    T: Target<TX> + HasSubmitPackage + HasGetRawMempoolEntries,
+{
+    fn evaluate(&self, context: &mut SubmitPackageContext<'a, T>) -> OracleResult {
+        if context.transactions.is_empty() {
+            return OracleResult::Pass;
+        }
+
+        let mempool: Vec<Txid> = match context.target.get_mempool_entries() {
+            Ok(entries) => entries.iter().map(|entry| *entry.txid()).collect(),
+            Err(e) => return OracleResult::Fail(format!("Failed to retrieve mempool: {}", e)),
+        };
+
+        // Packages that are not well-formed (e.g. not child-with-parents) are rejected as a
+        // whole, which is not a consistency issue.
+        let Ok(result) = context.target.submitpackage(context.transactions) else {
+            return OracleResult::Pass;
+        };
+
+        for (txid, reason) in result.rejected.iter() {
+            if mempool.contains(txid) {
+                return OracleResult::Fail(format!(
+                    "Transaction {} is in the mempool but was rejected by submitpackage: {}",
+                    txid, reason
+                ));
+            }
+        }
+
+        context.package_only = result
+            .accepted
+            .iter()
+            .filter(|txid| !mempool.contains(txid))
+            .copied()
+            .collect();
+        for txid in context.package_only.iter() {
+            log::info!(
+                "Transaction {} was only accepted as part of a package",
+                txid
+            );
+        }
+
+        OracleResult::Pass
+    }
+
+    fn name(&self) -> &str {
+        "SubmitPackageOracle"
+    }
+}
+
+/// `FeeRateContext` is the context for the `FeeRateOracle`
+pub struct FeeRateContext<'a, T> {
+    pub target: &'a T,
+    /// Confirmation target (in blocks) to request the estimate for
+    pub conf_target: u16,
+    /// Fee rates (in sat/kvB) of the transactions sent to the target during the testcase
+    pub fee_rates: &'a [u64],
+}
+
+/// Highest default minimum relay fee rate of Bitcoin Core (in sat/kvB), estimates never have to
+/// be lower than this
+const DEFAULT_MIN_RELAY_FEE_RATE: u64 = 1_000;
+/// Estimates may exceed the highest submitted fee rate by this factor (fee rates are tracked in
+/// exponentially spaced buckets)
+const MAX_ESTIMATE_FACTOR: u64 = 2;
+
+/// `FeeRateOracle` checks that the fee rate estimates of `estimatesmartfee` are within a
+/// reasonable range of the fee rates of the transactions submitted to the target, i.e. non-zero
+/// and at most `MAX_ESTIMATE_FACTOR` times the highest submitted fee rate (or the minimum relay
+/// fee rate).
+///
+/// All transactions the target has seen are submitted by the scenario, so an estimate without any
+/// submitted transactions is reported as a failure as well. Missing estimates always pass.
+pub struct FeeRateOracle<TX>(PhantomData<TX>);
+
+impl<TX> Default for FeeRateOracle<TX> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<TX> FeeRateOracle<TX> {
+    pub fn check_estimate(&self, estimate: &FeeEstimate, fee_rates: &[u64]) -> Result<(), String> {
+        let Some(max_fee_rate) = fee_rates.iter().max() else {
+            return Err(format!(
+                "Estimated {} sat/kvB without any submitted transactions",
+                estimate.sat_per_kvb
+            ));
+        };
+
+        let upper_bound = MAX_ESTIMATE_FACTOR * (*max_fee_rate).max(DEFAULT_MIN_RELAY_FEE_RATE);
+        if estimate.sat_per_kvb == 0 || estimate.sat_per_kvb > upper_bound {
+            return Err(format!(
+                "Estimated {} sat/kvB ({} blocks), submitted fee rates are at most {} sat/kvB",
+                estimate.sat_per_kvb, estimate.blocks, max_fee_rate
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, T, TX> Oracle<FeeRateContext<'a, T>> for FeeRateOracle<TX>
+where
+    TX: Transport,
+    T: Target<TX> + HasEstimateSmartFee,
+{
+    fn evaluate(&self, context: &mut FeeRateContext<'a, T>) -> OracleResult {
+        let estimate = match context.target.estimate_smart_fee(context.conf_target) {
+            Ok(Some(estimate)) => estimate,
+            Ok(None) => return OracleResult::Pass,
+            Err(e) => return OracleResult::Fail(format!("Failed to estimate fee: {}", e)),
+        };
+
+        match self.check_estimate(&estimate, context.fee_rates) {
+            Ok(()) => OracleResult::Pass,
+            Err(e) => OracleResult::Fail(e),
+        }
+    }
+
+    fn name(&self) -> &str {
+        "FeeRateOracle"
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    connections::{Connection, Transport},
+    oracles::{Oracle, OracleResult},
+};
+
+/// Number of round-trip times kept as the baseline of the `ResponseTimeOracle`
+pub const RTT_WINDOW_SIZE: usize = 100;
+/// Minimum number of baseline round-trip times required before anomalies are reported
+const MIN_BASELINE_SAMPLES: usize = 10;
+/// Factor by which a round-trip time has to exceed the baseline P99 to be reported
+const ANOMALY_FACTOR: u32 = 3;
+
+/// Round-trip ping times measured before and after the execution of a testcase
+#[derive(Debug, Clone, Copy)]
+pub struct PingTimeMeasurement {
+    /// Round-trip time before any of the testcase's messages were sent
+    pub pre: Duration,
+    /// Round-trip time after the testcase's messages were sent, i.e. including the time it took
+    /// the target to process them
+    pub post: Duration,
+}
+
+/// Measure the round-trip time of a single ping on `connection`
+pub fn measure_rtt<T: Transport>(connection: &mut Connection<T>) -> Result<Duration, String> {
+    let start = Instant::now();
+    connection.ping()?;
+    Ok(start.elapsed())
+}
+
+/// `ResponseTimeOracle` detects performance regressions by comparing the round-trip ping time
+/// after a testcase against the P99 of a rolling window of previous (pre-testcase) round-trip
+/// times.
+///
+/// A target that takes more than 3x the P99 baseline to respond after processing a testcase is
+/// reported as `OracleResult::PerformanceAnomaly`.
+#[derive(Debug, Default)]
+pub struct ResponseTimeOracle {
+    window: RefCell<VecDeque<Duration>>,
+}
+
+impl ResponseTimeOracle {
+    /// Add a round-trip time to the baseline window, evicting the oldest entry if the window is
+    /// full
+    pub fn record(&self, rtt: Duration) {
+        let mut window = self.window.borrow_mut();
+        if window.len() == RTT_WINDOW_SIZE {
+            window.pop_front();
+        }
+        window.push_back(rtt);
+    }
+
+    /// P99 of the baseline window (`None` if there are not enough samples yet)
+    pub fn baseline_p99(&self) -> Option<Duration> {
+        let window = self.window.borrow();
+        if window.len() < MIN_BASELINE_SAMPLES {
+            return None;
+        }
+
+        let mut sorted: Vec<Duration> = window.iter().copied().collect();
+        sorted.sort();
+        let index = (sorted.len() * 99).div_ceil(100) - 1;
+        Some(sorted[index])
+    }
+}
+
+impl Oracle<PingTimeMeasurement> for ResponseTimeOracle {
+    fn evaluate(&self, measurement: &mut PingTimeMeasurement) -> OracleResult {
+        self.record(measurement.pre);
+
+        let Some(baseline) = self.baseline_p99() else {
+            return OracleResult::Pass;
+        };
+
+        if measurement.post > baseline * ANOMALY_FACTOR {
+            return OracleResult::PerformanceAnomaly {
+                baseline_p99_ms: baseline.as_millis() as u64,
+                current_ms: measurement.post.as_millis() as u64,
+            };
+        }
+
+        OracleResult::Pass
+    }
+
+    fn name(&self) -> &str {
+        "ResponseTimeOracle"
+    }
+}
+
+/// Number of messages received from the target during a time span
+#[derive(Debug, Clone, Copy)]
+pub struct MessageCount {
+    pub messages: u64,
+    pub elapsed: Duration,
+}
+
+/// `MessageRateOracle` checks that the target keeps responding with at least `min_rate` messages
+/// per second
+#[derive(Debug, Clone, Copy)]
+pub struct MessageRateOracle {
+    pub min_rate: f64,
+}
+
+impl MessageRateOracle {
+    pub fn new(min_rate: f64) -> Self {
+        Self { min_rate }
+    }
+}
+
+impl Oracle<MessageCount> for MessageRateOracle {
+    fn evaluate(&self, count: &mut MessageCount) -> OracleResult {
+        // Too short to compute a meaningful rate
+        if count.elapsed.is_zero() {
+            return OracleResult::Pass;
+        }
+
+        let rate = count.messages as f64 / count.elapsed.as_secs_f64();
+        if rate < self.min_rate {
+            return OracleResult::Fail(format!(
+                "Message rate dropped to {:.2} msg/s (minimum: {:.2} msg/s)",
+                rate, self.min_rate
+            ));
+        }
+
+        OracleResult::Pass
+    }
+
+    fn name(&self) -> &str {
+        "MessageRateOracle"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connections::ConnectionType;
+    use std::{net, sync::Arc, sync::atomic::AtomicU64, sync::atomic::Ordering};
+
+    /// `MockTransport` answers pings with a pong after a (programmable) latency
+    struct MockTransport {
+        latency_ms: Arc<AtomicU64>,
+        pending: VecDeque<(String, Vec<u8>)>,
+    }
+
+    impl Transport for MockTransport {
+        fn send(&mut self, message: &(String, Vec<u8>)) -> Result<(), String> {
+            if message.0 == "ping" {
+                self.pending
+                    .push_back(("pong".to_string(), message.1.clone()));
+            }
+            Ok(())
+        }
+
+        fn receive(&mut self) -> Result<(String, Vec<u8>), String> {
+            std::thread::sleep(Duration::from_millis(
+                self.latency_ms.load(Ordering::Relaxed),
+            ));
+            self.pending
+                .pop_front()
+                .ok_or_else(|| "No pending messages".to_string())
+        }
+
+        fn local_addr(&self) -> Result<net::SocketAddr, String> {
+            Ok(net::SocketAddr::from(([127, 0, 0, 1], 0)))
+        }
+    }
+
+    #[test]
+    fn slow_response_is_reported() {
+        let latency_ms = Arc::new(AtomicU64::new(10));
+        let mut connection = Connection::new(
+            ConnectionType::Inbound,
+            MockTransport {
+                latency_ms: latency_ms.clone(),
+                pending: VecDeque::new(),
+            },
+        );
+
+        let oracle = ResponseTimeOracle::default();
+        for _ in 0..RTT_WINDOW_SIZE {
+            oracle.record(measure_rtt(&mut connection).unwrap());
+        }
+
+        let pre = measure_rtt(&mut connection).unwrap();
+        let post = measure_rtt(&mut connection).unwrap();
+        assert!(matches!(
+            oracle.evaluate(&mut PingTimeMeasurement { pre, post }),
+            OracleResult::Pass
+        ));
+
+        latency_ms.store(200, Ordering::Relaxed);
+        let post = measure_rtt(&mut connection).unwrap();
+        assert!(matches!(
+            oracle.evaluate(&mut PingTimeMeasurement { pre, post }),
+            OracleResult::PerformanceAnomaly { current_ms, .. } if current_ms >= 200
+        ));
+    }
+
+    #[test]
+    fn baseline_is_a_rolling_window() {
+        let oracle = ResponseTimeOracle::default();
+        assert!(oracle.baseline_p99().is_none());
+
+        for _ in 0..RTT_WINDOW_SIZE {
+            oracle.record(Duration::from_millis(50));
+        }
+        for _ in 0..RTT_WINDOW_SIZE {
+            oracle.record(Duration::from_millis(1));
+        }
+        assert_eq!(oracle.baseline_p99(), Some(Duration::from_millis(1)));
+
+        let mut measurement = PingTimeMeasurement {
+            pre: Duration::from_millis(1),
+            post: Duration::from_millis(10),
+        };
+        assert!(matches!(
+            oracle.evaluate(&mut measurement),
+            OracleResult::PerformanceAnomaly {
+                baseline_p99_ms: 1,
+                current_ms: 10
+            }
+        ));
+    }
+
+    #[test]
+    fn low_message_rate_is_reported() {
+        let oracle = MessageRateOracle::new(10.0);
+        let mut count = MessageCount {
+            messages: 100,
+            elapsed: Duration::from_secs(1),
+        };
+        assert!(matches!(oracle.evaluate(&mut count), OracleResult::Pass));
+
+        count.elapsed = Duration::from_secs(20);
+        assert!(matches!(oracle.evaluate(&mut count), OracleResult::Fail(_)));
+    }
+}
@@ -1,6 +1,9 @@
 use crate::{
-    connections::{Connection, ConnectionType, HandshakeOpts, Transport},
+    connections::{Connection, ConnectionType, HandshakeOpts, Transport, V2Transport},
     dictionaries::{Dictionary, FileDictionary},
+    oracles::{
+        Oracle, OracleResult, PingTimeMeasurement, RTT_WINDOW_SIZE, ResponseTimeOracle, measure_rtt,
+    },
     scenarios::{Scenario, ScenarioInput, ScenarioResult},
     targets::Target,
     test_utils,
@@ -47,12 +50,98 @@ impl<'a> ScenarioInput<'a> for TestCase {
     }
 }
 
+/// `GenericScenarioConfig` describes the initial state set up by `GenericScenario`
+#[derive(Debug, Clone)]
+pub struct GenericScenarioConfig {
+    /// Number of inbound connections using the scenario's transport
+    pub num_inbound: usize,
+    /// Number of outbound connections using the scenario's transport
+    pub num_outbound: usize,
+    /// Number of additional V2 (BIP-324) connections (alternating between outbound and inbound)
+    pub num_v2: usize,
+    /// Number of blocks mined on top of the genesis block
+    pub initial_blocks: usize,
+    /// Mocktime at the start of the setup
+    pub mocktime_start: u64,
+    /// Report testcases after which the target responds significantly slower than usual (see
+    /// `ResponseTimeOracle`)
+    pub with_performance_oracle: bool,
+}
+
+impl Default for GenericScenarioConfig {
+    fn default() -> Self {
+        let genesis_block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        Self {
+            num_inbound: 4,
+            num_outbound: 4,
+            num_v2: 0,
+            initial_blocks: 200,
+            mocktime_start: genesis_block.header.time as u64,
+            with_performance_oracle: false,
+        }
+    }
+}
+
+impl GenericScenarioConfig {
+    /// Create a config from the `FUZZAMOTO_NUM_INBOUND`, `FUZZAMOTO_NUM_OUTBOUND`,
+    /// `FUZZAMOTO_NUM_V2` and `FUZZAMOTO_INITIAL_BLOCKS` environment variables, falling back to
+    /// the defaults for unset (or invalid) variables. The performance oracle is enabled if
+    /// `FUZZAMOTO_PERFORMANCE_ORACLE` is set.
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: usize| {
+            std::env::var(name).map_or(default, |v| v.parse().unwrap_or(default))
+        };
+
+        let default = Self::default();
+        Self {
+            num_inbound: var("FUZZAMOTO_NUM_INBOUND", default.num_inbound),
+            num_outbound: var("FUZZAMOTO_NUM_OUTBOUND", default.num_outbound),
+            num_v2: var("FUZZAMOTO_NUM_V2", default.num_v2),
+            initial_blocks: var("FUZZAMOTO_INITIAL_BLOCKS", default.initial_blocks),
+            with_performance_oracle: std::env::var_os("FUZZAMOTO_PERFORMANCE_ORACLE").is_some(),
+            ..default
+        }
+    }
+
+    /// Total number of connections
+    pub fn num_connections(&self) -> usize {
+        self.num_inbound + self.num_outbound + self.num_v2
+    }
+}
+
+/// Handshake options (relay, wtxidrelay, addrv2, erlay) cycled through for outbound connections
+const OUTBOUND_HANDSHAKES: [(bool, bool, bool, bool); 4] = [
+    (true, true, true, false),
+    (true, true, false, true),
+    (true, false, true, true),
+    (false, false, true, false),
+];
+/// Handshake options (relay, wtxidrelay, addrv2, erlay) cycled through for inbound connections
+const INBOUND_HANDSHAKES: [(bool, bool, bool, bool); 4] = [
+    (true, true, true, true),
+    (true, true, false, true),
+    (true, false, true, true),
+    (false, false, true, false),
+];
+
+fn handshake_opts(time: u64, flags: (bool, bool, bool, bool)) -> HandshakeOpts {
+    let (relay, wtxidrelay, addrv2, erlay) = flags;
+    HandshakeOpts {
+        time: time as i64,
+        relay,
+        starting_height: 0,
+        wtxidrelay,
+        addrv2,
+        erlay,
+    }
+}
+
 /// `GenericScenario` is an implementation agnostic scenario testing the p2p interface of a target
 /// node.
 ///
 /// The scenario setup creates a couple of connections to the target node and mines a chain of 200
-/// blocks. Testcases simulate the processing of a series of messages by the target node, i.e. each
-/// testcase represents a series of three types of actions:
+/// blocks (see `GenericScenarioConfig`). Testcases simulate the processing of a series of messages
+/// by the target node, i.e. each testcase represents a series of three types of actions:
 ///
 /// 1. Send a message to the target node through one of the existing connections
 /// 2. Open a new p2p connection
@@ -60,98 +149,130 @@ impl<'a> ScenarioInput<'a> for TestCase {
 ///
 /// At the end of each test case execution the scenario ensures all sent messages are processed
 /// through a ping/pong roundtrip and checks that the target remains alive with `Target::is_alive`.
+/// If `with_performance_oracle` is set, the ping round-trip time after the testcase is
+/// additionally checked with the `ResponseTimeOracle`.
 pub struct GenericScenario<TX: Transport, T: Target<TX>> {
     pub target: T,
     pub connections: Vec<Connection<TX>>,
+    /// V2 connections opened in addition to `connections`, indexed after `connections` by
+    /// `send`, `send_and_recv` and `num_connections`
+    pub v2_connections: Vec<Connection<V2Transport>>,
     pub time: u64,
     pub block_tree: BTreeMap<BlockHash, (Block, u32)>,
+    pub with_performance_oracle: bool,
+    response_time_oracle: ResponseTimeOracle,
 
     _phantom: std::marker::PhantomData<(TX, T)>,
 }
 
 impl<TX: Transport, T: Target<TX>> GenericScenario<TX, T> {
-    fn from_target(mut target: T) -> Result<Self, String> {
+    /// Total number of connections (including V2 connections)
+    pub fn num_connections(&self) -> usize {
+        self.connections.len() + self.v2_connections.len()
+    }
+
+    /// Send `message` on the connection at `index` (see `num_connections`)
+    pub fn send(&mut self, index: usize, message: &(String, Vec<u8>)) -> Result<(), String> {
+        match index.checked_sub(self.connections.len()) {
+            None => self.connections[index].send(message),
+            Some(v2_index) => self
+                .v2_connections
+                .get_mut(v2_index)
+                .ok_or_else(|| format!("Invalid connection index: {}", index))?
+                .send(message),
+        }
+    }
+
+    /// Send `message` on the connection at `index` (see `num_connections`) and wait for it to be
+    /// processed, see `Connection::send_and_recv`
+    pub fn send_and_recv(
+        &mut self,
+        index: usize,
+        message: &(String, Vec<u8>),
+        recording: bool,
+    ) -> Result<Vec<(String, Vec<u8>)>, String> {
+        match index.checked_sub(self.connections.len()) {
+            None => self.connections[index].send_and_recv(message, recording),
+            Some(v2_index) => self
+                .v2_connections
+                .get_mut(v2_index)
+                .ok_or_else(|| format!("Invalid connection index: {}", index))?
+                .send_and_recv(message, recording),
+        }
+    }
+
+    /// Ping all connections, ensuring all previously sent messages have been processed
+    pub fn ping_connections(&mut self) {
+        for connection in self.connections.iter_mut() {
+            let _ = connection.ping();
+        }
+        for connection in self.v2_connections.iter_mut() {
+            let _ = connection.ping();
+        }
+    }
+}
+
+impl<TX: Transport, T: Target<TX> + Target<V2Transport>> GenericScenario<TX, T> {
+    /// Set up the scenario on `target` as described by `config`
+    #[tracing::instrument(skip(target))]
+    pub fn with_config(mut target: T, config: GenericScenarioConfig) -> Result<Self, String> {
+        if config.num_inbound + config.num_outbound == 0 {
+            return Err("At least one non-V2 connection is required".to_string());
+        }
+
         let genesis_block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
 
-        let mut time = genesis_block.header.time as u64;
+        let mut time = config.mocktime_start;
         target.set_mocktime(time)?;
 
-        let mut connections = vec![
-            (
-                target.connect(ConnectionType::Outbound)?,
-                true,
-                true,
-                true,
-                false,
-            ),
-            (
-                target.connect(ConnectionType::Outbound)?,
-                true,
-                true,
-                false,
-                true,
-            ),
-            (
-                target.connect(ConnectionType::Outbound)?,
-                true,
-                false,
-                true,
-                true,
-            ),
-            (
-                target.connect(ConnectionType::Outbound)?,
-                false,
-                false,
-                true,
-                false,
-            ),
-            (
-                target.connect(ConnectionType::Inbound)?,
-                true,
-                true,
-                true,
-                true,
-            ),
-            (
-                target.connect(ConnectionType::Inbound)?,
-                true,
-                true,
-                false,
-                true,
-            ),
-            (
-                target.connect(ConnectionType::Inbound)?,
-                true,
-                false,
-                true,
-                true,
-            ),
-            (
-                target.connect(ConnectionType::Inbound)?,
-                false,
-                false,
-                true,
-                false,
-            ),
-        ];
+        let mut connections = Vec::new();
+        for i in 0..config.num_outbound {
+            connections.push((
+                <T as Target<TX>>::connect(&mut target, ConnectionType::Outbound)?,
+                OUTBOUND_HANDSHAKES[i % OUTBOUND_HANDSHAKES.len()],
+            ));
+        }
+        for i in 0..config.num_inbound {
+            connections.push((
+                <T as Target<TX>>::connect(&mut target, ConnectionType::Inbound)?,
+                INBOUND_HANDSHAKES[i % INBOUND_HANDSHAKES.len()],
+            ));
+        }
 
-        let mut send_compact = false;
-        for (connection, relay, wtxidrelay, addrv2, erlay) in connections.iter_mut() {
-            connection.version_handshake(HandshakeOpts {
-                time: time as i64,
-                relay: *relay,
-                starting_height: 0,
-                wtxidrelay: *wtxidrelay,
-                addrv2: *addrv2,
-                erlay: *erlay,
-            })?;
+        let mut v2_connections = Vec::new();
+        for i in 0..config.num_v2 {
+            let connection_type = if i % 2 == 0 {
+                ConnectionType::Outbound
+            } else {
+                ConnectionType::Inbound
+            };
+            v2_connections.push((
+                <T as Target<V2Transport>>::connect(&mut target, connection_type)?,
+                (true, true, true, true),
+            ));
+        }
+
+        let sendcmpct = |send_compact| {
             let sendcmpct = NetworkMessage::SendCmpct(SendCmpct {
                 version: 2,
                 send_compact,
             });
-            connection.send(&("sendcmpct".to_string(), encode::serialize(&sendcmpct)))?;
+            ("sendcmpct".to_string(), encode::serialize(&sendcmpct))
+        };
+        let mut send_compact = false;
+        for (connection, flags) in connections.iter_mut() {
+            connection.version_handshake(handshake_opts(time, *flags))?;
+            connection.send(&sendcmpct(send_compact))?;
             send_compact = !send_compact;
         }
+        for (connection, flags) in v2_connections.iter_mut() {
+            connection.version_handshake(handshake_opts(time, *flags))?;
+            connection.send(&sendcmpct(send_compact))?;
+            send_compact = !send_compact;
+        }
+        let connections: Vec<Connection<TX>> = connections.into_iter().map(|(c, _)| c).collect();
+        let v2_connections: Vec<Connection<V2Transport>> =
+            v2_connections.into_iter().map(|(c, _)| c).collect();
 
         let mut prev_hash = genesis_block.block_hash();
         const INTERVAL: u64 = 1;
@@ -159,15 +280,13 @@ impl<TX: Transport, T: Target<TX>> GenericScenario<TX, T> {
         let mut dictionary = FileDictionary::new();
 
         let mut block_tree = BTreeMap::new();
-        for height in 1..=200 {
+        for height in 1..=config.initial_blocks as u32 {
             time += INTERVAL;
 
             let block = test_utils::mining::mine_block(prev_hash, height, time as u32)?;
 
             // Send block to the first connection
-            connections[0]
-                .0
-                .send(&("block".to_string(), encode::serialize(&block)))?;
+            connections[0].send(&("block".to_string(), encode::serialize(&block)))?;
 
             target.set_mocktime(time as u64)?;
 
@@ -193,33 +312,52 @@ impl<TX: Transport, T: Target<TX>> GenericScenario<TX, T> {
         let result = String::from_utf8(output.into_inner()).unwrap();
         println!("{}", result);
 
-        for (connection, _, _, _, _) in connections.iter_mut() {
-            connection.ping()?;
+        let mut scenario = Self {
+            target,
+            time,
+            connections,
+            v2_connections,
+            block_tree,
+            with_performance_oracle: config.with_performance_oracle,
+            response_time_oracle: ResponseTimeOracle::default(),
+            _phantom: std::marker::PhantomData,
+        };
+        scenario.ping_connections();
+
+        // Record the baseline round-trip times as part of the setup, so that they are available
+        // in every (snapshot restored) testcase execution
+        if scenario.with_performance_oracle {
+            for _ in 0..RTT_WINDOW_SIZE {
+                let rtt = measure_rtt(&mut scenario.connections[0])?;
+                scenario.response_time_oracle.record(rtt);
+            }
         }
 
         // Announce the tip on all connections
-        for (connection, _, _, _, _) in connections.iter_mut() {
-            let inv = NetworkMessage::Inv(vec![Inventory::Block(prev_hash)]);
-            connection.send_and_recv(&("inv".to_string(), encode::serialize(&inv)), false)?;
+        let inv = NetworkMessage::Inv(vec![Inventory::Block(prev_hash)]);
+        for index in 0..scenario.num_connections() {
+            scenario.send_and_recv(index, &("inv".to_string(), encode::serialize(&inv)), false)?;
         }
 
-        Ok(Self {
-            target,
-            time,
-            connections: connections.drain(..).map(|(c, _, _, _, _)| c).collect(),
-            block_tree,
-            _phantom: std::marker::PhantomData,
-        })
+        Ok(scenario)
     }
 }
 
-impl<'a, TX: Transport, T: Target<TX>> Scenario<'a, TestCase> for GenericScenario<TX, T> {
+impl<'a, TX: Transport, T: Target<TX> + Target<V2Transport>> Scenario<'a, TestCase>
+    for GenericScenario<TX, T>
+{
     fn new(args: &[String]) -> Result<Self, String> {
         let target = T::from_path(&args[1])?;
-        Self::from_target(target)
+        Self::with_config(target, GenericScenarioConfig::from_env())
     }
 
     fn run(&mut self, testcase: TestCase) -> ScenarioResult {
+        let pre_rtt = if self.with_performance_oracle {
+            measure_rtt(&mut self.connections[0]).ok()
+        } else {
+            None
+        };
+
         for action in testcase.actions {
             match action {
                 Action::Connect { connection_type: _ } => {
@@ -232,16 +370,15 @@ impl<'a, TX: Transport, T: Target<TX>> Scenario<'a, TestCase> for GenericScenari
                     command,
                     data,
                 } => {
-                    if self.connections.is_empty() {
+                    let num_connections = self.num_connections();
+                    if num_connections == 0 {
                         continue;
                     }
 
-                    let num_connections = self.connections.len();
-                    if let Some(connection) =
-                        self.connections.get_mut(from as usize % num_connections)
-                    {
-                        let _ = connection.send(&(command.to_string(), data));
-                    }
+                    let _ = self.send(
+                        from as usize % num_connections,
+                        &(command.to_string(), data),
+                    );
                 }
                 Action::SetMocktime { time } => {
                     let _ = self.target.set_mocktime(time);
@@ -253,14 +390,30 @@ impl<'a, TX: Transport, T: Target<TX>> Scenario<'a, TestCase> for GenericScenari
             }
         }
 
-        for connection in self.connections.iter_mut() {
-            let _ = connection.ping();
-        }
+        // The round-trip time after the testcase includes the processing of the messages sent on
+        // the first connection
+        let post_rtt = pre_rtt.and_then(|_| measure_rtt(&mut self.connections[0]).ok());
+
+        self.ping_connections();
 
         if let Err(e) = self.target.is_alive() {
             return ScenarioResult::Fail(format!("Target is not alive: {}", e));
         }
 
+        if let (Some(pre), Some(post)) = (pre_rtt, post_rtt)
+            && let OracleResult::PerformanceAnomaly {
+                baseline_p99_ms,
+                current_ms,
+            } = self
+                .response_time_oracle
+                .evaluate(&mut PingTimeMeasurement { pre, post })
+        {
+            return ScenarioResult::Fail(format!(
+                "Response time anomaly: {}ms (baseline P99: {}ms)",
+                current_ms, baseline_p99_ms
+            ));
+        }
+
         ScenarioResult::Ok
     }
 }
@@ -31,11 +31,10 @@ where
 macro_rules! fuzzamoto_main {
     ($scenario_type:ty, $testcase_type:ty) => {
         fn main() -> std::process::ExitCode {
-            use env_logger;
             use fuzzamoto::runners::{Runner, StdRunner};
             use std::process::ExitCode;
 
-            env_logger::init();
+            tracing_subscriber::fmt::init();
 
             // Initializing the runner before initializing the scenario is important when executing
             // in Nyx to ensure `nyx_init` is called before targets are spawned.
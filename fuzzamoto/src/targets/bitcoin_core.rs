@@ -1,12 +1,16 @@
 use crate::{
     connections::{Connection, ConnectionType, V1Transport, V2Transport},
     targets::{
-        GenerateToAddress, HasBlockTemplate, HasGetBlock, HasGetRawMempoolEntries, HasTipInfo,
+        GenerateToAddress, HasBlockTemplate, HasEstimateSmartFee, HasGetBlock, HasGetBlockFilter,
+        HasGetPeerInfo, HasGetRawMempoolEntries, HasScanTxOutSet, HasSubmitPackage, HasTipInfo,
         HasTxOutSetInfo, Target, TargetNode, Txid,
     },
 };
 
-use bitcoin::{Amount, Block, BlockHash};
+use bitcoin::{
+    Amount, Block, BlockHash, Transaction, bip158::FilterHeader, consensus::encode::serialize_hex,
+    hashes::Hash, hex::FromHex,
+};
 use corepc_node::{Conf, Node, P2P};
 use std::{
     net::{SocketAddrV4, TcpListener, TcpStream},
@@ -81,6 +85,7 @@ impl BitcoinCoreTarget {
 
 /// Transport-independent implementation for BitcoinCoreTarget
 impl TargetNode for BitcoinCoreTarget {
+    #[tracing::instrument]
     fn from_path(exe_path: &str) -> Result<Self, String> {
         let config = Self::base_config();
 
@@ -350,6 +355,15 @@ pub struct MempoolEntry {
 }
 
 impl MempoolEntry {
+    #[cfg(test)]
+    pub(crate) fn new(txid: Txid) -> Self {
+        Self {
+            txid,
+            depends: Vec::new(),
+            spentby: Vec::new(),
+        }
+    }
+
     pub fn txid(&self) -> &Txid {
         &self.txid
     }
@@ -480,6 +494,299 @@ impl HasTxOutSetInfo for BitcoinCoreTarget {
     }
 }
 
+/// Outcome of a `submitpackage` call
+#[derive(Clone, Debug, Default)]
+pub struct SubmitPackageResult {
+    /// Transactions that are in the mempool after the call (including ones that were already
+    /// present)
+    pub accepted: Vec<Txid>,
+    /// Transactions that were rejected, with the reject reason
+    pub rejected: Vec<(Txid, String)>,
+    /// Package feerate in BTC/kvB, 0 if not reported (e.g. if all transactions were already in
+    /// the mempool)
+    pub package_feerate: f64,
+}
+
+impl SubmitPackageResult {
+    /// Parse the result of a `submitpackage` RPC call
+    pub fn from_rpc_response(response: &serde_json::Value) -> Result<Self, String> {
+        let tx_results = response
+            .get("tx-results")
+            .and_then(|results| results.as_object())
+            .ok_or_else(|| "Failed to decode submitpackage tx-results".to_string())?;
+
+        let mut result = SubmitPackageResult {
+            package_feerate: response
+                .get("package_feerate")
+                .and_then(|feerate| feerate.as_f64())
+                .unwrap_or(0.0),
+            ..Default::default()
+        };
+
+        for (wtxid, tx_result) in tx_results.iter() {
+            let txid = tx_result
+                .get("txid")
+                .and_then(|txid| txid.as_str())
+                .and_then(|txid| Txid::from_str(txid).ok())
+                .ok_or_else(|| format!("Failed to decode txid for wtxid: {}", wtxid))?;
+
+            match tx_result.get("error") {
+                Some(serde_json::Value::String(error)) => {
+                    result.rejected.push((txid, error.clone()))
+                }
+                Some(serde_json::Value::Null) | None => result.accepted.push(txid),
+                Some(_) => return Err(format!("Failed to decode error for txid: {}", txid)),
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+impl HasSubmitPackage for BitcoinCoreTarget {
+    fn submitpackage(&self, txs: &[Transaction]) -> Result<SubmitPackageResult, String> {
+        let raw_txs: Vec<String> = txs.iter().map(serialize_hex).collect();
+        let response = self
+            .node
+            .client
+            .call::<serde_json::Value>("submitpackage", &[serde_json::json!(raw_txs)])
+            .map_err(|e| format!("Failed to call submitpackage: {:?}", e))?;
+
+        SubmitPackageResult::from_rpc_response(&response)
+    }
+}
+
+/// Unspent transaction output, as reported by `scantxoutset`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnspentOutput {
+    pub txid: Txid,
+    pub vout: u32,
+    pub script_pubkey: Vec<u8>,
+    pub amount: Amount,
+    /// Whether the output was created by a coinbase transaction
+    pub coinbase: bool,
+    /// Height of the block that created the output
+    pub height: u64,
+}
+
+impl UnspentOutput {
+    /// Parse the result of a `scantxoutset start` RPC call
+    pub fn from_rpc_response(response: &serde_json::Value) -> Result<Vec<Self>, String> {
+        let unspents = response
+            .get("unspents")
+            .and_then(|unspents| unspents.as_array())
+            .ok_or_else(|| "scantxoutset result has no unspents".to_string())?;
+
+        unspents
+            .iter()
+            .map(|unspent| {
+                let txid = unspent
+                    .get("txid")
+                    .and_then(|txid| txid.as_str())
+                    .and_then(|txid| Txid::from_str(txid).ok())
+                    .ok_or_else(|| "Failed to decode unspent txid".to_string())?;
+                let vout = unspent
+                    .get("vout")
+                    .and_then(|vout| vout.as_u64())
+                    .and_then(|vout| u32::try_from(vout).ok())
+                    .ok_or_else(|| "Missing unspent vout".to_string())?;
+                let script_pubkey = unspent
+                    .get("scriptPubKey")
+                    .and_then(|script| script.as_str())
+                    .and_then(|script| Vec::<u8>::from_hex(script).ok())
+                    .ok_or_else(|| "Failed to decode unspent scriptPubKey".to_string())?;
+                let amount = unspent
+                    .get("amount")
+                    .and_then(|amount| amount.as_f64())
+                    .and_then(|amount| Amount::from_btc(amount).ok())
+                    .ok_or_else(|| "Failed to decode unspent amount".to_string())?;
+                let coinbase = unspent
+                    .get("coinbase")
+                    .and_then(|coinbase| coinbase.as_bool())
+                    .ok_or_else(|| "Missing unspent coinbase flag".to_string())?;
+                let height = unspent
+                    .get("height")
+                    .and_then(|height| height.as_u64())
+                    .ok_or_else(|| "Missing unspent height".to_string())?;
+
+                Ok(UnspentOutput {
+                    txid,
+                    vout,
+                    script_pubkey,
+                    amount,
+                    coinbase,
+                    height,
+                })
+            })
+            .collect()
+    }
+}
+
+impl HasScanTxOutSet for BitcoinCoreTarget {
+    fn scan_tx_out_set(&self, descriptors: &[String]) -> Result<Vec<UnspentOutput>, String> {
+        let response = self
+            .node
+            .client
+            .call::<serde_json::Value>(
+                "scantxoutset",
+                &[serde_json::json!("start"), serde_json::json!(descriptors)],
+            )
+            .map_err(|e| format!("Failed to call scantxoutset: {:?}", e))?;
+
+        UnspentOutput::from_rpc_response(&response)
+    }
+}
+
+/// Compact block filter (BIP-158) of a block
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BlockFilter {
+    /// Serialized filter
+    pub filter: Vec<u8>,
+    /// Filter header (in internal byte order)
+    pub header: [u8; 32],
+}
+
+impl BlockFilter {
+    /// Parse the result of a `getblockfilter` RPC call
+    pub fn from_rpc_response(response: &serde_json::Value) -> Result<Self, String> {
+        let filter = response
+            .get("filter")
+            .and_then(|filter| filter.as_str())
+            .and_then(|filter| Vec::<u8>::from_hex(filter).ok())
+            .ok_or_else(|| "Failed to decode getblockfilter filter".to_string())?;
+        let header = response
+            .get("header")
+            .and_then(|header| header.as_str())
+            .and_then(|header| FilterHeader::from_str(header).ok())
+            .ok_or_else(|| "Failed to decode getblockfilter header".to_string())?;
+
+        Ok(BlockFilter {
+            filter,
+            header: header.to_byte_array(),
+        })
+    }
+}
+
+impl HasGetBlockFilter for BitcoinCoreTarget {
+    fn getblockfilter(&self, hash: BlockHash, filter_type: &str) -> Result<BlockFilter, String> {
+        let response = self
+            .node
+            .client
+            .call::<serde_json::Value>(
+                "getblockfilter",
+                &[
+                    serde_json::json!(hash.to_string()),
+                    serde_json::json!(filter_type),
+                ],
+            )
+            .map_err(|e| format!("Failed to call getblockfilter: {:?}", e))?;
+
+        BlockFilter::from_rpc_response(&response)
+    }
+}
+
+/// Peer of a node, as reported by `getpeerinfo`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerInfo {
+    /// Peer id assigned by the node
+    pub id: u64,
+    /// Address of the peer (e.g. `127.0.0.1:8333` or `[::1]:8333`)
+    pub addr: String,
+    /// Whether the peer connected to the node (as opposed to the node connecting to the peer)
+    pub inbound: bool,
+}
+
+impl PeerInfo {
+    /// Parse the result of a `getpeerinfo` RPC call
+    pub fn from_rpc_response(response: &serde_json::Value) -> Result<Vec<Self>, String> {
+        let peers = response
+            .as_array()
+            .ok_or_else(|| "getpeerinfo result is not an array".to_string())?;
+
+        peers
+            .iter()
+            .map(|peer| {
+                let id = peer
+                    .get("id")
+                    .and_then(|id| id.as_u64())
+                    .ok_or_else(|| "Missing peer id".to_string())?;
+                let addr = peer
+                    .get("addr")
+                    .and_then(|addr| addr.as_str())
+                    .ok_or_else(|| "Missing peer addr".to_string())?;
+                let inbound = peer
+                    .get("inbound")
+                    .and_then(|inbound| inbound.as_bool())
+                    .ok_or_else(|| "Missing peer inbound flag".to_string())?;
+                Ok(PeerInfo {
+                    id,
+                    addr: addr.to_string(),
+                    inbound,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Fee rate estimate of a node, as reported by `estimatesmartfee`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeEstimate {
+    /// Estimated fee rate in sat/kvB
+    pub sat_per_kvb: u64,
+    /// Confirmation target (in blocks) the estimate was found for
+    pub blocks: u64,
+}
+
+impl FeeEstimate {
+    /// Parse the result of an `estimatesmartfee` RPC call, `None` if the node has no estimate
+    /// (e.g. because it has not seen enough transactions confirm yet)
+    pub fn from_rpc_response(response: &serde_json::Value) -> Result<Option<Self>, String> {
+        let Some(feerate) = response.get("feerate") else {
+            return Ok(None);
+        };
+        let feerate = feerate
+            .as_f64()
+            .ok_or_else(|| "Failed to decode estimatesmartfee feerate".to_string())?;
+        let blocks = response
+            .get("blocks")
+            .and_then(|blocks| blocks.as_u64())
+            .ok_or_else(|| "Failed to decode estimatesmartfee blocks".to_string())?;
+
+        // BTC/kvB to sat/kvB
+        let sat_per_kvb = Amount::from_btc(feerate)
+            .map_err(|e| format!("Failed to decode estimatesmartfee feerate: {}", e))?
+            .to_sat();
+        Ok(Some(FeeEstimate {
+            sat_per_kvb,
+            blocks,
+        }))
+    }
+}
+
+impl HasEstimateSmartFee for BitcoinCoreTarget {
+    fn estimate_smart_fee(&self, conf_target: u16) -> Result<Option<FeeEstimate>, String> {
+        let response = self
+            .node
+            .client
+            .call::<serde_json::Value>("estimatesmartfee", &[conf_target.into()])
+            .map_err(|e| format!("Failed to call estimatesmartfee: {:?}", e))?;
+
+        FeeEstimate::from_rpc_response(&response)
+    }
+}
+
+impl HasGetPeerInfo for BitcoinCoreTarget {
+    fn get_peer_info(&self) -> Result<Vec<PeerInfo>, String> {
+        let response = self
+            .node
+            .client
+            .call::<serde_json::Value>("getpeerinfo", &[])
+            .map_err(|e| format!("Failed to call getpeerinfo: {:?}", e))?;
+
+        PeerInfo::from_rpc_response(&response)
+    }
+}
+
 impl HasBlockTemplate for BitcoinCoreTarget {
     fn block_template(&self) -> Result<(), String> {
         // After calling getblocktemplate, the peer will call BlockAssembler::CreateNewBlock(), and the node in turn calls TestBlockValidity for us
@@ -515,3 +822,141 @@ impl GenerateToAddress for BitcoinCoreTarget {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENT_TXID: &str = "8ab1c6e8b36e4c19b2a2b7e4c19b1a3f1de48cd6d3a7a2d16e28ef4c4d6a5b01";
+    const CHILD_TXID: &str = "2f0fa96c7a3f43c1b5fd1e9a2c5a1de54e0c2bd1f4a16f0c2bd8ea93a6a5b902";
+
+    #[test]
+    fn parse_accepted_package() {
+        let response = serde_json::json!({
+            "package_msg": "success",
+            "tx-results": {
+                "aa": { "txid": PARENT_TXID, "vsize": 110 },
+                "bb": { "txid": CHILD_TXID, "vsize": 110 },
+            },
+            "package_feerate": 0.0005,
+        });
+
+        let result = SubmitPackageResult::from_rpc_response(&response).unwrap();
+        assert_eq!(result.accepted.len(), 2);
+        assert!(
+            result
+                .accepted
+                .contains(&Txid::from_str(PARENT_TXID).unwrap())
+        );
+        assert!(result.rejected.is_empty());
+        assert_eq!(result.package_feerate, 0.0005);
+    }
+
+    #[test]
+    fn parse_rejected_transaction() {
+        let response = serde_json::json!({
+            "package_msg": "transaction failed",
+            "tx-results": {
+                "aa": { "txid": PARENT_TXID, "error": "min relay fee not met" },
+                "bb": { "txid": CHILD_TXID, "error": null },
+            },
+        });
+
+        let result = SubmitPackageResult::from_rpc_response(&response).unwrap();
+        assert_eq!(result.accepted, vec![Txid::from_str(CHILD_TXID).unwrap()]);
+        assert_eq!(
+            result.rejected,
+            vec![(
+                Txid::from_str(PARENT_TXID).unwrap(),
+                "min relay fee not met".to_string()
+            )]
+        );
+        assert_eq!(result.package_feerate, 0.0);
+    }
+
+    #[test]
+    fn parse_block_filter() {
+        let header = "8ab1c6e8b36e4c19b2a2b7e4c19b1a3f1de48cd6d3a7a2d16e28ef4c4d6a5b01";
+        let response = serde_json::json!({ "filter": "01f3b2a0", "header": header });
+
+        let filter = BlockFilter::from_rpc_response(&response).unwrap();
+        assert_eq!(filter.filter, vec![0x01, 0xf3, 0xb2, 0xa0]);
+        // Headers are displayed in reverse byte order
+        assert_eq!(filter.header[0], 0x01);
+        assert_eq!(filter.header[31], 0x8a);
+
+        assert!(BlockFilter::from_rpc_response(&serde_json::json!({ "filter": "01" })).is_err());
+    }
+
+    #[test]
+    fn parse_peer_info() {
+        let response = serde_json::json!([
+            { "id": 0, "addr": "127.0.0.1:50124", "inbound": true },
+            { "id": 3, "addr": "[fc00::1]:8333", "inbound": false },
+        ]);
+
+        let peers = PeerInfo::from_rpc_response(&response).unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[1].id, 3);
+        assert_eq!(peers[1].addr, "[fc00::1]:8333");
+        assert!(!peers[1].inbound);
+
+        assert!(PeerInfo::from_rpc_response(&serde_json::json!([{ "id": 0 }])).is_err());
+    }
+
+    #[test]
+    fn parse_fee_estimate() {
+        let response = serde_json::json!({ "feerate": 0.00012345, "blocks": 2 });
+        assert_eq!(
+            FeeEstimate::from_rpc_response(&response).unwrap(),
+            Some(FeeEstimate {
+                sat_per_kvb: 12_345,
+                blocks: 2,
+            })
+        );
+
+        let response = serde_json::json!({
+            "errors": ["Insufficient data or no feerate found"],
+            "blocks": 0,
+        });
+        assert_eq!(FeeEstimate::from_rpc_response(&response).unwrap(), None);
+
+        let response = serde_json::json!({ "feerate": "high", "blocks": 2 });
+        assert!(FeeEstimate::from_rpc_response(&response).is_err());
+    }
+
+    #[test]
+    fn reject_malformed_response() {
+        assert!(SubmitPackageResult::from_rpc_response(&serde_json::json!({})).is_err());
+        let response = serde_json::json!({ "tx-results": { "aa": { "txid": "zz" } } });
+        assert!(SubmitPackageResult::from_rpc_response(&response).is_err());
+    }
+
+    #[test]
+    fn parse_scan_tx_out_set() {
+        let response = serde_json::json!({
+            "success": true,
+            "txouts": 200,
+            "height": 200,
+            "unspents": [{
+                "txid": PARENT_TXID,
+                "vout": 0,
+                "scriptPubKey": "00204ae81572f06e1b88fd5ced7a1a000945432e83e1551e6f721ee9c00b8cc33260",
+                "amount": 25.0,
+                "coinbase": true,
+                "height": 1,
+            }],
+            "total_amount": 25.0,
+        });
+
+        let unspents = UnspentOutput::from_rpc_response(&response).unwrap();
+        assert_eq!(unspents.len(), 1);
+        assert_eq!(unspents[0].txid, Txid::from_str(PARENT_TXID).unwrap());
+        assert_eq!(unspents[0].script_pubkey.len(), 34);
+        assert_eq!(unspents[0].amount, Amount::from_btc(25.0).unwrap());
+        assert!(unspents[0].coinbase);
+        assert_eq!(unspents[0].height, 1);
+
+        assert!(UnspentOutput::from_rpc_response(&serde_json::json!({ "success": true })).is_err());
+    }
+}
@@ -0,0 +1,498 @@
+use std::{collections::HashSet, marker::PhantomData};
+
+use bitcoin::{BlockHash, Txid};
+
+use crate::{
+    connections::{Connection, ConnectionType, HandshakeOpts, Transport},
+    targets::{ConnectableTarget, HasBlockChainInterface, Target, TargetNode},
+};
+
+/// First message after which the primary and secondary target responded differently
+#[derive(Debug, Clone)]
+pub struct DivergencePoint {
+    /// Index of the sent message that triggered the diverging responses
+    pub message_index: usize,
+    /// Commands of the messages the primary target responded with
+    pub primary_commands: Vec<String>,
+    /// Commands of the messages the secondary target responded with
+    pub secondary_commands: Vec<String>,
+}
+
+/// Result of sending the same messages to two targets
+#[derive(Debug, Clone)]
+pub struct DiffResult {
+    pub primary_response: Vec<(String, Vec<u8>)>,
+    pub secondary_response: Vec<(String, Vec<u8>)>,
+    pub divergence: Option<DivergencePoint>,
+}
+
+/// Difference between the chain states (tip and mempool) of two targets
+#[derive(Debug, Clone)]
+pub struct ChainStateDiff {
+    pub primary_tip: Option<(BlockHash, u64)>,
+    pub secondary_tip: Option<(BlockHash, u64)>,
+    /// Mempool transactions only present on the primary target
+    pub primary_only_txids: Vec<Txid>,
+    /// Mempool transactions only present on the secondary target
+    pub secondary_only_txids: Vec<Txid>,
+}
+
+impl ChainStateDiff {
+    /// Whether both targets have the same tip and mempool
+    pub fn is_consistent(&self) -> bool {
+        self.primary_tip == self.secondary_tip
+            && self.primary_only_txids.is_empty()
+            && self.secondary_only_txids.is_empty()
+    }
+}
+
+/// `DifferentialTarget` wraps two targets (e.g. two different Bitcoin implementations) to apply
+/// the same inputs to both of them.
+///
+/// It acts as a `Target` by forwarding to the primary target, while `run_differential` sends
+/// messages to both targets and compares their responses.
+pub struct DifferentialTarget<TX1, TX2, T1, T2>
+where
+    TX1: Transport,
+    TX2: Transport,
+    T1: Target<TX1>,
+    T2: Target<TX2>,
+{
+    pub primary: T1,
+    pub secondary: T2,
+    connections: Option<(Connection<TX1>, Connection<TX2>)>,
+    time: u64,
+    _phantom: PhantomData<(TX1, TX2)>,
+}
+
+impl<TX1, TX2, T1, T2> DifferentialTarget<TX1, TX2, T1, T2>
+where
+    TX1: Transport,
+    TX2: Transport,
+    T1: Target<TX1>,
+    T2: Target<TX2>,
+{
+    pub fn new(primary: T1, secondary: T2) -> Self {
+        let genesis_block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        Self {
+            primary,
+            secondary,
+            connections: None,
+            time: genesis_block.header.time as u64,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Get the connections used for differential testing, creating them on first use
+    fn connections(&mut self) -> Result<&mut (Connection<TX1>, Connection<TX2>), String> {
+        if self.connections.is_none() {
+            let opts = HandshakeOpts {
+                time: self.time as i64,
+                relay: true,
+                starting_height: 0,
+                wtxidrelay: true,
+                addrv2: true,
+                erlay: false,
+            };
+
+            let mut primary = self.primary.connect(ConnectionType::Inbound)?;
+            primary.version_handshake(opts.clone())?;
+            let mut secondary = self.secondary.connect(ConnectionType::Inbound)?;
+            secondary.version_handshake(opts)?;
+
+            self.connections = Some((primary, secondary));
+        }
+
+        Ok(self.connections.as_mut().unwrap())
+    }
+
+    /// Send `messages` to both targets and compare the responses.
+    ///
+    /// Only the message types of the responses are compared, as their payloads may legitimately
+    /// differ between implementations (e.g. nonces or user agents).
+    pub fn run_differential(
+        &mut self,
+        messages: &[(String, Vec<u8>)],
+    ) -> Result<DiffResult, String> {
+        let (primary, secondary) = self.connections()?;
+
+        let mut result = DiffResult {
+            primary_response: Vec::new(),
+            secondary_response: Vec::new(),
+            divergence: None,
+        };
+
+        for (message_index, message) in messages.iter().enumerate() {
+            let primary_response = primary.send_and_recv(message, true)?;
+            let secondary_response = secondary.send_and_recv(message, true)?;
+
+            let commands = |response: &[(String, Vec<u8>)]| -> Vec<String> {
+                response
+                    .iter()
+                    .map(|(command, _)| command.clone())
+                    .collect()
+            };
+            let primary_commands = commands(&primary_response);
+            let secondary_commands = commands(&secondary_response);
+            if result.divergence.is_none() && primary_commands != secondary_commands {
+                result.divergence = Some(DivergencePoint {
+                    message_index,
+                    primary_commands,
+                    secondary_commands,
+                });
+            }
+
+            result.primary_response.extend(primary_response);
+            result.secondary_response.extend(secondary_response);
+        }
+
+        Ok(result)
+    }
+}
+
+/// Compare the tips and mempools of two targets
+pub fn compare_chain_state<T1: HasBlockChainInterface, T2: HasBlockChainInterface>(
+    t1: &T1,
+    t2: &T2,
+) -> Result<ChainStateDiff, String> {
+    let primary_txids: HashSet<Txid> = t1
+        .get_mempool_entries()?
+        .iter()
+        .map(|entry| *entry.txid())
+        .collect();
+    let secondary_txids: HashSet<Txid> = t2
+        .get_mempool_entries()?
+        .iter()
+        .map(|entry| *entry.txid())
+        .collect();
+
+    Ok(ChainStateDiff {
+        primary_tip: t1.get_tip_info(),
+        secondary_tip: t2.get_tip_info(),
+        primary_only_txids: primary_txids
+            .difference(&secondary_txids)
+            .copied()
+            .collect(),
+        secondary_only_txids: secondary_txids
+            .difference(&primary_txids)
+            .copied()
+            .collect(),
+    })
+}
+
+impl<TX1, TX2, T1, T2> TargetNode for DifferentialTarget<TX1, TX2, T1, T2>
+where
+    TX1: Transport,
+    TX2: Transport,
+    T1: Target<TX1>,
+    T2: Target<TX2>,
+{
+    /// Create both targets from a path of the form `<primary>,<secondary>`
+    fn from_path(path: &str) -> Result<Self, String> {
+        let (primary, secondary) = path
+            .split_once(',')
+            .ok_or_else(|| format!("Expected '<primary>,<secondary>' paths, got: {}", path))?;
+        Ok(Self::new(
+            T1::from_path(primary)?,
+            T2::from_path(secondary)?,
+        ))
+    }
+
+    fn set_mocktime(&mut self, time: u64) -> Result<(), String> {
+        self.time = time;
+        self.primary.set_mocktime(time)?;
+        self.secondary.set_mocktime(time)
+    }
+
+    fn is_alive(&self) -> Result<(), String> {
+        self.primary
+            .is_alive()
+            .map_err(|e| format!("Primary target is not alive: {}", e))?;
+        self.secondary
+            .is_alive()
+            .map_err(|e| format!("Secondary target is not alive: {}", e))
+    }
+}
+
+impl<TX1, TX2, T1, T2> Target<TX1> for DifferentialTarget<TX1, TX2, T1, T2>
+where
+    TX1: Transport,
+    TX2: Transport,
+    T1: Target<TX1>,
+    T2: Target<TX2>,
+{
+    fn connect(&mut self, connection_type: ConnectionType) -> Result<Connection<TX1>, String> {
+        self.primary.connect(connection_type)
+    }
+
+    fn connect_to<O: ConnectableTarget>(&mut self, other: &O) -> Result<(), String> {
+        self.primary.connect_to(other)
+    }
+}
+
+impl<TX1, TX2, T1, T2> ConnectableTarget for DifferentialTarget<TX1, TX2, T1, T2>
+where
+    TX1: Transport,
+    TX2: Transport,
+    T1: Target<TX1> + ConnectableTarget,
+    T2: Target<TX2>,
+{
+    fn get_addr(&self) -> Option<std::net::SocketAddrV4> {
+        self.primary.get_addr()
+    }
+
+    fn is_connected_to<O: ConnectableTarget>(&self, other: &O) -> bool {
+        self.primary.is_connected_to(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::targets::{
+        HasBlockTemplate, HasGetBlock, HasGetRawMempoolEntries, HasTipInfo, HasTxOutSetInfo,
+        bitcoin_core::{MempoolEntry, TxOutSetInfo},
+    };
+    use bitcoin::{Block, hashes::Hash};
+    use std::{collections::VecDeque, net};
+
+    /// Determines the commands a `FakeNode` responds with to a received command
+    type Responder = fn(&str) -> Vec<&'static str>;
+
+    /// `FakeTransport` answers handshakes and pings like a real node, and all other messages
+    /// according to its `Responder`
+    struct FakeTransport {
+        respond: Responder,
+        pending: VecDeque<(String, Vec<u8>)>,
+    }
+
+    impl Transport for FakeTransport {
+        fn send(&mut self, message: &(String, Vec<u8>)) -> Result<(), String> {
+            match message.0.as_str() {
+                "version" | "wtxidrelay" | "sendaddrv2" => {}
+                "verack" => self.pending.push_back(("verack".to_string(), vec![])),
+                "ping" => self
+                    .pending
+                    .push_back(("pong".to_string(), message.1.clone())),
+                command => self.pending.extend(
+                    (self.respond)(command)
+                        .into_iter()
+                        .map(|response| (response.to_string(), vec![])),
+                ),
+            }
+            Ok(())
+        }
+
+        fn receive(&mut self) -> Result<(String, Vec<u8>), String> {
+            self.pending
+                .pop_front()
+                .ok_or_else(|| "No pending messages".to_string())
+        }
+
+        fn local_addr(&self) -> Result<net::SocketAddr, String> {
+            Ok(net::SocketAddr::from(([127, 0, 0, 1], 0)))
+        }
+    }
+
+    struct FakeNode {
+        respond: Responder,
+        tip: Option<(BlockHash, u64)>,
+        mempool: Vec<Txid>,
+    }
+
+    impl FakeNode {
+        fn new(respond: Responder) -> Self {
+            Self {
+                respond,
+                tip: None,
+                mempool: Vec::new(),
+            }
+        }
+    }
+
+    impl TargetNode for FakeNode {
+        fn from_path(_path: &str) -> Result<Self, String> {
+            Ok(Self::new(|_| vec![]))
+        }
+
+        fn set_mocktime(&mut self, _time: u64) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn is_alive(&self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl Target<FakeTransport> for FakeNode {
+        fn connect(
+            &mut self,
+            connection_type: ConnectionType,
+        ) -> Result<Connection<FakeTransport>, String> {
+            Ok(Connection::new(
+                connection_type,
+                FakeTransport {
+                    respond: self.respond,
+                    pending: VecDeque::new(),
+                },
+            ))
+        }
+
+        fn connect_to<O: ConnectableTarget>(&mut self, _other: &O) -> Result<(), String> {
+            Err("Not supported".to_string())
+        }
+    }
+
+    impl HasTipInfo for FakeNode {
+        fn get_tip_info(&self) -> Option<(BlockHash, u64)> {
+            self.tip
+        }
+    }
+
+    impl HasGetBlock for FakeNode {
+        fn get_block(&self, _hash: BlockHash) -> Option<Block> {
+            None
+        }
+    }
+
+    impl HasTxOutSetInfo for FakeNode {
+        fn tx_out_set_info(&self) -> Result<TxOutSetInfo, String> {
+            Err("Not supported".to_string())
+        }
+    }
+
+    impl HasGetRawMempoolEntries for FakeNode {
+        fn get_mempool_entries(&self) -> Result<Vec<MempoolEntry>, String> {
+            Ok(self
+                .mempool
+                .iter()
+                .copied()
+                .map(MempoolEntry::new)
+                .collect())
+        }
+    }
+
+    impl HasBlockTemplate for FakeNode {
+        fn block_template(&self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn reference_responses(command: &str) -> Vec<&'static str> {
+        match command {
+            "getheaders" => vec!["headers"],
+            "mempool" => vec!["inv"],
+            "getaddr" => vec!["addr"],
+            _ => vec![],
+        }
+    }
+
+    /// Ignores `mempool` requests and answers `getaddr` with `addrv2`
+    fn diverging_responses(command: &str) -> Vec<&'static str> {
+        match command {
+            "mempool" => vec![],
+            "getaddr" => vec!["addrv2"],
+            command => reference_responses(command),
+        }
+    }
+
+    fn messages(commands: &[&str]) -> Vec<(String, Vec<u8>)> {
+        commands
+            .iter()
+            .map(|command| (command.to_string(), vec![]))
+            .collect()
+    }
+
+    fn response_commands(response: &[(String, Vec<u8>)]) -> Vec<&str> {
+        response
+            .iter()
+            .map(|(command, _)| command.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn identical_targets_do_not_diverge() {
+        let mut target: DifferentialTarget<FakeTransport, FakeTransport, _, _> =
+            DifferentialTarget::new(
+                FakeNode::new(reference_responses),
+                FakeNode::new(reference_responses),
+            );
+
+        let result = target
+            .run_differential(&messages(&["getheaders", "mempool", "getaddr"]))
+            .unwrap();
+        assert!(result.divergence.is_none());
+        assert_eq!(
+            response_commands(&result.primary_response),
+            ["headers", "inv", "addr"]
+        );
+        assert_eq!(result.primary_response, result.secondary_response);
+    }
+
+    #[test]
+    fn reports_first_divergence() {
+        let mut target: DifferentialTarget<FakeTransport, FakeTransport, _, _> =
+            DifferentialTarget::new(
+                FakeNode::new(reference_responses),
+                FakeNode::new(diverging_responses),
+            );
+
+        let result = target
+            .run_differential(&messages(&["getheaders", "mempool", "getaddr"]))
+            .unwrap();
+        let divergence = result.divergence.expect("targets should diverge");
+        assert_eq!(divergence.message_index, 1);
+        assert_eq!(divergence.primary_commands, ["inv"]);
+        assert!(divergence.secondary_commands.is_empty());
+
+        // All responses are recorded, including those after the divergence
+        assert_eq!(
+            response_commands(&result.primary_response),
+            ["headers", "inv", "addr"]
+        );
+        assert_eq!(
+            response_commands(&result.secondary_response),
+            ["headers", "addrv2"]
+        );
+
+        // Divergence is reported per run, on the connections of the previous run
+        let result = target
+            .run_differential(&messages(&["getheaders", "getaddr"]))
+            .unwrap();
+        let divergence = result.divergence.expect("targets should diverge");
+        assert_eq!(divergence.message_index, 1);
+        assert_eq!(divergence.primary_commands, ["addr"]);
+        assert_eq!(divergence.secondary_commands, ["addrv2"]);
+    }
+
+    #[test]
+    fn compares_tips_and_mempools() {
+        let tip = (BlockHash::from_byte_array([1; 32]), 10);
+        let shared = Txid::from_byte_array([2; 32]);
+        let primary_only = Txid::from_byte_array([3; 32]);
+        let secondary_only = Txid::from_byte_array([4; 32]);
+
+        let mut primary = FakeNode::new(reference_responses);
+        primary.tip = Some(tip);
+        primary.mempool = vec![shared];
+        let mut secondary = FakeNode::new(reference_responses);
+        secondary.tip = Some(tip);
+        secondary.mempool = vec![shared];
+
+        let diff = compare_chain_state(&primary, &secondary).unwrap();
+        assert!(diff.is_consistent());
+
+        primary.mempool.push(primary_only);
+        secondary.mempool.push(secondary_only);
+        let diff = compare_chain_state(&primary, &secondary).unwrap();
+        assert!(!diff.is_consistent());
+        assert_eq!(diff.primary_only_txids, [primary_only]);
+        assert_eq!(diff.secondary_only_txids, [secondary_only]);
+
+        secondary.mempool = primary.mempool.clone();
+        secondary.tip = Some((BlockHash::from_byte_array([5; 32]), 10));
+        let diff = compare_chain_state(&primary, &secondary).unwrap();
+        assert!(diff.primary_only_txids.is_empty() && diff.secondary_only_txids.is_empty());
+        assert!(!diff.is_consistent());
+    }
+}
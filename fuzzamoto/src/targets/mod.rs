@@ -1,10 +1,15 @@
 pub mod bitcoin_core;
+pub mod differential;
 use crate::{
     connections::{Connection, ConnectionType, Transport},
-    targets::bitcoin_core::{MempoolEntry, TxOutSetInfo},
+    targets::bitcoin_core::{
+        BlockFilter, FeeEstimate, MempoolEntry, PeerInfo, SubmitPackageResult, TxOutSetInfo,
+        UnspentOutput,
+    },
 };
-use bitcoin::{Block, BlockHash, Txid};
+use bitcoin::{Block, BlockHash, Transaction, Txid};
 pub use bitcoin_core::BitcoinCoreTarget;
+pub use differential::DifferentialTarget;
 use std::net::SocketAddrV4;
 
 /// Transport-independent operations for a target node.
@@ -50,6 +55,41 @@ pub trait ConnectableTarget {
     }
 
     fn is_connected_to<O: ConnectableTarget>(&self, other: &O) -> bool;
+
+    /// Establish all connections described by `topology` between `nodes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - The nodes to connect.
+    /// * `topology` - Pairs of node indices `(from, to)`, each making `nodes[from]` open an
+    ///   outbound connection to `nodes[to]`.
+    fn connect_grid<T: Transport>(
+        nodes: &mut [Self],
+        topology: &[(usize, usize)],
+    ) -> Result<(), String>
+    where
+        Self: Target<T> + Sized,
+    {
+        for &(from, to) in topology {
+            if from == to || from >= nodes.len() || to >= nodes.len() {
+                return Err(format!(
+                    "Invalid connection in topology: {} -> {}",
+                    from, to
+                ));
+            }
+
+            let (from_node, to_node) = if from < to {
+                let (left, right) = nodes.split_at_mut(to);
+                (&mut left[from], &right[0])
+            } else {
+                let (left, right) = nodes.split_at_mut(from);
+                (&mut right[0], &left[to])
+            };
+            <Self as Target<T>>::connect_to(from_node, to_node)?;
+        }
+
+        Ok(())
+    }
 }
 
 pub trait HasTipInfo {
@@ -68,6 +108,12 @@ pub trait HasTxOutSetInfo {
     fn tx_out_set_info(&self) -> Result<TxOutSetInfo, String>;
 }
 
+pub trait HasScanTxOutSet {
+    /// Scan the UTXO set for outputs matching the output `descriptors` (e.g. `addr(<address>)`)
+    /// via `scantxoutset`.
+    fn scan_tx_out_set(&self, descriptors: &[String]) -> Result<Vec<UnspentOutput>, String>;
+}
+
 pub trait HasBlockTemplate {
     fn block_template(&self) -> Result<(), String>;
 }
@@ -76,6 +122,28 @@ pub trait HasGetRawMempoolEntries {
     fn get_mempool_entries(&self) -> Result<Vec<MempoolEntry>, String>;
 }
 
+pub trait HasSubmitPackage {
+    /// Submit `txs` as a package (topologically sorted, child last) via `submitpackage`.
+    fn submitpackage(&self, txs: &[Transaction]) -> Result<SubmitPackageResult, String>;
+}
+
+pub trait HasGetBlockFilter {
+    /// Fetch the compact block filter (BIP-158) of type `filter_type` (e.g. "basic") for the
+    /// block with hash `hash` via `getblockfilter`.
+    fn getblockfilter(&self, hash: BlockHash, filter_type: &str) -> Result<BlockFilter, String>;
+}
+
+pub trait HasGetPeerInfo {
+    /// Fetch the peers the target is currently connected to via `getpeerinfo`.
+    fn get_peer_info(&self) -> Result<Vec<PeerInfo>, String>;
+}
+
+pub trait HasEstimateSmartFee {
+    /// Estimate the fee rate needed for a transaction to confirm within `conf_target` blocks via
+    /// `estimatesmartfee`. `None` if the target has no estimate.
+    fn estimate_smart_fee(&self, conf_target: u16) -> Result<Option<FeeEstimate>, String>;
+}
+
 pub trait HasBlockChainInterface:
     HasTipInfo + HasGetBlock + HasTxOutSetInfo + HasGetRawMempoolEntries + HasBlockTemplate
 {
@@ -87,3 +155,96 @@ impl<
 > HasBlockChainInterface for Target
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{self, Ipv4Addr};
+
+    struct MockTransport;
+
+    impl Transport for MockTransport {
+        fn send(&mut self, _message: &(String, Vec<u8>)) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn receive(&mut self) -> Result<(String, Vec<u8>), String> {
+            Err("No pending messages".to_string())
+        }
+
+        fn local_addr(&self) -> Result<net::SocketAddr, String> {
+            Ok(net::SocketAddr::from(([127, 0, 0, 1], 0)))
+        }
+    }
+
+    /// `MockNode` records the addresses it was connected to
+    struct MockNode {
+        addr: SocketAddrV4,
+        peers: Vec<SocketAddrV4>,
+    }
+
+    impl MockNode {
+        fn new(port: u16) -> Self {
+            Self {
+                addr: SocketAddrV4::new(Ipv4Addr::LOCALHOST, port),
+                peers: Vec::new(),
+            }
+        }
+    }
+
+    impl TargetNode for MockNode {
+        fn from_path(_path: &str) -> Result<Self, String> {
+            Ok(Self::new(0))
+        }
+
+        fn set_mocktime(&mut self, _time: u64) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn is_alive(&self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl Target<MockTransport> for MockNode {
+        fn connect(
+            &mut self,
+            _connection_type: ConnectionType,
+        ) -> Result<Connection<MockTransport>, String> {
+            Err("Not supported".to_string())
+        }
+
+        fn connect_to<O: ConnectableTarget>(&mut self, other: &O) -> Result<(), String> {
+            self.peers
+                .push(other.get_addr().ok_or("Other node has no address")?);
+            Ok(())
+        }
+    }
+
+    impl ConnectableTarget for MockNode {
+        fn get_addr(&self) -> Option<SocketAddrV4> {
+            Some(self.addr)
+        }
+
+        fn is_connected_to<O: ConnectableTarget>(&self, other: &O) -> bool {
+            other
+                .get_addr()
+                .is_some_and(|addr| self.peers.contains(&addr))
+        }
+    }
+
+    #[test]
+    fn connect_grid_follows_topology() {
+        let mut nodes: Vec<MockNode> = (1..=3).map(MockNode::new).collect();
+        MockNode::connect_grid::<MockTransport>(&mut nodes, &[(0, 1), (2, 1), (1, 0)]).unwrap();
+
+        assert!(nodes[0].is_connected_to(&nodes[1]));
+        assert!(nodes[1].is_connected_to(&nodes[0]));
+        assert!(nodes[2].is_connected_to(&nodes[1]));
+        assert!(!nodes[0].is_connected_to(&nodes[2]));
+        assert!(!nodes[1].is_connected_to(&nodes[2]));
+
+        assert!(MockNode::connect_grid::<MockTransport>(&mut nodes, &[(1, 1)]).is_err());
+        assert!(MockNode::connect_grid::<MockTransport>(&mut nodes, &[(0, 3)]).is_err());
+    }
+}
@@ -49,18 +49,17 @@ pub fn fixup_commitments(block: &mut Block) {
     block.header.merkle_root = block.compute_merkle_root().unwrap();
 }
 
-pub fn fixup_proof_of_work(block: &mut Block) {
+pub fn has_valid_proof_of_work(header: &block::Header) -> bool {
     if cfg!(feature = "reduced_pow") {
-        let mut block_hash = block.header.block_hash();
-        while block_hash.as_raw_hash()[31] & 0x80 != 0 {
-            block.header.nonce += 1;
-            block_hash = block.header.block_hash();
-        }
+        header.block_hash().as_raw_hash()[31] & 0x80 == 0
     } else {
-        let target = block.header.target();
-        while block.header.validate_pow(target).is_err() {
-            block.header.nonce += 1;
-        }
+        header.validate_pow(header.target()).is_ok()
+    }
+}
+
+pub fn fixup_proof_of_work(block: &mut Block) {
+    while !has_valid_proof_of_work(&block.header) {
+        block.header.nonce += 1;
     }
 }
 